@@ -0,0 +1,55 @@
+//! Encode and parse throughput
+//!
+//! Run with `cargo bench -p roland-core`. This is a plain timing loop
+//! rather than a criterion benchmark, since roland-core has no
+//! dependencies; compare numbers between runs on the same machine.
+
+use roland_core::{Address, Command, Response};
+use std::hint::black_box;
+use std::time::Instant;
+
+/// Iterations per benchmark
+const ITERATIONS: u32 = 1_000_000;
+
+fn bench<T>(name: &str, mut f: impl FnMut() -> T) {
+    // Warm up
+    for _ in 0..ITERATIONS / 10 {
+        black_box(f());
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    let per_iter = start.elapsed() / ITERATIONS;
+    println!("{:<32} {:>8.1?}/iter", name, per_iter);
+}
+
+fn main() {
+    let write = Command::write_parameter(Address::new(0x12, 0x34, 0x56), 0x01);
+    let read = Command::read(Address::new(0x12, 0x34, 0x56), 1).unwrap();
+    let block = Command::WriteBlock {
+        address: Address::new(0x12, 0x34, 0x56),
+        data: vec![0x01; 16],
+    };
+
+    bench("encode DTH", || black_box(&write).encode());
+    bench("encode RQH", || black_box(&read).encode());
+    bench("encode DTH block (16)", || black_box(&block).encode());
+    let mut buf = String::with_capacity(64);
+    bench("encode_into DTH", || {
+        buf.clear();
+        black_box(&write).encode_into(&mut buf);
+    });
+
+    bench("parse DTH", || Response::parse(black_box("DTH:123456,01;")));
+    bench("parse DTH block (4)", || {
+        Response::parse(black_box("DTH:123456,01,02,03,04;"))
+    });
+    bench("parse VER", || {
+        Response::parse(black_box("VER:VR-6HD,1.00;"))
+    });
+    bench("parse ack", || Response::parse(black_box("\x06")));
+    bench("parse command DTH", || {
+        Command::parse(black_box("DTH:123456,01;"))
+    });
+}
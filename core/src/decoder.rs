@@ -0,0 +1,221 @@
+//! Streaming frame decoder
+//!
+//! Data from the device arrives in arbitrary chunks: one read may contain
+//! half a frame, or several frames at once. [`Decoder`] buffers incoming
+//! bytes and splits them into complete frames so nothing is lost between
+//! reads.
+
+use crate::{ParseOptions, Response, RolandError};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// STX (start of text, RS-232 only)
+const STX: u8 = 0x02;
+/// ACK (acknowledge)
+const ACK: u8 = 0x06;
+/// XON (resume sending)
+const XON: u8 = 0x11;
+/// XOFF (pause sending)
+const XOFF: u8 = 0x13;
+
+/// Streaming frame decoder
+///
+/// # Example
+/// ```
+/// use roland_core::decoder::Decoder;
+/// use roland_core::Response;
+///
+/// let mut decoder = Decoder::new();
+/// decoder.push(b"\x06DTH:123");
+/// assert_eq!(decoder.decode(), Some(Ok(Response::Acknowledge)));
+/// assert_eq!(decoder.decode(), None);
+///
+/// decoder.push(b"456,01;");
+/// assert!(matches!(decoder.decode(), Some(Ok(Response::Data { value: 0x01, .. }))));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+    options: ParseOptions,
+}
+
+impl Decoder {
+    /// Create an empty decoder accepting only the documented frame format
+    pub fn new() -> Self {
+        Self::with_options(ParseOptions::STRICT)
+    }
+
+    /// Create an empty decoder accepting the variants in `options`
+    pub fn with_options(options: ParseOptions) -> Self {
+        Self {
+            buffer: Vec::new(),
+            options,
+        }
+    }
+
+    /// Get the accepted frame variants
+    pub fn options(&self) -> ParseOptions {
+        self.options
+    }
+
+    /// Change the accepted frame variants
+    ///
+    /// Applies to frames taken from the buffer from now on.
+    pub fn set_options(&mut self, options: ParseOptions) {
+        self.options = options;
+    }
+
+    /// Append received bytes
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Take the next complete frame from the buffer
+    ///
+    /// Frames are either a single control character (ACK, XON, XOFF) or
+    /// text terminated by `;` (or a line break, if
+    /// [`ParseOptions::allow_missing_semicolon`] is set). Whitespace
+    /// between frames is skipped.
+    /// Returns `None` until a complete frame has been received.
+    pub fn next_frame(&mut self) -> Option<String> {
+        let start = skip_whitespace(&self.buffer);
+        self.buffer.drain(..start);
+
+        let end = frame_len_with(&self.buffer, self.options)?;
+        let frame: Vec<u8> = self.buffer.drain(..end).collect();
+        Some(String::from_utf8_lossy(&frame).into_owned())
+    }
+
+    /// Take and parse the next complete frame
+    ///
+    /// Returns `None` until a complete frame has been received.
+    pub fn decode(&mut self) -> Option<Result<Response, RolandError>> {
+        self.next_frame()
+            .map(|frame| Response::parse_with(&frame, self.options))
+    }
+
+    /// Number of buffered bytes not yet returned as frames
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Check if there are no buffered bytes
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Discard all buffered bytes
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+/// Get the number of whitespace bytes at the start of `data`
+///
+/// Some firmware sends line breaks between frames.
+pub(crate) fn skip_whitespace(data: &[u8]) -> usize {
+    data.iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len())
+}
+
+/// Get the length of the complete frame at the start of `data`
+pub(crate) fn frame_len(data: &[u8]) -> Option<usize> {
+    frame_len_with(data, ParseOptions::STRICT)
+}
+
+/// Get the length of the complete frame at the start of `data`, accepting
+/// the variants in `options`
+pub(crate) fn frame_len_with(data: &[u8], options: ParseOptions) -> Option<usize> {
+    match *data.first()? {
+        ACK | XON | XOFF => return Some(1),
+        _ => {}
+    }
+
+    // Text forms of the control responses
+    for text in ["ack", "xon", "xoff"] {
+        if options.prefix_matches(data, text) {
+            return Some(text.len());
+        }
+    }
+
+    // Anything else (optionally preceded by STX) is terminated by ';'
+    let body = if data[0] == STX { &data[1..] } else { data };
+    let offset = data.len() - body.len();
+    let end = body.iter().position(|&b| {
+        b == b';' || (options.allow_missing_semicolon && matches!(b, b'\r' | b'\n'))
+    })?;
+    // The line break isn't part of the frame
+    Some(offset + end + (body[end] == b';') as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Address;
+
+    #[test]
+    fn test_split_frames() {
+        let mut decoder = Decoder::new();
+        decoder.push(b"\x06DTH:123456,01;VER:VR-6HD,1.00;");
+        assert_eq!(decoder.next_frame().unwrap(), "\x06");
+        assert_eq!(decoder.next_frame().unwrap(), "DTH:123456,01;");
+        assert_eq!(decoder.next_frame().unwrap(), "VER:VR-6HD,1.00;");
+        assert_eq!(decoder.next_frame(), None);
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn test_partial_frame() {
+        let mut decoder = Decoder::new();
+        decoder.push(b"DTH:12");
+        assert_eq!(decoder.decode(), None);
+        assert_eq!(decoder.len(), 6);
+
+        decoder.push(b"3456,7F;\x06");
+        assert_eq!(
+            decoder.decode(),
+            Some(Ok(Response::Data {
+                address: Address::new(0x12, 0x34, 0x56),
+                value: 0x7F,
+            }))
+        );
+        assert_eq!(decoder.decode(), Some(Ok(Response::Acknowledge)));
+    }
+
+    #[test]
+    fn test_skip_whitespace_and_stx() {
+        let mut decoder = Decoder::new();
+        decoder.push(b"\r\n\x02ERR:5;\r\n");
+        assert_eq!(
+            decoder.decode(),
+            Some(Ok(Response::Error(RolandError::OutOfRange)))
+        );
+        assert_eq!(decoder.decode(), None);
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn test_frames_in_one_read() {
+        let mut decoder = Decoder::new();
+        decoder.push(b"\r\nack\r\nDTH:000000,01;");
+        assert_eq!(decoder.decode(), Some(Ok(Response::Acknowledge)));
+        assert_eq!(
+            decoder.decode(),
+            Some(Ok(Response::Data {
+                address: Address::new(0x00, 0x00, 0x00),
+                value: 0x01,
+            }))
+        );
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn test_flow_control_frames() {
+        let mut decoder = Decoder::new();
+        decoder.push(b"\x13\x11ack");
+        assert_eq!(decoder.next_frame().unwrap(), "\x13");
+        assert_eq!(decoder.next_frame().unwrap(), "\x11");
+        assert_eq!(decoder.decode(), Some(Ok(Response::Acknowledge)));
+    }
+}
@@ -0,0 +1,517 @@
+//! Request/response correlation without I/O
+//!
+//! [`ProtocolEngine`] is the state machine of a driver: it decides when a
+//! command may be sent and which received frame answers which command,
+//! while the caller moves the bytes and supplies the time. Nothing in it
+//! blocks or needs `std`, so an RTOS task or an interrupt-driven UART
+//! driver can use it as it is.
+//!
+//! The device answers commands in the order it received them, so an ACK,
+//! ERR or VER completes the oldest command waiting. A DTH frame only
+//! completes a read of its address, see [`answers_with`]; other DTH
+//! frames are parameter changes the device reports on its own (e.g. an
+//! operator moving a fader) and are kept for
+//! [`ProtocolEngine::take_unsolicited`]. The std client runs its commands
+//! through an engine too, feeding it the frames it reads.
+//!
+//! # Example
+//! ```
+//! use roland_core::engine::ProtocolEngine;
+//! use roland_core::{Address, Command, Response};
+//!
+//! let mut engine = ProtocolEngine::new();
+//! let write = Command::write_parameter(Address::new(0x12, 0x34, 0x56), 0x01);
+//! assert_eq!(engine.submit(write.clone()).unwrap(), b"DTH:123456,01;");
+//! // Only one command is outstanding at a time by default
+//! assert_eq!(engine.submit(Command::GetVersion), None);
+//!
+//! engine.on_rx(&[0x06]);
+//! assert_eq!(engine.take_completion(), Some((write, Ok(Response::Acknowledge))));
+//! assert_eq!(engine.poll_transmit().unwrap(), b"VER;");
+//!
+//! engine.tick(1000);
+//! assert!(engine.take_completion().unwrap().1.is_err());
+//! ```
+
+use crate::decoder::Decoder;
+use crate::{Command, ParseOptions, Response, RolandError, WriteAnswer};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// STX (start of text, RS-232 only)
+const STX: u8 = 0x02;
+
+/// Command with how it ended: its response, a device error, a frame that
+/// didn't parse, or [`RolandError::Timeout`]
+pub type Completion = (Command, Result<Response, RolandError>);
+
+/// Check if a response answers `command`
+///
+/// DTH frames only answer a read of the same address, and XON/XOFF
+/// answer nothing; any other response answers whatever command the
+/// device got next.
+///
+/// # Example
+/// ```
+/// use roland_core::engine::answers;
+/// use roland_core::{Address, Command, Response};
+///
+/// let fader = Address::new(0x05, 0x00, 0x00);
+/// let read = Command::read(fader, 1).unwrap();
+/// assert!(answers(&read, &Response::Data { address: fader, value: 0x40 }));
+/// let other = Address::new(0x05, 0x01, 0x00);
+/// assert!(!answers(&read, &Response::Data { address: other, value: 0x40 }));
+/// assert!(answers(&read, &Response::Acknowledge));
+/// ```
+pub fn answers(command: &Command, response: &Response) -> bool {
+    answers_with(command, response, ParseOptions::STRICT)
+}
+
+/// Check if a response answers `command`, given how the firmware answers
+/// writes
+///
+/// Like [`answers`], but with [`ParseOptions::write_answer`] set to
+/// anything but [`WriteAnswer::AckOnly`], a DTH of the address a write
+/// starts at answers the write as well. Whether its value is the one
+/// written is left to the caller.
+///
+/// # Example
+/// ```
+/// use roland_core::engine::answers_with;
+/// use roland_core::{Address, Command, ParseOptions, Response, WriteAnswer};
+///
+/// let fader = Address::new(0x05, 0x00, 0x00);
+/// let write = Command::write_parameter(fader, 0x40);
+/// let dth = Response::Data { address: fader, value: 0x40 };
+/// let options = ParseOptions {
+///     write_answer: WriteAnswer::Dth,
+///     ..ParseOptions::LENIENT
+/// };
+/// assert!(answers_with(&write, &dth, options));
+/// assert!(!answers_with(&write, &dth, ParseOptions::LENIENT));
+/// ```
+pub fn answers_with(command: &Command, response: &Response, options: ParseOptions) -> bool {
+    match response {
+        Response::Data { address, .. } | Response::DataBlock { address, .. } => match command {
+            Command::ReadParameter {
+                address: requested, ..
+            } => requested == address,
+            Command::WriteParameter {
+                address: written, ..
+            }
+            | Command::WriteBlock {
+                address: written, ..
+            } => written == address && options.write_answer != WriteAnswer::AckOnly,
+            _ => false,
+        },
+        Response::Xon | Response::Xoff => false,
+        _ => true,
+    }
+}
+
+/// Correlation state of one connection, see the [module documentation](self)
+#[derive(Debug, Clone)]
+pub struct ProtocolEngine {
+    decoder: Decoder,
+    window: usize,
+    timeout_ms: u32,
+    stx: bool,
+    /// Time of the last [`ProtocolEngine::tick`]
+    now_ms: u32,
+    /// The device sent XOFF
+    paused: bool,
+    /// Submitted commands not sent yet, oldest first
+    queued: VecDeque<Command>,
+    /// Sent commands with the time they were sent, oldest first
+    outstanding: VecDeque<(Command, u32)>,
+    completions: VecDeque<Completion>,
+    unsolicited: VecDeque<Response>,
+}
+
+impl Default for ProtocolEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProtocolEngine {
+    /// Default for [`ProtocolEngine::timeout_ms`]
+    pub const DEFAULT_TIMEOUT_MS: u32 = 1000;
+
+    /// Create an engine sending one command at a time
+    pub fn new() -> Self {
+        Self::with_window(1)
+    }
+
+    /// Create an engine with up to `window` commands outstanding
+    ///
+    /// A window of 0 is taken as 1.
+    pub fn with_window(window: usize) -> Self {
+        Self {
+            decoder: Decoder::new(),
+            window: window.max(1),
+            timeout_ms: Self::DEFAULT_TIMEOUT_MS,
+            stx: false,
+            now_ms: 0,
+            paused: false,
+            queued: VecDeque::new(),
+            outstanding: VecDeque::new(),
+            completions: VecDeque::new(),
+            unsolicited: VecDeque::new(),
+        }
+    }
+
+    /// Get how many commands may be outstanding at once
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Set how many commands may be outstanding at once (at least 1)
+    ///
+    /// Commands already sent stay outstanding when the window shrinks.
+    pub fn set_window(&mut self, window: usize) {
+        self.window = window.max(1);
+    }
+
+    /// Get how long a sent command waits for its response
+    pub fn timeout_ms(&self) -> u32 {
+        self.timeout_ms
+    }
+
+    /// Set how long a sent command waits for its response
+    pub fn set_timeout_ms(&mut self, timeout_ms: u32) {
+        self.timeout_ms = timeout_ms;
+    }
+
+    /// Prefix commands with STX, as RS-232 needs
+    pub fn set_stx(&mut self, stx: bool) {
+        self.stx = stx;
+    }
+
+    /// Change the frame variants accepted from the device
+    pub fn set_parse_options(&mut self, options: ParseOptions) {
+        self.decoder.set_options(options);
+    }
+
+    /// Submit a command
+    ///
+    /// # Returns
+    /// * `Option<Vec<u8>>` - Bytes to transmit now, or `None` if earlier
+    ///   commands fill the window or the device sent XOFF; the command is
+    ///   queued then and comes out of [`ProtocolEngine::poll_transmit`]
+    pub fn submit(&mut self, command: Command) -> Option<Vec<u8>> {
+        self.queued.push_back(command);
+        if self.queued.len() > 1 {
+            return None;
+        }
+        self.poll_transmit()
+    }
+
+    /// Take the bytes of the next queued command, if it may be sent now
+    ///
+    /// Call this after [`ProtocolEngine::on_rx`] and
+    /// [`ProtocolEngine::tick`] until it returns `None`. The timeout of
+    /// the command starts at the last tick.
+    pub fn poll_transmit(&mut self) -> Option<Vec<u8>> {
+        if self.paused || self.outstanding.len() >= self.window {
+            return None;
+        }
+        let command = self.queued.pop_front()?;
+        let mut bytes = Vec::with_capacity(usize::from(self.stx) + command.encoded_len());
+        if self.stx {
+            bytes.push(STX);
+        }
+        bytes.extend_from_slice(command.encode().as_bytes());
+        self.outstanding.push_back((command, self.now_ms));
+        Some(bytes)
+    }
+
+    /// Feed bytes received from the device
+    ///
+    /// Bytes may come in any chunks; frames are completed as soon as
+    /// their last byte arrives. A frame that doesn't parse completes the
+    /// oldest outstanding command with the parse error, or is dropped if
+    /// none is outstanding.
+    pub fn on_rx(&mut self, data: &[u8]) {
+        self.decoder.push(data);
+        while let Some(frame) = self.decoder.decode() {
+            match frame {
+                Ok(response) => self.on_response(response),
+                Err(e) if !self.outstanding.is_empty() => self.complete(0, Err(e)),
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Feed a response the caller has framed and parsed itself
+    ///
+    /// For drivers whose transport needs its own framing, such as Telnet
+    /// with option negotiation; [`ProtocolEngine::on_rx`] feeds every
+    /// frame it decodes through here.
+    pub fn on_response(&mut self, response: Response) {
+        match response {
+            Response::Xoff => self.paused = true,
+            Response::Xon => self.paused = false,
+            response => {
+                match self.outstanding.iter().position(|(command, _)| {
+                    answers_with(command, &response, self.decoder.options())
+                }) {
+                    Some(i) => {
+                        let result = match response {
+                            Response::Error(e) => Err(e),
+                            response => Ok(response),
+                        };
+                        self.complete(i, result)
+                    }
+                    None => self.unsolicited.push_back(response),
+                }
+            }
+        }
+    }
+
+    /// Advance the clock, timing out commands waiting too long
+    ///
+    /// `now_ms` is any free-running millisecond counter, e.g. the RTOS
+    /// tick count; it may wrap around. Commands waiting at least
+    /// [`ProtocolEngine::timeout_ms`] complete with
+    /// [`RolandError::Timeout`].
+    pub fn tick(&mut self, now_ms: u32) {
+        self.now_ms = now_ms;
+        while let Some((_, sent_ms)) = self.outstanding.front() {
+            if now_ms.wrapping_sub(*sent_ms) < self.timeout_ms {
+                break;
+            }
+            self.complete(0, Err(RolandError::Timeout));
+        }
+    }
+
+    /// Take the oldest completed command
+    pub fn take_completion(&mut self) -> Option<Completion> {
+        self.completions.pop_front()
+    }
+
+    /// Take the oldest response that answered no command
+    pub fn take_unsolicited(&mut self) -> Option<Response> {
+        self.unsolicited.pop_front()
+    }
+
+    /// Get the number of commands sent and waiting for their response
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    /// Get the number of submitted commands not sent yet
+    pub fn queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Check if the device has paused transmission with XOFF
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Forget the commands queued and outstanding, e.g. after
+    /// reconnecting
+    ///
+    /// The pause is lifted as well. Completions and unsolicited responses
+    /// not taken yet are kept.
+    pub fn clear(&mut self) {
+        self.queued.clear();
+        self.outstanding.clear();
+        self.paused = false;
+    }
+
+    /// Check if no command is queued or outstanding
+    pub fn is_idle(&self) -> bool {
+        self.queued.is_empty() && self.outstanding.is_empty()
+    }
+
+    fn complete(&mut self, i: usize, result: Result<Response, RolandError>) {
+        if let Some((command, _)) = self.outstanding.remove(i) {
+            self.completions.push_back((command, result));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Address;
+    use alloc::vec;
+
+    const FADER: Address = Address::new(0x05, 0x00, 0x00);
+
+    fn write(value: u8) -> Command {
+        Command::write_parameter(FADER, value)
+    }
+
+    fn completions(engine: &mut ProtocolEngine) -> Vec<Completion> {
+        core::iter::from_fn(|| engine.take_completion()).collect()
+    }
+
+    #[test]
+    fn test_one_outstanding() {
+        let mut engine = ProtocolEngine::new();
+        assert_eq!(engine.submit(write(1)).unwrap(), b"DTH:050000,01;");
+        assert_eq!(engine.submit(write(2)), None);
+        assert_eq!(engine.submit(Command::GetVersion), None);
+        assert_eq!((engine.outstanding(), engine.queued()), (1, 2));
+        assert_eq!(engine.poll_transmit(), None);
+
+        // The ACK arrives in two pieces with an ERR for the next write
+        engine.on_rx(b"\x06");
+        assert_eq!(engine.poll_transmit().unwrap(), b"DTH:050000,02;");
+        engine.on_rx(b"ERR:");
+        assert_eq!(engine.poll_transmit(), None);
+        engine.on_rx(b"5;");
+        assert_eq!(engine.poll_transmit().unwrap(), b"VER;");
+        engine.on_rx(b"VER:VR-6HD,1.00;");
+
+        assert_eq!(
+            completions(&mut engine),
+            vec![
+                (write(1), Ok(Response::Acknowledge)),
+                (write(2), Err(RolandError::OutOfRange)),
+                (
+                    Command::GetVersion,
+                    Ok(Response::Version {
+                        product: "VR-6HD".into(),
+                        version: "1.00".into()
+                    })
+                ),
+            ]
+        );
+        assert!(engine.is_idle());
+    }
+
+    #[test]
+    fn test_framed_responses_and_clear() {
+        let mut engine = ProtocolEngine::new();
+        let read = Command::read(FADER, 1).unwrap();
+        assert!(engine.submit(read.clone()).is_some());
+        assert_eq!(engine.submit(write(1)), None);
+
+        // Responses framed by the caller are matched like received bytes
+        let other = Response::Data {
+            address: Address::new(0x05, 0x01, 0x00),
+            value: 0x40,
+        };
+        engine.on_response(other.clone());
+        engine.on_response(Response::Xoff);
+        assert!(engine.is_paused());
+        engine.on_response(Response::Error(RolandError::Invalid));
+        assert_eq!(engine.take_unsolicited(), Some(other));
+        assert_eq!(
+            engine.take_completion(),
+            Some((read, Err(RolandError::Invalid)))
+        );
+
+        // Clearing drops the queued write and lifts the pause
+        engine.clear();
+        assert!(engine.is_idle() && !engine.is_paused());
+        assert_eq!(engine.submit(write(2)).unwrap(), b"DTH:050000,02;");
+    }
+
+    #[test]
+    fn test_window_and_unsolicited() {
+        let mut engine = ProtocolEngine::with_window(3);
+        let read = Command::read(FADER, 1).unwrap();
+        assert!(engine.submit(write(1)).is_some());
+        assert!(engine.submit(read.clone()).is_some());
+        assert!(engine.submit(write(2)).is_some());
+        assert_eq!(engine.submit(write(3)), None);
+
+        // A change of another fader, then the answers in order; the DTH
+        // of the read overtakes nothing but still finds its read
+        engine.on_rx(b"DTH:050100,40;\x06DTH:050000,01;\x06");
+        assert_eq!(
+            engine.take_unsolicited(),
+            Some(Response::Data {
+                address: Address::new(0x05, 0x01, 0x00),
+                value: 0x40
+            })
+        );
+        assert_eq!(engine.take_unsolicited(), None);
+        let done = completions(&mut engine);
+        assert_eq!(done[0], (write(1), Ok(Response::Acknowledge)));
+        assert_eq!(
+            done[1],
+            (
+                read,
+                Ok(Response::Data {
+                    address: FADER,
+                    value: 0x01
+                })
+            )
+        );
+        assert_eq!(done[2], (write(2), Ok(Response::Acknowledge)));
+        assert_eq!(engine.poll_transmit().unwrap(), b"DTH:050000,03;");
+        assert_eq!(engine.poll_transmit(), None);
+
+        // Without a read outstanding, a DTH is unsolicited
+        engine.on_rx(b"DTH:050000,7F;");
+        assert!(engine.take_unsolicited().is_some());
+        assert_eq!(engine.outstanding(), 1);
+    }
+
+    #[test]
+    fn test_timeouts_tick_by_tick() {
+        let mut engine = ProtocolEngine::with_window(2);
+        engine.set_timeout_ms(100);
+        // The counter wraps around while the commands wait
+        engine.tick(u32::MAX - 49);
+        engine.submit(write(1)).unwrap();
+        engine.tick(u32::MAX - 9);
+        engine.submit(write(2)).unwrap();
+        engine.submit(write(3));
+
+        engine.tick(49);
+        assert!(completions(&mut engine).is_empty());
+        engine.tick(50);
+        assert_eq!(
+            completions(&mut engine),
+            vec![(write(1), Err(RolandError::Timeout))]
+        );
+        // The freed slot sends the next command, timed from now
+        assert!(engine.poll_transmit().is_some());
+        engine.tick(89);
+        assert!(completions(&mut engine).is_empty());
+        engine.on_rx(b"\x06");
+        assert_eq!(
+            completions(&mut engine),
+            vec![(write(2), Ok(Response::Acknowledge))]
+        );
+        engine.tick(149);
+        assert!(completions(&mut engine).is_empty());
+        engine.tick(150);
+        assert_eq!(
+            completions(&mut engine),
+            vec![(write(3), Err(RolandError::Timeout))]
+        );
+    }
+
+    #[test]
+    fn test_flow_control_and_framing() {
+        let mut engine = ProtocolEngine::new();
+        engine.set_stx(true);
+        assert_eq!(engine.submit(write(1)).unwrap(), b"\x02DTH:050000,01;");
+        engine.submit(write(2));
+
+        // XOFF with the ACK holds the next command back until XON
+        engine.on_rx(b"\x13\x06");
+        assert!(engine.is_paused());
+        assert_eq!(engine.poll_transmit(), None);
+        engine.on_rx(b"\x11");
+        assert_eq!(engine.poll_transmit().unwrap(), b"\x02DTH:050000,02;");
+
+        // Garbage completes the outstanding command with the parse error
+        engine.on_rx(b"XYZ;");
+        let done = completions(&mut engine);
+        assert_eq!(done.len(), 2);
+        assert!(done[1].1.is_err());
+        // and is dropped while none is outstanding
+        engine.on_rx(b"XYZ;");
+        assert!(engine.take_completion().is_none());
+        assert!(engine.take_unsolicited().is_none());
+    }
+}
@@ -8,6 +8,15 @@
 //! - `no_std` compatible (requires `alloc` for string operations)
 //! - Zero external dependencies
 //! - Pure protocol implementation
+//!
+//! # Stability
+//!
+//! [`Command`], [`Response`] and [`RolandError`] are `#[non_exhaustive]`:
+//! new protocol features add variants in minor releases. Matches outside
+//! this crate need a wildcard arm, and the accessors such as
+//! [`Response::as_data`] and [`RolandError::is_device_error`] keep working
+//! when variants are added. Existing variants are only removed or changed
+//! in major releases.
 
 #![no_std]
 
@@ -18,8 +27,23 @@ use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::fmt;
 
+pub mod decoder;
+pub mod engine;
+pub mod nibble;
+pub mod params;
+#[cfg(test)]
+mod properties;
+pub mod queue;
+pub mod signed;
+pub mod sysex;
+pub mod version;
+
+pub use decoder::Decoder;
+pub use version::{DeviceInfo, FirmwareVersion, ProductModel};
+
 /// Error types for Roland VR-6HD communication
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum RolandError {
     /// Syntax error in received command
     SyntaxError,
@@ -37,6 +61,14 @@ pub enum RolandError {
     InvalidValue,
     /// Invalid response format
     InvalidResponse,
+    /// SysEx message with a wrong checksum
+    ChecksumMismatch,
+    /// Data before or after the frame passed to [`Response::parse`]
+    UnframedData,
+    /// Encoded command doesn't fit in the buffer or queue
+    BufferFull,
+    /// No response arrived in time, see [`engine::ProtocolEngine`]
+    Timeout,
 }
 
 impl fmt::Display for RolandError {
@@ -50,12 +82,57 @@ impl fmt::Display for RolandError {
             RolandError::InvalidAddress => write!(f, "Invalid address format"),
             RolandError::InvalidValue => write!(f, "Invalid value format"),
             RolandError::InvalidResponse => write!(f, "Invalid response format"),
+            RolandError::ChecksumMismatch => write!(f, "SysEx checksum mismatch"),
+            RolandError::UnframedData => write!(f, "Unframed data around response"),
+            RolandError::BufferFull => write!(f, "Buffer full"),
+            RolandError::Timeout => write!(f, "No response in time"),
+        }
+    }
+}
+
+impl core::error::Error for RolandError {}
+
+impl RolandError {
+    /// Get the device error code (the number in `ERR:n;`)
+    ///
+    /// Returns `None` for errors that are detected locally and never
+    /// reported by the device itself.
+    pub fn code(&self) -> Option<u8> {
+        match self {
+            RolandError::SyntaxError => Some(0),
+            RolandError::Invalid => Some(4),
+            RolandError::OutOfRange => Some(5),
+            RolandError::NoStx => Some(6),
+            RolandError::UnknownError(code) => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Check if the error was reported by the device (`ERR:n;`)
+    ///
+    /// Other errors are detected locally, e.g. a response that can't be
+    /// parsed.
+    pub fn is_device_error(&self) -> bool {
+        self.code().is_some()
+    }
+
+    /// Create an error from a device error code
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0 => RolandError::SyntaxError,
+            4 => RolandError::Invalid,
+            5 => RolandError::OutOfRange,
+            6 => RolandError::NoStx,
+            _ => RolandError::UnknownError(code),
         }
     }
 }
 
 /// SysEx address (3 bytes)
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+///
+/// Addresses are ordered like the 24-bit numbers they stand for, so
+/// `00FF00 < 010000`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Address {
     /// High byte
     pub high: u8,
@@ -66,8 +143,14 @@ pub struct Address {
 }
 
 impl Address {
+    /// Lowest address (`000000`)
+    pub const MIN: Address = Address::new(0x00, 0x00, 0x00);
+
+    /// Highest address (`FFFFFF`)
+    pub const MAX: Address = Address::new(0xFF, 0xFF, 0xFF);
+
     /// Create a new address from three bytes
-    pub fn new(high: u8, mid: u8, low: u8) -> Self {
+    pub const fn new(high: u8, mid: u8, low: u8) -> Self {
         Self { high, mid, low }
     }
 
@@ -82,7 +165,8 @@ impl Address {
     /// assert_eq!(addr.low, 0x56);
     /// ```
     pub fn from_hex(hex: &str) -> Result<Self, RolandError> {
-        if hex.len() != 6 {
+        // Six bytes of multi-byte UTF-8 can't be sliced into digit pairs
+        if hex.len() != 6 || !hex.is_ascii() {
             return Err(RolandError::InvalidAddress);
         }
 
@@ -110,6 +194,53 @@ impl Address {
         write_hex_byte(w, self.mid)?;
         write_hex_byte(w, self.low)
     }
+
+    /// Get the next address, or `None` at [`Address::MAX`]
+    ///
+    /// # Example
+    /// ```
+    /// use roland_core::Address;
+    /// assert_eq!(
+    ///     Address::new(0x00, 0xFF, 0xFF).successor(),
+    ///     Some(Address::new(0x01, 0x00, 0x00))
+    /// );
+    /// assert_eq!(Address::MAX.successor(), None);
+    /// ```
+    pub fn successor(self) -> Option<Self> {
+        Address::try_from(u32::from(self) + 1).ok()
+    }
+}
+
+impl From<Address> for u32 {
+    /// Get the address as a 24-bit number
+    fn from(address: Address) -> Self {
+        (address.high as u32) << 16 | (address.mid as u32) << 8 | address.low as u32
+    }
+}
+
+impl TryFrom<u32> for Address {
+    type Error = RolandError;
+
+    /// Get the address for a 24-bit number, `InvalidAddress` above `FFFFFF`
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        if value > 0xFF_FFFF {
+            return Err(RolandError::InvalidAddress);
+        }
+        Ok(Address::new(
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+        ))
+    }
+}
+
+impl TryFrom<&str> for Address {
+    type Error = RolandError;
+
+    /// Parse an address from a hex string, see [`Address::from_hex`]
+    fn try_from(hex: &str) -> Result<Self, Self::Error> {
+        Address::from_hex(hex)
+    }
 }
 
 /// Parse a single hex byte (2 hex digits)
@@ -131,6 +262,23 @@ fn parse_hex_byte(s: &str) -> Result<u8, RolandError> {
     Ok(result)
 }
 
+/// Parse comma-separated hex bytes
+///
+/// A single value is returned on its own so it can be used without
+/// allocating; the `Vec` with all values is only built for two or more.
+fn parse_hex_values(s: &str) -> Result<(u8, Option<Vec<u8>>), RolandError> {
+    let Some((first, rest)) = s.split_once(',') else {
+        return Ok((parse_hex_byte(s)?, None));
+    };
+    // Each further value takes 3 bytes with its comma
+    let mut data = Vec::with_capacity(2 + rest.len() / 3);
+    data.push(parse_hex_byte(first)?);
+    for value in rest.split(',') {
+        data.push(parse_hex_byte(value)?);
+    }
+    Ok((data[0], Some(data)))
+}
+
 /// Write a byte as hex (2 hex digits, uppercase)
 fn write_hex_byte<W: fmt::Write>(w: &mut W, byte: u8) -> fmt::Result {
     let high = (byte >> 4) & 0x0F;
@@ -153,7 +301,11 @@ fn write_hex_byte<W: fmt::Write>(w: &mut W, byte: u8) -> fmt::Result {
 }
 
 /// Command types for VR-6HD
+///
+/// The variants can be built directly, but [`Command::read`] and
+/// [`Command::write_parameter`] are the validated way to build commands.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Command {
     /// Write parameter (DTH)
     WriteParameter {
@@ -167,38 +319,142 @@ pub enum Command {
         /// SysEx address
         address: Address,
         /// Size to read (typically 1 for single byte)
+        ///
+        /// Must be 1 to [`Command::MAX_READ_SIZE`]; use [`Command::read`]
+        /// to check this.
         size: u32,
     },
+    /// Write consecutive parameters at once (DTH with several values)
+    ///
+    /// `data[0]` goes to `address`, `data[1]` to the next address and so
+    /// on. This is how multi-byte parameters are written in one step.
+    WriteBlock {
+        /// SysEx address of the first byte
+        address: Address,
+        /// Values to write
+        data: Vec<u8>,
+    },
     /// Get version information (VER)
     GetVersion,
 }
 
 impl Command {
+    /// Largest size a read can request (six hex digits)
+    pub const MAX_READ_SIZE: u32 = 0xFF_FFFF;
+
+    /// Build a read command
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address
+    /// * `size` - Size to read (1 to [`Command::MAX_READ_SIZE`])
+    ///
+    /// # Returns
+    /// * `Result<Command, RolandError>` - Command, or `OutOfRange` if the
+    ///   size is 0 or doesn't fit in six hex digits
+    ///
+    /// # Example
+    /// ```
+    /// use roland_core::{Address, Command, RolandError};
+    /// let address = Address::new(0x12, 0x34, 0x56);
+    /// assert_eq!(Command::read(address, 1).unwrap().encode(), "RQH:123456,000001;");
+    /// assert_eq!(Command::read(address, 0), Err(RolandError::OutOfRange));
+    /// ```
+    pub fn read(address: Address, size: u32) -> Result<Self, RolandError> {
+        if !(1..=Self::MAX_READ_SIZE).contains(&size) {
+            return Err(RolandError::OutOfRange);
+        }
+        Ok(Command::ReadParameter { address, size })
+    }
+
+    /// Build a write command
+    ///
+    /// This is named after the variant since [`Command::write`] writes a
+    /// command to a formatter.
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address
+    /// * `value` - Value to write (0-255)
+    pub fn write_parameter(address: Address, value: u8) -> Self {
+        Command::WriteParameter { address, value }
+    }
+
+    /// Get the name of the command variant, e.g. `ReadParameter`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::WriteParameter { .. } => "WriteParameter",
+            Command::ReadParameter { .. } => "ReadParameter",
+            Command::WriteBlock { .. } => "WriteBlock",
+            Command::GetVersion => "GetVersion",
+        }
+    }
+
+    /// Get the address the command reads or writes
+    ///
+    /// Returns `None` for commands without an address, like
+    /// [`Command::GetVersion`].
+    pub fn address(&self) -> Option<Address> {
+        match self {
+            Command::WriteParameter { address, .. }
+            | Command::ReadParameter { address, .. }
+            | Command::WriteBlock { address, .. } => Some(*address),
+            Command::GetVersion => None,
+        }
+    }
+
     /// Encode command to string format
     ///
     /// For Telnet, STX (0x02) is optional and omitted here.
     /// For RS-232, STX should be prepended by the transport layer.
     ///
-    /// Requires `alloc` for String allocation.
+    /// Requires `alloc` for String allocation. The string is allocated once
+    /// at its final size.
     pub fn encode(&self) -> String {
-        match self {
-            Command::WriteParameter { address, value } => {
-                format!("DTH:{},{:02X};", address.to_hex(), value)
-            }
-            Command::ReadParameter { address, size } => {
-                // Size is 3 bytes in hex (6 hex digits)
-                let size_hex = format!("{:06X}", size);
-                format!("RQH:{},{};", address.to_hex(), size_hex)
-            }
-            Command::GetVersion => "VER;".to_string(),
-        }
+        let mut encoded = String::with_capacity(self.encoded_len());
+        self.encode_into(&mut encoded);
+        encoded
     }
 
     /// Encode command with STX prefix (for RS-232)
     ///
     /// Requires `alloc` for String allocation.
     pub fn encode_with_stx(&self) -> String {
-        format!("\x02{}", self.encode())
+        let mut encoded = String::with_capacity(1 + self.encoded_len());
+        encoded.push('\x02');
+        self.encode_into(&mut encoded);
+        encoded
+    }
+
+    /// Append the encoded command to `out`
+    ///
+    /// Reusing one buffer for many commands avoids allocating once it has
+    /// grown to fit.
+    ///
+    /// # Example
+    /// ```
+    /// use roland_core::{Address, Command};
+    /// let mut buf = String::new();
+    /// for value in 0..3 {
+    ///     buf.clear();
+    ///     Command::write_parameter(Address::new(0x12, 0x34, 0x56), value).encode_into(&mut buf);
+    /// }
+    /// assert_eq!(buf, "DTH:123456,02;");
+    /// ```
+    pub fn encode_into(&self, out: &mut String) {
+        out.reserve(self.encoded_len());
+        // Writing to a String can't fail
+        let _ = self.write(out);
+    }
+
+    /// Length of the encoded command in bytes, without STX
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            // DTH:aaaaaa,vv;
+            Command::WriteParameter { .. } => 14,
+            // RQH:aaaaaa,ssssss;
+            Command::ReadParameter { .. } => 18,
+            Command::WriteBlock { data, .. } => 11 + 3 * data.len(),
+            Command::GetVersion => 4,
+        }
     }
 
     /// Write command to a formatter
@@ -215,6 +471,7 @@ impl Command {
                 w.write_str(";")
             }
             Command::ReadParameter { address, size } => {
+                debug_assert!((1..=Self::MAX_READ_SIZE).contains(size));
                 w.write_str("RQH:")?;
                 address.write_hex(w)?;
                 w.write_str(",")?;
@@ -222,15 +479,126 @@ impl Command {
                 write_hex_u24(w, *size)?;
                 w.write_str(";")
             }
+            Command::WriteBlock { address, data } => {
+                w.write_str("DTH:")?;
+                address.write_hex(w)?;
+                for value in data {
+                    w.write_str(",")?;
+                    write_hex_byte(w, *value)?;
+                }
+                w.write_str(";")
+            }
             Command::GetVersion => w.write_str("VER;"),
         }
     }
 
+    /// Encode command into a byte buffer
+    ///
+    /// This doesn't allocate, like [`Command::write`].
+    ///
+    /// # Returns
+    /// * `Result<usize, RolandError>` - Number of bytes written, or
+    ///   `BufferFull` if the command doesn't fit
+    ///
+    /// # Example
+    /// ```
+    /// use roland_core::{Address, Command};
+    /// let mut buf = [0u8; 32];
+    /// let n = Command::write_parameter(Address::new(0x12, 0x34, 0x56), 0x01)
+    ///     .encode_to_slice(&mut buf)
+    ///     .unwrap();
+    /// assert_eq!(&buf[..n], b"DTH:123456,01;");
+    /// ```
+    pub fn encode_to_slice(&self, buf: &mut [u8]) -> Result<usize, RolandError> {
+        let mut writer = SliceWriter { buf, len: 0 };
+        self.write(&mut writer)
+            .map_err(|_| RolandError::BufferFull)?;
+        Ok(writer.len)
+    }
+
     /// Write command with STX prefix to a formatter
     pub fn write_with_stx<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
         w.write_char('\x02')?;
         self.write(w)
     }
+
+    /// Parse command from string slice
+    ///
+    /// This is the inverse of [`Command::encode`] and is what the device
+    /// does with incoming commands. A leading STX is accepted.
+    ///
+    /// # Example
+    /// ```
+    /// use roland_core::{Address, Command};
+    /// let cmd = Command::parse("DTH:123456,01;").unwrap();
+    /// assert_eq!(
+    ///     cmd,
+    ///     Command::WriteParameter {
+    ///         address: Address::new(0x12, 0x34, 0x56),
+    ///         value: 0x01,
+    ///     }
+    /// );
+    /// ```
+    pub fn parse(command: &str) -> Result<Self, RolandError> {
+        let command = command.strip_prefix('\x02').unwrap_or(command);
+
+        if command == "VER;" {
+            return Ok(Command::GetVersion);
+        }
+
+        // Parse DTH command: DTH:address,value; or DTH:address,value,value...;
+        if let Some(content) = command.strip_prefix("DTH:") {
+            let content = content.strip_suffix(';').ok_or(RolandError::SyntaxError)?;
+            let (address, values) = content.split_once(',').ok_or(RolandError::SyntaxError)?;
+            let address = Address::from_hex(address).map_err(|_| RolandError::SyntaxError)?;
+            return Ok(
+                match parse_hex_values(values).map_err(|_| RolandError::SyntaxError)? {
+                    (value, None) => Command::WriteParameter { address, value },
+                    (_, Some(data)) => Command::WriteBlock { address, data },
+                },
+            );
+        }
+
+        // Parse RQH command: RQH:address,size;
+        if let Some(content) = command.strip_prefix("RQH:") {
+            let content = content.strip_suffix(';').ok_or(RolandError::SyntaxError)?;
+            let (address, size) = content.split_once(',').ok_or(RolandError::SyntaxError)?;
+            let address = Address::from_hex(address).map_err(|_| RolandError::SyntaxError)?;
+            if size.len() != 6 {
+                return Err(RolandError::SyntaxError);
+            }
+            let mut value = 0u32;
+            for i in 0..3 {
+                let byte = parse_hex_byte(size.get(i * 2..i * 2 + 2).unwrap_or(""))
+                    .map_err(|_| RolandError::SyntaxError)?;
+                value = (value << 8) | byte as u32;
+            }
+            return Ok(Command::ReadParameter {
+                address,
+                size: value,
+            });
+        }
+
+        Err(RolandError::SyntaxError)
+    }
+}
+
+/// Formatter writing into a byte buffer
+struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl fmt::Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
 }
 
 /// Write a 24-bit value as hex (6 hex digits, uppercase)
@@ -240,8 +608,129 @@ fn write_hex_u24<W: fmt::Write>(w: &mut W, value: u32) -> fmt::Result {
     write_hex_byte(w, (value & 0xFF) as u8)
 }
 
+/// Response variants accepted by [`Response::parse_with`] and [`Decoder`]
+///
+/// Firmware revisions differ in details the protocol documentation
+/// doesn't cover. The default is [`ParseOptions::STRICT`], which accepts
+/// only the documented form; [`ParseOptions::LENIENT`] accepts every
+/// variant seen from real devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParseOptions {
+    /// Match `DTH:`, `VER:`, `ERR:`, `ack`, `xon` and `xoff` in any case,
+    /// e.g. `Ver:`
+    pub case_insensitive_prefixes: bool,
+    /// Ignore whitespace after a frame, e.g. `;\r\n`
+    ///
+    /// [`Decoder`] always skips whitespace between frames; this is for
+    /// parsing whole lines.
+    pub allow_trailing_whitespace: bool,
+    /// Accept frames without the terminating `;`
+    ///
+    /// [`Decoder`] then also ends a frame at a line break.
+    pub allow_missing_semicolon: bool,
+    /// What answers a write besides ACK and ERR
+    ///
+    /// See [`engine::answers_with`].
+    pub write_answer: WriteAnswer,
+}
+
+/// What answers a write besides ACK and ERR, see
+/// [`ParseOptions::write_answer`]
+///
+/// Some firmware, e.g. 2.x, answers a write with a DTH of the address
+/// written instead of ACK. Even [`ParseOptions::LENIENT`] waits for the
+/// ACK, since with local echo turned on the echo of a write is the same
+/// DTH.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteAnswer {
+    /// Nothing, as documented
+    #[default]
+    AckOnly,
+    /// A DTH of the address written, whatever value it holds
+    Dth,
+    /// A DTH of the address written, which must hold the value written
+    ///
+    /// Clients report another value as a mismatch, so don't use this for
+    /// parameters the device clamps or quantizes.
+    DthSameValue,
+}
+
+impl ParseOptions {
+    /// Only the documented frame format
+    pub const STRICT: ParseOptions = ParseOptions {
+        case_insensitive_prefixes: false,
+        allow_trailing_whitespace: false,
+        allow_missing_semicolon: false,
+        write_answer: WriteAnswer::AckOnly,
+    };
+
+    /// Every variant, for talking to real firmware
+    pub const LENIENT: ParseOptions = ParseOptions {
+        case_insensitive_prefixes: true,
+        allow_trailing_whitespace: true,
+        allow_missing_semicolon: true,
+        write_answer: WriteAnswer::AckOnly,
+    };
+
+    /// Check if `text` starts with `prefix`
+    pub(crate) fn prefix_matches(&self, text: &[u8], prefix: &str) -> bool {
+        match text.get(..prefix.len()) {
+            Some(start) if self.case_insensitive_prefixes => {
+                start.eq_ignore_ascii_case(prefix.as_bytes())
+            }
+            Some(start) => start == prefix.as_bytes(),
+            None => false,
+        }
+    }
+
+    /// Remove `prefix` from the start of `text`
+    fn strip_prefix<'a>(&self, text: &'a str, prefix: &str) -> Option<&'a str> {
+        // The prefix is ASCII, so a match ends on a character boundary
+        self.prefix_matches(text.as_bytes(), prefix)
+            .then(|| &text[prefix.len()..])
+    }
+
+    /// Remove the terminating `;` from the content of a frame
+    fn strip_terminator<'a>(&self, content: &'a str) -> Result<&'a str, RolandError> {
+        match content.strip_suffix(';') {
+            Some(content) => Ok(content),
+            None if self.allow_missing_semicolon => Ok(content),
+            None => Err(RolandError::InvalidResponse),
+        }
+    }
+}
+
 /// Response types from VR-6HD
+///
+/// Prefer the accessors over matching on variants, which needs a wildcard
+/// arm outside this crate:
+///
+/// ```
+/// use roland_core::{Address, Response};
+///
+/// let response = Response::parse("DTH:123456,01,02;").unwrap();
+/// assert_eq!(response.as_data(), Some((Address::new(0x12, 0x34, 0x56), &[1, 2][..])));
+/// assert!(Response::parse("\x06").unwrap().is_ack());
+/// ```
+///
+/// ```compile_fail
+/// use roland_core::Response;
+///
+/// fn describe(response: &Response) -> &'static str {
+///     // Error: new variants may be added, so this match isn't exhaustive
+///     match response {
+///         Response::Acknowledge => "ack",
+///         Response::Data { .. } => "data",
+///         Response::DataBlock { .. } => "block",
+///         Response::Version { .. } => "version",
+///         Response::Error(_) => "error",
+///         Response::Xon => "xon",
+///         Response::Xoff => "xoff",
+///     }
+/// }
+/// ```
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Response {
     /// Acknowledge (ack)
     Acknowledge,
@@ -252,95 +741,238 @@ pub enum Response {
         /// Parameter value
         value: u8,
     },
+    /// Data response with several values (DTH)
+    ///
+    /// `data[0]` is the value at `address`, `data[1]` the value at the
+    /// next address and so on. This is how the device answers reads of
+    /// more than one byte.
+    DataBlock {
+        /// SysEx address of the first byte
+        address: Address,
+        /// Parameter values
+        data: Vec<u8>,
+    },
     /// Version information (VER)
     Version {
         /// Product name
         product: String,
-        /// Version string
+        /// Version string: everything after the product, which may hold
+        /// further comma-separated fields (e.g. `SYS,2.01`), or be empty
         version: String,
     },
     /// Error response (ERR)
     Error(RolandError),
+    /// Device is ready to receive again (XON, 0x11)
+    Xon,
+    /// Device can't receive more commands for now (XOFF, 0x13)
+    Xoff,
 }
 
 impl Response {
+    /// Check if this is an acknowledge
+    pub fn is_ack(&self) -> bool {
+        matches!(self, Response::Acknowledge)
+    }
+
+    /// Get the address and values of a DTH response
+    ///
+    /// Works for both single and multi-byte responses.
+    pub fn as_data(&self) -> Option<(Address, &[u8])> {
+        match self {
+            Response::Data { address, value } => Some((*address, core::slice::from_ref(value))),
+            Response::DataBlock { address, data } => Some((*address, data)),
+            _ => None,
+        }
+    }
+
+    /// Get the product and version of a VER response
+    pub fn as_version(&self) -> Option<(&str, &str)> {
+        match self {
+            Response::Version { product, version } => Some((product, version)),
+            _ => None,
+        }
+    }
+
+    /// Get the error of an ERR response
+    pub fn as_error(&self) -> Option<&RolandError> {
+        match self {
+            Response::Error(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Check if this is XON or XOFF
+    pub fn is_flow_control(&self) -> bool {
+        matches!(self, Response::Xon | Response::Xoff)
+    }
+
+    /// Parse the first complete frame of received data
+    ///
+    /// Whitespace before the frame is skipped, and anything after it is
+    /// left alone, so this can be called repeatedly on a receive buffer.
+    /// [`Decoder`] does the same for data arriving in chunks.
+    ///
+    /// # Returns
+    /// * `Option<(Result<Self, RolandError>, usize)>` - Parsed frame and
+    ///   the number of bytes it took up including leading whitespace, or
+    ///   `None` if there is no complete frame yet
+    ///
+    /// # Example
+    /// ```
+    /// use roland_core::Response;
+    ///
+    /// let data = b"\r\nack\r\nDTH:000000,01;";
+    /// let (response, used) = Response::parse_first(data).unwrap();
+    /// assert_eq!(response, Ok(Response::Acknowledge));
+    /// let (response, _) = Response::parse_first(&data[used..]).unwrap();
+    /// assert!(response.unwrap().as_data().is_some());
+    /// ```
+    pub fn parse_first(input: &[u8]) -> Option<(Result<Self, RolandError>, usize)> {
+        let start = decoder::skip_whitespace(input);
+        let len = decoder::frame_len(&input[start..])?;
+        let frame = String::from_utf8_lossy(&input[start..start + len]);
+        Some((Self::parse(&frame), start + len))
+    }
+
     /// Parse response from string slice
     ///
-    /// Handles both Telnet (no STX) and RS-232 (with STX) formats.
+    /// Handles both Telnet (no STX) and RS-232 (with STX) formats. The
+    /// input must be exactly one frame: whitespace or data around it is
+    /// rejected with `UnframedData`. Use [`Response::parse_first`] or
+    /// [`Decoder`] to split received data into frames.
     ///
     /// Requires `alloc` for String allocation in Version response.
     pub fn parse(response: &str) -> Result<Self, RolandError> {
-        let response = response.trim();
+        Self::parse_with(response, ParseOptions::STRICT)
+    }
 
+    /// Parse response from string slice, accepting the variants enabled
+    /// in `options`
+    ///
+    /// # Example
+    /// ```
+    /// use roland_core::{ParseOptions, Response};
+    ///
+    /// assert!(Response::parse("Ver:VR-6HD,1.00;\r\n").is_err());
+    /// let response = Response::parse_with("Ver:VR-6HD,1.00;\r\n", ParseOptions::LENIENT);
+    /// assert_eq!(response.unwrap().as_version(), Some(("VR-6HD", "1.00")));
+    /// ```
+    pub fn parse_with(response: &str, options: ParseOptions) -> Result<Self, RolandError> {
         // Remove STX if present (0x02)
         let response = response.strip_prefix('\x02').unwrap_or(response);
+        let response = if options.allow_trailing_whitespace {
+            response.trim_end()
+        } else {
+            response
+        };
+
+        let bytes = response.as_bytes();
+        if bytes.first().is_some_and(u8::is_ascii_whitespace) {
+            return Err(RolandError::UnframedData);
+        }
+        if decoder::frame_len_with(bytes, options).is_some_and(|len| len < bytes.len()) {
+            return Err(RolandError::UnframedData);
+        }
+
+        let text_is =
+            |text: &str| response.len() == text.len() && options.prefix_matches(bytes, text);
 
         // Handle ACK (0x06)
-        if response == "\x06" || response == "ack" {
+        if response == "\x06" || text_is("ack") {
             return Ok(Response::Acknowledge);
         }
 
         // Handle XON/XOFF (flow control)
-        // These are handled by the transport layer, but we can detect them
-        if response == "\x11" || response == "xon" {
-            // XON - can continue sending (not an error, but transport layer should handle)
-            return Err(RolandError::InvalidResponse);
+        if response == "\x11" || text_is("xon") {
+            return Ok(Response::Xon);
         }
-        if response == "\x13" || response == "xoff" {
-            // XOFF - pause sending (not an error, but transport layer should handle)
-            return Err(RolandError::InvalidResponse);
+        if response == "\x13" || text_is("xoff") {
+            return Ok(Response::Xoff);
         }
 
-        // Parse DTH response: DTH:address,value;
-        if let Some(content) = response.strip_prefix("DTH:") {
-            if !content.ends_with(';') {
-                return Err(RolandError::InvalidResponse);
-            }
-            let content = &content[..content.len() - 1];
-            let parts: Vec<&str> = content.split(',').collect();
-            if parts.len() != 2 {
-                return Err(RolandError::InvalidResponse);
-            }
-            let address = Address::from_hex(parts[0])?;
-            let value = parse_hex_byte(parts[1])?;
-            return Ok(Response::Data { address, value });
+        // Parse DTH response: DTH:address,value; or DTH:address,value,value...;
+        if let Some(content) = options.strip_prefix(response, "DTH:") {
+            let content = options.strip_terminator(content)?;
+            let (address, values) = content
+                .split_once(',')
+                .ok_or(RolandError::InvalidResponse)?;
+            let address = Address::from_hex(address)?;
+            return Ok(match parse_hex_values(values)? {
+                (value, None) => Response::Data { address, value },
+                (_, Some(data)) => Response::DataBlock { address, data },
+            });
         }
 
         // Parse VER response: VER:product,version;
-        if let Some(content) = response.strip_prefix("VER:") {
-            if !content.ends_with(';') {
-                return Err(RolandError::InvalidResponse);
-            }
-            let content = &content[..content.len() - 1];
-            let parts: Vec<&str> = content.split(',').collect();
-            if parts.len() != 2 {
-                return Err(RolandError::InvalidResponse);
-            }
+        //
+        // Some firmware sends more fields (`VER:VR-6HD,SYS,2.01;`), or
+        // none after the product; everything after the first comma is the
+        // version.
+        if let Some(content) = options.strip_prefix(response, "VER:") {
+            let content = options.strip_terminator(content)?;
+            let (product, version) = content.split_once(',').unwrap_or((content, ""));
             return Ok(Response::Version {
-                product: parts[0].to_string(),
-                version: parts[1].to_string(),
+                product: product.to_string(),
+                version: version.to_string(),
             });
         }
 
         // Parse ERR response: ERR:code;
-        if let Some(content) = response.strip_prefix("ERR:") {
-            if !content.ends_with(';') {
-                return Err(RolandError::InvalidResponse);
-            }
-            let content = &content[..content.len() - 1];
+        if let Some(content) = options.strip_prefix(response, "ERR:") {
+            let content = options.strip_terminator(content)?;
             let code = parse_decimal_u8(content)?;
-            let error = match code {
-                0 => RolandError::SyntaxError,
-                4 => RolandError::Invalid,
-                5 => RolandError::OutOfRange,
-                6 => RolandError::NoStx,
-                _ => RolandError::UnknownError(code),
-            };
-            return Ok(Response::Error(error));
+            return Ok(Response::Error(RolandError::from_code(code)));
         }
 
         Err(RolandError::InvalidResponse)
     }
+
+    /// Encode response to string format
+    ///
+    /// This is the format the device sends, which is useful for
+    /// implementing device simulators. Errors that have no device error
+    /// code are encoded as `ERR:0;` (syntax error).
+    ///
+    /// Requires `alloc` for String allocation.
+    pub fn encode(&self) -> String {
+        let mut encoded = String::new();
+        // Writing to a String can't fail
+        let _ = self.write(&mut encoded);
+        encoded
+    }
+
+    /// Write response to a formatter, in the format of [`Response::encode`]
+    ///
+    /// This method doesn't require `alloc` and can be used in `no_std` environments
+    /// without heap allocation, e.g. to log responses in the same form as a
+    /// Telnet capture.
+    pub fn write<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        match self {
+            Response::Acknowledge => w.write_str("\x06"),
+            Response::Data { address, value } => {
+                w.write_str("DTH:")?;
+                address.write_hex(w)?;
+                w.write_str(",")?;
+                write_hex_byte(w, *value)?;
+                w.write_str(";")
+            }
+            Response::DataBlock { address, data } => {
+                w.write_str("DTH:")?;
+                address.write_hex(w)?;
+                for value in data {
+                    w.write_str(",")?;
+                    write_hex_byte(w, *value)?;
+                }
+                w.write_str(";")
+            }
+            Response::Version { product, version } => {
+                write!(w, "VER:{},{};", product, version)
+            }
+            Response::Error(e) => write!(w, "ERR:{};", e.code().unwrap_or(0)),
+            Response::Xon => w.write_str("\x11"),
+            Response::Xoff => w.write_str("\x13"),
+        }
+    }
 }
 
 /// Parse a decimal u8
@@ -362,6 +994,7 @@ fn parse_decimal_u8(s: &str) -> Result<u8, RolandError> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::vec;
 
     #[test]
     fn test_address_from_hex() {
@@ -371,6 +1004,19 @@ mod tests {
         assert_eq!(addr.low, 0x56);
     }
 
+    #[test]
+    fn test_address_try_from() {
+        assert_eq!(
+            Address::try_from("0A0b0C"),
+            Ok(Address::new(0x0A, 0x0B, 0x0C))
+        );
+        assert_eq!(Address::try_from("0A0B0"), Err(RolandError::InvalidAddress));
+        assert_eq!(
+            Address::try_from("0A0B0G"),
+            Err(RolandError::InvalidAddress)
+        );
+    }
+
     #[test]
     fn test_address_to_hex() {
         let addr = Address::new(0x12, 0x34, 0x56);
@@ -414,6 +1060,43 @@ mod tests {
         assert_eq!(cmd.encode(), "RQH:123456,000001;");
     }
 
+    #[test]
+    fn test_command_constructors() {
+        let address = Address::new(0x12, 0x34, 0x56);
+        assert_eq!(
+            Command::read(address, 1),
+            Ok(Command::ReadParameter { address, size: 1 })
+        );
+        assert_eq!(
+            Command::read(address, 0xFF_FFFF).unwrap().encode(),
+            "RQH:123456,FFFFFF;"
+        );
+        assert_eq!(Command::read(address, 0), Err(RolandError::OutOfRange));
+        assert_eq!(
+            Command::read(address, 0x100_0000),
+            Err(RolandError::OutOfRange)
+        );
+        assert_eq!(
+            Command::write_parameter(address, 0x7F).encode(),
+            "DTH:123456,7F;"
+        );
+    }
+
+    #[test]
+    fn test_encode_to_slice() {
+        let cmd = Command::ReadParameter {
+            address: Address::new(0x12, 0x34, 0x56),
+            size: 1,
+        };
+        let mut buf = [0u8; 18];
+        assert_eq!(cmd.encode_to_slice(&mut buf), Ok(18));
+        assert_eq!(&buf, b"RQH:123456,000001;");
+        assert_eq!(
+            cmd.encode_to_slice(&mut [0u8; 17]),
+            Err(RolandError::BufferFull)
+        );
+    }
+
     #[test]
     fn test_version_command() {
         let cmd = Command::GetVersion;
@@ -450,6 +1133,138 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_version_fields() {
+        let cases = [
+            ("VER:VR-6HD;", "VR-6HD", ""),
+            ("VER:VR-6HD,1.00;", "VR-6HD", "1.00"),
+            ("VER:VR-6HD,SYS,2.01;", "VR-6HD", "SYS,2.01"),
+            ("VER:VR-6HD,SYS,2.01,B;", "VR-6HD", "SYS,2.01,B"),
+            ("VER:,1.00;", "", "1.00"),
+        ];
+        for (frame, product, version) in cases {
+            let resp = Response::parse(frame).unwrap();
+            assert_eq!(resp.as_version(), Some((product, version)), "{}", frame);
+            assert_eq!(Response::parse(&resp.encode()).unwrap(), resp, "{}", frame);
+        }
+    }
+
+    #[test]
+    fn test_parse_data_block_and_flow_control() {
+        let resp = Response::parse("DTH:100000,01,02,03,04;").unwrap();
+        assert_eq!(
+            resp,
+            Response::DataBlock {
+                address: Address::new(0x10, 0x00, 0x00),
+                data: vec![0x01, 0x02, 0x03, 0x04],
+            }
+        );
+        assert_eq!(resp.encode(), "DTH:100000,01,02,03,04;");
+        assert_eq!(
+            resp.as_data(),
+            Some((Address::new(0x10, 0x00, 0x00), &[1, 2, 3, 4][..]))
+        );
+        assert!(Response::parse("DTH:100000;").is_err());
+
+        assert_eq!(Response::parse("\x11").unwrap(), Response::Xon);
+        assert_eq!(Response::parse("xoff").unwrap(), Response::Xoff);
+        assert!(Response::Xoff.is_flow_control());
+        assert!(!Response::Xoff.is_ack());
+    }
+
+    #[test]
+    fn test_parse_rejects_unframed_data() {
+        for response in [
+            "ack\r\n",
+            "\r\nack",
+            "\x06\x06",
+            "DTH:000000,01;DTH",
+            "VER:VR-6HD,1.00; ",
+        ] {
+            assert_eq!(
+                Response::parse(response),
+                Err(RolandError::UnframedData),
+                "{:?}",
+                response
+            );
+        }
+        assert_eq!(
+            Response::parse("\x02DTH:000000,01;"),
+            Ok(Response::Data {
+                address: Address::new(0x00, 0x00, 0x00),
+                value: 0x01,
+            })
+        );
+
+        // A single read holding two frames with line breaks
+        let data = b"\r\nack\r\nDTH:000000,01;";
+        let (first, used) = Response::parse_first(data).unwrap();
+        assert_eq!(first, Ok(Response::Acknowledge));
+        assert_eq!(used, 5);
+        let (second, used) = Response::parse_first(&data[5..]).unwrap();
+        assert_eq!(
+            second,
+            Ok(Response::Data {
+                address: Address::new(0x00, 0x00, 0x00),
+                value: 0x01,
+            })
+        );
+        assert_eq!(5 + used, data.len());
+        assert_eq!(Response::parse_first(b"\r\nDTH:0000"), None);
+    }
+
+    #[test]
+    fn test_accessors() {
+        assert!(Response::Acknowledge.is_ack());
+        assert_eq!(Response::Acknowledge.as_data(), None);
+        let version = Response::parse("VER:VR-6HD,1.00;").unwrap();
+        assert_eq!(version.as_version(), Some(("VR-6HD", "1.00")));
+        let error = Response::parse("ERR:5;").unwrap();
+        assert_eq!(error.as_error(), Some(&RolandError::OutOfRange));
+
+        assert!(RolandError::Invalid.is_device_error());
+        assert!(RolandError::UnknownError(9).is_device_error());
+        assert!(!RolandError::InvalidResponse.is_device_error());
+        assert!(!RolandError::ChecksumMismatch.is_device_error());
+    }
+
+    #[test]
+    fn test_address_order() {
+        assert!(Address::new(0x00, 0xFF, 0x00) < Address::new(0x01, 0x00, 0x00));
+        assert!(Address::new(0x00, 0x00, 0xFF) < Address::new(0x00, 0x01, 0x00));
+        assert!(Address::new(0x12, 0x34, 0x56) > Address::new(0x12, 0x34, 0x55));
+        assert!(Address::MIN < Address::MAX);
+
+        let mut addresses = vec![Address::MAX, Address::new(0x01, 0x00, 0x00), Address::MIN];
+        addresses.sort();
+        assert_eq!(
+            addresses,
+            vec![Address::MIN, Address::new(0x01, 0x00, 0x00), Address::MAX]
+        );
+    }
+
+    #[test]
+    fn test_address_u32() {
+        let address = Address::new(0x12, 0x34, 0x56);
+        assert_eq!(u32::from(address), 0x123456);
+        assert_eq!(Address::try_from(0x123456), Ok(address));
+        assert_eq!(Address::try_from(0xFF_FFFF), Ok(Address::MAX));
+        assert_eq!(
+            Address::try_from(0x100_0000),
+            Err(RolandError::InvalidAddress)
+        );
+
+        assert_eq!(
+            Address::new(0x00, 0xFF, 0xFF).successor(),
+            Some(Address::new(0x01, 0x00, 0x00))
+        );
+        assert_eq!(
+            Address::new(0x12, 0x34, 0x56).successor(),
+            Some(Address::new(0x12, 0x34, 0x57))
+        );
+        assert_eq!(Address::MAX.successor(), None);
+    }
+
     #[test]
     fn test_parse_error() {
         let resp = Response::parse("ERR:0;").unwrap();
@@ -458,4 +1273,186 @@ mod tests {
             _ => panic!("Expected SyntaxError"),
         }
     }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Command::parse("VER;").unwrap(), Command::GetVersion);
+        assert_eq!(
+            Command::parse("\x02RQH:123456,000001;").unwrap(),
+            Command::ReadParameter {
+                address: Address::new(0x12, 0x34, 0x56),
+                size: 1,
+            }
+        );
+        assert_eq!(
+            Command::parse("DTH:123456,7F;").unwrap(),
+            Command::WriteParameter {
+                address: Address::new(0x12, 0x34, 0x56),
+                value: 0x7F,
+            }
+        );
+    }
+
+    #[test]
+    fn test_write_block() {
+        let cmd = Command::WriteBlock {
+            address: Address::new(0x02, 0x00, 0x02),
+            data: vec![0x03, 0xE8, 0x00],
+        };
+        assert_eq!(cmd.encode(), "DTH:020002,03,E8,00;");
+
+        let mut buf = String::new();
+        cmd.write(&mut buf).unwrap();
+        assert_eq!(buf, cmd.encode());
+        assert_eq!(Command::parse(&buf).unwrap(), cmd);
+    }
+
+    #[test]
+    fn test_parse_command_invalid() {
+        assert_eq!(
+            Command::parse("DTH:123456,7F"),
+            Err(RolandError::SyntaxError)
+        );
+        assert_eq!(
+            Command::parse("DTH:12345,7F;"),
+            Err(RolandError::SyntaxError)
+        );
+        assert_eq!(
+            Command::parse("RQH:123456,1;"),
+            Err(RolandError::SyntaxError)
+        );
+        assert_eq!(Command::parse("FOO;"), Err(RolandError::SyntaxError));
+    }
+
+    #[test]
+    fn test_encode_response() {
+        let responses = [
+            Response::Acknowledge,
+            Response::Data {
+                address: Address::new(0x12, 0x34, 0x56),
+                value: 0x01,
+            },
+            Response::Version {
+                product: "VR-6HD".to_string(),
+                version: "1.00".to_string(),
+            },
+            Response::Error(RolandError::OutOfRange),
+        ];
+        for resp in responses {
+            assert_eq!(Response::parse(&resp.encode()).unwrap(), resp);
+        }
+        assert_eq!(Response::Error(RolandError::Invalid).encode(), "ERR:4;");
+    }
+
+    #[test]
+    fn test_write_response_matches_encode() {
+        let responses = [
+            Response::Acknowledge,
+            Response::DataBlock {
+                address: Address::new(0x12, 0x34, 0x56),
+                data: vec![0x01, 0x7F],
+            },
+            Response::Version {
+                product: "VR-6HD".to_string(),
+                version: "1.00".to_string(),
+            },
+            Response::Error(RolandError::Invalid),
+            Response::Xoff,
+        ];
+        for resp in responses {
+            let mut written = String::new();
+            resp.write(&mut written).unwrap();
+            assert_eq!(written, resp.encode());
+        }
+    }
+
+    /// Turns on one option flag
+    type Enable = fn(&mut ParseOptions);
+
+    /// Firmware variants by the option that accepts them, each next to the
+    /// documented frame it must parse like
+    ///
+    /// A newly observed quirk is one more line here.
+    const QUIRKS: &[(Enable, &[(&str, &str)])] = &[
+        (
+            |options| options.case_insensitive_prefixes = true,
+            &[
+                ("Ver:VR-6HD,1.00;", "VER:VR-6HD,1.00;"),
+                ("dth:123456,01;", "DTH:123456,01;"),
+                ("Err:5;", "ERR:5;"),
+                ("ACK", "ack"),
+                ("XOFF", "xoff"),
+                ("Xon", "xon"),
+            ],
+        ),
+        (
+            |options| options.allow_trailing_whitespace = true,
+            &[
+                ("DTH:123456,01;\r\n", "DTH:123456,01;"),
+                ("VER:VR-6HD,1.00; ", "VER:VR-6HD,1.00;"),
+                ("ack\r\n", "ack"),
+                ("\x06\n", "\x06"),
+            ],
+        ),
+        (
+            |options| options.allow_missing_semicolon = true,
+            &[
+                ("DTH:123456,01", "DTH:123456,01;"),
+                ("DTH:100000,01,02", "DTH:100000,01,02;"),
+                ("VER:VR-6HD,1.00", "VER:VR-6HD,1.00;"),
+                ("ERR:0", "ERR:0;"),
+            ],
+        ),
+    ];
+
+    #[test]
+    fn test_parse_options() {
+        for (enable, variants) in QUIRKS {
+            let mut options = ParseOptions::default();
+            enable(&mut options);
+            for (variant, documented) in *variants {
+                let expected = Response::parse(documented).unwrap();
+                assert!(Response::parse(variant).is_err(), "{:?}", variant);
+                assert_eq!(
+                    Response::parse_with(variant, options),
+                    Ok(expected.clone()),
+                    "{:?}",
+                    variant
+                );
+                assert_eq!(
+                    Response::parse_with(variant, ParseOptions::LENIENT),
+                    Ok(expected),
+                    "{:?}",
+                    variant
+                );
+            }
+        }
+        assert_eq!(ParseOptions::default(), ParseOptions::STRICT);
+    }
+
+    #[test]
+    fn test_decoder_options() {
+        for (enable, variants) in QUIRKS {
+            let mut options = ParseOptions::default();
+            enable(&mut options);
+            for (variant, documented) in *variants {
+                // Frames are followed by a line break or the next frame
+                let mut decoder = Decoder::with_options(options);
+                decoder.push(variant.as_bytes());
+                decoder.push(b"\r\nack");
+                assert_eq!(
+                    decoder.decode(),
+                    Some(Response::parse(documented)),
+                    "{:?}",
+                    variant
+                );
+                assert_eq!(decoder.decode(), Some(Ok(Response::Acknowledge)));
+            }
+        }
+
+        // Without the option, a frame only ends at `;`
+        let mut decoder = Decoder::new();
+        decoder.push(b"DTH:123456,01\r\n");
+        assert_eq!(decoder.decode(), None);
+    }
 }
@@ -0,0 +1,113 @@
+//! Values split across 4-bit nibbles
+//!
+//! Parameters with values above `7F` are stored in several consecutive
+//! addresses, each holding one nibble (`00` to `0F`) of the value, most
+//! significant nibble first. A value of `0x1234` in four nibbles is
+//! `01 02 03 04`.
+
+use crate::RolandError;
+
+/// Largest number of nibbles a `u32` value fills
+pub const MAX_NIBBLES: usize = 8;
+
+/// Check if a value fits in `count` nibbles
+pub fn fits_nibbles(value: u32, count: usize) -> bool {
+    count >= MAX_NIBBLES || value >> (4 * count) == 0
+}
+
+/// Split a value into `count` nibbles, most significant first
+///
+/// Bits above the `count` nibbles are dropped; check
+/// [`fits_nibbles`] first. More than [`MAX_NIBBLES`] nibbles are padded
+/// with leading zeros.
+///
+/// # Example
+/// ```
+/// use roland_core::nibble::encode_nibbles;
+///
+/// let nibbles: Vec<u8> = encode_nibbles(0x1234, 4).collect();
+/// assert_eq!(nibbles, [0x01, 0x02, 0x03, 0x04]);
+/// ```
+pub fn encode_nibbles(value: u32, count: usize) -> impl Iterator<Item = u8> {
+    (0..count)
+        .rev()
+        .map(move |i| (value.checked_shr(4 * i as u32).unwrap_or(0) & 0x0F) as u8)
+}
+
+/// Join nibbles, most significant first, into a value
+///
+/// # Returns
+/// * `Result<u32, RolandError>` - Value, `InvalidValue` if there are no
+///   nibbles or one is above `0F`, or `OutOfRange` if the value doesn't
+///   fit in a `u32`
+pub fn decode_nibbles(nibbles: &[u8]) -> Result<u32, RolandError> {
+    if nibbles.is_empty() {
+        return Err(RolandError::InvalidValue);
+    }
+    nibbles.iter().try_fold(0u32, |value, &nibble| {
+        if nibble > 0x0F {
+            return Err(RolandError::InvalidValue);
+        }
+        if value >> 28 != 0 {
+            return Err(RolandError::OutOfRange);
+        }
+        Ok(value << 4 | nibble as u32)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_round_trip() {
+        for count in 1..=MAX_NIBBLES {
+            let max = if count == MAX_NIBBLES {
+                u32::MAX
+            } else {
+                (1 << (4 * count)) - 1
+            };
+            for value in [0, 1, 0x0F, max / 2, max - 1, max] {
+                assert!(fits_nibbles(value, count));
+                let nibbles: Vec<u8> = encode_nibbles(value, count).collect();
+                assert_eq!(nibbles.len(), count);
+                assert!(nibbles.iter().all(|&n| n <= 0x0F));
+                assert_eq!(
+                    decode_nibbles(&nibbles),
+                    Ok(value),
+                    "{} in {}",
+                    value,
+                    count
+                );
+            }
+            if count < MAX_NIBBLES {
+                assert!(!fits_nibbles(max + 1, count));
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let nibbles: Vec<u8> = encode_nibbles(0xABC, 4).collect();
+        assert_eq!(nibbles, [0x00, 0x0A, 0x0B, 0x0C]);
+        let nibbles: Vec<u8> = encode_nibbles(0xFF, 10).collect();
+        assert_eq!(nibbles, [0, 0, 0, 0, 0, 0, 0, 0, 0x0F, 0x0F]);
+        assert_eq!(encode_nibbles(0xFF, 0).count(), 0);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(decode_nibbles(&[]), Err(RolandError::InvalidValue));
+        assert_eq!(
+            decode_nibbles(&[0x01, 0x10]),
+            Err(RolandError::InvalidValue)
+        );
+        assert_eq!(decode_nibbles(&[0x01; 9]), Err(RolandError::OutOfRange));
+        // Leading zeros beyond 8 nibbles are fine
+        assert_eq!(
+            decode_nibbles(&[0, 0, 0x0F, 0, 0, 0, 0, 0, 0, 0]),
+            Ok(0xF000_0000)
+        );
+    }
+}
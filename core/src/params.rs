@@ -0,0 +1,784 @@
+//! VR-6HD parameter map
+//!
+//! Named addresses for the remote control parameters of the VR-6HD, so
+//! applications don't have to keep their own table of magic hex values.
+//! The map is organized by block, one submodule per block. Values are
+//! single bytes unless noted otherwise.
+//!
+//! # Example
+//! ```
+//! use roland_core::params::audio;
+//! use roland_core::Command;
+//!
+//! // Mute CH3
+//! let cmd = Command::WriteParameter {
+//!     address: audio::CH3_MUTE,
+//!     value: 1,
+//! };
+//! assert_eq!(cmd.encode(), "DTH:050201,01;");
+//! ```
+
+/// Video switcher (block `01`)
+pub mod video {
+    use crate::signed::SignedEncoding;
+    use crate::Address;
+
+    /// Number of video input channels (HDMI 1-4, STILL 1-2)
+    pub const INPUT_COUNT: u8 = 6;
+
+    /// Program (PGM) channel select
+    ///
+    /// 0-5: video channel 1-6 (HDMI 1-4, STILL 1-2)
+    pub const PGM_SELECT: Address = Address::new(0x01, 0x00, 0x00);
+
+    /// Preset (PST) channel select
+    ///
+    /// 0-5: video channel 1-6 (HDMI 1-4, STILL 1-2)
+    pub const PST_SELECT: Address = Address::new(0x01, 0x00, 0x01);
+
+    /// Transition type
+    ///
+    /// 0: MIX, 1: WIPE
+    pub const TRANSITION_TYPE: Address = Address::new(0x01, 0x00, 0x02);
+
+    /// Transition time
+    ///
+    /// 0-40: 0.0-4.0 seconds in 0.1 second steps
+    pub const TRANSITION_TIME: Address = Address::new(0x01, 0x00, 0x03);
+
+    /// Wipe pattern
+    ///
+    /// 0: horizontal, 1: vertical, 2: upper left, 3: upper right,
+    /// 4: lower left, 5: lower right, 6: horizontal center,
+    /// 7: vertical center, 8: box, 9: circle
+    pub const WIPE_PATTERN: Address = Address::new(0x01, 0x00, 0x04);
+
+    /// Mix effect
+    ///
+    /// 0: MIX, 1: FAM (full additive mix), 2: NAM (non-additive mix)
+    pub const MIX_EFFECT: Address = Address::new(0x01, 0x00, 0x05);
+
+    /// CUT (write only)
+    ///
+    /// 1: switch PST to PGM immediately
+    pub const CUT: Address = Address::new(0x01, 0x00, 0x10);
+
+    /// AUTO TAKE (write only)
+    ///
+    /// 1: switch PST to PGM using the transition effect and time
+    pub const AUTO_TAKE: Address = Address::new(0x01, 0x00, 0x11);
+
+    /// Transition status (read only)
+    ///
+    /// 0: idle, 1: an AUTO TAKE transition is in progress
+    pub const TRANSITION_STATUS: Address = Address::new(0x01, 0x00, 0x12);
+
+    /// Manual transition
+    ///
+    /// 0: off, 1: the transition follows [`TRANSITION_POSITION`]
+    pub const MANUAL_TRANSITION: Address = Address::new(0x01, 0x00, 0x13);
+
+    /// Transition position, like a T-bar (single byte)
+    ///
+    /// 0-100: 0-100% from PGM to PST, while [`MANUAL_TRANSITION`] is on
+    pub const TRANSITION_POSITION: Address = Address::new(0x01, 0x00, 0x14);
+
+    /// HDMI 1 input assign
+    ///
+    /// 0-3: HDMI IN 1-4
+    pub const HDMI1_INPUT_ASSIGN: Address = Address::new(0x01, 0x01, 0x00);
+
+    /// HDMI 2 input assign
+    ///
+    /// 0-3: HDMI IN 1-4
+    pub const HDMI2_INPUT_ASSIGN: Address = Address::new(0x01, 0x01, 0x01);
+
+    /// HDMI 3 input assign
+    ///
+    /// 0-3: HDMI IN 1-4
+    pub const HDMI3_INPUT_ASSIGN: Address = Address::new(0x01, 0x01, 0x02);
+
+    /// HDMI 4 input assign
+    ///
+    /// 0-3: HDMI IN 1-4
+    pub const HDMI4_INPUT_ASSIGN: Address = Address::new(0x01, 0x01, 0x03);
+
+    /// Auto switching mode
+    ///
+    /// 0: off, 1: pattern (cycle through the inputs), 2: video follows
+    /// audio
+    pub const AUTO_SWITCH_MODE: Address = Address::new(0x01, 0x02, 0x00);
+
+    /// Auto switching pattern interval
+    ///
+    /// 1-120: 1-120 seconds
+    pub const AUTO_SWITCH_INTERVAL: Address = Address::new(0x01, 0x02, 0x01);
+
+    /// Address of the video-follows-audio threshold of an audio input
+    ///
+    /// `channel` is 0-5 for CH1-6; the thresholds are consecutive.
+    /// 0: unset, 1-127: -63.0 to 0.0 dBFS in 0.5 dB steps, like the
+    /// [`meter`](super::meter) scale.
+    pub const fn audio_follow_threshold(channel: u8) -> Address {
+        Address::new(0x01, 0x03, channel)
+    }
+
+    /// Video sent to the USB output
+    ///
+    /// 0: program, 1: AUX bus, 2: multi-view
+    pub const USB_OUTPUT_SOURCE: Address = Address::new(0x01, 0x04, 0x00);
+
+    /// Program output freeze (0: live, 1: frozen)
+    pub const FREEZE: Address = Address::new(0x01, 0x05, 0x00);
+
+    /// Address of the freeze of an HDMI input
+    ///
+    /// `input` is 0-3 for HDMI 1-4. 0: live, 1: frozen wherever the input
+    /// is shown
+    pub const fn input_freeze(input: u8) -> Address {
+        Address::new(0x01, 0x05, 0x10 + input)
+    }
+
+    /// First byte of the scaling block of HDMI 1
+    pub const SCALING_BASE: Address = Address::new(0x01, 0x06, 0x00);
+
+    /// Distance between the scaling blocks of consecutive HDMI inputs
+    pub const SCALING_STRIDE: u32 = 0x10;
+
+    /// Zoom offset within a scaling block
+    ///
+    /// 0-100: 0 shows the whole picture, 100 is the largest magnification
+    pub const SCALING_ZOOM: u8 = 0x00;
+
+    /// Horizontal position offset within a scaling block
+    ///
+    /// 0-200: -100 (left) to +100 (right) percent, 100 is center
+    pub const SCALING_POSITION_H: u8 = 0x01;
+
+    /// Vertical position offset within a scaling block
+    ///
+    /// 0-200: -100 (bottom) to +100 (top) percent, 100 is center
+    pub const SCALING_POSITION_V: u8 = 0x02;
+
+    /// Encoding of [`SCALING_POSITION_H`] and [`SCALING_POSITION_V`] values
+    pub const SCALING_POSITION_ENCODING: SignedEncoding =
+        SignedEncoding::OffsetBinary { zero: 100 };
+
+    /// Flip offset within a scaling block
+    ///
+    /// Bit 0: flip horizontally, bit 1: flip vertically
+    pub const SCALING_FLIP: u8 = 0x03;
+}
+
+/// Picture-in-picture (block `02`)
+pub mod pinp {
+    use crate::Address;
+
+    /// PinP on/off
+    ///
+    /// 0: off, 1: on
+    pub const ENABLE: Address = Address::new(0x02, 0x00, 0x00);
+
+    /// PinP source
+    ///
+    /// 0-5: video channel 1-6 (HDMI 1-4, STILL 1-2)
+    pub const SOURCE: Address = Address::new(0x02, 0x00, 0x01);
+
+    /// Horizontal window position (2 bytes, MSB first)
+    ///
+    /// 0-2000: -1000 (left edge) to +1000 (right edge), 1000 is center
+    pub const POSITION_H: Address = Address::new(0x02, 0x00, 0x02);
+
+    /// Vertical window position (2 bytes, MSB first)
+    ///
+    /// 0-2000: -1000 (bottom edge) to +1000 (top edge), 1000 is center
+    pub const POSITION_V: Address = Address::new(0x02, 0x00, 0x04);
+
+    /// Window size
+    ///
+    /// 10-100: percent of the full screen
+    pub const SIZE: Address = Address::new(0x02, 0x00, 0x06);
+
+    /// Border color
+    ///
+    /// 0: white, 1: black, 2: red, 3: green, 4: blue, 5: yellow
+    pub const BORDER_COLOR: Address = Address::new(0x02, 0x00, 0x07);
+
+    /// Border width
+    ///
+    /// 0-15: 0 is no border
+    pub const BORDER_WIDTH: Address = Address::new(0x02, 0x00, 0x08);
+}
+
+/// Split composition (block `02`)
+pub mod split {
+    use crate::Address;
+
+    /// Split mode
+    ///
+    /// 0: off, 1: split (both inputs cropped), 2: squeeze (both inputs
+    /// scaled to fit)
+    pub const MODE: Address = Address::new(0x02, 0x01, 0x00);
+
+    /// Left source
+    ///
+    /// 0-5: video channel 1-6 (HDMI 1-4, STILL 1-2)
+    pub const LEFT_SOURCE: Address = Address::new(0x02, 0x01, 0x01);
+
+    /// Right source
+    ///
+    /// 0-5: video channel 1-6 (HDMI 1-4, STILL 1-2); must differ from
+    /// [`LEFT_SOURCE`]
+    pub const RIGHT_SOURCE: Address = Address::new(0x02, 0x01, 0x02);
+
+    /// Divider position
+    ///
+    /// 0-100: percent of the screen width from the left edge, 50 is center
+    pub const POSITION: Address = Address::new(0x02, 0x01, 0x03);
+}
+
+/// Downstream keyer (block `03`)
+pub mod dsk {
+    use crate::Address;
+
+    /// DSK on/off
+    ///
+    /// 0: off, 1: on
+    pub const ENABLE: Address = Address::new(0x03, 0x00, 0x00);
+
+    /// DSK source
+    ///
+    /// 0-5: video channel 1-6 (HDMI 1-4, STILL 1-2)
+    pub const SOURCE: Address = Address::new(0x03, 0x00, 0x01);
+
+    /// Key level
+    ///
+    /// 0-255: luminance threshold of the key, or how far from the key
+    /// color is keyed out for a chroma key
+    pub const KEY_LEVEL: Address = Address::new(0x03, 0x00, 0x02);
+
+    /// Key gain
+    ///
+    /// 0-255: softness of the key edge
+    pub const KEY_GAIN: Address = Address::new(0x03, 0x00, 0x03);
+
+    /// Fill type
+    ///
+    /// 0: bus (fill with the key source), 1: matte (fill with a color)
+    pub const FILL_TYPE: Address = Address::new(0x03, 0x00, 0x04);
+
+    /// DSK fade time used when turning the DSK on or off
+    ///
+    /// 0-40: 0.0-4.0 seconds in 0.1 second steps
+    pub const FADE_TIME: Address = Address::new(0x03, 0x00, 0x05);
+
+    /// Key type
+    ///
+    /// 0: luminance key, 1: chroma key
+    pub const KEY_TYPE: Address = Address::new(0x03, 0x00, 0x06);
+
+    /// Key color hue (chroma key)
+    ///
+    /// 0-255: hue angle in 1/256 turns, e.g. 85 is green and 170 is blue
+    pub const KEY_HUE: Address = Address::new(0x03, 0x00, 0x07);
+
+    /// Key color saturation (chroma key)
+    ///
+    /// 0-255: 0 is gray, 255 is fully saturated
+    pub const KEY_SATURATION: Address = Address::new(0x03, 0x00, 0x08);
+}
+
+/// Still images (block `04`)
+pub mod still {
+    use crate::Address;
+
+    /// Number of still image memories
+    pub const COUNT: u8 = 16;
+
+    /// Image shown on the STILL 1 video channel
+    ///
+    /// 0-15: still image memory 1-16
+    pub const STILL1_IMAGE: Address = Address::new(0x04, 0x00, 0x00);
+
+    /// Image shown on the STILL 2 video channel
+    ///
+    /// 0-15: still image memory 1-16
+    pub const STILL2_IMAGE: Address = Address::new(0x04, 0x00, 0x01);
+
+    /// Capture the program output (write only)
+    ///
+    /// 0-15: store the current PGM picture to still image memory 1-16
+    pub const CAPTURE: Address = Address::new(0x04, 0x00, 0x10);
+
+    /// Capture status (read only)
+    ///
+    /// 0: idle, 1: busy (a capture is in progress)
+    pub const CAPTURE_BUSY: Address = Address::new(0x04, 0x00, 0x11);
+}
+
+/// Audio input channels (block `05`)
+///
+/// Each input channel has its own sub-block: `05 cc pp`, where `cc` is the
+/// channel index (0-5: CH1-6, 6: USB, 7: Bluetooth) and `pp` the parameter.
+pub mod audio {
+    use crate::signed::SignedEncoding;
+    use crate::Address;
+
+    /// Fader level offset within a channel block
+    ///
+    /// 0-127: -INF to +10 dB
+    pub const LEVEL: u8 = 0x00;
+
+    /// Mute offset within a channel block
+    ///
+    /// 0: off, 1: on
+    pub const MUTE: u8 = 0x01;
+
+    /// Solo offset within a channel block
+    ///
+    /// 0: off, 1: on
+    pub const SOLO: u8 = 0x02;
+
+    /// Pan offset within a channel block
+    ///
+    /// 0-127: L64 to R63, 64 is center
+    pub const PAN: u8 = 0x03;
+
+    /// Encoding of [`PAN`] values, -64 (L64) to 63 (R63)
+    pub const PAN_ENCODING: SignedEncoding = SignedEncoding::OffsetBinary { zero: 64 };
+
+    /// EQ low band gain offset within a channel block
+    ///
+    /// 0-60: -15 to +15 dB in 0.5 dB steps, 30 is 0 dB
+    pub const EQ_LOW_GAIN: u8 = 0x10;
+
+    /// Encoding of EQ gain values, in 0.5 dB steps from -30 to 30
+    pub const EQ_GAIN_ENCODING: SignedEncoding = SignedEncoding::OffsetBinary { zero: 30 };
+
+    /// EQ mid band gain offset within a channel block
+    ///
+    /// 0-60: -15 to +15 dB in 0.5 dB steps, 30 is 0 dB
+    pub const EQ_MID_GAIN: u8 = 0x11;
+
+    /// EQ mid band frequency offset within a channel block
+    ///
+    /// 0-16: 200 Hz to 8 kHz in 1/3 octave steps
+    pub const EQ_MID_FREQ: u8 = 0x12;
+
+    /// EQ high band gain offset within a channel block
+    ///
+    /// 0-60: -15 to +15 dB in 0.5 dB steps, 30 is 0 dB
+    pub const EQ_HIGH_GAIN: u8 = 0x13;
+
+    /// Compressor on/off offset within a channel block
+    ///
+    /// 0: off, 1: on
+    pub const COMP_ENABLE: u8 = 0x20;
+
+    /// Compressor threshold offset within a channel block
+    ///
+    /// 0-40: -40 to 0 dB in 1 dB steps
+    pub const COMP_THRESHOLD: u8 = 0x21;
+
+    /// Compressor ratio offset within a channel block
+    ///
+    /// 1-16: 1:1 to 16:1
+    pub const COMP_RATIO: u8 = 0x22;
+
+    /// Compressor attack time offset within a channel block
+    ///
+    /// 0-100: 0 to 100 ms in 1 ms steps
+    pub const COMP_ATTACK: u8 = 0x23;
+
+    /// Compressor release time offset within a channel block
+    ///
+    /// 1-100: 10 to 1000 ms in 10 ms steps
+    pub const COMP_RELEASE: u8 = 0x24;
+
+    /// Compressor makeup gain offset within a channel block
+    ///
+    /// 0-24: 0 to +24 dB in 1 dB steps
+    pub const COMP_GAIN: u8 = 0x25;
+
+    /// Noise gate on/off offset within a channel block
+    ///
+    /// 0: off, 1: on
+    pub const GATE_ENABLE: u8 = 0x30;
+
+    /// Noise gate threshold offset within a channel block
+    ///
+    /// 0-80: -80 to 0 dB in 1 dB steps
+    pub const GATE_THRESHOLD: u8 = 0x31;
+
+    /// Noise gate release time offset within a channel block
+    ///
+    /// 1-100: 10 to 1000 ms in 10 ms steps
+    pub const GATE_RELEASE: u8 = 0x32;
+
+    /// Address of a parameter of an input channel
+    ///
+    /// `channel` is the channel index (0-5: CH1-6, 6: USB, 7: Bluetooth)
+    /// and `offset` one of the parameter offsets in this module.
+    pub const fn channel(channel: u8, offset: u8) -> Address {
+        Address::new(0x05, channel, offset)
+    }
+
+    /// CH1 fader level (0-127: -INF to +10 dB)
+    pub const CH1_LEVEL: Address = channel(0, LEVEL);
+    /// CH2 fader level (0-127: -INF to +10 dB)
+    pub const CH2_LEVEL: Address = channel(1, LEVEL);
+    /// CH3 fader level (0-127: -INF to +10 dB)
+    pub const CH3_LEVEL: Address = channel(2, LEVEL);
+    /// CH4 fader level (0-127: -INF to +10 dB)
+    pub const CH4_LEVEL: Address = channel(3, LEVEL);
+    /// CH5 fader level (0-127: -INF to +10 dB)
+    pub const CH5_LEVEL: Address = channel(4, LEVEL);
+    /// CH6 fader level (0-127: -INF to +10 dB)
+    pub const CH6_LEVEL: Address = channel(5, LEVEL);
+    /// USB audio input fader level (0-127: -INF to +10 dB)
+    pub const USB_LEVEL: Address = channel(6, LEVEL);
+    /// Bluetooth audio input fader level (0-127: -INF to +10 dB)
+    pub const BLUETOOTH_LEVEL: Address = channel(7, LEVEL);
+
+    /// CH1 mute (0: off, 1: on)
+    pub const CH1_MUTE: Address = channel(0, MUTE);
+    /// CH2 mute (0: off, 1: on)
+    pub const CH2_MUTE: Address = channel(1, MUTE);
+    /// CH3 mute (0: off, 1: on)
+    pub const CH3_MUTE: Address = channel(2, MUTE);
+    /// CH4 mute (0: off, 1: on)
+    pub const CH4_MUTE: Address = channel(3, MUTE);
+    /// CH5 mute (0: off, 1: on)
+    pub const CH5_MUTE: Address = channel(4, MUTE);
+    /// CH6 mute (0: off, 1: on)
+    pub const CH6_MUTE: Address = channel(5, MUTE);
+    /// USB audio input mute (0: off, 1: on)
+    pub const USB_MUTE: Address = channel(6, MUTE);
+    /// Bluetooth audio input mute (0: off, 1: on)
+    pub const BLUETOOTH_MUTE: Address = channel(7, MUTE);
+}
+
+/// Audio output buses (block `06`)
+///
+/// Each bus has its own sub-block: `06 bb pp`, where `bb` is the bus index
+/// (0: MAIN, 1: AUX, 2: USB OUT, 3: PHONES) and `pp` the parameter. The
+/// parameter offsets are the same as for input channels (see
+/// [`audio`](super::audio)); PHONES has a level only.
+pub mod output {
+    use crate::Address;
+
+    /// Address of a parameter of an output bus
+    pub const fn bus(bus: u8, offset: u8) -> Address {
+        Address::new(0x06, bus, offset)
+    }
+
+    /// MAIN output level (0-127: -INF to +10 dB)
+    pub const MAIN_LEVEL: Address = bus(0, super::audio::LEVEL);
+    /// MAIN output mute (0: off, 1: on)
+    pub const MAIN_MUTE: Address = bus(0, super::audio::MUTE);
+    /// AUX output level (0-127: -INF to +10 dB)
+    pub const AUX_LEVEL: Address = bus(1, super::audio::LEVEL);
+    /// AUX output mute (0: off, 1: on)
+    pub const AUX_MUTE: Address = bus(1, super::audio::MUTE);
+    /// USB OUT level (0-127: -INF to +10 dB)
+    pub const USB_OUT_LEVEL: Address = bus(2, super::audio::LEVEL);
+    /// USB OUT mute (0: off, 1: on)
+    pub const USB_OUT_MUTE: Address = bus(2, super::audio::MUTE);
+    /// Audio sent to USB OUT (0: MAIN bus, 1: AUX bus), see [`assign`]
+    pub const USB_OUT_SOURCE: Address = assign(2);
+    /// Headphone level (0-127: -INF to +10 dB)
+    pub const PHONES_LEVEL: Address = bus(3, super::audio::LEVEL);
+
+    /// Audio monitored on the headphones, see [`assign`]
+    ///
+    /// 0: MAIN bus, 1: AUX bus, 2: soloed channels (MAIN while nothing is
+    /// soloed)
+    pub const PHONES_SOURCE: Address = assign(3);
+
+    /// Where soloed channels are picked up
+    ///
+    /// 0: PFL (before the fader), 1: AFL (after the fader)
+    pub const SOLO_MODE: Address = Address::new(0x06, 0x03, 0x10);
+
+    /// Number of physical audio outputs
+    pub const OUTPUT_COUNT: u8 = 5;
+
+    /// Address of the bus assigned to a physical audio output
+    ///
+    /// `output` is 0: MAIN OUT, 1: AUX OUT, 2: USB OUT, 3: PHONES,
+    /// 4: HDMI OUT. 0: MAIN bus, 1: AUX bus; PHONES also takes the solo
+    /// bus, see [`PHONES_SOURCE`]
+    pub const fn assign(output: u8) -> Address {
+        Address::new(0x06, 0x20, output)
+    }
+
+    /// Address of the AUX send level of an input channel
+    ///
+    /// `channel` is the channel index as in [`audio`](super::audio).
+    /// 0-127: -INF to +10 dB, like the fader levels
+    pub const fn aux_send(channel: u8) -> Address {
+        Address::new(0x06, 0x20, 0x10 + channel)
+    }
+
+    /// First address of the routing block, from [`assign`]`(0)` to
+    /// [`aux_send`]`(7)`
+    pub const ROUTING_START: Address = assign(0);
+    /// Size of the routing block in bytes
+    pub const ROUTING_SIZE: u32 = 0x18;
+
+    /// Output fade (video and audio)
+    ///
+    /// 0: fade in (normal output), 1: fade out (black/silence)
+    pub const FADE: Address = Address::new(0x06, 0x10, 0x00);
+
+    /// Output fade time
+    ///
+    /// 0-40: 0.0-4.0 seconds in 0.1 second steps
+    pub const FADE_TIME: Address = Address::new(0x06, 0x10, 0x01);
+
+    /// Output fade target
+    ///
+    /// 0: video and audio, 1: video only, 2: audio only
+    pub const FADE_MODE: Address = Address::new(0x06, 0x10, 0x02);
+}
+
+/// Scene memory (block `07`)
+pub mod scene {
+    use crate::Address;
+
+    /// Number of scene memories
+    pub const COUNT: u8 = 30;
+
+    /// Scene recall (write only)
+    ///
+    /// 0-29: recall scene memory 1-30
+    pub const RECALL: Address = Address::new(0x07, 0x00, 0x00);
+
+    /// Scene store (write only)
+    ///
+    /// 0-29: store the current settings to scene memory 1-30
+    pub const STORE: Address = Address::new(0x07, 0x00, 0x01);
+
+    /// Scene operation status (read only)
+    ///
+    /// 0: idle, 1: busy (a recall or store is in progress)
+    pub const BUSY: Address = Address::new(0x07, 0x00, 0x02);
+
+    /// Length of a scene name in characters
+    pub const NAME_LENGTH: u32 = 16;
+
+    /// Address of the name of a scene memory
+    ///
+    /// `index` is 0-29 for scene memory 1-30. Each name is a block of
+    /// [`NAME_LENGTH`] ASCII characters, padded with spaces.
+    pub const fn name(index: u8) -> Address {
+        Address::new(0x07, 0x10 + index, 0x00)
+    }
+}
+
+/// Streaming and recording (block `08`)
+pub mod transport {
+    use crate::Address;
+
+    /// Streaming start/stop
+    ///
+    /// 0: stop, 1: start
+    pub const STREAMING: Address = Address::new(0x08, 0x00, 0x00);
+
+    /// Recording start/stop
+    ///
+    /// 0: stop, 1: start
+    pub const RECORDING: Address = Address::new(0x08, 0x00, 0x01);
+
+    /// Transport status (read only)
+    ///
+    /// Bit 0: streaming, bit 1: recording, bit 2: SD card present
+    pub const STATUS: Address = Address::new(0x08, 0x00, 0x10);
+
+    /// Elapsed streaming/recording time (3 bytes, read only)
+    ///
+    /// Hours, minutes and seconds, one byte each; [`ELAPSED_IDLE`] while
+    /// neither is running
+    pub const ELAPSED: Address = Address::new(0x08, 0x00, 0x11);
+
+    /// Value of [`ELAPSED`] while neither streaming nor recording
+    pub const ELAPSED_IDLE: [u8; 3] = [0x7F, 0x7F, 0x7F];
+
+    /// SD card status (read only)
+    ///
+    /// Bit 0: card present, bit 1: write protected. Other bits are
+    /// reserved.
+    pub const STORAGE_STATUS: Address = Address::new(0x08, 0x00, 0x20);
+
+    /// Remaining recording time on the SD card (2 bytes, read only)
+    ///
+    /// Minutes, high byte first; 0 without a card
+    pub const STORAGE_REMAINING: Address = Address::new(0x08, 0x00, 0x21);
+
+    /// Size of the block from [`STORAGE_STATUS`] through
+    /// [`STORAGE_REMAINING`]
+    pub const STORAGE_SIZE: u32 = 3;
+}
+
+/// Tally (block `09`)
+pub mod tally {
+    use crate::Address;
+
+    /// Program tally (read only)
+    ///
+    /// Bit n set: video channel n+1 (HDMI 1-4, STILL 1-2) is on program.
+    /// During a transition both the outgoing and incoming channels are set.
+    pub const PROGRAM: Address = Address::new(0x09, 0x00, 0x00);
+
+    /// Preview tally (read only)
+    ///
+    /// Bit n set: video channel n+1 (HDMI 1-4, STILL 1-2) is on preview
+    pub const PREVIEW: Address = Address::new(0x09, 0x00, 0x01);
+}
+
+/// System settings (block `0A`)
+pub mod system {
+    use crate::Address;
+
+    /// Panel lock
+    ///
+    /// Bit 0: audio section, bit 1: video section, bit 2: menu.
+    /// A set bit locks the section's panel controls.
+    pub const PANEL_LOCK: Address = Address::new(0x0A, 0x00, 0x00);
+
+    /// Output video format
+    ///
+    /// 0: 720/59.94p, 1: 720/50p, 2: 1080/59.94i, 3: 1080/50i,
+    /// 4: 1080/59.94p, 5: 1080/50p, 6: 1080/29.97p, 7: 1080/25p,
+    /// 8: 1080/23.98p
+    pub const OUTPUT_FORMAT: Address = Address::new(0x0A, 0x00, 0x01);
+
+    /// Number of physical HDMI inputs
+    pub const HDMI_INPUT_COUNT: u8 = 4;
+
+    /// Signal status of an HDMI input (read only)
+    ///
+    /// `input` is 0-3 for HDMI IN 1-4. Bit 0: signal present, bit 1: HDCP
+    pub const fn input_status(input: u8) -> Address {
+        Address::new(0x0A, 0x01, input)
+    }
+
+    /// Detected video format of an HDMI input (read only)
+    ///
+    /// `input` is 0-3 for HDMI IN 1-4. Same codes as [`OUTPUT_FORMAT`]
+    pub const fn input_format(input: u8) -> Address {
+        Address::new(0x0A, 0x02, input)
+    }
+
+    /// Test tone on the outputs (0: off, 1: on)
+    pub const TEST_TONE: Address = Address::new(0x0A, 0x03, 0x00);
+
+    /// Test tone level (0-127: -INF to +10 dB, like the fader levels)
+    pub const TEST_TONE_LEVEL: Address = Address::new(0x0A, 0x03, 0x01);
+
+    /// Test tone signal
+    ///
+    /// 0: 1 kHz sine, 1: 440 Hz sine, 2: pink noise
+    pub const TEST_TONE_TYPE: Address = Address::new(0x0A, 0x03, 0x02);
+
+    /// Test pattern on the video outputs
+    ///
+    /// 0: off, 1: color bars, 2: grayscale ramp, 3: crosshatch
+    pub const TEST_PATTERN: Address = Address::new(0x0A, 0x03, 0x10);
+
+    /// Number of GPO (control output) pins
+    pub const GPO_COUNT: u8 = 4;
+
+    /// Number of GPI (control input) pins
+    pub const GPI_COUNT: u8 = 4;
+
+    /// Address of the function of a GPO pin
+    ///
+    /// `pin` is 0-3 for GPO 1-4. 0: off, 1: program tally, 2: preview
+    /// tally (of the input with the pin's number), 3: on while recording,
+    /// 4: on while streaming
+    pub const fn gpo_function(pin: u8) -> Address {
+        Address::new(0x0A, 0x04, pin)
+    }
+
+    /// Address of the function of a GPI pin
+    ///
+    /// `pin` is 0-3 for GPI 1-4. 0: none (state only), 1: CUT, 2: AUTO
+    /// TAKE, 3: start/stop recording
+    pub const fn gpi_function(pin: u8) -> Address {
+        Address::new(0x0A, 0x05, pin)
+    }
+
+    /// GPI pin states (read only)
+    ///
+    /// Bit n is set while GPI n+1 is closed. The device sends a DTH when
+    /// it changes.
+    pub const GPI_STATE: Address = Address::new(0x0A, 0x06, 0x00);
+}
+
+/// Audio level meters (block `0B`, read only)
+///
+/// Input meters are at `0B 00 cc`, with `cc` the channel index as in
+/// [`audio`](super::audio), and output meters at `0B 01 bb`, with `bb` the
+/// bus index as in [`output`](super::output), so the meters of neighboring
+/// channels can be read with a single multi-byte read.
+///
+/// Meter values are peak levels: 0 is -INF, and 1-127 map to -63.0 to
+/// 0.0 dBFS in 0.5 dB steps.
+pub mod meter {
+    use crate::Address;
+
+    /// Address of the meter of an input channel (0-5: CH1-6, 6: USB,
+    /// 7: Bluetooth)
+    pub const fn input(channel: u8) -> Address {
+        Address::new(0x0B, 0x00, channel)
+    }
+
+    /// Address of the meter of an output bus (0: MAIN, 1: AUX, 2: USB OUT)
+    pub const fn output(bus: u8) -> Address {
+        Address::new(0x0B, 0x01, bus)
+    }
+
+    /// Meter value of 0 dBFS
+    pub const FULL_SCALE: u8 = 127;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Address;
+
+    #[test]
+    fn test_known_addresses() {
+        assert_eq!(video::PGM_SELECT.to_hex(), "010000");
+        assert_eq!(video::PST_SELECT.to_hex(), "010001");
+        assert_eq!(video::TRANSITION_TYPE.to_hex(), "010002");
+        assert_eq!(video::TRANSITION_TIME.to_hex(), "010003");
+        assert_eq!(video::MIX_EFFECT.to_hex(), "010005");
+        assert_eq!(video::HDMI3_INPUT_ASSIGN.to_hex(), "010102");
+        assert_eq!(pinp::SOURCE.to_hex(), "020001");
+        assert_eq!(dsk::KEY_GAIN.to_hex(), "030003");
+        assert_eq!(still::CAPTURE_BUSY.to_hex(), "040011");
+        assert_eq!(audio::CH1_LEVEL.to_hex(), "050000");
+        assert_eq!(audio::CH6_MUTE.to_hex(), "050501");
+        assert_eq!(audio::BLUETOOTH_LEVEL.to_hex(), "050700");
+        assert_eq!(output::AUX_LEVEL.to_hex(), "060100");
+        assert_eq!(output::USB_OUT_MUTE.to_hex(), "060201");
+        assert_eq!(output::FADE.to_hex(), "061000");
+        assert_eq!(output::USB_OUT_SOURCE.to_hex(), "062002");
+        assert_eq!(output::aux_send(7).to_hex(), "062017");
+        assert_eq!(scene::RECALL.to_hex(), "070000");
+        assert_eq!(scene::BUSY.to_hex(), "070002");
+        assert_eq!(scene::name(29).to_hex(), "072D00");
+        assert_eq!(transport::STATUS.to_hex(), "080010");
+    }
+
+    #[test]
+    fn test_channel_addresses() {
+        assert_eq!(
+            audio::channel(3, audio::PAN),
+            Address::new(0x05, 0x03, 0x03)
+        );
+        assert_eq!(output::bus(0, audio::MUTE), output::MAIN_MUTE);
+    }
+}
@@ -0,0 +1,267 @@
+//! Fixed-capacity transmit queue
+//!
+//! [`CommandQueue`] holds encoded commands in an inline byte ring, so
+//! commands can be queued on targets without a heap. A UART transmit
+//! interrupt drains it byte by byte with [`CommandQueue::pop_byte`], which
+//! yields nothing while the device has signalled XOFF.
+//!
+//! Nothing on the queue path allocates: commands are encoded straight
+//! into the ring through [`Command::write`].
+
+use crate::{Command, RolandError};
+use core::fmt;
+
+/// STX (start of text, RS-232 only)
+const STX: u8 = 0x02;
+/// XON (resume sending)
+const XON: u8 = 0x11;
+/// XOFF (pause sending)
+const XOFF: u8 = 0x13;
+
+/// Queue of encoded commands with room for `N` bytes
+///
+/// # Example
+/// ```
+/// use roland_core::queue::CommandQueue;
+/// use roland_core::{Address, Command};
+///
+/// let mut queue = CommandQueue::<64>::new();
+/// queue
+///     .push_with_stx(&Command::write_parameter(Address::new(0x12, 0x34, 0x56), 0x01))
+///     .unwrap();
+///
+/// queue.receive(0x13); // XOFF from the device
+/// assert_eq!(queue.pop_byte(), None);
+/// queue.receive(0x11); // XON
+/// assert_eq!(queue.pop_byte(), Some(0x02));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandQueue<const N: usize> {
+    buffer: [u8; N],
+    head: usize,
+    len: usize,
+    paused: bool,
+}
+
+impl<const N: usize> Default for CommandQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> CommandQueue<N> {
+    /// Create an empty queue
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            head: 0,
+            len: 0,
+            paused: false,
+        }
+    }
+
+    /// Queue a command (Telnet framing, no STX)
+    ///
+    /// # Returns
+    /// * `Result<(), RolandError>` - Success, or `BufferFull` if the
+    ///   encoded command doesn't fit. Nothing is queued then.
+    pub fn push(&mut self, command: &Command) -> Result<(), RolandError> {
+        self.push_framed(command, false)
+    }
+
+    /// Queue a command with STX prefix (RS-232 framing)
+    ///
+    /// # Returns
+    /// * `Result<(), RolandError>` - Success, or `BufferFull` if the
+    ///   encoded command doesn't fit. Nothing is queued then.
+    pub fn push_with_stx(&mut self, command: &Command) -> Result<(), RolandError> {
+        self.push_framed(command, true)
+    }
+
+    fn push_framed(&mut self, command: &Command, stx: bool) -> Result<(), RolandError> {
+        let mut counter = Counter(usize::from(stx));
+        command
+            .write(&mut counter)
+            .map_err(|_| RolandError::BufferFull)?;
+        if counter.0 > self.free() {
+            return Err(RolandError::BufferFull);
+        }
+
+        if stx {
+            self.push_byte(STX);
+        }
+        // Can't fail: the space was checked above
+        let _ = command.write(self);
+        Ok(())
+    }
+
+    /// Take the next byte to transmit
+    ///
+    /// Returns `None` if the queue is empty or the device has paused
+    /// transmission with XOFF.
+    pub fn pop_byte(&mut self) -> Option<u8> {
+        if self.paused || self.len == 0 {
+            return None;
+        }
+        let byte = self.buffer[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+
+    /// Take as many bytes to transmit as fit in `out`
+    ///
+    /// # Returns
+    /// * `usize` - Number of bytes written to `out`, 0 while paused
+    pub fn pop_into(&mut self, out: &mut [u8]) -> usize {
+        let mut n = 0;
+        while n < out.len() {
+            match self.pop_byte() {
+                Some(byte) => out[n] = byte,
+                None => break,
+            }
+            n += 1;
+        }
+        n
+    }
+
+    /// Track flow control in a byte received from the device
+    ///
+    /// XOFF pauses transmission and XON resumes it; other bytes are
+    /// ignored, so every received byte can be passed in.
+    pub fn receive(&mut self, byte: u8) {
+        match byte {
+            XOFF => self.paused = true,
+            XON => self.paused = false,
+            _ => {}
+        }
+    }
+
+    /// Check if the device has paused transmission
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Number of queued bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if no bytes are queued
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bytes that can still be queued
+    pub fn free(&self) -> usize {
+        N - self.len
+    }
+
+    /// Discard all queued bytes
+    ///
+    /// The flow control state is kept.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        self.buffer[(self.head + self.len) % N] = byte;
+        self.len += 1;
+    }
+}
+
+impl<const N: usize> fmt::Write for CommandQueue<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if s.len() > self.free() {
+            return Err(fmt::Error);
+        }
+        for &byte in s.as_bytes() {
+            self.push_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Counts the bytes written to it
+struct Counter(usize);
+
+impl fmt::Write for Counter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Address;
+    use alloc::vec::Vec;
+
+    fn drain<const N: usize>(queue: &mut CommandQueue<N>) -> Vec<u8> {
+        core::iter::from_fn(|| queue.pop_byte()).collect()
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut queue = CommandQueue::<32>::new();
+        let command = Command::write_parameter(Address::new(0x12, 0x34, 0x56), 0x01);
+        queue.push(&command).unwrap();
+        queue.push_with_stx(&Command::GetVersion).unwrap();
+        assert_eq!(queue.len(), 19);
+
+        assert_eq!(drain(&mut queue), b"DTH:123456,01;\x02VER;");
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut queue = CommandQueue::<16>::new();
+        let command = Command::write_parameter(Address::new(0x12, 0x34, 0x56), 0x01);
+        queue.push(&command).unwrap();
+        assert_eq!(queue.push(&command), Err(RolandError::BufferFull));
+        // A failed push leaves the queue as it was
+        assert_eq!(queue.len(), 14);
+        queue.push(&Command::GetVersion).unwrap_err();
+
+        // Space freed by popping wraps around the ring
+        let mut out = [0u8; 10];
+        assert_eq!(queue.pop_into(&mut out), 10);
+        queue.push(&Command::GetVersion).unwrap();
+        assert_eq!(drain(&mut queue), b",01;VER;");
+    }
+
+    #[test]
+    fn test_xoff_mid_queue() {
+        let mut queue = CommandQueue::<64>::new();
+        for value in 0..3 {
+            queue
+                .push_with_stx(&Command::write_parameter(
+                    Address::new(0x05, 0x00, 0x00),
+                    value,
+                ))
+                .unwrap();
+        }
+
+        let mut sent = Vec::new();
+        let mut out = [0u8; 20];
+        let n = queue.pop_into(&mut out);
+        sent.extend_from_slice(&out[..n]);
+
+        // The device pauses halfway through the second command
+        queue.receive(XOFF);
+        assert!(queue.is_paused());
+        assert_eq!(queue.pop_byte(), None);
+        assert_eq!(queue.pop_into(&mut out), 0);
+        queue.receive(b'A');
+        assert_eq!(queue.pop_byte(), None);
+
+        queue.receive(XON);
+        sent.extend(drain(&mut queue));
+        assert_eq!(
+            sent,
+            b"\x02DTH:050000,00;\x02DTH:050000,01;\x02DTH:050000,02;"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Signed parameter values
+//!
+//! Parameters like pan hold signed values in an unsigned byte. Most use
+//! offset binary, where a fixed byte stands for zero (pan: `40` is
+//! center); a few use two's complement. [`SignedEncoding`] converts
+//! between the two forms.
+
+use crate::RolandError;
+
+/// How a signed value is stored in a parameter byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignedEncoding {
+    /// The value plus `zero`, e.g. `zero: 0x40` stores -1 as `3F`
+    OffsetBinary {
+        /// Byte that stands for 0
+        zero: u8,
+    },
+    /// Two's complement, e.g. -1 is `FF`
+    TwosComplement,
+}
+
+impl SignedEncoding {
+    /// Encode a value as a parameter byte
+    ///
+    /// # Example
+    /// ```
+    /// use roland_core::signed::SignedEncoding;
+    ///
+    /// let pan = SignedEncoding::OffsetBinary { zero: 0x40 };
+    /// assert_eq!(pan.encode_i8(-1), Ok(0x3F));
+    /// assert_eq!(SignedEncoding::TwosComplement.encode_i8(-1), Ok(0xFF));
+    /// ```
+    ///
+    /// # Returns
+    /// * `Result<u8, RolandError>` - Byte, or `OutOfRange` if the value
+    ///   doesn't fit the encoding
+    pub fn encode_i8(self, value: i8) -> Result<u8, RolandError> {
+        match self {
+            SignedEncoding::OffsetBinary { zero } => {
+                u8::try_from(zero as i16 + value as i16).map_err(|_| RolandError::OutOfRange)
+            }
+            SignedEncoding::TwosComplement => Ok(value as u8),
+        }
+    }
+
+    /// Decode a parameter byte
+    ///
+    /// # Returns
+    /// * `Result<i8, RolandError>` - Value, or `OutOfRange` if the byte
+    ///   stands for a value outside `i8`
+    pub fn decode_i8(self, byte: u8) -> Result<i8, RolandError> {
+        match self {
+            SignedEncoding::OffsetBinary { zero } => {
+                i8::try_from(byte as i16 - zero as i16).map_err(|_| RolandError::OutOfRange)
+            }
+            SignedEncoding::TwosComplement => Ok(byte as i8),
+        }
+    }
+
+    /// Get the smallest value the encoding can store
+    pub fn min(self) -> i8 {
+        match self {
+            SignedEncoding::OffsetBinary { zero } => (-(zero as i16)).max(i8::MIN as i16) as i8,
+            SignedEncoding::TwosComplement => i8::MIN,
+        }
+    }
+
+    /// Get the largest value the encoding can store
+    pub fn max(self) -> i8 {
+        match self {
+            SignedEncoding::OffsetBinary { zero } => (255 - zero as i16).min(i8::MAX as i16) as i8,
+            SignedEncoding::TwosComplement => i8::MAX,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFSET: SignedEncoding = SignedEncoding::OffsetBinary { zero: 0x40 };
+    const TWOS: SignedEncoding = SignedEncoding::TwosComplement;
+
+    #[test]
+    fn test_offset_binary() {
+        for (value, byte) in [(-64, 0x00), (-1, 0x3F), (0, 0x40), (1, 0x41), (127, 0xBF)] {
+            assert_eq!(OFFSET.encode_i8(value), Ok(byte), "{}", value);
+            assert_eq!(OFFSET.decode_i8(byte), Ok(value), "{:02X}", byte);
+        }
+        assert_eq!((OFFSET.min(), OFFSET.max()), (-64, 127));
+        assert_eq!(OFFSET.encode_i8(-65), Err(RolandError::OutOfRange));
+        assert_eq!(OFFSET.decode_i8(0xC0), Err(RolandError::OutOfRange));
+
+        let high = SignedEncoding::OffsetBinary { zero: 0xC0 };
+        assert_eq!((high.min(), high.max()), (-128, 63));
+        assert_eq!(high.encode_i8(64), Err(RolandError::OutOfRange));
+        assert_eq!(high.decode_i8(0x3F), Err(RolandError::OutOfRange));
+    }
+
+    #[test]
+    fn test_twos_complement() {
+        for (value, byte) in [(-128, 0x80), (-1, 0xFF), (0, 0x00), (1, 0x01), (127, 0x7F)] {
+            assert_eq!(TWOS.encode_i8(value), Ok(byte), "{}", value);
+            assert_eq!(TWOS.decode_i8(byte), Ok(value), "{:02X}", byte);
+        }
+        assert_eq!((TWOS.min(), TWOS.max()), (i8::MIN, i8::MAX));
+    }
+}
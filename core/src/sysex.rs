@@ -0,0 +1,355 @@
+//! MIDI System Exclusive wire format
+//!
+//! The parameters of the ASCII protocol can also be addressed with Roland
+//! DT1 (data set) and RQ1 (data request) messages over MIDI:
+//!
+//! ```text
+//! F0 41 <device> <model> 12 <address> <data...> <checksum> F7   DT1
+//! F0 41 <device> <model> 11 <address> <size> <checksum> F7      RQ1
+//! ```
+//!
+//! MIDI data bytes are 7-bit, so addresses and values above `7F` can't be
+//! sent this way. Sizes are encoded as three 7-bit digits.
+
+use crate::{Address, Command, Response, RolandError};
+use alloc::vec::Vec;
+
+/// Model ID used in DT1/RQ1 messages
+///
+/// Check this against the MIDI implementation chart of the firmware in
+/// use.
+pub const MODEL_ID: [u8; 4] = [0x00, 0x00, 0x00, 0x6A];
+
+/// Device ID that every device answers to
+pub const BROADCAST_DEVICE_ID: u8 = 0x7F;
+
+/// Roland manufacturer ID
+const ROLAND_ID: u8 = 0x41;
+/// Command ID of RQ1
+const RQ1: u8 = 0x11;
+/// Command ID of DT1
+const DT1: u8 = 0x12;
+/// Largest size an RQ1 can request (three 7-bit digits)
+const MAX_SIZE: u32 = (1 << 21) - 1;
+
+/// Compute the Roland checksum of address and data bytes
+///
+/// The checksum is the value that makes the 7-bit sum of the bytes and
+/// the checksum zero.
+///
+/// # Example
+/// ```
+/// use roland_core::sysex::checksum;
+/// assert_eq!(checksum(&[0x40, 0x00, 0x7F, 0x00]), 0x41);
+/// ```
+pub fn checksum(bytes: &[u8]) -> u8 {
+    let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b) & 0x7F);
+    (0x80 - sum) & 0x7F
+}
+
+/// Kind of a parsed SysEx message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SysexKind {
+    /// DT1: data for consecutive addresses
+    DataSet {
+        /// Address of the first byte
+        address: Address,
+        /// Values
+        data: Vec<u8>,
+    },
+    /// RQ1: request for consecutive addresses
+    DataRequest {
+        /// Address of the first byte
+        address: Address,
+        /// Number of bytes requested
+        size: u32,
+    },
+    /// Universal identity request, the SysEx counterpart of VER
+    IdentityRequest,
+}
+
+/// Parsed SysEx message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysexMessage {
+    /// Device ID (`7F` for all devices)
+    pub device_id: u8,
+    /// Message contents
+    pub kind: SysexKind,
+}
+
+impl SysexMessage {
+    /// Parse a complete SysEx message, from `F0` to `F7`
+    ///
+    /// # Returns
+    /// * `Result<Self, RolandError>` - Message, `ChecksumMismatch` if the
+    ///   checksum is wrong, `InvalidResponse` for messages of other
+    ///   manufacturers or models, or `SyntaxError` if it is malformed
+    pub fn parse(bytes: &[u8]) -> Result<Self, RolandError> {
+        let body = bytes
+            .strip_prefix(&[0xF0])
+            .and_then(|rest| rest.strip_suffix(&[0xF7]))
+            .ok_or(RolandError::SyntaxError)?;
+        if body.iter().any(|&b| b > 0x7F) {
+            return Err(RolandError::SyntaxError);
+        }
+
+        // Universal non-realtime identity request: 7E dev 06 01
+        if let [0x7E, device_id, 0x06, 0x01] = *body {
+            return Ok(Self {
+                device_id,
+                kind: SysexKind::IdentityRequest,
+            });
+        }
+
+        let [ROLAND_ID, device_id, rest @ ..] = body else {
+            return Err(RolandError::InvalidResponse);
+        };
+        let rest = rest
+            .strip_prefix(&MODEL_ID)
+            .ok_or(RolandError::InvalidResponse)?;
+        let (&command, rest) = rest.split_first().ok_or(RolandError::SyntaxError)?;
+        let (&sum, payload) = rest.split_last().ok_or(RolandError::SyntaxError)?;
+        if payload.len() < 3 {
+            return Err(RolandError::SyntaxError);
+        }
+        if checksum(payload) != sum {
+            return Err(RolandError::ChecksumMismatch);
+        }
+
+        let address = Address::new(payload[0], payload[1], payload[2]);
+        let kind = match (command, &payload[3..]) {
+            (DT1, data) if !data.is_empty() => SysexKind::DataSet {
+                address,
+                data: data.to_vec(),
+            },
+            (RQ1, &[a, b, c]) => SysexKind::DataRequest {
+                address,
+                size: (a as u32) << 14 | (b as u32) << 7 | c as u32,
+            },
+            _ => return Err(RolandError::SyntaxError),
+        };
+        Ok(Self {
+            device_id: *device_id,
+            kind,
+        })
+    }
+
+    /// Get the ASCII protocol command this message stands for
+    pub fn to_command(&self) -> Command {
+        match &self.kind {
+            SysexKind::DataSet { address, data } => match data[..] {
+                [value] => Command::WriteParameter {
+                    address: *address,
+                    value,
+                },
+                _ => Command::WriteBlock {
+                    address: *address,
+                    data: data.clone(),
+                },
+            },
+            SysexKind::DataRequest { address, size } => Command::ReadParameter {
+                address: *address,
+                size: *size,
+            },
+            SysexKind::IdentityRequest => Command::GetVersion,
+        }
+    }
+
+    /// Get the ASCII protocol response this message stands for
+    ///
+    /// Only a DT1 maps onto a response (`Data` or `DataBlock`).
+    pub fn to_response(&self) -> Option<Response> {
+        match &self.kind {
+            SysexKind::DataSet { address, data } => Some(match data[..] {
+                [value] => Response::Data {
+                    address: *address,
+                    value,
+                },
+                _ => Response::DataBlock {
+                    address: *address,
+                    data: data.clone(),
+                },
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Command {
+    /// Encode the command as a SysEx message
+    ///
+    /// Writes become DT1, reads RQ1 and VER the universal identity
+    /// request.
+    ///
+    /// # Arguments
+    /// * `device_id` - Device ID of the target (0x00-0x7F, `7F` for all)
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>, RolandError>` - Message, or `OutOfRange` if the
+    ///   device ID, an address or data byte is above `7F` or the size
+    ///   doesn't fit in three 7-bit digits
+    ///
+    /// # Example
+    /// ```
+    /// use roland_core::{sysex, Address, Command};
+    ///
+    /// let cmd = Command::WriteParameter {
+    ///     address: Address::new(0x01, 0x00, 0x00),
+    ///     value: 0x02,
+    /// };
+    /// let message = cmd.to_sysex(0x10).unwrap();
+    /// assert_eq!(&message[..3], &[0xF0, 0x41, 0x10]);
+    /// assert_eq!(
+    ///     sysex::SysexMessage::parse(&message).unwrap().to_command(),
+    ///     cmd
+    /// );
+    /// ```
+    pub fn to_sysex(&self, device_id: u8) -> Result<Vec<u8>, RolandError> {
+        if device_id > 0x7F {
+            return Err(RolandError::OutOfRange);
+        }
+        let (command, address, payload) = match self {
+            Command::WriteParameter { address, value } => (DT1, address, Vec::from([*value])),
+            Command::WriteBlock { address, data } => (DT1, address, data.clone()),
+            Command::ReadParameter { address, size } => {
+                if *size > MAX_SIZE {
+                    return Err(RolandError::OutOfRange);
+                }
+                let digits = [
+                    (size >> 14) as u8,
+                    (size >> 7) as u8 & 0x7F,
+                    *size as u8 & 0x7F,
+                ];
+                (RQ1, address, Vec::from(digits))
+            }
+            Command::GetVersion => {
+                return Ok(Vec::from([0xF0, 0x7E, device_id, 0x06, 0x01, 0xF7]));
+            }
+        };
+
+        let mut body = Vec::with_capacity(3 + payload.len());
+        body.extend_from_slice(&[address.high, address.mid, address.low]);
+        body.extend_from_slice(&payload);
+        if body.iter().any(|&b| b > 0x7F) {
+            return Err(RolandError::OutOfRange);
+        }
+
+        let mut message = Vec::with_capacity(body.len() + 10);
+        message.extend_from_slice(&[0xF0, ROLAND_ID, device_id]);
+        message.extend_from_slice(&MODEL_ID);
+        message.push(command);
+        message.extend_from_slice(&body);
+        message.push(checksum(&body));
+        message.push(0xF7);
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_checksum() {
+        // Examples from Roland MIDI implementation documents
+        assert_eq!(checksum(&[0x40, 0x00, 0x7F, 0x00]), 0x41);
+        assert_eq!(checksum(&[0x40, 0x11, 0x00, 0x41, 0x63]), 0x0B);
+        assert_eq!(checksum(&[]), 0x00);
+        // The 7-bit sum including the checksum is zero
+        let bytes = [0x7F; 9];
+        let sum: u32 = bytes.iter().map(|&b| b as u32).sum::<u32>() + checksum(&bytes) as u32;
+        assert_eq!(sum % 128, 0);
+    }
+
+    #[test]
+    fn test_encode() {
+        let write = Command::WriteParameter {
+            address: Address::new(0x05, 0x00, 0x00),
+            value: 0x6B,
+        };
+        assert_eq!(
+            write.to_sysex(0x10).unwrap(),
+            vec![
+                0xF0, 0x41, 0x10, 0x00, 0x00, 0x00, 0x6A, 0x12, 0x05, 0x00, 0x00, 0x6B, 0x10, 0xF7
+            ]
+        );
+
+        let read = Command::ReadParameter {
+            address: Address::new(0x01, 0x00, 0x00),
+            size: 0x81,
+        };
+        let message = read.to_sysex(0x7F).unwrap();
+        assert_eq!(&message[7..14], &[0x11, 0x01, 0x00, 0x00, 0x00, 0x01, 0x01]);
+
+        assert_eq!(
+            Command::GetVersion.to_sysex(0x7F).unwrap(),
+            vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]
+        );
+
+        let too_big = Command::WriteParameter {
+            address: Address::new(0x05, 0x00, 0x00),
+            value: 0x80,
+        };
+        assert_eq!(too_big.to_sysex(0x10), Err(RolandError::OutOfRange));
+        assert_eq!(write.to_sysex(0x80), Err(RolandError::OutOfRange));
+    }
+
+    #[test]
+    fn test_parse() {
+        let commands = [
+            Command::WriteParameter {
+                address: Address::new(0x01, 0x00, 0x00),
+                value: 0x03,
+            },
+            Command::WriteBlock {
+                address: Address::new(0x02, 0x00, 0x02),
+                data: vec![0x03, 0x68, 0x03, 0x68],
+            },
+            Command::ReadParameter {
+                address: Address::new(0x08, 0x00, 0x11),
+                size: 3,
+            },
+            Command::GetVersion,
+        ];
+        for command in commands {
+            let message = SysexMessage::parse(&command.to_sysex(0x10).unwrap()).unwrap();
+            assert_eq!(message.device_id, 0x10);
+            assert_eq!(message.to_command(), command);
+        }
+
+        let mut message = Command::WriteParameter {
+            address: Address::new(0x01, 0x00, 0x00),
+            value: 0x03,
+        }
+        .to_sysex(0x10)
+        .unwrap();
+        assert_eq!(
+            SysexMessage::parse(&message).unwrap().to_response(),
+            Some(Response::Data {
+                address: Address::new(0x01, 0x00, 0x00),
+                value: 0x03,
+            })
+        );
+        let len = message.len();
+        message[len - 2] ^= 0x01;
+        assert_eq!(
+            SysexMessage::parse(&message),
+            Err(RolandError::ChecksumMismatch)
+        );
+
+        // Another manufacturer / model
+        assert_eq!(
+            SysexMessage::parse(&[0xF0, 0x43, 0x10, 0x00, 0xF7]),
+            Err(RolandError::InvalidResponse)
+        );
+        assert_eq!(
+            SysexMessage::parse(&[0xF0, 0x41, 0x10, 0x00, 0x00, 0x00, 0x01, 0x12, 0xF7]),
+            Err(RolandError::InvalidResponse)
+        );
+        assert_eq!(
+            SysexMessage::parse(&[0xF0, 0x41, 0x10]),
+            Err(RolandError::SyntaxError)
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! Product and firmware version from a VER response
+//!
+//! `VER` answers with two strings. [`DeviceInfo`] makes them usable for
+//! feature gates: the product becomes a [`ProductModel`] and the version a
+//! [`FirmwareVersion`] that compares by number.
+
+use alloc::string::{String, ToString};
+use core::cmp::Ordering;
+use core::fmt;
+
+/// Firmware version, e.g. `2.01`
+///
+/// Parsed as `major.minor` followed by an optional suffix. The minor
+/// number is compared as a number, so `2.1` and `2.01` are the same
+/// version, and both are older than `2.10`. Versions with the same number
+/// are ordered by suffix, with no suffix first (`2.01 < 2.01a`).
+///
+/// Parsing never fails: a string that doesn't start with a number is kept
+/// as is, has no [`major`](Self::major) or [`minor`](Self::minor), and
+/// only compares equal to the same string. When the version has several
+/// comma-separated fields (`SYS,2.01`), the last one is parsed.
+///
+/// # Example
+/// ```
+/// use roland_core::version::FirmwareVersion;
+///
+/// let version = FirmwareVersion::parse("2.01");
+/// assert!(version >= FirmwareVersion::parse("2.00"));
+/// assert!(version.is_at_least(2, 0));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FirmwareVersion {
+    raw: String,
+    /// Major and minor number, `None` if unparsable
+    number: Option<(u16, u16)>,
+    /// Byte offset of the suffix in `raw`
+    suffix: usize,
+}
+
+impl FirmwareVersion {
+    /// Parse a version string as sent by the device
+    pub fn parse(version: &str) -> Self {
+        // The last field, and where it starts in the whole string
+        let field = version.rsplit(',').next().unwrap_or(version).trim_start();
+        let start = version.len() - field.len();
+        let (number, suffix) = match parse_number(field) {
+            Some((number, used)) => (Some(number), start + used),
+            None => (None, version.len()),
+        };
+        Self {
+            raw: version.to_string(),
+            number,
+            suffix,
+        }
+    }
+
+    /// Get the version string as sent by the device
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    /// Get the major number, e.g. 2 for `2.01`
+    pub fn major(&self) -> Option<u16> {
+        self.number.map(|(major, _)| major)
+    }
+
+    /// Get the minor number, e.g. 1 for `2.01`
+    pub fn minor(&self) -> Option<u16> {
+        self.number.map(|(_, minor)| minor)
+    }
+
+    /// Get what follows the number, e.g. `a` for `2.01a`
+    ///
+    /// Empty for unparsable versions.
+    pub fn suffix(&self) -> &str {
+        self.raw[self.suffix..].trim_end()
+    }
+
+    /// Check if this is `major.minor` or newer
+    ///
+    /// Unparsable versions are never at least any version.
+    pub fn is_at_least(&self, major: u16, minor: u16) -> bool {
+        self.number.is_some_and(|number| number >= (major, minor))
+    }
+}
+
+/// Parse `major[.minor]` at the start of a string
+///
+/// Returns the numbers and the number of bytes used.
+fn parse_number(s: &str) -> Option<((u16, u16), usize)> {
+    let (major, mut used) = parse_digits(s)?;
+    let mut minor = 0;
+    if let Some(rest) = s[used..].strip_prefix('.') {
+        if let Some((value, n)) = parse_digits(rest) {
+            minor = value;
+            used += 1 + n;
+        }
+    }
+    Some(((major, minor), used))
+}
+
+fn parse_digits(s: &str) -> Option<(u16, usize)> {
+    let len = s.bytes().take_while(u8::is_ascii_digit).count();
+    Some((s[..len].parse().ok()?, len))
+}
+
+impl PartialEq for FirmwareVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for FirmwareVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self.number, other.number) {
+            (Some(a), Some(b)) => Some(a.cmp(&b).then_with(|| self.suffix().cmp(other.suffix()))),
+            (None, None) if self.raw == other.raw => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+/// Device model, from the product name in a VER response
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ProductModel {
+    /// VR-6HD
+    Vr6Hd,
+    /// VR-120HD
+    Vr120Hd,
+    /// Any other product, with its name as sent
+    Other(String),
+}
+
+impl ProductModel {
+    /// Get the model for a product name, ignoring case and whitespace
+    pub fn parse(product: &str) -> Self {
+        let name = product.trim();
+        if name.eq_ignore_ascii_case("VR-6HD") {
+            ProductModel::Vr6Hd
+        } else if name.eq_ignore_ascii_case("VR-120HD") {
+            ProductModel::Vr120Hd
+        } else {
+            ProductModel::Other(product.to_string())
+        }
+    }
+
+    /// Get the product name
+    pub fn name(&self) -> &str {
+        match self {
+            ProductModel::Vr6Hd => "VR-6HD",
+            ProductModel::Vr120Hd => "VR-120HD",
+            ProductModel::Other(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for ProductModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Product and firmware of a device
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    /// Device model
+    pub product: ProductModel,
+    /// Firmware version
+    pub firmware: FirmwareVersion,
+}
+
+impl DeviceInfo {
+    /// Parse the product and version strings of a VER response
+    pub fn parse(product: &str, version: &str) -> Self {
+        Self {
+            product: ProductModel::parse(product),
+            firmware: FirmwareVersion::parse(version),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(version: &str) -> FirmwareVersion {
+        FirmwareVersion::parse(version)
+    }
+
+    #[test]
+    fn test_parse_firmware_version() {
+        let cases = [
+            ("1.00", Some(1), Some(0), ""),
+            ("2.1", Some(2), Some(1), ""),
+            ("2.01a", Some(2), Some(1), "a"),
+            ("3", Some(3), Some(0), ""),
+            ("SYS,2.01", Some(2), Some(1), ""),
+            ("garbage", None, None, ""),
+            ("", None, None, ""),
+        ];
+        for (raw, major, minor, suffix) in cases {
+            let version = v(raw);
+            assert_eq!(version.major(), major, "{}", raw);
+            assert_eq!(version.minor(), minor, "{}", raw);
+            assert_eq!(version.suffix(), suffix, "{}", raw);
+            assert_eq!(version.as_str(), raw);
+        }
+    }
+
+    #[test]
+    fn test_compare_firmware_versions() {
+        assert!(v("1.00") < v("2.00"));
+        assert!(v("2.01") > v("2.00"));
+        assert!(v("2.1") < v("2.10"));
+        assert_eq!(v("2.1"), v("2.01"));
+        assert!(v("2.01") < v("2.01a"));
+        assert!(v("2.01a").is_at_least(2, 1));
+        assert!(!v("1.99").is_at_least(2, 0));
+
+        // Garbage only equals itself
+        assert_eq!(v("garbage"), v("garbage"));
+        assert_eq!(v("garbage").partial_cmp(&v("1.00")), None);
+        assert!(!v("garbage").is_at_least(0, 0));
+    }
+
+    #[test]
+    fn test_product_model() {
+        let info = DeviceInfo::parse("vr-6hd", "1.00");
+        assert_eq!(info.product, ProductModel::Vr6Hd);
+        assert_eq!(info.product.to_string(), "VR-6HD");
+        assert_eq!(
+            ProductModel::parse("V-160HD"),
+            ProductModel::Other("V-160HD".to_string())
+        );
+        assert_eq!(ProductModel::parse("V-160HD").name(), "V-160HD");
+    }
+}
@@ -0,0 +1,74 @@
+//! Allocation counts on the DTH paths
+//!
+//! This needs its own test binary: the counting allocator is global, and
+//! counts are kept per thread so other tests can't disturb them.
+
+use roland_core::{Address, Command, Response};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Count the allocations made by `f` on this thread
+fn allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    let count = ALLOCATIONS.with(Cell::get) - before;
+    drop(result);
+    count
+}
+
+#[test]
+fn test_encode_allocations() {
+    let write = Command::write_parameter(Address::new(0x12, 0x34, 0x56), 0x01);
+    let read = Command::read(Address::new(0x12, 0x34, 0x56), 1).unwrap();
+    assert_eq!(allocations(|| write.encode()), 1);
+    assert_eq!(allocations(|| read.encode()), 1);
+    assert_eq!(allocations(|| write.encode_with_stx()), 1);
+
+    // A reused buffer doesn't allocate once it is large enough
+    let mut buf = String::with_capacity(32);
+    assert_eq!(
+        allocations(|| {
+            for _ in 0..10 {
+                buf.clear();
+                write.encode_into(&mut buf);
+                read.encode_into(&mut buf);
+            }
+        }),
+        0
+    );
+    assert_eq!(buf, "DTH:123456,01;RQH:123456,000001;");
+}
+
+#[test]
+fn test_parse_allocations() {
+    assert_eq!(allocations(|| Response::parse("DTH:123456,01;")), 0);
+    assert_eq!(allocations(|| Response::parse("\x02DTH:123456,7F;")), 0);
+    assert_eq!(allocations(|| Command::parse("DTH:123456,01;")), 0);
+    assert_eq!(
+        allocations(|| Response::parse_first(b"DTH:123456,01;ack")),
+        0
+    );
+
+    // Multi-byte data needs its Vec, and nothing else
+    assert_eq!(allocations(|| Response::parse("DTH:123456,01,02,03;")), 1);
+}
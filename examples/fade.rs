@@ -0,0 +1,38 @@
+//! Example: end of service fade
+//!
+//! Fades the output to black and silence, switches to another scene while
+//! nothing is visible, then fades back in.
+//!
+//! Usage: `cargo run --example fade -- <host> [scene]`
+
+use roland_rs::fade::FadeMode;
+use roland_rs::{TelnetClient, TelnetError};
+use std::thread;
+use std::time::Duration;
+
+fn main() -> Result<(), TelnetError> {
+    let mut args = std::env::args().skip(1);
+    let host = args.next().unwrap_or_else(|| "192.168.1.100".to_string());
+    let scene = args.next().and_then(|n| n.parse().ok()).unwrap_or(1);
+
+    let fade_time = Duration::from_secs(2);
+
+    println!("Connecting to {}...", host);
+    let mut client = TelnetClient::connect(&host, 23)?;
+
+    client.set_output_fade_mode(FadeMode::Both)?;
+
+    println!("Fading out...");
+    client.fade_out(fade_time)?;
+    thread::sleep(fade_time);
+
+    println!("Recalling scene {}...", scene);
+    client.recall_scene_blocking(scene, Duration::from_secs(5))?;
+
+    println!("Fading in...");
+    client.fade_in(fade_time)?;
+    thread::sleep(fade_time);
+
+    println!("Done");
+    Ok(())
+}
@@ -0,0 +1,65 @@
+//! Example: switch program from GPI contact closures
+//!
+//! Sets GPO 1-4 to program tally for HDMI 1-4, e.g. for camera CCUs, and
+//! GPI 1-4 to report their state. Closing GPI n then selects HDMI n on
+//! program. GPI changes arrive as events; the state is also read once a
+//! second in case one was missed.
+//!
+//! Usage: `cargo run --example gpi_switch -- <host>`
+
+use roland_rs::gpio::{GpiFunction, GpiState, GpoFunction};
+use roland_rs::params::system;
+use roland_rs::video::{VideoInput, VideoSwitcher};
+use roland_rs::{DeviceEvent, TelnetClient, TelnetError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often queued events are checked
+const EVENT_INTERVAL: Duration = Duration::from_millis(20);
+/// How often the GPI state is read regardless of events
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+fn main() -> Result<(), TelnetError> {
+    let host = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "192.168.1.100".to_string());
+
+    println!("Connecting to {}...", host);
+    let mut client = TelnetClient::connect(&host, 23)?;
+    for pin in 1..=system::GPO_COUNT {
+        client.configure_gpo(pin, GpoFunction::ProgramTally)?;
+    }
+    for pin in 1..=system::GPI_COUNT {
+        client.configure_gpi(pin, GpiFunction::None)?;
+    }
+
+    let mut state = client.read_gpi_state()?;
+    let mut last_poll = Instant::now();
+    println!("Waiting for GPI closures (Ctrl+C to quit)");
+    loop {
+        let mut latest = None;
+        while let Some(event) = client.poll_event()? {
+            if let DeviceEvent::ParameterChanged { address, value } = event {
+                if address == system::GPI_STATE {
+                    latest = Some(GpiState { bits: value });
+                }
+            }
+        }
+        if last_poll.elapsed() >= POLL_INTERVAL {
+            latest = Some(client.read_gpi_state()?);
+            last_poll = Instant::now();
+        }
+
+        if let Some(latest) = latest {
+            for pin in latest.newly_closed(state) {
+                // GPI n selects HDMI n
+                if let Some(input) = VideoInput::from_index(pin - 1) {
+                    println!("GPI {} closed: {:?} on program", pin, input);
+                    VideoSwitcher::new(&mut client).select_program(input)?;
+                }
+            }
+            state = latest;
+        }
+        thread::sleep(EVENT_INTERVAL);
+    }
+}
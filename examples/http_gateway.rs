@@ -0,0 +1,41 @@
+//! Example: HTTP gateway
+//!
+//! Serves a JSON API for a device (see `roland_rs::http`). Parameters can
+//! be addressed by name if a parameter map file is given.
+//!
+//! Usage: `cargo run --example http_gateway --features http -- <device> [map] [listen port]`
+//!
+//! ```text
+//! curl http://localhost:8080/version
+//! curl -X PUT -d '{"value":1}' http://localhost:8080/param/050000
+//! curl -X POST http://localhost:8080/scene/3/recall
+//! ```
+
+use roland_rs::http::HttpGateway;
+use roland_rs::param_map::ParameterMap;
+use roland_rs::shared::SharedClient;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let Some(device) = args.next() else {
+        eprintln!("Usage: http_gateway <device> [map] [listen port]");
+        std::process::exit(2);
+    };
+    let map = match args.next() {
+        Some(path) => ParameterMap::load(&path)?,
+        None => ParameterMap::default(),
+    };
+    let port: u16 = match args.next() {
+        Some(port) => port.parse()?,
+        None => 8080,
+    };
+    println!("Loaded {} parameters", map.len());
+
+    println!("Connecting to {}...", device);
+    let client = SharedClient::connect(&device, 23)?;
+
+    let gateway = HttpGateway::bind(("0.0.0.0", port), client, map)?;
+    println!("Listening on http://{}", gateway.local_addr()?);
+    gateway.run()?;
+    Ok(())
+}
@@ -0,0 +1,65 @@
+//! Example: audio level meters
+//!
+//! Reads the meters of CH1-6, USB and MAIN ten times a second and draws
+//! them as bars in the terminal.
+//!
+//! Usage: `cargo run --example meters -- <host>`
+
+use roland_rs::audio::{AudioMixer, MeterReading};
+use roland_rs::{TelnetClient, TelnetError};
+use std::io::Write;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time between meter updates
+const INTERVAL: Duration = Duration::from_millis(100);
+/// Width of a full scale bar in characters
+const WIDTH: usize = 40;
+/// Lowest level drawn, in dBFS
+const FLOOR: f32 = -60.0;
+
+fn main() -> Result<(), TelnetError> {
+    let host = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "192.168.1.100".to_string());
+
+    println!("Connecting to {}...", host);
+    let mut client = TelnetClient::connect(&host, 23)?;
+    let mut mixer = AudioMixer::new(&mut client);
+
+    let mut first = true;
+    loop {
+        let start = Instant::now();
+        let readings = mixer.read_all_meters()?;
+
+        // Redraw in place
+        if !first {
+            print!("\x1b[{}A", readings.len());
+        }
+        first = false;
+        for (channel, reading) in &readings {
+            println!(
+                "{:>4} {} {}",
+                channel.name(),
+                bar(*reading),
+                label(*reading)
+            );
+        }
+        std::io::stdout().flush()?;
+
+        thread::sleep(INTERVAL.saturating_sub(start.elapsed()));
+    }
+}
+
+fn bar(reading: MeterReading) -> String {
+    let fill = ((reading.dbfs() - FLOOR) / -FLOOR).clamp(0.0, 1.0);
+    let len = (fill * WIDTH as f32).round() as usize;
+    format!("[{}{}]", "#".repeat(len), " ".repeat(WIDTH - len))
+}
+
+fn label(reading: MeterReading) -> String {
+    match reading.dbfs() {
+        dbfs if dbfs.is_finite() => format!("{:>6.1} dBFS", dbfs),
+        _ => "  -INF dBFS".to_string(),
+    }
+}
@@ -0,0 +1,42 @@
+//! Example: OSC bridge
+//!
+//! Forwards OSC messages to a device and publishes the device's parameter
+//! changes to subscribed OSC clients. The OSC paths come from a parameter
+//! map file (TOML or CSV, see `roland_rs::param_map`).
+//!
+//! Usage: `cargo run --example osc_bridge --features osc -- <device> <map> [listen port]`
+
+use roland_rs::osc::{OscBridge, OscMapping, DEFAULT_PREFIX};
+use roland_rs::param_map::ParameterMap;
+use roland_rs::TelnetClient;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let (Some(device), Some(map)) = (args.next(), args.next()) else {
+        eprintln!("Usage: osc_bridge <device> <map> [listen port]");
+        std::process::exit(2);
+    };
+    let port: u16 = match args.next() {
+        Some(port) => port.parse()?,
+        None => 9000,
+    };
+
+    let map = ParameterMap::load(&map)?;
+    println!("Loaded {} parameters", map.len());
+
+    println!("Connecting to {}...", device);
+    let client = TelnetClient::connect(&device, 23)?;
+
+    let mut bridge = OscBridge::bind(
+        ("0.0.0.0", port),
+        client,
+        OscMapping::new(map, DEFAULT_PREFIX),
+    )?;
+    println!(
+        "Listening on {}, send {}/subscribe to receive changes",
+        bridge.local_addr()?,
+        DEFAULT_PREFIX
+    );
+    bridge.run()?;
+    Ok(())
+}
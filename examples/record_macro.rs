@@ -0,0 +1,46 @@
+//! Example: record and replay a macro
+//!
+//! Records a short sequence of writes, saves it and plays it back at
+//! double speed.
+//!
+//! Usage: `cargo run --example record_macro -- <host> [file]`
+
+use roland_rs::recorder::{Macro, Recorder};
+use roland_rs::{Address, TelnetClient};
+use std::thread;
+use std::time::Duration;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let host = args.next().unwrap_or_else(|| "192.168.1.100".to_string());
+    let path = args.next().unwrap_or_else(|| "macro.json".to_string());
+
+    println!("Connecting to {}...", host);
+    let mut client = TelnetClient::connect(&host, 23)?;
+
+    // Note: Be careful with actual addresses - this is just an example
+    let address = Address::new(0x00, 0x00, 0x00);
+
+    println!("Recording...");
+    let recorder = Recorder::start(&mut client);
+    for value in [0x00, 0x40, 0x7F] {
+        client.write_parameter_addr(address, value)?;
+        thread::sleep(Duration::from_millis(500));
+    }
+    let recording = recorder.stop(&mut client);
+    println!(
+        "Recorded {} command(s) over {:?}",
+        recording.steps.len(),
+        recording.duration()
+    );
+
+    recording.save(&path)?;
+    println!("Saved to {}", path);
+
+    let recording = Macro::load(&path)?;
+    println!("Playing back at double speed...");
+    client.play_macro(&recording, 2.0, false)?;
+
+    println!("Done");
+    Ok(())
+}
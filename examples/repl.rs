@@ -0,0 +1,393 @@
+//! Example: interactive console for reading and writing parameters
+//!
+//! Usage:
+//! * `cargo run --example repl -- <host> [port]` - connect via Telnet
+//! * `cargo run --example repl -- --stx <device>` - use RS-232 framing
+//!   (STX before every command) over a serial device, e.g. `/dev/ttyUSB0`.
+//!   Set up the port first, e.g. `stty -F /dev/ttyUSB0 9600 raw`.
+//!
+//! Type `help` for the commands. Lines are appended to the history file
+//! (`$ROLAND_REPL_HISTORY`, or `.roland_repl_history` in the home
+//! directory); `history` lists it and `!n` runs entry `n` again.
+
+use roland_rs::audio::{Db, MonitorSource, SoloMode};
+use roland_rs::engine::ProtocolEngine;
+use roland_rs::params::output;
+use roland_rs::split::{SplitConfig, SplitMode};
+use roland_rs::units::Percent;
+use roland_rs::video::VideoInput;
+use roland_rs::{Address, Command, DeviceEvent, Response, RolandError, TelnetClient, TelnetError};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const HELP: &str = "\
+read <address> [size]     read a parameter, e.g. `read 010000`
+write <address> <value>   write a hex value, e.g. `write 010000 01`
+dump <address> <count>    read <count> (hex) addresses from <address>
+watch <address>...        print unsolicited changes of addresses (Telnet only)
+unwatch                   stop watching
+events                    print queued unsolicited changes
+phones <main|aux|solo>    choose what the headphones monitor
+phones-level <dB>         set the headphone level, e.g. `phones-level -12`
+solo-mode <pfl|afl>       pick up soloed channels before or after the fader
+split                     print the split configuration (Telnet only)
+split <off|split|squeeze> <left> <right> [position]
+                          set the split, inputs 1-6, position in %
+ver                       print product and version
+history                   list the command history
+!<n>                      run history entry <n> again
+quit                      exit";
+
+/// Response timeout for the serial link
+const SERIAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connection to the device
+enum Link {
+    Telnet(Box<TelnetClient>),
+    Serial(SerialLink),
+}
+
+/// RS-232 link over a serial device file
+///
+/// The [`ProtocolEngine`] matches responses to commands, as the Telnet
+/// client does internally; this only moves the bytes.
+struct SerialLink {
+    port: File,
+    engine: ProtocolEngine,
+    /// Start of the engine's millisecond clock
+    opened: Instant,
+}
+
+impl SerialLink {
+    fn open(path: &str) -> io::Result<Self> {
+        let port = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut engine = ProtocolEngine::new();
+        engine.set_stx(true);
+        engine.set_timeout_ms(SERIAL_TIMEOUT.as_millis() as u32);
+        Ok(Self {
+            port,
+            engine,
+            opened: Instant::now(),
+        })
+    }
+
+    fn send(&mut self, command: &Command) -> Result<Response, TelnetError> {
+        self.tick();
+        if let Some(frame) = self.engine.submit(command.clone()) {
+            self.port.write_all(&frame)?;
+        }
+        let deadline = Instant::now() + SERIAL_TIMEOUT;
+        let mut buf = [0; 256];
+        loop {
+            self.tick();
+            // Held back while the device has sent XOFF
+            if let Some(frame) = self.engine.poll_transmit() {
+                self.port.write_all(&frame)?;
+            }
+            // Unsolicited changes aren't answers to the command
+            while let Some(response) = self.engine.take_unsolicited() {
+                if let Response::Data { address, value } = response {
+                    println!("  changed {} = {:02X}", address.to_hex(), value);
+                }
+            }
+            match self.engine.take_completion() {
+                Some((_, Ok(response))) => return Ok(response),
+                Some((_, Err(RolandError::Timeout))) => return Err(TelnetError::Timeout),
+                Some((_, Err(e))) => return Ok(Response::Error(e)),
+                None => {}
+            }
+            if self.engine.is_paused() && Instant::now() > deadline {
+                self.engine.clear();
+                return Err(TelnetError::Timeout);
+            }
+            let n = self.port.read(&mut buf)?;
+            if n == 0 {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            self.engine.on_rx(&buf[..n]);
+        }
+    }
+
+    /// Advance the engine's clock, timing out a command without answer
+    fn tick(&mut self) {
+        // The engine's clock may wrap around
+        self.engine.tick(self.opened.elapsed().as_millis() as u32);
+    }
+}
+
+impl Link {
+    fn read(&mut self, address: Address, size: u32) -> Result<u8, TelnetError> {
+        match self {
+            Link::Telnet(client) => client.read_parameter_addr(address, size),
+            Link::Serial(serial) => match serial.send(&Command::read(address, size)?)? {
+                Response::Data { value, .. } => Ok(value),
+                response => Err(unexpected(response)),
+            },
+        }
+    }
+
+    fn write(&mut self, address: Address, value: u8) -> Result<(), TelnetError> {
+        match self {
+            Link::Telnet(client) => client.write_parameter_addr(address, value),
+            Link::Serial(serial) => {
+                match serial.send(&Command::WriteParameter { address, value })? {
+                    Response::Acknowledge => Ok(()),
+                    response => Err(unexpected(response)),
+                }
+            }
+        }
+    }
+
+    fn version(&mut self) -> Result<(String, String), TelnetError> {
+        match self {
+            Link::Telnet(client) => client.get_version(),
+            Link::Serial(serial) => match serial.send(&Command::GetVersion)? {
+                Response::Version { product, version } => Ok((product, version)),
+                response => Err(unexpected(response)),
+            },
+        }
+    }
+}
+
+fn unexpected(response: Response) -> TelnetError {
+    match response {
+        Response::Error(e) => TelnetError::Protocol(e),
+        _ => TelnetError::Protocol(RolandError::InvalidResponse),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let link = match args.as_slice() {
+        [flag, path] if flag == "--stx" => {
+            SerialLink::open(path).map(Link::Serial).map_err(Into::into)
+        }
+        [host] => TelnetClient::connect(host, 23).map(|client| Link::Telnet(Box::new(client))),
+        [host, port] => match port.parse() {
+            Ok(port) => {
+                TelnetClient::connect(host, port).map(|client| Link::Telnet(Box::new(client)))
+            }
+            Err(_) => {
+                eprintln!("Invalid port: {}", port);
+                std::process::exit(2);
+            }
+        },
+        _ => {
+            eprintln!("Usage: repl <host> [port] | repl --stx <device>");
+            std::process::exit(2);
+        }
+    };
+    let mut link = match link {
+        Ok(link) => link,
+        Err(e) => {
+            eprintln!("Failed to connect: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let history_path = history_path();
+    let mut history: Vec<String> = history_path
+        .as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .map(|text| text.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+    let mut history_file =
+        history_path.and_then(|path| OpenOptions::new().create(true).append(true).open(path).ok());
+
+    println!("Type `help` for commands");
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().ok();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let mut line = line.trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(n) = line.strip_prefix('!') {
+            match n.parse::<usize>().ok().and_then(|n| history.get(n)) {
+                Some(entry) => {
+                    println!("{}", entry);
+                    line = entry.clone();
+                }
+                None => {
+                    println!("No history entry {}", n);
+                    continue;
+                }
+            }
+        }
+        if let Some(file) = &mut history_file {
+            writeln!(file, "{}", line).ok();
+        }
+        history.push(line.clone());
+
+        match run(&mut link, &line, &history) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(TelnetError::Protocol(e)) => println!("ERR: {:?}", e),
+            Err(TelnetError::ConnectionClosed) => {
+                println!("Connection closed");
+                break;
+            }
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+}
+
+/// Run one command line, returning `false` to exit
+fn run(link: &mut Link, line: &str, history: &[String]) -> Result<bool, TelnetError> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["help"] => println!("{}", HELP),
+        ["quit" | "exit"] => return Ok(false),
+        ["ver"] => {
+            let (product, version) = link.version()?;
+            println!("{} {}", product, version);
+        }
+        ["read", address] | ["read", address, _] => {
+            let size = match words.get(2) {
+                Some(size) => size.parse().map_err(|_| RolandError::InvalidValue)?,
+                None => 1,
+            };
+            let address = Address::from_hex(address)?;
+            let value = link.read(address, size)?;
+            println!("{} = {:02X} ({})", address.to_hex(), value, value);
+        }
+        ["write", address, value] => {
+            let address = Address::from_hex(address)?;
+            let value = u8::from_str_radix(value, 16).map_err(|_| RolandError::InvalidValue)?;
+            link.write(address, value)?;
+            println!("ACK");
+        }
+        ["dump", address, count] => {
+            let start = Address::from_hex(address)?;
+            let count = u32::from_str_radix(count, 16).map_err(|_| RolandError::InvalidValue)?;
+            for address in roland_rs::backup::AddressRange::with_len(start, count).iter() {
+                match link.read(address, 1) {
+                    Ok(value) => println!("{} = {:02X}", address.to_hex(), value),
+                    Err(TelnetError::Protocol(e)) => println!("{} ERR: {:?}", address.to_hex(), e),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        ["watch", addresses @ ..] if !addresses.is_empty() => {
+            let Link::Telnet(client) = link else {
+                println!("watch needs a Telnet connection");
+                return Ok(true);
+            };
+            let addresses = addresses
+                .iter()
+                .map(|address| Address::from_hex(address))
+                .collect::<Result<Vec<_>, _>>()?;
+            client.subscribe(&addresses, |address, value| {
+                println!("\n  changed {} = {:02X}", address.to_hex(), value);
+            })?;
+            println!("Watching {} address(es)", addresses.len());
+        }
+        ["unwatch"] => {
+            if let Link::Telnet(client) = link {
+                client.unsubscribe()?;
+            }
+        }
+        ["events"] => {
+            if let Link::Telnet(client) = link {
+                for event in client.events() {
+                    match event {
+                        DeviceEvent::ParameterChanged { address, value } => {
+                            println!("  changed {} = {:02X}", address.to_hex(), value)
+                        }
+                        event => println!("  {:?}", event),
+                    }
+                }
+            }
+        }
+        ["phones", source] => {
+            let source = match *source {
+                "main" => MonitorSource::Main,
+                "aux" => MonitorSource::Aux,
+                "solo" => MonitorSource::Solo,
+                _ => return Err(RolandError::InvalidValue.into()),
+            };
+            link.write(output::PHONES_SOURCE, source.value())?;
+            println!("ACK");
+        }
+        ["phones-level", level] => {
+            let level: f32 = level.parse().map_err(|_| RolandError::InvalidValue)?;
+            link.write(output::PHONES_LEVEL, Db(level).to_byte())?;
+            println!("ACK");
+        }
+        ["solo-mode", mode] => {
+            let mode = match *mode {
+                "pfl" => SoloMode::Pfl,
+                "afl" => SoloMode::Afl,
+                _ => return Err(RolandError::InvalidValue.into()),
+            };
+            link.write(output::SOLO_MODE, mode.value())?;
+            println!("ACK");
+        }
+        ["split", args @ ..] if matches!(args.len(), 0 | 3 | 4) => {
+            let Link::Telnet(client) = link else {
+                println!("split needs a Telnet connection");
+                return Ok(true);
+            };
+            if let [mode, left, right, position @ ..] = args {
+                let mode = match *mode {
+                    "off" => SplitMode::Off,
+                    "split" => SplitMode::Split,
+                    "squeeze" => SplitMode::Squeeze,
+                    _ => return Err(RolandError::InvalidValue.into()),
+                };
+                let position = match position.first() {
+                    Some(position) => position.parse().map_err(|_| RolandError::InvalidValue)?,
+                    None => 50,
+                };
+                client.set_split_config(&SplitConfig {
+                    mode,
+                    left: input(left)?,
+                    right: input(right)?,
+                    position: Percent::new(position)?,
+                })?;
+                println!("ACK");
+            } else {
+                let config = client.split_config()?;
+                println!(
+                    "{:?}: {:?} | {:?} at {}%",
+                    config.mode,
+                    config.left,
+                    config.right,
+                    config.position.value()
+                );
+            }
+        }
+        ["history"] => {
+            for (i, entry) in history.iter().enumerate() {
+                println!("{:4} {}", i, entry);
+            }
+        }
+        _ => println!("Unknown command, type `help`"),
+    }
+    Ok(true)
+}
+
+/// Parse a video input number, 1-6
+fn input(number: &str) -> Result<VideoInput, RolandError> {
+    number
+        .parse::<u8>()
+        .ok()
+        .and_then(|n| VideoInput::from_index(n.checked_sub(1)?))
+        .ok_or(RolandError::InvalidValue)
+}
+
+fn history_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("ROLAND_REPL_HISTORY") {
+        return Some(path.into());
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".roland_repl_history"))
+}
@@ -0,0 +1,33 @@
+//! Example: periodic program captures
+//!
+//! Captures the program output to a still image memory every N seconds,
+//! cycling through the memories.
+//!
+//! Usage: `cargo run --example still_capture -- <host> [interval_secs] [slots]`
+
+use roland_rs::{TelnetClient, TelnetError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn main() -> Result<(), TelnetError> {
+    let mut args = std::env::args().skip(1);
+    let host = args.next().unwrap_or_else(|| "192.168.1.100".to_string());
+    let interval = args.next().and_then(|s| s.parse().ok()).unwrap_or(10);
+    let slots: u8 = args.next().and_then(|s| s.parse().ok()).unwrap_or(4);
+
+    let interval = Duration::from_secs(interval);
+
+    println!("Connecting to {}...", host);
+    let mut client = TelnetClient::connect(&host, 23)?;
+
+    for slot in (1..=slots).cycle() {
+        let start = Instant::now();
+        match client.capture_program_to_still(slot, Duration::from_secs(10)) {
+            Ok(()) => println!("Captured program to still {}", slot),
+            Err(TelnetError::Timeout) => println!("Capture to still {} timed out", slot),
+            Err(e) => return Err(e),
+        }
+        thread::sleep(interval.saturating_sub(start.elapsed()));
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Example: manual transition sweep
+//!
+//! Drives the transition from PGM to PST like a T-bar, sweeping it over
+//! 2 seconds at 60 updates per second. The client is rate limited with
+//! coalescing on, so positions the device can't take in time are
+//! dropped in favour of the latest.
+//!
+//! Usage: `cargo run --example t_bar -- <host> [max commands/sec]`
+
+use roland_rs::units::Percent;
+use roland_rs::video::VideoSwitcher;
+use roland_rs::{TelnetClient, TelnetError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Length of the sweep
+const SWEEP: Duration = Duration::from_secs(2);
+/// Fader updates per second
+const UPDATES_PER_SEC: u32 = 60;
+
+fn main() -> Result<(), TelnetError> {
+    let mut args = std::env::args().skip(1);
+    let host = args.next().unwrap_or_else(|| "192.168.1.100".to_string());
+    let rate = args.next().and_then(|n| n.parse().ok()).unwrap_or(30);
+
+    println!("Connecting to {}...", host);
+    let mut client = TelnetClient::connect(&host, 23)?;
+    client.set_rate_limit(rate);
+    client.set_coalescing(true);
+
+    let interval = Duration::from_secs(1) / UPDATES_PER_SEC;
+    let updates = (SWEEP.as_millis() / interval.as_millis()) as u32;
+    let mut switcher = VideoSwitcher::new(&mut client);
+    switcher.begin_manual_transition()?;
+
+    println!("Sweeping over {:?} ({} commands/sec max)...", SWEEP, rate);
+    let start = Instant::now();
+    for update in 0..=updates {
+        let position = Percent::new((update * 100 / updates) as u8).unwrap_or(Percent::MAX);
+        switcher.set_transition_position(position)?;
+        let next = start + interval * (update + 1);
+        if let Some(wait) = next.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+    switcher.end_manual_transition()?;
+
+    let elapsed = start.elapsed();
+    println!(
+        "Done: {} updates in {:.2?} ({:.1}/sec)",
+        updates + 1,
+        elapsed,
+        (updates + 1) as f64 / elapsed.as_secs_f64()
+    );
+    Ok(())
+}
@@ -0,0 +1,36 @@
+//! Example: WebSocket bridge
+//!
+//! Pushes changes of the given addresses to browsers as JSON and writes
+//! what browsers send back (see `roland_rs::ws_bridge`).
+//!
+//! Usage: `cargo run --example ws_bridge --features ws -- <device> <address>... `
+//!
+//! ```text
+//! const ws = new WebSocket("ws://localhost:8081");
+//! ws.onmessage = (e) => console.log(JSON.parse(e.data));
+//! ws.send(JSON.stringify({ address: "050000", value: 100 }));
+//! ```
+
+use roland_rs::shared::SharedClient;
+use roland_rs::ws_bridge::WsBridge;
+use roland_rs::{Address, TelnetError};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let Some(device) = args.next() else {
+        eprintln!("Usage: ws_bridge <device> <address>...");
+        std::process::exit(2);
+    };
+    let watched = args
+        .map(|address| Address::from_hex(&address))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(TelnetError::Protocol)?;
+
+    println!("Connecting to {}...", device);
+    let client = SharedClient::connect(&device, 23)?;
+
+    let bridge = WsBridge::bind("0.0.0.0:8081", client, watched)?;
+    println!("Listening on ws://{}", bridge.local_addr()?);
+    bridge.run()?;
+    Ok(())
+}
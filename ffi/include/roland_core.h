@@ -0,0 +1,170 @@
+#ifndef ROLAND_CORE_H
+#define ROLAND_CORE_H
+
+/*
+ * Maintained by hand to match what cbindgen would generate from
+ * ffi/src/lib.rs with ffi/cbindgen.toml. Update it along with lib.rs.
+ */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Maximum number of values a [`RolandResponse`] holds
+#define ROLAND_MAX_DATA 256
+
+// Size of the product and version fields including the terminating NUL
+#define ROLAND_MAX_TEXT 64
+
+// Error of an `Error` or `Invalid` response
+typedef enum RolandErrorKind {
+  // No error
+  ROLAND_ERROR_KIND_NONE = 0,
+  // Syntax error in received command (`ERR:0;`)
+  ROLAND_ERROR_KIND_SYNTAX_ERROR = 1,
+  // Invalid command due to other settings (`ERR:4;`)
+  ROLAND_ERROR_KIND_INVALID = 2,
+  // Parameter out of range (`ERR:5;`)
+  ROLAND_ERROR_KIND_OUT_OF_RANGE = 3,
+  // Missing STX at command start (`ERR:6;`)
+  ROLAND_ERROR_KIND_NO_STX = 4,
+  // Other device error code, see `error_code`
+  ROLAND_ERROR_KIND_UNKNOWN_ERROR = 5,
+  // Invalid address format
+  ROLAND_ERROR_KIND_INVALID_ADDRESS = 6,
+  // Invalid value format
+  ROLAND_ERROR_KIND_INVALID_VALUE = 7,
+  // Invalid response format
+  ROLAND_ERROR_KIND_INVALID_RESPONSE = 8,
+  // SysEx checksum mismatch
+  ROLAND_ERROR_KIND_CHECKSUM_MISMATCH = 9,
+  // Data around the frame
+  ROLAND_ERROR_KIND_UNFRAMED_DATA = 10,
+  // Buffer full
+  ROLAND_ERROR_KIND_BUFFER_FULL = 11,
+  // An error added to roland-core after this header was generated
+  ROLAND_ERROR_KIND_OTHER = 12,
+} RolandErrorKind;
+
+// Kind of a parsed response
+typedef enum RolandResponseKind {
+  // `ack`
+  ROLAND_RESPONSE_KIND_ACK = 0,
+  // `DTH`, with one or more values
+  ROLAND_RESPONSE_KIND_DATA = 1,
+  // `VER`
+  ROLAND_RESPONSE_KIND_VERSION = 2,
+  // `ERR` from the device, see `error` and `error_code`
+  ROLAND_RESPONSE_KIND_ERROR = 3,
+  // XON (0x11)
+  ROLAND_RESPONSE_KIND_XON = 4,
+  // XOFF (0x13)
+  ROLAND_RESPONSE_KIND_XOFF = 5,
+  // A complete frame that couldn't be parsed, see `error`
+  ROLAND_RESPONSE_KIND_INVALID = 6,
+} RolandResponseKind;
+
+// Negative return values
+typedef enum RolandStatus {
+  // Success (never returned, counts are returned instead)
+  ROLAND_STATUS_OK = 0,
+  // A required pointer was NULL
+  ROLAND_STATUS_NULL_POINTER = -1,
+  // The output buffer is too small for the encoded command
+  ROLAND_STATUS_BUFFER_TOO_SMALL = -2,
+  // An argument is out of range, e.g. a read size of 0
+  ROLAND_STATUS_INVALID_ARGUMENT = -3,
+} RolandStatus;
+
+// Parsed response
+//
+// Only the fields belonging to `kind` are meaningful; the others are
+// zeroed.
+typedef struct RolandResponse {
+  // Kind of response
+  enum RolandResponseKind kind;
+  // Error of an `Error` or `Invalid` response
+  enum RolandErrorKind error;
+  // Number of values in `data`
+  uint32_t data_len;
+  // Address of `data[0]`, high byte first
+  uint8_t address[3];
+  // Device error code of an `Error` response (the `n` in `ERR:n;`)
+  uint8_t error_code;
+  // Non-zero if values or text didn't fit and were cut off
+  uint8_t truncated;
+  // Values of a `Data` response
+  uint8_t data[ROLAND_MAX_DATA];
+  // Product name of a `Version` response, NUL-terminated
+  char product[ROLAND_MAX_TEXT];
+  // Version of a `Version` response, NUL-terminated
+  char version[ROLAND_MAX_TEXT];
+} RolandResponse;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Encode a write command (`DTH:aaaaaa,vv;`)
+//
+// Writes the command without STX or NUL terminator to `out_buf`.
+//
+// # Returns
+// Number of bytes written, or a negative [`RolandStatus`].
+//
+// # Safety
+// `out_buf` must be valid for writes of `out_len` bytes.
+int32_t roland_encode_write(uint8_t addr_high,
+                            uint8_t addr_mid,
+                            uint8_t addr_low,
+                            uint8_t value,
+                            uint8_t *out_buf,
+                            size_t out_len);
+
+// Encode a read command (`RQH:aaaaaa,ssssss;`)
+//
+// Writes the command without STX or NUL terminator to `out_buf`.
+//
+// # Returns
+// Number of bytes written, or a negative [`RolandStatus`]. A `size` of 0
+// or above `0xFFFFFF` is an invalid argument.
+//
+// # Safety
+// `out_buf` must be valid for writes of `out_len` bytes.
+int32_t roland_encode_read(uint8_t addr_high,
+                           uint8_t addr_mid,
+                           uint8_t addr_low,
+                           uint32_t size,
+                           uint8_t *out_buf,
+                           size_t out_len);
+
+// Parse the first complete frame of received data
+//
+// Leading whitespace is skipped. On success, `out` is overwritten, and
+// the returned count tells how many bytes to drop from the front of the
+// receive buffer before the next call. A frame that is complete but
+// malformed is reported as a `ROLAND_RESPONSE_KIND_INVALID` response, so
+// it is consumed like any other.
+//
+// # Returns
+// Number of bytes consumed, `0` if `buf` holds no complete frame yet
+// (`out` is left untouched), or a negative [`RolandStatus`]. Buffers
+// longer than `INT32_MAX` are an invalid argument.
+//
+// # Safety
+// `buf` must be valid for reads of `len` bytes, and `out` must point to a
+// writable `RolandResponse`.
+int32_t roland_parse_response(const uint8_t *buf, size_t len, struct RolandResponse *out);
+
+// Size of [`RolandResponse`] as compiled into the library
+//
+// Compare with `sizeof(RolandResponse)` to catch a stale header.
+size_t roland_response_size(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ROLAND_CORE_H */
@@ -0,0 +1,384 @@
+//! C bindings for roland-core
+//!
+//! Exposes the command encoder and response parser to C and C++ so
+//! existing firmware can reuse the protocol implementation. The header is
+//! `include/roland_core.h`. It is maintained by hand to match what
+//! cbindgen would generate from this file with `cbindgen.toml`, so
+//! changes here must be made there as well.
+//!
+//! Memory is always provided by the caller: encoders write into a buffer
+//! passed in with its length, and the parser fills a [`RolandResponse`]
+//! whose capacity is fixed at compile time. Nothing allocated here is
+//! handed across the boundary.
+//!
+//! Functions return a signed count on success and a negative
+//! [`RolandStatus`] on failure, like `snprintf`.
+
+use core::ffi::c_char;
+use core::{ptr, slice};
+use roland_core::{Address, Command, Response, RolandError};
+
+/// Maximum number of values a [`RolandResponse`] holds
+pub const ROLAND_MAX_DATA: usize = 256;
+
+/// Size of the product and version fields including the terminating NUL
+pub const ROLAND_MAX_TEXT: usize = 64;
+
+/// Negative return values
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RolandStatus {
+    /// Success (never returned, counts are returned instead)
+    Ok = 0,
+    /// A required pointer was NULL
+    NullPointer = -1,
+    /// The output buffer is too small for the encoded command
+    BufferTooSmall = -2,
+    /// An argument is out of range, e.g. a read size of 0
+    InvalidArgument = -3,
+}
+
+/// Kind of a parsed response
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RolandResponseKind {
+    /// `ack`
+    Ack = 0,
+    /// `DTH`, with one or more values
+    Data = 1,
+    /// `VER`
+    Version = 2,
+    /// `ERR` from the device, see `error` and `error_code`
+    Error = 3,
+    /// XON (0x11)
+    Xon = 4,
+    /// XOFF (0x13)
+    Xoff = 5,
+    /// A complete frame that couldn't be parsed, see `error`
+    Invalid = 6,
+}
+
+/// Error of an `Error` or `Invalid` response
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RolandErrorKind {
+    /// No error
+    None = 0,
+    /// Syntax error in received command (`ERR:0;`)
+    SyntaxError = 1,
+    /// Invalid command due to other settings (`ERR:4;`)
+    Invalid = 2,
+    /// Parameter out of range (`ERR:5;`)
+    OutOfRange = 3,
+    /// Missing STX at command start (`ERR:6;`)
+    NoStx = 4,
+    /// Other device error code, see `error_code`
+    UnknownError = 5,
+    /// Invalid address format
+    InvalidAddress = 6,
+    /// Invalid value format
+    InvalidValue = 7,
+    /// Invalid response format
+    InvalidResponse = 8,
+    /// SysEx checksum mismatch
+    ChecksumMismatch = 9,
+    /// Data around the frame
+    UnframedData = 10,
+    /// Buffer full
+    BufferFull = 11,
+    /// An error added to roland-core after this header was generated
+    Other = 12,
+}
+
+impl From<&RolandError> for RolandErrorKind {
+    fn from(error: &RolandError) -> Self {
+        match error {
+            RolandError::SyntaxError => RolandErrorKind::SyntaxError,
+            RolandError::Invalid => RolandErrorKind::Invalid,
+            RolandError::OutOfRange => RolandErrorKind::OutOfRange,
+            RolandError::NoStx => RolandErrorKind::NoStx,
+            RolandError::UnknownError(_) => RolandErrorKind::UnknownError,
+            RolandError::InvalidAddress => RolandErrorKind::InvalidAddress,
+            RolandError::InvalidValue => RolandErrorKind::InvalidValue,
+            RolandError::InvalidResponse => RolandErrorKind::InvalidResponse,
+            RolandError::ChecksumMismatch => RolandErrorKind::ChecksumMismatch,
+            RolandError::UnframedData => RolandErrorKind::UnframedData,
+            RolandError::BufferFull => RolandErrorKind::BufferFull,
+            _ => RolandErrorKind::Other,
+        }
+    }
+}
+
+/// Parsed response
+///
+/// Only the fields belonging to `kind` are meaningful; the others are
+/// zeroed.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RolandResponse {
+    /// Kind of response
+    pub kind: RolandResponseKind,
+    /// Error of an `Error` or `Invalid` response
+    pub error: RolandErrorKind,
+    /// Number of values in `data`
+    pub data_len: u32,
+    /// Address of `data[0]`, high byte first
+    pub address: [u8; 3],
+    /// Device error code of an `Error` response (the `n` in `ERR:n;`)
+    pub error_code: u8,
+    /// Non-zero if values or text didn't fit and were cut off
+    pub truncated: u8,
+    /// Values of a `Data` response
+    pub data: [u8; ROLAND_MAX_DATA],
+    /// Product name of a `Version` response, NUL-terminated
+    pub product: [c_char; ROLAND_MAX_TEXT],
+    /// Version of a `Version` response, NUL-terminated
+    pub version: [c_char; ROLAND_MAX_TEXT],
+}
+
+impl RolandResponse {
+    fn empty(kind: RolandResponseKind) -> Self {
+        RolandResponse {
+            kind,
+            error: RolandErrorKind::None,
+            data_len: 0,
+            address: [0; 3],
+            error_code: 0,
+            truncated: 0,
+            data: [0; ROLAND_MAX_DATA],
+            product: [0; ROLAND_MAX_TEXT],
+            version: [0; ROLAND_MAX_TEXT],
+        }
+    }
+
+    fn from_result(result: Result<Response, RolandError>) -> Self {
+        match result {
+            Ok(Response::Acknowledge) => Self::empty(RolandResponseKind::Ack),
+            Ok(Response::Xon) => Self::empty(RolandResponseKind::Xon),
+            Ok(Response::Xoff) => Self::empty(RolandResponseKind::Xoff),
+            Ok(Response::Error(error)) => {
+                let mut out = Self::empty(RolandResponseKind::Error);
+                out.error = RolandErrorKind::from(&error);
+                out.error_code = error.code().unwrap_or(0);
+                out
+            }
+            Ok(Response::Version { product, version }) => {
+                let mut out = Self::empty(RolandResponseKind::Version);
+                let cut =
+                    copy_text(&mut out.product, &product) | copy_text(&mut out.version, &version);
+                out.truncated = cut as u8;
+                out
+            }
+            Ok(response) => match response.as_data() {
+                Some((address, data)) => {
+                    let mut out = Self::empty(RolandResponseKind::Data);
+                    let len = data.len().min(ROLAND_MAX_DATA);
+                    out.address = [address.high, address.mid, address.low];
+                    out.data[..len].copy_from_slice(&data[..len]);
+                    out.data_len = len as u32;
+                    out.truncated = (len < data.len()) as u8;
+                    out
+                }
+                None => {
+                    let mut out = Self::empty(RolandResponseKind::Invalid);
+                    out.error = RolandErrorKind::Other;
+                    out
+                }
+            },
+            Err(error) => {
+                let mut out = Self::empty(RolandResponseKind::Invalid);
+                out.error = RolandErrorKind::from(&error);
+                out
+            }
+        }
+    }
+}
+
+/// Copy text into a NUL-terminated field, returning whether it was cut off
+fn copy_text(field: &mut [c_char; ROLAND_MAX_TEXT], text: &str) -> bool {
+    let mut len = text.len().min(ROLAND_MAX_TEXT - 1);
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    for (dst, src) in field.iter_mut().zip(&text.as_bytes()[..len]) {
+        *dst = *src as c_char;
+    }
+    len < text.len()
+}
+
+/// Encode a command into a caller buffer
+///
+/// # Safety
+/// `out_buf` must be valid for writes of `out_len` bytes.
+unsafe fn encode(command: &Command, out_buf: *mut u8, out_len: usize) -> i32 {
+    if out_buf.is_null() {
+        return RolandStatus::NullPointer as i32;
+    }
+    let buf = slice::from_raw_parts_mut(out_buf, out_len);
+    match command.encode_to_slice(buf) {
+        Ok(len) => len as i32,
+        Err(_) => RolandStatus::BufferTooSmall as i32,
+    }
+}
+
+/// Encode a write command (`DTH:aaaaaa,vv;`)
+///
+/// Writes the command without STX or NUL terminator to `out_buf`.
+///
+/// # Returns
+/// Number of bytes written, or a negative [`RolandStatus`].
+///
+/// # Safety
+/// `out_buf` must be valid for writes of `out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn roland_encode_write(
+    addr_high: u8,
+    addr_mid: u8,
+    addr_low: u8,
+    value: u8,
+    out_buf: *mut u8,
+    out_len: usize,
+) -> i32 {
+    let address = Address::new(addr_high, addr_mid, addr_low);
+    encode(&Command::write_parameter(address, value), out_buf, out_len)
+}
+
+/// Encode a read command (`RQH:aaaaaa,ssssss;`)
+///
+/// Writes the command without STX or NUL terminator to `out_buf`.
+///
+/// # Returns
+/// Number of bytes written, or a negative [`RolandStatus`]. A `size` of 0
+/// or above `0xFFFFFF` is an invalid argument.
+///
+/// # Safety
+/// `out_buf` must be valid for writes of `out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn roland_encode_read(
+    addr_high: u8,
+    addr_mid: u8,
+    addr_low: u8,
+    size: u32,
+    out_buf: *mut u8,
+    out_len: usize,
+) -> i32 {
+    let address = Address::new(addr_high, addr_mid, addr_low);
+    match Command::read(address, size) {
+        Ok(command) => encode(&command, out_buf, out_len),
+        Err(_) => RolandStatus::InvalidArgument as i32,
+    }
+}
+
+/// Parse the first complete frame of received data
+///
+/// Leading whitespace is skipped. On success, `out` is overwritten, and
+/// the returned count tells how many bytes to drop from the front of the
+/// receive buffer before the next call. A frame that is complete but
+/// malformed is reported as a `ROLAND_RESPONSE_KIND_INVALID` response, so
+/// it is consumed like any other.
+///
+/// # Returns
+/// Number of bytes consumed, `0` if `buf` holds no complete frame yet
+/// (`out` is left untouched), or a negative [`RolandStatus`]. Buffers
+/// longer than `INT32_MAX` are an invalid argument.
+///
+/// # Safety
+/// `buf` must be valid for reads of `len` bytes, and `out` must point to a
+/// writable `RolandResponse`.
+#[no_mangle]
+pub unsafe extern "C" fn roland_parse_response(
+    buf: *const u8,
+    len: usize,
+    out: *mut RolandResponse,
+) -> i32 {
+    if buf.is_null() || out.is_null() {
+        return RolandStatus::NullPointer as i32;
+    }
+    if len > i32::MAX as usize {
+        return RolandStatus::InvalidArgument as i32;
+    }
+    let input = slice::from_raw_parts(buf, len);
+    match Response::parse_first(input) {
+        Some((result, used)) => {
+            ptr::write(out, RolandResponse::from_result(result));
+            used as i32
+        }
+        None => 0,
+    }
+}
+
+/// Size of [`RolandResponse`] as compiled into the library
+///
+/// Compare with `sizeof(RolandResponse)` to catch a stale header.
+#[no_mangle]
+pub extern "C" fn roland_response_size() -> usize {
+    core::mem::size_of::<RolandResponse>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::MaybeUninit;
+
+    fn parse(input: &[u8]) -> (i32, RolandResponse) {
+        let mut out = MaybeUninit::<RolandResponse>::zeroed();
+        let used = unsafe { roland_parse_response(input.as_ptr(), input.len(), out.as_mut_ptr()) };
+        (used, unsafe { out.assume_init() })
+    }
+
+    #[test]
+    fn test_encode() {
+        let mut buf = [0u8; 32];
+        let len =
+            unsafe { roland_encode_write(0x12, 0x34, 0x56, 0x7F, buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(&buf[..len as usize], b"DTH:123456,7F;");
+
+        let len = unsafe { roland_encode_read(0x12, 0x34, 0x56, 2, buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(&buf[..len as usize], b"RQH:123456,000002;");
+
+        let status = unsafe { roland_encode_write(0, 0, 0, 0, buf.as_mut_ptr(), 4) };
+        assert_eq!(status, RolandStatus::BufferTooSmall as i32);
+        let status = unsafe { roland_encode_read(0, 0, 0, 0, buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(status, RolandStatus::InvalidArgument as i32);
+        let status = unsafe { roland_encode_write(0, 0, 0, 0, ptr::null_mut(), 32) };
+        assert_eq!(status, RolandStatus::NullPointer as i32);
+    }
+
+    #[test]
+    fn test_parse() {
+        let input = b"\r\nDTH:123456,01,02;ack";
+        let (used, response) = parse(input);
+        assert_eq!(used, 19);
+        assert_eq!(response.kind, RolandResponseKind::Data);
+        assert_eq!(response.address, [0x12, 0x34, 0x56]);
+        assert_eq!(&response.data[..response.data_len as usize], &[1, 2]);
+
+        let (used, response) = parse(&input[19..]);
+        assert_eq!(used, 3);
+        assert_eq!(response.kind, RolandResponseKind::Ack);
+
+        let (used, response) = parse(b"ERR:5;");
+        assert_eq!(used, 6);
+        assert_eq!(response.kind, RolandResponseKind::Error);
+        assert_eq!(response.error, RolandErrorKind::OutOfRange);
+        assert_eq!(response.error_code, 5);
+
+        // Incomplete frames aren't consumed
+        assert_eq!(parse(b"DTH:1234").0, 0);
+    }
+
+    #[test]
+    fn test_parse_invalid_and_text() {
+        let (used, response) = parse(b"DTH:12,01;");
+        assert_eq!(used, 10);
+        assert_eq!(response.kind, RolandResponseKind::Invalid);
+        assert_eq!(response.error, RolandErrorKind::InvalidAddress);
+
+        let long = "X".repeat(100);
+        let (_, response) = parse(format!("VER:VR-6HD,{};", long).as_bytes());
+        assert_eq!(response.kind, RolandResponseKind::Version);
+        assert_eq!(response.product[..7], b"VR-6HD\0".map(|b| b as c_char));
+        assert_eq!(response.version[ROLAND_MAX_TEXT - 1], 0);
+        assert_eq!(response.truncated, 1);
+    }
+}
@@ -0,0 +1,61 @@
+/* Links against the static library and exercises every entry point.
+ * Built and run by tests/c_program.rs. */
+
+#include "roland_core.h"
+
+#include <stdio.h>
+#include <string.h>
+
+static int failures = 0;
+
+#define CHECK(cond)                                                    \
+  do {                                                                 \
+    if (!(cond)) {                                                     \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+              #cond);                                                  \
+      failures++;                                                      \
+    }                                                                  \
+  } while (0)
+
+int main(void) {
+  uint8_t buf[32];
+  RolandResponse response;
+  int32_t n;
+
+  CHECK(roland_response_size() == sizeof(RolandResponse));
+
+  n = roland_encode_write(0x12, 0x34, 0x56, 0x7F, buf, sizeof buf);
+  CHECK(n == 14 && memcmp(buf, "DTH:123456,7F;", 14) == 0);
+  n = roland_encode_read(0x05, 0x00, 0x00, 1, buf, sizeof buf);
+  CHECK(n == 18 && memcmp(buf, "RQH:050000,000001;", 18) == 0);
+  CHECK(roland_encode_write(0, 0, 0, 0, buf, 4) == ROLAND_STATUS_BUFFER_TOO_SMALL);
+  CHECK(roland_encode_read(0, 0, 0, 0, buf, sizeof buf) == ROLAND_STATUS_INVALID_ARGUMENT);
+
+  const char *rx = "DTH:050000,64;VER:VR-6HD,1.10;ERR:5;";
+  size_t len = strlen(rx);
+  const uint8_t *p = (const uint8_t *)rx;
+
+  n = roland_parse_response(p, len, &response);
+  CHECK(n == 14);
+  CHECK(response.kind == ROLAND_RESPONSE_KIND_DATA);
+  CHECK(response.address[0] == 0x05 && response.data_len == 1 && response.data[0] == 0x64);
+  p += n, len -= n;
+
+  n = roland_parse_response(p, len, &response);
+  CHECK(response.kind == ROLAND_RESPONSE_KIND_VERSION);
+  CHECK(strcmp(response.product, "VR-6HD") == 0 && strcmp(response.version, "1.10") == 0);
+  p += n, len -= n;
+
+  n = roland_parse_response(p, len, &response);
+  CHECK(response.kind == ROLAND_RESPONSE_KIND_ERROR);
+  CHECK(response.error == ROLAND_ERROR_KIND_OUT_OF_RANGE && response.error_code == 5);
+  p += n, len -= n;
+
+  CHECK(len == 0 && roland_parse_response(p, len, &response) == 0);
+  CHECK(roland_parse_response(NULL, 0, &response) == ROLAND_STATUS_NULL_POINTER);
+
+  if (failures == 0) {
+    printf("ok\n");
+  }
+  return failures == 0 ? 0 : 1;
+}
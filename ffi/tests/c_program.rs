@@ -0,0 +1,60 @@
+//! Build the C test program against the static library and run it
+//!
+//! This proves that the header matches the library and that the symbols
+//! link from C. It needs a C compiler (`cc`, or `$CC`); without one the
+//! test is skipped.
+
+use std::env;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Find `libroland_core_ffi.a` built for this test run
+///
+/// The test binary lives in `target/<profile>/deps`. The library is next
+/// to it, and copied up to `target/<profile>` on a plain `cargo build`.
+fn static_library() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    let deps = exe.parent().unwrap();
+    [deps, deps.parent().unwrap()]
+        .iter()
+        .map(|dir| dir.join("libroland_core_ffi.a"))
+        .find(|path| path.exists())
+        .expect("libroland_core_ffi.a not found")
+}
+
+#[test]
+fn test_c_program() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let library = static_library();
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("roland_test");
+
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&compiler)
+        .arg(manifest.join("tests/c/roland_test.c"))
+        .arg("-I")
+        .arg(manifest.join("include"))
+        .args(["-std=c99", "-Wall", "-Werror", "-o"])
+        .arg(&program)
+        .arg(&library)
+        // Dependencies of the Rust standard library
+        .args(["-lpthread", "-ldl", "-lm"])
+        .status();
+    let status = match status {
+        Ok(status) => status,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            eprintln!("skipping: C compiler {} not found", compiler);
+            return;
+        }
+        Err(e) => panic!("failed to run {}: {}", compiler, e),
+    };
+    assert!(status.success(), "compiling the C test program failed");
+
+    let output = Command::new(&program).output().unwrap();
+    assert!(
+        output.status.success(),
+        "C test program failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+}
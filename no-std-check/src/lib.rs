@@ -0,0 +1,47 @@
+//! Compile check for roland-core in a `#![no_std]` crate
+//!
+//! This crate is built with the workspace so that a change pulling `std`
+//! into roland-core, or into the allocation-free formatting paths used for
+//! embedded logging, fails the build. It has no API of its own.
+
+#![no_std]
+
+use core::fmt;
+use roland_core::queue::CommandQueue;
+use roland_core::{Address, Command, Response, RolandError};
+
+/// Formatter writing into a fixed buffer, like an RTT log channel
+struct LogBuffer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl fmt::Write for LogBuffer<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Log a command and the device's answer in their wire form
+pub fn log_exchange(command: &Command, response: &Response, buf: &mut [u8]) -> Option<usize> {
+    let mut log = LogBuffer { buf, len: 0 };
+    command.write(&mut log).ok()?;
+    fmt::Write::write_str(&mut log, " -> ").ok()?;
+    response.write(&mut log).ok()?;
+    Some(log.len)
+}
+
+/// Queue a write for a UART transmit interrupt
+pub fn queue_write<const N: usize>(
+    queue: &mut CommandQueue<N>,
+    address: Address,
+    value: u8,
+) -> Result<(), RolandError> {
+    queue.push_with_stx(&Command::write_parameter(address, value))
+}
@@ -0,0 +1,740 @@
+//! High-level audio mixer control
+
+use crate::glide::GlideHandle;
+use crate::units::ParamScale;
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::{audio, meter, output};
+use roland_core::{Address, RolandError};
+use std::time::Duration;
+
+/// Fader level in decibels
+///
+/// The device represents fader levels as a byte from 0 to 127:
+///
+/// * `0` is -INF (fully closed)
+/// * `1..=127` map linearly to -53.0 dB .. +10.0 dB in 0.5 dB steps,
+///   so `107` is 0 dB (unity gain)
+///
+/// Converting to a byte rounds to the nearest step and clamps to that
+/// range; levels below -53.25 dB close the fader. This is
+/// [`ParamScale::FADER`]; other parameters in dB convert with
+/// [`Db::to_device_byte`].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Db(pub f32);
+
+impl Db {
+    /// Fader closed
+    pub const NEG_INFINITY: Db = Db(f32::NEG_INFINITY);
+    /// Unity gain
+    pub const ZERO: Db = Db(0.0);
+    /// Highest fader level
+    pub const MAX: Db = Db(10.0);
+    /// Lowest fader level above -INF
+    pub const MIN: Db = Db(-53.0);
+
+    /// Convert a raw fader byte to a level
+    ///
+    /// Values above 127 are treated as 127.
+    pub fn from_byte(value: u8) -> Self {
+        Db::from_device_byte(value.min(127), &ParamScale::FADER).unwrap_or(Db::MAX)
+    }
+
+    /// Convert the level to a raw fader byte
+    pub fn to_byte(self) -> u8 {
+        self.to_device_byte(&ParamScale::FADER).unwrap_or(0)
+    }
+
+    /// Get the level in dB
+    pub fn value(self) -> f32 {
+        self.0
+    }
+}
+
+/// Audio channel or output bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioChannel {
+    /// Input CH1
+    Ch1,
+    /// Input CH2
+    Ch2,
+    /// Input CH3
+    Ch3,
+    /// Input CH4
+    Ch4,
+    /// Input CH5
+    Ch5,
+    /// Input CH6
+    Ch6,
+    /// USB audio input
+    Usb,
+    /// Bluetooth audio input
+    Bluetooth,
+    /// MAIN output bus
+    Main,
+    /// AUX output bus
+    Aux,
+    /// USB OUT bus, see [`AudioMixer::set_usb_audio_source`]
+    UsbOut,
+}
+
+impl AudioChannel {
+    /// All channels and buses
+    pub const ALL: [AudioChannel; 11] = [
+        AudioChannel::Ch1,
+        AudioChannel::Ch2,
+        AudioChannel::Ch3,
+        AudioChannel::Ch4,
+        AudioChannel::Ch5,
+        AudioChannel::Ch6,
+        AudioChannel::Usb,
+        AudioChannel::Bluetooth,
+        AudioChannel::Main,
+        AudioChannel::Aux,
+        AudioChannel::UsbOut,
+    ];
+
+    /// Get the address of a parameter of the channel
+    ///
+    /// `offset` is one of the offsets in [`roland_core::params::audio`].
+    pub fn address(self, offset: u8) -> Address {
+        match self {
+            AudioChannel::Main => output::bus(0, offset),
+            AudioChannel::Aux => output::bus(1, offset),
+            AudioChannel::UsbOut => output::bus(2, offset),
+            input => audio::channel(input as u8, offset),
+        }
+    }
+
+    /// Get the address of the level meter of the channel
+    pub fn meter_address(self) -> Address {
+        match self {
+            AudioChannel::Main => meter::output(0),
+            AudioChannel::Aux => meter::output(1),
+            AudioChannel::UsbOut => meter::output(2),
+            input => meter::input(input as u8),
+        }
+    }
+
+    /// Get the name of the channel in parameter names, e.g. `ch1` or `main`
+    pub fn name(self) -> &'static str {
+        match self {
+            AudioChannel::Ch1 => "ch1",
+            AudioChannel::Ch2 => "ch2",
+            AudioChannel::Ch3 => "ch3",
+            AudioChannel::Ch4 => "ch4",
+            AudioChannel::Ch5 => "ch5",
+            AudioChannel::Ch6 => "ch6",
+            AudioChannel::Usb => "usb",
+            AudioChannel::Bluetooth => "bluetooth",
+            AudioChannel::Main => "main",
+            AudioChannel::Aux => "aux",
+            AudioChannel::UsbOut => "usb_out",
+        }
+    }
+
+    /// Get a channel by its name, see [`AudioChannel::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|channel| channel.name() == name)
+    }
+
+    /// Check if this is an output bus rather than an input channel
+    pub fn is_bus(self) -> bool {
+        matches!(
+            self,
+            AudioChannel::Main | AudioChannel::Aux | AudioChannel::UsbOut
+        )
+    }
+}
+
+/// Audio sent to the USB output
+///
+/// Values this crate doesn't know decode to [`UsbAudioSource::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum UsbAudioSource {
+    /// MAIN bus mix
+    Main,
+    /// AUX bus mix
+    Aux,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl UsbAudioSource {
+    /// All documented sources
+    pub const ALL: [UsbAudioSource; 2] = [UsbAudioSource::Main, UsbAudioSource::Aux];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => UsbAudioSource::Main,
+            1 => UsbAudioSource::Aux,
+            value => UsbAudioSource::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            UsbAudioSource::Main => 0,
+            UsbAudioSource::Aux => 1,
+            UsbAudioSource::Other(value) => value,
+        }
+    }
+}
+
+/// Audio monitored on the headphones
+///
+/// Values this crate doesn't know decode to [`MonitorSource::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum MonitorSource {
+    /// MAIN bus mix
+    Main,
+    /// AUX bus mix
+    Aux,
+    /// Soloed channels, see [`SoloMode`]; MAIN while nothing is soloed
+    Solo,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl MonitorSource {
+    /// All documented sources
+    pub const ALL: [MonitorSource; 3] =
+        [MonitorSource::Main, MonitorSource::Aux, MonitorSource::Solo];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => MonitorSource::Main,
+            1 => MonitorSource::Aux,
+            2 => MonitorSource::Solo,
+            value => MonitorSource::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            MonitorSource::Main => 0,
+            MonitorSource::Aux => 1,
+            MonitorSource::Solo => 2,
+            MonitorSource::Other(value) => value,
+        }
+    }
+}
+
+/// Where soloed channels are picked up for monitoring
+///
+/// Values this crate doesn't know decode to [`SoloMode::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SoloMode {
+    /// Pre-fader listen: the channel as it enters the fader
+    Pfl,
+    /// After-fader listen: the channel at its fader level
+    Afl,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl SoloMode {
+    /// All documented modes
+    pub const ALL: [SoloMode; 2] = [SoloMode::Pfl, SoloMode::Afl];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => SoloMode::Pfl,
+            1 => SoloMode::Afl,
+            value => SoloMode::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            SoloMode::Pfl => 0,
+            SoloMode::Afl => 1,
+            SoloMode::Other(value) => value,
+        }
+    }
+}
+
+/// Level meter value
+///
+/// Meters show the peak level as a byte from 0 to 127: `0` is -INF, and
+/// `1..=127` map to -63.0 dBFS .. 0.0 dBFS in 0.5 dB steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeterReading {
+    /// Value as read from the device
+    pub raw: u8,
+}
+
+impl MeterReading {
+    /// Convert the reading to dBFS, `f32::NEG_INFINITY` for silence
+    ///
+    /// Values above 127 are treated as 127 (full scale).
+    pub fn dbfs(self) -> f32 {
+        match self.raw.min(meter::FULL_SCALE) {
+            0 => f32::NEG_INFINITY,
+            v => (v as f32 - meter::FULL_SCALE as f32) * 0.5,
+        }
+    }
+}
+
+/// Channels [`AudioMixer::read_all_meters`] reads
+const METERED: [AudioChannel; 8] = [
+    AudioChannel::Ch1,
+    AudioChannel::Ch2,
+    AudioChannel::Ch3,
+    AudioChannel::Ch4,
+    AudioChannel::Ch5,
+    AudioChannel::Ch6,
+    AudioChannel::Usb,
+    AudioChannel::Main,
+];
+
+/// Audio mixer facade
+///
+/// Wraps a client to control the mixer without dealing with addresses.
+/// Addresses are resolved through the client's
+/// [`crate::profile::Profile`].
+///
+/// # Example
+/// ```no_run
+/// use roland_rs::audio::{AudioChannel, AudioMixer, Db};
+/// use roland_rs::TelnetClient;
+///
+/// let mut client = TelnetClient::connect("192.168.1.100", 23)?;
+/// let mut mixer = AudioMixer::new(&mut client);
+/// mixer.set_fader(AudioChannel::Ch1, Db(-6.0))?;
+/// mixer.set_mute(AudioChannel::Bluetooth, true)?;
+/// # Ok::<(), roland_rs::TelnetError>(())
+/// ```
+pub struct AudioMixer<'a> {
+    client: &'a mut TelnetClient,
+}
+
+impl<'a> AudioMixer<'a> {
+    /// Largest pan value (full right); the smallest is -64 (full left)
+    pub const MAX_PAN: i8 = 63;
+
+    /// Create a facade for a connected client
+    pub fn new(client: &'a mut TelnetClient) -> Self {
+        Self { client }
+    }
+
+    /// Set the fader level of a channel
+    pub fn set_fader(&mut self, channel: AudioChannel, level: Db) -> Result<(), TelnetError> {
+        let address = self.address(channel, "fader")?;
+        self.client.write_parameter_addr(address, level.to_byte())
+    }
+
+    /// Move the fader of a channel to `target` gradually
+    ///
+    /// The fader moves from its current level, or from where a glide
+    /// running on it has got to, in steps evenly spaced in dB at the
+    /// client's [`TelnetClient::glide_rate`]. That glide is replaced. The
+    /// steps are written by [`TelnetClient::run_glides`], see
+    /// [`crate::glide`]; only the current level is read here.
+    ///
+    /// # Returns
+    /// * `Result<GlideHandle, TelnetError>` - Handle to cancel the glide
+    pub fn glide_fader(
+        &mut self,
+        channel: AudioChannel,
+        target: Db,
+        duration: Duration,
+    ) -> Result<GlideHandle, TelnetError> {
+        let address = self.address(channel, "fader")?;
+        let from = match self.client.glides.level(address) {
+            Some(level) => level,
+            None => Db::from_byte(self.client.read_parameter_addr(address, 1)?),
+        };
+        Ok(self.client.glides.start(address, from, target, duration))
+    }
+
+    /// Get the fader level of a channel
+    pub fn get_fader(&mut self, channel: AudioChannel) -> Result<Db, TelnetError> {
+        let address = self.address(channel, "fader")?;
+        let value = self.client.read_parameter_addr(address, 1)?;
+        Ok(Db::from_byte(value))
+    }
+
+    /// Mute or unmute a channel
+    pub fn set_mute(&mut self, channel: AudioChannel, mute: bool) -> Result<(), TelnetError> {
+        let address = self.address(channel, "mute")?;
+        self.client.write_parameter_addr(address, mute as u8)
+    }
+
+    /// Check if a channel is muted
+    pub fn is_muted(&mut self, channel: AudioChannel) -> Result<bool, TelnetError> {
+        let address = self.address(channel, "mute")?;
+        let value = self.client.read_parameter_addr(address, 1)?;
+        Ok(value != 0)
+    }
+
+    /// Solo or unsolo an input channel
+    ///
+    /// Output buses have no solo; they fail with `Invalid` without
+    /// sending anything.
+    pub fn set_solo(&mut self, channel: AudioChannel, solo: bool) -> Result<(), TelnetError> {
+        if channel.is_bus() {
+            return Err(TelnetError::Protocol(RolandError::Invalid));
+        }
+        let address = self.address(channel, "solo")?;
+        self.client.write_parameter_addr(address, solo as u8)
+    }
+
+    /// Set the pan of an input channel
+    ///
+    /// `pan` ranges from -64 (full left) to 63 (full right), 0 is center.
+    /// Other values fail with `OutOfRange`, and output buses with
+    /// `Invalid`, without sending anything.
+    pub fn set_pan(&mut self, channel: AudioChannel, pan: i8) -> Result<(), TelnetError> {
+        if channel.is_bus() {
+            return Err(TelnetError::Protocol(RolandError::Invalid));
+        }
+        if pan > Self::MAX_PAN {
+            return Err(TelnetError::Protocol(RolandError::OutOfRange));
+        }
+        let address = self.address(channel, "pan")?;
+        self.client
+            .write_parameter_addr(address, audio::PAN_ENCODING.encode_i8(pan)?)
+    }
+
+    /// Get the pan of an input channel, -64 (full left) to 63 (full right)
+    pub fn get_pan(&mut self, channel: AudioChannel) -> Result<i8, TelnetError> {
+        if channel.is_bus() {
+            return Err(TelnetError::Protocol(RolandError::Invalid));
+        }
+        let address = self.address(channel, "pan")?;
+        let value = self.client.read_parameter_addr(address, 1)?;
+        Ok(audio::PAN_ENCODING.decode_i8(value)?)
+    }
+
+    /// Set the mix sent to the USB output
+    ///
+    /// The USB OUT level and mute are set like any other bus, with
+    /// [`AudioChannel::UsbOut`].
+    pub fn set_usb_audio_source(&mut self, source: UsbAudioSource) -> Result<(), TelnetError> {
+        let address = self.address(AudioChannel::UsbOut, "source")?;
+        self.client.write_parameter_addr(address, source.value())
+    }
+
+    /// Get the mix sent to the USB output
+    pub fn usb_audio_source(&mut self) -> Result<UsbAudioSource, TelnetError> {
+        let address = self.address(AudioChannel::UsbOut, "source")?;
+        let value = self.client.read_parameter_addr(address, 1)?;
+        Ok(UsbAudioSource::from_value(value))
+    }
+
+    /// Set what the headphones monitor
+    ///
+    /// The main mix is unaffected.
+    pub fn set_phones_source(&mut self, source: MonitorSource) -> Result<(), TelnetError> {
+        let address = self.client.profile().address("audio.phones.source")?;
+        self.client.write_parameter_addr(address, source.value())
+    }
+
+    /// Get what the headphones monitor
+    pub fn phones_source(&mut self) -> Result<MonitorSource, TelnetError> {
+        let address = self.client.profile().address("audio.phones.source")?;
+        let value = self.client.read_parameter_addr(address, 1)?;
+        Ok(MonitorSource::from_value(value))
+    }
+
+    /// Set the headphone level, on the same scale as the faders
+    pub fn set_phones_level(&mut self, level: Db) -> Result<(), TelnetError> {
+        let address = self.client.profile().address("audio.phones.level")?;
+        self.client.write_parameter_addr(address, level.to_byte())
+    }
+
+    /// Get the headphone level
+    pub fn phones_level(&mut self) -> Result<Db, TelnetError> {
+        let address = self.client.profile().address("audio.phones.level")?;
+        let value = self.client.read_parameter_addr(address, 1)?;
+        Ok(Db::from_byte(value))
+    }
+
+    /// Set where soloed channels are picked up (PFL or AFL)
+    pub fn set_solo_mode(&mut self, mode: SoloMode) -> Result<(), TelnetError> {
+        let address = self.client.profile().address("audio.solo_mode")?;
+        self.client.write_parameter_addr(address, mode.value())
+    }
+
+    /// Get where soloed channels are picked up
+    pub fn solo_mode(&mut self) -> Result<SoloMode, TelnetError> {
+        let address = self.client.profile().address("audio.solo_mode")?;
+        let value = self.client.read_parameter_addr(address, 1)?;
+        Ok(SoloMode::from_value(value))
+    }
+
+    /// Read the level meter of a channel
+    ///
+    /// Meters always come from the device, never from the read cache.
+    pub fn read_meter(&mut self, channel: AudioChannel) -> Result<MeterReading, TelnetError> {
+        Ok(self.read_meters(&[channel])?[0].1)
+    }
+
+    /// Read the meters of CH1-6, USB and MAIN
+    ///
+    /// Meters at consecutive addresses are read together, so this takes
+    /// two commands with the VR-6HD layout.
+    pub fn read_all_meters(&mut self) -> Result<Vec<(AudioChannel, MeterReading)>, TelnetError> {
+        self.read_meters(&METERED)
+    }
+
+    /// Read the meters of several channels
+    ///
+    /// Runs of channels whose meters are at consecutive addresses are read
+    /// with a single command each.
+    ///
+    /// # Returns
+    /// * `Result<Vec<(AudioChannel, MeterReading)>, TelnetError>` - Readings
+    ///   in the order of `channels`
+    pub fn read_meters(
+        &mut self,
+        channels: &[AudioChannel],
+    ) -> Result<Vec<(AudioChannel, MeterReading)>, TelnetError> {
+        let addresses = channels
+            .iter()
+            .map(|&channel| self.address(channel, "meter"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut readings = Vec::with_capacity(channels.len());
+        let mut start = 0;
+        while start < channels.len() {
+            let mut end = start + 1;
+            while end < channels.len()
+                && u32::from(addresses[end]) == u32::from(addresses[start]) + (end - start) as u32
+            {
+                end += 1;
+            }
+            let data = self
+                .client
+                .read_parameter_bytes(addresses[start], (end - start) as u32)?;
+            for (&channel, &raw) in channels[start..end].iter().zip(&data) {
+                readings.push((channel, MeterReading { raw }));
+            }
+            start = end;
+        }
+        Ok(readings)
+    }
+
+    /// Resolve a channel parameter through the client's profile
+    fn address(&self, channel: AudioChannel, param: &str) -> Result<Address, TelnetError> {
+        let name = format!("audio.{}.{}", channel.name(), param);
+        self.client.profile().address(&name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::Command;
+
+    #[test]
+    fn test_db_curve() {
+        assert_eq!(Db::NEG_INFINITY.to_byte(), 0);
+        assert_eq!(Db::from_byte(0), Db::NEG_INFINITY);
+        assert_eq!(Db::ZERO.to_byte(), 107);
+        assert_eq!(Db::from_byte(107), Db::ZERO);
+        assert_eq!(Db::MAX.to_byte(), 127);
+        assert_eq!(Db::from_byte(127), Db::MAX);
+        assert_eq!(Db::MIN.to_byte(), 1);
+
+        // Rounding and clamping
+        assert_eq!(Db(-6.2).to_byte(), 95);
+        assert_eq!(Db(20.0).to_byte(), 127);
+        assert_eq!(Db(-53.2).to_byte(), 1);
+        assert_eq!(Db(-60.0).to_byte(), 0);
+        assert_eq!(Db(f32::NAN).to_byte(), 0);
+        assert_eq!(Db::from_byte(200), Db::MAX);
+
+        for value in 0..=127 {
+            assert_eq!(Db::from_byte(value).to_byte(), value);
+        }
+    }
+
+    #[test]
+    fn test_channel_addresses() {
+        assert_eq!(AudioChannel::Ch1.address(audio::LEVEL), audio::CH1_LEVEL);
+        assert_eq!(AudioChannel::Usb.address(audio::MUTE), audio::USB_MUTE);
+        assert_eq!(
+            AudioChannel::Bluetooth.address(audio::LEVEL),
+            audio::BLUETOOTH_LEVEL
+        );
+        assert_eq!(AudioChannel::Main.address(audio::LEVEL), output::MAIN_LEVEL);
+        assert_eq!(AudioChannel::Aux.address(audio::MUTE), output::AUX_MUTE);
+        assert_eq!(
+            AudioChannel::UsbOut.address(audio::LEVEL),
+            output::USB_OUT_LEVEL
+        );
+        assert_eq!(
+            AudioChannel::from_name("usb_out"),
+            Some(AudioChannel::UsbOut)
+        );
+    }
+
+    #[test]
+    fn test_set_get_fader() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut mixer = AudioMixer::new(&mut client);
+
+        mixer.set_fader(AudioChannel::Ch2, Db(-6.2)).unwrap();
+        assert_eq!(mixer.get_fader(AudioChannel::Ch2).unwrap(), Db(-6.0));
+
+        mixer
+            .set_fader(AudioChannel::Aux, Db::NEG_INFINITY)
+            .unwrap();
+        assert_eq!(mock.parameter(output::AUX_LEVEL), Some(0));
+        assert_eq!(
+            mixer.get_fader(AudioChannel::Aux).unwrap(),
+            Db::NEG_INFINITY
+        );
+    }
+
+    #[test]
+    fn test_meter_scale() {
+        assert_eq!(MeterReading { raw: 0 }.dbfs(), f32::NEG_INFINITY);
+        assert_eq!(MeterReading { raw: 1 }.dbfs(), -63.0);
+        assert_eq!(MeterReading { raw: 107 }.dbfs(), -10.0);
+        assert_eq!(MeterReading { raw: 127 }.dbfs(), 0.0);
+        assert_eq!(MeterReading { raw: 200 }.dbfs(), 0.0);
+    }
+
+    #[test]
+    fn test_read_meters() {
+        let (addr, mock) = MockDevice::spawn();
+        for (i, channel) in AudioChannel::ALL.into_iter().enumerate() {
+            mock.set_parameter(channel.meter_address(), 100 + i as u8);
+        }
+        let mut client = connect(addr);
+        client.set_read_cache(true);
+        let mut mixer = AudioMixer::new(&mut client);
+
+        let readings = mixer.read_all_meters().unwrap();
+        assert_eq!(readings.len(), 8);
+        assert_eq!(readings[0], (AudioChannel::Ch1, MeterReading { raw: 100 }));
+        assert_eq!(readings[6], (AudioChannel::Usb, MeterReading { raw: 106 }));
+        assert_eq!(readings[7], (AudioChannel::Main, MeterReading { raw: 108 }));
+        // CH1-6 and USB are consecutive, MAIN is in the output block
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::read(meter::input(0), 7).unwrap(),
+                Command::read(meter::output(0), 1).unwrap(),
+            ]
+        );
+
+        // Not cached
+        mock.set_parameter(AudioChannel::Aux.meter_address(), 50);
+        assert_eq!(mixer.read_meter(AudioChannel::Aux).unwrap().raw, 50);
+        mock.set_parameter(AudioChannel::Aux.meter_address(), 60);
+        assert_eq!(mixer.read_meter(AudioChannel::Aux).unwrap().raw, 60);
+    }
+
+    #[test]
+    fn test_mute_solo_pan() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut mixer = AudioMixer::new(&mut client);
+
+        mixer.set_mute(AudioChannel::Main, true).unwrap();
+        assert!(mixer.is_muted(AudioChannel::Main).unwrap());
+        mixer.set_solo(AudioChannel::Ch3, true).unwrap();
+        mixer.set_pan(AudioChannel::Ch4, -64).unwrap();
+        mixer.set_pan(AudioChannel::Ch4, 0).unwrap();
+
+        assert!(matches!(
+            mixer.set_pan(AudioChannel::Ch4, 64),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+        assert!(matches!(
+            mixer.set_solo(AudioChannel::Aux, true),
+            Err(TelnetError::Protocol(RolandError::Invalid))
+        ));
+
+        let encoded: Vec<String> = mock.received().iter().map(Command::encode).collect();
+        assert_eq!(
+            encoded,
+            vec![
+                "DTH:060001,01;",
+                "RQH:060001,000001;",
+                "DTH:050202,01;",
+                "DTH:050303,00;",
+                "DTH:050303,40;",
+            ]
+        );
+
+        mock.set_parameter(AudioChannel::Ch4.address(audio::PAN), 0x3F);
+        assert_eq!(mixer.get_pan(AudioChannel::Ch4).unwrap(), -1);
+        assert!(matches!(
+            mixer.set_pan(AudioChannel::Ch4, -65),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+    }
+
+    #[test]
+    fn test_usb_output() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut mixer = AudioMixer::new(&mut client);
+
+        for source in UsbAudioSource::ALL
+            .into_iter()
+            .chain([UsbAudioSource::Other(9)])
+        {
+            mixer.set_usb_audio_source(source).unwrap();
+            assert_eq!(mock.parameter(output::USB_OUT_SOURCE), Some(source.value()));
+            assert_eq!(mixer.usb_audio_source().unwrap(), source);
+        }
+        for value in 0..=255 {
+            assert_eq!(UsbAudioSource::from_value(value).value(), value);
+        }
+
+        mixer.set_fader(AudioChannel::UsbOut, Db(-10.0)).unwrap();
+        mixer.set_mute(AudioChannel::UsbOut, false).unwrap();
+        assert_eq!(mock.parameter(output::USB_OUT_LEVEL), Some(87));
+        assert_eq!(mock.parameter(output::USB_OUT_MUTE), Some(0));
+        assert_eq!(mixer.get_fader(AudioChannel::UsbOut).unwrap(), Db(-10.0));
+        assert!(matches!(
+            mixer.set_solo(AudioChannel::UsbOut, true),
+            Err(TelnetError::Protocol(RolandError::Invalid))
+        ));
+    }
+
+    #[test]
+    fn test_phones_and_solo_mode() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut mixer = AudioMixer::new(&mut client);
+
+        for source in MonitorSource::ALL
+            .into_iter()
+            .chain([MonitorSource::Other(7)])
+        {
+            mixer.set_phones_source(source).unwrap();
+            assert_eq!(mixer.phones_source().unwrap(), source);
+        }
+        for mode in SoloMode::ALL.into_iter().chain([SoloMode::Other(2)]) {
+            mixer.set_solo_mode(mode).unwrap();
+            assert_eq!(mixer.solo_mode().unwrap(), mode);
+        }
+        for value in 0..=255 {
+            assert_eq!(MonitorSource::from_value(value).value(), value);
+            assert_eq!(SoloMode::from_value(value).value(), value);
+        }
+
+        mixer.set_phones_level(Db(-12.0)).unwrap();
+        assert_eq!(mixer.phones_level().unwrap(), Db(-12.0));
+        assert_eq!(mock.parameter(output::PHONES_LEVEL), Some(83));
+        assert_eq!(mock.parameter(output::SOLO_MODE), Some(2));
+        // The main mix is untouched
+        assert_eq!(mock.parameter(output::MAIN_LEVEL), None);
+    }
+}
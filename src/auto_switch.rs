@@ -0,0 +1,298 @@
+//! Auto switching and video follows audio
+//!
+//! The device can switch inputs by itself, either cycling through them at
+//! a fixed interval ([`AutoSwitchMode::Pattern`]) or following the loudest
+//! audio input above its threshold ([`AutoSwitchMode::AudioFollow`]).
+//! Thresholds use the meter scale, see [`crate::audio::MeterReading`].
+
+use crate::audio::{AudioChannel, MeterReading};
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::video;
+use roland_core::RolandError;
+use std::time::Duration;
+
+/// Number of audio inputs with a video-follows-audio threshold (CH1-6)
+const THRESHOLD_COUNT: u8 = 6;
+
+/// How the device switches inputs by itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AutoSwitchMode {
+    /// No auto switching
+    Off,
+    /// Cycle through the inputs at the pattern interval
+    Pattern,
+    /// Switch to the input whose audio is above its threshold
+    AudioFollow,
+}
+
+impl AutoSwitchMode {
+    /// Get the parameter value of the mode
+    pub fn value(self) -> u8 {
+        self as u8
+    }
+
+    /// Get the mode for a parameter value
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(AutoSwitchMode::Off),
+            1 => Some(AutoSwitchMode::Pattern),
+            2 => Some(AutoSwitchMode::AudioFollow),
+            _ => None,
+        }
+    }
+}
+
+/// Auto switching facade
+///
+/// # Example
+/// ```no_run
+/// use roland_rs::audio::AudioChannel;
+/// use roland_rs::auto_switch::AutoSwitch;
+/// use roland_rs::TelnetClient;
+///
+/// let mut client = TelnetClient::connect("192.168.1.100", 23)?;
+/// AutoSwitch::new(&mut client).enable_audio_follow(&[
+///     (AudioChannel::Ch1, -30.0),
+///     (AudioChannel::Ch2, -24.0),
+/// ])?;
+/// # Ok::<(), roland_rs::TelnetError>(())
+/// ```
+pub struct AutoSwitch<'a> {
+    client: &'a mut TelnetClient,
+}
+
+impl<'a> AutoSwitch<'a> {
+    /// Shortest pattern interval
+    pub const MIN_INTERVAL: Duration = Duration::from_secs(1);
+    /// Longest pattern interval
+    pub const MAX_INTERVAL: Duration = Duration::from_secs(120);
+
+    /// Create a facade for a connected client
+    pub fn new(client: &'a mut TelnetClient) -> Self {
+        Self { client }
+    }
+
+    /// Set the auto switching mode
+    ///
+    /// Before switching to [`AutoSwitchMode::AudioFollow`], the thresholds
+    /// are read back from the device; if any of CH1-6 is unset, this fails
+    /// with `InvalidValue` and the mode is left as it was, so the device
+    /// never follows garbage thresholds.
+    pub fn set_auto_switch_mode(&mut self, mode: AutoSwitchMode) -> Result<(), TelnetError> {
+        if mode == AutoSwitchMode::AudioFollow {
+            let thresholds = self
+                .client
+                .read_parameter_bytes(video::audio_follow_threshold(0), THRESHOLD_COUNT as u32)?;
+            if thresholds
+                .iter()
+                .any(|&raw| decode_threshold(raw).is_none())
+            {
+                return Err(TelnetError::Protocol(RolandError::InvalidValue));
+            }
+        }
+        self.client
+            .write_parameter_addr(video::AUTO_SWITCH_MODE, mode.value())
+    }
+
+    /// Get the auto switching mode
+    pub fn auto_switch_mode(&mut self) -> Result<AutoSwitchMode, TelnetError> {
+        let value = self
+            .client
+            .read_parameter_addr(video::AUTO_SWITCH_MODE, 1)?;
+        AutoSwitchMode::from_value(value).ok_or(TelnetError::Protocol(RolandError::InvalidValue))
+    }
+
+    /// Set the level above which an input's audio switches to its video
+    ///
+    /// `dbfs` is rounded to the nearest 0.5 dB. Levels outside -63 to
+    /// 0 dBFS fail with `OutOfRange`, and channels other than CH1-6 with
+    /// `Invalid`, without sending anything.
+    pub fn set_audio_follow_threshold(
+        &mut self,
+        channel: AudioChannel,
+        dbfs: f32,
+    ) -> Result<(), TelnetError> {
+        let address = threshold_address(channel)?;
+        let raw = encode_threshold(dbfs)?;
+        self.client.write_parameter_addr(address, raw)
+    }
+
+    /// Get the video-follows-audio threshold of a channel in dBFS
+    ///
+    /// # Returns
+    /// * `Result<Option<f32>, TelnetError>` - Threshold, or `None` if unset
+    pub fn audio_follow_threshold(
+        &mut self,
+        channel: AudioChannel,
+    ) -> Result<Option<f32>, TelnetError> {
+        let address = threshold_address(channel)?;
+        let raw = self.client.read_parameter_addr(address, 1)?;
+        Ok(decode_threshold(raw))
+    }
+
+    /// Write thresholds, check them and switch to video follows audio
+    ///
+    /// Each threshold is written and read back before the mode is set; the
+    /// first that fails or reads back differently stops with an error,
+    /// leaving the mode unchanged.
+    pub fn enable_audio_follow(
+        &mut self,
+        thresholds: &[(AudioChannel, f32)],
+    ) -> Result<(), TelnetError> {
+        // Validate all before writing any
+        let encoded = thresholds
+            .iter()
+            .map(|&(channel, dbfs)| Ok((threshold_address(channel)?, encode_threshold(dbfs)?)))
+            .collect::<Result<Vec<_>, TelnetError>>()?;
+        for (address, raw) in encoded {
+            if self.client.write_parameter_verified(address, raw)? != raw {
+                return Err(TelnetError::Protocol(RolandError::InvalidValue));
+            }
+        }
+        self.set_auto_switch_mode(AutoSwitchMode::AudioFollow)
+    }
+
+    /// Set how long each input stays on program in pattern mode
+    ///
+    /// The device uses 1 second steps, so `interval` is rounded to the
+    /// nearest second. Intervals outside
+    /// [`AutoSwitch::MIN_INTERVAL`]..=[`AutoSwitch::MAX_INTERVAL`] fail with
+    /// `OutOfRange` without sending anything.
+    pub fn set_pattern_interval(&mut self, interval: Duration) -> Result<(), TelnetError> {
+        let secs = (interval.as_millis() + 500) / 1000;
+        if !(Self::MIN_INTERVAL.as_secs() as u128..=Self::MAX_INTERVAL.as_secs() as u128)
+            .contains(&secs)
+        {
+            return Err(TelnetError::Protocol(RolandError::OutOfRange));
+        }
+        self.client
+            .write_parameter_addr(video::AUTO_SWITCH_INTERVAL, secs as u8)
+    }
+
+    /// Get the pattern interval
+    pub fn pattern_interval(&mut self) -> Result<Duration, TelnetError> {
+        let secs = self
+            .client
+            .read_parameter_addr(video::AUTO_SWITCH_INTERVAL, 1)?;
+        Ok(Duration::from_secs(secs as u64))
+    }
+}
+
+fn threshold_address(channel: AudioChannel) -> Result<roland_core::Address, TelnetError> {
+    let index = AudioChannel::ALL
+        .iter()
+        .position(|&c| c == channel)
+        .filter(|&index| index < THRESHOLD_COUNT as usize)
+        .ok_or(TelnetError::Protocol(RolandError::Invalid))?;
+    Ok(video::audio_follow_threshold(index as u8))
+}
+
+/// Encode a threshold on the meter scale
+fn encode_threshold(dbfs: f32) -> Result<u8, TelnetError> {
+    let raw = (dbfs * 2.0).round() + 127.0;
+    if !(1.0..=127.0).contains(&raw) {
+        return Err(TelnetError::Protocol(RolandError::OutOfRange));
+    }
+    Ok(raw as u8)
+}
+
+/// Decode a threshold, `None` if unset or invalid
+fn decode_threshold(raw: u8) -> Option<f32> {
+    (1..=127)
+        .contains(&raw)
+        .then(|| MeterReading { raw }.dbfs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::Command;
+
+    #[test]
+    fn test_enable_audio_follow_order() {
+        let (addr, mock) = MockDevice::spawn();
+        for i in 0..THRESHOLD_COUNT {
+            mock.set_parameter(video::audio_follow_threshold(i), 100);
+        }
+        let mut client = connect(addr);
+        let mut auto = AutoSwitch::new(&mut client);
+
+        auto.enable_audio_follow(&[(AudioChannel::Ch1, -30.0), (AudioChannel::Ch2, -24.0)])
+            .unwrap();
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::write_parameter(video::audio_follow_threshold(0), 67),
+                Command::read(video::audio_follow_threshold(0), 1).unwrap(),
+                Command::write_parameter(video::audio_follow_threshold(1), 79),
+                Command::read(video::audio_follow_threshold(1), 1).unwrap(),
+                Command::read(video::audio_follow_threshold(0), 6).unwrap(),
+                Command::write_parameter(video::AUTO_SWITCH_MODE, 2),
+            ]
+        );
+        assert_eq!(
+            auto.auto_switch_mode().unwrap(),
+            AutoSwitchMode::AudioFollow
+        );
+        assert_eq!(
+            auto.audio_follow_threshold(AudioChannel::Ch2).unwrap(),
+            Some(-24.0)
+        );
+    }
+
+    #[test]
+    fn test_audio_follow_not_enabled_on_bad_thresholds() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut auto = AutoSwitch::new(&mut client);
+
+        // CH3-6 are unset
+        auto.enable_audio_follow(&[(AudioChannel::Ch1, -30.0), (AudioChannel::Ch2, -30.0)])
+            .unwrap_err();
+        // A threshold write fails
+        mock.set_address_error(video::audio_follow_threshold(1), RolandError::Invalid);
+        assert!(matches!(
+            auto.enable_audio_follow(&[(AudioChannel::Ch2, -30.0)]),
+            Err(TelnetError::Device {
+                error: RolandError::Invalid,
+                ..
+            })
+        ));
+        assert!(!mock
+            .received()
+            .contains(&Command::write_parameter(video::AUTO_SWITCH_MODE, 2)));
+
+        // Invalid thresholds are rejected before sending
+        mock.clear_received();
+        assert!(matches!(
+            auto.enable_audio_follow(&[(AudioChannel::Ch1, -30.0), (AudioChannel::Ch1, 3.0)]),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+        assert!(matches!(
+            auto.set_audio_follow_threshold(AudioChannel::Main, -30.0),
+            Err(TelnetError::Protocol(RolandError::Invalid))
+        ));
+        assert!(mock.received().is_empty());
+    }
+
+    #[test]
+    fn test_pattern_mode() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut auto = AutoSwitch::new(&mut client);
+
+        auto.set_pattern_interval(Duration::from_millis(4600))
+            .unwrap();
+        assert_eq!(auto.pattern_interval().unwrap(), Duration::from_secs(5));
+        auto.set_auto_switch_mode(AutoSwitchMode::Pattern).unwrap();
+        assert_eq!(mock.parameter(video::AUTO_SWITCH_MODE), Some(1));
+
+        for interval in [Duration::ZERO, Duration::from_secs(121)] {
+            assert!(matches!(
+                auto.set_pattern_interval(interval),
+                Err(TelnetError::Protocol(RolandError::OutOfRange))
+            ));
+        }
+    }
+}
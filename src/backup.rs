@@ -0,0 +1,1007 @@
+//! Backup and restore of parameter ranges
+//!
+//! A [`ParameterDump`] holds the values of every address in a set of
+//! [`AddressRange`]s together with the product and version of the device
+//! it was read from. It can be saved as JSON or in a compact binary form:
+//!
+//! ```text
+//! {"product":"VR-6HD","version":"1.00","parameters":{"010000":3,"010001":1}}
+//! ```
+//!
+//! The binary form starts with `RLDP`, a format version byte, then the
+//! product and version as length-prefixed strings, followed by runs of
+//! contiguous addresses (3 address bytes, a big endian `u16` count and the
+//! values).
+//!
+//! There is no serde feature: the crate depends on nothing but
+//! roland-core and has to build offline, so the JSON is read and written
+//! by hand in [`ParameterDump::to_json`] and [`ParameterDump::from_json`].
+//!
+//! Addresses the device refuses to read, e.g. write-only ones, are listed
+//! as unreadable: in JSON as `"unreadable":["120034"]`, and in the binary
+//! form (format version 2) as a big endian `u16` count and the addresses
+//! before the runs.
+
+use crate::cancel::CancellationToken;
+use crate::{TelnetClient, TelnetError};
+use roland_core::{Address, RolandError};
+use std::fmt;
+
+/// Magic bytes of the binary dump format
+const MAGIC: &[u8; 4] = b"RLDP";
+/// Version of the binary dump format
+const FORMAT_VERSION: u8 = 1;
+/// Version of the binary dump format with unreadable addresses
+const FORMAT_VERSION_UNREADABLE: u8 = 2;
+/// Number of parameters written per batch during a restore
+const RESTORE_CHUNK: usize = 64;
+/// Most addresses read with one RQH during a dump
+const DUMP_READ_SIZE: u32 = 128;
+
+/// Range of consecutive addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AddressRange {
+    start: Address,
+    len: u32,
+}
+
+impl AddressRange {
+    /// Create a range from `start` to `end`, both included
+    ///
+    /// The range is empty if `end` comes before `start`.
+    pub fn new(start: Address, end: Address) -> Self {
+        let len = (u32::from(end) + 1).saturating_sub(u32::from(start));
+        Self { start, len }
+    }
+
+    /// Create a range of `len` addresses starting at `start`
+    ///
+    /// The range is cut off at `FFFFFF`.
+    pub fn with_len(start: Address, len: u32) -> Self {
+        let len = len.min(0x100_0000 - u32::from(start));
+        Self { start, len }
+    }
+
+    /// Get the first address
+    pub fn start(&self) -> Address {
+        self.start
+    }
+
+    /// Get the number of addresses in the range
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Check if the range contains no address
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over the addresses in the range
+    pub fn iter(&self) -> impl Iterator<Item = Address> {
+        let start = u32::from(self.start);
+        (start..start + self.len).map(from_u24)
+    }
+
+    /// Split the range into runs of at most [`DUMP_READ_SIZE`] addresses
+    fn runs(&self) -> impl Iterator<Item = AddressRange> {
+        let (start, len) = (u32::from(self.start), self.len);
+        (0..len).step_by(DUMP_READ_SIZE as usize).map(move |i| {
+            AddressRange::with_len(from_u24(start + i), (len - i).min(DUMP_READ_SIZE))
+        })
+    }
+}
+
+/// Parameter values read from a device
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ParameterDump {
+    /// Product name reported by the device
+    pub product: String,
+    /// Firmware version reported by the device
+    pub version: String,
+    /// Addresses and values, in the order they were read
+    pub parameters: Vec<(Address, u8)>,
+    /// Addresses the device answered with `Invalid`, e.g. write-only ones
+    pub unreadable: Vec<Address>,
+}
+
+impl ParameterDump {
+    /// Get the value stored for an address
+    pub fn get(&self, address: Address) -> Option<u8> {
+        self.parameters
+            .iter()
+            .find(|(a, _)| *a == address)
+            .map(|&(_, value)| value)
+    }
+
+    /// Encode the dump as JSON
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"product\":");
+        write_json_string(&mut json, &self.product);
+        json.push_str(",\"version\":");
+        write_json_string(&mut json, &self.version);
+        json.push_str(",\"parameters\":{");
+        for (i, (address, value)) in self.parameters.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push('"');
+            json.push_str(&address.to_hex());
+            json.push_str("\":");
+            json.push_str(&value.to_string());
+        }
+        json.push('}');
+        if !self.unreadable.is_empty() {
+            json.push_str(",\"unreadable\":[");
+            for (i, address) in self.unreadable.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_json_string(&mut json, &address.to_hex());
+            }
+            json.push(']');
+        }
+        json.push('}');
+        json
+    }
+
+    /// Decode a dump from JSON
+    ///
+    /// Unknown fields are ignored, and `unreadable` is optional.
+    pub fn from_json(json: &str) -> Result<Self, DumpFormatError> {
+        let JsonValue::Object(fields) = parse_json(json)? else {
+            return Err(DumpFormatError::Syntax(0));
+        };
+        let field = |name: &'static str| {
+            fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value)
+                .ok_or(DumpFormatError::MissingField(name))
+        };
+        let string = |name: &'static str| match field(name)? {
+            JsonValue::String(s) => Ok(s.clone()),
+            _ => Err(DumpFormatError::MissingField(name)),
+        };
+
+        let JsonValue::Object(entries) = field("parameters")? else {
+            return Err(DumpFormatError::MissingField("parameters"));
+        };
+        let mut parameters = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let address =
+                Address::from_hex(key).map_err(|_| DumpFormatError::InvalidAddress(key.clone()))?;
+            let value = match value {
+                JsonValue::Number(n) if *n <= 255 => *n as u8,
+                _ => return Err(DumpFormatError::InvalidValue(address)),
+            };
+            parameters.push((address, value));
+        }
+        let mut unreadable = Vec::new();
+        match field("unreadable") {
+            Ok(JsonValue::Array(items)) => {
+                for item in items {
+                    let JsonValue::String(key) = item else {
+                        return Err(DumpFormatError::MissingField("unreadable"));
+                    };
+                    unreadable.push(
+                        Address::from_hex(key)
+                            .map_err(|_| DumpFormatError::InvalidAddress(key.clone()))?,
+                    );
+                }
+            }
+            Ok(_) => return Err(DumpFormatError::MissingField("unreadable")),
+            Err(_) => {}
+        }
+
+        Ok(Self {
+            product: string("product")?,
+            version: string("version")?,
+            parameters,
+            unreadable,
+        })
+    }
+
+    /// Encode the dump in the binary form
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        if self.unreadable.is_empty() {
+            bytes.push(FORMAT_VERSION);
+        } else {
+            bytes.push(FORMAT_VERSION_UNREADABLE);
+        }
+        write_short_string(&mut bytes, &self.product);
+        write_short_string(&mut bytes, &self.version);
+        if !self.unreadable.is_empty() {
+            let count = self.unreadable.len().min(u16::MAX as usize);
+            bytes.extend_from_slice(&(count as u16).to_be_bytes());
+            for address in &self.unreadable[..count] {
+                bytes.extend_from_slice(&[address.high, address.mid, address.low]);
+            }
+        }
+
+        let mut rest = &self.parameters[..];
+        while let Some(&(start, _)) = rest.first() {
+            // Longest run of consecutive addresses, up to u16::MAX values
+            let len = rest
+                .iter()
+                .enumerate()
+                .take(u16::MAX as usize)
+                .take_while(|(i, (address, _))| u32::from(*address) == u32::from(start) + *i as u32)
+                .count();
+            bytes.extend_from_slice(&[start.high, start.mid, start.low]);
+            bytes.extend_from_slice(&(len as u16).to_be_bytes());
+            bytes.extend(rest[..len].iter().map(|&(_, value)| value));
+            rest = &rest[len..];
+        }
+        bytes
+    }
+
+    /// Decode a dump from the binary form
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DumpFormatError> {
+        let mut reader = ByteReader { bytes, pos: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(DumpFormatError::Syntax(0));
+        }
+        let format = reader.take(1)?[0];
+        if format != FORMAT_VERSION && format != FORMAT_VERSION_UNREADABLE {
+            return Err(DumpFormatError::Syntax(MAGIC.len()));
+        }
+        let product = reader.short_string()?;
+        let version = reader.short_string()?;
+        let mut unreadable = Vec::new();
+        if format == FORMAT_VERSION_UNREADABLE {
+            let count = reader.take(2)?;
+            for _ in 0..u16::from_be_bytes([count[0], count[1]]) {
+                let address = reader.take(3)?;
+                unreadable.push(Address::new(address[0], address[1], address[2]));
+            }
+        }
+
+        let mut parameters = Vec::new();
+        while reader.pos < bytes.len() {
+            let start = reader.take(3)?;
+            let start = u32::from(Address::new(start[0], start[1], start[2]));
+            let len = reader.take(2)?;
+            let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+            let values = reader.take(len)?;
+            if start as usize + len > 0x100_0000 {
+                return Err(DumpFormatError::Syntax(reader.pos));
+            }
+            parameters.extend(
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &value)| (from_u24(start + i as u32), value)),
+            );
+        }
+
+        Ok(Self {
+            product,
+            version,
+            parameters,
+            unreadable,
+        })
+    }
+}
+
+/// Outcome of [`TelnetClient::restore_parameters`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestoreReport {
+    /// Number of parameters the device accepted
+    pub written: usize,
+    /// Parameters the device rejected, with the error it reported
+    pub failed: Vec<(Address, RolandError)>,
+    /// Product and version of the device restored to, if they differ
+    /// from the ones in the dump
+    pub mismatch: Option<(String, String)>,
+}
+
+impl RestoreReport {
+    /// Check if every parameter was written
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Error decoding a saved [`ParameterDump`], [`Macro`](crate::recorder::Macro) or
+/// [`Capture`](crate::capture::Capture)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DumpFormatError {
+    /// Malformed input at the given byte offset
+    Syntax(usize),
+    /// Input ended early
+    Truncated,
+    /// Required field missing or of the wrong type
+    MissingField(&'static str),
+    /// Key that isn't 6 hex digits
+    InvalidAddress(String),
+    /// Value that isn't 0-255
+    InvalidValue(Address),
+    /// Command that doesn't parse
+    InvalidCommand(String),
+}
+
+impl fmt::Display for DumpFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DumpFormatError::Syntax(pos) => write!(f, "Malformed dump at byte {}", pos),
+            DumpFormatError::Truncated => write!(f, "Dump ended early"),
+            DumpFormatError::MissingField(name) => write!(f, "Missing dump field: {}", name),
+            DumpFormatError::InvalidAddress(key) => {
+                write!(f, "{}: {:?}", RolandError::InvalidAddress, key)
+            }
+            DumpFormatError::InvalidValue(address) => {
+                write!(f, "{} at {}", RolandError::InvalidValue, address.to_hex())
+            }
+            DumpFormatError::InvalidCommand(command) => {
+                write!(f, "{}: {:?}", RolandError::SyntaxError, command)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DumpFormatError {}
+
+impl TelnetClient {
+    /// Read every address in `ranges`
+    ///
+    /// See [`TelnetClient::dump_parameters_with_progress`].
+    pub fn dump_parameters(
+        &mut self,
+        ranges: &[AddressRange],
+    ) -> Result<ParameterDump, TelnetError> {
+        self.dump_parameters_with_progress(ranges, |_, _| {})
+    }
+
+    /// Read every address in `ranges`, reporting progress
+    ///
+    /// Consecutive addresses are read together, up to 128 with one RQH.
+    /// If the device refuses such a read, its addresses are read one by
+    /// one: addresses the device answers with `Invalid`, e.g. write-only
+    /// ones, don't stop the dump; they are listed in
+    /// [`ParameterDump::unreadable`].
+    ///
+    /// # Arguments
+    /// * `ranges` - Addresses to read
+    /// * `progress` - Called with the number of addresses read so far and
+    ///   the total after each read
+    ///
+    /// # Returns
+    /// * `Result<ParameterDump, TelnetError>` - Dump, or the first error
+    pub fn dump_parameters_with_progress(
+        &mut self,
+        ranges: &[AddressRange],
+        progress: impl FnMut(usize, usize),
+    ) -> Result<ParameterDump, TelnetError> {
+        self.dump_parameters_cancellable(ranges, progress, &CancellationToken::new())
+    }
+
+    /// Read every address in `ranges` until `cancel` is cancelled
+    ///
+    /// Works like [`TelnetClient::dump_parameters_with_progress`]; the
+    /// token is checked before each RQH.
+    ///
+    /// # Returns
+    /// * `Result<ParameterDump, TelnetError>` - Dump, `Cancelled`, or the
+    ///   first error
+    pub fn dump_parameters_cancellable(
+        &mut self,
+        ranges: &[AddressRange],
+        mut progress: impl FnMut(usize, usize),
+        cancel: &CancellationToken,
+    ) -> Result<ParameterDump, TelnetError> {
+        cancel.check()?;
+        let (product, version) = self.get_version()?;
+        let total = ranges.iter().map(|range| range.len() as usize).sum();
+        let mut parameters = Vec::with_capacity(total);
+        let mut unreadable = Vec::new();
+        for run in ranges.iter().flat_map(AddressRange::runs) {
+            cancel.check()?;
+            match self.read_parameter_bytes(run.start, run.len) {
+                Ok(values) => parameters.extend(run.iter().zip(values)),
+                // Find the addresses refusing the read
+                Err(TelnetError::Device { .. }) => {
+                    for address in run.iter() {
+                        cancel.check()?;
+                        match self.read_parameter_addr(address, 1) {
+                            Ok(value) => parameters.push((address, value)),
+                            Err(TelnetError::Device {
+                                error: RolandError::Invalid,
+                                ..
+                            }) => unreadable.push(address),
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+            progress(parameters.len() + unreadable.len(), total);
+        }
+        Ok(ParameterDump {
+            product,
+            version,
+            parameters,
+            unreadable,
+        })
+    }
+
+    /// Write a dump back to the device
+    ///
+    /// See [`TelnetClient::restore_parameters_with_progress`].
+    pub fn restore_parameters(
+        &mut self,
+        dump: &ParameterDump,
+    ) -> Result<RestoreReport, TelnetError> {
+        self.restore_parameters_with_progress(dump, |_, _| {})
+    }
+
+    /// Write a dump back to the device, reporting progress
+    ///
+    /// Parameters are written in batches (see
+    /// [`TelnetClient::write_parameters`]). A parameter the device rejects
+    /// doesn't stop the restore; it is listed in
+    /// [`RestoreReport::failed`]. The restore also proceeds if the device
+    /// reports a different product or version than the dump, which is
+    /// flagged in [`RestoreReport::mismatch`].
+    ///
+    /// # Arguments
+    /// * `dump` - Parameters to write
+    /// * `progress` - Called with the number of parameters written so far
+    ///   and the total after each batch
+    ///
+    /// # Returns
+    /// * `Result<RestoreReport, TelnetError>` - Report, or an error if the
+    ///   connection failed
+    pub fn restore_parameters_with_progress(
+        &mut self,
+        dump: &ParameterDump,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<RestoreReport, TelnetError> {
+        self.restore_parameters_cancellable(dump, progress, &CancellationToken::new())
+    }
+
+    /// Write a dump back to the device until `cancel` is cancelled
+    ///
+    /// Works like [`TelnetClient::restore_parameters_with_progress`]; the
+    /// token is checked before each batch, so the parameters written
+    /// before cancelling stay written.
+    ///
+    /// # Returns
+    /// * `Result<RestoreReport, TelnetError>` - Report, `Cancelled`, or an
+    ///   error if the connection failed
+    pub fn restore_parameters_cancellable(
+        &mut self,
+        dump: &ParameterDump,
+        mut progress: impl FnMut(usize, usize),
+        cancel: &CancellationToken,
+    ) -> Result<RestoreReport, TelnetError> {
+        cancel.check()?;
+        let (product, version) = self.get_version()?;
+        let mismatch =
+            (product != dump.product || version != dump.version).then_some((product, version));
+
+        let total = dump.parameters.len();
+        let mut report = RestoreReport {
+            written: 0,
+            failed: Vec::new(),
+            mismatch,
+        };
+        let mut done = 0;
+        for chunk in dump.parameters.chunks(RESTORE_CHUNK) {
+            cancel.check()?;
+            let results = self.write_parameters(chunk)?;
+            for (&(address, _), result) in chunk.iter().zip(results) {
+                match result {
+                    Ok(()) => report.written += 1,
+                    Err(e) => report.failed.push((address, e)),
+                }
+            }
+            done += chunk.len();
+            progress(done, total);
+        }
+        Ok(report)
+    }
+}
+
+fn from_u24(value: u32) -> Address {
+    Address::new((value >> 16) as u8, (value >> 8) as u8, value as u8)
+}
+
+pub(crate) fn write_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// Write a string with a length byte, cutting it at 255 bytes
+fn write_short_string(bytes: &mut Vec<u8>, s: &str) {
+    let mut len = s.len().min(u8::MAX as usize);
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    bytes.push(len as u8);
+    bytes.extend_from_slice(&s.as_bytes()[..len]);
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DumpFormatError> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or(DumpFormatError::Truncated)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn short_string(&mut self) -> Result<String, DumpFormatError> {
+        let pos = self.pos;
+        let len = self.take(1)?[0] as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DumpFormatError::Syntax(pos))
+    }
+}
+
+/// JSON value, as far as dumps and macros need it
+pub(crate) enum JsonValue {
+    String(String),
+    /// Non-negative integer
+    Number(u64),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+    /// Any other value, which dumps don't use
+    Other,
+}
+
+impl JsonValue {
+    /// Get a field of an object
+    pub(crate) fn field(&self, name: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(fields) => fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+/// Deepest nesting of objects and arrays [`parse_json`] accepts
+const MAX_JSON_DEPTH: usize = 64;
+
+/// Parse a complete JSON document
+pub(crate) fn parse_json(json: &str) -> Result<JsonValue, DumpFormatError> {
+    let mut parser = JsonParser {
+        input: json.as_bytes(),
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos != parser.input.len() {
+        return Err(DumpFormatError::Syntax(parser.pos));
+    }
+    Ok(value)
+}
+
+struct JsonParser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    /// Objects and arrays open around the current position
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), DumpFormatError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b) if b == byte => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(_) => Err(DumpFormatError::Syntax(self.pos)),
+            None => Err(DumpFormatError::Truncated),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, DumpFormatError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(DumpFormatError::Truncated),
+            Some(open @ (b'{' | b'[')) => {
+                // Nesting is recursion; untrusted input mustn't overflow
+                // the stack
+                if self.depth == MAX_JSON_DEPTH {
+                    return Err(DumpFormatError::Syntax(self.pos));
+                }
+                self.depth += 1;
+                let value = if open == b'{' {
+                    self.parse_object()
+                } else {
+                    self.parse_array()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some(b'"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some(b'0'..=b'9') => self.parse_number(),
+            Some(_) => {
+                for literal in ["true", "false", "null"] {
+                    if self.input[self.pos..].starts_with(literal.as_bytes()) {
+                        self.pos += literal.len();
+                        return Ok(JsonValue::Other);
+                    }
+                }
+                // Negative and fractional numbers aren't used by dumps
+                if self.peek() == Some(b'-') {
+                    self.pos += 1;
+                    self.parse_number()?;
+                    return Ok(JsonValue::Other);
+                }
+                Err(DumpFormatError::Syntax(self.pos))
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, DumpFormatError> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(DumpFormatError::Syntax(self.pos));
+            }
+            let key = self.parse_string()?;
+            self.expect(b':')?;
+            fields.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(fields));
+                }
+                Some(_) => return Err(DumpFormatError::Syntax(self.pos)),
+                None => return Err(DumpFormatError::Truncated),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, DumpFormatError> {
+        self.expect(b'[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(values));
+                }
+                Some(_) => return Err(DumpFormatError::Syntax(self.pos)),
+                None => return Err(DumpFormatError::Truncated),
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, DumpFormatError> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        if matches!(self.peek(), Some(b'.' | b'e' | b'E')) {
+            // Skip the fraction and exponent; dumps only use integers
+            while matches!(
+                self.peek(),
+                Some(b'0'..=b'9' | b'.' | b'e' | b'E' | b'+' | b'-')
+            ) {
+                self.pos += 1;
+            }
+            return Ok(JsonValue::Other);
+        }
+        std::str::from_utf8(&self.input[start..self.pos])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .map(JsonValue::Number)
+            .ok_or(DumpFormatError::Syntax(start))
+    }
+
+    fn parse_string(&mut self) -> Result<String, DumpFormatError> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(byte) = self.peek() else {
+                return Err(DumpFormatError::Truncated);
+            };
+            self.pos += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(escape) = self.peek() else {
+                        return Err(DumpFormatError::Truncated);
+                    };
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.parse_unicode_escape()?,
+                        _ => return Err(DumpFormatError::Syntax(self.pos - 1)),
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                byte => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| DumpFormatError::Syntax(self.pos))
+    }
+
+    /// Parse the 4 hex digits after `\u`, combining surrogate pairs
+    fn parse_unicode_escape(&mut self) -> Result<char, DumpFormatError> {
+        let high = self.parse_hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.input[self.pos..].starts_with(b"\\u") {
+                return Err(DumpFormatError::Syntax(self.pos));
+            }
+            self.pos += 2;
+            let low = self.parse_hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(DumpFormatError::Syntax(self.pos));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or(DumpFormatError::Syntax(self.pos))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, DumpFormatError> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .ok_or(DumpFormatError::Truncated)?;
+        let value = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or(DumpFormatError::Syntax(self.pos))?;
+        self.pos += 4;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::params::{pinp, video};
+    use roland_core::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    fn sample() -> ParameterDump {
+        ParameterDump {
+            product: "VR-6HD \"test\"".to_string(),
+            version: "1.00".to_string(),
+            parameters: vec![
+                (Address::new(0x01, 0x00, 0xFF), 1),
+                (Address::new(0x01, 0x01, 0x00), 2),
+                (Address::new(0x02, 0x00, 0x00), 255),
+            ],
+            unreadable: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_address_range() {
+        let range = AddressRange::new(
+            Address::new(0x01, 0x00, 0xFE),
+            Address::new(0x01, 0x01, 0x01),
+        );
+        assert_eq!(range.len(), 4);
+        assert_eq!(
+            range.iter().map(|a| a.to_hex()).collect::<Vec<_>>(),
+            vec!["0100FE", "0100FF", "010100", "010101"]
+        );
+        assert!(AddressRange::new(video::PST_SELECT, video::PGM_SELECT).is_empty());
+        assert!(AddressRange::with_len(video::PGM_SELECT, 0).is_empty());
+        assert!(AddressRange::with_len(Address::new(0, 0, 0), 0).is_empty());
+        assert_eq!(
+            AddressRange::with_len(Address::new(0xFF, 0xFF, 0xFE), 8).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_json_depth_limit() {
+        let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse_json(&nested(MAX_JSON_DEPTH)).is_ok());
+        assert!(matches!(
+            parse_json(&nested(MAX_JSON_DEPTH + 1)),
+            Err(DumpFormatError::Syntax(MAX_JSON_DEPTH))
+        ));
+        // Far too deep to recurse into, e.g. a whole HTTP body of `[`
+        assert!(matches!(
+            parse_json(&"[{\"a\":".repeat(40_000)),
+            Err(DumpFormatError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let dump = sample();
+        let json = dump.to_json();
+        assert!(json.contains("\"0100FF\":1"));
+        assert_eq!(ParameterDump::from_json(&json).unwrap(), dump);
+
+        // Two runs: 0100FF-010100 and 020000
+        let bytes = dump.to_bytes();
+        assert_eq!(bytes.len(), 5 + 1 + 13 + 1 + 4 + (5 + 2) + (5 + 1));
+        assert_eq!(ParameterDump::from_bytes(&bytes).unwrap(), dump);
+
+        let pretty = "{ \"version\": \"2.0\", \"extra\": [1, -2.5, null],\n  \
+                      \"product\": \"V\\u0052\", \"parameters\": { \"010000\": 3 } }";
+        let parsed = ParameterDump::from_json(pretty).unwrap();
+        assert_eq!(parsed.product, "VR");
+        assert_eq!(parsed.get(video::PGM_SELECT), Some(3));
+
+        assert_eq!(
+            ParameterDump::from_json("{\"product\":\"x\",\"version\":\"y\"}"),
+            Err(DumpFormatError::MissingField("parameters"))
+        );
+        assert!(matches!(
+            ParameterDump::from_json(&json.replace(":255", ":256")),
+            Err(DumpFormatError::InvalidValue(_))
+        ));
+        assert_eq!(
+            ParameterDump::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DumpFormatError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_dump_and_restore() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_version("VR-6HD", "1.00");
+        mock.set_parameter(video::PGM_SELECT, 2);
+        mock.set_parameter(pinp::SOURCE, 3);
+        let mut client = connect(addr);
+
+        let ranges = [
+            AddressRange::new(video::PGM_SELECT, video::PST_SELECT),
+            AddressRange::with_len(pinp::ENABLE, 2),
+        ];
+        let mut calls = Vec::new();
+        let dump = client
+            .dump_parameters_with_progress(&ranges, |done, total| calls.push((done, total)))
+            .unwrap();
+        // One RQH per range
+        assert_eq!(calls, vec![(2, 4), (4, 4)]);
+        assert_eq!(
+            mock.received()[1..],
+            [
+                Command::read(video::PGM_SELECT, 2).unwrap(),
+                Command::read(pinp::ENABLE, 2).unwrap(),
+            ]
+        );
+        assert_eq!(dump.product, "VR-6HD");
+        assert_eq!(dump.get(video::PGM_SELECT), Some(2));
+        assert_eq!(dump.get(pinp::SOURCE), Some(3));
+
+        // Restore onto a device with newer firmware that rejects one value
+        mock.set_parameter(video::PGM_SELECT, 0);
+        mock.set_version("VR-6HD", "2.00");
+        mock.set_address_error(pinp::SOURCE, RolandError::OutOfRange);
+        let report = client.restore_parameters(&dump).unwrap();
+        assert_eq!(mock.parameter(video::PGM_SELECT), Some(2));
+        assert_eq!(report.written, 3);
+        assert_eq!(report.failed, vec![(pinp::SOURCE, RolandError::OutOfRange)]);
+        assert!(!report.is_complete());
+        assert_eq!(
+            report.mismatch,
+            Some(("VR-6HD".to_string(), "2.00".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_dump_cancelled() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_delay(Duration::from_millis(5));
+        let mut client = connect(addr);
+
+        let total = 60 * DUMP_READ_SIZE as usize;
+        let range = AddressRange::with_len(Address::new(0x12, 0x00, 0x00), total as u32);
+        let cancel = CancellationToken::new();
+        let done = thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(100));
+                cancel.cancel();
+            });
+            let mut done = 0;
+            let result = client.dump_parameters_cancellable(&[range], |n, _| done = n, &cancel);
+            assert!(matches!(result, Err(TelnetError::Cancelled)));
+            done
+        });
+        assert!(done > 0 && done < total);
+        // One read per run read, plus the version
+        assert_eq!(done % DUMP_READ_SIZE as usize, 0);
+        assert_eq!(mock.received().len(), done / DUMP_READ_SIZE as usize + 1);
+
+        // The connection is still in step
+        mock.set_version("VR-6HD", "1.00");
+        assert_eq!(client.get_version().unwrap().0, "VR-6HD");
+        assert!(matches!(
+            client.restore_parameters_cancellable(&sample(), |_, _| {}, &cancel),
+            Err(TelnetError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn test_dump_skips_unreadable() {
+        let (addr, mock) = MockDevice::spawn();
+        let write_only = Address::new(0x12, 0x00, 0x34);
+        mock.set_address_error(write_only, RolandError::Invalid);
+        let mut client = connect(addr);
+
+        let range = AddressRange::with_len(Address::new(0x12, 0x00, 0x33), 3);
+        let dump = client.dump_parameters(&[range]).unwrap();
+        assert_eq!(dump.parameters.len(), 2);
+        assert_eq!(dump.unreadable, vec![write_only]);
+        // The refused run is read again address by address
+        assert_eq!(mock.received().len(), 1 + 1 + 3);
+        assert_eq!(dump.get(write_only), None);
+
+        let json = dump.to_json();
+        assert!(json.ends_with(",\"unreadable\":[\"120034\"]}"));
+        assert_eq!(ParameterDump::from_json(&json).unwrap(), dump);
+        let bytes = dump.to_bytes();
+        assert_eq!(bytes[4], FORMAT_VERSION_UNREADABLE);
+        assert_eq!(ParameterDump::from_bytes(&bytes).unwrap(), dump);
+
+        // Other errors still abort the dump
+        mock.set_address_error(write_only, RolandError::OutOfRange);
+        let error = client.dump_parameters(&[range]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Device rejected RQH:120034,000001: Parameter out of range"
+        );
+    }
+}
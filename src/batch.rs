@@ -0,0 +1,242 @@
+//! Pipelined batch writes
+
+use crate::{TelnetClient, TelnetError};
+use roland_core::engine::answers_with;
+use roland_core::{Address, Command, Response, RolandError};
+use std::time::Instant;
+
+impl TelnetClient {
+    /// Write several parameters without waiting for each ACK
+    ///
+    /// DTH commands are sent back-to-back, with at most
+    /// [`TelnetClient::max_in_flight`] of them unacknowledged at a time, and
+    /// the ACK/ERR responses are matched to the commands in order. Sending
+    /// pauses while the device has signalled XOFF.
+    ///
+    /// A device error for one parameter doesn't abort the others. Each
+    /// answer must arrive within the client's timeout for its command,
+    /// counted from when it became the oldest unanswered one, see
+    /// [`Timeouts::for_command`](crate::timeouts::Timeouts::for_command); otherwise
+    /// the batch fails with `Timeout`.
+    ///
+    /// # Arguments
+    /// * `params` - Addresses and values to write, in order
+    ///
+    /// # Returns
+    /// * `Result<Vec<Result<(), RolandError>>, TelnetError>` - Per-parameter
+    ///   results, or an error if the connection failed
+    pub fn write_parameters(
+        &mut self,
+        params: &[(Address, u8)],
+    ) -> Result<Vec<Result<(), RolandError>>, TelnetError> {
+        let commands: Vec<Command> = params
+            .iter()
+            .map(|&(address, value)| Command::write_parameter(address, value))
+            .collect();
+        self.write_pipelined(&commands)
+    }
+
+    /// Send write commands back-to-back, see [`TelnetClient::write_parameters`]
+    pub(crate) fn write_pipelined(
+        &mut self,
+        commands: &[Command],
+    ) -> Result<Vec<Result<(), RolandError>>, TelnetError> {
+        self.flush_writes()?;
+        let mut results = Vec::with_capacity(commands.len());
+        let mut sent = 0;
+        // Oldest outstanding write and when its answer is due
+        let mut waiting: Option<(usize, Instant)> = None;
+
+        loop {
+            while sent < commands.len() && sent - results.len() < self.max_in_flight && !self.paused
+            {
+                self.write_command(&commands[sent])?;
+                sent += 1;
+            }
+            if results.len() == commands.len() {
+                return Ok(results);
+            }
+
+            let oldest = results.len();
+            let deadline = match waiting {
+                Some((index, deadline)) if index == oldest => deadline,
+                _ => {
+                    // Nothing outstanding means sending is paused by XOFF
+                    let timeout = match commands.get(oldest).filter(|_| oldest < sent) {
+                        Some(command) => self.timeouts.for_command(command, &self.profile),
+                        None => self.timeouts.other,
+                    };
+                    let deadline = Instant::now() + timeout;
+                    waiting = Some((oldest, deadline));
+                    deadline
+                }
+            };
+            let frame = self
+                .read_frame_before(deadline)?
+                .ok_or(TelnetError::Timeout)?;
+            self.handle_batch_frame(&frame, commands, &mut results);
+
+            // Frames that arrived together, e.g. an ACK followed by XOFF,
+            // must be handled before sending more
+            while let Some(frame) = self.buffered_frame() {
+                self.handle_batch_frame(&frame?, commands, &mut results);
+            }
+        }
+    }
+
+    /// Set the maximum number of unacknowledged commands in a batch
+    ///
+    /// Some firmware versions drop commands past about 8 unacknowledged
+    /// ones. Values below 1 are treated as 1.
+    pub fn set_max_in_flight(&mut self, max_in_flight: usize) {
+        self.max_in_flight = max_in_flight.max(1);
+    }
+
+    /// Get the maximum number of unacknowledged commands in a batch
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    /// Match a frame received during a batch to the oldest outstanding write
+    ///
+    /// A DTH answering the write as
+    /// [`ParseOptions::write_answer`](crate::ParseOptions::write_answer)
+    /// allows counts as success, or as `InvalidValue` if it holds other
+    /// values with [`WriteAnswer::DthSameValue`](crate::WriteAnswer::DthSameValue).
+    fn handle_batch_frame(
+        &mut self,
+        frame: &str,
+        commands: &[Command],
+        results: &mut Vec<Result<(), RolandError>>,
+    ) {
+        if self.update_flow_control(frame) {
+            return;
+        }
+        let response = self.parse(frame);
+        if response
+            .as_ref()
+            .is_ok_and(|response| self.settle_write(response))
+        {
+            return;
+        }
+        if let (Ok(response), Some(command)) = (&response, commands.get(results.len())) {
+            if response.as_data().is_some() && answers_with(command, response, self.parse_options) {
+                let result = self
+                    .write_answered(command, response.clone())
+                    .map_err(|_| RolandError::InvalidValue);
+                results.push(result);
+                return;
+            }
+        }
+        match response {
+            Ok(Response::Acknowledge) => results.push(Ok(())),
+            Ok(Response::Error(e)) => results.push(Err(e)),
+            Ok(Response::Data { address, value }) => self.push_event(address, value),
+            Ok(Response::DataBlock { address, data }) => self.push_events(address, &data),
+            Ok(_) => results.push(Err(RolandError::InvalidResponse)),
+            Err(e) => results.push(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::timeouts::Timeouts;
+    use std::time::Duration;
+
+    fn params(count: u8) -> Vec<(Address, u8)> {
+        (0..count)
+            .map(|i| (Address::new(0x05, i, 0x00), i))
+            .collect()
+    }
+
+    #[test]
+    fn test_write_parameters() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let params = params(20);
+        let results = client.write_parameters(&params).unwrap();
+        assert_eq!(results, vec![Ok(()); 20]);
+
+        let expected: Vec<Command> = params
+            .iter()
+            .map(|&(address, value)| Command::WriteParameter { address, value })
+            .collect();
+        assert_eq!(mock.received(), expected);
+        assert_eq!(mock.parameter(Address::new(0x05, 19, 0x00)), Some(19));
+    }
+
+    #[test]
+    fn test_write_parameters_partial_failure() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_address_error(Address::new(0x05, 1, 0x00), RolandError::OutOfRange);
+        let mut client = connect(addr);
+
+        let results = client.write_parameters(&params(3)).unwrap();
+        assert_eq!(results, vec![Ok(()), Err(RolandError::OutOfRange), Ok(())]);
+        assert_eq!(mock.parameter(Address::new(0x05, 2, 0x00)), Some(2));
+    }
+
+    #[test]
+    fn test_write_parameters_empty() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        assert_eq!(client.write_parameters(&[]).unwrap(), vec![]);
+        assert!(mock.received().is_empty());
+    }
+
+    #[test]
+    fn test_max_in_flight() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_delay(Duration::from_millis(20));
+        let mut client = connect(addr);
+        assert_eq!(client.max_in_flight(), 8);
+
+        client.write_parameters(&params(10)).unwrap();
+        assert!(mock.max_pending() > 1);
+        assert!(mock.max_pending() <= 8);
+
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_delay(Duration::from_millis(20));
+        let mut client = connect(addr);
+        client.set_max_in_flight(2);
+
+        client.write_parameters(&params(10)).unwrap();
+        assert!(mock.max_pending() <= 2);
+    }
+
+    #[test]
+    fn test_write_parameters_respects_xoff() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.inject_xoff(Duration::from_millis(100));
+        let mut client = connect(addr);
+        client.set_max_in_flight(1);
+
+        let results = client.write_parameters(&params(5)).unwrap();
+        assert_eq!(results, vec![Ok(()); 5]);
+        assert_eq!(mock.xoff_violations(), 0);
+        assert_eq!(mock.received().len(), 5);
+    }
+
+    #[test]
+    fn test_write_parameters_timeout() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        client.set_timeouts(Timeouts {
+            write: Duration::from_millis(100),
+            ..Timeouts::default()
+        });
+        mock.set_delay(Duration::from_millis(500));
+
+        let start = Instant::now();
+        assert!(matches!(
+            client.write_parameters(&params(2)),
+            Err(TelnetError::Timeout)
+        ));
+        assert!(start.elapsed() < Duration::from_millis(400));
+    }
+}
@@ -0,0 +1,535 @@
+//! Command-line control of a VR-6HD
+//!
+//! Usage: `roland-ctl --host <host> [--port <port>] [--json] <command>`
+//!
+//! Commands:
+//! * `version` - print product and version
+//! * `read <address> [size]` - read `size` (default 1) values
+//! * `write <address> <hex byte>...` - write one value, or several
+//!   consecutive ones as a block
+//! * `scene recall <n>` - recall scene memory `n` (1-30)
+//! * `fader <channel> <dB>` - set a fader, e.g. `fader ch1 -10` or
+//!   `fader main -inf`
+//! * `dump <address> <count> [--out <file>]` - save `count` addresses from
+//!   `address` as JSON, to stdout without `--out`
+//! * `restore <file>` - write a saved dump back to the device
+//! * `apply <config> [--dry-run]` - make the device match a config file
+//!   (see [`roland_rs::config`]), or with `--dry-run` only list what
+//!   would change
+//! * `capture --out <file> <command>` - run another command and save the
+//!   raw session as a capture (see [`roland_rs::capture`]), e.g. for a bug
+//!   report
+//! * `replay <file> [--port <port>]` - play a capture back to one client
+//!   connecting to `127.0.0.1`, on a free port unless given, then report
+//!   whether the client sent the captured commands
+//!
+//! Addresses are 6 hex digits, counts and sizes decimal. `--host` may be
+//! left out when `ROLAND_HOST` is set, and isn't needed for `replay`.
+//! With `--json`, results are printed as one JSON object per command, and
+//! errors as `{"error": "..."}` on stderr.
+//!
+//! Exit codes, so scripts can react:
+//! * 0 - success
+//! * 2 - invalid arguments
+//! * 3 - connection error: the device can't be reached or stopped answering
+//! * 4 - device error: the device rejected a command or answered wrongly,
+//!   a restore or apply wasn't complete, or a replayed client sent other
+//!   commands
+//! * 5 - file error: a dump or config can't be read, written or parsed
+
+use roland_rs::audio::{AudioChannel, AudioMixer, Db};
+use roland_rs::backup::{AddressRange, ParameterDump};
+use roland_rs::capture::{Capture, CaptureRecorder, ReplayDevice};
+use roland_rs::config::{ApplyReport, DeviceConfig};
+use roland_rs::{Address, TelnetClient, TelnetError};
+use std::fmt::Write as _;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: roland-ctl --host <host> [--port <port>] [--json] <command>
+
+commands:
+  version                              print product and version
+  read <address> [size]                read values, e.g. `read 010000 2`
+  write <address> <hex byte>...        write values, e.g. `write 010000 01`
+  scene recall <n>                     recall scene memory n (1-30)
+  fader <channel> <dB>                 set a fader, e.g. `fader ch1 -10`
+  dump <address> <count> [--out file]  save parameters as JSON
+  restore <file>                       write saved parameters back
+  apply <config> [--dry-run]           make the device match a config
+  capture --out <file> <command>       run a command and save the session
+  replay <file> [--port <port>]        play a saved session to a client";
+
+/// Default Telnet port
+const DEFAULT_PORT: u16 = 23;
+
+/// Why a command failed, deciding the exit code
+#[derive(Debug)]
+enum Failure {
+    Usage(String),
+    Connection(TelnetError),
+    Device(String),
+    File(String),
+}
+
+impl Failure {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Failure::Usage(_) => 2,
+            Failure::Connection(_) => 3,
+            Failure::Device(_) => 4,
+            Failure::File(_) => 5,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Failure::Usage(message) | Failure::Device(message) | Failure::File(message) => {
+                message.clone()
+            }
+            Failure::Connection(e) => e.to_string(),
+        }
+    }
+}
+
+impl From<TelnetError> for Failure {
+    fn from(e: TelnetError) -> Self {
+        match e {
+            TelnetError::Io(_)
+            | TelnetError::ConnectionClosed
+            | TelnetError::Timeout
+            | TelnetError::HostUnreachable(_)
+            | TelnetError::ConnectionRefused(_)
+            | TelnetError::AuthFailed => Failure::Connection(e),
+            e => Failure::Device(e.to_string()),
+        }
+    }
+}
+
+/// Parsed command line
+#[derive(Debug, PartialEq)]
+struct Args {
+    host: Option<String>,
+    port: Option<u16>,
+    json: bool,
+    out: Option<String>,
+    command: Vec<String>,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, Failure> {
+    let mut host = std::env::var("ROLAND_HOST").ok();
+    let mut port = None;
+    let mut json = false;
+    let mut out = None;
+    let mut command = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| Failure::Usage(format!("{} needs a value", name)))
+        };
+        match arg.as_str() {
+            "--host" => host = Some(value("--host")?),
+            "--port" => {
+                port = Some(
+                    value("--port")?
+                        .parse()
+                        .map_err(|_| Failure::Usage("invalid port".to_string()))?,
+                )
+            }
+            "--out" => out = Some(value("--out")?),
+            "--json" => json = true,
+            "-h" | "--help" => return Err(Failure::Usage(USAGE.to_string())),
+            _ => command.push(arg),
+        }
+    }
+
+    Ok(Args {
+        host,
+        port,
+        json,
+        out,
+        command,
+    })
+}
+
+fn address(arg: &str) -> Result<Address, Failure> {
+    Address::from_hex(arg).map_err(|_| Failure::Usage(format!("invalid address: {}", arg)))
+}
+
+fn number<T: std::str::FromStr>(arg: &str) -> Result<T, Failure> {
+    arg.parse()
+        .map_err(|_| Failure::Usage(format!("invalid number: {}", arg)))
+}
+
+fn hex_byte(arg: &str) -> Result<u8, Failure> {
+    u8::from_str_radix(arg, 16).map_err(|_| Failure::Usage(format!("invalid hex byte: {}", arg)))
+}
+
+/// Encode a string as a JSON string literal
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Output of a command, as text and as JSON
+struct Output {
+    text: String,
+    json: String,
+}
+
+fn run(args: &Args) -> Result<Output, Failure> {
+    let command: Vec<_> = args.command.iter().map(String::as_str).collect();
+    match command.as_slice() {
+        ["replay", file] => replay(file, args.port),
+        ["capture", command @ ..] => {
+            let out = args
+                .out
+                .as_deref()
+                .ok_or_else(|| Failure::Usage("capture needs --out".to_string()))?;
+            check(command)?;
+            let mut client = connect(args)?;
+            let recorder = CaptureRecorder::start(&mut client);
+            let result = execute(&mut client, command, None);
+            // Saved even if the command failed, that's what it's for
+            let capture = recorder.stop(&mut client);
+            capture
+                .save(out)
+                .map_err(|e| Failure::File(format!("{}: {}", out, e)))?;
+            let output = result?;
+            let chunks = capture.records.len();
+            Ok(Output {
+                text: format!("{}\nCaptured {} chunks to {}", output.text, chunks, out),
+                json: format!(
+                    "{{\"result\":{},\"capture\":{{\"file\":{},\"chunks\":{}}}}}",
+                    output.json,
+                    json_string(out),
+                    chunks
+                ),
+            })
+        }
+        command => {
+            check(command)?;
+            let mut client = connect(args)?;
+            execute(&mut client, command, args.out.as_deref())
+        }
+    }
+}
+
+/// Check the shape of a command before connecting
+fn check(command: &[&str]) -> Result<(), Failure> {
+    match command {
+        ["version"]
+        | ["read", _]
+        | ["read", _, _]
+        | ["scene", "recall", _]
+        | ["fader", _, _]
+        | ["dump", _, _]
+        | ["restore", _]
+        | ["apply", _]
+        | ["apply", _, "--dry-run"] => Ok(()),
+        ["write", _, values @ ..] if !values.is_empty() => Ok(()),
+        _ => Err(Failure::Usage(USAGE.to_string())),
+    }
+}
+
+fn connect(args: &Args) -> Result<TelnetClient, Failure> {
+    let host = args
+        .host
+        .as_deref()
+        .ok_or_else(|| Failure::Usage("--host is required".to_string()))?;
+    TelnetClient::connect(host, args.port.unwrap_or(DEFAULT_PORT)).map_err(Failure::from)
+}
+
+fn replay(file: &str, port: Option<u16>) -> Result<Output, Failure> {
+    let capture = Capture::load(file).map_err(|e| Failure::File(format!("{}: {}", file, e)))?;
+    let chunks = capture.records.len();
+    let bind = ([127, 0, 0, 1], port.unwrap_or(0)).into();
+    let (addr, replay) =
+        ReplayDevice::spawn_on(bind, capture).map_err(|e| Failure::Connection(e.into()))?;
+    // Printed right away, the client needs it to connect
+    eprintln!("Replaying {} chunks on {}", chunks, addr);
+    replay
+        .finish()
+        .map_err(|mismatch| Failure::Device(mismatch.to_string()))?;
+    Ok(Output {
+        text: format!("Replayed {} chunks", chunks),
+        json: format!("{{\"chunks\":{}}}", chunks),
+    })
+}
+
+/// Run a command other than `capture` and `replay`
+fn execute(
+    client: &mut TelnetClient,
+    command: &[&str],
+    out: Option<&str>,
+) -> Result<Output, Failure> {
+    match command {
+        ["version"] => {
+            let (product, version) = client.get_version()?;
+            Ok(Output {
+                text: format!("{} {}", product, version),
+                json: format!(
+                    "{{\"product\":{},\"version\":{}}}",
+                    json_string(&product),
+                    json_string(&version)
+                ),
+            })
+        }
+        ["read", start, size @ ..] => {
+            let start = address(start)?;
+            let size = size.first().map_or(Ok(1), |size| number(size))?;
+            let values = client.read_parameter_bytes(start, size)?;
+            let text: Vec<_> = values.iter().map(|v| format!("{:02X}", v)).collect();
+            let json: Vec<_> = values.iter().map(u8::to_string).collect();
+            Ok(Output {
+                text: text.join(" "),
+                json: format!(
+                    "{{\"address\":\"{}\",\"values\":[{}]}}",
+                    start.to_hex(),
+                    json.join(",")
+                ),
+            })
+        }
+        ["write", start, values @ ..] => {
+            let start = address(start)?;
+            let values = values
+                .iter()
+                .map(|v| hex_byte(v))
+                .collect::<Result<Vec<_>, _>>()?;
+            match values.as_slice() {
+                [value] => client.write_parameter_addr(start, *value)?,
+                values => client.write_parameter_block(start, values)?,
+            }
+            Ok(Output {
+                text: format!("Wrote {} value(s) at {}", values.len(), start.to_hex()),
+                json: format!(
+                    "{{\"address\":\"{}\",\"written\":{}}}",
+                    start.to_hex(),
+                    values.len()
+                ),
+            })
+        }
+        ["scene", "recall", n] => {
+            let n = number(n)?;
+            client.recall_scene(n)?;
+            Ok(Output {
+                text: format!("Recalled scene {}", n),
+                json: format!("{{\"scene\":{}}}", n),
+            })
+        }
+        ["fader", channel, level] => {
+            let channel = AudioChannel::from_name(channel)
+                .ok_or_else(|| Failure::Usage(format!("unknown channel: {}", channel)))?;
+            let level = Db(number(level)?);
+            AudioMixer::new(client).set_fader(channel, level)?;
+            // -INF has no JSON number
+            let (text, json) = if level == Db::NEG_INFINITY {
+                ("-inf".to_string(), "null".to_string())
+            } else {
+                let level = format!("{:.1}", level.value());
+                (level.clone(), level)
+            };
+            Ok(Output {
+                text: format!("{} at {} dB", channel.name(), text),
+                json: format!("{{\"channel\":\"{}\",\"level\":{}}}", channel.name(), json),
+            })
+        }
+        ["dump", start, count] => {
+            let range = AddressRange::with_len(address(start)?, number(count)?);
+            let dump = client.dump_parameters(&[range])?;
+            let Some(out) = out else {
+                // The dump is JSON already
+                let json = dump.to_json();
+                return Ok(Output {
+                    text: json.clone(),
+                    json,
+                });
+            };
+            std::fs::write(out, dump.to_json())
+                .map_err(|e| Failure::File(format!("{}: {}", out, e)))?;
+            Ok(Output {
+                text: format!("Saved {} parameters to {}", dump.parameters.len(), out),
+                json: format!(
+                    "{{\"file\":{},\"parameters\":{}}}",
+                    json_string(out),
+                    dump.parameters.len()
+                ),
+            })
+        }
+        ["restore", file] => {
+            let json = std::fs::read_to_string(file)
+                .map_err(|e| Failure::File(format!("{}: {}", file, e)))?;
+            let dump = ParameterDump::from_json(&json)
+                .map_err(|e| Failure::File(format!("{}: {}", file, e)))?;
+            let report = client.restore_parameters(&dump)?;
+            let failed: Vec<_> = report
+                .failed
+                .iter()
+                .map(|(address, error)| format!("{}: {}", address.to_hex(), error))
+                .collect();
+            if !report.is_complete() {
+                return Err(Failure::Device(format!(
+                    "Restored {} parameters, {} failed: {}",
+                    report.written,
+                    failed.len(),
+                    failed.join(", ")
+                )));
+            }
+            Ok(Output {
+                text: format!("Restored {} parameters", report.written),
+                json: format!("{{\"written\":{},\"failed\":0}}", report.written),
+            })
+        }
+        ["apply", file, dry_run @ ..] => {
+            let config = DeviceConfig::from_toml(file)
+                .map_err(|e| Failure::File(format!("{}: {}", file, e)))?;
+            let report = if dry_run.is_empty() {
+                client.apply_config(&config)?
+            } else {
+                client.diff_config(&config)?
+            };
+            apply_output(&report)
+        }
+        _ => Err(Failure::Usage(USAGE.to_string())),
+    }
+}
+
+/// Describe an [`ApplyReport`], failing if an entry failed
+fn apply_output(report: &ApplyReport) -> Result<Output, Failure> {
+    let verb = if report.dry_run {
+        "Would apply"
+    } else {
+        "Applied"
+    };
+    let mut text = format!(
+        "{} {}, skipped {}, failed {}",
+        verb,
+        report.applied.len(),
+        report.skipped.len(),
+        report.failed.len()
+    );
+    for entry in &report.applied {
+        let _ = write!(text, "\n  {}", entry);
+    }
+    let failed: Vec<_> = report
+        .failed
+        .iter()
+        .map(|(entry, failure)| format!("{}: {}", entry, failure))
+        .collect();
+    if !report.is_complete() {
+        return Err(Failure::Device(format!(
+            "{}\n  {}",
+            text,
+            failed.join("\n  ")
+        )));
+    }
+    let applied: Vec<_> = report
+        .applied
+        .iter()
+        .map(|entry| json_string(&entry.to_string()))
+        .collect();
+    Ok(Output {
+        text,
+        json: format!(
+            "{{\"dry_run\":{},\"applied\":[{}],\"skipped\":{},\"failed\":0}}",
+            report.dry_run,
+            applied.join(","),
+            report.skipped.len()
+        ),
+    })
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(std::env::args().skip(1)).map(|args| (args.json, run(&args)));
+    let (json, failure) = match result {
+        Ok((json, Ok(output))) => {
+            println!("{}", if json { output.json } else { output.text });
+            return ExitCode::SUCCESS;
+        }
+        Ok((json, Err(failure))) => (json, failure),
+        Err(failure) => (false, failure),
+    };
+    if json {
+        eprintln!("{{\"error\":{}}}", json_string(&failure.message()));
+    } else {
+        eprintln!("{}", failure.message());
+    }
+    ExitCode::from(failure.exit_code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roland_rs::RolandError;
+
+    fn args(args: &[&str]) -> Result<Args, Failure> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = args(&[
+            "--host", "10.0.0.5", "dump", "010000", "16", "--out", "a.json",
+        ])
+        .unwrap();
+        assert_eq!(
+            parsed,
+            Args {
+                host: Some("10.0.0.5".to_string()),
+                port: None,
+                json: false,
+                out: Some("a.json".to_string()),
+                command: vec!["dump".into(), "010000".into(), "16".into()],
+            }
+        );
+        let parsed = args(&["--json", "--port", "2300", "--host", "h", "version"]).unwrap();
+        assert!(parsed.json);
+        assert_eq!(parsed.port, Some(2300));
+
+        assert!(matches!(args(&["--host"]), Err(Failure::Usage(_))));
+        assert!(matches!(
+            args(&["--host", "h", "--port", "x"]),
+            Err(Failure::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let code = |e: TelnetError| Failure::from(e).exit_code();
+        assert_eq!(code(TelnetError::ConnectionClosed), 3);
+        assert_eq!(code(TelnetError::Timeout), 3);
+        assert_eq!(code(TelnetError::Protocol(RolandError::OutOfRange)), 4);
+        assert_eq!(
+            code(TelnetError::ShortRead {
+                requested: 2,
+                got: 1
+            }),
+            4
+        );
+        assert_eq!(json_string("a\"b\\\n"), "\"a\\\"b\\\\\\u000a\"");
+    }
+
+    #[test]
+    fn test_check_apply() {
+        assert!(check(&["apply", "setup.toml"]).is_ok());
+        assert!(check(&["apply", "setup.toml", "--dry-run"]).is_ok());
+        assert!(matches!(check(&["apply"]), Err(Failure::Usage(_))));
+        assert!(matches!(
+            check(&["apply", "setup.toml", "--force"]),
+            Err(Failure::Usage(_))
+        ));
+    }
+}
@@ -0,0 +1,235 @@
+//! Read-through cache for polled parameters
+//!
+//! Dashboards poll many status parameters whose values rarely change.
+//! With the cache enabled, [`TelnetClient::read_parameter_cached`] answers
+//! from values read recently instead of asking the device again. Plain
+//! reads ([`TelnetClient::read_parameter_addr`] and friends) always go to
+//! the device and never touch the cache.
+//!
+//! A cached value is dropped when the client writes to its address, or
+//! when an unsolicited DTH for it is queued as an event, even one arriving
+//! while it is being read. Recalling a scene (`scene.recall` of the
+//! client's [`Profile`]) clears the whole cache, since it changes
+//! parameters without telling.
+//! DTH frames handed to a [`TelnetClient::subscribe`] callback bypass the
+//! client and so don't invalidate anything; keep `max_age` short for
+//! addresses watched that way.
+
+use crate::profile::Profile;
+use crate::{offset, TelnetClient, TelnetError};
+use roland_core::{Address, Command};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Values read through the cache, with the time they were read
+#[derive(Debug, Default)]
+pub(crate) struct ReadCache {
+    entries: HashMap<Address, (u8, Instant)>,
+    /// Address being read through the cache, until it is invalidated
+    reading: Option<Address>,
+}
+
+impl ReadCache {
+    /// Get a value read no longer than `max_age` ago
+    fn fresh(&self, address: Address, max_age: Duration) -> Option<u8> {
+        let &(value, read_at) = self.entries.get(&address)?;
+        (read_at.elapsed() <= max_age).then_some(value)
+    }
+
+    /// Drop the value of an address
+    pub(crate) fn invalidate(&mut self, address: Address) {
+        self.entries.remove(&address);
+        if self.reading == Some(address) {
+            self.reading = None;
+        }
+    }
+
+    /// Drop every value
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.reading = None;
+    }
+
+    /// Drop the values a command is about to change
+    ///
+    /// The profile tells which address recalls a scene.
+    pub(crate) fn invalidate_for(&mut self, command: &Command, profile: &Profile) {
+        match command {
+            Command::WriteParameter { address, .. } if profile.is("scene.recall", *address) => {
+                self.clear()
+            }
+            Command::WriteParameter { address, .. } => self.invalidate(*address),
+            Command::WriteBlock { address, data } => {
+                (0..data.len()).for_each(|i| self.invalidate(offset(*address, i)))
+            }
+            _ => {}
+        }
+    }
+}
+
+impl TelnetClient {
+    /// Turn the read cache on or off
+    ///
+    /// The cache is off by default. Turning it off drops cached values.
+    pub fn set_read_cache(&mut self, enabled: bool) {
+        self.cache = enabled.then(ReadCache::default);
+    }
+
+    /// Check if the read cache is on
+    pub fn read_cache(&self) -> bool {
+        self.cache.is_some()
+    }
+
+    /// Read a parameter value, reusing a recent read of it
+    ///
+    /// Answers from the cache if the value was read through it at most
+    /// `max_age` ago and hasn't been invalidated since; otherwise reads it
+    /// from the device and caches it. With the cache off, this is
+    /// [`TelnetClient::read_parameter_addr`] with a size of 1.
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address
+    /// * `max_age` - How old a cached value may be
+    ///
+    /// # Returns
+    /// * `Result<u8, TelnetError>` - Parameter value or error
+    pub fn read_parameter_cached(
+        &mut self,
+        address: Address,
+        max_age: Duration,
+    ) -> Result<u8, TelnetError> {
+        if let Some(value) = self.cache.as_ref().and_then(|c| c.fresh(address, max_age)) {
+            return Ok(value);
+        }
+        if let Some(cache) = &mut self.cache {
+            cache.reading = Some(address);
+        }
+        let value = self.read_parameter_addr(address, 1)?;
+        // A change reported during the read is newer than the value read
+        if let Some(cache) = &mut self.cache {
+            if cache.reading.take() == Some(address) {
+                cache.entries.insert(address, (value, Instant::now()));
+            }
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDevice;
+    use crate::param_map::ParameterMap;
+    use std::thread;
+
+    const LEVEL: Address = Address::new(0x05, 0x00, 0x00);
+    const MUTE: Address = Address::new(0x05, 0x00, 0x01);
+    const AGE: Duration = Duration::from_secs(60);
+
+    fn connect(addr: std::net::SocketAddr) -> TelnetClient {
+        let mut client = crate::mock::connect(addr);
+        client.set_read_cache(true);
+        client
+    }
+
+    fn reads(mock: &crate::mock::MockHandle) -> usize {
+        mock.received()
+            .iter()
+            .filter(|c| matches!(c, Command::ReadParameter { .. }))
+            .count()
+    }
+
+    #[test]
+    fn test_polling_hits_cache() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(LEVEL, 100);
+        mock.set_parameter(MUTE, 1);
+        let mut client = connect(addr);
+
+        for _ in 0..10 {
+            assert_eq!(client.read_parameter_cached(LEVEL, AGE).unwrap(), 100);
+            assert_eq!(client.read_parameter_cached(MUTE, AGE).unwrap(), 1);
+        }
+        assert_eq!(reads(&mock), 2);
+
+        // Plain reads bypass the cache
+        client.read_parameter_addr(LEVEL, 1).unwrap();
+        assert_eq!(reads(&mock), 3);
+
+        // Stale values are read again
+        thread::sleep(Duration::from_millis(20));
+        client
+            .read_parameter_cached(LEVEL, Duration::from_millis(10))
+            .unwrap();
+        assert_eq!(reads(&mock), 4);
+    }
+
+    #[test]
+    fn test_invalidation() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(LEVEL, 100);
+        mock.set_parameter(MUTE, 0);
+        let mut client = connect(addr);
+        client.read_parameter_cached(LEVEL, AGE).unwrap();
+        client.read_parameter_cached(MUTE, AGE).unwrap();
+
+        // A local write drops only the written address
+        client.write_parameter_addr(LEVEL, 50).unwrap();
+        assert_eq!(client.read_parameter_cached(LEVEL, AGE).unwrap(), 50);
+        assert_eq!(client.read_parameter_cached(MUTE, AGE).unwrap(), 0);
+        assert_eq!(reads(&mock), 3);
+
+        // So does an unsolicited change
+        mock.set_parameter(MUTE, 1);
+        mock.inject_unsolicited(MUTE, 1);
+        client.get_version().unwrap();
+        assert_eq!(client.read_parameter_cached(MUTE, AGE).unwrap(), 1);
+        assert_eq!(reads(&mock), 4);
+
+        // A scene recall drops everything
+        client.recall_scene(1).unwrap();
+        client.read_parameter_cached(LEVEL, AGE).unwrap();
+        client.read_parameter_cached(MUTE, AGE).unwrap();
+        assert_eq!(reads(&mock), 6);
+    }
+
+    #[test]
+    fn test_scene_recall_of_profile() {
+        let (addr, mock) = MockDevice::spawn();
+        let recall = Address::new(0x0B, 0x00, 0x00);
+        let mut client = connect(addr);
+        let map = ParameterMap::from_toml("[scene.recall]\naddress = \"0B0000\"\n").unwrap();
+        client.set_profile(Profile::Custom(map));
+        client.read_parameter_cached(LEVEL, AGE).unwrap();
+
+        client.write_parameter_addr(recall, 1).unwrap();
+        client.read_parameter_cached(LEVEL, AGE).unwrap();
+        assert_eq!(reads(&mock), 2);
+    }
+
+    #[test]
+    fn test_change_during_read_not_overwritten() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(LEVEL, 100);
+        // A block change covering LEVEL, read while waiting for the answer
+        mock.set_preamble(b"DTH:04FFFF,01,02;");
+        let mut client = connect(addr);
+
+        assert_eq!(client.read_parameter_cached(LEVEL, AGE).unwrap(), 100);
+        assert_eq!(client.events().count(), 2);
+        assert_eq!(client.read_parameter_cached(LEVEL, AGE).unwrap(), 100);
+        assert_eq!(reads(&mock), 2);
+        client.read_parameter_cached(LEVEL, AGE).unwrap();
+        assert_eq!(reads(&mock), 2);
+    }
+
+    #[test]
+    fn test_off_by_default() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = crate::mock::connect(addr);
+        assert!(!client.read_cache());
+        client.read_parameter_cached(LEVEL, AGE).unwrap();
+        client.read_parameter_cached(LEVEL, AGE).unwrap();
+        assert_eq!(reads(&mock), 2);
+    }
+}
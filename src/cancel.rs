@@ -0,0 +1,89 @@
+//! Cancellation of long-running operations
+//!
+//! Dumps, restores, macros and glides can run for tens of seconds. Their
+//! `_cancellable` variants take a [`CancellationToken`]; cancelling it
+//! from any thread makes the operation return
+//! [`TelnetError::Cancelled`] once the command in progress has been
+//! answered, so the connection stays usable.
+
+use crate::TelnetError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest sleep between checks of a token while waiting
+const CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Handle to cancel an operation, cheap to clone
+///
+/// Clones share the same state: cancelling one cancels them all. A token
+/// stays cancelled; use a new one for the next operation.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a token that isn't cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the operations using the token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Check if the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fail with `Cancelled` if the token was cancelled
+    pub(crate) fn check(&self) -> Result<(), TelnetError> {
+        if self.is_cancelled() {
+            Err(TelnetError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sleep until `deadline`, failing early with `Cancelled`
+    pub(crate) fn sleep_until(&self, deadline: Instant) -> Result<(), TelnetError> {
+        loop {
+            self.check()?;
+            match deadline.checked_duration_since(Instant::now()) {
+                Some(wait) if !wait.is_zero() => thread::sleep(wait.min(CHECK_INTERVAL)),
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        assert!(token.check().is_ok());
+
+        let start = Instant::now();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(30));
+                token.cancel();
+            });
+            assert!(matches!(
+                clone.sleep_until(start + Duration::from_secs(5)),
+                Err(TelnetError::Cancelled)
+            ));
+        });
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(clone.is_cancelled());
+    }
+}
@@ -0,0 +1,456 @@
+//! Session capture and replay for offline debugging
+//!
+//! A [`CaptureRecorder`] records every chunk of bytes a client writes or
+//! reads, with its time, into a [`Capture`]. Captures are saved as
+//! newline-delimited JSON (`.rcap`): a header line, then one line per
+//! chunk with the time in microseconds since the start of the capture,
+//! the direction and the bytes in hex:
+//!
+//! ```text
+//! {"rcap":1}
+//! {"us":0,"dir":"sent","hex":"5645523B"}
+//! {"us":412,"dir":"received","hex":"5645523A56522D3648442C312E30303B"}
+//! ```
+//!
+//! [`ReplayDevice`] serves a capture to one client like the device did,
+//! so a reported bug can be reproduced in a test without the hardware: it
+//! sends the received chunks in order, and before each one waits for the
+//! bytes the client sent at that point, stopping at the first difference.
+//! Timing isn't reproduced; chunks are sent as soon as it's their turn.
+
+use crate::backup::{parse_json, DumpFormatError, JsonValue};
+use crate::wire::Direction;
+use crate::TelnetClient;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Version of the capture format
+const FORMAT_VERSION: u64 = 1;
+
+/// How often the replay device checks whether it was stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long the replay device waits for the bytes the client should send
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Chunk of bytes on the wire
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    /// Time since the start of the capture
+    pub at: Duration,
+    /// Whether the client wrote or read the bytes
+    pub direction: Direction,
+    /// Bytes, exactly as they went over the wire
+    pub bytes: Vec<u8>,
+}
+
+/// Recorded session
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capture {
+    /// Chunks in the order they went over the wire
+    pub records: Vec<CaptureRecord>,
+}
+
+impl Capture {
+    /// Encode the capture as newline-delimited JSON
+    pub fn to_ndjson(&self) -> String {
+        let mut ndjson = format!("{{\"rcap\":{}}}\n", FORMAT_VERSION);
+        for record in &self.records {
+            let direction = match record.direction {
+                Direction::Sent => "sent",
+                Direction::Received => "received",
+            };
+            let hex: String = record.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            ndjson.push_str(&format!(
+                "{{\"us\":{},\"dir\":\"{}\",\"hex\":\"{}\"}}\n",
+                record.at.as_micros(),
+                direction,
+                hex
+            ));
+        }
+        ndjson
+    }
+
+    /// Decode a capture from newline-delimited JSON
+    ///
+    /// Blank lines and unknown fields are ignored. Syntax errors give the
+    /// byte offset in the whole input.
+    pub fn from_ndjson(ndjson: &str) -> Result<Self, DumpFormatError> {
+        let mut lines = Vec::new();
+        let mut pos = 0;
+        for line in ndjson.split_inclusive('\n') {
+            if !line.trim().is_empty() {
+                lines.push((pos, line.trim_end()));
+            }
+            pos += line.len();
+        }
+        let at_pos = |pos: usize| {
+            move |e| match e {
+                DumpFormatError::Syntax(offset) => DumpFormatError::Syntax(pos + offset),
+                e => e,
+            }
+        };
+
+        let mut lines = lines.into_iter();
+        let (pos, header) = lines.next().ok_or(DumpFormatError::Truncated)?;
+        match parse_json(header).map_err(at_pos(pos))?.field("rcap") {
+            Some(JsonValue::Number(FORMAT_VERSION)) => {}
+            _ => return Err(DumpFormatError::MissingField("rcap")),
+        }
+
+        let mut records = Vec::new();
+        for (pos, line) in lines {
+            let value = parse_json(line).map_err(at_pos(pos))?;
+            let at = match value.field("us") {
+                Some(JsonValue::Number(us)) => Duration::from_micros(*us),
+                _ => return Err(DumpFormatError::MissingField("us")),
+            };
+            let direction = match value.field("dir") {
+                Some(JsonValue::String(dir)) if dir == "sent" => Direction::Sent,
+                Some(JsonValue::String(dir)) if dir == "received" => Direction::Received,
+                _ => return Err(DumpFormatError::MissingField("dir")),
+            };
+            let bytes = match value.field("hex") {
+                Some(JsonValue::String(hex)) => {
+                    decode_hex(hex).ok_or(DumpFormatError::Syntax(pos))?
+                }
+                _ => return Err(DumpFormatError::MissingField("hex")),
+            };
+            records.push(CaptureRecord {
+                at,
+                direction,
+                bytes,
+            });
+        }
+        Ok(Self { records })
+    }
+
+    /// Save the capture as newline-delimited JSON
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_ndjson())
+    }
+
+    /// Load a capture saved with [`Capture::save`]
+    ///
+    /// Malformed files give an `InvalidData` error wrapping the
+    /// [`DumpFormatError`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let ndjson = std::fs::read_to_string(path)?;
+        Self::from_ndjson(&ndjson).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Get all bytes sent in one direction, joined
+    pub fn bytes(&self, direction: Direction) -> Vec<u8> {
+        self.records
+            .iter()
+            .filter(|record| record.direction == direction)
+            .flat_map(|record| record.bytes.iter().copied())
+            .collect()
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Records the raw bytes of a client's connection
+///
+/// The recorder uses the client's wire logger, replacing any logger that
+/// was set.
+///
+/// # Example
+/// ```no_run
+/// use roland_rs::capture::CaptureRecorder;
+/// use roland_rs::TelnetClient;
+///
+/// let mut client = TelnetClient::connect("192.168.1.100", 23).unwrap();
+/// let recorder = CaptureRecorder::start(&mut client);
+/// client.get_version().unwrap();
+/// let capture = recorder.stop(&mut client);
+/// capture.save("session.rcap").unwrap();
+/// ```
+pub struct CaptureRecorder {
+    records: Arc<Mutex<Vec<CaptureRecord>>>,
+}
+
+impl CaptureRecorder {
+    /// Start recording the bytes of `client`'s connection
+    pub fn start(client: &mut TelnetClient) -> Self {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let shared = Arc::clone(&records);
+        let start = Instant::now();
+        client.set_wire_logger(move |direction, bytes| {
+            shared.lock().unwrap().push(CaptureRecord {
+                // As precise as the file format
+                at: Duration::from_micros(start.elapsed().as_micros() as u64),
+                direction,
+                bytes: bytes.to_vec(),
+            });
+        });
+        Self { records }
+    }
+
+    /// Number of chunks recorded so far
+    pub fn len(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+
+    /// Check if nothing has been recorded yet
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stop recording and clear the client's wire logger
+    pub fn stop(self, client: &mut TelnetClient) -> Capture {
+        client.clear_wire_logger();
+        Capture {
+            records: std::mem::take(&mut self.records.lock().unwrap()),
+        }
+    }
+}
+
+/// Client bytes that differ from the capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayMismatch {
+    /// Offset of the first expected byte, counting all bytes the client
+    /// sent
+    pub offset: usize,
+    /// Bytes the capture has at that point
+    pub expected: Vec<u8>,
+    /// Bytes the client sent instead, shorter if it stopped sending
+    pub got: Vec<u8>,
+}
+
+impl fmt::Display for ReplayMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Client sent {:?} at byte {} but the capture has {:?}",
+            String::from_utf8_lossy(&self.got),
+            self.offset,
+            String::from_utf8_lossy(&self.expected)
+        )
+    }
+}
+
+impl std::error::Error for ReplayMismatch {}
+
+/// Fake device playing back a [`Capture`]
+pub struct ReplayDevice;
+
+impl ReplayDevice {
+    /// Serve a capture on a free local port
+    ///
+    /// # Returns
+    /// * `(SocketAddr, ReplayHandle)` - Address to connect to and a handle
+    ///   to get the outcome. Dropping the handle stops the device.
+    pub fn spawn(capture: Capture) -> (SocketAddr, ReplayHandle) {
+        Self::spawn_on("127.0.0.1:0".parse().unwrap(), capture)
+            .expect("failed to bind replay device")
+    }
+
+    /// Serve a capture on the given address
+    ///
+    /// Only the first client to connect gets the capture.
+    pub fn spawn_on(bind: SocketAddr, capture: Capture) -> io::Result<(SocketAddr, ReplayHandle)> {
+        let listener = TcpListener::bind(bind)?;
+        let addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let stream = loop {
+                    if stop.load(Ordering::SeqCst) {
+                        return Ok(());
+                    }
+                    match listener.accept() {
+                        Ok((stream, _)) => break stream,
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                        // Nobody to report a mismatch to
+                        Err(_) => return Ok(()),
+                    }
+                };
+                replay(stream, &capture, &stop)
+            })
+        };
+        let handle = ReplayHandle {
+            stop,
+            thread: Some(thread),
+        };
+        Ok((addr, handle))
+    }
+}
+
+/// Handle of a running [`ReplayDevice`]
+pub struct ReplayHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<(), ReplayMismatch>>>,
+}
+
+impl ReplayHandle {
+    /// Wait until the capture has been played back
+    ///
+    /// # Returns
+    /// * `Result<(), ReplayMismatch>` - Success if the client sent the
+    ///   captured bytes, or the first difference
+    pub fn finish(mut self) -> Result<(), ReplayMismatch> {
+        let thread = self.thread.take().expect("replay joined twice");
+        thread.join().expect("replay thread panicked")
+    }
+}
+
+impl Drop for ReplayHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn replay(
+    mut stream: TcpStream,
+    capture: &Capture,
+    stop: &AtomicBool,
+) -> Result<(), ReplayMismatch> {
+    let _ = stream.set_read_timeout(Some(POLL_INTERVAL));
+    let mut offset = 0;
+    let mut records = capture.records.iter().peekable();
+    while let Some(record) = records.next() {
+        if record.direction == Direction::Received {
+            if stream.write_all(&record.bytes).is_err() {
+                return Ok(());
+            }
+            continue;
+        }
+        // Chunks may be split differently this time; compare the run
+        let mut expected = record.bytes.clone();
+        while let Some(next) = records.next_if(|next| next.direction == Direction::Sent) {
+            expected.extend_from_slice(&next.bytes);
+        }
+        let got = read_bytes(&mut stream, expected.len(), stop);
+        if got != expected {
+            return Err(ReplayMismatch {
+                offset,
+                expected,
+                got,
+            });
+        }
+        offset += expected.len();
+    }
+    Ok(())
+}
+
+/// Read `len` bytes, fewer if the client stops sending or the device is
+/// stopped
+fn read_bytes(stream: &mut TcpStream, len: usize, stop: &AtomicBool) -> Vec<u8> {
+    let deadline = Instant::now() + REPLAY_TIMEOUT;
+    let mut bytes = vec![0; len];
+    let mut filled = 0;
+    while filled < len && Instant::now() < deadline && !stop.load(Ordering::SeqCst) {
+        match stream.read(&mut bytes[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => break,
+        }
+    }
+    bytes.truncate(filled);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::Address;
+
+    /// Version request, a read and a write, captured against the mock
+    const SESSION: &str = include_str!("../testdata/version_read_write.rcap");
+
+    #[test]
+    fn test_record_and_round_trip() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(Address::new(0x01, 0x00, 0x00), 0x02);
+        let mut client = connect(addr);
+
+        let recorder = CaptureRecorder::start(&mut client);
+        client.get_version().unwrap();
+        client.read_parameter("010000", 1).unwrap();
+        client.write_parameter("010000", 0x03).unwrap();
+        let capture = recorder.stop(&mut client);
+
+        assert_eq!(capture.records.len(), 6);
+        assert_eq!(
+            capture.bytes(Direction::Sent),
+            b"VER;RQH:010000,000001;DTH:010000,03;"
+        );
+        assert!(capture.records.windows(2).all(|w| w[0].at <= w[1].at));
+        assert_eq!(Capture::from_ndjson(&capture.to_ndjson()), Ok(capture));
+    }
+
+    #[test]
+    fn test_replay_committed_capture() {
+        let capture = Capture::from_ndjson(SESSION).unwrap();
+        let (addr, replay) = ReplayDevice::spawn(capture);
+        let mut client = connect(addr);
+
+        assert_eq!(
+            client.get_version().unwrap(),
+            ("VR-6HD".to_string(), "1.00".to_string())
+        );
+        assert_eq!(client.read_parameter("010000", 1).unwrap(), 0x02);
+        client.write_parameter("010000", 0x03).unwrap();
+        assert_eq!(replay.finish(), Ok(()));
+    }
+
+    #[test]
+    fn test_replay_mismatch() {
+        let capture = Capture::from_ndjson(SESSION).unwrap();
+        let (addr, replay) = ReplayDevice::spawn(capture);
+        let mut client = connect(addr);
+
+        client.get_version().unwrap();
+        // A different address than in the capture
+        assert!(client.read_parameter("010001", 1).is_err());
+        let mismatch = replay.finish().unwrap_err();
+        assert_eq!(mismatch.offset, 4);
+        assert_eq!(mismatch.expected, b"RQH:010000,000001;");
+        assert_eq!(mismatch.got, b"RQH:010001,000001;");
+    }
+
+    #[test]
+    fn test_malformed_captures() {
+        assert_eq!(Capture::from_ndjson(""), Err(DumpFormatError::Truncated));
+        assert_eq!(
+            Capture::from_ndjson("{\"rcap\":2}\n"),
+            Err(DumpFormatError::MissingField("rcap"))
+        );
+        assert_eq!(
+            Capture::from_ndjson("{\"rcap\":1}\n{\"us\":0,\"dir\":\"up\",\"hex\":\"00\"}\n"),
+            Err(DumpFormatError::MissingField("dir"))
+        );
+        assert_eq!(
+            Capture::from_ndjson("{\"rcap\":1}\n{\"us\":0,\"dir\":\"sent\",\"hex\":\"0\"}\n"),
+            Err(DumpFormatError::Syntax(11))
+        );
+        assert_eq!(
+            Capture::from_ndjson("{\"rcap\":1}\n{\"us\":0,"),
+            Err(DumpFormatError::Syntax(19))
+        );
+    }
+}
@@ -0,0 +1,550 @@
+//! Device setup from a configuration file
+//!
+//! A [`DeviceConfig`] describes how a device should be set up: parameter
+//! values by name, scenes to store and the panel lock. It is written in
+//! the TOML subset of [`crate::param_map`]:
+//!
+//! ```toml
+//! map = "vr6hd_map.toml"
+//! panel_lock = ["audio", "menu"]
+//!
+//! [parameters]
+//! video.pgm_select = "hdmi2"
+//! audio.ch1.fader = "-10dB"
+//!
+//! [scenes]
+//! 3 = "Interview"
+//! ```
+//!
+//! `map` is the [`ParameterMap`] giving the names, relative to the config
+//! file. Values are checked against it when the config is loaded. Each
+//! entry of `[scenes]` stores the settings, after the parameters are
+//! written, to the scene with that number and names it.
+//!
+//! [`TelnetClient::apply_config`] makes the device match the config and
+//! reports what it changed; [`TelnetClient::diff_config`] only reads and
+//! reports what would change.
+
+use crate::panel::{LockSection, LockSections};
+use crate::param_map::{
+    address_from_u24, parse_error, strip_comment, ParamMapError, ParameterMap, TomlValue,
+};
+use crate::scene::StoreSceneError;
+use crate::text::check_text;
+use crate::{Command, TelnetClient, TelnetError};
+use roland_core::params::scene;
+use roland_core::{Address, RolandError};
+use std::fmt;
+use std::path::Path;
+
+/// Setup described by a configuration file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceConfig {
+    /// Map naming the parameters
+    pub map: ParameterMap,
+    /// Parameter names and values, in the order they are written
+    pub parameters: Vec<(String, String)>,
+    /// Scene numbers (1-30) and names to store the settings to
+    pub scenes: Vec<(u8, String)>,
+    /// Panel sections to lock, the others are unlocked; `None` leaves the
+    /// panel lock as it is
+    pub panel_lock: Option<LockSections>,
+}
+
+/// Table of the config file a key is in
+enum Table {
+    Top,
+    Parameters,
+    Scenes,
+}
+
+impl DeviceConfig {
+    /// Load a config file and the parameter map it names
+    pub fn from_toml(path: impl AsRef<Path>) -> Result<Self, ParamMapError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(ParamMapError::Io)?;
+        Self::parse(&text, path.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Parse a config, loading the map relative to `dir`
+    fn parse(text: &str, dir: &Path) -> Result<Self, ParamMapError> {
+        let mut map_file = None;
+        let mut parameters = Vec::new();
+        let mut scenes = Vec::new();
+        let mut panel_lock = None;
+
+        let mut table = Table::Top;
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                table = match header.strip_suffix(']').map(str::trim) {
+                    Some("parameters") => Table::Parameters,
+                    Some("scenes") => Table::Scenes,
+                    _ => return Err(parse_error(line_no, "expected [parameters] or [scenes]")),
+                };
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| parse_error(line_no, "expected `key = value`"))?;
+            let key = key.trim();
+            let value = TomlValue::parse(value.trim()).ok_or_else(|| {
+                parse_error(line_no, "expected a string, integer or string array")
+            })?;
+            match (&table, key, value) {
+                (Table::Top, "map", TomlValue::String(file)) => map_file = Some(file),
+                (Table::Top, "panel_lock", TomlValue::Array(names)) => {
+                    let sections = names
+                        .iter()
+                        .map(|name| LockSection::from_name(name))
+                        .collect::<Option<LockSections>>()
+                        .ok_or_else(|| parse_error(line_no, "unknown panel section"))?;
+                    panel_lock = Some(sections);
+                }
+                (Table::Top, _, _) => return Err(parse_error(line_no, "unknown key")),
+                (Table::Parameters, name, TomlValue::String(value)) => {
+                    parameters.push((line_no, name.to_string(), value))
+                }
+                (Table::Parameters, name, TomlValue::Integer(value)) => {
+                    parameters.push((line_no, name.to_string(), value.to_string()))
+                }
+                (Table::Scenes, number, TomlValue::String(name)) => {
+                    let number = number
+                        .parse()
+                        .ok()
+                        .filter(|n| (1..=30).contains(n))
+                        .ok_or_else(|| parse_error(line_no, "scene number must be 1-30"))?;
+                    if check_text(&name, scene::NAME_LENGTH).is_err() {
+                        return Err(parse_error(line_no, "invalid scene name"));
+                    }
+                    scenes.push((number, name));
+                }
+                _ => return Err(parse_error(line_no, "wrong type of value")),
+            }
+        }
+
+        let map_file = map_file.ok_or_else(|| parse_error(1, "missing `map`"))?;
+        let map = ParameterMap::load(dir.join(map_file))?;
+        for (line_no, name, value) in &parameters {
+            let param = map
+                .lookup(name)
+                .map_err(|_| parse_error(*line_no, &format!("unknown parameter {}", name)))?;
+            param.encode(value)?;
+        }
+        Ok(Self {
+            map,
+            parameters: parameters
+                .into_iter()
+                .map(|(_, name, value)| (name, value))
+                .collect(),
+            scenes,
+            panel_lock,
+        })
+    }
+}
+
+/// One entry of a [`DeviceConfig`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigEntry {
+    /// Parameter value
+    Parameter {
+        /// Parameter name
+        name: String,
+        /// Value as given in the config
+        value: String,
+    },
+    /// Scene to store the settings to
+    Scene {
+        /// Scene number (1-30)
+        number: u8,
+        /// Scene name
+        name: String,
+    },
+    /// Panel lock state
+    PanelLock(LockSections),
+}
+
+impl fmt::Display for ConfigEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigEntry::Parameter { name, value } => write!(f, "{} = {}", name, value),
+            ConfigEntry::Scene { number, name } => write!(f, "scene {} {:?}", number, name),
+            ConfigEntry::PanelLock(sections) => {
+                let names: Vec<_> = sections.iter().map(LockSection::name).collect();
+                write!(f, "panel lock [{}]", names.join(", "))
+            }
+        }
+    }
+}
+
+/// Why an entry of a [`DeviceConfig`] couldn't be applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyFailure {
+    /// The device rejected reading or writing the entry
+    Device(RolandError),
+    /// The device accepted the write but reads back other bytes
+    Mismatch {
+        /// Bytes written
+        expected: Vec<u8>,
+        /// Bytes read back
+        actual: Vec<u8>,
+    },
+}
+
+impl fmt::Display for ApplyFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyFailure::Device(e) => write!(f, "{}", e),
+            ApplyFailure::Mismatch { expected, actual } => {
+                write!(f, "wrote {:02X?}, read back {:02X?}", expected, actual)
+            }
+        }
+    }
+}
+
+/// Outcome of [`TelnetClient::apply_config`] or
+/// [`TelnetClient::diff_config`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ApplyReport {
+    /// Entries written and verified, or for a dry run the entries that
+    /// would be written
+    pub applied: Vec<ConfigEntry>,
+    /// Entries the device already matched
+    pub skipped: Vec<ConfigEntry>,
+    /// Entries that couldn't be applied, or for a dry run read
+    pub failed: Vec<(ConfigEntry, ApplyFailure)>,
+    /// Whether this is the report of a dry run
+    pub dry_run: bool,
+}
+
+impl ApplyReport {
+    /// Check if no entry failed
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Parameter of a config with the bytes it should hold
+struct Setting {
+    entry: ConfigEntry,
+    address: Address,
+    bytes: Vec<u8>,
+}
+
+impl TelnetClient {
+    /// Make the device match a config
+    ///
+    /// Parameters the device already matches are skipped, the others are
+    /// written in one batch and read back to verify them. Then the
+    /// scenes are stored, which is always done since their contents
+    /// can't be compared, and the panel lock is set.
+    ///
+    /// # Returns
+    /// * `Result<ApplyReport, TelnetError>` - What was applied, skipped and
+    ///   failed; an error only if the connection failed
+    pub fn apply_config(&mut self, config: &DeviceConfig) -> Result<ApplyReport, TelnetError> {
+        self.run_config(config, false)
+    }
+
+    /// Compare the device with a config without writing anything
+    ///
+    /// The report lists the entries that [`TelnetClient::apply_config`]
+    /// would write as applied, and every scene.
+    pub fn diff_config(&mut self, config: &DeviceConfig) -> Result<ApplyReport, TelnetError> {
+        self.run_config(config, true)
+    }
+
+    fn run_config(
+        &mut self,
+        config: &DeviceConfig,
+        dry_run: bool,
+    ) -> Result<ApplyReport, TelnetError> {
+        let mut report = ApplyReport {
+            dry_run,
+            ..ApplyReport::default()
+        };
+
+        let mut pending = Vec::new();
+        for (name, value) in &config.parameters {
+            let param = config.map.lookup(name)?;
+            let setting = Setting {
+                entry: ConfigEntry::Parameter {
+                    name: name.clone(),
+                    value: value.clone(),
+                },
+                address: param.address,
+                bytes: param.encode(value)?,
+            };
+            match self.read_config_bytes(setting.address, setting.bytes.len())? {
+                Err(e) => report.failed.push((setting.entry, ApplyFailure::Device(e))),
+                Ok(bytes) if bytes == setting.bytes => report.skipped.push(setting.entry),
+                Ok(_) => pending.push(setting),
+            }
+        }
+
+        if dry_run {
+            report
+                .applied
+                .extend(pending.into_iter().map(|setting| setting.entry));
+        } else {
+            let commands: Vec<Command> = pending
+                .iter()
+                .map(|setting| match setting.bytes.as_slice() {
+                    [value] => Command::write_parameter(setting.address, *value),
+                    data => Command::WriteBlock {
+                        address: setting.address,
+                        data: data.to_vec(),
+                    },
+                })
+                .collect();
+            let results = self.write_pipelined(&commands)?;
+            for (setting, result) in pending.into_iter().zip(results) {
+                if let Err(e) = result {
+                    report.failed.push((setting.entry, ApplyFailure::Device(e)));
+                    continue;
+                }
+                match self.read_config_bytes(setting.address, setting.bytes.len())? {
+                    Err(e) => report.failed.push((setting.entry, ApplyFailure::Device(e))),
+                    Ok(actual) if actual != setting.bytes => report.failed.push((
+                        setting.entry,
+                        ApplyFailure::Mismatch {
+                            expected: setting.bytes,
+                            actual,
+                        },
+                    )),
+                    Ok(_) => report.applied.push(setting.entry),
+                }
+            }
+        }
+
+        for (number, name) in &config.scenes {
+            let entry = ConfigEntry::Scene {
+                number: *number,
+                name: name.clone(),
+            };
+            if dry_run {
+                report.applied.push(entry);
+                continue;
+            }
+            match self.store_scene_named(*number, name) {
+                Ok(()) => report.applied.push(entry),
+                Err(
+                    StoreSceneError::Store(e)
+                    | StoreSceneError::InvalidName(e)
+                    | StoreSceneError::Name(e),
+                ) => {
+                    let e = device_error(e)?;
+                    report.failed.push((entry, ApplyFailure::Device(e)));
+                }
+            }
+        }
+
+        if let Some(sections) = config.panel_lock {
+            let entry = ConfigEntry::PanelLock(sections);
+            match self.apply_panel_lock(sections, dry_run)? {
+                Ok(true) => report.applied.push(entry),
+                Ok(false) => report.skipped.push(entry),
+                Err(e) => report.failed.push((entry, e)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Set the panel lock unless it matches, returning whether it was set
+    fn apply_panel_lock(
+        &mut self,
+        sections: LockSections,
+        dry_run: bool,
+    ) -> Result<Result<bool, ApplyFailure>, TelnetError> {
+        let current = match self.panel_lock_state() {
+            Ok(current) => current,
+            Err(e) => return Ok(Err(ApplyFailure::Device(device_error(e)?))),
+        };
+        if current == sections {
+            return Ok(Ok(false));
+        }
+        if dry_run {
+            return Ok(Ok(true));
+        }
+        let actual = self
+            .set_locked_sections(sections)
+            .and_then(|()| self.panel_lock_state());
+        Ok(match actual {
+            Ok(actual) if actual == sections => Ok(true),
+            Ok(actual) => Err(ApplyFailure::Mismatch {
+                expected: vec![sections.bits()],
+                actual: vec![actual.bits()],
+            }),
+            Err(e) => Err(ApplyFailure::Device(device_error(e)?)),
+        })
+    }
+
+    /// Read the bytes of a parameter one at a time, like
+    /// [`TelnetClient::read_named`], returning a device error separately
+    fn read_config_bytes(
+        &mut self,
+        address: Address,
+        len: usize,
+    ) -> Result<Result<Vec<u8>, RolandError>, TelnetError> {
+        let start = u32::from(address);
+        let mut bytes = Vec::with_capacity(len);
+        for i in 0..len as u32 {
+            match self.read_parameter_addr(address_from_u24(start + i), 1) {
+                Ok(byte) => bytes.push(byte),
+                Err(e) => return device_error(e).map(Err),
+            }
+        }
+        Ok(Ok(bytes))
+    }
+}
+
+/// Get the error the device reported, passing other errors on
+fn device_error(e: TelnetError) -> Result<RolandError, TelnetError> {
+    match e {
+        TelnetError::Device { error, .. } => Ok(error),
+        e => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::offset;
+    use roland_core::params::{pinp, system, video};
+
+    const SAMPLE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/device_config.toml");
+
+    fn parse(text: &str) -> Result<DeviceConfig, ParamMapError> {
+        DeviceConfig::parse(
+            text,
+            Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata")),
+        )
+    }
+
+    #[test]
+    fn test_load_sample() {
+        let config = DeviceConfig::from_toml(SAMPLE).unwrap();
+        assert_eq!(config.map.len(), 4);
+        assert_eq!(config.parameters.len(), 4);
+        assert_eq!(
+            config.parameters[0],
+            ("video.pgm_select".to_string(), "hdmi2".to_string())
+        );
+        assert_eq!(config.scenes, vec![(3, "Interview".to_string())]);
+        let locked: LockSections = [LockSection::Audio, LockSection::Menu]
+            .into_iter()
+            .collect();
+        assert_eq!(config.panel_lock, Some(locked));
+
+        let map = "map = \"device_config_map.toml\"\n";
+        assert!(matches!(
+            parse(&format!(
+                "{}[parameters]\nvideo.pgm_select = \"hdmi9\"",
+                map
+            )),
+            Err(ParamMapError::UnknownValue { .. })
+        ));
+        assert!(matches!(
+            parse(&format!("{}[parameters]\nvideo.wipe = 1", map)),
+            Err(ParamMapError::Parse { line: 3, .. })
+        ));
+        assert!(matches!(
+            parse(&format!("{}[scenes]\n31 = \"Late\"", map)),
+            Err(ParamMapError::Parse { line: 3, .. })
+        ));
+        assert!(matches!(
+            parse("panel_lock = [\"audio\"]"),
+            Err(ParamMapError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let config = DeviceConfig::from_toml(SAMPLE).unwrap();
+
+        let report = client.apply_config(&config).unwrap();
+        assert!(report.is_complete());
+        assert!(!report.dry_run);
+        assert_eq!(report.applied.len(), 6);
+        assert!(report.skipped.is_empty());
+        assert_eq!(mock.parameter(video::PGM_SELECT), Some(1));
+        assert_eq!(mock.parameter(video::TRANSITION_TIME), Some(15));
+        let position = offset(pinp::POSITION_H, 1);
+        assert_eq!(mock.parameter(pinp::POSITION_H), Some(0x05));
+        assert_eq!(mock.parameter(position), Some(0xDC));
+        assert_eq!(mock.parameter(system::PANEL_LOCK), Some(0x05));
+        assert_eq!(client.scene_name(3).unwrap(), "Interview");
+
+        // Only the scene is applied again
+        let report = client.apply_config(&config).unwrap();
+        assert_eq!(
+            report.applied,
+            vec![ConfigEntry::Scene {
+                number: 3,
+                name: "Interview".to_string()
+            }]
+        );
+        assert_eq!(report.skipped.len(), 5);
+    }
+
+    #[test]
+    fn test_diff_config() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let config = DeviceConfig::from_toml(SAMPLE).unwrap();
+        mock.set_parameter(video::PGM_SELECT, 1);
+        mock.clear_received();
+
+        let report = client.diff_config(&config).unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(
+            report.skipped[0].to_string(),
+            "video.pgm_select = hdmi2".to_string()
+        );
+        assert_eq!(report.applied.len(), 5);
+        assert!(mock
+            .received()
+            .iter()
+            .all(|command| matches!(command, Command::ReadParameter { .. })));
+        assert_eq!(mock.parameter(video::TRANSITION_TIME), None);
+    }
+
+    #[test]
+    fn test_apply_config_failures() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let config = DeviceConfig::from_toml(SAMPLE).unwrap();
+        mock.set_address_error(video::TRANSITION_TIME, RolandError::OutOfRange);
+        mock.set_write_filter(Address::new(0x05, 0x00, 0x00), |value| value - 1);
+
+        let report = client.apply_config(&config).unwrap();
+        assert_eq!(report.applied.len(), 4);
+        assert_eq!(report.failed.len(), 2);
+        assert!(matches!(
+            &report.failed[0],
+            (ConfigEntry::Parameter { name, .. }, ApplyFailure::Device(RolandError::OutOfRange))
+                if name == "video.transition_time"
+        ));
+        let (entry, failure) = &report.failed[1];
+        assert_eq!(entry.to_string(), "audio.ch1.fader = -10dB");
+        let expected = config
+            .map
+            .lookup("audio.ch1.fader")
+            .unwrap()
+            .encode("-10dB")
+            .unwrap();
+        assert!(matches!(
+            failure,
+            ApplyFailure::Mismatch { expected: e, actual } if *e == expected && actual[0] == e[0] - 1
+        ));
+    }
+}
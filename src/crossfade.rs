@@ -0,0 +1,299 @@
+//! Video transition with an audio crossfade
+//!
+//! [`SharedClient::crossfade`] takes the next video source with AUTO
+//! TAKE and, over the same time, fades one group of audio channels out
+//! and another in. The fades are glides (see [`crate::glide`]) run by the
+//! worker, while another thread waits for the device to finish the video
+//! transition.
+
+use crate::audio::{AudioChannel, AudioMixer, Db};
+use crate::glide::GlideHandle;
+use crate::shared::SharedClient;
+use crate::video::{VideoInput, VideoSwitcher};
+use crate::TelnetError;
+use roland_core::{Address, RolandError};
+use std::error::Error;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the end of the transition and the glides are checked
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// How long past the crossfade time to wait before giving up
+const GRACE: Duration = Duration::from_secs(1);
+
+/// Failure of [`SharedClient::crossfade`]
+#[derive(Debug)]
+pub enum CrossfadeError {
+    /// Checking the program or setting up the transition failed, nothing
+    /// was taken
+    Setup(TelnetError),
+    /// Starting the fade of a channel failed, nothing was taken
+    Audio(AudioChannel, TelnetError),
+    /// AUTO TAKE failed; the fades were stopped
+    Take(TelnetError),
+    /// The video transition didn't finish, or checking it failed
+    Transition(TelnetError),
+    /// The device rejected fader steps, or the fades didn't finish
+    Fade(Vec<(Address, RolandError)>),
+}
+
+impl fmt::Display for CrossfadeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrossfadeError::Setup(e) => write!(f, "Setting up the transition failed: {}", e),
+            CrossfadeError::Audio(channel, e) => {
+                write!(f, "Starting the {} fade failed: {}", channel.name(), e)
+            }
+            CrossfadeError::Take(e) => write!(f, "AUTO TAKE failed: {}", e),
+            CrossfadeError::Transition(e) => write!(f, "The transition failed: {}", e),
+            CrossfadeError::Fade(errors) if errors.is_empty() => {
+                write!(f, "The fades didn't finish in time")
+            }
+            CrossfadeError::Fade(errors) => {
+                write!(f, "{} fader steps were rejected", errors.len())
+            }
+        }
+    }
+}
+
+impl Error for CrossfadeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CrossfadeError::Setup(e)
+            | CrossfadeError::Audio(_, e)
+            | CrossfadeError::Take(e)
+            | CrossfadeError::Transition(e) => Some(e),
+            CrossfadeError::Fade(_) => None,
+        }
+    }
+}
+
+impl SharedClient {
+    /// Take `video_to` from `video_from` while crossfading audio
+    ///
+    /// Sets the preset and the transition time, then in one go starts
+    /// fading the `audio_fade_out` channels to -INF and the
+    /// `audio_fade_in` channels to 0 dB and triggers AUTO TAKE, so
+    /// commands of other threads don't come in between. Returns once the
+    /// device reports the transition finished and every fade has reached
+    /// its level.
+    ///
+    /// # Arguments
+    /// * `duration` - Time of the transition and the fades, rounded to
+    ///   the device's 100 ms steps (at most 4 seconds)
+    ///
+    /// # Returns
+    /// * `Result<(), CrossfadeError>` - Success, or the step that failed;
+    ///   `Setup` with `OutOfRange` for a duration above 4 seconds and
+    ///   with `Invalid` if `video_from` isn't on program (nothing is sent
+    ///   then)
+    pub fn crossfade(
+        &self,
+        video_from: VideoInput,
+        video_to: VideoInput,
+        audio_fade_out: &[AudioChannel],
+        audio_fade_in: &[AudioChannel],
+        duration: Duration,
+    ) -> Result<(), CrossfadeError> {
+        let ms = u16::try_from(duration.as_millis())
+            .ok()
+            .filter(|&ms| ms <= VideoSwitcher::MAX_TRANSITION_TIME_MS)
+            .ok_or(CrossfadeError::Setup(TelnetError::Protocol(
+                RolandError::OutOfRange,
+            )))?;
+        self.with(move |client| {
+            let mut switcher = VideoSwitcher::new(client);
+            if switcher.program()? != video_from {
+                return Err(TelnetError::Protocol(RolandError::Invalid));
+            }
+            switcher.select_preset(video_to)?;
+            switcher.set_transition_time_ms(ms)
+        })
+        .and_then(|result| result)
+        .map_err(CrossfadeError::Setup)?;
+
+        let fades: Vec<(AudioChannel, Db)> = audio_fade_out
+            .iter()
+            .map(|&channel| (channel, Db::NEG_INFINITY))
+            .chain(audio_fade_in.iter().map(|&channel| (channel, Db::ZERO)))
+            .collect();
+        let glides = self
+            .with(move |client| {
+                let mut glides: Vec<GlideHandle> = Vec::new();
+                let mut mixer = AudioMixer::new(client);
+                for (channel, level) in fades {
+                    match mixer.glide_fader(channel, level, duration) {
+                        Ok(glide) => glides.push(glide),
+                        Err(e) => {
+                            glides.iter().for_each(GlideHandle::cancel);
+                            return Err(CrossfadeError::Audio(channel, e));
+                        }
+                    }
+                }
+                if let Err(e) = VideoSwitcher::new(client).auto_take() {
+                    glides.iter().for_each(GlideHandle::cancel);
+                    return Err(CrossfadeError::Take(e));
+                }
+                Ok(glides)
+            })
+            .map_err(CrossfadeError::Take)??;
+
+        let deadline = Instant::now() + duration + GRACE;
+        let transition = {
+            let client = self.clone();
+            thread::spawn(move || wait_transition(&client, deadline))
+        };
+        while !glides.iter().all(GlideHandle::is_finished) {
+            if Instant::now() >= deadline {
+                glides.iter().for_each(GlideHandle::cancel);
+                let _ = transition.join();
+                return Err(CrossfadeError::Fade(Vec::new()));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        let errors = self.with(|client| {
+            client
+                .wait_write_acks()
+                .map(|()| client.drain_write_errors().collect::<Vec<_>>())
+        });
+
+        transition
+            .join()
+            .unwrap_or(Err(TelnetError::ConnectionClosed))
+            .map_err(CrossfadeError::Transition)?;
+        match errors.and_then(|errors| errors) {
+            Ok(errors) if errors.is_empty() => Ok(()),
+            Ok(errors) => Err(CrossfadeError::Fade(errors)),
+            Err(e) => Err(CrossfadeError::Transition(e)),
+        }
+    }
+}
+
+/// Poll the transition status until the transition has finished
+fn wait_transition(client: &SharedClient, deadline: Instant) -> Result<(), TelnetError> {
+    loop {
+        if !client.with(|client| VideoSwitcher::new(client).transition_in_progress())?? {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(TelnetError::Timeout);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDevice;
+    use roland_core::params::{audio, video};
+    use roland_core::Command;
+    use std::net::SocketAddr;
+
+    fn connect(addr: SocketAddr) -> SharedClient {
+        SharedClient::new(crate::mock::connect(addr))
+    }
+
+    fn position(commands: &[Command], address: Address) -> Option<usize> {
+        commands.iter().position(
+            |command| matches!(command, Command::WriteParameter { address: a, .. } if *a == address),
+        )
+    }
+
+    fn last_write(commands: &[Command], address: Address) -> Option<u8> {
+        commands.iter().rev().find_map(|command| match command {
+            Command::WriteParameter { address: a, value } if *a == address => Some(*value),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_crossfade_order() {
+        let (addr, mock) = MockDevice::spawn();
+        let ch1 = audio::channel(0, audio::LEVEL);
+        let ch2 = audio::channel(1, audio::LEVEL);
+        mock.set_parameter(video::PGM_SELECT, 0);
+        mock.set_parameter(ch1, Db(-10.0).to_byte());
+        mock.set_parameter(ch2, 0);
+        mock.set_parameter(video::TRANSITION_STATUS, 1);
+        let client = connect(addr);
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(150));
+                mock.set_parameter(video::TRANSITION_STATUS, 0);
+            });
+            client
+                .crossfade(
+                    VideoInput::Hdmi1,
+                    VideoInput::Hdmi2,
+                    &[AudioChannel::Ch1],
+                    &[AudioChannel::Ch2],
+                    Duration::from_millis(300),
+                )
+                .unwrap();
+        });
+
+        let received = mock.received();
+        let preset = position(&received, video::PST_SELECT).unwrap();
+        let time = position(&received, video::TRANSITION_TIME).unwrap();
+        let take = position(&received, video::AUTO_TAKE).unwrap();
+        assert!(preset < time && time < take);
+        assert_eq!(mock.parameter(video::TRANSITION_TIME), Some(3));
+        // The fades start with the take and end at their levels
+        for fader in [ch1, ch2] {
+            assert!(position(&received, fader).unwrap() > take);
+        }
+        assert_eq!(last_write(&received, ch1), Some(0));
+        assert_eq!(last_write(&received, ch2), Some(Db::ZERO.to_byte()));
+        // The transition was waited for while the fades ran
+        let status_reads = received[take..]
+            .iter()
+            .filter(|command| {
+                matches!(command, Command::ReadParameter { address, .. } if *address == video::TRANSITION_STATUS)
+            })
+            .count();
+        assert!(status_reads > 1);
+    }
+
+    #[test]
+    fn test_crossfade_failures() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(video::PGM_SELECT, 2);
+        let client = connect(addr);
+        let crossfade = |duration| {
+            client.crossfade(
+                VideoInput::Hdmi1,
+                VideoInput::Hdmi2,
+                &[AudioChannel::Ch1],
+                &[],
+                duration,
+            )
+        };
+
+        assert!(matches!(
+            crossfade(Duration::from_secs(5)),
+            Err(CrossfadeError::Setup(TelnetError::Protocol(
+                RolandError::OutOfRange
+            )))
+        ));
+        assert!(matches!(
+            crossfade(Duration::from_secs(1)),
+            Err(CrossfadeError::Setup(TelnetError::Protocol(
+                RolandError::Invalid
+            )))
+        ));
+        assert_eq!(position(&mock.received(), video::PST_SELECT), None);
+
+        mock.set_parameter(video::PGM_SELECT, 0);
+        mock.set_address_error(video::AUTO_TAKE, RolandError::Invalid);
+        assert!(matches!(
+            crossfade(Duration::from_millis(200)),
+            Err(CrossfadeError::Take(TelnetError::Device { .. }))
+        ));
+        thread::sleep(Duration::from_millis(300));
+        let ch1 = audio::channel(0, audio::LEVEL);
+        assert_eq!(position(&mock.received(), ch1), None);
+    }
+}
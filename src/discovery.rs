@@ -0,0 +1,432 @@
+//! Device discovery via mDNS (requires the `discovery` feature)
+//!
+//! Sends a one-shot mDNS query (RFC 6762) for the service types in
+//! [`SERVICES`] and collects the answers. Every responder is included, so
+//! other Roland models answering the same query show up with their own
+//! model string; filter on [`DiscoveredDevice::model`] if needed.
+
+use crate::{TelnetClient, TelnetError};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Service types queried by [`discover`]
+pub const SERVICES: &[&str] = &["_roland._tcp.local", "_telnet._tcp.local"];
+
+/// mDNS multicast group and port
+const MDNS_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+/// Telnet port used when a device doesn't report one
+const DEFAULT_PORT: u16 = 23;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+/// Class IN with the "unicast response" bit set
+const CLASS_IN_QU: u16 = 0x8001;
+
+/// Device that answered a discovery query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredDevice {
+    /// Service instance name, e.g. `VR-6HD Stage Left`
+    pub name: String,
+    /// Model reported by the device, or the instance name if it reports none
+    pub model: String,
+    /// IP address
+    pub ip: IpAddr,
+    /// Port of the control service
+    pub port: u16,
+    /// MAC address, if the device reports it
+    pub mac: Option<String>,
+}
+
+/// Discover devices on the local network
+///
+/// Waits `timeout` for answers; devices that answer late are missed.
+pub fn discover(timeout: Duration) -> Result<Vec<DiscoveredDevice>, TelnetError> {
+    discover_at(MDNS_ADDR, SERVICES, timeout)
+}
+
+/// Discover devices advertising `service`, e.g. `_roland._tcp.local`
+pub fn discover_service(
+    service: &str,
+    timeout: Duration,
+) -> Result<Vec<DiscoveredDevice>, TelnetError> {
+    discover_at(MDNS_ADDR, &[service], timeout)
+}
+
+fn discover_at(
+    target: SocketAddr,
+    services: &[&str],
+    timeout: Duration,
+) -> Result<Vec<DiscoveredDevice>, TelnetError> {
+    let bind = if target.ip().is_loopback() {
+        Ipv4Addr::LOCALHOST
+    } else {
+        Ipv4Addr::UNSPECIFIED
+    };
+    let socket = UdpSocket::bind((bind, 0))?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.send_to(&encode_query(services), target)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut devices: Vec<DiscoveredDevice> = Vec::new();
+    let mut buf = [0; 9000];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(devices);
+        }
+        socket.set_read_timeout(Some(deadline - now))?;
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(devices)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // Malformed packets, e.g. from other mDNS software, are ignored
+        let Some(found) = parse_response(&buf[..len], services, from.ip()) else {
+            continue;
+        };
+        for device in found {
+            if !devices
+                .iter()
+                .any(|d| d.name == device.name && d.ip == device.ip)
+            {
+                devices.push(device);
+            }
+        }
+    }
+}
+
+impl TelnetClient {
+    /// Connect to a device found by [`discover`]
+    pub fn connect_discovered(device: &DiscoveredDevice) -> Result<Self, TelnetError> {
+        Self::connect_addr(SocketAddr::new(device.ip, device.port))
+    }
+}
+
+/// Encode an mDNS query for the PTR records of `services`
+fn encode_query(services: &[&str]) -> Vec<u8> {
+    let mut packet = vec![0; 12];
+    packet[4..6].copy_from_slice(&(services.len() as u16).to_be_bytes());
+    for service in services {
+        for label in service.split('.').filter(|label| !label.is_empty()) {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.push(0);
+        packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN_QU.to_be_bytes());
+    }
+    packet
+}
+
+/// Resource record fields used for discovery
+enum Record {
+    Ptr(String),
+    Srv { port: u16, target: String },
+    Txt(Vec<(String, String)>),
+    A(Ipv4Addr),
+    Other,
+}
+
+/// Parse an mDNS response into the devices it announces
+///
+/// `from` is used as the address of devices whose A record is missing.
+fn parse_response(packet: &[u8], services: &[&str], from: IpAddr) -> Option<Vec<DiscoveredDevice>> {
+    let mut reader = DnsReader { packet, pos: 0 };
+    let header = reader.take(12)?;
+    // Only responses
+    if header[2] & 0x80 == 0 {
+        return Some(Vec::new());
+    }
+    let count = |i: usize| u16::from_be_bytes([header[i], header[i + 1]]) as usize;
+    let (questions, records) = (count(4), count(6) + count(8) + count(10));
+
+    for _ in 0..questions {
+        reader.name()?;
+        reader.take(4)?;
+    }
+    let mut parsed = Vec::with_capacity(records);
+    for _ in 0..records {
+        parsed.push(reader.record()?);
+    }
+
+    let mut instances = Vec::new();
+    let mut srv = HashMap::new();
+    let mut txt = HashMap::new();
+    let mut hosts = HashMap::new();
+    for (name, record) in parsed {
+        match record {
+            Record::Ptr(instance) if services.iter().any(|s| same_name(&name, s)) => {
+                instances.push(instance)
+            }
+            Record::Srv { port, target } => {
+                srv.insert(name.to_ascii_lowercase(), (port, target));
+            }
+            Record::Txt(entries) => {
+                txt.insert(name.to_ascii_lowercase(), entries);
+            }
+            Record::A(ip) => {
+                hosts.insert(name.to_ascii_lowercase(), ip);
+            }
+            _ => {}
+        }
+    }
+
+    let devices = instances
+        .into_iter()
+        .map(|instance| {
+            let key = instance.to_ascii_lowercase();
+            let (port, ip) = match srv.get(&key) {
+                Some((port, target)) => (
+                    *port,
+                    hosts
+                        .get(&target.to_ascii_lowercase())
+                        .map(|&ip| IpAddr::V4(ip))
+                        .unwrap_or(from),
+                ),
+                None => (DEFAULT_PORT, from),
+            };
+            let entries = txt.get(&key);
+            let lookup = |keys: &[&str]| {
+                entries.and_then(|entries| {
+                    entries
+                        .iter()
+                        .find(|(k, _)| keys.iter().any(|key| k.eq_ignore_ascii_case(key)))
+                        .map(|(_, v)| v.clone())
+                })
+            };
+            // Instance names are "<instance>.<service>"
+            let name = instance
+                .split_once("._")
+                .map_or(instance.as_str(), |(name, _)| name)
+                .to_string();
+            DiscoveredDevice {
+                model: lookup(&["model", "md", "product"]).unwrap_or_else(|| name.clone()),
+                mac: lookup(&["mac", "macaddr"]),
+                name,
+                ip,
+                port,
+            }
+        })
+        .collect();
+    Some(devices)
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.')
+        .eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+struct DnsReader<'a> {
+    packet: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> DnsReader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.packet.get(self.pos..self.pos + n)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.take(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Read a possibly compressed name at the current position
+    fn name(&mut self) -> Option<String> {
+        let (name, end) = read_name(self.packet, self.pos)?;
+        self.pos = end;
+        Some(name)
+    }
+
+    fn record(&mut self) -> Option<(String, Record)> {
+        let name = self.name()?;
+        let kind = self.u16()?;
+        self.take(6)?; // class and TTL
+        let len = self.u16()? as usize;
+        let start = self.pos;
+        let data = self.take(len)?;
+        let record = match kind {
+            TYPE_PTR => Record::Ptr(read_name(self.packet, start)?.0),
+            TYPE_SRV if len >= 6 => Record::Srv {
+                port: u16::from_be_bytes([data[4], data[5]]),
+                target: read_name(self.packet, start + 6)?.0,
+            },
+            TYPE_TXT => {
+                let mut entries = Vec::new();
+                let mut rest = data;
+                while let Some((&n, tail)) = rest.split_first() {
+                    let entry = tail.get(..n as usize)?;
+                    let entry = String::from_utf8_lossy(entry);
+                    let (key, value) = entry.split_once('=').unwrap_or((&entry, ""));
+                    entries.push((key.to_string(), value.to_string()));
+                    rest = &tail[n as usize..];
+                }
+                Record::Txt(entries)
+            }
+            TYPE_A if len == 4 => Record::A(Ipv4Addr::new(data[0], data[1], data[2], data[3])),
+            _ => Record::Other,
+        };
+        Some((name, record))
+    }
+}
+
+/// Read a name starting at `pos`, returning it and the position after it
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bound the number of pointers followed so loops can't hang
+    for _ in 0..128 {
+        let len = *packet.get(pos)?;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(pos + 1)));
+            }
+            len if len & 0xC0 == 0xC0 => {
+                let low = *packet.get(pos + 1)?;
+                end.get_or_insert(pos + 2);
+                pos = ((len as usize & 0x3F) << 8) | low as usize;
+            }
+            len => {
+                let label = packet.get(pos + 1..pos + 1 + len as usize)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len as usize;
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Build a response announcing one instance of `service`
+    fn response(service: &str, instance: &str, txt: &[&str], with_a: bool) -> Vec<u8> {
+        fn name(packet: &mut Vec<u8>, name: &str) {
+            for label in name.split('.') {
+                packet.push(label.len() as u8);
+                packet.extend_from_slice(label.as_bytes());
+            }
+            packet.push(0);
+        }
+        fn record(packet: &mut Vec<u8>, owner: &[u8], kind: u16, data: &[u8]) {
+            packet.extend_from_slice(owner);
+            packet.extend_from_slice(&kind.to_be_bytes());
+            packet.extend_from_slice(&[0x80, 0x01, 0, 0, 0x11, 0x94]);
+            packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            packet.extend_from_slice(data);
+        }
+
+        let records = if with_a { 4u16 } else { 3 };
+        let mut packet = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0];
+        packet.extend_from_slice(&(records - 1).to_be_bytes());
+
+        // PTR service -> instance; the instance name is compressed
+        // against the service name in the owner
+        let service_offset = packet.len();
+        let mut owner = Vec::new();
+        name(&mut owner, service);
+        let mut ptr = Vec::new();
+        ptr.push(instance.len() as u8);
+        ptr.extend_from_slice(instance.as_bytes());
+        ptr.extend_from_slice(&[0xC0, service_offset as u8]);
+        record(&mut packet, &owner, TYPE_PTR, &ptr);
+
+        let mut instance_name = Vec::new();
+        name(&mut instance_name, &format!("{}.{}", instance, service));
+        let mut srv = vec![0, 0, 0, 0, 0, 23];
+        name(&mut srv, "vr6hd.local");
+        record(&mut packet, &instance_name, TYPE_SRV, &srv);
+
+        let mut txt_data = Vec::new();
+        for entry in txt {
+            txt_data.push(entry.len() as u8);
+            txt_data.extend_from_slice(entry.as_bytes());
+        }
+        record(&mut packet, &instance_name, TYPE_TXT, &txt_data);
+
+        if with_a {
+            let mut host = Vec::new();
+            name(&mut host, "vr6hd.local");
+            record(&mut packet, &host, TYPE_A, &[192, 168, 1, 50]);
+        }
+        packet
+    }
+
+    #[test]
+    fn test_encode_query() {
+        let query = encode_query(&["_roland._tcp.local"]);
+        assert_eq!(&query[..12], &[0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&query[12..20], b"\x07_roland");
+        assert_eq!(&query[query.len() - 4..], &[0, 12, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let from = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9));
+        let packet = response(
+            "_roland._tcp.local",
+            "Stage Left",
+            &["model=VR-6HD", "mac=00:11:22:33:44:55"],
+            true,
+        );
+        let devices = parse_response(&packet, SERVICES, from).unwrap();
+        assert_eq!(
+            devices,
+            vec![DiscoveredDevice {
+                name: "Stage Left".into(),
+                model: "VR-6HD".into(),
+                ip: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 50)),
+                port: 23,
+                mac: Some("00:11:22:33:44:55".into()),
+            }]
+        );
+
+        // Another model without TXT details or A record
+        let packet = response("_telnet._tcp.local", "V-160HD", &[], false);
+        let devices = parse_response(&packet, SERVICES, from).unwrap();
+        assert_eq!(devices[0].model, "V-160HD");
+        assert_eq!(devices[0].ip, from);
+        assert_eq!(devices[0].mac, None);
+
+        // Truncated packets are rejected, not panicked on
+        assert_eq!(
+            parse_response(&packet[..packet.len() - 3], SERVICES, from),
+            None
+        );
+    }
+
+    #[test]
+    fn test_discover() {
+        let responder = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = responder.local_addr().unwrap();
+        let thread = thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (_, from) = responder.recv_from(&mut buf).unwrap();
+            responder.send_to(b"garbage", from).unwrap();
+            let packet = response("_roland._tcp.local", "VR-6HD", &["model=VR-6HD"], false);
+            // Devices answer repeatedly; duplicates are dropped
+            responder.send_to(&packet, from).unwrap();
+            responder.send_to(&packet, from).unwrap();
+        });
+
+        let devices = discover_at(target, SERVICES, Duration::from_millis(300)).unwrap();
+        thread.join().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].ip, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(devices[0].port, 23);
+    }
+}
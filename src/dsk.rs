@@ -0,0 +1,492 @@
+//! Downstream keyer control
+//!
+//! A key is set up by several interacting parameters; [`KeySettings`]
+//! holds them together and writes them in one batch. A parameter the
+//! device rejects doesn't stop the others, so after a partial failure
+//! the device may hold a mix of old and new settings: read them back
+//! with [`KeySettings::read`].
+
+use crate::fade::fade_time_value;
+use crate::video::VideoInput;
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::dsk;
+use roland_core::{Address, Command, RolandError};
+use std::time::Duration;
+
+/// What fills the keyed area
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FillType {
+    /// Fill with the key source itself
+    Bus,
+    /// Fill with a matte color
+    Matte,
+}
+
+impl FillType {
+    /// Get the parameter value of the fill type
+    pub fn value(self) -> u8 {
+        self as u8
+    }
+
+    /// Get the fill type for a parameter value
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(FillType::Bus),
+            1 => Some(FillType::Matte),
+            _ => None,
+        }
+    }
+}
+
+/// How the keyed area is picked
+///
+/// Values this crate doesn't know decode to [`KeyType::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum KeyType {
+    /// Key out by brightness
+    Luminance,
+    /// Key out a color, e.g. a green screen
+    Chroma,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl KeyType {
+    /// All documented key types
+    pub const ALL: [KeyType; 2] = [KeyType::Luminance, KeyType::Chroma];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => KeyType::Luminance,
+            1 => KeyType::Chroma,
+            value => KeyType::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            KeyType::Luminance => 0,
+            KeyType::Chroma => 1,
+            KeyType::Other(value) => value,
+        }
+    }
+}
+
+/// Parameter of a [`KeySettings`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyField {
+    /// [`KeySettings::key_type`]
+    KeyType,
+    /// [`KeySettings::hue`]
+    Hue,
+    /// [`KeySettings::saturation`]
+    Saturation,
+    /// [`KeySettings::level`]
+    Level,
+    /// [`KeySettings::gain`]
+    Gain,
+}
+
+impl KeyField {
+    /// All fields, in the order [`KeySettings::apply`] writes them
+    pub const ALL: [KeyField; 5] = [
+        KeyField::KeyType,
+        KeyField::Hue,
+        KeyField::Saturation,
+        KeyField::Level,
+        KeyField::Gain,
+    ];
+
+    /// Get the address of the parameter
+    pub fn address(self) -> Address {
+        match self {
+            KeyField::KeyType => dsk::KEY_TYPE,
+            KeyField::Hue => dsk::KEY_HUE,
+            KeyField::Saturation => dsk::KEY_SATURATION,
+            KeyField::Level => dsk::KEY_LEVEL,
+            KeyField::Gain => dsk::KEY_GAIN,
+        }
+    }
+}
+
+/// Keyer settings, written together by [`KeySettings::apply`]
+///
+/// `hue` and `saturation` pick the key color and only matter for
+/// [`KeyType::Chroma`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeySettings {
+    /// How the keyed area is picked
+    pub key_type: KeyType,
+    /// Hue of the key color in 1/256 turns
+    pub hue: u8,
+    /// Saturation of the key color
+    pub saturation: u8,
+    /// Key threshold (0-255)
+    pub level: u8,
+    /// Softness of the key edge (0-255)
+    pub gain: u8,
+}
+
+impl KeySettings {
+    /// Write all settings in one batch
+    ///
+    /// # Returns
+    /// * `Result<Vec<(KeyField, RolandError)>, TelnetError>` - Fields the
+    ///   device rejected, with the error it reported (empty if all were
+    ///   written), or an error if the connection failed
+    pub fn apply(
+        &self,
+        client: &mut TelnetClient,
+    ) -> Result<Vec<(KeyField, RolandError)>, TelnetError> {
+        let results = client.write_parameters(&self.parameters())?;
+        Ok(KeyField::ALL
+            .into_iter()
+            .zip(results)
+            .filter_map(|(field, result)| result.err().map(|e| (field, e)))
+            .collect())
+    }
+
+    /// Read the settings the device has
+    pub fn read(client: &mut TelnetClient) -> Result<Self, TelnetError> {
+        let mut read = |field: KeyField| client.read_parameter_addr(field.address(), 1);
+        Ok(Self {
+            key_type: KeyType::from_value(read(KeyField::KeyType)?),
+            hue: read(KeyField::Hue)?,
+            saturation: read(KeyField::Saturation)?,
+            level: read(KeyField::Level)?,
+            gain: read(KeyField::Gain)?,
+        })
+    }
+
+    /// Get the address and value of each field, in [`KeyField::ALL`] order
+    pub fn parameters(&self) -> [(Address, u8); 5] {
+        KeyField::ALL.map(|field| {
+            let value = match field {
+                KeyField::KeyType => self.key_type.value(),
+                KeyField::Hue => self.hue,
+                KeyField::Saturation => self.saturation,
+                KeyField::Level => self.level,
+                KeyField::Gain => self.gain,
+            };
+            (field.address(), value)
+        })
+    }
+}
+
+/// Chroma key settings for common backdrops
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChromaPreset {
+    /// Green screen
+    GreenScreen,
+    /// Blue screen
+    BlueScreen,
+}
+
+impl ChromaPreset {
+    /// Get the key settings of the preset
+    pub fn settings(self) -> KeySettings {
+        let hue = match self {
+            ChromaPreset::GreenScreen => 85,
+            ChromaPreset::BlueScreen => 170,
+        };
+        KeySettings {
+            key_type: KeyType::Chroma,
+            hue,
+            saturation: 0xC0,
+            level: 0x30,
+            gain: 0x20,
+        }
+    }
+}
+
+/// Complete key setup, see [`Dsk::show`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DskKey {
+    /// Key source
+    pub source: VideoInput,
+    /// Luminance threshold of the key (0-255)
+    pub level: u8,
+    /// Softness of the key edge (0-255)
+    pub gain: u8,
+    /// What fills the keyed area
+    pub fill: FillType,
+}
+
+/// Downstream keyer facade
+///
+/// # Example
+/// ```no_run
+/// use roland_rs::dsk::{Dsk, DskKey, FillType};
+/// use roland_rs::video::VideoInput;
+/// use roland_rs::TelnetClient;
+/// use std::time::Duration;
+///
+/// let mut client = TelnetClient::connect("192.168.1.100", 23)?;
+/// let mut dsk = Dsk::new(&mut client);
+/// let lower_third = DskKey {
+///     source: VideoInput::Still1,
+///     level: 40,
+///     gain: 10,
+///     fill: FillType::Bus,
+/// };
+/// dsk.show(&lower_third, Some(Duration::from_millis(500)))?;
+/// # Ok::<(), roland_rs::TelnetError>(())
+/// ```
+pub struct Dsk<'a> {
+    client: &'a mut TelnetClient,
+}
+
+impl<'a> Dsk<'a> {
+    /// Create a facade for a connected client
+    pub fn new(client: &'a mut TelnetClient) -> Self {
+        Self { client }
+    }
+
+    /// Turn the DSK on or off
+    pub fn set_enabled(&mut self, enabled: bool) -> Result<(), TelnetError> {
+        self.client.write_parameter_addr(dsk::ENABLE, enabled as u8)
+    }
+
+    /// Turn the DSK on or off with a fade
+    ///
+    /// The fade time is written before the toggle, so the toggle always
+    /// uses it. If writing the time fails, the DSK isn't toggled.
+    ///
+    /// # Arguments
+    /// * `enabled` - Whether to turn the DSK on
+    /// * `fade` - Fade time, rounded to 0.1 seconds (at most 4 seconds)
+    pub fn set_enabled_with_fade(
+        &mut self,
+        enabled: bool,
+        fade: Duration,
+    ) -> Result<(), TelnetError> {
+        let tenths = fade_time_value(fade)?;
+        self.client.write_parameter_addr(dsk::FADE_TIME, tenths)?;
+        self.set_enabled(enabled)
+    }
+
+    /// Check if the DSK is on
+    pub fn is_enabled(&mut self) -> Result<bool, TelnetError> {
+        Ok(self.client.read_parameter_addr(dsk::ENABLE, 1)? != 0)
+    }
+
+    /// Select the key source
+    pub fn set_source(&mut self, input: VideoInput) -> Result<(), TelnetError> {
+        self.client.write_parameter_addr(dsk::SOURCE, input.index())
+    }
+
+    /// Set the luminance threshold of the key
+    pub fn set_key_level(&mut self, level: u8) -> Result<(), TelnetError> {
+        self.client.write_parameter_addr(dsk::KEY_LEVEL, level)
+    }
+
+    /// Set the softness of the key edge
+    pub fn set_key_gain(&mut self, gain: u8) -> Result<(), TelnetError> {
+        self.client.write_parameter_addr(dsk::KEY_GAIN, gain)
+    }
+
+    /// Select what fills the keyed area
+    pub fn set_fill_type(&mut self, fill: FillType) -> Result<(), TelnetError> {
+        self.client
+            .write_parameter_addr(dsk::FILL_TYPE, fill.value())
+    }
+
+    /// Get the fill type
+    pub fn fill_type(&mut self) -> Result<FillType, TelnetError> {
+        let value = self.client.read_parameter_addr(dsk::FILL_TYPE, 1)?;
+        FillType::from_value(value).ok_or(TelnetError::Protocol(RolandError::InvalidValue))
+    }
+
+    /// Set up the key and turn the DSK on
+    ///
+    /// Every key parameter is written before the DSK is turned on, so the
+    /// key never shows with stale settings. Stops at the first error,
+    /// leaving the DSK as it was.
+    ///
+    /// # Arguments
+    /// * `key` - Key setup
+    /// * `fade` - Optional fade time for turning the DSK on
+    pub fn show(&mut self, key: &DskKey, fade: Option<Duration>) -> Result<(), TelnetError> {
+        // Validate before sending anything
+        let fade = fade.map(fade_time_value).transpose()?;
+
+        self.set_source(key.source)?;
+        self.set_key_level(key.level)?;
+        self.set_key_gain(key.gain)?;
+        self.set_fill_type(key.fill)?;
+        if let Some(tenths) = fade {
+            self.client.write_parameter_addr(dsk::FADE_TIME, tenths)?;
+        }
+        self.set_enabled(true)
+    }
+
+    /// Key out a green or blue screen behind a source
+    ///
+    /// The source and the preset's [`KeySettings`] are written in one
+    /// batch, and the DSK is only turned on if all of them were accepted.
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or a `Device` error for the
+    ///   first parameter the device rejected (the DSK is left as it was;
+    ///   read the settings back with [`KeySettings::read`])
+    pub fn enable_chroma_key(
+        &mut self,
+        source: VideoInput,
+        preset: ChromaPreset,
+    ) -> Result<(), TelnetError> {
+        let mut params = vec![(dsk::SOURCE, source.index())];
+        params.extend(preset.settings().parameters());
+        let results = self.client.write_parameters(&params)?;
+        for (&(address, value), result) in params.iter().zip(results) {
+            if let Err(e) = result {
+                let command = Command::write_parameter(address, value);
+                return Err(TelnetError::device(&command, e));
+            }
+        }
+        self.set_enabled(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::Command;
+
+    fn encoded(commands: Vec<Command>) -> Vec<String> {
+        commands.iter().map(Command::encode).collect()
+    }
+
+    const KEY: DskKey = DskKey {
+        source: VideoInput::Still2,
+        level: 0x40,
+        gain: 0x10,
+        fill: FillType::Matte,
+    };
+
+    #[test]
+    fn test_show_writes_key_before_enable() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut dsk = Dsk::new(&mut client);
+
+        dsk.show(&KEY, Some(Duration::from_millis(500))).unwrap();
+        assert_eq!(
+            encoded(mock.received()),
+            vec![
+                "DTH:030001,05;",
+                "DTH:030002,40;",
+                "DTH:030003,10;",
+                "DTH:030004,01;",
+                "DTH:030005,05;",
+                "DTH:030000,01;",
+            ]
+        );
+        assert!(dsk.is_enabled().unwrap());
+        assert_eq!(dsk.fill_type().unwrap(), FillType::Matte);
+    }
+
+    #[test]
+    fn test_show_stops_on_error() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_address_error(dsk::KEY_GAIN, RolandError::Invalid);
+        let mut client = connect(addr);
+        let mut dsk = Dsk::new(&mut client);
+
+        assert!(dsk.show(&KEY, None).is_err());
+        assert_eq!(mock.received().len(), 3);
+        assert_eq!(mock.parameter(dsk::ENABLE), None);
+
+        // Invalid fade time is caught before anything is sent
+        mock.clear_received();
+        assert!(matches!(
+            dsk.show(&KEY, Some(Duration::from_secs(5))),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+        assert!(mock.received().is_empty());
+    }
+
+    #[test]
+    fn test_toggle_with_fade() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut dsk = Dsk::new(&mut client);
+
+        dsk.set_enabled_with_fade(false, Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(
+            encoded(mock.received()),
+            vec!["DTH:030005,0A;", "DTH:030000,00;"]
+        );
+    }
+
+    #[test]
+    fn test_key_type_values() {
+        assert_eq!(KeyType::ALL.map(KeyType::value), [0, 1]);
+        for value in 0..=255 {
+            assert_eq!(KeyType::from_value(value).value(), value);
+        }
+        assert_eq!(KeyType::from_value(2), KeyType::Other(2));
+    }
+
+    #[test]
+    fn test_green_screen_preset() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut dsk = Dsk::new(&mut client);
+
+        dsk.enable_chroma_key(VideoInput::Hdmi2, ChromaPreset::GreenScreen)
+            .unwrap();
+        assert_eq!(
+            encoded(mock.received()),
+            vec![
+                "DTH:030001,01;",
+                "DTH:030006,01;",
+                "DTH:030007,55;",
+                "DTH:030008,C0;",
+                "DTH:030002,30;",
+                "DTH:030003,20;",
+                "DTH:030000,01;",
+            ]
+        );
+        assert_eq!(
+            KeySettings::read(&mut client).unwrap(),
+            ChromaPreset::GreenScreen.settings()
+        );
+    }
+
+    #[test]
+    fn test_apply_reports_failed_fields() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(dsk::KEY_TYPE, KeyType::Luminance.value());
+        mock.inject_error(RolandError::Invalid);
+        let mut client = connect(addr);
+
+        let settings = ChromaPreset::BlueScreen.settings();
+        let failed = settings.apply(&mut client).unwrap();
+        assert_eq!(failed, vec![(KeyField::KeyType, RolandError::Invalid)]);
+        // The other fields were still written
+        let actual = KeySettings::read(&mut client).unwrap();
+        assert_eq!(
+            actual,
+            KeySettings {
+                key_type: KeyType::Luminance,
+                ..settings
+            }
+        );
+
+        mock.set_address_error(dsk::KEY_SATURATION, RolandError::OutOfRange);
+        mock.clear_received();
+        let err = Dsk::new(&mut client)
+            .enable_chroma_key(VideoInput::Hdmi1, ChromaPreset::BlueScreen)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Device rejected DTH:030008,C0: Parameter out of range"
+        );
+        assert_eq!(mock.parameter(dsk::ENABLE), None);
+    }
+}
@@ -0,0 +1,193 @@
+//! Suppression of echoed commands
+//!
+//! A device with local echo turned on in its Telnet settings sends every
+//! command back as soon as it receives it, before the answer. The echo of
+//! a read doesn't parse as an answer, and the echo of a write looks like
+//! a parameter change, so the demultiplexer drops echoes before anything
+//! else sees the frame.
+//!
+//! Every command sent is kept until it is answered. A frame equal to the
+//! oldest command not echoed yet is its echo; an ACK, ERR or version, or
+//! the data a read asked for, answers the oldest command. A device without
+//! echo just answers, so nothing is dropped for it.
+//!
+//! When [`crate::ParseOptions::write_answer`] takes a DTH as the answer to
+//! a write, the echo of a write can't be told apart from that answer, so
+//! it isn't dropped; don't combine the two.
+//!
+//! While subscribed, the echo of a write to a watched address still
+//! reaches the subscription callback, which runs before the client sees
+//! the frame.
+
+use crate::TelnetClient;
+use roland_core::engine::answers_with;
+use roland_core::{Command, WriteAnswer};
+use std::collections::VecDeque;
+
+/// Commands sent and not answered yet
+#[derive(Debug)]
+pub(crate) struct EchoFilter {
+    enabled: bool,
+    /// Oldest first
+    pending: VecDeque<Pending>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    /// `None` for bytes sent with [`TelnetClient::send_raw`] that don't
+    /// parse as a command
+    command: Option<Command>,
+    encoded: String,
+    echoed: bool,
+}
+
+impl Default for EchoFilter {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl EchoFilter {
+    /// Remember a command just sent
+    pub(crate) fn sent(&mut self, command: Option<&Command>, encoded: String) {
+        if self.enabled {
+            self.pending.push_back(Pending {
+                command: command.cloned(),
+                encoded,
+                echoed: false,
+            });
+        }
+    }
+
+    /// Forget the commands sent so far
+    pub(crate) fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+impl TelnetClient {
+    /// Check if echoed commands are dropped
+    pub fn suppress_echo(&self) -> bool {
+        self.echo.enabled
+    }
+
+    /// Drop commands the device echoes back
+    ///
+    /// On by default: it costs nothing for a device without echo, while
+    /// with echo and suppression off every read fails with a malformed
+    /// answer and every write queues a spurious
+    /// [`crate::DeviceEvent::ParameterChanged`].
+    pub fn set_suppress_echo(&mut self, suppress: bool) {
+        self.echo.enabled = suppress;
+        self.echo.clear();
+    }
+
+    /// Check if `frame` is the echo of a command sent, and track answers
+    pub(crate) fn take_echo(&mut self, frame: &str) -> bool {
+        if self.echo.pending.is_empty() {
+            return false;
+        }
+        let frame = frame.trim_start_matches('\x02').trim();
+        // A write may be answered with a DTH just like its echo; then the
+        // DTH is the answer
+        let dth_answers = self.parse_options.write_answer != WriteAnswer::AckOnly;
+        if let Some(pending) = self.echo.pending.iter_mut().find(|p| !p.echoed) {
+            let write = matches!(
+                pending.command,
+                Some(Command::WriteParameter { .. } | Command::WriteBlock { .. })
+            );
+            if pending.encoded == frame && !(write && dth_answers) {
+                pending.echoed = true;
+                return true;
+            }
+        }
+
+        let oldest = &self.echo.pending[0].command;
+        let answered = match (oldest, self.parse(frame)) {
+            (Some(oldest), Ok(response)) => answers_with(oldest, &response, self.parse_options),
+            (Some(_), Err(_)) => false,
+            // Anything but a parameter change may answer unknown bytes
+            (None, Ok(response)) => response.as_data().is_none() && !response.is_flow_control(),
+            (None, Err(_)) => true,
+        };
+        if answered {
+            self.echo.pending.pop_front();
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::{connect, MockDevice};
+    use crate::{offset, DeviceEvent};
+    use roland_core::Address;
+
+    #[test]
+    fn test_echo_suppressed() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_echo(true);
+        let address = Address::new(0x01, 0x00, 0x00);
+        let mut client = connect(addr);
+        assert!(client.suppress_echo());
+
+        assert_eq!(client.get_version().unwrap().0, "VR-6HD");
+        client.write_parameter_addr(address, 0x03).unwrap();
+        assert_eq!(client.read_parameter_addr(address, 1).unwrap(), 0x03);
+        assert_eq!(
+            client.read_parameter_bytes(address, 2).unwrap(),
+            [0x03, 0x00]
+        );
+
+        // Batches and nowait writes have several commands in flight
+        let writes: Vec<_> = (0..4).map(|i| (offset(address, i), i as u8)).collect();
+        let results = client.write_parameters(&writes).unwrap();
+        assert!(results.iter().all(Result::is_ok));
+        for (address, value) in writes {
+            client.write_parameter_nowait(address, value).unwrap();
+        }
+        client.wait_write_acks().unwrap();
+        assert_eq!(client.poll_event().unwrap(), None);
+        assert!(client.echo.pending.is_empty());
+    }
+
+    #[test]
+    fn test_echo_not_suppressed() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_echo(true);
+        let address = Address::new(0x01, 0x00, 0x00);
+        let mut client = connect(addr);
+        client.set_suppress_echo(false);
+
+        // The echo of a write shows up as a change, the echo of a read as
+        // a malformed answer
+        client.write_parameter_addr(address, 0x04).unwrap();
+        assert_eq!(
+            client.events().collect::<Vec<_>>(),
+            [DeviceEvent::ParameterChanged {
+                address,
+                value: 0x04
+            }]
+        );
+        assert!(client.read_parameter_addr(address, 1).is_err());
+    }
+
+    #[test]
+    fn test_no_echo() {
+        let (addr, mock) = MockDevice::spawn();
+        let address = Address::new(0x01, 0x00, 0x00);
+        mock.set_parameter(address, 0x07);
+        let mut client = connect(addr);
+
+        // A change equal to an earlier write is still a change
+        client.write_parameter_addr(address, 0x07).unwrap();
+        assert!(client.echo.pending.is_empty());
+        mock.send_unsolicited(address, 0x07);
+        client.read_parameter_addr(offset(address, 1), 1).unwrap();
+        assert_eq!(client.events().count(), 1);
+        assert_eq!(client.read_parameter_addr(address, 1).unwrap(), 0x07);
+    }
+}
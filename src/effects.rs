@@ -0,0 +1,396 @@
+//! Audio input effects: EQ, compressor and noise gate
+//!
+//! Each settings struct covers one effect of an input channel. `apply`
+//! validates every field before sending anything and then writes all of
+//! them in one batch, so an invalid value never leaves a channel
+//! half-configured. `read` reads the settings back.
+//!
+//! Values are converted to device bytes as follows:
+//!
+//! | Field | Range | Step |
+//! |-------|-------|------|
+//! | EQ gains | -15 to +15 dB | 0.5 dB |
+//! | EQ mid frequency | 200 Hz to 8 kHz | 1/3 octave |
+//! | Compressor threshold | -40 to 0 dB | 1 dB |
+//! | Compressor ratio | 1:1 to 16:1 | 1 |
+//! | Compressor attack | 0 to 100 ms | 1 ms |
+//! | Compressor and gate release | 10 to 1000 ms | 10 ms |
+//! | Compressor makeup gain | 0 to +24 dB | 1 dB |
+//! | Gate threshold | -80 to 0 dB | 1 dB |
+//!
+//! Values between steps are rounded to the nearest step; values outside
+//! the range fail with `OutOfRange`, and bytes read back outside it with
+//! `InvalidValue`. The scales are [`crate::units::ParamScale`]s.
+
+use crate::audio::{AudioChannel, Db};
+use crate::units::{Millis, ParamScale};
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::audio;
+use roland_core::RolandError;
+use std::time::Duration;
+
+const EQ_GAIN: ParamScale = ParamScale::linear(-15.0, 15.0, 0, 60);
+const COMP_THRESHOLD: ParamScale = ParamScale::linear(-40.0, 0.0, 0, 40);
+const MAKEUP_GAIN: ParamScale = ParamScale::linear(0.0, 24.0, 0, 24);
+const GATE_THRESHOLD: ParamScale = ParamScale::linear(-80.0, 0.0, 0, 80);
+const ATTACK: ParamScale = ParamScale::linear(0.0, 100.0, 0, 100);
+const RELEASE: ParamScale = ParamScale::linear(10.0, 1000.0, 1, 100);
+
+/// EQ mid band frequencies in Hz, indexed by parameter value
+const MID_FREQUENCIES: [u32; 17] = [
+    200, 250, 315, 400, 500, 630, 800, 1000, 1250, 1600, 2000, 2500, 3150, 4000, 5000, 6300, 8000,
+];
+
+/// Frequency in hertz
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hz(pub u32);
+
+/// Three band EQ of an input channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqSettings {
+    /// Low band gain
+    pub low_gain: Db,
+    /// Mid band gain
+    pub mid_gain: Db,
+    /// Mid band center frequency
+    pub mid_freq: Hz,
+    /// High band gain
+    pub high_gain: Db,
+}
+
+impl EqSettings {
+    /// Write the settings to an input channel
+    pub fn apply(
+        &self,
+        client: &mut TelnetClient,
+        channel: AudioChannel,
+    ) -> Result<(), TelnetError> {
+        let values = [
+            (audio::EQ_LOW_GAIN, encode_eq_gain(self.low_gain)?),
+            (audio::EQ_MID_GAIN, encode_eq_gain(self.mid_gain)?),
+            (audio::EQ_MID_FREQ, encode_frequency(self.mid_freq)?),
+            (audio::EQ_HIGH_GAIN, encode_eq_gain(self.high_gain)?),
+        ];
+        write_group(client, channel, &values)
+    }
+
+    /// Read the settings of an input channel
+    pub fn read(client: &mut TelnetClient, channel: AudioChannel) -> Result<Self, TelnetError> {
+        let [low, mid, freq, high] = read_group(
+            client,
+            channel,
+            [
+                audio::EQ_LOW_GAIN,
+                audio::EQ_MID_GAIN,
+                audio::EQ_MID_FREQ,
+                audio::EQ_HIGH_GAIN,
+            ],
+        )?;
+        Ok(Self {
+            low_gain: Db::from_device_byte(low, &EQ_GAIN)?,
+            mid_gain: Db::from_device_byte(mid, &EQ_GAIN)?,
+            mid_freq: decode_frequency(freq)?,
+            high_gain: Db::from_device_byte(high, &EQ_GAIN)?,
+        })
+    }
+}
+
+/// Compressor of an input channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressorSettings {
+    /// Compressor on/off
+    pub enabled: bool,
+    /// Level above which the signal is compressed
+    pub threshold: Db,
+    /// Compression ratio n:1 (1-16)
+    pub ratio: u8,
+    /// Attack time
+    pub attack: Duration,
+    /// Release time
+    pub release: Duration,
+    /// Makeup gain
+    pub makeup_gain: Db,
+}
+
+impl CompressorSettings {
+    /// Write the settings to an input channel
+    pub fn apply(
+        &self,
+        client: &mut TelnetClient,
+        channel: AudioChannel,
+    ) -> Result<(), TelnetError> {
+        if !(1..=16).contains(&self.ratio) {
+            return Err(TelnetError::Protocol(RolandError::OutOfRange));
+        }
+        let values = [
+            (audio::COMP_ENABLE, self.enabled as u8),
+            (
+                audio::COMP_THRESHOLD,
+                self.threshold.to_device_byte(&COMP_THRESHOLD)?,
+            ),
+            (audio::COMP_RATIO, self.ratio),
+            (audio::COMP_ATTACK, encode_attack(self.attack)?),
+            (audio::COMP_RELEASE, encode_release(self.release)?),
+            (
+                audio::COMP_GAIN,
+                self.makeup_gain.to_device_byte(&MAKEUP_GAIN)?,
+            ),
+        ];
+        write_group(client, channel, &values)
+    }
+
+    /// Read the settings of an input channel
+    pub fn read(client: &mut TelnetClient, channel: AudioChannel) -> Result<Self, TelnetError> {
+        let [enabled, threshold, ratio, attack, release, gain] = read_group(
+            client,
+            channel,
+            [
+                audio::COMP_ENABLE,
+                audio::COMP_THRESHOLD,
+                audio::COMP_RATIO,
+                audio::COMP_ATTACK,
+                audio::COMP_RELEASE,
+                audio::COMP_GAIN,
+            ],
+        )?;
+        Ok(Self {
+            enabled: enabled != 0,
+            threshold: Db::from_device_byte(threshold, &COMP_THRESHOLD)?,
+            ratio,
+            attack: decode_millis(attack, &ATTACK)?,
+            release: decode_millis(release, &RELEASE)?,
+            makeup_gain: Db::from_device_byte(gain, &MAKEUP_GAIN)?,
+        })
+    }
+}
+
+/// Noise gate of an input channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GateSettings {
+    /// Gate on/off
+    pub enabled: bool,
+    /// Level below which the signal is muted
+    pub threshold: Db,
+    /// Release time
+    pub release: Duration,
+}
+
+impl GateSettings {
+    /// Write the settings to an input channel
+    pub fn apply(
+        &self,
+        client: &mut TelnetClient,
+        channel: AudioChannel,
+    ) -> Result<(), TelnetError> {
+        let values = [
+            (audio::GATE_ENABLE, self.enabled as u8),
+            (
+                audio::GATE_THRESHOLD,
+                self.threshold.to_device_byte(&GATE_THRESHOLD)?,
+            ),
+            (audio::GATE_RELEASE, encode_release(self.release)?),
+        ];
+        write_group(client, channel, &values)
+    }
+
+    /// Read the settings of an input channel
+    pub fn read(client: &mut TelnetClient, channel: AudioChannel) -> Result<Self, TelnetError> {
+        let [enabled, threshold, release] = read_group(
+            client,
+            channel,
+            [
+                audio::GATE_ENABLE,
+                audio::GATE_THRESHOLD,
+                audio::GATE_RELEASE,
+            ],
+        )?;
+        Ok(Self {
+            enabled: enabled != 0,
+            threshold: Db::from_device_byte(threshold, &GATE_THRESHOLD)?,
+            release: decode_millis(release, &RELEASE)?,
+        })
+    }
+}
+
+/// Write parameter values of an input channel in one batch
+fn write_group(
+    client: &mut TelnetClient,
+    channel: AudioChannel,
+    values: &[(u8, u8)],
+) -> Result<(), TelnetError> {
+    if channel.is_bus() {
+        return Err(TelnetError::Protocol(RolandError::Invalid));
+    }
+    let params: Vec<_> = values
+        .iter()
+        .map(|&(offset, value)| (channel.address(offset), value))
+        .collect();
+    for result in client.write_parameters(&params)? {
+        result?;
+    }
+    Ok(())
+}
+
+/// Read parameter values of an input channel
+fn read_group<const N: usize>(
+    client: &mut TelnetClient,
+    channel: AudioChannel,
+    offsets: [u8; N],
+) -> Result<[u8; N], TelnetError> {
+    if channel.is_bus() {
+        return Err(TelnetError::Protocol(RolandError::Invalid));
+    }
+    let mut values = [0; N];
+    for (value, offset) in values.iter_mut().zip(offsets) {
+        *value = client.read_parameter_addr(channel.address(offset), 1)?;
+    }
+    Ok(values)
+}
+
+fn encode_eq_gain(gain: Db) -> Result<u8, TelnetError> {
+    Ok(gain.to_device_byte(&EQ_GAIN)?)
+}
+
+/// Encode a frequency as the index of the nearest 1/3 octave step
+fn encode_frequency(freq: Hz) -> Result<u8, TelnetError> {
+    let (min, max) = (
+        MID_FREQUENCIES[0],
+        MID_FREQUENCIES[MID_FREQUENCIES.len() - 1],
+    );
+    if !(min..=max).contains(&freq.0) {
+        return Err(TelnetError::Protocol(RolandError::OutOfRange));
+    }
+    let distance = |step: u32| (freq.0 as f64 / step as f64).ln().abs();
+    let index = (0..MID_FREQUENCIES.len())
+        .min_by(|&a, &b| distance(MID_FREQUENCIES[a]).total_cmp(&distance(MID_FREQUENCIES[b])))
+        .unwrap_or(0);
+    Ok(index as u8)
+}
+
+fn decode_frequency(value: u8) -> Result<Hz, TelnetError> {
+    MID_FREQUENCIES
+        .get(value as usize)
+        .map(|&freq| Hz(freq))
+        .ok_or(TelnetError::Protocol(RolandError::InvalidValue))
+}
+
+/// Encode an attack time (0-100 ms in 1 ms steps)
+fn encode_attack(attack: Duration) -> Result<u8, TelnetError> {
+    Ok(ATTACK.encode(attack.as_secs_f32() * 1000.0)?)
+}
+
+/// Encode a release time (10-1000 ms in 10 ms steps)
+fn encode_release(release: Duration) -> Result<u8, TelnetError> {
+    Ok(RELEASE.encode(release.as_secs_f32() * 1000.0)?)
+}
+
+fn decode_millis(value: u8, scale: &ParamScale) -> Result<Duration, TelnetError> {
+    let Millis(ms) = Millis::from_device_byte(value, scale)?;
+    Ok(Duration::from_millis(ms as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(encode_eq_gain(Db(-15.0)).unwrap(), 0);
+        assert_eq!(encode_eq_gain(Db::ZERO).unwrap(), 30);
+        assert_eq!(encode_eq_gain(Db(15.0)).unwrap(), 60);
+        assert!(encode_eq_gain(Db(15.1)).is_err());
+        assert!(encode_eq_gain(Db(f32::NAN)).is_err());
+
+        assert_eq!(encode_frequency(Hz(200)).unwrap(), 0);
+        assert_eq!(encode_frequency(Hz(1100)).unwrap(), 7);
+        assert_eq!(encode_frequency(Hz(8000)).unwrap(), 16);
+        assert!(encode_frequency(Hz(199)).is_err());
+        assert!(decode_frequency(17).is_err());
+
+        assert_eq!(encode_release(Duration::from_millis(10)).unwrap(), 1);
+        assert_eq!(encode_release(Duration::from_secs(1)).unwrap(), 100);
+        assert!(encode_release(Duration::from_millis(5)).is_err());
+        assert!(encode_attack(Duration::from_millis(101)).is_err());
+    }
+
+    #[test]
+    fn test_eq_round_trip() {
+        let (addr, _mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let eq = EqSettings {
+            low_gain: Db(-3.5),
+            mid_gain: Db(2.0),
+            mid_freq: Hz(2500),
+            high_gain: Db(15.0),
+        };
+        eq.apply(&mut client, AudioChannel::Ch2).unwrap();
+        assert_eq!(
+            EqSettings::read(&mut client, AudioChannel::Ch2).unwrap(),
+            eq
+        );
+    }
+
+    #[test]
+    fn test_dynamics_round_trip() {
+        let (addr, _mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let compressor = CompressorSettings {
+            enabled: true,
+            threshold: Db(-18.0),
+            ratio: 4,
+            attack: Duration::from_millis(5),
+            release: Duration::from_millis(250),
+            makeup_gain: Db(6.0),
+        };
+        compressor.apply(&mut client, AudioChannel::Usb).unwrap();
+        assert_eq!(
+            CompressorSettings::read(&mut client, AudioChannel::Usb).unwrap(),
+            compressor
+        );
+
+        let gate = GateSettings {
+            enabled: true,
+            threshold: Db(-60.0),
+            release: Duration::from_millis(100),
+        };
+        gate.apply(&mut client, AudioChannel::Ch5).unwrap();
+        assert_eq!(
+            GateSettings::read(&mut client, AudioChannel::Ch5).unwrap(),
+            gate
+        );
+    }
+
+    #[test]
+    fn test_invalid_settings_send_nothing() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let compressor = CompressorSettings {
+            enabled: true,
+            threshold: Db(-18.0),
+            ratio: 4,
+            attack: Duration::from_millis(5),
+            release: Duration::from_millis(250),
+            // Last field out of range
+            makeup_gain: Db(30.0),
+        };
+        assert!(matches!(
+            compressor.apply(&mut client, AudioChannel::Ch1),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+
+        let gate = GateSettings {
+            enabled: true,
+            threshold: Db(-60.0),
+            release: Duration::from_millis(100),
+        };
+        assert!(matches!(
+            gate.apply(&mut client, AudioChannel::Main),
+            Err(TelnetError::Protocol(RolandError::Invalid))
+        ));
+        assert!(mock.received().is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+//! Unsolicited device events
+//!
+//! A [`TelnetClient`](crate::TelnetClient) queues events until they are
+//! drained with `poll_event` or `events`. A
+//! [`SharedClient`](crate::shared::SharedClient) can instead feed them to
+//! [`EventStream`]s from its worker thread, see
+//! [`SharedClient::event_stream`](crate::shared::SharedClient::event_stream).
+//!
+//! # Backpressure
+//!
+//! Each stream holds at most the number of events it was opened with.
+//! When a slow reader lets it fill up, the oldest event is dropped to make
+//! room, so the worker never blocks and the stream always ends with the
+//! latest state. [`EventStream::dropped`] counts the events lost that way.
+
+use crate::tally::TallyState;
+use crate::transport::TransportStatus;
+use roland_core::Address;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Event pushed by the device without being requested
+///
+/// The VR-6HD sends a DTH frame whenever a parameter changes on the
+/// device itself, e.g. when an operator moves a fader on the panel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    /// A parameter changed on the device
+    ParameterChanged {
+        /// SysEx address
+        address: Address,
+        /// New parameter value
+        value: u8,
+    },
+    /// Program or preview tally changed
+    ///
+    /// Queued right after the [`DeviceEvent::ParameterChanged`] of the
+    /// tally parameter.
+    TallyChanged(TallyState),
+    /// Streaming, recording or the SD card changed
+    ///
+    /// Queued right after the [`DeviceEvent::ParameterChanged`] of the
+    /// profile's `transport.status` parameter. The status byte doesn't
+    /// carry the elapsed time, so `elapsed` is always `None`; read it with
+    /// [`TelnetClient::recording_elapsed`](crate::TelnetClient::recording_elapsed).
+    TransportChanged(TransportStatus),
+    /// The device closed the connection or it failed
+    ///
+    /// Sent once by a [`SharedClient`](crate::shared::SharedClient)'s
+    /// worker when it notices.
+    ConnectionLost,
+    /// A new connection replaced a lost one, see
+    /// [`SharedClient::reconnect`](crate::shared::SharedClient::reconnect)
+    Reconnected,
+}
+
+/// Receiving end of a bounded event queue
+///
+/// See the [module documentation](self) for what happens when it fills
+/// up. The stream ends once the worker feeding it stops.
+pub struct EventStream {
+    queue: Arc<EventQueue>,
+}
+
+pub(crate) struct EventQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+struct QueueState {
+    events: VecDeque<DeviceEvent>,
+    dropped: u64,
+    closed: bool,
+}
+
+impl EventStream {
+    /// Open a stream and get the queue to feed it through
+    ///
+    /// A capacity of 0 is taken as 1.
+    pub(crate) fn new(capacity: usize) -> (Self, Arc<EventQueue>) {
+        let queue = Arc::new(EventQueue {
+            state: Mutex::new(QueueState {
+                events: VecDeque::new(),
+                dropped: 0,
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            capacity: capacity.max(1),
+        });
+        (
+            Self {
+                queue: queue.clone(),
+            },
+            queue,
+        )
+    }
+
+    /// Wait for the next event
+    ///
+    /// Returns `None` once the stream has ended and is empty.
+    pub fn recv(&self) -> Option<DeviceEvent> {
+        let mut state = self.queue.state();
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Some(event);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.queue.ready.wait(state).unwrap();
+        }
+    }
+
+    /// Wait up to `timeout` for the next event
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<DeviceEvent> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.queue.state();
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Some(event);
+            }
+            let now = Instant::now();
+            if state.closed || now >= deadline {
+                return None;
+            }
+            state = self
+                .queue
+                .ready
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Get the next event without waiting
+    pub fn try_recv(&self) -> Option<DeviceEvent> {
+        self.queue.state().events.pop_front()
+    }
+
+    /// Get the number of events dropped because the stream was full
+    pub fn dropped(&self) -> u64 {
+        self.queue.state().dropped
+    }
+}
+
+impl Iterator for EventStream {
+    type Item = DeviceEvent;
+
+    /// Wait for the next event, see [`EventStream::recv`]
+    fn next(&mut self) -> Option<DeviceEvent> {
+        self.recv()
+    }
+}
+
+impl EventQueue {
+    fn state(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Add an event, dropping the oldest one if the queue is full
+    pub(crate) fn push(&self, event: DeviceEvent) {
+        let mut state = self.state();
+        if state.events.len() == self.capacity {
+            state.events.pop_front();
+            state.dropped += 1;
+        }
+        state.events.push_back(event);
+        self.ready.notify_all();
+    }
+
+    /// End the stream; queued events can still be received
+    pub(crate) fn close(&self) {
+        self.state().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed(value: u8) -> DeviceEvent {
+        DeviceEvent::ParameterChanged {
+            address: Address::new(0x05, 0x00, 0x00),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_drop_oldest_when_full() {
+        let (stream, queue) = EventStream::new(2);
+        for value in 0..5 {
+            queue.push(changed(value));
+        }
+        assert_eq!(stream.dropped(), 3);
+        assert_eq!(stream.try_recv(), Some(changed(3)));
+        assert_eq!(stream.try_recv(), Some(changed(4)));
+        assert_eq!(stream.try_recv(), None);
+    }
+
+    #[test]
+    fn test_end_of_stream() {
+        let (stream, queue) = EventStream::new(8);
+        assert_eq!(stream.recv_timeout(Duration::from_millis(10)), None);
+
+        queue.push(DeviceEvent::ConnectionLost);
+        queue.close();
+        assert_eq!(stream.collect::<Vec<_>>(), [DeviceEvent::ConnectionLost]);
+    }
+}
@@ -0,0 +1,151 @@
+//! Output fade (fade to black / fade audio)
+
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::output;
+use roland_core::RolandError;
+use std::time::Duration;
+
+/// Longest fade time the device supports
+pub const MAX_FADE_TIME: Duration = Duration::from_secs(4);
+
+/// What the output fade affects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FadeMode {
+    /// Fade video and audio together
+    Both,
+    /// Fade video only
+    VideoOnly,
+    /// Fade audio only
+    AudioOnly,
+}
+
+impl FadeMode {
+    /// Get the parameter value of the mode
+    pub fn value(self) -> u8 {
+        self as u8
+    }
+
+    /// Get the mode for a parameter value
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(FadeMode::Both),
+            1 => Some(FadeMode::VideoOnly),
+            2 => Some(FadeMode::AudioOnly),
+            _ => None,
+        }
+    }
+}
+
+impl TelnetClient {
+    /// Fade the output out (to black and/or silence)
+    ///
+    /// Sets the fade time, then triggers the fade. If setting the time
+    /// fails, the fade isn't triggered. Returns once the device has
+    /// acknowledged the trigger, not when the fade has finished.
+    ///
+    /// # Arguments
+    /// * `duration` - Fade time, rounded to 0.1 seconds (at most 4 seconds)
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `OutOfRange` for a duration
+    ///   above [`MAX_FADE_TIME`] (nothing is sent then)
+    pub fn fade_out(&mut self, duration: Duration) -> Result<(), TelnetError> {
+        self.fade(1, duration)
+    }
+
+    /// Fade the output back in
+    ///
+    /// Works like [`TelnetClient::fade_out`].
+    ///
+    /// # Arguments
+    /// * `duration` - Fade time, rounded to 0.1 seconds (at most 4 seconds)
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `OutOfRange` for a duration
+    ///   above [`MAX_FADE_TIME`] (nothing is sent then)
+    pub fn fade_in(&mut self, duration: Duration) -> Result<(), TelnetError> {
+        self.fade(0, duration)
+    }
+
+    /// Select what the output fade affects
+    pub fn set_output_fade_mode(&mut self, mode: FadeMode) -> Result<(), TelnetError> {
+        self.write_parameter_addr(output::FADE_MODE, mode.value())
+    }
+
+    /// Get what the output fade affects
+    pub fn output_fade_mode(&mut self) -> Result<FadeMode, TelnetError> {
+        let value = self.read_parameter_addr(output::FADE_MODE, 1)?;
+        FadeMode::from_value(value).ok_or(TelnetError::Protocol(RolandError::InvalidValue))
+    }
+
+    fn fade(&mut self, target: u8, duration: Duration) -> Result<(), TelnetError> {
+        let tenths = fade_time_value(duration)?;
+        self.write_parameter_addr(output::FADE_TIME, tenths)?;
+        self.write_parameter_addr(output::FADE, target)
+    }
+}
+
+/// Convert a fade time to its parameter value (0.1 second steps)
+///
+/// Returns `OutOfRange` for times above [`MAX_FADE_TIME`].
+pub(crate) fn fade_time_value(duration: Duration) -> Result<u8, TelnetError> {
+    if duration > MAX_FADE_TIME {
+        return Err(TelnetError::Protocol(RolandError::OutOfRange));
+    }
+    Ok(((duration.as_millis() + 50) / 100) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::Command;
+
+    #[test]
+    fn test_fade_sequence() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        client.set_output_fade_mode(FadeMode::AudioOnly).unwrap();
+        client.fade_out(Duration::from_millis(2500)).unwrap();
+        client.fade_in(Duration::ZERO).unwrap();
+        assert_eq!(client.output_fade_mode().unwrap(), FadeMode::AudioOnly);
+
+        let encoded: Vec<String> = mock.received().iter().map(Command::encode).collect();
+        assert_eq!(
+            encoded,
+            vec![
+                "DTH:061002,02;",
+                "DTH:061001,19;",
+                "DTH:061000,01;",
+                "DTH:061001,00;",
+                "DTH:061000,00;",
+                "RQH:061002,000001;",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fade_errors() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        assert!(matches!(
+            client.fade_out(Duration::from_millis(4100)),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+        assert!(mock.received().is_empty());
+
+        // A failed time update stops before the trigger
+        mock.set_address_error(output::FADE_TIME, RolandError::Invalid);
+        assert!(matches!(
+            client.fade_out(Duration::from_secs(1)),
+            Err(TelnetError::Device {
+                error: RolandError::Invalid,
+                ..
+            })
+        ));
+        assert_eq!(mock.received().len(), 1);
+        assert_eq!(mock.parameter(output::FADE), None);
+    }
+}
@@ -0,0 +1,389 @@
+//! Fader glides
+//!
+//! A fader jumping from -40 dB to 0 dB pops. A glide, started with
+//! [`AudioMixer::glide_fader`](crate::audio::AudioMixer::glide_fader),
+//! moves the fader there in steps instead, evenly spaced in dB so the
+//! change sounds even. The steps are written without waiting for their
+//! ACKs by [`TelnetClient::run_glides`], which the application calls in
+//! its loop, or by [`TelnetClient::wait_glides`]. A
+//! [`SharedClient`](crate::shared::SharedClient) runs them on its own.
+
+use crate::audio::Db;
+use crate::cancel::CancellationToken;
+use crate::{TelnetClient, TelnetError};
+use roland_core::{Address, RolandError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Default number of glide steps per second
+pub const DEFAULT_GLIDE_RATE: u32 = 30;
+
+/// Handle to a running glide
+///
+/// Dropping the handle doesn't stop the glide.
+#[derive(Debug, Clone)]
+pub struct GlideHandle {
+    finished: Arc<AtomicBool>,
+}
+
+impl GlideHandle {
+    /// Stop the glide, leaving the fader at the last level written
+    pub fn cancel(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+    }
+
+    /// Check if the glide reached its target, was cancelled or was
+    /// replaced by another glide on the same fader
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+}
+
+/// Glide in progress
+#[derive(Debug)]
+struct Glide {
+    address: Address,
+    /// Level in dB at the start, at least [`Db::MIN`]
+    from: f32,
+    target: Db,
+    start: Instant,
+    duration: Duration,
+    steps: u32,
+    /// Steps done so far
+    done: u32,
+    /// Level of the last step done
+    level: Db,
+    finished: Arc<AtomicBool>,
+}
+
+impl Glide {
+    /// Get the level after `step` steps
+    fn level_at(&self, step: u32) -> Db {
+        if step >= self.steps {
+            return self.target;
+        }
+        let to = self.target.value().max(Db::MIN.value());
+        Db(self.from + (to - self.from) * step as f32 / self.steps as f32)
+    }
+
+    /// Get the number of steps due at `now`
+    fn due(&self, now: Instant) -> u32 {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= self.duration {
+            return self.steps;
+        }
+        (elapsed.as_secs_f64() / self.duration.as_secs_f64() * self.steps as f64) as u32
+    }
+
+    /// Get when the next step is due
+    fn next_due(&self) -> Instant {
+        self.start
+            + self
+                .duration
+                .mul_f64((self.done + 1) as f64 / self.steps as f64)
+    }
+}
+
+/// Glides of one client
+#[derive(Debug)]
+pub(crate) struct Glides {
+    glides: Vec<Glide>,
+    rate: u32,
+}
+
+impl Default for Glides {
+    fn default() -> Self {
+        Self {
+            glides: Vec::new(),
+            rate: DEFAULT_GLIDE_RATE,
+        }
+    }
+}
+
+impl Glides {
+    /// Get the current level of a glide on `address`, if one is running
+    pub(crate) fn level(&self, address: Address) -> Option<Db> {
+        self.glides
+            .iter()
+            .find(|glide| glide.address == address && !glide.finished.load(Ordering::SeqCst))
+            .map(|glide| glide.level)
+    }
+
+    /// Start a glide, replacing any on the same address
+    pub(crate) fn start(
+        &mut self,
+        address: Address,
+        from: Db,
+        target: Db,
+        duration: Duration,
+    ) -> GlideHandle {
+        self.glides.retain(|glide| {
+            if glide.address == address {
+                glide.finished.store(true, Ordering::SeqCst);
+            }
+            glide.address != address
+        });
+        let steps = (duration.as_secs_f64() * self.rate as f64).ceil().max(1.0) as u32;
+        let finished = Arc::new(AtomicBool::new(false));
+        self.glides.push(Glide {
+            address,
+            from: from.value().max(Db::MIN.value()),
+            target,
+            start: Instant::now(),
+            duration,
+            steps,
+            done: 0,
+            level: from,
+            finished: finished.clone(),
+        });
+        GlideHandle { finished }
+    }
+}
+
+impl TelnetClient {
+    /// Set the number of steps per second of glides started from now on
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `OutOfRange` for 0
+    pub fn set_glide_rate(&mut self, steps_per_sec: u32) -> Result<(), TelnetError> {
+        if steps_per_sec == 0 {
+            return Err(TelnetError::Protocol(RolandError::OutOfRange));
+        }
+        self.glides.rate = steps_per_sec;
+        Ok(())
+    }
+
+    /// Get the number of steps per second of new glides
+    pub fn glide_rate(&self) -> u32 {
+        self.glides.rate
+    }
+
+    /// Write the glide steps that are due
+    ///
+    /// Steps missed by calling this late are skipped, only the latest
+    /// level is written. Device errors for the writes are reported by
+    /// [`TelnetClient::drain_write_errors`].
+    ///
+    /// # Returns
+    /// * `Result<usize, TelnetError>` - Number of glides still running
+    pub fn run_glides(&mut self) -> Result<usize, TelnetError> {
+        self.run_glides_at(Instant::now())
+    }
+
+    /// Run the glides until every one has finished
+    ///
+    /// Returns once the device has acknowledged the last step.
+    pub fn wait_glides(&mut self) -> Result<(), TelnetError> {
+        self.wait_glides_cancellable(&CancellationToken::new())
+    }
+
+    /// Run the glides until every one has finished or `cancel` is
+    /// cancelled
+    ///
+    /// Cancelling stops every glide, leaving the faders at the last
+    /// levels written, and still waits for the ACKs of the steps sent.
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, `Cancelled`, or an error
+    pub fn wait_glides_cancellable(
+        &mut self,
+        cancel: &CancellationToken,
+    ) -> Result<(), TelnetError> {
+        while self.run_glides()? > 0 {
+            let next = self.glides.glides.iter().map(Glide::next_due).min();
+            if let Err(e) = cancel.sleep_until(next.unwrap_or_else(Instant::now)) {
+                for glide in self.glides.glides.drain(..) {
+                    glide.finished.store(true, Ordering::SeqCst);
+                }
+                self.flush_writes()?;
+                self.wait_write_acks()?;
+                return Err(e);
+            }
+        }
+        // Steps held back by coalescing
+        self.flush_writes()?;
+        self.wait_write_acks()
+    }
+
+    pub(crate) fn run_glides_at(&mut self, now: Instant) -> Result<usize, TelnetError> {
+        let mut glides = std::mem::take(&mut self.glides.glides);
+        glides.retain(|glide| !glide.finished.load(Ordering::SeqCst));
+        let mut result = Ok(());
+        for glide in glides.iter_mut() {
+            let due = glide.due(now);
+            if due <= glide.done {
+                continue;
+            }
+            let level = glide.level_at(due);
+            if level.to_byte() != glide.level.to_byte() || due == glide.steps {
+                result = self.write_parameter_nowait(glide.address, level.to_byte());
+                if result.is_err() {
+                    break;
+                }
+            }
+            glide.done = due;
+            glide.level = level;
+            if due == glide.steps {
+                glide.finished.store(true, Ordering::SeqCst);
+            }
+        }
+        glides.retain(|glide| !glide.finished.load(Ordering::SeqCst));
+        self.glides.glides = glides;
+        result.map(|()| self.glides.glides.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{AudioChannel, AudioMixer};
+    use crate::mock::{connect, MockDevice};
+    use roland_core::params::audio;
+    use roland_core::Command;
+    use std::thread;
+
+    fn fader_writes(commands: &[Command]) -> Vec<u8> {
+        let fader = audio::channel(0, audio::LEVEL);
+        commands
+            .iter()
+            .filter_map(|command| match command {
+                Command::WriteParameter { address, value } if *address == fader => Some(*value),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_glide_steps() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(audio::channel(0, audio::LEVEL), Db(-40.0).to_byte());
+        let mut client = connect(addr);
+
+        let handle = AudioMixer::new(&mut client)
+            .glide_fader(AudioChannel::Ch1, Db::ZERO, Duration::from_millis(100))
+            .unwrap();
+        // 3 steps at 30 per second, evenly spaced in dB
+        let start = client.glides.glides[0].start;
+        for ms in [10, 40, 40, 70, 100] {
+            client
+                .run_glides_at(start + Duration::from_millis(ms))
+                .unwrap();
+        }
+        assert!(handle.is_finished());
+        client.wait_write_acks().unwrap();
+        assert_eq!(fader_writes(&mock.received()), vec![54, 80, 107]);
+        assert_eq!(client.run_glides().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_glide_to_closed_fader() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(audio::channel(0, audio::LEVEL), Db(-33.0).to_byte());
+        let mut client = connect(addr);
+        client.set_glide_rate(2).unwrap();
+        assert_eq!(client.glide_rate(), 2);
+        assert!(client.set_glide_rate(0).is_err());
+
+        AudioMixer::new(&mut client)
+            .glide_fader(AudioChannel::Ch1, Db::NEG_INFINITY, Duration::from_secs(1))
+            .unwrap();
+        let start = client.glides.glides[0].start;
+        for ms in [500, 1000] {
+            client
+                .run_glides_at(start + Duration::from_millis(ms))
+                .unwrap();
+        }
+        client.wait_write_acks().unwrap();
+        // -43 dB halfway to -53 dB, then closed
+        assert_eq!(fader_writes(&mock.received()), vec![21, 0]);
+    }
+
+    #[test]
+    fn test_cancel_and_replace() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(audio::channel(0, audio::LEVEL), Db(-40.0).to_byte());
+        let mut client = connect(addr);
+
+        let mut mixer = AudioMixer::new(&mut client);
+        let first = mixer
+            .glide_fader(AudioChannel::Ch1, Db::ZERO, Duration::from_secs(1))
+            .unwrap();
+        let start = client.glides.glides[0].start;
+        client
+            .run_glides_at(start + Duration::from_millis(100))
+            .unwrap();
+        first.cancel();
+        assert_eq!(
+            client
+                .run_glides_at(start + Duration::from_secs(1))
+                .unwrap(),
+            0
+        );
+        client.wait_write_acks().unwrap();
+        assert_eq!(fader_writes(&mock.received()), vec![Db(-36.0).to_byte()]);
+
+        // A second glide on the fader replaces the first, from where the
+        // first one was
+        let first = AudioMixer::new(&mut client)
+            .glide_fader(AudioChannel::Ch1, Db::ZERO, Duration::from_secs(1))
+            .unwrap();
+        mock.clear_received();
+        let second = AudioMixer::new(&mut client)
+            .glide_fader(AudioChannel::Ch1, Db(-6.0), Duration::ZERO)
+            .unwrap();
+        assert!(first.is_finished());
+        client.wait_glides().unwrap();
+        assert!(second.is_finished());
+        assert_eq!(fader_writes(&mock.received()), vec![Db(-6.0).to_byte()]);
+    }
+
+    #[test]
+    fn test_glide_coalescing() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(audio::channel(0, audio::LEVEL), Db(-40.0).to_byte());
+        let mut client = connect(addr);
+        client.set_rate_limit(5);
+        client.set_coalescing(true);
+
+        AudioMixer::new(&mut client)
+            .glide_fader(AudioChannel::Ch1, Db::ZERO, Duration::from_millis(300))
+            .unwrap();
+        let start = Instant::now();
+        client.wait_glides().unwrap();
+        // Steps don't wait for the rate limit, only the last one does
+        assert!(start.elapsed() < Duration::from_millis(700));
+        let writes = fader_writes(&mock.received());
+        assert!(writes.len() <= 3, "{:?}", writes);
+        assert_eq!(writes.last(), Some(&Db::ZERO.to_byte()));
+        assert_eq!(client.pending_writes(), 0);
+    }
+
+    #[test]
+    fn test_wait_glides_cancelled() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(audio::channel(0, audio::LEVEL), Db(-40.0).to_byte());
+        let mut client = connect(addr);
+
+        let handle = AudioMixer::new(&mut client)
+            .glide_fader(AudioChannel::Ch1, Db::ZERO, Duration::from_secs(5))
+            .unwrap();
+        let cancel = CancellationToken::new();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(100));
+                cancel.cancel();
+            });
+            assert!(matches!(
+                client.wait_glides_cancellable(&cancel),
+                Err(TelnetError::Cancelled)
+            ));
+        });
+        assert!(handle.is_finished());
+        assert_eq!(client.pending_write_acks(), 0);
+        // No step got near the target
+        let writes = fader_writes(&mock.received());
+        assert!(writes.iter().all(|&value| value < Db(-30.0).to_byte()));
+        assert_eq!(client.run_glides().unwrap(), 0);
+    }
+}
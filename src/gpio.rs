@@ -0,0 +1,263 @@
+//! Control I/O: GPO and GPI pins
+//!
+//! The GPO pins drive external equipment, e.g. tally lights on camera
+//! CCUs, and the GPI pins take contact closures. Each pin has a function
+//! set by parameter. Pins are numbered 1 and up, as printed on the
+//! device.
+//!
+//! GPI pins without a function ([`GpiFunction::None`]) just report their
+//! state: read it with [`TelnetClient::read_gpi_state`], or watch for
+//! [`crate::DeviceEvent::ParameterChanged`] at
+//! [`roland_core::params::system::GPI_STATE`] and decode the value with
+//! [`GpiState`].
+
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::system;
+use roland_core::RolandError;
+
+/// Function of a GPO pin
+///
+/// Values this crate doesn't know decode to [`GpoFunction::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum GpoFunction {
+    /// Always open
+    Off,
+    /// Closed while the input with the pin's number is on program
+    ProgramTally,
+    /// Closed while the input with the pin's number is on preview
+    PreviewTally,
+    /// Closed while recording
+    Recording,
+    /// Closed while streaming
+    Streaming,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl GpoFunction {
+    /// All documented functions
+    pub const ALL: [GpoFunction; 5] = [
+        GpoFunction::Off,
+        GpoFunction::ProgramTally,
+        GpoFunction::PreviewTally,
+        GpoFunction::Recording,
+        GpoFunction::Streaming,
+    ];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => GpoFunction::Off,
+            1 => GpoFunction::ProgramTally,
+            2 => GpoFunction::PreviewTally,
+            3 => GpoFunction::Recording,
+            4 => GpoFunction::Streaming,
+            value => GpoFunction::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            GpoFunction::Off => 0,
+            GpoFunction::ProgramTally => 1,
+            GpoFunction::PreviewTally => 2,
+            GpoFunction::Recording => 3,
+            GpoFunction::Streaming => 4,
+            GpoFunction::Other(value) => value,
+        }
+    }
+}
+
+/// Function of a GPI pin
+///
+/// Values this crate doesn't know decode to [`GpiFunction::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum GpiFunction {
+    /// No function, the state is only reported
+    None,
+    /// CUT on closing
+    Cut,
+    /// AUTO TAKE on closing
+    AutoTake,
+    /// Start or stop recording on closing
+    ToggleRecording,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl GpiFunction {
+    /// All documented functions
+    pub const ALL: [GpiFunction; 4] = [
+        GpiFunction::None,
+        GpiFunction::Cut,
+        GpiFunction::AutoTake,
+        GpiFunction::ToggleRecording,
+    ];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => GpiFunction::None,
+            1 => GpiFunction::Cut,
+            2 => GpiFunction::AutoTake,
+            3 => GpiFunction::ToggleRecording,
+            value => GpiFunction::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            GpiFunction::None => 0,
+            GpiFunction::Cut => 1,
+            GpiFunction::AutoTake => 2,
+            GpiFunction::ToggleRecording => 3,
+            GpiFunction::Other(value) => value,
+        }
+    }
+}
+
+/// States of the GPI pins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GpiState {
+    /// Bit n is set while GPI n+1 is closed
+    pub bits: u8,
+}
+
+impl GpiState {
+    /// Check if a pin is closed
+    ///
+    /// Pins out of range are never closed.
+    pub fn is_closed(self, pin: u8) -> bool {
+        pin_index(pin, system::GPI_COUNT).is_ok_and(|i| self.bits & (1 << i) != 0)
+    }
+
+    /// Iterate over the closed pins
+    pub fn closed(self) -> impl Iterator<Item = u8> {
+        (1..=system::GPI_COUNT).filter(move |&pin| self.is_closed(pin))
+    }
+
+    /// Get the pins closed in `self` but open in `previous`
+    pub fn newly_closed(self, previous: GpiState) -> impl Iterator<Item = u8> {
+        (1..=system::GPI_COUNT).filter(move |&pin| self.is_closed(pin) && !previous.is_closed(pin))
+    }
+}
+
+impl TelnetClient {
+    /// Set the function of a GPO pin
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `OutOfRange` for a pin
+    ///   outside 1-4 (nothing is sent then)
+    pub fn configure_gpo(&mut self, pin: u8, function: GpoFunction) -> Result<(), TelnetError> {
+        let index = pin_index(pin, system::GPO_COUNT)?;
+        self.write_parameter_addr(system::gpo_function(index), function.value())
+    }
+
+    /// Get the function of a GPO pin
+    pub fn gpo_function(&mut self, pin: u8) -> Result<GpoFunction, TelnetError> {
+        let index = pin_index(pin, system::GPO_COUNT)?;
+        let value = self.read_parameter_addr(system::gpo_function(index), 1)?;
+        Ok(GpoFunction::from_value(value))
+    }
+
+    /// Set the function of a GPI pin
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `OutOfRange` for a pin
+    ///   outside 1-4 (nothing is sent then)
+    pub fn configure_gpi(&mut self, pin: u8, function: GpiFunction) -> Result<(), TelnetError> {
+        let index = pin_index(pin, system::GPI_COUNT)?;
+        self.write_parameter_addr(system::gpi_function(index), function.value())
+    }
+
+    /// Get the function of a GPI pin
+    pub fn gpi_function(&mut self, pin: u8) -> Result<GpiFunction, TelnetError> {
+        let index = pin_index(pin, system::GPI_COUNT)?;
+        let value = self.read_parameter_addr(system::gpi_function(index), 1)?;
+        Ok(GpiFunction::from_value(value))
+    }
+
+    /// Read the states of all GPI pins
+    pub fn read_gpi_state(&mut self) -> Result<GpiState, TelnetError> {
+        let bits = self.read_parameter_addr(system::GPI_STATE, 1)?;
+        Ok(GpiState { bits })
+    }
+}
+
+/// Convert a pin number (1 and up) to its parameter index
+fn pin_index(pin: u8, count: u8) -> Result<u8, TelnetError> {
+    if (1..=count).contains(&pin) {
+        Ok(pin - 1)
+    } else {
+        Err(TelnetError::Protocol(RolandError::OutOfRange))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+
+    #[test]
+    fn test_configure_pins() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        for function in GpoFunction::ALL {
+            client.configure_gpo(4, function).unwrap();
+            assert_eq!(client.gpo_function(4).unwrap(), function);
+        }
+        for function in GpiFunction::ALL {
+            client.configure_gpi(1, function).unwrap();
+            assert_eq!(client.gpi_function(1).unwrap(), function);
+        }
+        assert_eq!(mock.parameter(system::gpo_function(3)), Some(4));
+        assert_eq!(mock.parameter(system::gpi_function(0)), Some(3));
+
+        for value in 0..=255 {
+            assert_eq!(GpoFunction::from_value(value).value(), value);
+            assert_eq!(GpiFunction::from_value(value).value(), value);
+        }
+    }
+
+    #[test]
+    fn test_pin_range() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        for pin in [0, 5] {
+            assert!(matches!(
+                client.configure_gpo(pin, GpoFunction::ProgramTally),
+                Err(TelnetError::Protocol(RolandError::OutOfRange))
+            ));
+            assert!(matches!(
+                client.configure_gpi(pin, GpiFunction::Cut),
+                Err(TelnetError::Protocol(RolandError::OutOfRange))
+            ));
+        }
+        assert!(mock.received().is_empty());
+    }
+
+    #[test]
+    fn test_gpi_state() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(system::GPI_STATE, 0b0101);
+        let mut client = connect(addr);
+
+        let state = client.read_gpi_state().unwrap();
+        assert_eq!(state.closed().collect::<Vec<_>>(), [1, 3]);
+        assert!(state.is_closed(3));
+        assert!(!state.is_closed(2));
+        assert!(!GpiState { bits: 0xFF }.is_closed(5));
+        assert_eq!(
+            GpiState { bits: 0b0110 }
+                .newly_closed(state)
+                .collect::<Vec<_>>(),
+            [2]
+        );
+    }
+}
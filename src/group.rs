@@ -0,0 +1,246 @@
+//! Several devices controlled together
+//!
+//! A [`DeviceGroup`] holds named connections, e.g. to mirrored switchers
+//! at a venue. Devices can be used one at a time with
+//! [`DeviceGroup::get`], or all at once with [`DeviceGroup::broadcast`],
+//! which runs the operation on every device in parallel so one slow
+//! device doesn't hold up the others. Results are reported per device.
+
+use crate::{TelnetClient, TelnetError};
+use roland_core::Address;
+use std::error::Error;
+use std::fmt;
+use std::thread;
+
+/// Named connections to several devices
+#[derive(Default)]
+pub struct DeviceGroup {
+    devices: Vec<(String, TelnetClient)>,
+}
+
+impl DeviceGroup {
+    /// Create an empty group
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a device under a name
+    ///
+    /// Returns the client previously added under this name, if any.
+    pub fn add(&mut self, name: &str, client: TelnetClient) -> Option<TelnetClient> {
+        match self.devices.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => Some(std::mem::replace(existing, client)),
+            None => {
+                self.devices.push((name.to_string(), client));
+                None
+            }
+        }
+    }
+
+    /// Remove a device from the group
+    pub fn remove(&mut self, name: &str) -> Option<TelnetClient> {
+        let index = self.devices.iter().position(|(n, _)| n == name)?;
+        Some(self.devices.remove(index).1)
+    }
+
+    /// Get the client of a device
+    pub fn get(&mut self, name: &str) -> Option<&mut TelnetClient> {
+        self.devices
+            .iter_mut()
+            .find(|(n, _)| n == name)
+            .map(|(_, client)| client)
+    }
+
+    /// Iterate over the device names, in the order they were added
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.devices.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Get the number of devices
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// Check if the group has no devices
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Run an operation on every device in parallel
+    ///
+    /// Each device gets its own thread; this returns once all are done.
+    pub fn broadcast<T, F>(&mut self, operation: F) -> BroadcastResult<T>
+    where
+        T: Send,
+        F: Fn(&mut TelnetClient) -> Result<T, TelnetError> + Sync,
+    {
+        let operation = &operation;
+        let results = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .devices
+                .iter_mut()
+                .map(|(name, client)| (name, scope.spawn(move || operation(client))))
+                .collect();
+            handles
+                .into_iter()
+                .map(|(name, handle)| (name.clone(), handle.join().unwrap()))
+                .collect()
+        });
+        BroadcastResult { results }
+    }
+
+    /// Write a parameter value on every device in parallel
+    pub fn broadcast_write(&mut self, address: Address, value: u8) -> BroadcastResult<()> {
+        self.broadcast(|client| client.write_parameter_addr(address, value))
+    }
+}
+
+/// Per-device results of [`DeviceGroup::broadcast`]
+#[derive(Debug)]
+pub struct BroadcastResult<T> {
+    /// Device names and results, in the order the devices were added
+    pub results: Vec<(String, Result<T, TelnetError>)>,
+}
+
+impl<T> BroadcastResult<T> {
+    /// Check if the operation succeeded on every device
+    pub fn all_ok(&self) -> bool {
+        self.results.iter().all(|(_, result)| result.is_ok())
+    }
+
+    /// Get the result of a device
+    pub fn get(&self, name: &str) -> Option<&Result<T, TelnetError>> {
+        self.results
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, result)| result)
+    }
+
+    /// Iterate over the devices the operation failed on
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &TelnetError)> {
+        self.results
+            .iter()
+            .filter_map(|(name, result)| Some((name.as_str(), result.as_ref().err()?)))
+    }
+
+    /// Get the values if the operation succeeded everywhere
+    ///
+    /// # Returns
+    /// * `Result<Vec<(String, T)>, GroupError>` - Values by device, or
+    ///   every failure if there were any
+    pub fn into_result(self) -> Result<Vec<(String, T)>, GroupError> {
+        let mut values = Vec::new();
+        let mut failures = Vec::new();
+        for (name, result) in self.results {
+            match result {
+                Ok(value) => values.push((name, value)),
+                Err(e) => failures.push((name, e)),
+            }
+        }
+        if failures.is_empty() {
+            Ok(values)
+        } else {
+            Err(GroupError { failures })
+        }
+    }
+}
+
+/// Failure of an operation on some devices of a group
+#[derive(Debug)]
+pub struct GroupError {
+    /// Names of the devices that failed, with their errors
+    pub failures: Vec<(String, TelnetError)>,
+}
+
+impl fmt::Display for GroupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed on {} device(s)", self.failures.len())?;
+        for (i, (name, e)) in self.failures.iter().enumerate() {
+            write!(f, "{} {}: {}", if i == 0 { ":" } else { ";" }, name, e)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for GroupError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDevice;
+    use crate::timeouts::Timeouts;
+    use roland_core::Command;
+    use std::time::{Duration, Instant};
+
+    const FADER: Address = Address::new(0x05, 0x00, 0x00);
+
+    fn connect(addr: std::net::SocketAddr) -> TelnetClient {
+        let mut client = crate::mock::connect(addr);
+        client.set_timeouts(Timeouts {
+            write: Duration::from_millis(300),
+            ..Timeouts::default()
+        });
+        client
+    }
+
+    #[test]
+    fn test_broadcast_with_one_timeout() {
+        let mut group = DeviceGroup::new();
+        let mut mocks = Vec::new();
+        for name in ["stage_left", "center", "stage_right"] {
+            let (addr, mock) = MockDevice::spawn();
+            assert!(group.add(name, connect(addr)).is_none());
+            mocks.push(mock);
+        }
+        mocks[1].set_delay(Duration::from_millis(600));
+
+        let start = Instant::now();
+        let result = group.broadcast_write(FADER, 0x40);
+        // In parallel: about one timeout, not more
+        assert!(start.elapsed() < Duration::from_millis(550));
+
+        assert!(!result.all_ok());
+        assert!(result.get("stage_left").unwrap().is_ok());
+        assert!(result.get("stage_right").unwrap().is_ok());
+        let failures: Vec<_> = result.failures().collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, "center");
+        assert!(matches!(failures[0].1, TelnetError::Timeout));
+        for mock in [&mocks[0], &mocks[2]] {
+            assert_eq!(mock.parameter(FADER), Some(0x40));
+        }
+
+        let error = result.into_result().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed on 1 device(s): center: Timed out waiting for the device"
+        );
+    }
+
+    #[test]
+    fn test_individual_access() {
+        let (addr_a, mock_a) = MockDevice::spawn();
+        let (addr_b, mock_b) = MockDevice::spawn();
+        let mut group = DeviceGroup::new();
+        group.add("a", connect(addr_a));
+        group.add("b", connect(addr_b));
+        assert_eq!(group.names().collect::<Vec<_>>(), ["a", "b"]);
+
+        group
+            .get("b")
+            .unwrap()
+            .write_parameter_addr(FADER, 1)
+            .unwrap();
+        assert!(mock_a.received().is_empty());
+        assert_eq!(mock_b.received(), vec![Command::write_parameter(FADER, 1)]);
+        assert!(group.get("c").is_none());
+
+        let versions = group
+            .broadcast(|client| client.get_version())
+            .into_result()
+            .unwrap();
+        assert_eq!(versions.len(), 2);
+        assert!(group.remove("a").is_some());
+        assert_eq!(group.len(), 1);
+    }
+}
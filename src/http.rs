@@ -0,0 +1,637 @@
+//! HTTP gateway (requires the `http` feature)
+//!
+//! [`HttpGateway`] serves a small JSON API for control systems that only
+//! speak HTTP, forwarding requests to a device through a [`SharedClient`]:
+//!
+//! | Request | Body | Response |
+//! |---|---|---|
+//! | `GET /param/{address}` | | `{"address":"050000","value":1}` |
+//! | `PUT /param/{address}` | `{"value":1}` | `{"address":"050000","value":1}` |
+//! | `GET /version` | | `{"product":"VR-6HD","version":"1.00"}` |
+//! | `POST /scene/{n}/recall` | | `{"scene":3}` |
+//!
+//! `{address}` is either 6 hex digits or a parameter name from the
+//! [`ParameterMap`]. Named parameters take and return enum value names
+//! where the map has them, e.g. `{"value":"hdmi2"}`, and their responses
+//! carry a `name` field as well.
+//!
+//! Errors are answered with `{"error":"<description>"}`, plus the device
+//! error `code` where there is one:
+//!
+//! * 400: malformed request, address or body
+//! * 404: unknown path or parameter name
+//! * 405: known path with an unsupported method
+//! * 422: the device rejected the command (`ERR:4`, `ERR:5`, ...) or the
+//!   value is outside what the parameter takes
+//! * 502: the connection to the device failed or it answered nonsense
+//! * 504: the device didn't answer in time
+
+use crate::backup::{parse_json, write_json_string, JsonValue};
+use crate::param_map::{ParamDef, ParamMapError, ParameterMap};
+use crate::shared::SharedClient;
+use crate::TelnetError;
+use roland_core::{Address, RolandError};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Largest accepted request head (request line and headers)
+const MAX_HEAD: usize = 8 * 1024;
+/// Largest accepted request body
+const MAX_BODY: usize = 64 * 1024;
+/// How long a connection may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP request, as far as the gateway needs it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    /// Method, e.g. `GET`
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    /// Body
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// Read a request from a connection
+    ///
+    /// # Returns
+    /// * `Result<HttpRequest, HttpResponse>` - Request, or the response
+    ///   to send if it is malformed
+    pub fn read_from(reader: &mut impl BufRead) -> Result<Self, HttpResponse> {
+        let bad_request = |message: &str| HttpResponse::error(400, message);
+
+        let mut head_len = 0;
+        let mut read_line = |reader: &mut dyn BufRead| -> Result<String, HttpResponse> {
+            let mut line = String::new();
+            let n = reader
+                .take((MAX_HEAD - head_len) as u64)
+                .read_line(&mut line)
+                .map_err(|_| bad_request("Malformed request"))?;
+            head_len += n;
+            if !line.ends_with('\n') {
+                return Err(bad_request("Request head too long or incomplete"));
+            }
+            Ok(line.trim_end().to_string())
+        };
+
+        let request_line = read_line(reader)?;
+        let mut parts = request_line.split(' ');
+        let (Some(method), Some(target), Some(_version)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(bad_request("Malformed request line"));
+        };
+        let path = target.split('?').next().unwrap_or_default().to_string();
+
+        let mut content_length = 0;
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(bad_request("Malformed header"));
+            };
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| bad_request("Malformed Content-Length"))?;
+            }
+        }
+        if content_length > MAX_BODY {
+            return Err(HttpResponse::error(413, "Request body too large"));
+        }
+
+        let mut body = vec![0; content_length];
+        reader
+            .read_exact(&mut body)
+            .map_err(|_| bad_request("Request body incomplete"))?;
+        Ok(Self {
+            method: method.to_string(),
+            path,
+            body,
+        })
+    }
+}
+
+/// HTTP response with a JSON body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    /// Status code
+    pub status: u16,
+    /// JSON body
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// Create a `200 OK` response
+    pub fn ok(body: String) -> Self {
+        Self { status: 200, body }
+    }
+
+    /// Create an error response with `{"error":"<message>"}`
+    pub fn error(status: u16, message: &str) -> Self {
+        let mut body = String::from("{\"error\":");
+        write_json_string(&mut body, message);
+        body.push('}');
+        Self { status, body }
+    }
+
+    /// Create the error response for a failed device operation
+    pub fn from_error(error: &TelnetError) -> Self {
+        let status = status_of(error);
+        match error {
+            // Report the device's own error text, not the wrapper
+            TelnetError::Protocol(e) | TelnetError::Device { error: e, .. } => {
+                let mut response = Self::error(status, &e.to_string());
+                if let Some(code) = e.code() {
+                    response.body.pop();
+                    response.body.push_str(&format!(",\"code\":{}}}", code));
+                }
+                response
+            }
+            e => Self::error(status, &e.to_string()),
+        }
+    }
+
+    /// Write the response to a connection
+    pub fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason(self.status),
+            self.body.len(),
+            self.body
+        )?;
+        writer.flush()
+    }
+}
+
+/// Get the HTTP status for a failed device operation
+pub fn status_of(error: &TelnetError) -> u16 {
+    match error {
+        TelnetError::Protocol(e) if e.is_device_error() => 422,
+        TelnetError::Device { error, .. } if error.is_device_error() => 422,
+        TelnetError::Device { .. } => 502,
+        TelnetError::Protocol(RolandError::InvalidValue | RolandError::InvalidAddress)
+        | TelnetError::InvalidAddress(_) => 400,
+        TelnetError::Protocol(_) => 502,
+        TelnetError::Timeout => 504,
+        TelnetError::Io(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+            504
+        }
+        TelnetError::Io(_)
+        | TelnetError::ConnectionClosed
+        | TelnetError::AddressMismatch { .. }
+        | TelnetError::ShortRead { .. }
+        | TelnetError::WriteMismatch { .. }
+        | TelnetError::UnknownProduct(_)
+        | TelnetError::HostUnreachable(_)
+        | TelnetError::ConnectionRefused(_)
+        | TelnetError::AuthFailed => 502,
+        TelnetError::Parameter(ParamMapError::UnknownParameter(_)) => 404,
+        TelnetError::Parameter(_) => 422,
+        TelnetError::SafetyInterlock { .. }
+        | TelnetError::TransitionInProgress
+        | TelnetError::StorageUnavailable { .. } => 409,
+        TelnetError::Cancelled => 503,
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Error",
+    }
+}
+
+/// Endpoint of the gateway
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    /// `GET /param/{address}`
+    GetParam(String),
+    /// `PUT /param/{address}`
+    PutParam(String),
+    /// `GET /version`
+    GetVersion,
+    /// `POST /scene/{n}/recall`
+    RecallScene(u8),
+}
+
+impl Route {
+    /// Match a request to an endpoint
+    ///
+    /// # Returns
+    /// * `Result<Route, HttpResponse>` - Endpoint, or a 400, 404 or 405
+    ///   response
+    pub fn parse(method: &str, path: &str) -> Result<Self, HttpResponse> {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let allowed = match segments[..] {
+            ["param", address] => match method {
+                "GET" => return Ok(Route::GetParam(address.to_string())),
+                "PUT" => return Ok(Route::PutParam(address.to_string())),
+                _ => "GET, PUT",
+            },
+            ["version"] => match method {
+                "GET" => return Ok(Route::GetVersion),
+                _ => "GET",
+            },
+            ["scene", n, "recall"] => match method {
+                "POST" => {
+                    return n
+                        .parse()
+                        .map(Route::RecallScene)
+                        .map_err(|_| HttpResponse::error(400, "Scene must be a number"))
+                }
+                _ => "POST",
+            },
+            _ => return Err(HttpResponse::error(404, "Not found")),
+        };
+        Err(HttpResponse::error(
+            405,
+            &format!("Method {} not allowed, use {}", method, allowed),
+        ))
+    }
+}
+
+/// Parameter a request refers to
+enum Target {
+    Raw(Address),
+    Named(ParamDef),
+}
+
+/// HTTP server forwarding requests to a device
+pub struct HttpGateway {
+    listener: TcpListener,
+    handler: Handler,
+}
+
+/// Request handling, shared by the connection threads
+#[derive(Clone)]
+struct Handler {
+    client: SharedClient,
+    map: Arc<ParameterMap>,
+}
+
+impl HttpGateway {
+    /// Listen for HTTP requests on `addr`
+    ///
+    /// # Arguments
+    /// * `addr` - Address to listen on
+    /// * `client` - Connection to the device
+    /// * `map` - Parameters that can be addressed by name
+    pub fn bind(
+        addr: impl ToSocketAddrs,
+        client: SharedClient,
+        map: ParameterMap,
+    ) -> Result<Self, TelnetError> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            handler: Handler {
+                client,
+                map: Arc::new(map),
+            },
+        })
+    }
+
+    /// Get the address the gateway listens on
+    pub fn local_addr(&self) -> Result<SocketAddr, TelnetError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serve requests, each connection on its own thread
+    ///
+    /// Requests are answered one at a time by the device connection, in
+    /// the order they arrive. Only fails if accepting connections fails.
+    pub fn run(&self) -> Result<(), TelnetError> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let handler = self.handler.clone();
+            thread::spawn(move || {
+                // The client may have gone away; nothing to report to
+                let _ = handler.serve(stream);
+            });
+        }
+        Ok(())
+    }
+
+    /// Answer a single request
+    pub fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        self.handler.handle(request)
+    }
+}
+
+impl Handler {
+    fn serve(&self, stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let response = match HttpRequest::read_from(&mut reader) {
+            Ok(request) => self.handle(&request),
+            Err(response) => response,
+        };
+        response.write_to(&mut &stream)
+    }
+
+    fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        let result = match Route::parse(&request.method, &request.path) {
+            Ok(route) => self.dispatch(route, &request.body),
+            Err(response) => return response,
+        };
+        result.unwrap_or_else(|e| e)
+    }
+
+    fn dispatch(&self, route: Route, body: &[u8]) -> Result<HttpResponse, HttpResponse> {
+        match route {
+            Route::GetParam(address) => {
+                let target = self.target(&address)?;
+                let bytes = self.read(&target)?;
+                Ok(HttpResponse::ok(param_json(&target, &bytes)?))
+            }
+            Route::PutParam(address) => {
+                let target = self.target(&address)?;
+                let bytes = encode_value(&target, body)?;
+                let address = match &target {
+                    Target::Raw(address) => *address,
+                    Target::Named(param) => param.address,
+                };
+                let data = bytes.clone();
+                self.client
+                    .with(move |client| client.write_parameter_block(address, &data))
+                    .and_then(|result| result)
+                    .map_err(|e| HttpResponse::from_error(&e))?;
+                Ok(HttpResponse::ok(param_json(&target, &bytes)?))
+            }
+            Route::GetVersion => {
+                let (product, version) = self
+                    .client
+                    .get_version()
+                    .map_err(|e| HttpResponse::from_error(&e))?;
+                let mut json = String::from("{\"product\":");
+                write_json_string(&mut json, &product);
+                json.push_str(",\"version\":");
+                write_json_string(&mut json, &version);
+                json.push('}');
+                Ok(HttpResponse::ok(json))
+            }
+            Route::RecallScene(n) => {
+                self.client
+                    .with(move |client| client.recall_scene(n))
+                    .and_then(|result| result)
+                    .map_err(|e| HttpResponse::from_error(&e))?;
+                Ok(HttpResponse::ok(format!("{{\"scene\":{}}}", n)))
+            }
+        }
+    }
+
+    /// Resolve an address path segment: 6 hex digits or a parameter name
+    fn target(&self, segment: &str) -> Result<Target, HttpResponse> {
+        if let Ok(address) = Address::from_hex(segment) {
+            return Ok(Target::Raw(address));
+        }
+        self.map
+            .lookup(segment)
+            .map(|param| Target::Named(param.clone()))
+            .map_err(|e| HttpResponse::from_error(&e.into()))
+    }
+
+    /// Read the bytes of a parameter, one RQH per byte
+    fn read(&self, target: &Target) -> Result<Vec<u8>, HttpResponse> {
+        let (start, size) = match target {
+            Target::Raw(address) => (*address, 1),
+            Target::Named(param) => (param.address, param.size),
+        };
+        self.client
+            .with(move |client| {
+                std::iter::successors(Some(start), |a| a.successor())
+                    .take(size as usize)
+                    .map(|address| client.read_parameter_addr(address, 1))
+                    .collect::<Result<Vec<u8>, TelnetError>>()
+            })
+            .and_then(|result| result)
+            .map_err(|e| HttpResponse::from_error(&e))
+    }
+}
+
+/// Encode the `value` of a PUT body for a parameter
+fn encode_value(target: &Target, body: &[u8]) -> Result<Vec<u8>, HttpResponse> {
+    let body =
+        std::str::from_utf8(body).map_err(|_| HttpResponse::error(400, "Body isn't UTF-8"))?;
+    let json = parse_json(body).map_err(|e| HttpResponse::error(400, &e.to_string()))?;
+    let value = json
+        .field("value")
+        .ok_or_else(|| HttpResponse::error(400, "Body needs a \"value\" field"))?;
+    match (target, value) {
+        (Target::Raw(_), JsonValue::Number(n)) => u8::try_from(*n)
+            .map(|value| vec![value])
+            .map_err(|_| HttpResponse::from_error(&RolandError::InvalidValue.into())),
+        (Target::Raw(_), _) => Err(HttpResponse::error(400, "Value must be a number 0-255")),
+        (Target::Named(param), JsonValue::Number(n)) => param
+            .encode(&n.to_string())
+            .map_err(|e| HttpResponse::from_error(&e.into())),
+        (Target::Named(param), JsonValue::String(s)) => param
+            .encode(s)
+            .map_err(|e| HttpResponse::from_error(&e.into())),
+        (Target::Named(_), _) => Err(HttpResponse::error(
+            400,
+            "Value must be a number or a value name",
+        )),
+    }
+}
+
+/// Build the JSON for a parameter value
+fn param_json(target: &Target, bytes: &[u8]) -> Result<String, HttpResponse> {
+    match target {
+        Target::Raw(address) => Ok(format!(
+            "{{\"address\":\"{}\",\"value\":{}}}",
+            address.to_hex(),
+            bytes[0]
+        )),
+        Target::Named(param) => {
+            let value = param
+                .decode(bytes)
+                .map_err(|e| HttpResponse::from_error(&e.into()))?;
+            let mut json = String::from("{\"name\":");
+            write_json_string(&mut json, &param.name);
+            json.push_str(&format!(
+                ",\"address\":\"{}\",\"value\":",
+                param.address.to_hex()
+            ));
+            // Numbers stay numbers; enum names become strings
+            match value.parse::<u32>() {
+                Ok(number) => json.push_str(&number.to_string()),
+                Err(_) => write_json_string(&mut json, &value),
+            }
+            json.push('}');
+            Ok(json)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice, MockHandle};
+
+    const MAP: &str =
+        "[video.pgm_select]\naddress = \"000000\"\nvalues = [\"hdmi1=1\", \"hdmi2=2\"]\nmax = 2\n";
+
+    fn gateway() -> (HttpGateway, MockHandle) {
+        let (addr, mock) = MockDevice::spawn();
+        let client = connect(addr);
+        let map = ParameterMap::from_toml(MAP).unwrap();
+        let gateway = HttpGateway::bind("127.0.0.1:0", SharedClient::new(client), map).unwrap();
+        (gateway, mock)
+    }
+
+    fn request(method: &str, path: &str, body: &str) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_routes() {
+        assert_eq!(
+            Route::parse("GET", "/param/050000"),
+            Ok(Route::GetParam("050000".to_string()))
+        );
+        assert_eq!(
+            Route::parse("POST", "/scene/3/recall"),
+            Ok(Route::RecallScene(3))
+        );
+        assert_eq!(Route::parse("GET", "/version/"), Ok(Route::GetVersion));
+        assert_eq!(
+            Route::parse("DELETE", "/param/050000").unwrap_err().status,
+            405
+        );
+        assert_eq!(Route::parse("GET", "/nothing").unwrap_err().status, 404);
+        assert_eq!(
+            Route::parse("POST", "/scene/x/recall").unwrap_err().status,
+            400
+        );
+    }
+
+    #[test]
+    fn test_error_mapping() {
+        let response = HttpResponse::from_error(&TelnetError::Protocol(RolandError::OutOfRange));
+        assert_eq!(response.status, 422);
+        assert_eq!(
+            response.body,
+            format!("{{\"error\":\"{}\",\"code\":5}}", RolandError::OutOfRange)
+        );
+        assert_eq!(status_of(&TelnetError::Timeout), 504);
+        assert_eq!(
+            status_of(&std::io::Error::from(ErrorKind::TimedOut).into()),
+            504
+        );
+        assert_eq!(status_of(&TelnetError::ConnectionClosed), 502);
+        assert_eq!(
+            status_of(&TelnetError::InvalidAddress("05".to_string())),
+            status_of(&TelnetError::Protocol(RolandError::InvalidAddress))
+        );
+        assert_eq!(
+            status_of(&ParamMapError::UnknownParameter("x".to_string()).into()),
+            404
+        );
+        assert_eq!(
+            status_of(&TelnetError::SafetyInterlock {
+                operation: "enable the test tone"
+            }),
+            409
+        );
+    }
+
+    #[test]
+    fn test_raw_and_named_params() {
+        let (gateway, mock) = gateway();
+
+        let put = gateway.handle(&request("PUT", "/param/050000", "{\"value\": 7}"));
+        assert_eq!(
+            put,
+            HttpResponse::ok("{\"address\":\"050000\",\"value\":7}".into())
+        );
+        assert_eq!(mock.parameter(Address::new(0x05, 0x00, 0x00)), Some(7));
+        let get = gateway.handle(&request("GET", "/param/050000", ""));
+        assert_eq!(get.body, "{\"address\":\"050000\",\"value\":7}");
+
+        let put = gateway.handle(&request(
+            "PUT",
+            "/param/video.pgm_select",
+            "{\"value\":\"hdmi2\"}",
+        ));
+        assert_eq!(put.status, 200);
+        assert_eq!(mock.parameter(Address::new(0x00, 0x00, 0x00)), Some(2));
+        let get = gateway.handle(&request("GET", "/param/video.pgm_select", ""));
+        assert_eq!(
+            get.body,
+            "{\"name\":\"video.pgm_select\",\"address\":\"000000\",\"value\":\"hdmi2\"}"
+        );
+
+        assert_eq!(
+            gateway.handle(&request("GET", "/param/nope", "")).status,
+            404
+        );
+        assert_eq!(
+            gateway
+                .handle(&request("PUT", "/param/050000", "{\"value\":256}"))
+                .status,
+            400
+        );
+        assert_eq!(
+            gateway
+                .handle(&request(
+                    "PUT",
+                    "/param/video.pgm_select",
+                    "{\"value\":\"sdi\"}"
+                ))
+                .status,
+            422
+        );
+
+        mock.inject_error(RolandError::OutOfRange);
+        let rejected = gateway.handle(&request("PUT", "/param/050000", "{\"value\":1}"));
+        assert_eq!(rejected.status, 422);
+        assert!(rejected.body.contains("\"code\":5"));
+    }
+
+    #[test]
+    fn test_serve_over_tcp() {
+        let (gateway, _mock) = gateway();
+        let addr = gateway.local_addr().unwrap();
+        thread::spawn(move || gateway.run());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /version HTTP/1.1\r\nHost: test\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("{\"product\":\"VR-6HD\",\"version\":\"1.00\"}"));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"POST /scene/3/recall HTTP/1.1\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("{\"scene\":3}"));
+    }
+}
@@ -5,33 +5,285 @@
 
 pub use roland_core::*;
 
-use roland_core::{Address, Command, Response, RolandError};
-use std::io::{Read, Write};
-use std::net::TcpStream;
-use std::time::Duration;
+pub mod audio;
+pub mod auto_switch;
+pub mod backup;
+mod batch;
+mod cache;
+pub mod cancel;
+pub mod capture;
+pub mod config;
+pub mod crossfade;
+#[cfg(any(test, feature = "discovery"))]
+pub mod discovery;
+pub mod dsk;
+mod echo;
+pub mod effects;
+pub mod event;
+pub mod fade;
+pub mod glide;
+pub mod gpio;
+pub mod group;
+#[cfg(any(test, feature = "http"))]
+pub mod http;
+pub mod login;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+mod nowait;
+#[cfg(any(test, feature = "osc"))]
+pub mod osc;
+pub mod panel;
+pub mod param_map;
+pub mod pinp;
+pub mod poller;
+pub mod profile;
+mod rate_limit;
+mod raw;
+pub mod recorder;
+pub mod retry;
+pub mod routing;
+pub mod scaling;
+mod scene;
+pub mod scheduler;
+pub mod shared;
+pub mod split;
+pub mod state;
+pub mod status;
+mod still;
+mod subscription;
+pub mod switcher;
+pub mod tally;
+mod telnet;
+pub mod test_signal;
+pub mod text;
+pub mod timeouts;
+pub mod transport;
+pub mod trigger;
+pub mod udp;
+pub mod units;
+pub mod video;
+pub mod wire;
+#[cfg(any(test, feature = "ws"))]
+pub mod ws_bridge;
+
+pub use event::DeviceEvent;
+pub use scene::StoreSceneError;
+
+use cache::ReadCache;
+use echo::EchoFilter;
+use glide::Glides;
+use nowait::NowaitWrites;
+use profile::Profile;
+use rate_limit::RateLimiter;
+use retry::RetryPolicy;
+use subscription::Subscription;
+use tally::TallyState;
+use telnet::Iac;
+use text::StringWriteMode;
+use timeouts::Timeouts;
+use trigger::ChangeLog;
+use wire::{Direction, WireLog};
+
+use roland_core::engine::{answers_with, ProtocolEngine};
+
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// Default read and write timeout
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long [`TelnetClient::close`] waits for the device to close its side
+const CLOSE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// How long to wait for the next DTH of a read split into several frames
+const CONTINUATION_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Values of a block write shown in an error message
+const ERROR_BLOCK_VALUES: usize = 4;
+
+/// Default number of unacknowledged commands in a batch
+const DEFAULT_MAX_IN_FLIGHT: usize = 8;
 
 /// Error type for Telnet client
 #[derive(Debug)]
 pub enum TelnetError {
     /// Protocol-level error from roland-core
     Protocol(RolandError),
+    /// Device answered a command with `ERR:n;`
+    ///
+    /// The display shows the command as sent, e.g. `Device rejected
+    /// DTH:123456,7F: Parameter out of range`; the error reported is the
+    /// [`source`](std::error::Error::source).
+    Device {
+        /// Command the device refused
+        command: Command,
+        /// Error the device reported
+        error: RolandError,
+    },
     /// I/O error
     Io(std::io::Error),
     /// Connection closed
     ConnectionClosed,
+    /// Address string that isn't 6 hex digits
+    ///
+    /// This stands in for `Protocol(RolandError::InvalidAddress)`, which
+    /// can't hold the string since [`RolandError`] variants carry no data.
+    /// The string is shown by `Display` and
+    /// [`RolandError::InvalidAddress`] is the source; retries and HTTP
+    /// status codes treat both the same.
+    InvalidAddress(String),
+    /// Device didn't finish an operation in time
+    Timeout,
+    /// Named parameter or value rejected by a parameter map
+    Parameter(param_map::ParamMapError),
+    /// Device answered a read with data for another address
+    AddressMismatch {
+        /// Address that was read
+        requested: Address,
+        /// Address in the device's DTH
+        received: Address,
+    },
+    /// Device reported a product without a [`profile::Profile`]
+    UnknownProduct(String),
+    /// Device answered a multi-byte read with fewer bytes than requested
+    ShortRead {
+        /// Number of bytes that were read
+        requested: u32,
+        /// Number of bytes the device sent
+        got: u32,
+    },
+    /// Operation refused because it would disturb a live stream or
+    /// recording; see [`test_signal`]
+    SafetyInterlock {
+        /// What was refused, e.g. `enable the test tone`
+        operation: &'static str,
+    },
+    /// Operation refused because a video transition is in progress
+    TransitionInProgress,
+    /// No route to the device while connecting: it is off, on another
+    /// network or behind a firewall
+    HostUnreachable(std::io::Error),
+    /// The device refused the connection: wrong address or port, or no
+    /// free session
+    ConnectionRefused(std::io::Error),
+    /// The device or a Telnet front-end asked for a password and didn't
+    /// accept the one given, or none was given; see [`login`]
+    AuthFailed,
+    /// Recording refused because the SD card isn't ready; see
+    /// [`TelnetClient::set_recording_precheck`]
+    StorageUnavailable {
+        /// Card status that was read
+        status: transport::StorageStatus,
+    },
+    /// Device answered a write with a DTH of other values, with
+    /// [`WriteAnswer::DthSameValue`]
+    WriteMismatch {
+        /// Address written
+        address: Address,
+        /// Values written
+        written: Vec<u8>,
+        /// Values in the device's DTH
+        answered: Vec<u8>,
+    },
+    /// Operation stopped through a [`cancel::CancellationToken`]
+    Cancelled,
 }
 
 impl std::fmt::Display for TelnetError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             TelnetError::Protocol(e) => write!(f, "Protocol error: {}", e),
+            TelnetError::Device { command, error } => {
+                write!(f, "Device rejected {}: {}", command_text(command), error)
+            }
             TelnetError::Io(e) => write!(f, "I/O error: {}", e),
             TelnetError::ConnectionClosed => write!(f, "Connection closed"),
+            TelnetError::InvalidAddress(address) => {
+                write!(f, "{}: {:?}", RolandError::InvalidAddress, address)
+            }
+            TelnetError::Timeout => write!(f, "Timed out waiting for the device"),
+            TelnetError::Parameter(e) => write!(f, "{}", e),
+            TelnetError::AddressMismatch {
+                requested,
+                received,
+            } => write!(
+                f,
+                "Requested {} but received data for {}",
+                requested.to_hex(),
+                received.to_hex()
+            ),
+            TelnetError::UnknownProduct(product) => {
+                write!(f, "No device profile for product {:?}", product)
+            }
+            TelnetError::ShortRead { requested, got } => {
+                write!(f, "Requested {} bytes but received {}", requested, got)
+            }
+            TelnetError::SafetyInterlock { operation } => {
+                write!(f, "Refusing to {} while streaming or recording", operation)
+            }
+            TelnetError::TransitionInProgress => write!(f, "A transition is in progress"),
+            TelnetError::HostUnreachable(e) => {
+                write!(f, "Device is off or unreachable: {}", e)
+            }
+            TelnetError::ConnectionRefused(e) => write!(f, "Connection refused: {}", e),
+            TelnetError::AuthFailed => write!(f, "Login failed: password missing or rejected"),
+            TelnetError::StorageUnavailable { status } => {
+                write!(f, "SD card not ready for recording: ")?;
+                match status.remaining_minutes {
+                    None => write!(f, "no card"),
+                    Some(_) if status.write_protected => write!(f, "write protected"),
+                    Some(minutes) => write!(f, "{} minutes left", minutes),
+                }
+            }
+            TelnetError::WriteMismatch {
+                address,
+                written,
+                answered,
+            } => write!(
+                f,
+                "Wrote {} to {} but the device answered {}",
+                hex_values(written),
+                address.to_hex(),
+                hex_values(answered)
+            ),
+            TelnetError::Cancelled => write!(f, "Operation cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for TelnetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TelnetError::Protocol(e) | TelnetError::Device { error: e, .. } => Some(e),
+            TelnetError::Io(e)
+            | TelnetError::HostUnreachable(e)
+            | TelnetError::ConnectionRefused(e) => Some(e),
+            TelnetError::Parameter(e) => Some(e),
+            TelnetError::InvalidAddress(_) => Some(&RolandError::InvalidAddress),
+            _ => None,
         }
     }
 }
 
-impl std::error::Error for TelnetError {}
+impl TelnetError {
+    /// Create the error for a command the device answered with `ERR:n;`
+    pub(crate) fn device(command: &Command, error: RolandError) -> Self {
+        TelnetError::Device {
+            command: command.clone(),
+            error,
+        }
+    }
+
+    /// Get the error the device reported, for [`TelnetError::Device`]
+    pub fn device_error(&self) -> Option<&RolandError> {
+        match self {
+            TelnetError::Device { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
 
 impl From<RolandError> for TelnetError {
     fn from(e: RolandError) -> Self {
@@ -39,6 +291,12 @@ impl From<RolandError> for TelnetError {
     }
 }
 
+impl From<param_map::ParamMapError> for TelnetError {
+    fn from(e: param_map::ParamMapError) -> Self {
+        TelnetError::Parameter(e)
+    }
+}
+
 impl From<std::io::Error> for TelnetError {
     fn from(e: std::io::Error) -> Self {
         TelnetError::Io(e)
@@ -46,88 +304,563 @@ impl From<std::io::Error> for TelnetError {
 }
 
 /// Telnet client for Roland VR-6HD
+///
+/// Every frame received from the device goes through a demultiplexer:
+/// frames that answer the outstanding command complete it, while
+/// unsolicited DTH frames (e.g. an operator moving a fader on the panel)
+/// are queued as [`DeviceEvent`]s. Drain them with [`TelnetClient::poll_event`]
+/// or [`TelnetClient::events`]. Echoes of the commands sent are dropped,
+/// see [`TelnetClient::set_suppress_echo`].
 pub struct TelnetClient {
     stream: TcpStream,
-    buffer: Vec<u8>,
+    decoder: Decoder,
+    /// Strips Telnet option negotiation ahead of the decoder
+    iac: Iac,
+    wire: WireLog,
+    events: VecDeque<DeviceEvent>,
+    subscription: Option<Subscription>,
+    /// Set when the device sent XOFF, cleared by XON
+    paused: bool,
+    /// Last known tally, kept up to date from tally parameter changes
+    tally: TallyState,
+    max_in_flight: usize,
+    retry: RetryPolicy,
+    rate: RateLimiter,
+    profile: Profile,
+    timeouts: Timeouts,
+    /// Recent reads, `None` unless the read cache is on
+    cache: Option<ReadCache>,
+    nowait: NowaitWrites,
+    /// Frame variants accepted from the device
+    parse_options: ParseOptions,
+    string_write_mode: StringWriteMode,
+    /// Minimum minutes left on the SD card to start recording, if checked
+    recording_precheck: Option<u32>,
+    echo: EchoFilter,
+    /// Changes waiting for [`trigger::Triggers::run`]
+    changes: ChangeLog,
+    glides: Glides,
+    /// Matches responses to the command sent by
+    /// [`TelnetClient::send_command`]
+    engine: ProtocolEngine,
 }
 
 impl TelnetClient {
     /// Connect to VR-6HD device via Telnet
     ///
     /// # Arguments
-    /// * `host` - IP address or hostname of the VR-6HD device
+    /// * `host` - IP address or hostname of the VR-6HD device. IPv6
+    ///   literals may be given with or without brackets (`fe80::1`,
+    ///   `[fe80::1]`). Each address a hostname resolves to is tried in turn.
     /// * `port` - Telnet port (default: 23)
     ///
     /// # Returns
-    /// * `Result<Self, TelnetError>` - Connected client or error
+    /// * `Result<Self, TelnetError>` - Connected client or error; see
+    ///   [`TelnetClient::connect_with_timeout`] for the connection errors
     pub fn connect(host: &str, port: u16) -> Result<Self, TelnetError> {
-        let addr = format!("{}:{}", host, port);
-        let stream = TcpStream::connect(&addr)?;
+        Self::connect_host(host, port, None)
+    }
+
+    /// Connect to VR-6HD device via Telnet, giving up on each address
+    /// after `timeout`
+    ///
+    /// Without a timeout, a device behind a firewall that drops the
+    /// connection attempt keeps [`TelnetClient::connect`] waiting for the
+    /// OS default, about 20 s on Windows.
+    ///
+    /// # Arguments
+    /// * `host` - IP address or hostname, as for [`TelnetClient::connect`].
+    ///   Each address a hostname resolves to is tried in turn, each with
+    ///   its own timeout.
+    /// * `port` - Telnet port (default: 23)
+    /// * `timeout` - How long to wait for each address
+    ///
+    /// # Returns
+    /// * `Result<Self, TelnetError>` - Connected client, or the error of
+    ///   the last address tried: `Timeout` if it didn't answer,
+    ///   `HostUnreachable` if there is no route to it, `ConnectionRefused`
+    ///   if it refused the connection
+    pub fn connect_with_timeout(
+        host: &str,
+        port: u16,
+        timeout: Duration,
+    ) -> Result<Self, TelnetError> {
+        Self::connect_host(host, port, Some(timeout))
+    }
+
+    fn connect_host(host: &str, port: u16, timeout: Option<Duration>) -> Result<Self, TelnetError> {
+        // IPv6 literals may come with or without brackets
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+
+        // Try every resolved address, like TcpStream::connect does
+        let mut last_error = None;
+        for addr in (host, port).to_socket_addrs()? {
+            let result = match timeout {
+                Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                None => TcpStream::connect(addr),
+            };
+            match result {
+                Ok(stream) => return Self::from_stream(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(connect_error(last_error.unwrap_or_else(|| {
+            std::io::Error::new(ErrorKind::NotFound, "no address resolved")
+        })))
+    }
+
+    /// Connect to VR-6HD device at a socket address
+    ///
+    /// # Arguments
+    /// * `addr` - IPv4 or IPv6 address and port of the device
+    ///
+    /// # Returns
+    /// * `Result<Self, TelnetError>` - Connected client or error
+    pub fn connect_addr(addr: SocketAddr) -> Result<Self, TelnetError> {
+        Self::from_stream(TcpStream::connect(addr).map_err(connect_error)?)
+    }
+
+    /// Connect to VR-6HD device, giving up after `timeout`
+    ///
+    /// # Arguments
+    /// * `addr` - IPv4 or IPv6 address and port of the device
+    /// * `timeout` - How long to wait for the connection
+    ///
+    /// # Returns
+    /// * `Result<Self, TelnetError>` - Connected client or error
+    pub fn connect_timeout(addr: SocketAddr, timeout: Duration) -> Result<Self, TelnetError> {
+        Self::from_stream(TcpStream::connect_timeout(&addr, timeout).map_err(connect_error)?)
+    }
 
+    fn from_stream(stream: TcpStream) -> Result<Self, TelnetError> {
         // Set read timeout
-        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
 
         // Set write timeout
-        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
 
+        let mut engine = ProtocolEngine::new();
+        engine.set_parse_options(ParseOptions::LENIENT);
         Ok(Self {
             stream,
-            buffer: Vec::new(),
+            decoder: Decoder::with_options(ParseOptions::LENIENT),
+            parse_options: ParseOptions::LENIENT,
+            iac: Iac::default(),
+            wire: WireLog::default(),
+            events: VecDeque::new(),
+            subscription: None,
+            paused: false,
+            tally: TallyState::default(),
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            retry: RetryPolicy::never(),
+            rate: RateLimiter::default(),
+            profile: Profile::default(),
+            timeouts: Timeouts::default(),
+            cache: None,
+            nowait: NowaitWrites::default(),
+            string_write_mode: StringWriteMode::default(),
+            recording_precheck: None,
+            echo: EchoFilter::default(),
+            changes: ChangeLog::default(),
+            glides: Glides::default(),
+            engine,
         })
     }
 
+    /// Close the session cleanly
+    ///
+    /// Stops any subscription, shuts down the sending side and waits
+    /// briefly for the device to close its side, so the device frees the
+    /// session slot right away. Frames still arriving are discarded.
+    ///
+    /// Dropping the client also shuts the connection down, but without
+    /// waiting for the device.
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success or error
+    pub fn close(mut self) -> Result<(), TelnetError> {
+        self.flush_writes()?;
+        self.unsubscribe()?;
+        self.stream.shutdown(Shutdown::Write)?;
+        self.stream.set_read_timeout(Some(CLOSE_TIMEOUT))?;
+
+        let deadline = Instant::now() + CLOSE_TIMEOUT;
+        let mut buf = [0u8; 1024];
+        while Instant::now() < deadline {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => self.wire.log(Direction::Received, &buf[..n]),
+                // The device may reset instead; the session is gone either way
+                Err(_) => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if the connection is still open
+    ///
+    /// Peeks at the socket without blocking, so this notices a device
+    /// that closed the connection even while no command is outstanding.
+    pub fn is_connected(&self) -> bool {
+        if self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let connected = match self.stream.peek(&mut [0u8; 1]) {
+            Ok(n) => n > 0,
+            Err(e) => e.kind() == ErrorKind::WouldBlock,
+        };
+        self.stream.set_nonblocking(false).is_ok() && connected
+    }
+
     /// Send a command and wait for response
     ///
+    /// Unsolicited frames received while waiting are queued as events.
+    /// Writes held back by coalescing are sent first. The response must
+    /// arrive within the client's timeout for this command, see
+    /// [`Timeouts::for_command`].
+    ///
     /// # Arguments
     /// * `command` - Command to send
     ///
     /// # Returns
     /// * `Result<Response, TelnetError>` - Response from device or error
     pub fn send_command(&mut self, command: &Command) -> Result<Response, TelnetError> {
+        let timeout = self.timeouts.for_command(command, &self.profile);
+        self.send_command_with_timeout(command, timeout)
+    }
+
+    /// Send a command and wait at most `timeout` for the response
+    ///
+    /// The timeout covers the whole wait, including unsolicited frames
+    /// that arrive in the meantime; it starts once the command is sent.
+    ///
+    /// # Returns
+    /// * `Result<Response, TelnetError>` - Response from device, `Timeout`
+    ///   if it didn't arrive in time, or another error
+    pub fn send_command_with_timeout(
+        &mut self,
+        command: &Command,
+        timeout: Duration,
+    ) -> Result<Response, TelnetError> {
+        self.exchange(command, timeout)
+            .map(|(response, _)| response)
+    }
+
+    /// Send a command and read its response with the frame it came in
+    fn exchange(
+        &mut self,
+        command: &Command,
+        timeout: Duration,
+    ) -> Result<(Response, String), TelnetError> {
+        self.flush_writes()?;
+        self.wait_until_resumed()?;
+
+        // Make sure the reader thread forwards the answer to a read
+        if let (Some(subscription), Command::ReadParameter { address, .. }) =
+            (&self.subscription, command)
+        {
+            subscription.set_awaiting(Some(*address));
+        }
+
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate_for(command, &self.profile);
+        }
+
+        // Send command; an engine left with a command by an earlier
+        // failure starts over
+        self.engine.clear();
+        let frame = self
+            .engine
+            .submit(command.clone())
+            .ok_or(TelnetError::Protocol(RolandError::BufferFull))?;
+        self.write_frame(Some(command), String::from_utf8_lossy(&frame).into_owned())?;
+
+        // Read response
+        let response = self.read_response(Instant::now() + timeout);
+        if let Some(subscription) = &self.subscription {
+            subscription.set_awaiting(None);
+        }
+        if matches!(response, Err(TelnetError::Timeout)) {
+            // An echo still due would no longer line up with its command
+            self.echo.clear();
+        }
+        response
+    }
+
+    /// Encode and send a command without waiting for the response
+    fn write_command(&mut self, command: &Command) -> Result<(), TelnetError> {
         // Encode command (without STX for Telnet)
-        let cmd_str = command.encode();
-        let cmd_bytes = cmd_str.as_bytes();
+        self.write_frame(Some(command), command.encode())
+    }
 
-        // Send command
-        self.stream.write_all(cmd_bytes)?;
+    /// Send an encoded command, `command` if it is one
+    fn write_frame(&mut self, command: Option<&Command>, frame: String) -> Result<(), TelnetError> {
+        self.throttle();
+        self.wire.log(Direction::Sent, frame.as_bytes());
+        self.stream.write_all(frame.as_bytes())?;
         self.stream.flush()?;
+        self.echo.sent(command, frame);
+        Ok(())
+    }
 
-        // Read response
-        self.read_response()
+    /// Block until the device allows sending again after XOFF
+    ///
+    /// Must only be called while no command is outstanding.
+    fn wait_until_resumed(&mut self) -> Result<(), TelnetError> {
+        while self.paused {
+            let frame = self.read_frame()?;
+            self.queue_event(&frame);
+        }
+        Ok(())
+    }
+
+    /// Get the frame variants accepted from the device
+    pub fn parse_options(&self) -> ParseOptions {
+        self.parse_options
     }
 
-    /// Read response from device
-    fn read_response(&mut self) -> Result<Response, TelnetError> {
+    /// Change the frame variants accepted from the device
+    ///
+    /// Clients accept [`ParseOptions::LENIENT`] by default, since firmware
+    /// revisions differ in how they terminate and capitalize responses.
+    /// While subscribed, the reader thread keeps the options it was
+    /// started with until the next [`TelnetClient::subscribe`].
+    pub fn set_parse_options(&mut self, options: ParseOptions) {
+        self.parse_options = options;
+        self.decoder.set_options(options);
+        self.engine.set_parse_options(options);
+    }
+
+    /// Parse a frame received from the device
+    pub(crate) fn parse(&self, frame: &str) -> Result<Response, RolandError> {
+        Response::parse_with(frame, self.parse_options)
+    }
+
+    /// Track XON/XOFF, returning whether `frame` was a flow control frame
+    fn update_flow_control(&mut self, frame: &str) -> bool {
+        match self.parse(frame) {
+            Ok(Response::Xoff) => self.paused = true,
+            Ok(Response::Xon) => self.paused = false,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Read the response to the command in the engine and its frame,
+    /// queueing unrelated frames as events
+    ///
+    /// Frames are read here, with flow control, echoes and nowait ACKs
+    /// taken out, and handed to the engine, which decides what answers
+    /// the command.
+    fn read_response(&mut self, deadline: Instant) -> Result<(Response, String), TelnetError> {
+        loop {
+            let frame = self
+                .read_frame_before(deadline)?
+                .ok_or(TelnetError::Timeout)?;
+            if self.update_flow_control(&frame) {
+                continue;
+            }
+
+            let response = self.parse(&frame)?;
+            if self.settle_write(&response) {
+                continue;
+            }
+            self.engine.on_response(response);
+            while let Some(response) = self.engine.take_unsolicited() {
+                match response {
+                    Response::Data { address, value } => self.push_event(address, value),
+                    Response::DataBlock { address, data } => self.push_events(address, &data),
+                    _ => {}
+                }
+            }
+            // The engine isn't ticked, the deadline times out instead, so
+            // an error is one the device answered with
+            if let Some((_, result)) = self.engine.take_completion() {
+                return Ok((result.unwrap_or_else(Response::Error), frame));
+            }
+        }
+    }
+
+    /// Read the next complete frame, blocking until one is available
+    fn read_frame(&mut self) -> Result<String, TelnetError> {
+        loop {
+            let frame = self.read_any_frame()?;
+            if !self.take_echo(&frame) {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /// Read the next complete frame, echoes included
+    fn read_any_frame(&mut self) -> Result<String, TelnetError> {
+        if let Some(subscription) = &self.subscription {
+            return subscription.recv_frame(TIMEOUT);
+        }
+
         let mut buf = [0u8; 1024];
 
-        // Read data
-        let n = self.stream.read(&mut buf)?;
+        loop {
+            if let Some(frame) = self.decoder.next_frame() {
+                return Ok(frame);
+            }
+
+            let n = self.stream.read(&mut buf)?;
+            if n == 0 {
+                return Err(TelnetError::ConnectionClosed);
+            }
+            self.receive(&buf[..n])?;
+        }
+    }
+
+    /// Feed received bytes to the decoder, answering any IAC negotiation
+    fn receive(&mut self, bytes: &[u8]) -> Result<(), TelnetError> {
+        let data = self.strip_negotiation(bytes)?;
+        self.decoder.push(&data);
+        Ok(())
+    }
 
-        if n == 0 {
-            return Err(TelnetError::ConnectionClosed);
+    /// Log received bytes and remove IAC negotiation from them, answering it
+    fn strip_negotiation(&mut self, bytes: &[u8]) -> Result<Vec<u8>, TelnetError> {
+        self.wire.log(Direction::Received, bytes);
+        let mut data = Vec::with_capacity(bytes.len());
+        for negotiation in self.iac.filter(bytes, &mut data) {
+            if let Some(refusal) = negotiation.refusal() {
+                self.wire.log(Direction::Sent, &refusal.to_bytes());
+                self.stream.write_all(&refusal.to_bytes())?;
+            }
         }
+        Ok(data)
+    }
 
-        // Append to buffer
-        self.buffer.extend_from_slice(&buf[..n]);
+    /// Take the next frame that has already been received, without reading
+    fn buffered_frame(&mut self) -> Option<Result<String, TelnetError>> {
+        loop {
+            let frame = match &self.subscription {
+                Some(subscription) => subscription.try_recv_frame()?,
+                None => Ok(self.decoder.next_frame()?),
+            };
+            match frame {
+                Ok(frame) if self.take_echo(&frame) => {}
+                frame => return Some(frame),
+            }
+        }
+    }
 
-        // Try to parse response
-        // Responses typically end with ';' or control characters
-        let response_str = String::from_utf8_lossy(&self.buffer);
+    /// Get the next unsolicited event without blocking
+    ///
+    /// Sends the writes held back by coalescing if the rate limit allows
+    /// (see [`TelnetClient::set_coalescing`]), reads whatever the device
+    /// has sent so far, then returns the oldest queued event, if any.
+    ///
+    /// # Returns
+    /// * `Result<Option<DeviceEvent>, TelnetError>` - Next event, `None` if there is none
+    pub fn poll_event(&mut self) -> Result<Option<DeviceEvent>, TelnetError> {
+        self.flush_due_writes()?;
+        if self.events.is_empty() {
+            self.read_available()?;
+        }
+        Ok(self.events.pop_front())
+    }
 
-        // Look for complete response (ends with ';' or is a control character)
-        if response_str.ends_with(';') ||
-           response_str.contains('\x06') || // ACK
-           response_str.contains('\x11') || // XON
-           response_str.contains('\x13')
+    /// Drain the events queued so far
+    ///
+    /// This doesn't read from the connection; use [`TelnetClient::poll_event`]
+    /// to pick up frames the device sent while no command was outstanding.
+    pub fn events(&mut self) -> impl Iterator<Item = DeviceEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Read all pending data without blocking and queue the events in it
+    fn read_available(&mut self) -> Result<(), TelnetError> {
+        self.flush_due_writes()?;
+        if let Some(subscription) = &self.subscription {
+            let mut frames = Vec::new();
+            while let Some(frame) = subscription.try_recv_frame() {
+                frames.push(frame?);
+            }
+            for frame in frames {
+                if !self.take_echo(&frame) {
+                    self.queue_event(&frame);
+                }
+            }
+            return Ok(());
+        }
+
+        let mut buf = [0u8; 1024];
+        let mut received = Vec::new();
+
+        self.stream.set_nonblocking(true)?;
+        let result = loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break Err(TelnetError::ConnectionClosed),
+                Ok(n) => received.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e.into()),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        // Negotiation replies are written in blocking mode
+        self.receive(&received)?;
+        result?;
+
+        while let Some(frame) = self.decoder.next_frame() {
+            if !self.take_echo(&frame) {
+                self.queue_event(&frame);
+            }
+        }
+        Ok(())
+    }
+
+    /// Queue a frame received while no command is outstanding
+    ///
+    /// Only DTH frames and the answers to nowait writes are meaningful
+    /// then; flow control is tracked and anything else is dropped.
+    fn queue_event(&mut self, frame: &str) {
+        if self.update_flow_control(frame) {
+            return;
+        }
+        let Ok(response) = self.parse(frame) else {
+            return;
+        };
+        if self.settle_write(&response) {
+            return;
+        }
+        if let Some((address, data)) = response.as_data() {
+            self.push_events(address, data);
+        }
+    }
+
+    /// Queue the events for unsolicited changes of consecutive parameters
+    fn push_events(&mut self, address: Address, data: &[u8]) {
+        for (i, &value) in data.iter().enumerate() {
+            self.push_event(offset(address, i), value);
+        }
+    }
+
+    /// Queue the events for an unsolicited parameter change
+    ///
+    /// Changes of tally parameters also queue a [`DeviceEvent::TallyChanged`],
+    /// and changes of the transport status a [`DeviceEvent::TransportChanged`].
+    fn push_event(&mut self, address: Address, value: u8) {
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate(address);
+        }
+        self.events
+            .push_back(DeviceEvent::ParameterChanged { address, value });
+        self.changes.record(address, value);
+        if let Some(tally) = self.update_tally(address, value) {
+            self.events.push_back(DeviceEvent::TallyChanged(tally));
+        }
+        if self
+            .profile
+            .address("transport.status")
+            .is_ok_and(|status| status == address)
         {
-            // XOFF
-            let response = Response::parse(&response_str)?;
-            self.buffer.clear();
-            Ok(response)
-        } else {
-            // Incomplete response, wait a bit and try again
-            std::thread::sleep(Duration::from_millis(100));
-            self.read_response()
+            let status = transport::TransportStatus::decode(value, None);
+            self.events.push_back(DeviceEvent::TransportChanged(status));
         }
     }
 
@@ -140,18 +873,81 @@ impl TelnetClient {
     /// # Returns
     /// * `Result<(), TelnetError>` - Success or error
     pub fn write_parameter(&mut self, address: &str, value: u8) -> Result<(), TelnetError> {
-        let addr = Address::from_hex(address)?;
-        let cmd = Command::WriteParameter {
-            address: addr,
-            value,
-        };
-        let response = self.send_command(&cmd)?;
+        self.write_parameter_addr(parse_address(address)?, value)
+    }
 
-        match response {
-            Response::Acknowledge => Ok(()),
-            Response::Error(e) => Err(TelnetError::Protocol(e)),
-            _ => Err(TelnetError::Protocol(RolandError::InvalidResponse)),
+    /// Write a parameter value
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address
+    /// * `value` - Value to write (0-255)
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success or error
+    pub fn write_parameter_addr(&mut self, address: Address, value: u8) -> Result<(), TelnetError> {
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate(address);
+        }
+        if self.coalesce_write(address, value) {
+            return Ok(());
         }
+        self.write_parameter_now(address, value)
+    }
+
+    /// Write a parameter value, bypassing coalescing
+    pub(crate) fn write_parameter_now(
+        &mut self,
+        address: Address,
+        value: u8,
+    ) -> Result<(), TelnetError> {
+        let cmd = Command::WriteParameter { address, value };
+        self.retrying(|client| {
+            let response = client.send_command(&cmd)?;
+            client.write_answered(&cmd, response)
+        })
+    }
+
+    /// Check the response to a write, see [`write_answered`]
+    pub(crate) fn write_answered(
+        &self,
+        command: &Command,
+        response: Response,
+    ) -> Result<(), TelnetError> {
+        write_answered(command, response, self.parse_options)
+    }
+
+    /// Write consecutive parameters in a single command
+    ///
+    /// The device applies all bytes at once, so multi-byte parameters are
+    /// never seen half-written.
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address of the first byte
+    /// * `data` - Values to write to `address` and the following addresses
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success or error (`InvalidValue` if
+    ///   `data` is empty)
+    pub fn write_parameter_block(
+        &mut self,
+        address: Address,
+        data: &[u8],
+    ) -> Result<(), TelnetError> {
+        let cmd = match data {
+            [] => return Err(TelnetError::Protocol(RolandError::InvalidValue)),
+            [value] => Command::WriteParameter {
+                address,
+                value: *value,
+            },
+            _ => Command::WriteBlock {
+                address,
+                data: data.to_vec(),
+            },
+        };
+        self.retrying(|client| {
+            let response = client.send_command(&cmd)?;
+            client.write_answered(&cmd, response)
+        })
     }
 
     /// Read a parameter value
@@ -163,18 +959,279 @@ impl TelnetClient {
     /// # Returns
     /// * `Result<u8, TelnetError>` - Parameter value or error
     pub fn read_parameter(&mut self, address: &str, size: u32) -> Result<u8, TelnetError> {
-        let addr = Address::from_hex(address)?;
-        let cmd = Command::ReadParameter {
-            address: addr,
-            size,
+        self.read_parameter_addr(parse_address(address)?, size)
+    }
+
+    /// Read a parameter value
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address
+    /// * `size` - Size to read (typically 1 for single byte)
+    ///
+    /// # Returns
+    /// * `Result<u8, TelnetError>` - Parameter value or error
+    pub fn read_parameter_addr(&mut self, address: Address, size: u32) -> Result<u8, TelnetError> {
+        self.read_parameter_full(address, size)
+            .map(|(_, value)| value)
+    }
+
+    /// Read a parameter value together with the address the device answered for
+    ///
+    /// DTH frames for other addresses that arrive while waiting (e.g. an
+    /// operator moving a fader) are queued as events; they never answer
+    /// the read.
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address
+    /// * `size` - Size to read (typically 1 for single byte)
+    ///
+    /// # Returns
+    /// * `Result<(Address, u8), TelnetError>` - Address and value,
+    ///   `OutOfRange` for a size of 0 or above 24 bits, or `AddressMismatch`
+    ///   if the answer is for another address
+    pub fn read_parameter_full(
+        &mut self,
+        address: Address,
+        size: u32,
+    ) -> Result<(Address, u8), TelnetError> {
+        let cmd = Command::read(address, size)?;
+        self.retrying(|client| data_for(&cmd, address, client.send_command(&cmd)?))
+    }
+
+    /// Read several consecutive parameter values
+    ///
+    /// The device answers with one DTH holding all values, or splits a
+    /// large read into several DTH frames at increasing addresses; these
+    /// are stitched together in order. DTH frames outside the requested
+    /// range are queued as events.
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address of the first value
+    /// * `size` - Number of values to read
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>, TelnetError>` - Exactly `size` values,
+    ///   `OutOfRange` for a size of 0 or above 24 bits, `AddressMismatch`
+    ///   if a frame doesn't start where the previous one ended (a gap or an
+    ///   overlap), or `ShortRead` if the device stops early
+    pub fn read_parameter_bytes(
+        &mut self,
+        address: Address,
+        size: u32,
+    ) -> Result<Vec<u8>, TelnetError> {
+        let cmd = Command::read(address, size)?;
+        self.retrying(|client| {
+            let response = client.send_command(&cmd)?;
+            let data = block_for(&cmd, address, response)?;
+            let result = client.read_continuation(address, size, data);
+            if let Some(subscription) = &client.subscription {
+                subscription.set_awaiting(None);
+            }
+            result
+        })
+    }
+
+    /// Read the remaining frames of a read split by the device
+    ///
+    /// `data` holds the values received so far, starting at `start`.
+    fn read_continuation(
+        &mut self,
+        start: Address,
+        size: u32,
+        mut data: Vec<u8>,
+    ) -> Result<Vec<u8>, TelnetError> {
+        let in_range = |address: Address| {
+            let distance = u32::from(address).wrapping_sub(u32::from(start)) & 0xFF_FFFF;
+            distance < size
         };
-        let response = self.send_command(&cmd)?;
+        while data.len() < size as usize {
+            let next = offset(start, data.len());
+            if let Some(subscription) = &self.subscription {
+                subscription.set_awaiting(Some(next));
+            }
+            let Some(frame) = self.read_frame_within(CONTINUATION_TIMEOUT)? else {
+                return Err(TelnetError::ShortRead {
+                    requested: size,
+                    got: data.len() as u32,
+                });
+            };
+            if self.update_flow_control(&frame) {
+                continue;
+            }
+            match self.parse(&frame)?.as_data() {
+                Some((address, values)) if address == next => data.extend_from_slice(values),
+                Some((address, _)) if in_range(address) => {
+                    return Err(TelnetError::AddressMismatch {
+                        requested: next,
+                        received: address,
+                    })
+                }
+                Some((address, values)) => self.push_events(address, values),
+                None => return Err(TelnetError::Protocol(RolandError::InvalidResponse)),
+            }
+        }
+        if data.len() > size as usize {
+            return Err(TelnetError::Protocol(RolandError::InvalidResponse));
+        }
+        Ok(data)
+    }
 
-        match response {
-            Response::Data { value, .. } => Ok(value),
-            Response::Error(e) => Err(TelnetError::Protocol(e)),
-            _ => Err(TelnetError::Protocol(RolandError::InvalidResponse)),
+    /// Write a two-byte parameter in a single command
+    ///
+    /// The value is sent big endian, MSB at `address` and LSB at the next
+    /// address, as the parameter tables list them. Both bytes go in one
+    /// DTH, so the device never applies a torn value.
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address of the MSB
+    /// * `value` - Value to write
+    /// * `max` - Largest valid value, if the parameter has one
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success or error (`OutOfRange` if
+    ///   `value` is above `max`)
+    pub fn write_u16_parameter(
+        &mut self,
+        address: Address,
+        value: u16,
+        max: Option<u16>,
+    ) -> Result<(), TelnetError> {
+        if max.is_some_and(|max| value > max) {
+            return Err(TelnetError::Protocol(RolandError::OutOfRange));
         }
+        self.write_parameter_block(address, &value.to_be_bytes())
+    }
+
+    /// Read a two-byte parameter in a single command
+    ///
+    /// The counterpart of [`TelnetClient::write_u16_parameter`]: MSB at
+    /// `address`, LSB at the next address.
+    ///
+    /// # Returns
+    /// * `Result<u16, TelnetError>` - Parameter value or error
+    pub fn read_u16_parameter(&mut self, address: Address) -> Result<u16, TelnetError> {
+        let data = self.read_parameter_bytes(address, 2)?;
+        Ok(u16::from_be_bytes([data[0], data[1]]))
+    }
+
+    /// Write a value split across 4-bit nibbles in a single command
+    ///
+    /// See [`roland_core::nibble`] for the encoding.
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address of the most significant nibble
+    /// * `value` - Value to write
+    /// * `nibble_count` - Number of addresses the value is split across
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success or error (`OutOfRange` if
+    ///   `value` doesn't fit in `nibble_count` nibbles, `InvalidValue` for a
+    ///   count of 0)
+    pub fn write_nibble_parameter(
+        &mut self,
+        address: Address,
+        value: u32,
+        nibble_count: usize,
+    ) -> Result<(), TelnetError> {
+        if !nibble::fits_nibbles(value, nibble_count) {
+            return Err(TelnetError::Protocol(RolandError::OutOfRange));
+        }
+        let data: Vec<u8> = nibble::encode_nibbles(value, nibble_count).collect();
+        self.write_parameter_block(address, &data)
+    }
+
+    /// Read a value split across 4-bit nibbles in a single command
+    ///
+    /// # Returns
+    /// * `Result<u32, TelnetError>` - Value, or `InvalidValue` if the
+    ///   device sent a byte above `0F`
+    pub fn read_nibble_parameter(
+        &mut self,
+        address: Address,
+        nibble_count: usize,
+    ) -> Result<u32, TelnetError> {
+        let size = u32::try_from(nibble_count)
+            .map_err(|_| TelnetError::Protocol(RolandError::OutOfRange))?;
+        let data = self.read_parameter_bytes(address, size)?;
+        Ok(nibble::decode_nibbles(&data)?)
+    }
+
+    /// Read the next frame, or `None` if none arrives within `timeout`
+    fn read_frame_within(&mut self, timeout: Duration) -> Result<Option<String>, TelnetError> {
+        self.read_frame_before(Instant::now() + timeout)
+    }
+
+    /// Read the next frame, or `None` if none is complete at `deadline`
+    ///
+    /// Unlike a socket read timeout, this bounds the total time, however
+    /// the data trickles in.
+    fn read_frame_before(&mut self, deadline: Instant) -> Result<Option<String>, TelnetError> {
+        loop {
+            match self.read_any_frame_before(deadline)? {
+                Some(frame) if self.take_echo(&frame) => {}
+                frame => return Ok(frame),
+            }
+        }
+    }
+
+    /// Read the next complete frame before `deadline`, echoes included
+    fn read_any_frame_before(&mut self, deadline: Instant) -> Result<Option<String>, TelnetError> {
+        if let Some(subscription) = &self.subscription {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            return match subscription.recv_frame(remaining) {
+                Ok(frame) => Ok(Some(frame)),
+                Err(TelnetError::Io(e)) if e.kind() == ErrorKind::TimedOut => Ok(None),
+                Err(e) => Err(e),
+            };
+        }
+
+        let mut buf = [0u8; 1024];
+        let result = loop {
+            if let Some(frame) = self.decoder.next_frame() {
+                break Ok(Some(frame));
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break Ok(None);
+            }
+            if let Err(e) = self.stream.set_read_timeout(Some(remaining)) {
+                break Err(e.into());
+            }
+            match self.stream.read(&mut buf) {
+                Ok(0) => break Err(TelnetError::ConnectionClosed),
+                Ok(n) => {
+                    if let Err(e) = self.receive(&buf[..n]) {
+                        break Err(e);
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => break Err(e.into()),
+            }
+        };
+        self.stream.set_read_timeout(Some(TIMEOUT))?;
+        result
+    }
+
+    /// Write a parameter value and read back what the device stored
+    ///
+    /// Some parameters clamp or quantize written values, so the value the
+    /// device actually stores can differ from `value`. This doesn't treat
+    /// a difference as an error: compare the returned value with `value`
+    /// to detect it.
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address
+    /// * `value` - Value to write (0-255)
+    ///
+    /// # Returns
+    /// * `Result<u8, TelnetError>` - Value stored by the device or error
+    pub fn write_parameter_verified(
+        &mut self,
+        address: Address,
+        value: u8,
+    ) -> Result<u8, TelnetError> {
+        self.write_parameter_addr(address, value)?;
+        self.read_parameter_addr(address, 1)
     }
 
     /// Get version information
@@ -187,8 +1244,873 @@ impl TelnetClient {
 
         match response {
             Response::Version { product, version } => Ok((product, version)),
-            Response::Error(e) => Err(TelnetError::Protocol(e)),
+            Response::Error(e) => Err(TelnetError::device(&cmd, e)),
             _ => Err(TelnetError::Protocol(RolandError::InvalidResponse)),
         }
     }
+
+    /// Get the product model and firmware version
+    ///
+    /// Like [`TelnetClient::get_version`], but parsed for comparisons,
+    /// e.g. to use features only newer firmware has.
+    ///
+    /// # Returns
+    /// * `Result<DeviceInfo, TelnetError>` - Product and firmware or error
+    pub fn get_version_info(&mut self) -> Result<DeviceInfo, TelnetError> {
+        let (product, version) = self.get_version()?;
+        Ok(DeviceInfo::parse(&product, &version))
+    }
+}
+
+impl Drop for TelnetClient {
+    fn drop(&mut self) {
+        // Best effort: the device frees the session once it sees the close
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Parse a hex address string, keeping the string in the error
+pub(crate) fn parse_address(address: &str) -> Result<Address, TelnetError> {
+    Address::try_from(address).map_err(|_| TelnetError::InvalidAddress(address.to_string()))
+}
+
+/// Format values as comma-separated hex, like in a DTH
+fn hex_values(values: &[u8]) -> String {
+    values
+        .iter()
+        .map(|value| format!("{:02X}", value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Get a command as sent, without the `;`, for error messages
+///
+/// Long blocks are cut after [`ERROR_BLOCK_VALUES`] values.
+fn command_text(command: &Command) -> String {
+    match command {
+        Command::WriteBlock { address, data } if data.len() > ERROR_BLOCK_VALUES => format!(
+            "DTH:{},{},... ({} bytes)",
+            address.to_hex(),
+            hex_values(&data[..ERROR_BLOCK_VALUES]),
+            data.len()
+        ),
+        _ => command.encode().trim_end_matches(';').to_string(),
+    }
+}
+
+/// Get the address `n` bytes after `address`, wrapping at FFFFFF
+pub(crate) fn offset(address: Address, n: usize) -> Address {
+    let value =
+        ((address.high as usize) << 16 | (address.mid as usize) << 8 | address.low as usize)
+            .wrapping_add(n);
+    Address::new((value >> 16) as u8, (value >> 8) as u8, value as u8)
+}
+
+/// Classify an error from opening a connection
+fn connect_error(e: std::io::Error) -> TelnetError {
+    match e.kind() {
+        ErrorKind::TimedOut => TelnetError::Timeout,
+        ErrorKind::ConnectionRefused => TelnetError::ConnectionRefused(e),
+        ErrorKind::HostUnreachable | ErrorKind::NetworkUnreachable => {
+            TelnetError::HostUnreachable(e)
+        }
+        _ => TelnetError::Io(e),
+    }
+}
+
+/// Get the data a read of `requested` was answered with
+pub(crate) fn data_for(
+    command: &Command,
+    requested: Address,
+    response: Response,
+) -> Result<(Address, u8), TelnetError> {
+    match response {
+        Response::Data { address, value } if address == requested => Ok((address, value)),
+        // Only the first byte of a longer answer
+        Response::DataBlock { address, data } if address == requested => data
+            .first()
+            .map(|&value| (address, value))
+            .ok_or(TelnetError::Protocol(RolandError::InvalidResponse)),
+        Response::Data { address, .. } | Response::DataBlock { address, .. } => {
+            Err(TelnetError::AddressMismatch {
+                requested,
+                received: address,
+            })
+        }
+        Response::Error(e) => Err(TelnetError::device(command, e)),
+        _ => Err(TelnetError::Protocol(RolandError::InvalidResponse)),
+    }
+}
+
+/// Check the response to a write
+///
+/// Besides ACK, this takes the DTH some firmware answers with, as
+/// [`ParseOptions::write_answer`] allows.
+pub(crate) fn write_answered(
+    command: &Command,
+    response: Response,
+    options: ParseOptions,
+) -> Result<(), TelnetError> {
+    let written = match command {
+        Command::WriteParameter { value, .. } => std::slice::from_ref(value),
+        Command::WriteBlock { data, .. } => data.as_slice(),
+        _ => &[],
+    };
+    match response {
+        Response::Acknowledge => Ok(()),
+        Response::Error(e) => Err(TelnetError::device(command, e)),
+        response => match response.as_data() {
+            Some(_) if !answers_with(command, &response, options) => {
+                Err(TelnetError::Protocol(RolandError::InvalidResponse))
+            }
+            Some((address, answered))
+                if options.write_answer == WriteAnswer::DthSameValue && answered != written =>
+            {
+                Err(TelnetError::WriteMismatch {
+                    address,
+                    written: written.to_vec(),
+                    answered: answered.to_vec(),
+                })
+            }
+            Some(_) => Ok(()),
+            None => Err(TelnetError::Protocol(RolandError::InvalidResponse)),
+        },
+    }
+}
+
+/// Get the values a multi-byte read of `requested` was answered with
+fn block_for(
+    command: &Command,
+    requested: Address,
+    response: Response,
+) -> Result<Vec<u8>, TelnetError> {
+    match response.as_data() {
+        Some((address, data)) if address == requested => Ok(data.to_vec()),
+        Some((address, _)) => Err(TelnetError::AddressMismatch {
+            requested,
+            received: address,
+        }),
+        None => match response {
+            Response::Error(e) => Err(TelnetError::device(command, e)),
+            _ => Err(TelnetError::Protocol(RolandError::InvalidResponse)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+
+    #[test]
+    fn test_get_version() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_version("VR-6HD", "2.01");
+        let mut client = connect(addr);
+
+        let (product, version) = client.get_version().unwrap();
+        assert_eq!(product, "VR-6HD");
+        assert_eq!(version, "2.01");
+        assert_eq!(mock.received(), vec![Command::GetVersion]);
+
+        // Firmware with extra fields
+        mock.set_version("VR-6HD", "SYS,2.01");
+        let (product, version) = client.get_version().unwrap();
+        assert_eq!(product, "VR-6HD");
+        assert_eq!(version, "SYS,2.01");
+
+        let info = client.get_version_info().unwrap();
+        assert_eq!(info.product, ProductModel::Vr6Hd);
+        assert!(info.firmware.is_at_least(2, 0));
+        assert_eq!(info.firmware.as_str(), "SYS,2.01");
+    }
+
+    #[test]
+    fn test_write_then_read_parameter() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        client.write_parameter("123456", 0x7F).unwrap();
+        assert_eq!(mock.parameter(Address::new(0x12, 0x34, 0x56)), Some(0x7F));
+        assert_eq!(client.read_parameter("123456", 1).unwrap(), 0x7F);
+    }
+
+    #[test]
+    fn test_read_preseeded_parameter() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(Address::new(0x00, 0x01, 0x02), 0x05);
+        let mut client = connect(addr);
+
+        assert_eq!(client.read_parameter("000102", 1).unwrap(), 0x05);
+        assert_eq!(
+            mock.received(),
+            vec![Command::ReadParameter {
+                address: Address::new(0x00, 0x01, 0x02),
+                size: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_injected_error() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.inject_error(RolandError::OutOfRange);
+        let mut client = connect(addr);
+
+        match client.write_parameter("123456", 0xFF) {
+            Err(
+                e @ TelnetError::Device {
+                    error: RolandError::OutOfRange,
+                    ..
+                },
+            ) => assert_eq!(
+                e.to_string(),
+                "Device rejected DTH:123456,FF: Parameter out of range"
+            ),
+            other => panic!("Expected OutOfRange, got {:?}", other),
+        }
+        assert_eq!(mock.parameter(Address::new(0x12, 0x34, 0x56)), None);
+
+        // Only the next command fails
+        client.write_parameter("123456", 0x01).unwrap();
+    }
+
+    #[test]
+    fn test_delayed_response() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_delay(Duration::from_millis(300));
+        let mut client = connect(addr);
+
+        client.write_parameter("123456", 0x01).unwrap();
+        assert_eq!(mock.received().len(), 1);
+
+        // Longer than the client waits
+        client.set_timeouts(Timeouts {
+            write: Duration::from_millis(100),
+            ..Timeouts::default()
+        });
+        let start = Instant::now();
+        assert!(matches!(
+            client.write_parameter("123456", 0x02),
+            Err(TelnetError::Timeout)
+        ));
+        assert!(start.elapsed() < Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_unsolicited_between_write_and_ack() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let fader = Address::new(0x05, 0x00, 0x00);
+        mock.inject_unsolicited(fader, 0x40);
+        client.write_parameter("123456", 0x01).unwrap();
+
+        let events: Vec<DeviceEvent> = client.events().collect();
+        assert_eq!(
+            events,
+            vec![DeviceEvent::ParameterChanged {
+                address: fader,
+                value: 0x40,
+            }]
+        );
+        assert_eq!(client.events().count(), 0);
+    }
+
+    #[test]
+    fn test_unsolicited_before_read_response() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(Address::new(0x12, 0x34, 0x56), 0x10);
+        let mut client = connect(addr);
+
+        let fader = Address::new(0x05, 0x00, 0x00);
+        mock.inject_unsolicited(fader, 0x40);
+        assert_eq!(client.read_parameter("123456", 1).unwrap(), 0x10);
+        assert_eq!(
+            client.poll_event().unwrap(),
+            Some(DeviceEvent::ParameterChanged {
+                address: fader,
+                value: 0x40,
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_parameter_full() {
+        let (addr, mock) = MockDevice::spawn();
+        let address = Address::new(0x12, 0x34, 0x56);
+        mock.set_parameter(address, 0x10);
+        let mut client = connect(addr);
+
+        // A mismatched DTH arriving first doesn't answer the read
+        let fader = Address::new(0x05, 0x00, 0x00);
+        mock.inject_unsolicited(fader, 0x40);
+        assert_eq!(
+            client.read_parameter_full(address, 1).unwrap(),
+            (address, 0x10)
+        );
+        assert_eq!(client.events().count(), 1);
+
+        let err = data_for(
+            &Command::read(address, 1).unwrap(),
+            address,
+            Response::Data {
+                address: fader,
+                value: 0x40,
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            TelnetError::AddressMismatch { requested, received }
+                if requested == address && received == fader
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Requested 123456 but received data for 050000"
+        );
+    }
+
+    /// Mock with `100000..=100003` set to 1, 2, 3, 4
+    fn block_device() -> (std::net::SocketAddr, mock::MockHandle) {
+        let (addr, mock) = MockDevice::spawn();
+        for i in 0..4 {
+            mock.set_parameter(offset(Address::new(0x10, 0x00, 0x00), i), i as u8 + 1);
+        }
+        (addr, mock)
+    }
+
+    #[test]
+    fn test_read_parameter_bytes_single_frame() {
+        let (addr, mock) = block_device();
+        let start = Address::new(0x10, 0x00, 0x00);
+        let mut client = connect(addr);
+
+        assert_eq!(client.read_parameter_bytes(start, 4).unwrap(), [1, 2, 3, 4]);
+        assert_eq!(mock.received(), vec![Command::read(start, 4).unwrap()]);
+
+        // The device stops after two bytes
+        mock.inject_read_segments(&[(0, 2)]);
+        let err = client.read_parameter_bytes(start, 4).unwrap_err();
+        assert!(matches!(
+            err,
+            TelnetError::ShortRead {
+                requested: 4,
+                got: 2
+            }
+        ));
+        assert_eq!(err.to_string(), "Requested 4 bytes but received 2");
+    }
+
+    #[test]
+    fn test_read_parameter_bytes_multi_frame() {
+        let (addr, mock) = block_device();
+        let start = Address::new(0x10, 0x00, 0x00);
+        let mut client = connect(addr);
+
+        mock.inject_read_segments(&[(0, 1), (1, 2), (3, 1)]);
+        // Unrelated DTH frames in between are events
+        mock.inject_unsolicited(Address::new(0x05, 0x00, 0x00), 0x40);
+        assert_eq!(client.read_parameter_bytes(start, 4).unwrap(), [1, 2, 3, 4]);
+        assert_eq!(client.events().count(), 1);
+
+        // The client is in sync for the next command
+        assert_eq!(client.read_parameter_addr(start, 1).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_read_parameter_bytes_gap_and_overlap() {
+        let (addr, mock) = block_device();
+        let start = Address::new(0x10, 0x00, 0x00);
+        let mut client = connect(addr);
+
+        mock.inject_read_segments(&[(0, 2), (3, 1)]);
+        assert!(matches!(
+            client.read_parameter_bytes(start, 4),
+            Err(TelnetError::AddressMismatch { requested, received })
+                if requested == Address::new(0x10, 0x00, 0x02)
+                    && received == Address::new(0x10, 0x00, 0x03)
+        ));
+
+        mock.inject_read_segments(&[(0, 2), (1, 3)]);
+        assert!(matches!(
+            client.read_parameter_bytes(start, 4),
+            Err(TelnetError::AddressMismatch { requested, received })
+                if requested == Address::new(0x10, 0x00, 0x02)
+                    && received == Address::new(0x10, 0x00, 0x01)
+        ));
+    }
+
+    #[test]
+    fn test_u16_parameter() {
+        let (addr, mock) = MockDevice::spawn();
+        let start = Address::new(0x10, 0x00, 0x00);
+        let mut client = connect(addr);
+
+        client.write_u16_parameter(start, 0x0203, None).unwrap();
+        assert_eq!(mock.parameter(start), Some(0x02));
+        assert_eq!(mock.parameter(Address::new(0x10, 0x00, 0x01)), Some(0x03));
+        assert_eq!(client.read_u16_parameter(start).unwrap(), 0x0203);
+
+        // One command each way
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::WriteBlock {
+                    address: start,
+                    data: vec![0x02, 0x03],
+                },
+                Command::read(start, 2).unwrap(),
+            ]
+        );
+
+        assert!(matches!(
+            client.write_u16_parameter(start, 2001, Some(2000)),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+        assert_eq!(mock.received().len(), 2);
+    }
+
+    #[test]
+    fn test_nibble_parameter() {
+        let (addr, mock) = MockDevice::spawn();
+        let start = Address::new(0x10, 0x00, 0x00);
+        let mut client = connect(addr);
+
+        client.write_nibble_parameter(start, 0x1A3, 4).unwrap();
+        assert_eq!(
+            mock.received(),
+            vec![Command::WriteBlock {
+                address: start,
+                data: vec![0x00, 0x01, 0x0A, 0x03],
+            }]
+        );
+        assert_eq!(client.read_nibble_parameter(start, 4).unwrap(), 0x1A3);
+
+        // Too large for the nibbles, rejected before sending
+        assert!(matches!(
+            client.write_nibble_parameter(start, 0x100, 2),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+        assert_eq!(mock.received().len(), 2);
+
+        mock.set_parameter(start, 0x10);
+        assert!(matches!(
+            client.read_nibble_parameter(start, 2),
+            Err(TelnetError::Protocol(RolandError::InvalidValue))
+        ));
+    }
+
+    #[test]
+    fn test_connect_ipv6() {
+        let (addr, _mock) = MockDevice::spawn_on("[::1]:0".parse().unwrap());
+
+        let mut client = TelnetClient::connect("::1", addr.port()).unwrap();
+        assert!(client.get_version().is_ok());
+        let mut client = TelnetClient::connect("[::1]", addr.port()).unwrap();
+        assert!(client.get_version().is_ok());
+        let mut client = TelnetClient::connect_addr(addr).unwrap();
+        assert!(client.get_version().is_ok());
+    }
+
+    #[test]
+    fn test_connect_timeout() {
+        let (addr, _mock) = MockDevice::spawn();
+        let mut client = TelnetClient::connect_timeout(addr, Duration::from_secs(1)).unwrap();
+        assert!(client.get_version().is_ok());
+
+        // A hostname resolving to several addresses, IPv6 first or not
+        let mut client = TelnetClient::connect("localhost", addr.port()).unwrap();
+        assert!(client.get_version().is_ok());
+    }
+
+    #[test]
+    fn test_connect_errors() {
+        // A local port nothing listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        assert!(matches!(
+            TelnetClient::connect_with_timeout("127.0.0.1", port, Duration::from_secs(1)),
+            Err(TelnetError::ConnectionRefused(_))
+        ));
+        assert!(matches!(
+            TelnetClient::connect("127.0.0.1", port),
+            Err(TelnetError::ConnectionRefused(_))
+        ));
+
+        let error = |kind| connect_error(std::io::Error::from(kind));
+        assert!(matches!(error(ErrorKind::TimedOut), TelnetError::Timeout));
+        assert!(matches!(
+            error(ErrorKind::NetworkUnreachable),
+            TelnetError::HostUnreachable(_)
+        ));
+        assert!(matches!(
+            error(ErrorKind::PermissionDenied),
+            TelnetError::Io(_)
+        ));
+    }
+
+    #[test]
+    #[ignore = "depends on the routing and firewall of the host"]
+    fn test_connect_timeout_unrouted() {
+        // TEST-NET-1 is never routed. Depending on the network the attempt
+        // times out, finds no route or is rejected by a firewall, but it
+        // never takes longer than the timeout.
+        let start = Instant::now();
+        let result =
+            TelnetClient::connect_with_timeout("192.0.2.1", 23, Duration::from_millis(200));
+        assert!(matches!(
+            result,
+            Err(TelnetError::Timeout
+                | TelnetError::HostUnreachable(_)
+                | TelnetError::ConnectionRefused(_))
+        ));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_close_and_reconnect() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_max_sessions(1);
+
+        let mut client = connect(addr);
+        client.get_version().unwrap();
+        assert!(client.is_connected());
+
+        // The only session is taken
+        let mut rejected = connect(addr);
+        assert!(rejected.get_version().is_err());
+        assert!(!rejected.is_connected());
+
+        client.close().unwrap();
+        let mut client = connect(addr);
+        assert!(client.get_version().is_ok());
+    }
+
+    #[test]
+    fn test_read_size_out_of_range() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        for size in [0, 0x100_0000] {
+            assert!(matches!(
+                client.read_parameter("123456", size),
+                Err(TelnetError::Protocol(RolandError::OutOfRange))
+            ));
+        }
+        assert!(mock.received().is_empty());
+    }
+
+    #[test]
+    fn test_poll_event() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        assert_eq!(client.poll_event().unwrap(), None);
+
+        // Make sure the mock has registered the connection
+        client.get_version().unwrap();
+        let fader = Address::new(0x05, 0x01, 0x00);
+        mock.send_unsolicited(fader, 0x20);
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(2);
+        let event = loop {
+            if let Some(event) = client.poll_event().unwrap() {
+                break event;
+            }
+            assert!(std::time::Instant::now() < deadline, "no event received");
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(
+            event,
+            DeviceEvent::ParameterChanged {
+                address: fader,
+                value: 0x20,
+            }
+        );
+    }
+
+    #[test]
+    fn test_lenient_parsing() {
+        let (addr, mock) = MockDevice::spawn();
+        // Lowercase prefix and a line break instead of `;`
+        mock.set_preamble(b"dth:050100,20\r\n");
+
+        let mut client = connect(addr);
+        assert_eq!(client.parse_options(), ParseOptions::LENIENT);
+        client.get_version().unwrap();
+        assert_eq!(
+            client.events().collect::<Vec<_>>(),
+            vec![DeviceEvent::ParameterChanged {
+                address: Address::new(0x05, 0x01, 0x00),
+                value: 0x20,
+            }]
+        );
+
+        // A strict client reads on to the next `;` and can't parse that
+        let mut client = connect(addr);
+        client.set_parse_options(ParseOptions::STRICT);
+        assert!(matches!(
+            client.get_version(),
+            Err(TelnetError::Protocol(RolandError::InvalidResponse))
+        ));
+    }
+
+    #[test]
+    fn test_write_parameter_verified() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let address = Address::new(0x12, 0x34, 0x56);
+        assert_eq!(
+            client.write_parameter_verified(address, 0x40).unwrap(),
+            0x40
+        );
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::WriteParameter {
+                    address,
+                    value: 0x40,
+                },
+                Command::ReadParameter { address, size: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_parameter_verified_clamped() {
+        let (addr, mock) = MockDevice::spawn();
+        let address = Address::new(0x12, 0x34, 0x56);
+        mock.set_write_filter(address, |value| value.min(0x7F) & !1);
+        let mut client = connect(addr);
+
+        assert_eq!(
+            client.write_parameter_verified(address, 0xFF).unwrap(),
+            0x7E
+        );
+        assert_eq!(
+            client.write_parameter_verified(address, 0x21).unwrap(),
+            0x20
+        );
+    }
+
+    #[test]
+    fn test_write_parameter_verified_error() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.inject_error(RolandError::Invalid);
+        let mut client = connect(addr);
+
+        match client.write_parameter_verified(Address::new(0x12, 0x34, 0x56), 0x01) {
+            Err(TelnetError::Device {
+                error: RolandError::Invalid,
+                ..
+            }) => {}
+            other => panic!("Expected Invalid, got {:?}", other),
+        }
+        // The read-back is skipped when the write fails
+        assert_eq!(mock.received().len(), 1);
+    }
+
+    #[test]
+    fn test_error_display() {
+        let address = Address::new(0x12, 0x34, 0x56);
+        let io = || std::io::Error::other("reset");
+        let cases = [
+            (
+                TelnetError::Protocol(RolandError::OutOfRange),
+                "Protocol error: Parameter out of range",
+            ),
+            (
+                TelnetError::device(
+                    &Command::write_parameter(address, 0x7F),
+                    RolandError::OutOfRange,
+                ),
+                "Device rejected DTH:123456,7F: Parameter out of range",
+            ),
+            (
+                TelnetError::device(&Command::read(address, 2).unwrap(), RolandError::Invalid),
+                "Device rejected RQH:123456,000002: Invalid command due to other settings",
+            ),
+            (
+                TelnetError::device(&Command::GetVersion, RolandError::UnknownError(9)),
+                "Device rejected VER: Unknown error code: 9",
+            ),
+            (
+                TelnetError::device(
+                    &Command::WriteBlock {
+                        address,
+                        data: vec![1, 2, 3, 4, 5],
+                    },
+                    RolandError::SyntaxError,
+                ),
+                "Device rejected DTH:123456,01,02,03,04,... (5 bytes): \
+                 Syntax error in received command",
+            ),
+            (TelnetError::Io(io()), "I/O error: reset"),
+            (TelnetError::ConnectionClosed, "Connection closed"),
+            (
+                TelnetError::InvalidAddress("12345".to_string()),
+                "Invalid address format: \"12345\"",
+            ),
+            (TelnetError::Timeout, "Timed out waiting for the device"),
+            (
+                TelnetError::AddressMismatch {
+                    requested: address,
+                    received: Address::new(0, 0, 1),
+                },
+                "Requested 123456 but received data for 000001",
+            ),
+            (
+                TelnetError::ShortRead {
+                    requested: 4,
+                    got: 2,
+                },
+                "Requested 4 bytes but received 2",
+            ),
+            (
+                TelnetError::WriteMismatch {
+                    address,
+                    written: vec![0xFF],
+                    answered: vec![0x7F],
+                },
+                "Wrote FF to 123456 but the device answered 7F",
+            ),
+            (
+                TelnetError::HostUnreachable(io()),
+                "Device is off or unreachable: reset",
+            ),
+            (
+                TelnetError::ConnectionRefused(io()),
+                "Connection refused: reset",
+            ),
+            (
+                TelnetError::AuthFailed,
+                "Login failed: password missing or rejected",
+            ),
+            (TelnetError::Cancelled, "Operation cancelled"),
+        ];
+        for (error, display) in cases {
+            assert_eq!(error.to_string(), display);
+        }
+    }
+
+    #[test]
+    fn test_error_source() {
+        use std::error::Error;
+
+        let error = TelnetError::device(
+            &Command::write_parameter(Address::new(0x12, 0x34, 0x56), 0x7F),
+            RolandError::OutOfRange,
+        );
+        let source = error.source().unwrap();
+        assert_eq!(
+            source.downcast_ref::<RolandError>(),
+            Some(&RolandError::OutOfRange)
+        );
+        assert!(source.source().is_none());
+
+        let io = TelnetError::Io(std::io::Error::other("reset"));
+        assert!(io.source().unwrap().is::<std::io::Error>());
+        assert!(TelnetError::Protocol(RolandError::Timeout)
+            .source()
+            .unwrap()
+            .is::<RolandError>());
+        assert!(TelnetError::Timeout.source().is_none());
+    }
+
+    #[test]
+    fn test_dth_write_answer() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_dth_write_answer(true);
+        let address = Address::new(0x12, 0x34, 0x56);
+        let mut client = connect(addr);
+        client.set_parse_options(ParseOptions {
+            write_answer: WriteAnswer::Dth,
+            ..ParseOptions::LENIENT
+        });
+
+        // The DTH is taken as the answer
+        client.write_parameter_addr(address, 0x40).unwrap();
+        client
+            .write_parameter_block(address, &[0x01, 0x02])
+            .unwrap();
+        client.write_parameter_nowait(address, 0x03).unwrap();
+        client.wait_write_acks().unwrap();
+        assert_eq!(client.read_parameter_addr(address, 1).unwrap(), 0x03);
+        assert_eq!(client.poll_event().unwrap(), None);
+
+        // By default the client waits for an ACK that never comes
+        client.set_parse_options(ParseOptions::LENIENT);
+        client.set_timeouts(Timeouts {
+            write: Duration::from_millis(100),
+            ..Timeouts::default()
+        });
+        assert!(matches!(
+            client.write_parameter_addr(address, 0x04),
+            Err(TelnetError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn test_dth_write_answer_same_value() {
+        let (addr, mock) = MockDevice::spawn();
+        let address = Address::new(0x12, 0x34, 0x56);
+        mock.set_write_filter(address, |value| value.min(0x7F));
+        let mut client = connect(addr);
+        client.set_parse_options(ParseOptions {
+            write_answer: WriteAnswer::DthSameValue,
+            ..ParseOptions::LENIENT
+        });
+
+        // An ACK is still an answer
+        client.write_parameter_addr(address, 0xFF).unwrap();
+
+        mock.set_dth_write_answer(true);
+        client.write_parameter_addr(address, 0x20).unwrap();
+        match client.write_parameter_addr(address, 0xFF) {
+            Err(TelnetError::WriteMismatch {
+                address: a,
+                written,
+                answered,
+            }) => {
+                assert_eq!(a, address);
+                assert_eq!(written, vec![0xFF]);
+                assert_eq!(answered, vec![0x7F]);
+            }
+            other => panic!("Expected WriteMismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_typed_address() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let address = Address::new(0x12, 0x34, 0x56);
+        client.write_parameter_addr(address, 0x22).unwrap();
+        assert_eq!(client.read_parameter_addr(address, 1).unwrap(), 0x22);
+        assert_eq!(mock.parameter(address), Some(0x22));
+    }
+
+    #[test]
+    fn test_invalid_address_string() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let err = client.write_parameter("12345Z", 0x01).unwrap_err();
+        assert!(matches!(&err, TelnetError::InvalidAddress(a) if a == "12345Z"));
+        assert_eq!(err.to_string(), "Invalid address format: \"12345Z\"");
+        assert_eq!(
+            std::error::Error::source(&err).and_then(|e| e.downcast_ref::<RolandError>()),
+            Some(&RolandError::InvalidAddress)
+        );
+        assert!(matches!(
+            client.read_parameter("1234", 1),
+            Err(TelnetError::InvalidAddress(_))
+        ));
+        assert!(mock.received().is_empty());
+    }
 }
@@ -0,0 +1,206 @@
+//! Login to devices behind a banner or password prompt
+//!
+//! Some devices, and Telnet front-ends placed in front of them, greet a
+//! new session with a banner or ask for a password before taking protocol
+//! commands. [`TelnetClient::connect`] would read that text as the answer
+//! to the first command. [`TelnetClient::connect_with_login`] reads and
+//! discards the greeting, answers a password prompt, and returns once a
+//! `VER;` probe gets its answer.
+//!
+//! The greeting is over once the connection stays quiet for
+//! [`LoginOptions::quiet`]. A prompt is recognized at the end of the text
+//! received so far, ignoring case and trailing whitespace.
+
+use crate::{TelnetClient, TelnetError};
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+/// How the greeting of a session is recognized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginOptions {
+    /// Text a password prompt ends with
+    pub prompt: String,
+    /// Silence after which the greeting is taken to be over
+    pub quiet: Duration,
+    /// Longest time the whole login may take
+    pub timeout: Duration,
+}
+
+/// Prompt `Password:`, 200 ms of quiet and a 5 s timeout
+impl Default for LoginOptions {
+    fn default() -> Self {
+        Self {
+            prompt: "password:".to_string(),
+            quiet: Duration::from_millis(200),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl TelnetClient {
+    /// Connect and log in with the default [`LoginOptions`]
+    ///
+    /// # Arguments
+    /// * `host` - IP address or hostname, as for [`TelnetClient::connect`]
+    /// * `port` - Telnet port (default: 23)
+    /// * `password` - Answer to a password prompt, if one is expected
+    ///
+    /// # Returns
+    /// * `Result<Self, TelnetError>` - Client whose `VER;` probe was
+    ///   answered, `AuthFailed` if a password was asked for and not
+    ///   accepted or not given, or another error
+    pub fn connect_with_login(
+        host: &str,
+        port: u16,
+        password: Option<&str>,
+    ) -> Result<Self, TelnetError> {
+        Self::connect_with_login_options(host, port, password, &LoginOptions::default())
+    }
+
+    /// Connect and log in
+    ///
+    /// See [`TelnetClient::connect_with_login`]. A password is sent once;
+    /// a second prompt, or the connection closing after it, means it was
+    /// rejected.
+    pub fn connect_with_login_options(
+        host: &str,
+        port: u16,
+        password: Option<&str>,
+        options: &LoginOptions,
+    ) -> Result<Self, TelnetError> {
+        let mut client = Self::connect(host, port)?;
+        let answered = client.skip_greeting(password, options)?;
+        match client.get_version() {
+            Ok(_) => Ok(client),
+            // A rejected password can also just close the session or
+            // leave text that doesn't parse
+            Err(TelnetError::ConnectionClosed | TelnetError::Protocol(_)) if answered => {
+                Err(TelnetError::AuthFailed)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read and discard the greeting, answering a password prompt
+    ///
+    /// Returns whether a password was sent.
+    fn skip_greeting(
+        &mut self,
+        password: Option<&str>,
+        options: &LoginOptions,
+    ) -> Result<bool, TelnetError> {
+        let deadline = Instant::now() + options.timeout;
+        let mut text = Vec::new();
+        let mut answered = false;
+        let mut buf = [0u8; 1024];
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break Err(TelnetError::Timeout);
+            }
+            self.stream
+                .set_read_timeout(Some(options.quiet.min(remaining)))?;
+            let n = match self.stream.read(&mut buf) {
+                Ok(0) if answered => break Err(TelnetError::AuthFailed),
+                Ok(0) => break Err(TelnetError::ConnectionClosed),
+                Ok(n) => n,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    break Ok(answered)
+                }
+                Err(e) => break Err(e.into()),
+            };
+            let data = self.strip_negotiation(&buf[..n])?;
+            text.extend_from_slice(&data);
+            if !ends_with_prompt(&text, &options.prompt) {
+                continue;
+            }
+            match password {
+                Some(password) if !answered => {
+                    let line = format!("{}\r\n", password);
+                    self.stream.write_all(line.as_bytes())?;
+                    self.stream.flush()?;
+                    answered = true;
+                    text.clear();
+                }
+                _ => break Err(TelnetError::AuthFailed),
+            }
+        };
+        self.stream.set_read_timeout(Some(crate::TIMEOUT))?;
+        result
+    }
+}
+
+/// Check if `text` ends with `prompt`, ignoring case and trailing whitespace
+fn ends_with_prompt(text: &[u8], prompt: &str) -> bool {
+    let text = String::from_utf8_lossy(text);
+    let text = text.trim_end().to_ascii_lowercase();
+    !prompt.is_empty() && text.ends_with(&prompt.trim_end().to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDevice;
+
+    fn login(
+        addr: std::net::SocketAddr,
+        password: Option<&str>,
+    ) -> Result<TelnetClient, TelnetError> {
+        TelnetClient::connect_with_login(&addr.ip().to_string(), addr.port(), password)
+    }
+
+    #[test]
+    fn test_banner_and_clean_session() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_preamble(b"\r\nWelcome to the VR-6HD\r\n");
+        let mut client = login(addr, None).unwrap();
+        assert_eq!(client.get_version().unwrap().0, "VR-6HD");
+
+        let (addr, _mock) = MockDevice::spawn();
+        let mut client = login(addr, Some("unused")).unwrap();
+        assert_eq!(client.get_version().unwrap().0, "VR-6HD");
+    }
+
+    #[test]
+    fn test_password_prompt() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_preamble(b"VR-6HD remote\r\n");
+        mock.set_login("Password: ", "secret");
+
+        let mut client = login(addr, Some("secret")).unwrap();
+        assert_eq!(client.get_version().unwrap().1, "1.00");
+
+        assert!(matches!(
+            login(addr, Some("wrong")),
+            Err(TelnetError::AuthFailed)
+        ));
+        assert!(matches!(login(addr, None), Err(TelnetError::AuthFailed)));
+    }
+
+    #[test]
+    fn test_custom_prompt() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_login("Enter PIN > ", "1234");
+        let options = LoginOptions {
+            prompt: "pin >".to_string(),
+            ..LoginOptions::default()
+        };
+        let connect = |password| {
+            TelnetClient::connect_with_login_options(
+                &addr.ip().to_string(),
+                addr.port(),
+                password,
+                &options,
+            )
+        };
+        assert!(connect(Some("1234")).is_ok());
+
+        // Not recognized as a prompt, the probe fails instead
+        assert!(matches!(
+            login(addr, Some("1234")),
+            Err(TelnetError::Timeout)
+        ));
+        assert!(ends_with_prompt(b"Login\r\nPASSWORD:  ", "password:"));
+        assert!(!ends_with_prompt(b"password: ok", "password:"));
+    }
+}
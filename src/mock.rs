@@ -0,0 +1,1087 @@
+//! Mock VR-6HD device for integration testing
+//!
+//! [`MockDevice`] listens on a local TCP port and speaks the remote
+//! control protocol like a real VR-6HD: incoming commands are parsed with
+//! roland-core, parameters are kept in an in-memory store and responses
+//! are ACK/DTH/VER/ERR frames.
+//!
+//! A [`FaultPlan`] makes the device misbehave on chosen commands: late,
+//! lost, split or corrupted responses, bursts of unsolicited frames and
+//! dropped connections, to test how clients cope.
+//!
+//! This module is available in the crate's own tests and, for downstream
+//! crates, behind the `mock` feature.
+//!
+//! # Example
+//! ```ignore
+//! use roland_rs::mock::MockDevice;
+//! use roland_rs::{Address, TelnetClient};
+//!
+//! let (addr, mock) = MockDevice::spawn();
+//! mock.set_parameter(Address::new(0x12, 0x34, 0x56), 0x7F);
+//!
+//! let mut client = TelnetClient::connect(&addr.ip().to_string(), addr.port()).unwrap();
+//! assert_eq!(client.read_parameter("123456", 1).unwrap(), 0x7F);
+//! ```
+
+use crate::offset;
+use crate::telnet::Iac;
+use roland_core::{Address, Command, Response, RolandError};
+use std::collections::{HashMap, VecDeque};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often the server threads check whether they should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Misbehavior of the device when answering a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Wait before answering
+    Delay(Duration),
+    /// Wait a random time in `min..=max` before answering
+    RandomDelay {
+        /// Shortest delay
+        min: Duration,
+        /// Longest delay
+        max: Duration,
+    },
+    /// Handle the command but send no response
+    Drop,
+    /// Send the response `chunk` bytes at a time, waiting `gap` between
+    /// the writes
+    Split {
+        /// Bytes per write, at least 1
+        chunk: usize,
+        /// Wait between writes
+        gap: Duration,
+    },
+    /// Replace the byte at `index` of the response, if it is that long
+    Corrupt {
+        /// Offset in the encoded response
+        index: usize,
+        /// Byte to put there
+        byte: u8,
+    },
+    /// Send `count` unsolicited DTH frames for `address`, with values
+    /// counting up from 0, before the response
+    Burst {
+        /// Address of the frames
+        address: Address,
+        /// Number of frames
+        count: u8,
+    },
+    /// Close the connection instead of answering
+    Disconnect,
+}
+
+/// Faults to inject, by command
+///
+/// Commands are counted from 1 across all sessions, starting when the
+/// plan is set with [`MockHandle::set_fault_plan`]. A command gets the
+/// faults added for its number and those added for every command, in the
+/// order they were added.
+///
+/// # Example
+/// ```ignore
+/// use roland_rs::mock::{Fault, FaultPlan, MockDevice};
+/// use std::time::Duration;
+///
+/// let mut plan = FaultPlan::new();
+/// plan.add(2, Fault::Drop);
+/// plan.add_every(Fault::Delay(Duration::from_millis(5)));
+///
+/// let (addr, mock) = MockDevice::spawn();
+/// mock.set_fault_plan(plan);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultPlan {
+    /// Command number, `None` for every command, and the fault
+    faults: Vec<(Option<usize>, Fault)>,
+    /// State of the generator for random delays
+    seed: u64,
+}
+
+impl FaultPlan {
+    /// Create an empty plan
+    pub fn new() -> Self {
+        Self {
+            faults: Vec::new(),
+            seed: 0x2545_F491_4F6C_DD1D,
+        }
+    }
+
+    /// Inject a fault when answering command `n`, counting from 1
+    pub fn add(&mut self, n: usize, fault: Fault) {
+        self.faults.push((Some(n), fault));
+    }
+
+    /// Inject a fault when answering every command
+    pub fn add_every(&mut self, fault: Fault) {
+        self.faults.push((None, fault));
+    }
+
+    /// Seed the random delays, so a failing run can be repeated
+    pub fn set_seed(&mut self, seed: u64) {
+        // Xorshift never leaves 0
+        self.seed = seed.max(1);
+    }
+
+    /// Get the faults for command `n`, with random delays resolved
+    fn faults_for(&mut self, n: usize) -> Vec<Fault> {
+        let Self { faults, seed } = self;
+        faults
+            .iter()
+            .filter(|(at, _)| at.is_none_or(|at| at == n))
+            .map(|(_, fault)| match fault {
+                Fault::RandomDelay { min, max } => {
+                    let range = max.saturating_sub(*min).as_micros() as u64;
+                    let random = next_random(seed) % (range + 1);
+                    Fault::Delay(*min + Duration::from_micros(random))
+                }
+                fault => fault.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Advance the xorshift generator of a [`FaultPlan`]
+fn next_random(seed: &mut u64) -> u64 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    *seed
+}
+
+impl Default for FaultPlan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Mock VR-6HD device
+pub struct MockDevice;
+
+impl MockDevice {
+    /// Spawn a mock device listening on a free local port
+    ///
+    /// # Returns
+    /// * `(SocketAddr, MockHandle)` - Address to connect to and a handle
+    ///   controlling the device. Dropping the handle stops the device.
+    pub fn spawn() -> (SocketAddr, MockHandle) {
+        Self::spawn_on("127.0.0.1:0".parse().unwrap())
+    }
+
+    /// Spawn a mock device listening on the given address
+    ///
+    /// Use port 0 for a free port, e.g. `[::1]:0` for an IPv6 device.
+    ///
+    /// # Returns
+    /// * `(SocketAddr, MockHandle)` - Address to connect to and a handle
+    ///   controlling the device. Dropping the handle stops the device.
+    pub fn spawn_on(bind: SocketAddr) -> (SocketAddr, MockHandle) {
+        let listener = TcpListener::bind(bind).expect("failed to bind mock device");
+        let addr = listener.local_addr().expect("failed to get mock address");
+        listener
+            .set_nonblocking(true)
+            .expect("failed to configure mock listener");
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || accept_loop(listener, state, stop))
+        };
+
+        let handle = MockHandle {
+            state,
+            stop,
+            thread: Some(thread),
+        };
+        (addr, handle)
+    }
+
+    /// Spawn a mock device answering datagrams on a free local UDP port
+    ///
+    /// Each datagram may hold several commands; the reply to each is sent
+    /// back as one datagram to where the command came from, or as several
+    /// with [`Fault::Split`]. [`Fault::Disconnect`] drops the reply, and
+    /// [`MockHandle::send_unsolicited`] reaches only TCP clients.
+    ///
+    /// # Returns
+    /// * `(SocketAddr, MockHandle)` - Address to send to and a handle
+    ///   controlling the device. Dropping the handle stops the device.
+    pub fn spawn_udp() -> (SocketAddr, MockHandle) {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("failed to bind mock device");
+        let addr = socket.local_addr().expect("failed to get mock address");
+        socket
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .expect("failed to configure mock socket");
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || udp_loop(socket, state, stop))
+        };
+
+        let handle = MockHandle {
+            state,
+            stop,
+            thread: Some(thread),
+        };
+        (addr, handle)
+    }
+}
+
+/// Connect a client to a mock device, for the crate's tests
+#[cfg(test)]
+pub(crate) fn connect(addr: SocketAddr) -> crate::TelnetClient {
+    crate::TelnetClient::connect(&addr.ip().to_string(), addr.port()).unwrap()
+}
+
+/// Handle controlling a running [`MockDevice`]
+pub struct MockHandle {
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockHandle {
+    /// Preseed a parameter value
+    pub fn set_parameter(&self, address: Address, value: u8) {
+        self.state().parameters.insert(address, value);
+    }
+
+    /// Get the current value of a parameter
+    ///
+    /// Returns `None` if the parameter was never written or preseeded.
+    pub fn parameter(&self, address: Address) -> Option<u8> {
+        self.state().parameters.get(&address).copied()
+    }
+
+    /// Set the product and version strings reported by `VER`
+    pub fn set_version(&self, product: &str, version: &str) {
+        let mut state = self.state();
+        state.product = product.to_string();
+        state.version = version.to_string();
+    }
+
+    /// Answer the next command with an error instead of handling it
+    ///
+    /// Injected errors are queued, so calling this twice fails the next
+    /// two commands.
+    pub fn inject_error(&self, error: RolandError) {
+        self.state().errors.push_back(error);
+    }
+
+    /// Transform values written to `address` before storing them
+    ///
+    /// This simulates parameters that the device clamps or quantizes.
+    pub fn set_write_filter(&self, address: Address, filter: impl Fn(u8) -> u8 + Send + 'static) {
+        self.state().filters.insert(address, Box::new(filter));
+    }
+
+    /// Answer every command addressing `address` with an error
+    ///
+    /// Block reads and writes covering `address` fail as well.
+    pub fn set_address_error(&self, address: Address, error: RolandError) {
+        self.state().address_errors.insert(address, error);
+    }
+
+    /// Answer the next multi-byte read with several DTH frames
+    ///
+    /// Each segment is an `(offset, len)` range of the requested bytes,
+    /// relative to the start address, and becomes one frame. Segments may
+    /// leave gaps, overlap or stop short of the requested size, to
+    /// simulate a device that splits or truncates large reads. Without
+    /// this, a read is answered with a single frame.
+    pub fn inject_read_segments(&self, segments: &[(usize, usize)]) {
+        self.state().read_segments.push_back(segments.to_vec());
+    }
+
+    /// Pause the client with XOFF after the reply to the next command
+    ///
+    /// The device sends XON again once `duration` has passed. Bytes the
+    /// client sends in between are counted, see [`MockHandle::xoff_violations`].
+    pub fn inject_xoff(&self, duration: Duration) {
+        self.state().pauses.push_back(duration);
+    }
+
+    /// Number of bytes the client sent while paused by XOFF
+    pub fn xoff_violations(&self) -> usize {
+        self.state().xoff_violations
+    }
+
+    /// Largest number of commands seen waiting for a reply at once
+    ///
+    /// This shows how deeply a client pipelines commands.
+    pub fn max_pending(&self) -> usize {
+        self.state().max_pending
+    }
+
+    /// Push an unsolicited DTH to every connected client right away
+    ///
+    /// This simulates an operator changing the parameter on the panel, so
+    /// the stored value is updated as well.
+    pub fn send_unsolicited(&self, address: Address, value: u8) {
+        let mut state = self.state();
+        state.parameters.insert(address, value);
+        let frame = Response::Data { address, value }.encode();
+        state
+            .clients
+            .retain_mut(|client| client.write_all(frame.as_bytes()).is_ok());
+    }
+
+    /// Send an unsolicited DTH just before the reply to the next command
+    ///
+    /// This is the worst case for a client: a frame that isn't the
+    /// response arrives while it is waiting for one.
+    pub fn inject_unsolicited(&self, address: Address, value: u8) {
+        self.state().unsolicited.push_back((address, value));
+    }
+
+    /// Limit the number of concurrent sessions, like the real device
+    ///
+    /// Connections beyond the limit are accepted and closed right away.
+    /// A session ends when the client closes its side of the connection.
+    pub fn set_max_sessions(&self, max: usize) {
+        self.state().max_sessions = Some(max);
+    }
+
+    /// Delay every response by the given duration
+    pub fn set_delay(&self, delay: Duration) {
+        self.state().delay = delay;
+    }
+
+    /// Echo every command back before answering it, like a device with
+    /// local echo turned on
+    pub fn set_echo(&self, echo: bool) {
+        self.state().echo = echo;
+    }
+
+    /// Answer writes with a DTH of the values stored instead of ACK, like
+    /// 2.x firmware
+    ///
+    /// Values changed by a [`MockHandle::set_write_filter`] are answered
+    /// as stored.
+    pub fn set_dth_write_answer(&self, dth: bool) {
+        self.state().dth_write_answer = dth;
+    }
+
+    /// Inject faults as described by a plan, replacing any earlier plan
+    ///
+    /// Set the plan before the client connects, so its first command is
+    /// command 1 of the plan.
+    pub fn set_fault_plan(&self, plan: FaultPlan) {
+        let mut state = self.state();
+        state.fault_plan = Some(plan);
+        state.fault_commands = 0;
+    }
+
+    /// Get all commands received so far, in order
+    pub fn received(&self) -> Vec<Command> {
+        self.state().received.clone()
+    }
+
+    /// Forget the commands received so far
+    pub fn clear_received(&self) {
+        self.state().received.clear();
+    }
+
+    /// Send raw bytes to every client as soon as it connects
+    ///
+    /// Use this to open sessions with Telnet option negotiation, like a
+    /// Telnet-aware gateway in front of the device.
+    pub fn set_preamble(&self, bytes: &[u8]) {
+        self.state().preamble = bytes.to_vec();
+    }
+
+    /// Ask every client for a password after the preamble
+    ///
+    /// `prompt` is sent, then one line is read. A wrong password gets
+    /// `Login incorrect` and the prompt again; the session is closed after
+    /// three wrong ones.
+    pub fn set_login(&self, prompt: &str, password: &str) {
+        self.state().login = Some((prompt.to_string(), password.to_string()));
+    }
+
+    /// Get the raw Telnet option negotiation clients sent so far
+    pub fn negotiation(&self) -> Vec<u8> {
+        self.state().negotiation.clone()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Drop for MockHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Shared device state
+struct State {
+    parameters: HashMap<Address, u8>,
+    received: Vec<Command>,
+    errors: VecDeque<RolandError>,
+    unsolicited: VecDeque<(Address, u8)>,
+    address_errors: HashMap<Address, RolandError>,
+    filters: HashMap<Address, Box<dyn Fn(u8) -> u8 + Send>>,
+    pauses: VecDeque<Duration>,
+    read_segments: VecDeque<Vec<(usize, usize)>>,
+    xoff_violations: usize,
+    max_pending: usize,
+    clients: Vec<TcpStream>,
+    sessions: usize,
+    max_sessions: Option<usize>,
+    preamble: Vec<u8>,
+    login: Option<(String, String)>,
+    negotiation: Vec<u8>,
+    delay: Duration,
+    echo: bool,
+    dth_write_answer: bool,
+    fault_plan: Option<FaultPlan>,
+    fault_commands: usize,
+    product: String,
+    version: String,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            parameters: HashMap::new(),
+            received: Vec::new(),
+            errors: VecDeque::new(),
+            unsolicited: VecDeque::new(),
+            address_errors: HashMap::new(),
+            filters: HashMap::new(),
+            pauses: VecDeque::new(),
+            read_segments: VecDeque::new(),
+            xoff_violations: 0,
+            max_pending: 0,
+            clients: Vec::new(),
+            sessions: 0,
+            max_sessions: None,
+            preamble: Vec::new(),
+            login: None,
+            negotiation: Vec::new(),
+            delay: Duration::ZERO,
+            echo: false,
+            dth_write_answer: false,
+            fault_plan: None,
+            fault_commands: 0,
+            product: "VR-6HD".to_string(),
+            version: "1.00".to_string(),
+        }
+    }
+}
+
+fn accept_loop(listener: TcpListener, state: Arc<Mutex<State>>, stop: Arc<AtomicBool>) {
+    let mut connections = Vec::new();
+
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                {
+                    let mut state = state.lock().unwrap();
+                    if state.max_sessions.is_some_and(|max| state.sessions >= max) {
+                        continue;
+                    }
+                    state.sessions += 1;
+                }
+                let state = Arc::clone(&state);
+                let stop = Arc::clone(&stop);
+                connections.push(thread::spawn(move || serve(stream, state, stop)));
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(_) => break,
+        }
+    }
+
+    for connection in connections {
+        let _ = connection.join();
+    }
+}
+
+fn udp_loop(socket: UdpSocket, state: Arc<Mutex<State>>, stop: Arc<AtomicBool>) {
+    let mut buf = [0u8; 1024];
+
+    while !stop.load(Ordering::SeqCst) {
+        let (n, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(_) => break,
+        };
+        let datagram = String::from_utf8_lossy(&buf[..n]).into_owned();
+        for frame in datagram.split_inclusive(';') {
+            if frame.trim().is_empty() {
+                continue;
+            }
+            let reply = handle_command(frame.trim(), &state);
+            if !reply.delay.is_zero() {
+                thread::sleep(reply.delay);
+            }
+            let Faulted::Send { data, split } = inject_faults(&state, reply.frames.into_bytes())
+            else {
+                continue;
+            };
+            let (chunk, gap) = split.unwrap_or((data.len().max(1), Duration::ZERO));
+            for (i, part) in data.chunks(chunk).enumerate() {
+                if i > 0 {
+                    thread::sleep(gap);
+                }
+                let _ = socket.send_to(part, peer);
+            }
+        }
+    }
+}
+
+/// Ends a session when its connection thread returns
+struct Session {
+    state: Arc<Mutex<State>>,
+    peer: Option<SocketAddr>,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.sessions -= 1;
+        // Dropping the clone lets the socket close
+        let peer = self.peer;
+        state
+            .clients
+            .retain(|client| client.peer_addr().is_ok_and(|addr| Some(addr) != peer));
+    }
+}
+
+fn serve(mut stream: TcpStream, state: Arc<Mutex<State>>, stop: Arc<AtomicBool>) {
+    let _session = Session {
+        state: Arc::clone(&state),
+        peer: stream.peer_addr().ok(),
+    };
+    if stream.set_nonblocking(false).is_err()
+        || stream.set_read_timeout(Some(POLL_INTERVAL)).is_err()
+    {
+        return;
+    }
+    if let Ok(client) = stream.try_clone() {
+        state.lock().unwrap().clients.push(client);
+    }
+    let preamble = state.lock().unwrap().preamble.clone();
+    if send(&mut stream, &state, &preamble).is_err() {
+        return;
+    }
+
+    let mut buffer = Vec::new();
+    let mut buf = [0u8; 1024];
+    let mut iac = Iac::default();
+    let login = state.lock().unwrap().login.clone();
+    if let Some((prompt, password)) = login {
+        if !log_in(
+            &mut stream,
+            &mut iac,
+            &state,
+            &stop,
+            &mut buffer,
+            &prompt,
+            &password,
+        ) {
+            return;
+        }
+    }
+
+    while !stop.load(Ordering::SeqCst) {
+        let n = match stream.read(&mut buf) {
+            Ok(0) => return,
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(_) => return,
+        };
+        receive(&mut iac, &state, &buf[..n], &mut buffer);
+
+        // Every command is terminated by ';'
+        while let Some(end) = buffer.iter().position(|&b| b == b';') {
+            let frame: Vec<u8> = buffer.drain(..=end).collect();
+            let frame = String::from_utf8_lossy(&frame);
+
+            // Count the commands the client sent without waiting for a reply
+            if read_available(&mut stream, &mut iac, &state, &mut buffer).is_err() {
+                return;
+            }
+            let pending = 1 + buffer.iter().filter(|&&b| b == b';').count();
+            {
+                let mut state = state.lock().unwrap();
+                state.max_pending = state.max_pending.max(pending);
+            }
+
+            let echo = state.lock().unwrap().echo;
+            if echo && send(&mut stream, &state, frame.trim().as_bytes()).is_err() {
+                return;
+            }
+            let mut reply = handle_command(frame.trim(), &state);
+            if !reply.delay.is_zero() {
+                thread::sleep(reply.delay);
+            }
+            // XOFF goes out together with the reply, like a device whose
+            // input buffer filled up while handling the command
+            if reply.pause.is_some() {
+                reply.frames.push('\x13');
+            }
+            if !send_with_faults(&mut stream, &state, reply.frames.into_bytes()) {
+                return;
+            }
+
+            if let Some(pause) = reply.pause {
+                thread::sleep(pause);
+                match read_available(&mut stream, &mut iac, &state, &mut buffer) {
+                    Ok(violations) => state.lock().unwrap().xoff_violations += violations,
+                    Err(_) => return,
+                }
+                if send(&mut stream, &state, b"\x11").is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Ask for a password until the right one arrives
+///
+/// Returns false if the session should be closed.
+fn log_in(
+    stream: &mut TcpStream,
+    iac: &mut Iac,
+    state: &Mutex<State>,
+    stop: &AtomicBool,
+    buffer: &mut Vec<u8>,
+    prompt: &str,
+    password: &str,
+) -> bool {
+    let mut buf = [0u8; 1024];
+    for _ in 0..3 {
+        if send(stream, state, prompt.as_bytes()).is_err() {
+            return false;
+        }
+        let line = loop {
+            if let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                break String::from_utf8_lossy(&line).trim().to_string();
+            }
+            if stop.load(Ordering::SeqCst) {
+                return false;
+            }
+            match stream.read(&mut buf) {
+                Ok(0) => return false,
+                Ok(n) => receive(iac, state, &buf[..n], buffer),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => return false,
+            }
+        };
+        if line == password {
+            return send(stream, state, b"\r\nWelcome\r\n").is_ok();
+        }
+        if send(stream, state, b"\r\nLogin incorrect\r\n").is_err() {
+            return false;
+        }
+    }
+    false
+}
+
+/// Reply after injecting the faults planned for its command
+enum Faulted {
+    /// Send the bytes, `chunk` at a time with `gap` in between if split
+    Send {
+        data: Vec<u8>,
+        split: Option<(usize, Duration)>,
+    },
+    /// Close the connection instead
+    Disconnect,
+}
+
+/// Inject the faults planned for the next command into its reply
+fn inject_faults(state: &Mutex<State>, mut reply: Vec<u8>) -> Faulted {
+    let faults = {
+        let mut state = state.lock().unwrap();
+        state.fault_commands += 1;
+        let n = state.fault_commands;
+        match state.fault_plan.as_mut() {
+            Some(plan) => plan.faults_for(n),
+            None => Vec::new(),
+        }
+    };
+
+    let mut split = None;
+    for fault in faults {
+        match fault {
+            Fault::Delay(delay) => thread::sleep(delay),
+            Fault::RandomDelay { .. } => unreachable!("resolved by the plan"),
+            Fault::Drop => reply.clear(),
+            Fault::Split { chunk, gap } => split = Some((chunk.max(1), gap)),
+            Fault::Corrupt { index, byte } => {
+                if let Some(b) = reply.get_mut(index) {
+                    *b = byte;
+                }
+            }
+            Fault::Burst { address, count } => {
+                let mut frames = Vec::new();
+                for value in 0..count {
+                    frames.extend_from_slice(Response::Data { address, value }.encode().as_bytes());
+                }
+                if let Some(last) = count.checked_sub(1) {
+                    state.lock().unwrap().parameters.insert(address, last);
+                }
+                reply.splice(0..0, frames);
+            }
+            Fault::Disconnect => return Faulted::Disconnect,
+        }
+    }
+    Faulted::Send { data: reply, split }
+}
+
+/// Send a reply, injecting the faults planned for its command
+///
+/// Returns false if the session should be closed.
+fn send_with_faults(stream: &mut TcpStream, state: &Mutex<State>, reply: Vec<u8>) -> bool {
+    match inject_faults(state, reply) {
+        Faulted::Disconnect => {
+            let _ = stream.shutdown(Shutdown::Both);
+            false
+        }
+        Faulted::Send { data, split: None } => send(stream, state, &data).is_ok(),
+        Faulted::Send {
+            data,
+            split: Some((chunk, gap)),
+        } => {
+            for (i, part) in data.chunks(chunk).enumerate() {
+                if i > 0 {
+                    thread::sleep(gap);
+                }
+                if send(stream, state, part).is_err() {
+                    return false;
+                }
+            }
+            true
+        }
+    }
+}
+
+/// Send bytes to the client
+///
+/// The state lock is held while writing so replies never interleave with
+/// frames pushed by `send_unsolicited`.
+fn send(stream: &mut TcpStream, state: &Mutex<State>, data: &[u8]) -> std::io::Result<()> {
+    let _state = state.lock().unwrap();
+    stream.write_all(data)
+}
+
+/// Append received bytes to `buffer`, recording any IAC negotiation
+fn receive(iac: &mut Iac, state: &Mutex<State>, bytes: &[u8], buffer: &mut Vec<u8>) {
+    let negotiation = iac.filter(bytes, buffer);
+    let mut state = state.lock().unwrap();
+    for negotiation in negotiation {
+        state.negotiation.extend_from_slice(&negotiation.to_bytes());
+    }
+}
+
+/// Read everything the client has sent so far without blocking
+///
+/// Returns the number of bytes read.
+fn read_available(
+    stream: &mut TcpStream,
+    iac: &mut Iac,
+    state: &Mutex<State>,
+    buffer: &mut Vec<u8>,
+) -> std::io::Result<usize> {
+    let mut buf = [0u8; 1024];
+    let mut total = 0;
+
+    stream.set_nonblocking(true)?;
+    let result = loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                receive(iac, state, &buf[..n], buffer);
+                total += n;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(total),
+            Err(e) => break Err(e),
+        }
+    };
+    stream.set_nonblocking(false)?;
+    result
+}
+
+/// Reply to a single command
+struct Reply {
+    /// Encoded frames to send
+    frames: String,
+    /// Delay before sending
+    delay: Duration,
+    /// XOFF pause after sending
+    pause: Option<Duration>,
+}
+
+/// Handle a single command frame and build the reply
+fn handle_command(frame: &str, state: &Mutex<State>) -> Reply {
+    let mut state = state.lock().unwrap();
+    let mut reply = Reply {
+        frames: String::new(),
+        delay: state.delay,
+        pause: None,
+    };
+
+    while let Some((address, value)) = state.unsolicited.pop_front() {
+        state.parameters.insert(address, value);
+        reply
+            .frames
+            .push_str(&Response::Data { address, value }.encode());
+    }
+
+    let command = match Command::parse(frame) {
+        Ok(command) => command,
+        Err(e) => {
+            reply.frames.push_str(&Response::Error(e).encode());
+            return reply;
+        }
+    };
+    state.received.push(command.clone());
+    reply.pause = state.pauses.pop_front();
+
+    let address_error = match &command {
+        Command::WriteParameter { address, .. } => state.address_errors.get(address).cloned(),
+        Command::ReadParameter { address, size } => (0..*size as usize)
+            .find_map(|i| state.address_errors.get(&offset(*address, i)))
+            .cloned(),
+        Command::WriteBlock { address, data } => (0..data.len())
+            .find_map(|i| state.address_errors.get(&offset(*address, i)))
+            .cloned(),
+        _ => None,
+    };
+    if let Some(error) = state.errors.pop_front().or(address_error) {
+        reply.frames.push_str(&Response::Error(error).encode());
+        return reply;
+    }
+
+    let response = match command {
+        Command::WriteParameter { address, value } => {
+            let value = match state.filters.get(&address) {
+                Some(filter) => filter(value),
+                None => value,
+            };
+            state.parameters.insert(address, value);
+            if state.dth_write_answer {
+                Response::Data { address, value }
+            } else {
+                Response::Acknowledge
+            }
+        }
+        Command::WriteBlock { address, data } => {
+            let mut stored = Vec::with_capacity(data.len());
+            for (i, value) in data.into_iter().enumerate() {
+                let address = offset(address, i);
+                let value = match state.filters.get(&address) {
+                    Some(filter) => filter(value),
+                    None => value,
+                };
+                state.parameters.insert(address, value);
+                stored.push(value);
+            }
+            if state.dth_write_answer {
+                Response::DataBlock {
+                    address,
+                    data: stored,
+                }
+            } else {
+                Response::Acknowledge
+            }
+        }
+        Command::ReadParameter { address, size: 1 } => Response::Data {
+            address,
+            value: state.parameters.get(&address).copied().unwrap_or(0),
+        },
+        Command::ReadParameter { address, size } => {
+            let segments = state
+                .read_segments
+                .pop_front()
+                .unwrap_or_else(|| vec![(0, size as usize)]);
+            for (start, len) in segments {
+                let data = (start..start + len)
+                    .map(|i| {
+                        let address = offset(address, i);
+                        state.parameters.get(&address).copied().unwrap_or(0)
+                    })
+                    .collect();
+                let address = offset(address, start);
+                reply
+                    .frames
+                    .push_str(&Response::DataBlock { address, data }.encode());
+            }
+            return reply;
+        }
+        Command::GetVersion => Response::Version {
+            product: state.product.clone(),
+            version: state.version.clone(),
+        },
+        _ => Response::Error(RolandError::SyntaxError),
+    };
+    reply.frames.push_str(&response.encode());
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::{RetryClass, RetryPolicy};
+    use crate::shared::SharedClient;
+    use crate::timeouts::Timeouts;
+    use crate::{DeviceEvent, TelnetClient, TelnetError};
+    use std::time::Instant;
+
+    const ADDRESS: Address = Address::new(0x12, 0x34, 0x56);
+
+    fn connect(addr: SocketAddr) -> TelnetClient {
+        let mut client = super::connect(addr);
+        let timeout = Duration::from_millis(200);
+        client.set_timeouts(Timeouts {
+            write: timeout,
+            scene: timeout,
+            capture: timeout,
+            other: timeout,
+        });
+        client
+    }
+
+    fn spawn_with(faults: &[(usize, Fault)]) -> (SocketAddr, MockHandle) {
+        let (addr, mock) = MockDevice::spawn();
+        let mut plan = FaultPlan::new();
+        for (n, fault) in faults {
+            plan.add(*n, fault.clone());
+        }
+        mock.set_fault_plan(plan);
+        mock.set_parameter(ADDRESS, 0x7F);
+        (addr, mock)
+    }
+
+    #[test]
+    fn test_delays() {
+        let (addr, mock) = spawn_with(&[(1, Fault::Delay(Duration::from_millis(400)))]);
+        let mut client = connect(addr);
+        assert!(matches!(
+            client.read_parameter_addr(ADDRESS, 1),
+            Err(TelnetError::Timeout)
+        ));
+
+        let mut plan = FaultPlan::new();
+        plan.set_seed(7);
+        plan.add_every(Fault::RandomDelay {
+            min: Duration::from_millis(20),
+            max: Duration::from_millis(60),
+        });
+        mock.set_fault_plan(plan);
+        let mut client = connect(addr);
+        for _ in 0..3 {
+            let start = Instant::now();
+            assert_eq!(client.read_parameter_addr(ADDRESS, 1).unwrap(), 0x7F);
+            assert!(start.elapsed() >= Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_dropped_response() {
+        let (addr, _mock) = spawn_with(&[(1, Fault::Drop), (2, Fault::Drop)]);
+        let mut client = connect(addr);
+        assert!(matches!(
+            client.write_parameter_addr(ADDRESS, 0x01),
+            Err(TelnetError::Timeout)
+        ));
+
+        // Retrying on timeouts gets past the second dropped response
+        client.set_retry_policy(RetryPolicy {
+            max_attempts: 2,
+            backoff: Duration::from_millis(1),
+            retry_on: vec![RetryClass::Timeout],
+        });
+        assert_eq!(client.read_parameter_addr(ADDRESS, 1).unwrap(), 0x01);
+    }
+
+    #[test]
+    fn test_split_and_corrupted_responses() {
+        let split = Fault::Split {
+            chunk: 1,
+            gap: Duration::from_millis(5),
+        };
+        let corrupt = Fault::Corrupt {
+            index: 1,
+            byte: b'X',
+        };
+        let (addr, _mock) = spawn_with(&[(1, split), (2, corrupt)]);
+        let mut client = connect(addr);
+        assert_eq!(client.read_parameter_addr(ADDRESS, 1).unwrap(), 0x7F);
+        assert!(matches!(
+            client.read_parameter_addr(ADDRESS, 1),
+            Err(TelnetError::Protocol(_))
+        ));
+    }
+
+    #[test]
+    fn test_unsolicited_burst() {
+        let other = Address::new(0x01, 0x00, 0x00);
+        let burst = Fault::Burst {
+            address: other,
+            count: 5,
+        };
+        let (addr, _mock) = spawn_with(&[(1, burst)]);
+        let mut client = connect(addr);
+        assert_eq!(client.read_parameter_addr(ADDRESS, 1).unwrap(), 0x7F);
+        let events: Vec<_> = client.events().collect();
+        assert_eq!(events.len(), 5);
+        assert!(matches!(
+            events[4],
+            DeviceEvent::ParameterChanged { address, value: 4 } if address == other
+        ));
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let (addr, _mock) = spawn_with(&[(2, Fault::Disconnect)]);
+        let mut client = connect(addr);
+        client.get_version().unwrap();
+        assert!(matches!(
+            client.read_parameter_addr(ADDRESS, 1),
+            Err(TelnetError::ConnectionClosed | TelnetError::Io(_))
+        ));
+
+        // A shared client notices and carries on with a new connection
+        let (addr, _mock) = spawn_with(&[(2, Fault::Disconnect)]);
+        let shared = SharedClient::new(connect(addr));
+        shared.get_version().unwrap();
+        assert!(shared.read_parameter_addr(ADDRESS, 1).is_err());
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !shared
+            .events()
+            .unwrap()
+            .contains(&DeviceEvent::ConnectionLost)
+        {
+            assert!(Instant::now() < deadline, "connection loss not noticed");
+            // Noticed while the worker is idle
+            thread::sleep(Duration::from_millis(50));
+        }
+        shared.reconnect(connect(addr)).unwrap();
+        assert_eq!(shared.read_parameter_addr(ADDRESS, 1).unwrap(), 0x7F);
+        assert_eq!(shared.events().unwrap(), vec![DeviceEvent::Reconnected]);
+    }
+}
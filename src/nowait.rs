@@ -0,0 +1,243 @@
+//! Writes that don't wait for their ACK
+//!
+//! [`TelnetClient::write_parameter_nowait`] sends a DTH and returns right
+//! away, saving a round trip per value during fader rides. The device
+//! answers commands in order, so the client keeps the addresses of such
+//! writes in a queue and hands the oldest one each ACK or ERR that
+//! arrives first, whatever it is waiting for at the time. Only then is a
+//! response matched to a blocking command, so mixing both kinds of
+//! commands never pairs a command with a stale ACK.
+//!
+//! Device errors for nowait writes are kept until
+//! [`TelnetClient::drain_write_errors`] is called.
+
+use crate::{TelnetClient, TelnetError};
+use roland_core::{Address, Command, Response, RolandError, WriteAnswer};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Outstanding nowait writes and the errors they got
+#[derive(Debug, Default)]
+pub(crate) struct NowaitWrites {
+    /// Addresses of writes still waiting for ACK/ERR, oldest first
+    pending: VecDeque<Address>,
+    errors: Vec<(Address, RolandError)>,
+}
+
+impl TelnetClient {
+    /// Write a parameter value without waiting for the ACK
+    ///
+    /// The ACK or ERR is picked up while waiting for a later command, or by
+    /// [`TelnetClient::wait_write_acks`] or [`TelnetClient::poll_event`].
+    /// With [`TelnetClient::max_in_flight`] writes already unacknowledged,
+    /// this first waits for the oldest ACK.
+    ///
+    /// With coalescing on, a write the rate limit would delay is held back
+    /// as for [`TelnetClient::write_parameter_addr`], see
+    /// [`TelnetClient::set_coalescing`], so moving a T-bar never waits.
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address
+    /// * `value` - Value to write (0-255)
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Sent, or an error sending it; device
+    ///   errors are reported by [`TelnetClient::drain_write_errors`]
+    pub fn write_parameter_nowait(
+        &mut self,
+        address: Address,
+        value: u8,
+    ) -> Result<(), TelnetError> {
+        if self.coalesce_write(address, value) {
+            if let Some(cache) = &mut self.cache {
+                cache.invalidate(address);
+            }
+            return Ok(());
+        }
+        self.flush_writes()?;
+        while self.nowait.pending.len() >= self.max_in_flight {
+            self.settle_next_write()?;
+        }
+        self.wait_until_resumed()?;
+
+        let command = Command::WriteParameter { address, value };
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate_for(&command, &self.profile);
+        }
+        self.write_command(&command)?;
+        self.nowait.pending.push_back(address);
+        Ok(())
+    }
+
+    /// Get the number of nowait writes not acknowledged yet
+    pub fn pending_write_acks(&self) -> usize {
+        self.nowait.pending.len()
+    }
+
+    /// Wait until every nowait write is acknowledged
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - All answered (check
+    ///   [`TelnetClient::drain_write_errors`] for device errors), or
+    ///   `Timeout` if the device stopped answering
+    pub fn wait_write_acks(&mut self) -> Result<(), TelnetError> {
+        while !self.nowait.pending.is_empty() {
+            self.settle_next_write()?;
+        }
+        Ok(())
+    }
+
+    /// Take the device errors of nowait writes received so far
+    ///
+    /// Each error comes with the address of the write it answered.
+    pub fn drain_write_errors(&mut self) -> impl Iterator<Item = (Address, RolandError)> + '_ {
+        self.nowait.errors.drain(..)
+    }
+
+    /// Hand an ACK or ERR to the oldest nowait write
+    ///
+    /// A DTH of its address is taken as well, if
+    /// [`ParseOptions::write_answer`](roland_core::ParseOptions::write_answer)
+    /// allows it; its value isn't checked. Returns whether the response
+    /// was taken; other responses, and any response while no nowait write
+    /// is pending, are left alone.
+    pub(crate) fn settle_write(&mut self, response: &Response) -> bool {
+        let Some(&address) = self.nowait.pending.front() else {
+            return false;
+        };
+        let error = match response {
+            Response::Acknowledge => None,
+            Response::Error(e) => Some(e.clone()),
+            Response::Data {
+                address: answered, ..
+            } if *answered == address
+                && self.parse_options.write_answer != WriteAnswer::AckOnly =>
+            {
+                None
+            }
+            _ => return false,
+        };
+        self.nowait.pending.pop_front();
+        if let Some(e) = error {
+            self.nowait.errors.push((address, e));
+        }
+        true
+    }
+
+    /// Read frames until the oldest nowait write is answered
+    fn settle_next_write(&mut self) -> Result<(), TelnetError> {
+        let deadline = Instant::now() + self.timeouts.write;
+        let pending = self.nowait.pending.len();
+        while self.nowait.pending.len() == pending {
+            let frame = self
+                .read_frame_before(deadline)?
+                .ok_or(TelnetError::Timeout)?;
+            self.queue_event(&frame);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::DeviceEvent;
+    use std::time::Duration;
+
+    fn fader(i: u8) -> Address {
+        Address::new(0x05, i, 0x00)
+    }
+
+    #[test]
+    fn test_nowait_writes_then_read() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_delay(Duration::from_millis(5));
+        let mut client = connect(addr);
+        client.set_max_in_flight(16);
+
+        for i in 0..10 {
+            client.write_parameter_nowait(fader(0), i).unwrap();
+        }
+        assert!(client.pending_write_acks() > 0);
+
+        // The ten ACKs go to the writes, the DTH to the read
+        assert_eq!(client.read_parameter_addr(fader(0), 1).unwrap(), 9);
+        assert_eq!(client.pending_write_acks(), 0);
+        assert_eq!(client.drain_write_errors().count(), 0);
+        assert_eq!(client.events().count(), 0);
+
+        // A blocking write gets its own ACK, not a stale one
+        client.write_parameter_nowait(fader(1), 1).unwrap();
+        client.write_parameter_addr(fader(2), 2).unwrap();
+        assert_eq!(client.pending_write_acks(), 0);
+        assert_eq!(mock.received().len(), 13);
+    }
+
+    #[test]
+    fn test_nowait_write_errors() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        mock.set_address_error(fader(3), RolandError::OutOfRange);
+        mock.inject_unsolicited(fader(9), 0x40);
+
+        for i in 0..5 {
+            client.write_parameter_nowait(fader(i), i).unwrap();
+        }
+        client.wait_write_acks().unwrap();
+        assert_eq!(
+            client.drain_write_errors().collect::<Vec<_>>(),
+            vec![(fader(3), RolandError::OutOfRange)]
+        );
+        assert_eq!(client.drain_write_errors().count(), 0);
+
+        // Unrelated frames in between are still events
+        assert_eq!(
+            client.events().collect::<Vec<_>>(),
+            vec![DeviceEvent::ParameterChanged {
+                address: fader(9),
+                value: 0x40,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_in_flight_limit() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_delay(Duration::from_millis(20));
+        let mut client = connect(addr);
+        client.set_max_in_flight(2);
+
+        for i in 0..6 {
+            client.write_parameter_nowait(fader(i), i).unwrap();
+            assert!(client.pending_write_acks() <= 2);
+        }
+        client.wait_write_acks().unwrap();
+        assert_eq!(mock.received().len(), 6);
+    }
+
+    #[test]
+    fn test_nowait_coalescing() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        client.set_rate_limit(20);
+        client.set_coalescing(true);
+
+        let start = Instant::now();
+        for value in 0..50 {
+            client.write_parameter_nowait(fader(0), value).unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_millis(40));
+        assert_eq!(client.pending_writes(), 1);
+
+        client.flush_writes().unwrap();
+        client.wait_write_acks().unwrap();
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::write_parameter(fader(0), 0),
+                Command::write_parameter(fader(0), 49),
+            ]
+        );
+    }
+}
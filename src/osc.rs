@@ -0,0 +1,490 @@
+//! OSC bridge (requires the `osc` feature)
+//!
+//! [`OscBridge`] listens for OSC messages on UDP and turns them into
+//! parameter writes, and sends unsolicited parameter changes back out to
+//! subscribed OSC clients. Paths come from a [`ParameterMap`]: the
+//! parameter `audio.ch1.fader` is `/vr6hd/audio/ch1/fader` with the
+//! default prefix.
+//!
+//! Arguments are translated as follows:
+//!
+//! * float 0.0-1.0: scaled onto `min..=max` of the parameter
+//! * int: the raw parameter value
+//! * string: an enum value name or number, as for [`ParamDef::encode`]
+//!
+//! Changes are sent out as int for enum parameters and as float 0.0-1.0
+//! otherwise. Clients register with `<prefix>/subscribe` and leave with
+//! `<prefix>/unsubscribe`; a message that can't be applied is answered
+//! with `<prefix>/error` and a description.
+
+use crate::param_map::{ParamDef, ParamMapError, ParameterMap};
+use crate::{DeviceEvent, TelnetClient, TelnetError};
+use roland_core::Address;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+/// Default path prefix
+pub const DEFAULT_PREFIX: &str = "/vr6hd";
+
+/// How long [`OscBridge::run`] waits for a message before checking the
+/// device for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// OSC argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    /// 32-bit integer (`i`)
+    Int(i32),
+    /// 32-bit float (`f`)
+    Float(f32),
+    /// String (`s`)
+    String(String),
+}
+
+/// OSC message
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    /// Address pattern, e.g. `/vr6hd/video/pgm_select`
+    pub path: String,
+    /// Arguments
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    /// Create a message
+    pub fn new(path: impl Into<String>, args: Vec<OscArg>) -> Self {
+        Self {
+            path: path.into(),
+            args,
+        }
+    }
+
+    /// Encode the message as an OSC packet
+    pub fn encode(&self) -> Vec<u8> {
+        let mut packet = Vec::new();
+        write_osc_string(&mut packet, &self.path);
+        let mut tags = String::from(",");
+        for arg in &self.args {
+            tags.push(match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::String(_) => 's',
+            });
+        }
+        write_osc_string(&mut packet, &tags);
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(i) => packet.extend_from_slice(&i.to_be_bytes()),
+                OscArg::Float(f) => packet.extend_from_slice(&f.to_be_bytes()),
+                OscArg::String(s) => write_osc_string(&mut packet, s),
+            }
+        }
+        packet
+    }
+
+    /// Decode an OSC packet
+    ///
+    /// Bundles are flattened into their messages; time tags are ignored.
+    /// Returns `None` for malformed packets and for argument types other
+    /// than `i`, `f` and `s`.
+    pub fn decode(packet: &[u8]) -> Option<Vec<OscMessage>> {
+        let mut reader = OscReader { packet, pos: 0 };
+        if packet.starts_with(b"#bundle\0") {
+            reader.take(16)?; // "#bundle" and the time tag
+            let mut messages = Vec::new();
+            while reader.pos < packet.len() {
+                let len = u32::from_be_bytes(reader.take(4)?.try_into().ok()?) as usize;
+                messages.extend(Self::decode(reader.take(len)?)?);
+            }
+            return Some(messages);
+        }
+
+        let path = reader.string()?;
+        if !path.starts_with('/') {
+            return None;
+        }
+        // A missing type tag string means no arguments
+        let tags = if reader.pos < packet.len() {
+            reader.string()?
+        } else {
+            ",".to_string()
+        };
+        let mut args = Vec::new();
+        for tag in tags.strip_prefix(',')?.chars() {
+            args.push(match tag {
+                'i' => OscArg::Int(i32::from_be_bytes(reader.take(4)?.try_into().ok()?)),
+                'f' => OscArg::Float(f32::from_be_bytes(reader.take(4)?.try_into().ok()?)),
+                's' => OscArg::String(reader.string()?),
+                _ => return None,
+            });
+        }
+        Some(vec![OscMessage { path, args }])
+    }
+}
+
+/// Translation between OSC messages and parameters
+#[derive(Debug, Clone)]
+pub struct OscMapping {
+    map: ParameterMap,
+    prefix: String,
+}
+
+impl OscMapping {
+    /// Map the parameters of `map` below `prefix`, e.g. [`DEFAULT_PREFIX`]
+    pub fn new(map: ParameterMap, prefix: &str) -> Self {
+        Self {
+            map,
+            prefix: prefix.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Get the OSC path of a parameter
+    pub fn path(&self, param: &ParamDef) -> String {
+        format!("{}/{}", self.prefix, param.name.replace('.', "/"))
+    }
+
+    /// Get the parameter of an OSC path
+    pub fn lookup(&self, path: &str) -> Result<&ParamDef, ParamMapError> {
+        let name = path
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|rest| rest.strip_prefix('/'))
+            .ok_or_else(|| ParamMapError::UnknownParameter(path.to_string()))?;
+        self.map.lookup(&name.replace('/', "."))
+    }
+
+    /// Translate a message into the parameter write it stands for
+    ///
+    /// # Returns
+    /// * `Result<(Address, Vec<u8>), ParamMapError>` - Address and bytes
+    ///   to write, or why the message doesn't map onto a valid write
+    pub fn to_write(&self, message: &OscMessage) -> Result<(Address, Vec<u8>), ParamMapError> {
+        let param = self.lookup(&message.path)?;
+        let unknown = |value: String| ParamMapError::UnknownValue {
+            name: param.name.clone(),
+            value,
+        };
+        let bytes = match message.args.as_slice() {
+            [OscArg::Float(f)] => {
+                if !(0.0..=1.0).contains(f) {
+                    return Err(unknown(f.to_string()));
+                }
+                let span = (param.max - param.min) as f64;
+                let value = param.min + (*f as f64 * span).round() as u32;
+                param.encode(&value.to_string())?
+            }
+            [OscArg::Int(i)] if *i >= 0 => param.encode(&i.to_string())?,
+            [OscArg::Int(i)] => return Err(unknown(i.to_string())),
+            [OscArg::String(s)] => param.encode(s)?,
+            args => return Err(unknown(format!("{:?}", args))),
+        };
+        Ok((param.address, bytes))
+    }
+
+    /// Translate a parameter change into a message
+    ///
+    /// Returns `None` if no single-byte parameter of the map is at
+    /// `address`.
+    pub fn to_message(&self, address: Address, value: u8) -> Option<OscMessage> {
+        let param = self
+            .map
+            .iter()
+            .find(|param| param.address == address && param.size == 1)?;
+        let arg = if param.values.is_empty() {
+            let span = param.max.saturating_sub(param.min);
+            let offset = (value as u32).saturating_sub(param.min);
+            let level = if span == 0 {
+                0.0
+            } else {
+                (offset as f32 / span as f32).min(1.0)
+            };
+            OscArg::Float(level)
+        } else {
+            OscArg::Int(value as i32)
+        };
+        Some(OscMessage::new(self.path(param), vec![arg]))
+    }
+}
+
+/// UDP server translating between OSC and a device
+pub struct OscBridge {
+    socket: UdpSocket,
+    client: TelnetClient,
+    mapping: OscMapping,
+    subscribers: Vec<SocketAddr>,
+}
+
+impl OscBridge {
+    /// Listen for OSC messages on `addr`
+    pub fn bind(
+        addr: impl ToSocketAddrs,
+        client: TelnetClient,
+        mapping: OscMapping,
+    ) -> Result<Self, TelnetError> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr)?,
+            client,
+            mapping,
+            subscribers: Vec::new(),
+        })
+    }
+
+    /// Get the address the bridge listens on
+    pub fn local_addr(&self) -> Result<SocketAddr, TelnetError> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Get the subscribed OSC clients
+    pub fn subscribers(&self) -> &[SocketAddr] {
+        &self.subscribers
+    }
+
+    /// Serve until the connection to the device fails
+    pub fn run(&mut self) -> Result<(), TelnetError> {
+        loop {
+            self.poll(POLL_INTERVAL)?;
+        }
+    }
+
+    /// Handle at most one incoming packet, waiting up to `timeout`, then
+    /// send out the parameter changes the device reported
+    ///
+    /// Errors from single messages are answered with an error message
+    /// rather than returned; only connection errors are returned.
+    pub fn poll(&mut self, timeout: Duration) -> Result<(), TelnetError> {
+        self.socket
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let mut buf = [0; 1536];
+        match self.socket.recv_from(&mut buf) {
+            Ok((len, from)) => {
+                // Malformed packets are dropped
+                for message in OscMessage::decode(&buf[..len]).unwrap_or_default() {
+                    self.handle(&message, from)?;
+                }
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        while let Some(event) = self.client.poll_event()? {
+            if let DeviceEvent::ParameterChanged { address, value } = event {
+                if let Some(message) = self.mapping.to_message(address, value) {
+                    self.publish(&message)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, message: &OscMessage, from: SocketAddr) -> Result<(), TelnetError> {
+        let control = message.path.strip_prefix(self.mapping.prefix.as_str());
+        match control {
+            Some("/subscribe") => {
+                if !self.subscribers.contains(&from) {
+                    self.subscribers.push(from);
+                }
+                return Ok(());
+            }
+            Some("/unsubscribe") => {
+                self.subscribers.retain(|&subscriber| subscriber != from);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let result = match self.mapping.to_write(message) {
+            Ok((address, bytes)) => self.client.write_parameter_block(address, &bytes),
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(TelnetError::Protocol(e) | TelnetError::Device { error: e, .. }) => {
+                self.reply_error(from, &message.path, &e.to_string())
+            }
+            Err(TelnetError::Parameter(e)) => self.reply_error(from, &message.path, &e.to_string()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn reply_error(&self, to: SocketAddr, path: &str, error: &str) -> Result<(), TelnetError> {
+        let message = OscMessage::new(
+            format!("{}/error", self.mapping.prefix),
+            vec![
+                OscArg::String(path.to_string()),
+                OscArg::String(error.to_string()),
+            ],
+        );
+        self.socket.send_to(&message.encode(), to)?;
+        Ok(())
+    }
+
+    fn publish(&self, message: &OscMessage) -> Result<(), TelnetError> {
+        let packet = message.encode();
+        for subscriber in &self.subscribers {
+            self.socket.send_to(&packet, subscriber)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write a string with a null terminator, padded to 4 bytes
+fn write_osc_string(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    packet.resize(packet.len() + padding, 0);
+}
+
+struct OscReader<'a> {
+    packet: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> OscReader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.packet.get(self.pos..self.pos + n)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let rest = self.packet.get(self.pos..)?;
+        let len = rest.iter().position(|&b| b == 0)?;
+        let s = std::str::from_utf8(&rest[..len]).ok()?.to_string();
+        self.take((len / 4 + 1) * 4)?;
+        Some(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::params::{audio, video};
+
+    const MAP: &str = "name,address,max,values
+audio.ch1.fader,050000,127,
+video.pgm,010000,,hdmi1|hdmi2|hdmi3|hdmi4|still1|still2
+";
+
+    fn mapping() -> OscMapping {
+        OscMapping::new(ParameterMap::from_csv(MAP).unwrap(), DEFAULT_PREFIX)
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let message = OscMessage::new(
+            "/vr6hd/audio/ch1/fader",
+            vec![
+                OscArg::Float(0.5),
+                OscArg::Int(-3),
+                OscArg::String("abc".into()),
+            ],
+        );
+        let packet = message.encode();
+        assert_eq!(&packet[..24], b"/vr6hd/audio/ch1/fader\0\0");
+        assert_eq!(&packet[24..32], b",fis\0\0\0\0");
+        assert_eq!(packet.len(), 32 + 4 + 4 + 4);
+        assert_eq!(OscMessage::decode(&packet).unwrap(), vec![message.clone()]);
+
+        let mut bundle = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        bundle.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+        bundle.extend_from_slice(&packet);
+        assert_eq!(OscMessage::decode(&bundle).unwrap(), vec![message]);
+
+        assert_eq!(OscMessage::decode(b"/x\0\0,b\0\0"), None);
+        assert_eq!(OscMessage::decode(&packet[..30]), None);
+    }
+
+    #[test]
+    fn test_translation() {
+        let mapping = mapping();
+        let fader = |arg| OscMessage::new("/vr6hd/audio/ch1/fader", vec![arg]);
+        let pgm = |arg| OscMessage::new("/vr6hd/video/pgm", vec![arg]);
+
+        assert_eq!(
+            mapping.to_write(&fader(OscArg::Float(1.0))).unwrap(),
+            (audio::CH1_LEVEL, vec![127])
+        );
+        assert_eq!(
+            mapping.to_write(&fader(OscArg::Float(0.5))).unwrap(),
+            (audio::CH1_LEVEL, vec![64])
+        );
+        assert_eq!(
+            mapping.to_write(&pgm(OscArg::Int(1))).unwrap(),
+            (video::PGM_SELECT, vec![1])
+        );
+        assert_eq!(
+            mapping
+                .to_write(&pgm(OscArg::String("still1".into())))
+                .unwrap(),
+            (video::PGM_SELECT, vec![4])
+        );
+        assert!(matches!(
+            mapping.to_write(&fader(OscArg::Float(1.5))),
+            Err(ParamMapError::UnknownValue { .. })
+        ));
+        assert!(matches!(
+            mapping.to_write(&pgm(OscArg::Int(6))),
+            Err(ParamMapError::UnknownValue { .. })
+        ));
+        assert!(matches!(
+            mapping.to_write(&OscMessage::new("/other/video/pgm", vec![OscArg::Int(0)])),
+            Err(ParamMapError::UnknownParameter(_))
+        ));
+
+        assert_eq!(
+            mapping.to_message(audio::CH1_LEVEL, 127),
+            Some(fader(OscArg::Float(1.0)))
+        );
+        assert_eq!(
+            mapping.to_message(video::PGM_SELECT, 2),
+            Some(pgm(OscArg::Int(2)))
+        );
+        assert_eq!(mapping.to_message(audio::CH2_LEVEL, 0), None);
+    }
+
+    #[test]
+    fn test_bridge() {
+        let (addr, mock) = MockDevice::spawn();
+        let client = connect(addr);
+        let mut bridge = OscBridge::bind("127.0.0.1:0", client, mapping()).unwrap();
+        let bridge_addr = bridge.local_addr().unwrap();
+
+        let osc = UdpSocket::bind("127.0.0.1:0").unwrap();
+        osc.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let send = |message: OscMessage| {
+            osc.send_to(&message.encode(), bridge_addr).unwrap();
+        };
+        let receive = || {
+            let mut buf = [0; 512];
+            let len = osc.recv(&mut buf).unwrap();
+            OscMessage::decode(&buf[..len]).unwrap().remove(0)
+        };
+
+        send(OscMessage::new("/vr6hd/subscribe", vec![]));
+        bridge.poll(Duration::from_secs(1)).unwrap();
+        assert_eq!(bridge.subscribers(), &[osc.local_addr().unwrap()]);
+
+        // The panel switches PGM while the fader write is in flight
+        mock.inject_unsolicited(video::PGM_SELECT, 3);
+        send(OscMessage::new(
+            "/vr6hd/audio/ch1/fader",
+            vec![OscArg::Float(1.0)],
+        ));
+        bridge.poll(Duration::from_secs(1)).unwrap();
+        assert_eq!(mock.parameter(audio::CH1_LEVEL), Some(127));
+        assert_eq!(
+            receive(),
+            OscMessage::new("/vr6hd/video/pgm", vec![OscArg::Int(3)])
+        );
+
+        // Invalid values are answered with an error and not sent
+        mock.clear_received();
+        send(OscMessage::new("/vr6hd/video/pgm", vec![OscArg::Int(9)]));
+        bridge.poll(Duration::from_secs(1)).unwrap();
+        let error = receive();
+        assert_eq!(error.path, "/vr6hd/error");
+        assert_eq!(error.args[0], OscArg::String("/vr6hd/video/pgm".into()));
+        assert!(mock.received().is_empty());
+    }
+}
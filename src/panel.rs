@@ -0,0 +1,192 @@
+//! Panel lock
+
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::system;
+
+/// Section of the front panel that can be locked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockSection {
+    /// Audio faders and buttons
+    Audio,
+    /// Video switching controls
+    Video,
+    /// Menu and setup
+    Menu,
+}
+
+impl LockSection {
+    /// All sections
+    pub const ALL: [LockSection; 3] = [LockSection::Audio, LockSection::Video, LockSection::Menu];
+
+    /// Get the bit of the section in the panel lock mask
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Get the name of the section, e.g. `audio`
+    pub fn name(self) -> &'static str {
+        match self {
+            LockSection::Audio => "audio",
+            LockSection::Video => "video",
+            LockSection::Menu => "menu",
+        }
+    }
+
+    /// Get a section by its name, see [`LockSection::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|section| section.name() == name)
+    }
+}
+
+/// Set of locked panel sections
+///
+/// # Example
+/// ```
+/// use roland_rs::panel::{LockSection, LockSections};
+///
+/// let mut locked = LockSections::empty();
+/// locked.insert(LockSection::Audio);
+/// assert!(locked.contains(LockSection::Audio));
+/// assert_eq!(locked.bits(), 0x01);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct LockSections(u8);
+
+impl LockSections {
+    /// No section locked
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Every section locked
+    pub const fn all() -> Self {
+        Self(0x07)
+    }
+
+    /// Decode a panel lock mask, ignoring reserved bits
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits & Self::all().0)
+    }
+
+    /// Get the panel lock mask
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Check if a section is in the set
+    pub fn contains(self, section: LockSection) -> bool {
+        self.0 & section.bit() != 0
+    }
+
+    /// Add a section to the set
+    pub fn insert(&mut self, section: LockSection) {
+        self.0 |= section.bit();
+    }
+
+    /// Remove a section from the set
+    pub fn remove(&mut self, section: LockSection) {
+        self.0 &= !section.bit();
+    }
+
+    /// Check if no section is in the set
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Iterate over the sections in the set
+    pub fn iter(self) -> impl Iterator<Item = LockSection> {
+        LockSection::ALL
+            .into_iter()
+            .filter(move |&section| self.contains(section))
+    }
+}
+
+impl FromIterator<LockSection> for LockSections {
+    fn from_iter<I: IntoIterator<Item = LockSection>>(iter: I) -> Self {
+        let mut sections = Self::empty();
+        for section in iter {
+            sections.insert(section);
+        }
+        sections
+    }
+}
+
+impl TelnetClient {
+    /// Lock or unlock the whole front panel
+    pub fn set_panel_lock(&mut self, locked: bool) -> Result<(), TelnetError> {
+        let sections = if locked {
+            LockSections::all()
+        } else {
+            LockSections::empty()
+        };
+        self.set_locked_sections(sections)
+    }
+
+    /// Lock exactly the given panel sections, unlocking the others
+    pub fn set_locked_sections(&mut self, sections: LockSections) -> Result<(), TelnetError> {
+        self.write_parameter_addr(system::PANEL_LOCK, sections.bits())
+    }
+
+    /// Lock one panel section, keeping the others as they are
+    pub fn lock_section(&mut self, section: LockSection) -> Result<(), TelnetError> {
+        let mut sections = self.panel_lock_state()?;
+        sections.insert(section);
+        self.set_locked_sections(sections)
+    }
+
+    /// Unlock one panel section, keeping the others as they are
+    pub fn unlock_section(&mut self, section: LockSection) -> Result<(), TelnetError> {
+        let mut sections = self.panel_lock_state()?;
+        sections.remove(section);
+        self.set_locked_sections(sections)
+    }
+
+    /// Get the locked panel sections
+    pub fn panel_lock_state(&mut self) -> Result<LockSections, TelnetError> {
+        let bits = self.read_parameter_addr(system::PANEL_LOCK, 1)?;
+        Ok(LockSections::from_bits(bits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+
+    #[test]
+    fn test_mask() {
+        let sections: LockSections = [LockSection::Menu, LockSection::Audio]
+            .into_iter()
+            .collect();
+        assert_eq!(sections.bits(), 0x05);
+        assert_eq!(
+            sections.iter().collect::<Vec<_>>(),
+            vec![LockSection::Audio, LockSection::Menu]
+        );
+        assert_eq!(LockSections::from_bits(0xFA).bits(), 0x02);
+        assert!(LockSections::from_bits(0x00).is_empty());
+    }
+
+    #[test]
+    fn test_lock_sections() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        client.lock_section(LockSection::Audio).unwrap();
+        client.lock_section(LockSection::Video).unwrap();
+        assert_eq!(mock.parameter(system::PANEL_LOCK), Some(0x03));
+
+        let state = client.panel_lock_state().unwrap();
+        assert!(state.contains(LockSection::Audio));
+        assert!(state.contains(LockSection::Video));
+        assert!(!state.contains(LockSection::Menu));
+
+        client.unlock_section(LockSection::Audio).unwrap();
+        assert_eq!(mock.parameter(system::PANEL_LOCK), Some(0x02));
+
+        client.set_panel_lock(true).unwrap();
+        assert_eq!(client.panel_lock_state().unwrap(), LockSections::all());
+        client.set_panel_lock(false).unwrap();
+        assert!(client.panel_lock_state().unwrap().is_empty());
+    }
+}
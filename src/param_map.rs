@@ -0,0 +1,1085 @@
+//! Parameter map loaded at runtime
+//!
+//! The constants in [`roland_core::params`] follow one firmware version.
+//! A [`ParameterMap`] describes parameters in a file instead, so a map for
+//! newer firmware doesn't need a new build. Maps can be written as TOML,
+//! one table per parameter:
+//!
+//! ```toml
+//! [video.pgm_select]
+//! address = "010000"
+//! values = ["hdmi1", "hdmi2", "hdmi3", "hdmi4", "still1", "still2"]
+//!
+//! [audio.ch1.fader]
+//! address = "050000"
+//! max = 127
+//! unit = "step"
+//! ```
+//!
+//! or as CSV with a header line naming the columns:
+//!
+//! ```text
+//! name,address,size,min,max,unit,values
+//! video.pgm_select,010000,1,,,,hdmi1|hdmi2|hdmi3|hdmi4|still1|still2
+//! pinp.position_h,020002,2,0,2000,,
+//! ```
+//!
+//! Only `name` and `address` are required. `size` defaults to 1 byte,
+//! `min` to 0 and `max` to the largest value that fits (or the last enum
+//! value). Enum values are numbered from 0 in order; `name=n` gives a value
+//! explicitly. Multi-byte values are sent most significant byte first.
+//!
+//! Single-byte parameters holding signed values name their encoding,
+//! `encoding = "offset_binary:64"` (the byte for 0 after the colon) or
+//! `encoding = "twos_complement"`. Their values are given and decoded as
+//! signed numbers, while `min` and `max` still bound the encoded byte.
+//!
+//! Parameters holding a level, percentage or time can give a scale, so
+//! their values are written with a unit, e.g. `-10.5dB` or `75%`:
+//!
+//! ```toml
+//! [audio.ch1.fader]
+//! address = "050000"
+//! min = 1
+//! max = 127
+//! unit = "dB"
+//! scale = "linear -53..10 clamp"
+//! off = 0
+//! ```
+//!
+//! `scale` is the curve, `linear` or `log` (see [`Curve`]), the values of
+//! the `min` and `max` bytes, and optionally `clamp` to clamp values
+//! outside instead of rejecting them. `off` is a byte below the scale,
+//! e.g. for a closed fader. The unit is `dB`, `%` or `ms`. Plain numbers
+//! are still taken as bytes.
+//!
+//! Only the parts of TOML used above are supported: table headers, and
+//! strings, integers and single-line string arrays as values.
+
+use crate::units::{Curve, ParamScale, Quantity, Unit};
+use crate::{TelnetClient, TelnetError};
+use roland_core::signed::SignedEncoding;
+use roland_core::{Address, RolandError};
+use std::fmt;
+use std::path::Path;
+
+/// Largest supported parameter size in bytes
+const MAX_SIZE: u8 = 4;
+
+/// Description of one parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamDef {
+    /// Dotted name, e.g. `audio.ch1.fader`
+    pub name: String,
+    /// Address of the first byte
+    pub address: Address,
+    /// Size in bytes (1-4)
+    pub size: u8,
+    /// Smallest valid value
+    pub min: u32,
+    /// Largest valid value
+    pub max: u32,
+    /// Unit of the value, for display
+    pub unit: Option<String>,
+    /// Names of enum values; empty for numeric parameters
+    pub values: Vec<(String, u32)>,
+    /// Encoding of signed values; `None` for unsigned parameters
+    pub encoding: Option<SignedEncoding>,
+    /// Scale of values given with the unit; `None` if values are bytes
+    pub scale: Option<ParamScale>,
+}
+
+impl ParamDef {
+    /// Encode a value given by name or number
+    ///
+    /// Enum names match case-insensitively. Numbers must be within
+    /// `min..=max`, and for enum parameters must also be an enum value.
+    /// Parameters with a scale also take a value with their unit.
+    pub fn encode(&self, value: &str) -> Result<Vec<u8>, ParamMapError> {
+        self.check_size()?;
+        let value = value.trim();
+        if let (Some(scale), Some(quantity)) = (&self.scale, Quantity::parse(value)) {
+            if Some(quantity.unit()) != self.scale_unit() {
+                return Err(self.unknown_value(value));
+            }
+            return quantity
+                .to_device_byte(scale)
+                .map(|byte| vec![byte])
+                .map_err(|_| ParamMapError::OutOfScale {
+                    name: self.name.clone(),
+                    value: value.to_string(),
+                });
+        }
+        if let Some(encoding) = self.encoding {
+            let byte = value
+                .parse()
+                .ok()
+                .and_then(|value| encoding.encode_i8(value).ok())
+                .ok_or_else(|| self.unknown_value(value))?;
+            return self.check_range(byte as u32).map(|()| vec![byte]);
+        }
+        let number = match self.value_of(value) {
+            Some(number) => number,
+            None => parse_number(value).ok_or_else(|| self.unknown_value(value))?,
+        };
+        self.encode_number(number)
+    }
+
+    /// Encode a number after checking it with [`ParamDef::validate`]
+    pub fn encode_number(&self, number: u32) -> Result<Vec<u8>, ParamMapError> {
+        self.validate(number)?;
+        let bytes = number.to_be_bytes();
+        Ok(bytes[bytes.len() - self.size as usize..].to_vec())
+    }
+
+    /// Check that the parameter takes a number
+    ///
+    /// Parameters whose size isn't 1-4 bytes fail with `InvalidSize`.
+    /// Numbers that aren't one of the values of an enum parameter fail
+    /// with `UnknownValue`, and numbers outside `min..=max` with
+    /// `OutOfRange`.
+    pub fn validate(&self, number: u32) -> Result<(), ParamMapError> {
+        self.check_size()?;
+        if !self.values.is_empty() && self.name_of(number).is_none() {
+            return Err(self.unknown_value(&number.to_string()));
+        }
+        self.check_range(number)
+    }
+
+    /// Maps only load sizes up to [`MAX_SIZE`], but a `ParamDef` can be
+    /// built with any
+    fn check_size(&self) -> Result<(), ParamMapError> {
+        if !(1..=MAX_SIZE).contains(&self.size) {
+            return Err(ParamMapError::InvalidSize {
+                name: self.name.clone(),
+                size: self.size,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_range(&self, number: u32) -> Result<(), ParamMapError> {
+        if !(self.min..=self.max).contains(&number) {
+            return Err(ParamMapError::OutOfRange {
+                name: self.name.clone(),
+                value: number,
+                min: self.min,
+                max: self.max,
+            });
+        }
+        Ok(())
+    }
+
+    /// Decode bytes read from the device
+    ///
+    /// Enum parameters decode to the value name, or to the number if the
+    /// map doesn't name it; parameters with a scale decode to a value with
+    /// the unit, e.g. `-10.5dB`, or to the number for bytes outside the
+    /// scale; other parameters decode to the number, signed for
+    /// parameters with an encoding.
+    pub fn decode(&self, bytes: &[u8]) -> Result<String, ParamMapError> {
+        if bytes.len() != self.size as usize {
+            return Err(ParamMapError::WrongSize {
+                name: self.name.clone(),
+                expected: self.size,
+                got: bytes.len(),
+            });
+        }
+        let number = bytes
+            .iter()
+            .fold(0u32, |number, &byte| number << 8 | byte as u32);
+        if let (Some(scale), Some(unit)) = (&self.scale, self.scale_unit()) {
+            if let Ok(quantity) = unit.decode(number as u8, scale) {
+                return Ok(quantity.to_string());
+            }
+        }
+        if let Some(encoding) = self.encoding {
+            return encoding
+                .decode_i8(number as u8)
+                .map(|value| value.to_string())
+                .map_err(|_| self.unknown_value(&number.to_string()));
+        }
+        Ok(match self.name_of(number) {
+            Some(name) => name.to_string(),
+            None => number.to_string(),
+        })
+    }
+
+    fn scale_unit(&self) -> Option<Unit> {
+        self.unit.as_deref().and_then(Unit::from_symbol)
+    }
+
+    fn value_of(&self, name: &str) -> Option<u32> {
+        self.values
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, value)| value)
+    }
+
+    fn name_of(&self, value: u32) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|&&(_, v)| v == value)
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn unknown_value(&self, value: &str) -> ParamMapError {
+        ParamMapError::UnknownValue {
+            name: self.name.clone(),
+            value: value.to_string(),
+        }
+    }
+}
+
+/// Set of parameter descriptions, looked up by name
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ParameterMap {
+    params: Vec<ParamDef>,
+}
+
+impl ParameterMap {
+    /// Load a map from a file, as CSV if the extension is `.csv` and as
+    /// TOML otherwise
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ParamMapError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(ParamMapError::Io)?;
+        let is_csv = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+        if is_csv {
+            Self::from_csv(&text)
+        } else {
+            Self::from_toml(&text)
+        }
+    }
+
+    /// Parse a map written as TOML
+    pub fn from_toml(text: &str) -> Result<Self, ParamMapError> {
+        let mut map = Self::default();
+        let mut current: Option<(usize, RawDef)> = None;
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let name = header
+                    .strip_suffix(']')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| parse_error(line_no, "malformed table header"))?;
+                if let Some((line, raw)) = current.take() {
+                    map.insert(raw.finish(line)?, line)?;
+                }
+                current = Some((line_no, RawDef::new(name)));
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| parse_error(line_no, "expected `key = value`"))?;
+            let Some((_, raw)) = current.as_mut() else {
+                return Err(parse_error(line_no, "key outside of a table"));
+            };
+            let value = TomlValue::parse(value.trim()).ok_or_else(|| {
+                parse_error(line_no, "expected a string, integer or string array")
+            })?;
+            raw.set(key.trim(), value, line_no)?;
+        }
+        if let Some((line, raw)) = current {
+            map.insert(raw.finish(line)?, line)?;
+        }
+        Ok(map)
+    }
+
+    /// Parse a map written as CSV
+    ///
+    /// Fields can't contain commas; enum values are separated by `|`.
+    pub fn from_csv(text: &str) -> Result<Self, ParamMapError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        let Some((_, header)) = lines.next() else {
+            return Ok(Self::default());
+        };
+        let columns: Vec<&str> = header.split(',').map(str::trim).collect();
+
+        let mut map = Self::default();
+        for (line_no, line) in lines {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() > columns.len() {
+                return Err(parse_error(line_no, "more fields than columns"));
+            }
+            let mut raw = None;
+            for (column, field) in columns.iter().zip(&fields) {
+                if *column == "name" {
+                    raw = Some(RawDef::new(field));
+                }
+            }
+            let mut raw = raw.ok_or_else(|| parse_error(line_no, "missing name"))?;
+            for (column, field) in columns.iter().zip(fields) {
+                if *column == "name" || field.is_empty() {
+                    continue;
+                }
+                let value = match *column {
+                    "values" => TomlValue::Array(field.split('|').map(str::to_string).collect()),
+                    "address" | "unit" | "encoding" | "scale" => {
+                        TomlValue::String(field.to_string())
+                    }
+                    _ => TomlValue::parse(field)
+                        .ok_or_else(|| parse_error(line_no, "expected an integer"))?,
+                };
+                raw.set(column, value, line_no)?;
+            }
+            map.insert(raw.finish(line_no)?, line_no)?;
+        }
+        Ok(map)
+    }
+
+    /// Get a parameter by name
+    pub fn lookup(&self, name: &str) -> Result<&ParamDef, ParamMapError> {
+        self.params
+            .iter()
+            .find(|param| param.name == name)
+            .ok_or_else(|| ParamMapError::UnknownParameter(name.to_string()))
+    }
+
+    /// Iterate over the parameters, in file order
+    pub fn iter(&self) -> impl Iterator<Item = &ParamDef> {
+        self.params.iter()
+    }
+
+    /// Get the number of parameters
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Check if the map has no parameters
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    fn insert(&mut self, param: ParamDef, line: usize) -> Result<(), ParamMapError> {
+        if self.lookup(&param.name).is_ok() {
+            return Err(parse_error(line, "duplicate parameter name"));
+        }
+        self.params.push(param);
+        Ok(())
+    }
+}
+
+/// Error loading a [`ParameterMap`] or encoding or decoding a value
+#[derive(Debug)]
+pub enum ParamMapError {
+    /// The map file couldn't be read
+    Io(std::io::Error),
+    /// Malformed map file
+    Parse {
+        /// Line number, starting at 1
+        line: usize,
+        /// What is wrong
+        message: String,
+    },
+    /// No parameter with this name in the map
+    UnknownParameter(String),
+    /// Value that is neither an enum name nor a number the parameter takes
+    UnknownValue {
+        /// Parameter name
+        name: String,
+        /// Value as given
+        value: String,
+    },
+    /// Number outside the range of the parameter
+    OutOfRange {
+        /// Parameter name
+        name: String,
+        /// Value as given
+        value: u32,
+        /// Smallest valid value
+        min: u32,
+        /// Largest valid value
+        max: u32,
+    },
+    /// Value with a unit outside the scale of the parameter
+    OutOfScale {
+        /// Parameter name
+        name: String,
+        /// Value as given
+        value: String,
+    },
+    /// Parameter size outside 1-4 bytes
+    InvalidSize {
+        /// Parameter name
+        name: String,
+        /// Parameter size
+        size: u8,
+    },
+    /// Number of bytes that doesn't match the parameter size
+    WrongSize {
+        /// Parameter name
+        name: String,
+        /// Parameter size
+        expected: u8,
+        /// Number of bytes given
+        got: usize,
+    },
+}
+
+impl fmt::Display for ParamMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamMapError::Io(e) => write!(f, "Failed to read parameter map: {}", e),
+            ParamMapError::Parse { line, message } => {
+                write!(f, "Parameter map line {}: {}", line, message)
+            }
+            ParamMapError::UnknownParameter(name) => write!(f, "Unknown parameter: {}", name),
+            ParamMapError::UnknownValue { name, value } => {
+                write!(f, "{}: unknown value {:?}", name, value)
+            }
+            ParamMapError::OutOfRange {
+                name,
+                value,
+                min,
+                max,
+            } => write!(f, "{}: {} is outside {}-{}", name, value, min, max),
+            ParamMapError::OutOfScale { name, value } => {
+                write!(f, "{}: {} is outside the scale", name, value)
+            }
+            ParamMapError::InvalidSize { name, size } => {
+                write!(f, "{}: size {} is outside 1-{}", name, size, MAX_SIZE)
+            }
+            ParamMapError::WrongSize {
+                name,
+                expected,
+                got,
+            } => write!(f, "{}: expected {} bytes, got {}", name, expected, got),
+        }
+    }
+}
+
+/// Values the device wouldn't take have [`RolandError::OutOfRange`] as
+/// their source, the error it would have answered with
+impl std::error::Error for ParamMapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParamMapError::Io(e) => Some(e),
+            ParamMapError::UnknownValue { .. }
+            | ParamMapError::OutOfRange { .. }
+            | ParamMapError::OutOfScale { .. } => Some(&RolandError::OutOfRange),
+            _ => None,
+        }
+    }
+}
+
+impl TelnetClient {
+    /// Write a parameter by name
+    ///
+    /// `value` is validated against the map before anything is sent.
+    ///
+    /// # Arguments
+    /// * `map` - Parameter map
+    /// * `name` - Parameter name, e.g. `video.pgm_select`
+    /// * `value` - Enum value name or number, e.g. `hdmi2`
+    pub fn write_named(
+        &mut self,
+        map: &ParameterMap,
+        name: &str,
+        value: &str,
+    ) -> Result<(), TelnetError> {
+        let param = map.lookup(name)?;
+        let bytes = param.encode(value)?;
+        self.write_parameter_block(param.address, &bytes)
+    }
+
+    /// Write a number to a parameter after checking it against the map
+    ///
+    /// Values the parameter doesn't take fail with
+    /// [`ParamMapError::OutOfRange`], naming the parameter and its range,
+    /// or [`ParamMapError::UnknownValue`] for enum parameters, without
+    /// sending anything. Either has [`RolandError::OutOfRange`] as its
+    /// source, as the device would have answered. [`TelnetClient::write_named`]
+    /// checks values the same way.
+    ///
+    /// # Arguments
+    /// * `param` - Parameter from a [`ParameterMap`]
+    /// * `value` - Number to write
+    pub fn write_checked(&mut self, param: &ParamDef, value: u8) -> Result<(), TelnetError> {
+        let bytes = param.encode_number(value as u32)?;
+        self.write_parameter_block(param.address, &bytes)
+    }
+
+    /// Read a parameter by name
+    ///
+    /// Multi-byte parameters are read with one request for all bytes.
+    ///
+    /// # Returns
+    /// * `Result<String, TelnetError>` - Enum value name or number
+    pub fn read_named(&mut self, map: &ParameterMap, name: &str) -> Result<String, TelnetError> {
+        let param = map.lookup(name)?;
+        let bytes = self.read_parameter_bytes(param.address, u32::from(param.size))?;
+        Ok(param.decode(&bytes)?)
+    }
+}
+
+/// Parameter fields collected while parsing
+struct RawDef {
+    name: String,
+    address: Option<Address>,
+    size: u8,
+    min: Option<u32>,
+    max: Option<u32>,
+    unit: Option<String>,
+    values: Vec<(String, u32)>,
+    encoding: Option<SignedEncoding>,
+    scale: Option<(Curve, f32, f32, bool)>,
+    off: Option<u8>,
+}
+
+impl RawDef {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            address: None,
+            size: 1,
+            min: None,
+            max: None,
+            unit: None,
+            values: Vec::new(),
+            encoding: None,
+            scale: None,
+            off: None,
+        }
+    }
+
+    fn set(&mut self, key: &str, value: TomlValue, line: usize) -> Result<(), ParamMapError> {
+        let invalid = |what: &str| parse_error(line, &format!("invalid {}", what));
+        match (key, value) {
+            ("address", TomlValue::String(hex)) => {
+                self.address = Some(Address::from_hex(&hex).map_err(|_| invalid("address"))?);
+            }
+            ("size", TomlValue::Integer(size)) if (1..=MAX_SIZE as u32).contains(&size) => {
+                self.size = size as u8;
+            }
+            ("min", TomlValue::Integer(min)) => self.min = Some(min),
+            ("max", TomlValue::Integer(max)) => self.max = Some(max),
+            ("unit", TomlValue::String(unit)) => self.unit = Some(unit),
+            ("encoding", TomlValue::String(encoding)) => {
+                self.encoding = Some(parse_encoding(&encoding).ok_or_else(|| invalid("encoding"))?);
+            }
+            ("scale", TomlValue::String(scale)) => {
+                self.scale = Some(parse_scale(&scale).ok_or_else(|| invalid("scale"))?);
+            }
+            ("off", TomlValue::Integer(off)) => {
+                self.off = Some(u8::try_from(off).map_err(|_| invalid("off"))?);
+            }
+            ("values", TomlValue::Array(names)) => {
+                let mut next = 0;
+                for name in names {
+                    let (name, value) = match name.split_once('=') {
+                        Some((name, value)) => (
+                            name.trim(),
+                            parse_number(value.trim()).ok_or_else(|| invalid("enum value"))?,
+                        ),
+                        None => (name.trim(), next),
+                    };
+                    self.values.push((name.to_string(), value));
+                    next = value + 1;
+                }
+            }
+            (key, _) => return Err(invalid(key)),
+        }
+        Ok(())
+    }
+
+    fn finish(self, line: usize) -> Result<ParamDef, ParamMapError> {
+        let address = self
+            .address
+            .ok_or_else(|| parse_error(line, &format!("{}: missing address", self.name)))?;
+        let largest = u32::MAX >> (8 * (MAX_SIZE - self.size));
+        let max = self.max.unwrap_or_else(|| {
+            self.values
+                .iter()
+                .map(|&(_, value)| value)
+                .max()
+                .unwrap_or(largest)
+        });
+        let min = self.min.unwrap_or(0);
+        if min > max || max > largest {
+            return Err(parse_error(line, &format!("{}: invalid range", self.name)));
+        }
+        if self.encoding.is_some() && self.size != 1 {
+            return Err(parse_error(
+                line,
+                &format!("{}: signed parameters must be 1 byte", self.name),
+            ));
+        }
+        let scale = match self.scale {
+            Some((curve, low, high, clamp)) => {
+                let scale = ParamScale {
+                    min: low,
+                    max: high,
+                    first: min as u8,
+                    last: max as u8,
+                    curve,
+                    off: self.off,
+                    clamp,
+                };
+                let unit = self.unit.as_deref().and_then(Unit::from_symbol);
+                let off_ok = self
+                    .off
+                    .is_none_or(|off| !(min..=max).contains(&(off as u32)));
+                if self.size != 1 || unit.is_none() || !scale.is_valid() || !off_ok {
+                    return Err(parse_error(line, &format!("{}: invalid scale", self.name)));
+                }
+                Some(scale)
+            }
+            None if self.off.is_some() => {
+                return Err(parse_error(
+                    line,
+                    &format!("{}: off without a scale", self.name),
+                ));
+            }
+            None => None,
+        };
+        Ok(ParamDef {
+            name: self.name,
+            address,
+            size: self.size,
+            min,
+            max,
+            unit: self.unit,
+            values: self.values,
+            encoding: self.encoding,
+            scale,
+        })
+    }
+}
+
+/// Value on the right of `key = ` in the TOML subset
+pub(crate) enum TomlValue {
+    String(String),
+    Integer(u32),
+    Array(Vec<String>),
+}
+
+impl TomlValue {
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        if let Some(inner) = text.strip_prefix('[') {
+            let inner = inner.strip_suffix(']')?.trim();
+            let items = inner
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| unquote(item).map(str::to_string))
+                .collect::<Option<_>>()?;
+            return Some(TomlValue::Array(items));
+        }
+        if let Some(s) = unquote(text) {
+            return Some(TomlValue::String(s.to_string()));
+        }
+        parse_number(text).map(TomlValue::Integer)
+    }
+}
+
+fn unquote(text: &str) -> Option<&str> {
+    text.strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Cut a TOML line at a `#` outside of a string
+pub(crate) fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parse a decimal or `0x` hexadecimal number
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Parse `offset_binary:<zero>` or `twos_complement`
+fn parse_encoding(text: &str) -> Option<SignedEncoding> {
+    if text == "twos_complement" {
+        return Some(SignedEncoding::TwosComplement);
+    }
+    let zero = parse_number(text.strip_prefix("offset_binary:")?)?;
+    Some(SignedEncoding::OffsetBinary {
+        zero: u8::try_from(zero).ok()?,
+    })
+}
+
+/// Parse `<curve> <min>..<max>`, optionally followed by `clamp`
+fn parse_scale(text: &str) -> Option<(Curve, f32, f32, bool)> {
+    let mut words = text.split_whitespace();
+    let curve = match words.next()? {
+        "linear" => Curve::Linear,
+        "log" => Curve::Log,
+        _ => return None,
+    };
+    let (low, high) = words.next()?.split_once("..")?;
+    let clamp = match words.next() {
+        None => false,
+        Some("clamp") => true,
+        Some(_) => return None,
+    };
+    if words.next().is_some() {
+        return None;
+    }
+    Some((curve, low.parse().ok()?, high.parse().ok()?, clamp))
+}
+
+pub(crate) fn parse_error(line: usize, message: &str) -> ParamMapError {
+    ParamMapError::Parse {
+        line,
+        message: message.to_string(),
+    }
+}
+
+pub(crate) fn address_from_u24(value: u32) -> Address {
+    Address::new((value >> 16) as u8, (value >> 8) as u8, value as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::params::{pinp, video};
+    use roland_core::Command;
+    use std::error::Error;
+
+    const TOML: &str = r#"
+# Video
+[video.pgm_select]
+address = "010000"
+values = ["hdmi1", "hdmi2", "hdmi3", "hdmi4", "still1", "still2"]
+
+[pinp.position_h]
+address = "020002"
+size = 2
+max = 2000 # center is 1000
+
+[audio.ch1.fader]
+address = "050000"
+max = 0x7F
+unit = "step"
+"#;
+
+    const CSV: &str = "name,address,size,min,max,unit,values
+video.pgm_select,010000,,,,,hdmi1|hdmi2|hdmi3|hdmi4|still1|still2
+pinp.position_h,020002,2,,2000,,
+audio.ch1.fader,050000,,,0x7F,step,
+";
+
+    #[test]
+    fn test_load_formats() {
+        let map = ParameterMap::from_toml(TOML).unwrap();
+        assert_eq!(map, ParameterMap::from_csv(CSV).unwrap());
+        assert_eq!(map.len(), 3);
+
+        let fader = map.lookup("audio.ch1.fader").unwrap();
+        assert_eq!(fader.address, Address::new(0x05, 0x00, 0x00));
+        assert_eq!((fader.min, fader.max), (0, 127));
+        assert_eq!(fader.unit.as_deref(), Some("step"));
+        assert_eq!(map.lookup("video.pgm_select").unwrap().max, 5);
+
+        let sparse = ParameterMap::from_toml(
+            "[fade.mode]\naddress = \"061002\"\nvalues = [\"both\", \"audio=2\"]\n",
+        )
+        .unwrap();
+        let mode = sparse.lookup("fade.mode").unwrap();
+        assert_eq!(mode.values, vec![("both".into(), 0), ("audio".into(), 2)]);
+
+        assert!(matches!(
+            ParameterMap::from_toml("[a]\naddress = \"01\"\n"),
+            Err(ParamMapError::Parse { line: 2, .. })
+        ));
+        assert!(matches!(
+            ParameterMap::from_toml("[a]\nsize = 1\n"),
+            Err(ParamMapError::Parse { line: 1, .. })
+        ));
+        assert!(matches!(
+            map.lookup("video.nope"),
+            Err(ParamMapError::UnknownParameter(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let map = ParameterMap::from_toml(TOML).unwrap();
+        let pgm = map.lookup("video.pgm_select").unwrap();
+        assert_eq!(pgm.encode("HDMI2").unwrap(), vec![1]);
+        assert_eq!(pgm.encode("4").unwrap(), vec![4]);
+        assert_eq!(pgm.decode(&[5]).unwrap(), "still2");
+        assert_eq!(pgm.decode(&[9]).unwrap(), "9");
+
+        let error = pgm.encode("sdi1").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "video.pgm_select: unknown value \"sdi1\""
+        );
+        assert!(matches!(
+            pgm.encode("6"),
+            Err(ParamMapError::UnknownValue { .. })
+        ));
+
+        let position = map.lookup("pinp.position_h").unwrap();
+        assert_eq!(position.encode("1000").unwrap(), vec![0x03, 0xE8]);
+        assert_eq!(position.decode(&[0x07, 0xD0]).unwrap(), "2000");
+        assert_eq!(
+            position.encode("2001").unwrap_err().to_string(),
+            "pinp.position_h: 2001 is outside 0-2000"
+        );
+        assert!(matches!(
+            position.decode(&[1]),
+            Err(ParamMapError::WrongSize { got: 1, .. })
+        ));
+
+        // Sizes the loader rejects fail instead of panicking
+        for size in [0, 5] {
+            let def = ParamDef {
+                size,
+                ..position.clone()
+            };
+            assert!(matches!(
+                def.encode_number(1),
+                Err(ParamMapError::InvalidSize { size: s, .. }) if s == size
+            ));
+            assert!(def.encode("1").is_err());
+        }
+        assert_eq!(
+            ParamDef {
+                size: 5,
+                ..position.clone()
+            }
+            .validate(1)
+            .unwrap_err()
+            .to_string(),
+            "pinp.position_h: size 5 is outside 1-4"
+        );
+    }
+
+    #[test]
+    fn test_signed_parameters() {
+        let map = ParameterMap::from_toml(
+            r#"
+[audio.ch1.pan]
+address = "050003"
+max = 127
+encoding = "offset_binary:64"
+
+[trim]
+address = "050020"
+encoding = "twos_complement"
+"#,
+        )
+        .unwrap();
+        let csv = "name,address,max,encoding\n\
+                   audio.ch1.pan,050003,127,offset_binary:64\n\
+                   trim,050020,,twos_complement\n";
+        assert_eq!(map, ParameterMap::from_csv(csv).unwrap());
+
+        let pan = map.lookup("audio.ch1.pan").unwrap();
+        assert_eq!(pan.encode("-1").unwrap(), vec![0x3F]);
+        assert_eq!(pan.encode("0").unwrap(), vec![0x40]);
+        assert_eq!(pan.encode("63").unwrap(), vec![0x7F]);
+        assert_eq!(pan.decode(&[0x00]).unwrap(), "-64");
+        assert!(matches!(
+            pan.encode("64"),
+            Err(ParamMapError::OutOfRange { value: 128, .. })
+        ));
+        assert!(matches!(
+            pan.encode("-65"),
+            Err(ParamMapError::UnknownValue { .. })
+        ));
+
+        let trim = map.lookup("trim").unwrap();
+        assert_eq!(trim.encode("-1").unwrap(), vec![0xFF]);
+        assert_eq!(trim.decode(&[0x80]).unwrap(), "-128");
+
+        assert!(matches!(
+            ParameterMap::from_toml("[a]\naddress = \"010000\"\nencoding = \"bcd\"\n"),
+            Err(ParamMapError::Parse { line: 3, .. })
+        ));
+        assert!(matches!(
+            ParameterMap::from_toml(
+                "[a]\naddress = \"010000\"\nsize = 2\nencoding = \"twos_complement\"\n"
+            ),
+            Err(ParamMapError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn test_scaled_parameters() {
+        let map = ParameterMap::from_toml(
+            r#"
+[audio.ch1.fader]
+address = "050000"
+min = 1
+max = 127
+unit = "dB"
+scale = "linear -53..10 clamp"
+off = 0
+
+[fade.level]
+address = "061000"
+max = 200
+unit = "%"
+scale = "linear 0..100"
+"#,
+        )
+        .unwrap();
+        let csv = "name,address,min,max,unit,scale,off\n\
+                   audio.ch1.fader,050000,1,127,dB,linear -53..10 clamp,0\n\
+                   fade.level,061000,,200,%,linear 0..100,\n";
+        assert_eq!(map, ParameterMap::from_csv(csv).unwrap());
+
+        let fader = map.lookup("audio.ch1.fader").unwrap();
+        assert_eq!(fader.scale, Some(ParamScale::FADER));
+        assert_eq!(fader.encode("-10.5dB").unwrap(), vec![86]);
+        assert_eq!(fader.encode("+20 dB").unwrap(), vec![127]);
+        assert_eq!(fader.encode("-inf dB").unwrap(), vec![0]);
+        assert_eq!(fader.encode("107").unwrap(), vec![107]);
+        assert_eq!(fader.decode(&[86]).unwrap(), "-10.5dB");
+        assert_eq!(fader.decode(&[0]).unwrap(), "-infdB");
+        assert!(matches!(
+            fader.encode("75%"),
+            Err(ParamMapError::UnknownValue { .. })
+        ));
+
+        let level = map.lookup("fade.level").unwrap();
+        assert_eq!(level.encode("75%").unwrap(), vec![150]);
+        assert_eq!(level.decode(&[150]).unwrap(), "75%");
+        assert_eq!(level.decode(&[201]).unwrap(), "201");
+        assert_eq!(
+            ParamDef {
+                scale: Some(ParamScale::linear(10.0, 100.0, 0, 200)),
+                ..level.clone()
+            }
+            .encode("5%")
+            .unwrap_err()
+            .to_string(),
+            "fade.level: 5% is outside the scale"
+        );
+
+        for bad in [
+            "unit = \"Hz\"\nscale = \"linear 0..10\"",
+            "unit = \"dB\"\nscale = \"cubic 0..10\"",
+            "unit = \"dB\"\nscale = \"linear 10..0\"",
+            "unit = \"dB\"\nscale = \"linear 0..10\"\noff = 5",
+            "off = 0",
+        ] {
+            let text = format!("[a]\naddress = \"010000\"\n{}\n", bad);
+            assert!(
+                matches!(
+                    ParameterMap::from_toml(&text),
+                    Err(ParamMapError::Parse { .. })
+                ),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_named_access() {
+        let (addr, mock) = MockDevice::spawn();
+        let map = ParameterMap::from_csv(CSV).unwrap();
+        let mut client = connect(addr);
+
+        client
+            .write_named(&map, "video.pgm_select", "hdmi2")
+            .unwrap();
+        assert_eq!(mock.parameter(video::PGM_SELECT), Some(1));
+        assert_eq!(
+            client.read_named(&map, "video.pgm_select").unwrap(),
+            "hdmi2"
+        );
+
+        client.write_named(&map, "pinp.position_h", "1500").unwrap();
+        assert_eq!(mock.parameter(pinp::POSITION_H), Some(0x05));
+        mock.clear_received();
+        assert_eq!(client.read_named(&map, "pinp.position_h").unwrap(), "1500");
+        assert_eq!(
+            mock.received(),
+            vec![Command::ReadParameter {
+                address: pinp::POSITION_H,
+                size: 2,
+            }]
+        );
+
+        // Invalid values are rejected before sending
+        mock.clear_received();
+        assert!(matches!(
+            client.write_named(&map, "audio.ch1.fader", "128"),
+            Err(TelnetError::Parameter(ParamMapError::OutOfRange { .. }))
+        ));
+        assert!(matches!(
+            client.write_named(&map, "audio.ch9.fader", "0"),
+            Err(TelnetError::Parameter(ParamMapError::UnknownParameter(_)))
+        ));
+        assert!(mock.received().is_empty());
+
+        mock.set_address_error(video::PGM_SELECT, RolandError::Invalid);
+        assert!(matches!(
+            client.read_named(&map, "video.pgm_select"),
+            Err(TelnetError::Device {
+                error: RolandError::Invalid,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_write_checked() {
+        let (addr, mock) = MockDevice::spawn();
+        let map = ParameterMap::from_toml(
+            "[pinp.size]\naddress = \"020004\"\nmin = 10\nmax = 100\n\
+             [fade.mode]\naddress = \"061002\"\nvalues = [\"both\", \"audio=2\"]\n",
+        )
+        .unwrap();
+        let size = map.lookup("pinp.size").unwrap();
+        let mode = map.lookup("fade.mode").unwrap();
+        let mut client = connect(addr);
+
+        client.write_checked(size, 10).unwrap();
+        client.write_checked(size, 100).unwrap();
+        assert_eq!(mock.parameter(size.address), Some(100));
+        client.write_checked(mode, 2).unwrap();
+        assert_eq!(mock.parameter(mode.address), Some(2));
+
+        mock.clear_received();
+        let error = client.write_checked(size, 9).unwrap_err();
+        assert_eq!(error.to_string(), "pinp.size: 9 is outside 10-100");
+        let device_error = error.source().and_then(Error::source);
+        assert_eq!(
+            device_error.and_then(|e| e.downcast_ref::<RolandError>()),
+            Some(&RolandError::OutOfRange)
+        );
+        assert!(matches!(
+            client.write_checked(size, 101),
+            Err(TelnetError::Parameter(ParamMapError::OutOfRange {
+                value: 101,
+                min: 10,
+                max: 100,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            client.write_checked(mode, 1),
+            Err(TelnetError::Parameter(ParamMapError::UnknownValue { .. }))
+        ));
+        assert!(mock.received().is_empty());
+    }
+}
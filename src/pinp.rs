@@ -0,0 +1,264 @@
+//! Picture-in-picture window control
+
+use crate::video::VideoInput;
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::pinp;
+use roland_core::{Address, RolandError};
+
+/// PinP window border color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BorderColor {
+    /// White
+    White,
+    /// Black
+    Black,
+    /// Red
+    Red,
+    /// Green
+    Green,
+    /// Blue
+    Blue,
+    /// Yellow
+    Yellow,
+}
+
+impl BorderColor {
+    /// Get the parameter value of the color
+    pub fn value(self) -> u8 {
+        self as u8
+    }
+
+    /// Get the color for a parameter value
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(BorderColor::White),
+            1 => Some(BorderColor::Black),
+            2 => Some(BorderColor::Red),
+            3 => Some(BorderColor::Green),
+            4 => Some(BorderColor::Blue),
+            5 => Some(BorderColor::Yellow),
+            _ => None,
+        }
+    }
+}
+
+/// PinP window facade
+///
+/// Positions are in units of 0.1% of the screen, from -1000 to 1000 on
+/// each axis with 0 at the center. Values outside the ranges documented
+/// on each method are rejected with `OutOfRange` rather than clamped, and
+/// nothing is sent then.
+///
+/// # Example
+/// ```no_run
+/// use roland_rs::pinp::Pinp;
+/// use roland_rs::video::VideoInput;
+/// use roland_rs::TelnetClient;
+///
+/// let mut client = TelnetClient::connect("192.168.1.100", 23)?;
+/// let mut pinp = Pinp::new(&mut client);
+/// pinp.set_source(VideoInput::Hdmi3)?;
+/// pinp.set_size(25)?;
+/// pinp.set_position(650, 600)?;
+/// pinp.enable(true)?;
+/// # Ok::<(), roland_rs::TelnetError>(())
+/// ```
+pub struct Pinp<'a> {
+    client: &'a mut TelnetClient,
+}
+
+impl<'a> Pinp<'a> {
+    /// Largest distance of the window position from the center
+    pub const MAX_POSITION: i16 = 1000;
+    /// Smallest window size in percent
+    pub const MIN_SIZE: u8 = 10;
+    /// Largest window size in percent
+    pub const MAX_SIZE: u8 = 100;
+    /// Largest border width
+    pub const MAX_BORDER_WIDTH: u8 = 15;
+
+    /// Create a facade for a connected client
+    pub fn new(client: &'a mut TelnetClient) -> Self {
+        Self { client }
+    }
+
+    /// Show or hide the PinP window
+    pub fn enable(&mut self, enabled: bool) -> Result<(), TelnetError> {
+        self.client
+            .write_parameter_addr(pinp::ENABLE, enabled as u8)
+    }
+
+    /// Check if the PinP window is shown
+    pub fn is_enabled(&mut self) -> Result<bool, TelnetError> {
+        Ok(self.client.read_parameter_addr(pinp::ENABLE, 1)? != 0)
+    }
+
+    /// Select the video shown in the window
+    pub fn set_source(&mut self, input: VideoInput) -> Result<(), TelnetError> {
+        self.client
+            .write_parameter_addr(pinp::SOURCE, input.index())
+    }
+
+    /// Get the video shown in the window
+    pub fn source(&mut self) -> Result<VideoInput, TelnetError> {
+        let value = self.client.read_parameter_addr(pinp::SOURCE, 1)?;
+        VideoInput::from_index(value).ok_or(TelnetError::Protocol(RolandError::InvalidValue))
+    }
+
+    /// Move the window
+    ///
+    /// Both coordinates are written in a single command, so the window
+    /// never jumps to a half-updated position.
+    ///
+    /// # Arguments
+    /// * `x` - Horizontal position (-1000 left to 1000 right)
+    /// * `y` - Vertical position (-1000 bottom to 1000 top)
+    pub fn set_position(&mut self, x: i16, y: i16) -> Result<(), TelnetError> {
+        let [x_msb, x_lsb] = encode_position(x)?;
+        let [y_msb, y_lsb] = encode_position(y)?;
+        self.client
+            .write_parameter_block(pinp::POSITION_H, &[x_msb, x_lsb, y_msb, y_lsb])
+    }
+
+    /// Get the window position as `(x, y)`
+    pub fn position(&mut self) -> Result<(i16, i16), TelnetError> {
+        let x = self.read_position(pinp::POSITION_H)?;
+        let y = self.read_position(pinp::POSITION_V)?;
+        Ok((x, y))
+    }
+
+    /// Resize the window
+    ///
+    /// # Arguments
+    /// * `percent` - Window size (10-100% of the screen)
+    pub fn set_size(&mut self, percent: u8) -> Result<(), TelnetError> {
+        if !(Self::MIN_SIZE..=Self::MAX_SIZE).contains(&percent) {
+            return Err(TelnetError::Protocol(RolandError::OutOfRange));
+        }
+        self.client.write_parameter_addr(pinp::SIZE, percent)
+    }
+
+    /// Get the window size in percent
+    pub fn size(&mut self) -> Result<u8, TelnetError> {
+        self.client.read_parameter_addr(pinp::SIZE, 1)
+    }
+
+    /// Set the window border
+    ///
+    /// # Arguments
+    /// * `color` - Border color
+    /// * `width` - Border width (0-15, 0 is no border)
+    pub fn set_border(&mut self, color: BorderColor, width: u8) -> Result<(), TelnetError> {
+        if width > Self::MAX_BORDER_WIDTH {
+            return Err(TelnetError::Protocol(RolandError::OutOfRange));
+        }
+        self.client
+            .write_parameter_block(pinp::BORDER_COLOR, &[color.value(), width])
+    }
+
+    /// Get the window border as `(color, width)`
+    pub fn border(&mut self) -> Result<(BorderColor, u8), TelnetError> {
+        let color = self.client.read_parameter_addr(pinp::BORDER_COLOR, 1)?;
+        let color = BorderColor::from_value(color)
+            .ok_or(TelnetError::Protocol(RolandError::InvalidValue))?;
+        let width = self.client.read_parameter_addr(pinp::BORDER_WIDTH, 1)?;
+        Ok((color, width))
+    }
+
+    fn read_position(&mut self, address: Address) -> Result<i16, TelnetError> {
+        let value = self.client.read_u16_parameter(address)?;
+        decode_position(value.to_be_bytes())
+    }
+}
+
+/// Encode a position as two bytes (MSB first), offset so 0 is 1000
+fn encode_position(position: i16) -> Result<[u8; 2], TelnetError> {
+    if !(-Pinp::MAX_POSITION..=Pinp::MAX_POSITION).contains(&position) {
+        return Err(TelnetError::Protocol(RolandError::OutOfRange));
+    }
+    Ok(((position + Pinp::MAX_POSITION) as u16).to_be_bytes())
+}
+
+/// Decode a position from two bytes (MSB first)
+fn decode_position(bytes: [u8; 2]) -> Result<i16, TelnetError> {
+    let value = u16::from_be_bytes(bytes);
+    if value > 2 * Pinp::MAX_POSITION as u16 {
+        return Err(TelnetError::Protocol(RolandError::InvalidValue));
+    }
+    Ok(value as i16 - Pinp::MAX_POSITION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::Command;
+
+    #[test]
+    fn test_position_encoding() {
+        assert_eq!(encode_position(0).unwrap(), [0x03, 0xE8]);
+        assert_eq!(encode_position(-1000).unwrap(), [0x00, 0x00]);
+        assert_eq!(encode_position(1000).unwrap(), [0x07, 0xD0]);
+        assert!(encode_position(1001).is_err());
+        assert!(encode_position(-1001).is_err());
+
+        assert_eq!(decode_position([0x03, 0xE8]).unwrap(), 0);
+        assert_eq!(decode_position([0x00, 0x00]).unwrap(), -1000);
+        assert!(decode_position([0x07, 0xD1]).is_err());
+    }
+
+    #[test]
+    fn test_position_round_trip() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut pinp = Pinp::new(&mut client);
+
+        pinp.set_position(-250, 640).unwrap();
+        assert_eq!(pinp.position().unwrap(), (-250, 640));
+        assert_eq!(
+            mock.received()[0],
+            Command::WriteBlock {
+                address: pinp::POSITION_H,
+                data: vec![0x02, 0xEE, 0x06, 0x68],
+            }
+        );
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut pinp = Pinp::new(&mut client);
+
+        for result in [
+            pinp.set_position(0, 1001),
+            pinp.set_position(-1001, 0),
+            pinp.set_size(9),
+            pinp.set_size(101),
+            pinp.set_border(BorderColor::Red, 16),
+        ] {
+            assert!(matches!(
+                result,
+                Err(TelnetError::Protocol(RolandError::OutOfRange))
+            ));
+        }
+        assert!(mock.received().is_empty());
+    }
+
+    #[test]
+    fn test_window_settings() {
+        let (addr, _mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut pinp = Pinp::new(&mut client);
+
+        pinp.set_source(VideoInput::Hdmi4).unwrap();
+        pinp.set_size(25).unwrap();
+        pinp.set_border(BorderColor::Blue, 3).unwrap();
+        pinp.enable(true).unwrap();
+
+        assert_eq!(pinp.source().unwrap(), VideoInput::Hdmi4);
+        assert_eq!(pinp.size().unwrap(), 25);
+        assert_eq!(pinp.border().unwrap(), (BorderColor::Blue, 3));
+        assert!(pinp.is_enabled().unwrap());
+    }
+}
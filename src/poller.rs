@@ -0,0 +1,394 @@
+//! Polling parameters for changes
+//!
+//! A [`Poller`] reads a set of addresses on a background thread through a
+//! [`SharedClient`] and calls back for every value that changed since the
+//! previous poll. Contiguous addresses are read together, so watching a
+//! whole block costs one command per poll. When the device answers with
+//! an error or not at all, the poller waits longer between polls, up to
+//! [`MAX_BACKOFF`] times the interval, and returns to the interval after
+//! the next successful poll.
+//!
+//! # Example
+//! ```ignore
+//! use roland_rs::params::tally;
+//! use roland_rs::poller::Poller;
+//! use roland_rs::shared::SharedClient;
+//! use std::time::Duration;
+//!
+//! let client = SharedClient::connect("192.168.1.100", 23)?;
+//! let poller = Poller::spawn(
+//!     client,
+//!     [tally::PROGRAM, tally::PREVIEW],
+//!     Duration::from_millis(100),
+//!     |address, old, new| println!("{}: {:?} -> {}", address.to_hex(), old, new),
+//! );
+//! ```
+
+use crate::offset;
+use crate::shared::SharedClient;
+use roland_core::Address;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Largest number of addresses read with one command
+pub const MAX_READ_SIZE: u32 = 64;
+
+/// Longest wait between polls after errors, as a multiple of the interval
+pub const MAX_BACKOFF: u32 = 16;
+
+/// Watches addresses on a background thread
+///
+/// The callback gets the address, the value from the previous poll
+/// (`None` the first time an address is read) and the new value, and is
+/// only called when the value changed. It runs on the poller's thread.
+///
+/// Dropping the poller stops the thread.
+pub struct Poller {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled when the state changes, to wake the poller early
+    changed: Condvar,
+}
+
+struct State {
+    watched: BTreeSet<Address>,
+    paused: bool,
+    stopped: bool,
+}
+
+impl Poller {
+    /// Start polling
+    ///
+    /// # Arguments
+    /// * `client` - Connection to poll through
+    /// * `addresses` - Addresses to watch
+    /// * `interval` - Time between polls
+    /// * `callback` - Called with the address, old and new value of each
+    ///   change
+    pub fn spawn(
+        client: SharedClient,
+        addresses: impl IntoIterator<Item = Address>,
+        interval: Duration,
+        callback: impl FnMut(Address, Option<u8>, u8) + Send + 'static,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                watched: addresses.into_iter().collect(),
+                paused: false,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        });
+        let thread = {
+            let shared = shared.clone();
+            thread::spawn(move || run(&shared, &client, interval, callback))
+        };
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Start watching an address
+    ///
+    /// The first poll that reads it reports its value as a change from
+    /// `None`.
+    pub fn watch(&self, address: Address) {
+        self.watch_range(address, 1);
+    }
+
+    /// Start watching `len` addresses from `start`
+    pub fn watch_range(&self, start: Address, len: u32) {
+        self.update(|state| state.watched.extend(range(start, len)));
+    }
+
+    /// Stop watching an address
+    pub fn unwatch(&self, address: Address) {
+        self.unwatch_range(address, 1);
+    }
+
+    /// Stop watching `len` addresses from `start`
+    pub fn unwatch_range(&self, start: Address, len: u32) {
+        self.update(|state| {
+            for address in range(start, len) {
+                state.watched.remove(&address);
+            }
+        });
+    }
+
+    /// Get the watched addresses, in order
+    pub fn watched(&self) -> Vec<Address> {
+        self.state().watched.iter().copied().collect()
+    }
+
+    /// Stop polling until [`Poller::resume`]
+    ///
+    /// A poll already running finishes first.
+    pub fn pause(&self) {
+        self.update(|state| state.paused = true);
+    }
+
+    /// Resume polling right away
+    ///
+    /// Changes made while paused are reported by the next poll.
+    pub fn resume(&self) {
+        self.update(|state| state.paused = false);
+    }
+
+    /// Check if polling is paused
+    pub fn is_paused(&self) -> bool {
+        self.state().paused
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.shared.state.lock().unwrap()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        f(&mut self.state());
+        self.shared.changed.notify_all();
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.update(|state| state.stopped = true);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(
+    shared: &Shared,
+    client: &SharedClient,
+    interval: Duration,
+    mut callback: impl FnMut(Address, Option<u8>, u8),
+) {
+    let mut values: HashMap<Address, u8> = HashMap::new();
+    let mut failures = 0;
+    loop {
+        let watched = {
+            let mut state = shared.state.lock().unwrap();
+            while state.paused && !state.stopped {
+                state = shared.changed.wait(state).unwrap();
+            }
+            if state.stopped {
+                return;
+            }
+            state.watched.clone()
+        };
+        values.retain(|address, _| watched.contains(address));
+
+        let blocks = coalesce(&watched);
+        // Only fails once the connection's worker is gone
+        let Ok(results) = client.with(move |client| {
+            blocks
+                .into_iter()
+                .map(|(start, len)| (start, client.read_parameter_bytes(start, len)))
+                .collect::<Vec<_>>()
+        }) else {
+            return;
+        };
+        let mut failed = false;
+        for (start, result) in results {
+            let Ok(data) = result else {
+                failed = true;
+                continue;
+            };
+            for (i, new) in data.into_iter().enumerate() {
+                let address = offset(start, i);
+                // Unwatched while the poll ran
+                if !shared.state.lock().unwrap().watched.contains(&address) {
+                    continue;
+                }
+                let old = values.insert(address, new);
+                if old != Some(new) {
+                    callback(address, old, new);
+                }
+            }
+        }
+        failures = if failed { failures + 1 } else { 0 };
+
+        let state = shared.state.lock().unwrap();
+        let _ = shared
+            .changed
+            .wait_timeout_while(state, backoff(interval, failures), |state| {
+                !state.stopped && !state.paused
+            })
+            .unwrap();
+    }
+}
+
+/// Get the wait before the next poll after `failures` failed polls in a row
+///
+/// The interval doubles with every failure, up to [`MAX_BACKOFF`] times.
+pub fn backoff(interval: Duration, failures: u32) -> Duration {
+    let factor = 1u32
+        .checked_shl(failures)
+        .unwrap_or(u32::MAX)
+        .min(MAX_BACKOFF);
+    interval.saturating_mul(factor)
+}
+
+/// Split addresses into runs of contiguous addresses
+///
+/// # Returns
+/// * `Vec<(Address, u32)>` - Start and length of each run, at most
+///   [`MAX_READ_SIZE`] long
+pub fn coalesce(addresses: &BTreeSet<Address>) -> Vec<(Address, u32)> {
+    let mut blocks: Vec<(Address, u32)> = Vec::new();
+    for &address in addresses {
+        match blocks.last_mut() {
+            Some((start, len))
+                if *len < MAX_READ_SIZE && offset(*start, *len as usize) == address =>
+            {
+                *len += 1;
+            }
+            _ => blocks.push((address, 1)),
+        }
+    }
+    blocks
+}
+
+/// Get `len` addresses from `start`, stopping at [`Address::MAX`]
+fn range(start: Address, len: u32) -> impl Iterator<Item = Address> {
+    std::iter::successors(Some(start), |address| address.successor()).take(len as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::{Command, RolandError};
+    use std::sync::mpsc::{self, Receiver};
+
+    const INTERVAL: Duration = Duration::from_millis(10);
+    /// Long enough for several polls
+    const QUIET: Duration = Duration::from_millis(100);
+
+    type Change = (Address, Option<u8>, u8);
+
+    fn spawn(addresses: &[Address]) -> (Poller, Receiver<Change>, crate::mock::MockHandle) {
+        let (addr, mock) = MockDevice::spawn();
+        for &address in addresses {
+            mock.set_parameter(address, 0);
+        }
+        let client = SharedClient::new(connect(addr));
+        let (tx, rx) = mpsc::channel();
+        let poller = Poller::spawn(
+            client,
+            addresses.iter().copied(),
+            INTERVAL,
+            move |address, old, new| {
+                let _ = tx.send((address, old, new));
+            },
+        );
+        (poller, rx, mock)
+    }
+
+    fn next(rx: &Receiver<Change>) -> Change {
+        rx.recv_timeout(Duration::from_secs(2)).unwrap()
+    }
+
+    #[test]
+    fn test_change_only_callbacks() {
+        let a = Address::new(0x05, 0x00, 0x00);
+        let b = Address::new(0x05, 0x00, 0x01);
+        let c = Address::new(0x06, 0x00, 0x00);
+        let (_poller, rx, mock) = spawn(&[a, b, c]);
+
+        // The first poll reports every value
+        let mut first = [next(&rx), next(&rx), next(&rx)];
+        first.sort();
+        assert_eq!(first, [(a, None, 0), (b, None, 0), (c, None, 0)]);
+        assert!(rx.recv_timeout(QUIET).is_err());
+
+        mock.set_parameter(b, 42);
+        assert_eq!(next(&rx), (b, Some(0), 42));
+        mock.set_parameter(c, 7);
+        assert_eq!(next(&rx), (c, Some(0), 7));
+        assert!(rx.recv_timeout(QUIET).is_err());
+
+        // The contiguous pair is one read
+        let reads = mock.received();
+        assert!(reads.contains(&Command::read(a, 2).unwrap()));
+        assert!(reads.contains(&Command::read(c, 1).unwrap()));
+        assert!(!reads.contains(&Command::read(b, 1).unwrap()));
+    }
+
+    #[test]
+    fn test_pause_and_watch_changes() {
+        let a = Address::new(0x05, 0x00, 0x00);
+        let b = Address::new(0x05, 0x00, 0x10);
+        let (poller, rx, mock) = spawn(&[a]);
+        assert_eq!(next(&rx), (a, None, 0));
+
+        poller.pause();
+        assert!(poller.is_paused());
+        // Let a poll still running finish
+        thread::sleep(QUIET);
+        mock.set_parameter(a, 1);
+        assert!(rx.recv_timeout(QUIET).is_err());
+        poller.resume();
+        assert_eq!(next(&rx), (a, Some(0), 1));
+
+        mock.set_parameter(b, 9);
+        poller.watch(b);
+        assert_eq!(next(&rx), (b, None, 9));
+        poller.unwatch(a);
+        assert_eq!(poller.watched(), [b]);
+        mock.set_parameter(a, 2);
+        assert!(rx.recv_timeout(QUIET).is_err());
+    }
+
+    #[test]
+    fn test_coalesce_and_backoff() {
+        let addresses: BTreeSet<_> = range(Address::new(0x00, 0xFF, 0xFE), 4)
+            .chain(range(Address::new(0x02, 0x00, 0x00), MAX_READ_SIZE + 1))
+            .chain([Address::new(0x03, 0x00, 0x00)])
+            .collect();
+        assert_eq!(
+            coalesce(&addresses),
+            [
+                (Address::new(0x00, 0xFF, 0xFE), 4),
+                (Address::new(0x02, 0x00, 0x00), MAX_READ_SIZE),
+                (Address::new(0x02, 0x00, 0x40), 1),
+                (Address::new(0x03, 0x00, 0x00), 1),
+            ]
+        );
+        assert_eq!(range(Address::MAX, 3).count(), 1);
+
+        assert_eq!(backoff(INTERVAL, 0), INTERVAL);
+        assert_eq!(backoff(INTERVAL, 3), INTERVAL * 8);
+        assert_eq!(backoff(INTERVAL, 4), INTERVAL * MAX_BACKOFF);
+        assert_eq!(backoff(INTERVAL, 100), INTERVAL * MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_recovers_after_errors() {
+        let a = Address::new(0x05, 0x00, 0x00);
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(a, 3);
+        for _ in 0..3 {
+            mock.inject_error(RolandError::Invalid);
+        }
+        let client = SharedClient::new(connect(addr));
+        let (tx, rx) = mpsc::channel();
+        let _poller = Poller::spawn(client, [a], INTERVAL, move |address, old, new| {
+            let _ = tx.send((address, old, new));
+        });
+
+        // Three failed polls wait 20 + 40 + 80 ms before the fourth
+        let start = std::time::Instant::now();
+        assert_eq!(next(&rx), (a, None, 3));
+        assert!(start.elapsed() >= INTERVAL * 14);
+        assert_eq!(mock.received().len(), 4);
+    }
+}
@@ -0,0 +1,271 @@
+//! Device profiles
+//!
+//! The remote protocol is shared across Roland V-series devices, but
+//! their parameter maps differ. A [`Profile`] resolves the parameters the
+//! high-level facades ([`crate::video::VideoSwitcher`],
+//! [`crate::audio::AudioMixer`]) use to addresses, so the same facade call
+//! works on every supported device. Each client has a profile, the VR-6HD
+//! one unless set otherwise.
+//!
+//! Parameters are named like in a [`ParameterMap`]:
+//!
+//! * `video.pgm_select`, `video.pst_select`, `video.cut`,
+//!   `video.auto_take`, `video.transition_time`, `video.transition_type`,
+//!   `video.wipe_pattern`, `video.mix_effect`, `video.usb_output_source`,
+//!   `video.freeze`, `video.transition_status`, `video.manual_transition`,
+//!   `video.transition_position`
+//! * `video.<input>.freeze`, where `<input>` is one of `hdmi1` to `hdmi4`
+//! * `audio.<channel>.fader`, `audio.<channel>.mute`, `audio.<channel>.solo`,
+//!   `audio.<channel>.pan`, `audio.<channel>.meter`, where `<channel>` is one of `ch1` to `ch6`,
+//!   `usb`, `bluetooth`, `main`, `aux` and `usb_out`
+//! * `audio.usb_out.source`, `audio.phones.source`, `audio.phones.level`,
+//!   `audio.solo_mode`
+//! * `transport.status`, decoded into [`crate::DeviceEvent::TransportChanged`]
+//!   when it changes
+//! * `scene.recall`, `scene.store`, `still.capture`, which the client
+//!   looks up to clear its read cache after a recall and to pick
+//!   [`crate::timeouts::Timeouts`]
+//!
+//! Blocks of parameters repeated for every HDMI input are resolved to the
+//! block of HDMI 1 and the distance between blocks with
+//! [`Profile::input_block`]:
+//!
+//! * `video.scaling`, handled by [`crate::scaling::InputScaling`]
+//!
+//! A custom profile needs entries only for the parameters it is used with.
+
+use crate::audio::AudioChannel;
+use crate::param_map::{ParamMapError, ParameterMap};
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::{audio, output, scene, still, transport, video};
+use roland_core::{Address, ProductModel};
+
+/// Parameter map of a device model
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Profile {
+    /// VR-6HD, using [`roland_core::params`]
+    #[default]
+    Vr6Hd,
+    /// VR-120HD
+    ///
+    /// Detected from the product name, but its parameter map isn't built
+    /// in yet: every lookup fails with `UnknownParameter`. Use
+    /// [`Profile::Custom`] with a map from the VR-120HD remote control
+    /// guide until then.
+    Vr120Hd,
+    /// Parameters from a map file
+    Custom(ParameterMap),
+}
+
+impl Profile {
+    /// Get the profile of a product, as reported by `VER`
+    ///
+    /// Returns `None` for products without a profile.
+    pub fn for_product(product: &str) -> Option<Self> {
+        match ProductModel::parse(product) {
+            ProductModel::Vr6Hd => Some(Profile::Vr6Hd),
+            ProductModel::Vr120Hd => Some(Profile::Vr120Hd),
+            _ => None,
+        }
+    }
+
+    /// Get the address of a parameter
+    ///
+    /// # Returns
+    /// * `Result<Address, TelnetError>` - Address, or `UnknownParameter`
+    ///   if the profile doesn't have the parameter
+    pub fn address(&self, name: &str) -> Result<Address, TelnetError> {
+        let address = match self {
+            Profile::Vr6Hd => vr6hd_address(name),
+            Profile::Vr120Hd => None,
+            Profile::Custom(map) => return Ok(map.lookup(name)?.address),
+        };
+        address.ok_or_else(|| ParamMapError::UnknownParameter(name.to_string()).into())
+    }
+
+    /// Get the first address and the stride of a block repeated per input
+    ///
+    /// The block of HDMI n starts `(n - 1) * stride` bytes after the
+    /// returned address. A custom map gives the blocks of HDMI 1 and 2,
+    /// e.g. `video.hdmi1.scaling` and `video.hdmi2.scaling` for
+    /// `video.scaling`, and the stride is the distance between them.
+    ///
+    /// # Returns
+    /// * `Result<(Address, u32), TelnetError>` - Address of the HDMI 1
+    ///   block and the stride, or `UnknownParameter`
+    pub fn input_block(&self, name: &str) -> Result<(Address, u32), TelnetError> {
+        let unknown = || ParamMapError::UnknownParameter(name.to_string());
+        match self {
+            Profile::Vr6Hd => match name {
+                "video.scaling" => Ok((video::SCALING_BASE, video::SCALING_STRIDE)),
+                _ => Err(unknown().into()),
+            },
+            Profile::Vr120Hd => Err(unknown().into()),
+            Profile::Custom(map) => {
+                let (prefix, block) = name.rsplit_once('.').ok_or_else(unknown)?;
+                let first = map.lookup(&format!("{}.hdmi1.{}", prefix, block))?.address;
+                let second = map.lookup(&format!("{}.hdmi2.{}", prefix, block))?.address;
+                let stride = u32::from(second)
+                    .checked_sub(u32::from(first))
+                    .ok_or_else(unknown)?;
+                Ok((first, stride))
+            }
+        }
+    }
+}
+
+/// Resolve a parameter name with the constants in [`roland_core::params`]
+fn vr6hd_address(name: &str) -> Option<Address> {
+    let address = match name {
+        "video.pgm_select" => video::PGM_SELECT,
+        "video.pst_select" => video::PST_SELECT,
+        "video.cut" => video::CUT,
+        "video.auto_take" => video::AUTO_TAKE,
+        "video.transition_time" => video::TRANSITION_TIME,
+        "video.transition_type" => video::TRANSITION_TYPE,
+        "video.wipe_pattern" => video::WIPE_PATTERN,
+        "video.mix_effect" => video::MIX_EFFECT,
+        "video.usb_output_source" => video::USB_OUTPUT_SOURCE,
+        "video.freeze" => video::FREEZE,
+        "video.transition_status" => video::TRANSITION_STATUS,
+        "video.manual_transition" => video::MANUAL_TRANSITION,
+        "video.transition_position" => video::TRANSITION_POSITION,
+        "video.hdmi1.freeze" => video::input_freeze(0),
+        "video.hdmi2.freeze" => video::input_freeze(1),
+        "video.hdmi3.freeze" => video::input_freeze(2),
+        "video.hdmi4.freeze" => video::input_freeze(3),
+        "audio.usb_out.source" => output::USB_OUT_SOURCE,
+        "audio.phones.source" => output::PHONES_SOURCE,
+        "audio.phones.level" => output::PHONES_LEVEL,
+        "audio.solo_mode" => output::SOLO_MODE,
+        "transport.status" => transport::STATUS,
+        "scene.recall" => scene::RECALL,
+        "scene.store" => scene::STORE,
+        "still.capture" => still::CAPTURE,
+        _ => {
+            let (channel, param) = name.strip_prefix("audio.")?.split_once('.')?;
+            let channel = AudioChannel::from_name(channel)?;
+            let offset = match param {
+                "fader" => audio::LEVEL,
+                "mute" => audio::MUTE,
+                "solo" => audio::SOLO,
+                "pan" => audio::PAN,
+                "meter" => return Some(channel.meter_address()),
+                _ => return None,
+            };
+            channel.address(offset)
+        }
+    };
+    Some(address)
+}
+
+impl Profile {
+    /// Check if `address` is the address of a parameter
+    ///
+    /// `false` if the profile doesn't have the parameter.
+    pub(crate) fn is(&self, name: &str, address: Address) -> bool {
+        self.address(name).is_ok_and(|a| a == address)
+    }
+}
+
+impl TelnetClient {
+    /// Get the profile the facades resolve addresses with
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Set the profile the facades resolve addresses with
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.profile = profile;
+    }
+
+    /// Pick the profile matching the connected device
+    ///
+    /// Asks the device for its product name with `VER` and switches to
+    /// the matching profile.
+    ///
+    /// # Returns
+    /// * `Result<&Profile, TelnetError>` - The new profile, or
+    ///   `UnknownProduct` if no profile matches (the profile is unchanged)
+    pub fn detect_profile(&mut self) -> Result<&Profile, TelnetError> {
+        let (product, _) = self.get_version()?;
+        self.profile =
+            Profile::for_product(&product).ok_or(TelnetError::UnknownProduct(product))?;
+        Ok(&self.profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::video::{VideoInput, VideoSwitcher};
+    use roland_core::Command;
+
+    #[test]
+    fn test_same_call_under_two_profiles() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        VideoSwitcher::new(&mut client)
+            .select_program(VideoInput::Hdmi2)
+            .unwrap();
+
+        let map = ParameterMap::from_toml("[video.pgm_select]\naddress = \"0A0000\"\n").unwrap();
+        client.set_profile(Profile::Custom(map));
+        VideoSwitcher::new(&mut client)
+            .select_program(VideoInput::Hdmi2)
+            .unwrap();
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::write_parameter(video::PGM_SELECT, 1),
+                Command::write_parameter(Address::new(0x0A, 0x00, 0x00), 1),
+            ]
+        );
+
+        // Parameters missing from the profile are rejected before sending
+        assert!(matches!(
+            VideoSwitcher::new(&mut client).cut(),
+            Err(TelnetError::Parameter(ParamMapError::UnknownParameter(name)))
+                if name == "video.cut"
+        ));
+        assert_eq!(mock.received().len(), 2);
+    }
+
+    #[test]
+    fn test_vr6hd_addresses() {
+        let profile = Profile::Vr6Hd;
+        assert_eq!(profile.address("video.cut").unwrap(), video::CUT);
+        assert_eq!(
+            profile.address("audio.ch3.fader").unwrap(),
+            audio::channel(2, audio::LEVEL)
+        );
+        assert_eq!(
+            profile.address("audio.aux.mute").unwrap(),
+            AudioChannel::Aux.address(audio::MUTE)
+        );
+        assert_eq!(profile.address("scene.recall").unwrap(), scene::RECALL);
+        for name in ["audio.ch7.fader", "audio.ch1.gain", "video", "scene.name"] {
+            assert!(profile.address(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_detect_profile() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        mock.set_version("VR-120HD", "1.00");
+        assert_eq!(client.detect_profile().unwrap(), &Profile::Vr120Hd);
+
+        mock.set_version("V-160HD", "1.00");
+        let err = client.detect_profile().unwrap_err();
+        assert!(matches!(&err, TelnetError::UnknownProduct(product) if product == "V-160HD"));
+        assert_eq!(err.to_string(), "No device profile for product \"V-160HD\"");
+        assert_eq!(client.profile(), &Profile::Vr120Hd);
+
+        mock.set_version("VR-6HD", "1.00");
+        assert_eq!(client.detect_profile().unwrap(), &Profile::Vr6Hd);
+    }
+}
@@ -0,0 +1,286 @@
+//! Client-side rate limiting
+//!
+//! The device silently drops commands that arrive faster than it can
+//! process them, e.g. during a fast fader sweep. With a rate limit set,
+//! the client spaces commands out; with coalescing on as well, writes that
+//! would have to wait are held back instead, and a later write to the same
+//! address replaces the held value, so only the latest position of a
+//! fader goes over the wire.
+
+use crate::{TelnetClient, TelnetError};
+use roland_core::Address;
+use std::mem;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Pacing state of a client
+#[derive(Debug, Default)]
+pub(crate) struct RateLimiter {
+    /// Minimum time between commands, `None` without a limit
+    interval: Option<Duration>,
+    /// Earliest time the next command may be sent
+    next_send: Option<Instant>,
+    coalesce: bool,
+    /// Held-back writes in the order their addresses were first written
+    pending: Vec<(Address, u8)>,
+}
+
+impl RateLimiter {
+    /// Check if a command could be sent right now
+    fn ready(&self) -> bool {
+        self.next_send.is_none_or(|next| Instant::now() >= next)
+    }
+
+    /// Hold back a write, replacing a held value for the same address
+    fn defer(&mut self, address: Address, value: u8) {
+        match self.pending.iter_mut().find(|(a, _)| *a == address) {
+            Some((_, held)) => *held = value,
+            None => self.pending.push((address, value)),
+        }
+    }
+}
+
+impl TelnetClient {
+    /// Limit the number of commands sent per second
+    ///
+    /// Commands are spaced at least `1 / max_commands_per_sec` apart,
+    /// sleeping before a command when needed. This includes every
+    /// command of a batch write. `0` removes the limit.
+    pub fn set_rate_limit(&mut self, max_commands_per_sec: u32) {
+        self.rate.interval =
+            (max_commands_per_sec > 0).then(|| Duration::from_secs(1) / max_commands_per_sec);
+        if self.rate.interval.is_none() {
+            self.rate.next_send = None;
+        }
+    }
+
+    /// Get the minimum time between commands, if a rate limit is set
+    pub fn rate_limit_interval(&self) -> Option<Duration> {
+        self.rate.interval
+    }
+
+    /// Coalesce writes that the rate limit would delay
+    ///
+    /// With coalescing on, [`TelnetClient::write_parameter`],
+    /// [`TelnetClient::write_parameter_addr`],
+    /// [`TelnetClient::write_parameter_nowait`] and glide steps don't wait
+    /// for the rate limit: a write that can't be sent yet is held back and `Ok(())` is
+    /// returned right away. If the same address is written again before
+    /// then, only the last value is sent (last write wins), also when the
+    /// rate limit allows sending it right away.
+    ///
+    /// Held writes are sent before the next command that goes out, by
+    /// [`TelnetClient::poll_event`] once the rate limit allows, or by
+    /// [`TelnetClient::flush_writes`]; errors for them are reported there.
+    /// Without a rate limit, this has no effect.
+    pub fn set_coalescing(&mut self, coalesce: bool) {
+        self.rate.coalesce = coalesce;
+    }
+
+    /// Check if writes are coalesced
+    pub fn coalescing(&self) -> bool {
+        self.rate.coalesce
+    }
+
+    /// Number of held-back writes waiting to be sent
+    pub fn pending_writes(&self) -> usize {
+        self.rate.pending.len()
+    }
+
+    /// Send all held-back writes, waiting for the rate limit
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or the first error. The
+    ///   remaining writes are still sent.
+    pub fn flush_writes(&mut self) -> Result<(), TelnetError> {
+        let mut result = Ok(());
+        for (address, value) in mem::take(&mut self.rate.pending) {
+            let written = self.write_parameter_now(address, value);
+            if result.is_ok() {
+                result = written;
+            }
+        }
+        result
+    }
+
+    /// Send the held-back writes once the rate limit allows the next
+    /// command
+    ///
+    /// Called while polling and by the worker of a
+    /// [`SharedClient`](crate::shared::SharedClient), so the last value of a
+    /// sweep reaches the device without [`TelnetClient::flush_writes`].
+    pub(crate) fn flush_due_writes(&mut self) -> Result<(), TelnetError> {
+        if self.rate.pending.is_empty() || !self.rate.ready() {
+            return Ok(());
+        }
+        self.flush_writes()
+    }
+
+    /// Hold back a write if coalescing applies and it can't be sent yet
+    ///
+    /// Returns whether the write was held back.
+    pub(crate) fn coalesce_write(&mut self, address: Address, value: u8) -> bool {
+        if !self.rate.coalesce || self.rate.interval.is_none() {
+            return false;
+        }
+        if self.rate.ready() {
+            // The new value goes out now; a held one is out of date
+            self.rate.pending.retain(|(held, _)| *held != address);
+            return false;
+        }
+        self.rate.defer(address, value);
+        true
+    }
+
+    /// Sleep until the rate limit allows the next command
+    pub(crate) fn throttle(&mut self) {
+        let Some(interval) = self.rate.interval else {
+            return;
+        };
+        if let Some(next) = self.rate.next_send {
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+            }
+        }
+        self.rate.next_send = Some(Instant::now() + interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::shared::SharedClient;
+    use roland_core::Command;
+
+    #[test]
+    fn test_rate_limit_spaces_commands() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        client.set_rate_limit(50);
+        assert_eq!(
+            client.rate_limit_interval(),
+            Some(Duration::from_millis(20))
+        );
+
+        let start = Instant::now();
+        for value in 0..5 {
+            client.write_parameter("123456", value).unwrap();
+        }
+        // The first command goes out right away
+        assert!(start.elapsed() >= Duration::from_millis(80));
+        assert_eq!(mock.received().len(), 5);
+
+        client.set_rate_limit(0);
+        assert_eq!(client.rate_limit_interval(), None);
+    }
+
+    #[test]
+    fn test_coalesce_writes_to_one_address() {
+        let (addr, mock) = MockDevice::spawn();
+        let address = Address::new(0x12, 0x34, 0x56);
+        let mut client = connect(addr);
+        client.set_rate_limit(10);
+        client.set_coalescing(true);
+
+        for value in 0..100 {
+            client.write_parameter_addr(address, value).unwrap();
+        }
+        assert_eq!(client.pending_writes(), 1);
+        client.flush_writes().unwrap();
+        assert_eq!(client.pending_writes(), 0);
+
+        assert!(mock.received().len() < 10);
+        assert_eq!(
+            mock.received().last(),
+            Some(&Command::write_parameter(address, 99))
+        );
+        assert_eq!(mock.parameter(address), Some(99));
+    }
+
+    #[test]
+    fn test_ready_write_replaces_held_value() {
+        let (addr, mock) = MockDevice::spawn();
+        let address = Address::new(0x05, 0x00, 0x00);
+        let mut client = connect(addr);
+        client.set_rate_limit(20);
+        client.set_coalescing(true);
+
+        for value in 0..40 {
+            let sent = mock.received().len();
+            let start = Instant::now();
+            client.write_parameter_addr(address, value).unwrap();
+            assert!(start.elapsed() < Duration::from_millis(25));
+
+            // Only the value just written goes out, never a held older one
+            for command in &mock.received()[sent..] {
+                assert_eq!(command, &Command::write_parameter(address, value));
+            }
+            thread::sleep(Duration::from_millis(16));
+        }
+        client.flush_writes().unwrap();
+        assert_eq!(mock.parameter(address), Some(39));
+    }
+
+    #[test]
+    fn test_held_writes_sent_when_due() {
+        let (addr, mock) = MockDevice::spawn();
+        let address = Address::new(0x05, 0x00, 0x00);
+        let mut client = connect(addr);
+        client.set_rate_limit(20);
+        client.set_coalescing(true);
+
+        // The end of a sweep
+        for value in 0..50 {
+            client.write_parameter_addr(address, value).unwrap();
+        }
+        assert_eq!(client.pending_writes(), 1);
+        assert_eq!(client.poll_event().unwrap(), None);
+        assert_eq!(client.pending_writes(), 1);
+
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(client.poll_event().unwrap(), None);
+        assert_eq!(client.pending_writes(), 0);
+        assert_eq!(mock.parameter(address), Some(49));
+
+        // A shared client sends them on its own
+        let shared = SharedClient::new(client);
+        shared
+            .with(move |client| {
+                for value in 0..50 {
+                    client.write_parameter_addr(address, 100 + value).unwrap();
+                }
+            })
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(mock.parameter(address), Some(149));
+    }
+
+    #[test]
+    fn test_held_writes_sent_before_next_command() {
+        let (addr, mock) = MockDevice::spawn();
+        let fader = Address::new(0x05, 0x00, 0x00);
+        let other = Address::new(0x05, 0x00, 0x01);
+        let mut client = connect(addr);
+        client.set_rate_limit(20);
+        client.set_coalescing(true);
+
+        client.write_parameter_addr(fader, 1).unwrap();
+        client.write_parameter_addr(other, 7).unwrap();
+        client.write_parameter_addr(fader, 2).unwrap();
+        assert_eq!(client.pending_writes(), 2);
+
+        // A read sees the held writes applied
+        assert_eq!(client.read_parameter_addr(fader, 1).unwrap(), 2);
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::write_parameter(fader, 1),
+                Command::write_parameter(other, 7),
+                Command::write_parameter(fader, 2),
+                Command::read(fader, 1).unwrap(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Raw frames: the undocumented command path
+//!
+//! [`TelnetClient::send_command_raw`] returns the exact text of the
+//! response along with the parsed response, for logging and for firmware
+//! quirks the parser doesn't know about. [`TelnetClient::send_raw`]
+//! sends bytes the crate can't build a [`Command`] for, such as commands
+//! missing from the documentation, and returns the next frame unparsed.
+//!
+//! Raw bytes go through the same path as every other command: they wait
+//! for XON and for pending nowait writes to be answered, echoes are
+//! dropped, and parameter changes the device reports in the meantime are
+//! queued as events, so the connection stays in step.
+
+use crate::wire::Direction;
+use crate::{TelnetClient, TelnetError};
+use roland_core::engine::answers_with;
+use roland_core::{Command, Response, RolandError};
+use std::io::Write;
+use std::time::Instant;
+
+/// STX (start of text, RS-232 only)
+const STX: u8 = 0x02;
+/// Telnet IAC, which would be taken as negotiation
+const IAC: u8 = 0xFF;
+
+impl TelnetClient {
+    /// Send a command and get the response with the frame it came in
+    ///
+    /// Same as [`TelnetClient::send_command`], but also returns the text
+    /// the device sent, e.g. `DTH:123456,01;`.
+    ///
+    /// # Returns
+    /// * `Result<(Response, String), TelnetError>` - Parsed response and
+    ///   its frame, or error
+    pub fn send_command_raw(
+        &mut self,
+        command: &Command,
+    ) -> Result<(Response, String), TelnetError> {
+        let timeout = self.timeouts.for_command(command, &self.profile);
+        self.exchange(command, timeout)
+    }
+
+    /// Send arbitrary bytes and get the next frame unparsed
+    ///
+    /// This is the undocumented command path: `bytes` go out as they are
+    /// and the frame that answers them comes back as it was received,
+    /// terminator included. `bytes` must be exactly one frame, i.e. end
+    /// with `;` and contain no other `;`, no control characters but an
+    /// optional leading STX, and no Telnet IAC (0xFF); otherwise the
+    /// device would answer several commands, or none, and later responses
+    /// would go to the wrong commands.
+    ///
+    /// If `bytes` parse as a [`Command`], the response is matched as for
+    /// that command, so a parameter change for another address is queued
+    /// as an event instead of being returned. Otherwise the next frame
+    /// that isn't flow control, an echo or the answer to a nowait write is
+    /// returned, and the read cache is cleared, since the command may
+    /// have changed anything.
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>, TelnetError>` - Frame received, `SyntaxError` if
+    ///   `bytes` aren't one frame (nothing is sent then), `Timeout`, or
+    ///   another error
+    pub fn send_raw(&mut self, bytes: &[u8]) -> Result<Vec<u8>, TelnetError> {
+        let frame = check_frame(bytes)?;
+        let command = Command::parse(frame).ok();
+        let timeout = match &command {
+            Some(command) => self.timeouts.for_command(command, &self.profile),
+            None => self.timeouts.other,
+        };
+
+        self.flush_writes()?;
+        self.wait_until_resumed()?;
+        if let (Some(subscription), Some(Command::ReadParameter { address, .. })) =
+            (&self.subscription, &command)
+        {
+            subscription.set_awaiting(Some(*address));
+        }
+        if let Some(cache) = &mut self.cache {
+            match &command {
+                Some(command) => cache.invalidate_for(command, &self.profile),
+                None => cache.clear(),
+            }
+        }
+
+        self.throttle();
+        self.wire.log(Direction::Sent, bytes);
+        self.stream.write_all(bytes)?;
+        self.stream.flush()?;
+        let encoded = frame.trim_start_matches('\x02').to_string();
+        self.echo.sent(command.as_ref(), encoded);
+
+        let response = self.read_raw_response(command.as_ref(), Instant::now() + timeout);
+        if let Some(subscription) = &self.subscription {
+            subscription.set_awaiting(None);
+        }
+        if matches!(response, Err(TelnetError::Timeout)) {
+            // An echo still due would no longer line up with its command
+            self.echo.clear();
+        }
+        response.map(String::into_bytes)
+    }
+
+    /// Read the frame answering raw bytes, `command` if they parsed as one
+    fn read_raw_response(
+        &mut self,
+        command: Option<&Command>,
+        deadline: Instant,
+    ) -> Result<String, TelnetError> {
+        loop {
+            let frame = self
+                .read_frame_before(deadline)?
+                .ok_or(TelnetError::Timeout)?;
+            if self.update_flow_control(&frame) {
+                continue;
+            }
+            let Ok(response) = self.parse(&frame) else {
+                return Ok(frame);
+            };
+            if self.settle_write(&response) {
+                continue;
+            }
+            match command {
+                Some(command) if !answers_with(command, &response, self.parse_options) => {
+                    if let Some((address, data)) = response.as_data() {
+                        self.push_events(address, data);
+                    }
+                }
+                _ => return Ok(frame),
+            }
+        }
+    }
+}
+
+/// Check that `bytes` are exactly one frame
+fn check_frame(bytes: &[u8]) -> Result<&str, TelnetError> {
+    let body = bytes.strip_prefix(&[STX]).unwrap_or(bytes);
+    let one_frame = body.len() > 1
+        && body.iter().position(|&b| b == b';') == Some(body.len() - 1)
+        && !body.iter().any(|&b| b.is_ascii_control() || b == IAC);
+    match std::str::from_utf8(bytes) {
+        Ok(frame) if one_frame => Ok(frame),
+        _ => Err(TelnetError::Protocol(RolandError::SyntaxError)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, Fault, FaultPlan, MockDevice};
+    use crate::DeviceEvent;
+    use roland_core::Address;
+    use std::time::Duration;
+
+    const ADDRESS: Address = Address::new(0x12, 0x34, 0x56);
+    const FADER: Address = Address::new(0x05, 0x00, 0x00);
+
+    #[test]
+    fn test_send_command_raw() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(ADDRESS, 0x1A);
+        let mut client = connect(addr);
+
+        mock.inject_unsolicited(FADER, 0x40);
+        let (response, raw) = client
+            .send_command_raw(&Command::read(ADDRESS, 1).unwrap())
+            .unwrap();
+        assert_eq!(
+            response,
+            Response::Data {
+                address: ADDRESS,
+                value: 0x1A
+            }
+        );
+        assert_eq!(raw, "DTH:123456,1A;");
+        assert_eq!(
+            client.poll_event().unwrap(),
+            Some(DeviceEvent::ParameterChanged {
+                address: FADER,
+                value: 0x40
+            })
+        );
+    }
+
+    #[test]
+    fn test_send_raw_frame_boundaries() {
+        let split = Fault::Split {
+            chunk: 2,
+            gap: Duration::from_millis(2),
+        };
+        let mut plan = FaultPlan::new();
+        plan.add(1, split.clone());
+        plan.add(2, split);
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_fault_plan(plan);
+        mock.set_parameter(FADER, 0x22);
+        let mut client = connect(addr);
+
+        // The answer arrives in pieces and comes back as one frame
+        assert_eq!(client.send_raw(b"XYZ:1;").unwrap(), b"ERR:0;");
+        assert_eq!(
+            client.send_raw(b"\x02RQH:050000,000001;").unwrap(),
+            b"DTH:050000,22;"
+        );
+
+        // Anything but exactly one frame is refused without sending
+        for bytes in [
+            &b"DTH:050000,01;DTH:050000,02;"[..],
+            b"DTH:050000,01",
+            b";",
+            b"DTH:050000,01;\r\n",
+            b"\xFF\xFB\x01;",
+        ] {
+            assert!(matches!(
+                client.send_raw(bytes),
+                Err(TelnetError::Protocol(RolandError::SyntaxError))
+            ));
+        }
+        assert_eq!(mock.received(), vec![Command::read(FADER, 1).unwrap()]);
+    }
+
+    #[test]
+    fn test_send_raw_stays_in_step() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_echo(true);
+        mock.set_parameter(ADDRESS, 0x10);
+        let mut client = connect(addr);
+        client.set_max_in_flight(4);
+
+        // Neither the ACKs of pending nowait writes nor the echo answer it
+        client.write_parameter_nowait(FADER, 0x01).unwrap();
+        client.write_parameter_nowait(FADER, 0x02).unwrap();
+        assert_eq!(client.send_raw(b"XYZ;").unwrap(), b"ERR:0;");
+        assert_eq!(client.pending_write_acks(), 0);
+        assert_eq!(client.send_raw(b"DTH:050000,03;").unwrap(), b"\x06");
+
+        // Later commands still get their own answers
+        assert_eq!(client.read_parameter_addr(ADDRESS, 1).unwrap(), 0x10);
+        assert_eq!(mock.parameter(FADER), Some(0x03));
+    }
+}
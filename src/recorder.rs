@@ -0,0 +1,436 @@
+//! Macro recording and playback
+//!
+//! A [`Recorder`] captures the commands a client sends, with their timing
+//! and whether the device rejected them, into a [`Macro`].
+//! [`TelnetClient::play_macro`] sends the writes of a macro again with the
+//! same relative timing, so an operator's actions can be replayed later.
+//!
+//! Macros are saved as JSON, with times in milliseconds since the start of
+//! the recording and the device error code of rejected commands:
+//!
+//! ```text
+//! {"steps":[{"at_ms":0,"command":"DTH:050000,01;"},{"at_ms":850,"command":"DTH:050000,02;","error":4}]}
+//! ```
+
+use crate::backup::{parse_json, DumpFormatError, JsonValue};
+use crate::cancel::CancellationToken;
+use crate::telnet::Iac;
+use crate::wire::Direction;
+use crate::{TelnetClient, TelnetError};
+use roland_core::decoder::Decoder;
+use roland_core::{Command, Response, RolandError};
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Recorded command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroStep {
+    /// Time since the start of the recording
+    pub at: Duration,
+    /// Command as it was sent
+    pub command: Command,
+    /// Error the device answered with, if any
+    pub error: Option<RolandError>,
+}
+
+/// Recorded sequence of commands
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Macro {
+    /// Steps in the order they were sent
+    pub steps: Vec<MacroStep>,
+}
+
+impl Macro {
+    /// Encode the macro as JSON
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"steps\":[");
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str(&format!(
+                "{{\"at_ms\":{},\"command\":\"{}\"",
+                step.at.as_millis(),
+                step.command.encode()
+            ));
+            if let Some(code) = step.error.as_ref().and_then(RolandError::code) {
+                json.push_str(&format!(",\"error\":{}", code));
+            }
+            json.push('}');
+        }
+        json.push_str("]}");
+        json
+    }
+
+    /// Decode a macro from JSON
+    ///
+    /// Unknown fields are ignored.
+    pub fn from_json(json: &str) -> Result<Self, DumpFormatError> {
+        let value = parse_json(json)?;
+        let Some(JsonValue::Array(entries)) = value.field("steps") else {
+            return Err(DumpFormatError::MissingField("steps"));
+        };
+
+        let mut steps = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let at = match entry.field("at_ms") {
+                Some(JsonValue::Number(ms)) => Duration::from_millis(*ms),
+                _ => return Err(DumpFormatError::MissingField("at_ms")),
+            };
+            let command = match entry.field("command") {
+                Some(JsonValue::String(command)) => Command::parse(command)
+                    .map_err(|_| DumpFormatError::InvalidCommand(command.clone()))?,
+                _ => return Err(DumpFormatError::MissingField("command")),
+            };
+            let error = match entry.field("error") {
+                None => None,
+                Some(JsonValue::Number(code)) if *code <= 255 => {
+                    Some(RolandError::from_code(*code as u8))
+                }
+                Some(_) => return Err(DumpFormatError::MissingField("error")),
+            };
+            steps.push(MacroStep { at, command, error });
+        }
+        Ok(Self { steps })
+    }
+
+    /// Save the macro as JSON
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+
+    /// Load a macro saved with [`Macro::save`]
+    ///
+    /// Malformed files give an `InvalidData` error wrapping the
+    /// [`DumpFormatError`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Self::from_json(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Length of the macro at normal speed
+    pub fn duration(&self) -> Duration {
+        self.steps.last().map_or(Duration::ZERO, |step| step.at)
+    }
+}
+
+/// Recording state shared with the wire logger
+struct Recording {
+    start: Instant,
+    steps: Vec<MacroStep>,
+    /// Indices of steps waiting for their response, oldest first
+    outstanding: VecDeque<usize>,
+    iac: Iac,
+    decoder: Decoder,
+}
+
+impl Recording {
+    fn sent(&mut self, bytes: &[u8]) {
+        // Telnet refusals are logged as sent bytes too; they don't parse
+        let Some(command) = std::str::from_utf8(bytes)
+            .ok()
+            .and_then(|command| Command::parse(command).ok())
+        else {
+            return;
+        };
+        self.outstanding.push_back(self.steps.len());
+        self.steps.push(MacroStep {
+            at: self.start.elapsed(),
+            command,
+            error: None,
+        });
+    }
+
+    fn received(&mut self, bytes: &[u8]) {
+        let mut data = Vec::new();
+        self.iac.filter(bytes, &mut data);
+        self.decoder.push(&data);
+        while let Some(response) = self.decoder.decode() {
+            let Ok(response) = response else {
+                continue;
+            };
+            let Some(&index) = self.outstanding.front() else {
+                continue;
+            };
+            let answers = match (&self.steps[index].command, &response) {
+                (_, Response::Error(_)) => true,
+                (Command::ReadParameter { address, .. }, _) => response
+                    .as_data()
+                    .is_some_and(|(received, _)| received == *address),
+                (Command::GetVersion, _) => response.as_version().is_some(),
+                (_, response) => response.is_ack(),
+            };
+            if answers {
+                self.outstanding.pop_front();
+                if let Response::Error(e) = response {
+                    self.steps[index].error = Some(e);
+                }
+            }
+        }
+    }
+}
+
+/// Records the commands a client sends
+///
+/// The recorder uses the client's wire logger, replacing any logger that
+/// was set.
+///
+/// # Example
+/// ```no_run
+/// use roland_rs::recorder::Recorder;
+/// use roland_rs::TelnetClient;
+///
+/// let mut client = TelnetClient::connect("192.168.1.100", 23).unwrap();
+/// let recorder = Recorder::start(&mut client);
+/// client.write_parameter("050000", 0x01).unwrap();
+/// let recording = recorder.stop(&mut client);
+/// recording.save("macro.json").unwrap();
+/// ```
+pub struct Recorder {
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl Recorder {
+    /// Start recording the commands `client` sends
+    pub fn start(client: &mut TelnetClient) -> Self {
+        let recording = Arc::new(Mutex::new(Recording {
+            start: Instant::now(),
+            steps: Vec::new(),
+            outstanding: VecDeque::new(),
+            iac: Iac::default(),
+            decoder: Decoder::with_options(client.parse_options()),
+        }));
+        let shared = Arc::clone(&recording);
+        client.set_wire_logger(move |direction, bytes| {
+            let mut recording = shared.lock().unwrap();
+            match direction {
+                Direction::Sent => recording.sent(bytes),
+                Direction::Received => recording.received(bytes),
+            }
+        });
+        Self { recording }
+    }
+
+    /// Number of commands recorded so far
+    pub fn len(&self) -> usize {
+        self.recording.lock().unwrap().steps.len()
+    }
+
+    /// Check if no commands have been recorded yet
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stop recording and clear the client's wire logger
+    pub fn stop(self, client: &mut TelnetClient) -> Macro {
+        client.clear_wire_logger();
+        let mut recording = self.recording.lock().unwrap();
+        Macro {
+            steps: std::mem::take(&mut recording.steps),
+        }
+    }
+}
+
+impl TelnetClient {
+    /// Replay the writes of a macro
+    ///
+    /// Steps are sent with their recorded relative timing, divided by
+    /// `speed` (2.0 plays twice as fast). Reads and version requests are
+    /// skipped.
+    ///
+    /// # Arguments
+    /// * `recording` - Macro to play
+    /// * `speed` - Playback speed, must be positive
+    /// * `replay_failed` - Also send writes the device rejected during
+    ///   recording; errors for them are ignored again. Without this, they
+    ///   are skipped.
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or the first error
+    ///   (`InvalidValue` if `speed` isn't positive)
+    pub fn play_macro(
+        &mut self,
+        recording: &Macro,
+        speed: f32,
+        replay_failed: bool,
+    ) -> Result<(), TelnetError> {
+        self.play_macro_cancellable(recording, speed, replay_failed, &CancellationToken::new())
+    }
+
+    /// Replay the writes of a macro until `cancel` is cancelled
+    ///
+    /// Works like [`TelnetClient::play_macro`]; the token is also checked
+    /// while waiting for the next step, so cancelling takes effect before
+    /// it is due.
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, `Cancelled`, or the first
+    ///   error
+    pub fn play_macro_cancellable(
+        &mut self,
+        recording: &Macro,
+        speed: f32,
+        replay_failed: bool,
+        cancel: &CancellationToken,
+    ) -> Result<(), TelnetError> {
+        if !(speed.is_finite() && speed > 0.0) {
+            return Err(TelnetError::Protocol(RolandError::InvalidValue));
+        }
+
+        let start = Instant::now();
+        for step in &recording.steps {
+            let is_write = matches!(
+                step.command,
+                Command::WriteParameter { .. } | Command::WriteBlock { .. }
+            );
+            if !is_write || (step.error.is_some() && !replay_failed) {
+                continue;
+            }
+
+            cancel.sleep_until(start + step.at.div_f32(speed))?;
+            match self.send_command(&step.command)? {
+                Response::Acknowledge => {}
+                Response::Error(_) if step.error.is_some() => {}
+                Response::Error(e) => return Err(TelnetError::device(&step.command, e)),
+                _ => return Err(TelnetError::Protocol(RolandError::InvalidResponse)),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::Address;
+    use std::thread;
+
+    #[test]
+    fn test_record_and_play() {
+        let (addr, mock) = MockDevice::spawn();
+        let address = Address::new(0x05, 0x00, 0x00);
+        let mut client = connect(addr);
+
+        let recorder = Recorder::start(&mut client);
+        client.write_parameter_addr(address, 0x01).unwrap();
+        thread::sleep(Duration::from_millis(40));
+        client.read_parameter_addr(address, 1).unwrap();
+        client.write_parameter_addr(address, 0x02).unwrap();
+        assert_eq!(recorder.len(), 3);
+        let recording = recorder.stop(&mut client);
+        assert!(recording.steps[1].at >= Duration::from_millis(40));
+
+        mock.set_parameter(address, 0x00);
+        mock.clear_received();
+        let start = Instant::now();
+        client.play_macro(&recording, 2.0, false).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::write_parameter(address, 0x01),
+                Command::write_parameter(address, 0x02),
+            ]
+        );
+        assert_eq!(mock.parameter(address), Some(0x02));
+        assert!(client.play_macro(&recording, 0.0, false).is_err());
+    }
+
+    #[test]
+    fn test_play_cancelled() {
+        let (addr, mock) = MockDevice::spawn();
+        let address = Address::new(0x05, 0x00, 0x00);
+        let write = |value, ms| MacroStep {
+            at: Duration::from_millis(ms),
+            command: Command::write_parameter(address, value),
+            error: None,
+        };
+        let recording = Macro {
+            steps: vec![write(0x01, 0), write(0x02, 5000)],
+        };
+        let mut client = connect(addr);
+
+        // Cancelling stops the wait for the second step
+        let cancel = CancellationToken::new();
+        let start = Instant::now();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                cancel.cancel();
+            });
+            assert!(matches!(
+                client.play_macro_cancellable(&recording, 1.0, false, &cancel),
+                Err(TelnetError::Cancelled)
+            ));
+        });
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(mock.parameter(address), Some(0x01));
+        assert_eq!(client.read_parameter_addr(address, 1).unwrap(), 0x01);
+    }
+
+    #[test]
+    fn test_failed_steps() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let recorder = Recorder::start(&mut client);
+        client.write_parameter("050000", 0x01).unwrap();
+        mock.inject_error(RolandError::Invalid);
+        client.write_parameter("050001", 0x01).unwrap_err();
+        let recording = recorder.stop(&mut client);
+        assert_eq!(recording.steps[0].error, None);
+        assert_eq!(recording.steps[1].error, Some(RolandError::Invalid));
+
+        mock.clear_received();
+        client.play_macro(&recording, 100.0, false).unwrap();
+        assert_eq!(mock.received().len(), 1);
+
+        // Replayed failures are tolerated
+        mock.clear_received();
+        mock.set_address_error(Address::new(0x05, 0x00, 0x01), RolandError::Invalid);
+        client.play_macro(&recording, 100.0, true).unwrap();
+        assert_eq!(mock.received().len(), 2);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let recording = Macro {
+            steps: vec![
+                MacroStep {
+                    at: Duration::ZERO,
+                    command: Command::write_parameter(Address::new(0x05, 0x00, 0x00), 0x01),
+                    error: None,
+                },
+                MacroStep {
+                    at: Duration::from_millis(850),
+                    command: Command::WriteBlock {
+                        address: Address::new(0x05, 0x00, 0x00),
+                        data: vec![0x02, 0x03],
+                    },
+                    error: Some(RolandError::Invalid),
+                },
+            ],
+        };
+        let json = recording.to_json();
+        assert_eq!(Macro::from_json(&json).unwrap(), recording);
+        assert_eq!(recording.duration(), Duration::from_millis(850));
+
+        let path = std::env::temp_dir().join(format!("roland-macro-{}.json", std::process::id()));
+        recording.save(&path).unwrap();
+        assert_eq!(Macro::load(&path).unwrap(), recording);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            Macro::from_json(r#"{"steps":[{"at_ms":0,"command":"FOO;"}]}"#),
+            Err(DumpFormatError::InvalidCommand("FOO;".to_string()))
+        );
+        assert_eq!(
+            Macro::from_json("{}"),
+            Err(DumpFormatError::MissingField("steps"))
+        );
+    }
+}
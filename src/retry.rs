@@ -0,0 +1,217 @@
+//! Automatic retries for transient device errors
+//!
+//! The device sometimes answers `ERR:4` (Invalid) when a write races with
+//! an internal operation such as a menu change, and the same write
+//! succeeds a moment later. With a [`RetryPolicy`] set,
+//! [`TelnetClient::write_parameter`] and [`TelnetClient::read_parameter`]
+//! (and their `_addr` variants) retry such errors with exponential backoff.
+
+use crate::{TelnetClient, TelnetError};
+use roland_core::RolandError;
+use std::io::ErrorKind;
+use std::thread;
+use std::time::Duration;
+
+/// Class of errors worth retrying
+///
+/// Errors that retrying can't fix, like `OutOfRange` or `SyntaxError`,
+/// have no class and are never retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetryClass {
+    /// Device answered `ERR:4` (Invalid command due to other settings)
+    Invalid,
+    /// No response in time
+    ///
+    /// A late response to the first attempt may still arrive; for reads
+    /// it is told apart by its address, but a late ACK can be taken as
+    /// the answer to the retried write.
+    Timeout,
+}
+
+impl RetryClass {
+    /// Get the class of an error, if it has one
+    pub fn of(error: &TelnetError) -> Option<Self> {
+        match error {
+            TelnetError::Protocol(RolandError::Invalid)
+            | TelnetError::Device {
+                error: RolandError::Invalid,
+                ..
+            } => Some(RetryClass::Invalid),
+            TelnetError::Timeout => Some(RetryClass::Timeout),
+            TelnetError::Io(e)
+                if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) =>
+            {
+                Some(RetryClass::Timeout)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// When and how often to retry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first one
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for every further retry
+    pub backoff: Duration,
+    /// Error classes to retry
+    pub retry_on: Vec<RetryClass>,
+}
+
+impl RetryPolicy {
+    /// Policy that never retries (the client's default)
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::ZERO,
+            retry_on: Vec::new(),
+        }
+    }
+
+    /// Check if an error should be retried
+    pub fn should_retry(&self, error: &TelnetError) -> bool {
+        RetryClass::of(error).is_some_and(|class| self.retry_on.contains(&class))
+    }
+}
+
+/// Three attempts, waiting 50 ms and then 100 ms, retrying Invalid and timeouts
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Duration::from_millis(50),
+            retry_on: vec![RetryClass::Invalid, RetryClass::Timeout],
+        }
+    }
+}
+
+impl TelnetClient {
+    /// Set the retry policy for parameter reads and writes
+    ///
+    /// Every attempt goes over the wire again, so each one shows up in the
+    /// wire logger.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+
+    /// Get the retry policy
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    /// Run `operation` until it succeeds, fails permanently or runs out of
+    /// attempts, returning the last result
+    pub(crate) fn retrying<T>(
+        &mut self,
+        mut operation: impl FnMut(&mut Self) -> Result<T, TelnetError>,
+    ) -> Result<T, TelnetError> {
+        let mut delay = self.retry.backoff;
+        let mut attempt = 1;
+        loop {
+            match operation(self) {
+                Err(e) if attempt < self.retry.max_attempts && self.retry.should_retry(&e) => {
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::offset;
+    use roland_core::{Address, Command};
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_retry_until_success() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        client.set_retry_policy(fast_policy());
+
+        mock.inject_error(RolandError::Invalid);
+        mock.inject_error(RolandError::Invalid);
+        client.write_parameter("123456", 0x01).unwrap();
+        assert_eq!(mock.received().len(), 3);
+        assert_eq!(mock.parameter(Address::new(0x12, 0x34, 0x56)), Some(0x01));
+
+        mock.clear_received();
+        mock.inject_error(RolandError::Invalid);
+        assert_eq!(client.read_parameter("123456", 1).unwrap(), 0x01);
+        assert_eq!(mock.received().len(), 2);
+    }
+
+    #[test]
+    fn test_retry_block_write() {
+        let (addr, mock) = MockDevice::spawn();
+        let address = Address::new(0x12, 0x34, 0x56);
+        let mut client = connect(addr);
+        client.set_retry_policy(fast_policy());
+
+        mock.inject_error(RolandError::Invalid);
+        mock.inject_error(RolandError::Invalid);
+        client
+            .write_parameter_block(address, &[0x01, 0x02])
+            .unwrap();
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::WriteBlock {
+                    address,
+                    data: vec![0x01, 0x02]
+                };
+                3
+            ]
+        );
+        assert_eq!(mock.parameter(offset(address, 1)), Some(0x02));
+    }
+
+    #[test]
+    fn test_give_up_after_max_attempts() {
+        let (addr, mock) = MockDevice::spawn();
+        let address = Address::new(0x12, 0x34, 0x56);
+        mock.set_address_error(address, RolandError::Invalid);
+        let mut client = connect(addr);
+        client.set_retry_policy(fast_policy());
+
+        assert!(matches!(
+            client.write_parameter_addr(address, 0x01),
+            Err(TelnetError::Device {
+                error: RolandError::Invalid,
+                ..
+            })
+        ));
+        assert_eq!(mock.received().len(), 3);
+    }
+
+    #[test]
+    fn test_permanent_errors_not_retried() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        client.set_retry_policy(fast_policy());
+
+        mock.inject_error(RolandError::OutOfRange);
+        assert!(client.write_parameter("123456", 0x01).is_err());
+        mock.inject_error(RolandError::SyntaxError);
+        assert!(client.write_parameter("123456", 0x01).is_err());
+        assert_eq!(mock.received().len(), 2);
+
+        // Without a policy nothing is retried
+        client.set_retry_policy(RetryPolicy::never());
+        mock.inject_error(RolandError::Invalid);
+        assert!(client.write_parameter("123456", 0x01).is_err());
+        assert_eq!(mock.received().len(), 3);
+    }
+}
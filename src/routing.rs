@@ -0,0 +1,308 @@
+//! Output routing
+//!
+//! Each physical audio output carries one of the mix buses, and each
+//! input channel has a send level to the AUX bus. All of it lives in one
+//! block starting at [`output::ROUTING_START`], so
+//! [`TelnetClient::get_routing`] reads the whole routing with a single
+//! command.
+
+use crate::audio::{AudioChannel, Db};
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::output;
+use roland_core::RolandError;
+use std::fmt;
+
+/// Number of input channels with an AUX send (CH1-6, USB, Bluetooth)
+const SEND_COUNT: usize = 8;
+
+/// Physical audio output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputBus {
+    /// MAIN OUT jacks
+    MainOut,
+    /// AUX OUT jacks
+    AuxOut,
+    /// USB audio to the computer
+    UsbOut,
+    /// Headphones
+    Phones,
+    /// Audio embedded in HDMI OUT
+    HdmiOut,
+}
+
+impl OutputBus {
+    /// All outputs, in parameter order
+    pub const ALL: [OutputBus; output::OUTPUT_COUNT as usize] = [
+        OutputBus::MainOut,
+        OutputBus::AuxOut,
+        OutputBus::UsbOut,
+        OutputBus::Phones,
+        OutputBus::HdmiOut,
+    ];
+
+    /// Get the index of the output in the routing block (0-4)
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// Get the name of the output as printed on the device
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputBus::MainOut => "MAIN OUT",
+            OutputBus::AuxOut => "AUX OUT",
+            OutputBus::UsbOut => "USB OUT",
+            OutputBus::Phones => "PHONES",
+            OutputBus::HdmiOut => "HDMI OUT",
+        }
+    }
+}
+
+/// Mix bus assigned to an output
+///
+/// Values this crate doesn't know decode to [`BusSource::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum BusSource {
+    /// MAIN bus
+    Main,
+    /// AUX bus
+    Aux,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl BusSource {
+    /// All documented sources
+    pub const ALL: [BusSource; 2] = [BusSource::Main, BusSource::Aux];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => BusSource::Main,
+            1 => BusSource::Aux,
+            value => BusSource::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            BusSource::Main => 0,
+            BusSource::Aux => 1,
+            BusSource::Other(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for BusSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusSource::Main => f.write_str("MAIN"),
+            BusSource::Aux => f.write_str("AUX"),
+            BusSource::Other(value) => write!(f, "({:02X})", value),
+        }
+    }
+}
+
+/// Output assignments and AUX sends, as read by
+/// [`TelnetClient::get_routing`]
+///
+/// `Display` prints both as a table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoutingMatrix {
+    assign: [BusSource; output::OUTPUT_COUNT as usize],
+    aux_sends: [Db; SEND_COUNT],
+}
+
+impl RoutingMatrix {
+    /// Decode the routing block, starting at [`output::ROUTING_START`]
+    ///
+    /// # Returns
+    /// * `Result<Self, RolandError>` - Routing, or `InvalidResponse` unless
+    ///   `data` is exactly [`output::ROUTING_SIZE`] bytes
+    pub fn decode(data: &[u8]) -> Result<Self, RolandError> {
+        if data.len() != output::ROUTING_SIZE as usize {
+            return Err(RolandError::InvalidResponse);
+        }
+        let at = |address| (u32::from(address) - u32::from(output::ROUTING_START)) as usize;
+        Ok(Self {
+            assign: OutputBus::ALL
+                .map(|o| BusSource::from_value(data[at(output::assign(o.index()))])),
+            aux_sends: core::array::from_fn(|i| Db::from_byte(data[at(output::aux_send(i as u8))])),
+        })
+    }
+
+    /// Get the bus assigned to an output
+    pub fn source(&self, output: OutputBus) -> BusSource {
+        self.assign[output.index() as usize]
+    }
+
+    /// Get the AUX send level of an input channel, `None` for buses
+    pub fn aux_send(&self, channel: AudioChannel) -> Option<Db> {
+        let index = send_index(channel).ok()?;
+        Some(self.aux_sends[index as usize])
+    }
+}
+
+impl fmt::Display for RoutingMatrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<10}Source", "Output")?;
+        for output in OutputBus::ALL {
+            writeln!(f, "{:<10}{}", output.name(), self.source(output))?;
+        }
+        writeln!(f)?;
+        write!(f, "{:<10}AUX send", "Input")?;
+        for (channel, level) in AudioChannel::ALL.iter().zip(self.aux_sends) {
+            let name = channel.name().to_ascii_uppercase();
+            if level == Db::NEG_INFINITY {
+                write!(f, "\n{:<10}-INF", name)?;
+            } else {
+                write!(f, "\n{:<10}{:.1} dB", name, level.value())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TelnetClient {
+    /// Set the AUX send level of an input channel
+    ///
+    /// Output buses have no AUX send; they fail with `Invalid` without
+    /// sending anything.
+    pub fn set_aux_send(&mut self, channel: AudioChannel, level: Db) -> Result<(), TelnetError> {
+        let index = send_index(channel)?;
+        self.write_parameter_addr(output::aux_send(index), level.to_byte())
+    }
+
+    /// Assign a mix bus to a physical output
+    pub fn set_output_assign(
+        &mut self,
+        output: OutputBus,
+        source: BusSource,
+    ) -> Result<(), TelnetError> {
+        self.write_parameter_addr(output::assign(output.index()), source.value())
+    }
+
+    /// Get the mix bus assigned to a physical output
+    pub fn output_assign(&mut self, output: OutputBus) -> Result<BusSource, TelnetError> {
+        let value = self.read_parameter_addr(output::assign(output.index()), 1)?;
+        Ok(BusSource::from_value(value))
+    }
+
+    /// Read all output assignments and AUX sends with one command
+    pub fn get_routing(&mut self) -> Result<RoutingMatrix, TelnetError> {
+        let data = self.read_parameter_bytes(output::ROUTING_START, output::ROUTING_SIZE)?;
+        Ok(RoutingMatrix::decode(&data)?)
+    }
+}
+
+/// Get the AUX send index of an input channel
+fn send_index(channel: AudioChannel) -> Result<u8, TelnetError> {
+    if channel.is_bus() {
+        return Err(TelnetError::Protocol(RolandError::Invalid));
+    }
+    Ok(channel as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::offset;
+    use roland_core::Command;
+
+    /// Routing block of a device with AUX on the phones and a monitor mix
+    const CAPTURED: [u8; 24] = [
+        0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, // assign, reserved
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // reserved
+        0x6B, 0x5F, 0x00, 0x00, 0x7F, 0x01, 0x6B, 0x00, // AUX sends
+    ];
+
+    #[test]
+    fn test_decode_captured_block() {
+        let matrix = RoutingMatrix::decode(&CAPTURED).unwrap();
+        assert_eq!(
+            OutputBus::ALL.map(|o| matrix.source(o)),
+            [
+                BusSource::Main,
+                BusSource::Aux,
+                BusSource::Main,
+                BusSource::Aux,
+                BusSource::Main,
+            ]
+        );
+        assert_eq!(matrix.aux_send(AudioChannel::Ch1), Some(Db::ZERO));
+        assert_eq!(matrix.aux_send(AudioChannel::Ch2), Some(Db(-6.0)));
+        assert_eq!(matrix.aux_send(AudioChannel::Ch3), Some(Db::NEG_INFINITY));
+        assert_eq!(matrix.aux_send(AudioChannel::Ch5), Some(Db::MAX));
+        assert_eq!(matrix.aux_send(AudioChannel::Ch6), Some(Db::MIN));
+        assert_eq!(matrix.aux_send(AudioChannel::Usb), Some(Db::ZERO));
+        assert_eq!(matrix.aux_send(AudioChannel::Main), None);
+
+        assert_eq!(
+            matrix.to_string(),
+            "Output    Source\n\
+             MAIN OUT  MAIN\n\
+             AUX OUT   AUX\n\
+             USB OUT   MAIN\n\
+             PHONES    AUX\n\
+             HDMI OUT  MAIN\n\
+             \n\
+             Input     AUX send\n\
+             CH1       0.0 dB\n\
+             CH2       -6.0 dB\n\
+             CH3       -INF\n\
+             CH4       -INF\n\
+             CH5       10.0 dB\n\
+             CH6       -53.0 dB\n\
+             USB       0.0 dB\n\
+             BLUETOOTH -INF"
+        );
+
+        assert_eq!(
+            RoutingMatrix::decode(&CAPTURED[..23]),
+            Err(RolandError::InvalidResponse)
+        );
+    }
+
+    #[test]
+    fn test_get_routing_single_read() {
+        let (addr, mock) = MockDevice::spawn();
+        for (i, &b) in CAPTURED.iter().enumerate() {
+            mock.set_parameter(offset(output::ROUTING_START, i), b);
+        }
+        let mut client = connect(addr);
+
+        let matrix = client.get_routing().unwrap();
+        assert_eq!(matrix, RoutingMatrix::decode(&CAPTURED).unwrap());
+        assert_eq!(
+            mock.received(),
+            vec![Command::read(output::ROUTING_START, 24).unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_set_routing() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        client.set_aux_send(AudioChannel::Ch4, Db(-6.0)).unwrap();
+        client
+            .set_output_assign(OutputBus::Phones, BusSource::Aux)
+            .unwrap();
+        assert_eq!(
+            client.output_assign(OutputBus::Phones).unwrap(),
+            BusSource::Aux
+        );
+        assert_eq!(mock.parameter(output::aux_send(3)), Some(95));
+        assert_eq!(
+            client.get_routing().unwrap().aux_send(AudioChannel::Ch4),
+            Some(Db(-6.0))
+        );
+
+        assert!(matches!(
+            client.set_aux_send(AudioChannel::Aux, Db::ZERO),
+            Err(TelnetError::Protocol(RolandError::Invalid))
+        ));
+    }
+}
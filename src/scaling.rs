@@ -0,0 +1,199 @@
+//! Per-input scaling: zoom, position and flip of the HDMI inputs
+//!
+//! Every HDMI input has its own block of scaling parameters, laid out
+//! the same way and a fixed stride apart. The block of an input is found
+//! through the client's [`crate::profile::Profile`] (`video.scaling`, see
+//! [`crate::profile::Profile::input_block`]), so maps with a different
+//! layout only need to give the first two blocks.
+
+use crate::units::Percent;
+use crate::video::VideoInput;
+use crate::{offset, TelnetClient, TelnetError};
+use roland_core::params::video;
+use roland_core::{Address, RolandError};
+
+/// Flip bit: horizontal
+const FLIP_H_BIT: u8 = 0x01;
+/// Flip bit: vertical
+const FLIP_V_BIT: u8 = 0x02;
+
+/// Scaling of an HDMI input
+///
+/// Positions are in percent from the center, -100 to 100 on each axis;
+/// values outside that are rejected with `OutOfRange` and nothing is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InputScaling {
+    /// Magnification, 0% shows the whole picture
+    pub zoom: Percent,
+    /// Horizontal position (-100 left to 100 right)
+    pub pos_x: i16,
+    /// Vertical position (-100 bottom to 100 top)
+    pub pos_y: i16,
+    /// Mirror the picture left to right
+    pub flip_h: bool,
+    /// Mirror the picture top to bottom
+    pub flip_v: bool,
+}
+
+impl InputScaling {
+    /// Largest distance of the position from the center
+    pub const MAX_POSITION: i16 = 100;
+
+    /// Write the scaling of an input in a single command
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, `OutOfRange` for a position
+    ///   outside +-100, or `Invalid` for a still (nothing is sent then)
+    pub fn apply(&self, client: &mut TelnetClient, input: VideoInput) -> Result<(), TelnetError> {
+        let bytes = [
+            self.zoom.value(),
+            encode_position(self.pos_x)?,
+            encode_position(self.pos_y)?,
+            (self.flip_h as u8 * FLIP_H_BIT) | (self.flip_v as u8 * FLIP_V_BIT),
+        ];
+        let base = scaling_block(client, input)?;
+        client.write_parameter_block(base, &bytes)
+    }
+
+    /// Read the scaling of an input
+    ///
+    /// # Returns
+    /// * `Result<InputScaling, TelnetError>` - Scaling, `InvalidValue` if
+    ///   the device holds a zoom or position out of range, or `Invalid`
+    ///   for a still
+    pub fn read(client: &mut TelnetClient, input: VideoInput) -> Result<Self, TelnetError> {
+        let base = scaling_block(client, input)?;
+        let mut read = |param: u8| client.read_parameter_addr(offset(base, param as usize), 1);
+        let zoom = Percent::new(read(video::SCALING_ZOOM)?)
+            .map_err(|_| TelnetError::Protocol(RolandError::InvalidValue))?;
+        let pos_x = decode_position(read(video::SCALING_POSITION_H)?)?;
+        let pos_y = decode_position(read(video::SCALING_POSITION_V)?)?;
+        let flip = read(video::SCALING_FLIP)?;
+        Ok(Self {
+            zoom,
+            pos_x,
+            pos_y,
+            flip_h: flip & FLIP_H_BIT != 0,
+            flip_v: flip & FLIP_V_BIT != 0,
+        })
+    }
+}
+
+/// Get the first address of the scaling block of an HDMI input
+fn scaling_block(client: &TelnetClient, input: VideoInput) -> Result<Address, TelnetError> {
+    if matches!(input, VideoInput::Still1 | VideoInput::Still2) {
+        return Err(TelnetError::Protocol(RolandError::Invalid));
+    }
+    let (base, stride) = client.profile().input_block("video.scaling")?;
+    Ok(offset(base, (input.index() as u32 * stride) as usize))
+}
+
+fn encode_position(position: i16) -> Result<u8, TelnetError> {
+    if !(-InputScaling::MAX_POSITION..=InputScaling::MAX_POSITION).contains(&position) {
+        return Err(TelnetError::Protocol(RolandError::OutOfRange));
+    }
+    Ok(video::SCALING_POSITION_ENCODING.encode_i8(position as i8)?)
+}
+
+fn decode_position(byte: u8) -> Result<i16, TelnetError> {
+    video::SCALING_POSITION_ENCODING
+        .decode_i8(byte)
+        .map(i16::from)
+        .ok()
+        .filter(|position| position.abs() <= InputScaling::MAX_POSITION)
+        .ok_or(TelnetError::Protocol(RolandError::InvalidValue))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::param_map::ParameterMap;
+    use crate::profile::Profile;
+    use roland_core::Command;
+
+    const SCALING: InputScaling = InputScaling {
+        zoom: Percent::MAX,
+        pos_x: -25,
+        pos_y: 100,
+        flip_h: true,
+        flip_v: false,
+    };
+
+    #[test]
+    fn test_hdmi3_uses_stride() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        SCALING.apply(&mut client, VideoInput::Hdmi3).unwrap();
+        // HDMI 3 is two strides after HDMI 1
+        assert_eq!(
+            offset(video::SCALING_BASE, 2 * video::SCALING_STRIDE as usize),
+            Address::new(0x01, 0x06, 0x20)
+        );
+        let encoded: Vec<String> = mock.received().iter().map(Command::encode).collect();
+        assert_eq!(encoded, vec!["DTH:010620,64,4B,C8,01;"]);
+        assert_eq!(
+            InputScaling::read(&mut client, VideoInput::Hdmi3).unwrap(),
+            SCALING
+        );
+        assert_eq!(
+            InputScaling::read(&mut client, VideoInput::Hdmi1).unwrap(),
+            InputScaling {
+                pos_x: -100,
+                pos_y: -100,
+                ..InputScaling::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_custom_stride() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let map = ParameterMap::from_toml(
+            "[video.hdmi1.scaling]\naddress = \"200000\"\n\
+             [video.hdmi2.scaling]\naddress = \"200100\"\n",
+        )
+        .unwrap();
+        client.set_profile(Profile::Custom(map));
+
+        let scaling = InputScaling {
+            flip_v: true,
+            ..InputScaling::default()
+        };
+        scaling.apply(&mut client, VideoInput::Hdmi4).unwrap();
+        let encoded: Vec<String> = mock.received().iter().map(Command::encode).collect();
+        assert_eq!(encoded, vec!["DTH:200300,00,64,64,02;"]);
+        assert_eq!(
+            InputScaling::read(&mut client, VideoInput::Hdmi4).unwrap(),
+            scaling
+        );
+    }
+
+    #[test]
+    fn test_invalid_scaling() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let scaling = InputScaling {
+            pos_x: 101,
+            ..InputScaling::default()
+        };
+        assert!(matches!(
+            scaling.apply(&mut client, VideoInput::Hdmi1),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+        assert!(matches!(
+            SCALING.apply(&mut client, VideoInput::Still2),
+            Err(TelnetError::Protocol(RolandError::Invalid))
+        ));
+        assert!(mock.received().is_empty());
+
+        mock.set_parameter(Address::new(0x01, 0x06, 0x12), 0xC9);
+        assert!(matches!(
+            InputScaling::read(&mut client, VideoInput::Hdmi2),
+            Err(TelnetError::Protocol(RolandError::InvalidValue))
+        ));
+    }
+}
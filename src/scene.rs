@@ -0,0 +1,340 @@
+//! Scene memory recall, store and names
+
+use crate::text::check_text;
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::scene;
+use roland_core::{Address, RolandError};
+use std::error::Error;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often busy status parameters are polled
+const BUSY_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+impl TelnetClient {
+    /// Recall a scene memory
+    ///
+    /// Returns once the device has acknowledged the recall, which may
+    /// still be in progress; see [`TelnetClient::recall_scene_blocking`].
+    ///
+    /// # Arguments
+    /// * `n` - Scene memory number (1-30)
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `OutOfRange` for an invalid
+    ///   scene number (nothing is sent then)
+    pub fn recall_scene(&mut self, n: u8) -> Result<(), TelnetError> {
+        let value = scene_value(n)?;
+        self.write_parameter_addr(scene::RECALL, value)
+    }
+
+    /// Store the current settings to a scene memory
+    ///
+    /// # Arguments
+    /// * `n` - Scene memory number (1-30)
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `OutOfRange` for an invalid
+    ///   scene number (nothing is sent then)
+    pub fn store_scene(&mut self, n: u8) -> Result<(), TelnetError> {
+        let value = scene_value(n)?;
+        self.write_parameter_addr(scene::STORE, value)
+    }
+
+    /// Get the name of a scene memory
+    ///
+    /// # Arguments
+    /// * `n` - Scene memory number (1-30)
+    ///
+    /// # Returns
+    /// * `Result<String, TelnetError>` - Name without padding, or
+    ///   `OutOfRange` for an invalid scene number (nothing is sent then)
+    pub fn scene_name(&mut self, n: u8) -> Result<String, TelnetError> {
+        let index = scene_value(n)?;
+        self.read_string_parameter(scene::name(index), scene::NAME_LENGTH)
+    }
+
+    /// Rename a scene memory
+    ///
+    /// An empty name clears the name to spaces.
+    ///
+    /// # Arguments
+    /// * `n` - Scene memory number (1-30)
+    /// * `name` - Printable ASCII, at most [`scene::NAME_LENGTH`] characters
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, `OutOfRange` for an invalid
+    ///   scene number or a name that is too long, or `InvalidValue` for
+    ///   other characters (nothing is sent then)
+    pub fn set_scene_name(&mut self, n: u8, name: &str) -> Result<(), TelnetError> {
+        let index = scene_value(n)?;
+        self.write_string_parameter(scene::name(index), name, scene::NAME_LENGTH)
+    }
+
+    /// Store the current settings to a scene memory and name it
+    ///
+    /// The number and the name are checked before anything is sent, so an
+    /// invalid one fails without storing.
+    ///
+    /// # Returns
+    /// * `Result<(), StoreSceneError>` - Success, or the step that failed
+    ///   with its error: `Store` or `InvalidName` if nothing was stored,
+    ///   `Name` if the settings are stored under the old name
+    pub fn store_scene_named(&mut self, n: u8, name: &str) -> Result<(), StoreSceneError> {
+        scene_value(n).map_err(StoreSceneError::Store)?;
+        check_text(name, scene::NAME_LENGTH).map_err(StoreSceneError::InvalidName)?;
+        self.store_scene(n).map_err(StoreSceneError::Store)?;
+        self.set_scene_name(n, name).map_err(StoreSceneError::Name)
+    }
+
+    /// Recall a scene memory and wait until the recall has completed
+    ///
+    /// After the ACK, the scene busy status is polled until the device
+    /// reports idle.
+    ///
+    /// # Arguments
+    /// * `n` - Scene memory number (1-30)
+    /// * `timeout` - How long to wait for the recall to complete
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, `OutOfRange` for an invalid
+    ///   scene number, or `Timeout` if the device is still busy
+    pub fn recall_scene_blocking(&mut self, n: u8, timeout: Duration) -> Result<(), TelnetError> {
+        self.recall_scene(n)?;
+        self.wait_while_busy(scene::BUSY, timeout)
+    }
+
+    /// Poll a busy status parameter until it reads 0
+    ///
+    /// Returns `Timeout` if it is still non-zero after `timeout`.
+    pub(crate) fn wait_while_busy(
+        &mut self,
+        busy: Address,
+        timeout: Duration,
+    ) -> Result<(), TelnetError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.read_parameter_addr(busy, 1)? == 0 {
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(TelnetError::Timeout);
+            }
+            thread::sleep(BUSY_POLL_INTERVAL.min(deadline - now));
+        }
+    }
+}
+
+/// Failure of [`TelnetClient::store_scene_named`]
+#[derive(Debug)]
+pub enum StoreSceneError {
+    /// Storing the settings failed, nothing was changed
+    Store(TelnetError),
+    /// The name can't be written, nothing was sent
+    InvalidName(TelnetError),
+    /// The settings were stored, but naming the scene failed
+    Name(TelnetError),
+}
+
+impl StoreSceneError {
+    /// Get the error of the failed step
+    pub fn error(&self) -> &TelnetError {
+        match self {
+            StoreSceneError::Store(e)
+            | StoreSceneError::InvalidName(e)
+            | StoreSceneError::Name(e) => e,
+        }
+    }
+}
+
+impl fmt::Display for StoreSceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreSceneError::Store(e) => write!(f, "Storing the scene failed: {}", e),
+            StoreSceneError::InvalidName(e) => write!(f, "Invalid scene name: {}", e),
+            StoreSceneError::Name(e) => write!(f, "Naming the scene failed: {}", e),
+        }
+    }
+}
+
+impl Error for StoreSceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error())
+    }
+}
+
+/// Convert a scene memory number to its parameter value
+fn scene_value(n: u8) -> Result<u8, TelnetError> {
+    if (1..=scene::COUNT).contains(&n) {
+        Ok(n - 1)
+    } else {
+        Err(TelnetError::Protocol(RolandError::OutOfRange))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::Command;
+
+    #[test]
+    fn test_scene_range() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        for n in [0, 31, 255] {
+            assert!(matches!(
+                client.recall_scene(n),
+                Err(TelnetError::Protocol(RolandError::OutOfRange))
+            ));
+            assert!(matches!(
+                client.store_scene(n),
+                Err(TelnetError::Protocol(RolandError::OutOfRange))
+            ));
+        }
+        assert!(mock.received().is_empty());
+
+        client.recall_scene(1).unwrap();
+        client.store_scene(30).unwrap();
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::WriteParameter {
+                    address: scene::RECALL,
+                    value: 0,
+                },
+                Command::WriteParameter {
+                    address: scene::STORE,
+                    value: 29,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recall_scene_blocking() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(scene::BUSY, 1);
+        let mut client = connect(addr);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(150));
+                mock.set_parameter(scene::BUSY, 0);
+            });
+            client
+                .recall_scene_blocking(5, Duration::from_secs(2))
+                .unwrap();
+        });
+
+        let polls = mock
+            .received()
+            .iter()
+            .filter(|command| matches!(command, Command::ReadParameter { .. }))
+            .count();
+        assert!(polls > 1);
+    }
+
+    #[test]
+    fn test_recall_scene_blocking_timeout() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(scene::BUSY, 1);
+        let mut client = connect(addr);
+
+        let start = Instant::now();
+        assert!(matches!(
+            client.recall_scene_blocking(5, Duration::from_millis(200)),
+            Err(TelnetError::Timeout)
+        ));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_scene_names() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        client.set_scene_name(3, "Sermon Wide Shot").unwrap();
+        assert_eq!(client.scene_name(3).unwrap(), "Sermon Wide Shot");
+        assert_eq!(mock.parameter(scene::name(2)), Some(b'S'));
+
+        // An empty name clears to spaces
+        client.set_scene_name(3, "").unwrap();
+        assert_eq!(
+            mock.received().last(),
+            Some(&Command::WriteBlock {
+                address: scene::name(2),
+                data: vec![b' '; 16],
+            })
+        );
+        assert_eq!(client.scene_name(3).unwrap(), "");
+
+        mock.clear_received();
+        assert!(matches!(
+            client.set_scene_name(3, "Sermon Wide Shot 2"),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+        for n in [0, 31] {
+            assert!(matches!(
+                client.scene_name(n),
+                Err(TelnetError::Protocol(RolandError::OutOfRange))
+            ));
+            assert!(matches!(
+                client.set_scene_name(n, "Intro"),
+                Err(TelnetError::Protocol(RolandError::OutOfRange))
+            ));
+        }
+        assert!(mock.received().is_empty());
+    }
+
+    #[test]
+    fn test_store_scene_named() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        client.store_scene_named(30, "Segment 4").unwrap();
+        assert_eq!(
+            mock.received()[0],
+            Command::write_parameter(scene::STORE, 29)
+        );
+        assert_eq!(client.scene_name(30).unwrap(), "Segment 4");
+
+        // Invalid arguments fail before storing
+        mock.clear_received();
+        assert!(matches!(
+            client.store_scene_named(31, "Segment 5"),
+            Err(StoreSceneError::Store(TelnetError::Protocol(
+                RolandError::OutOfRange
+            )))
+        ));
+        assert!(matches!(
+            client.store_scene_named(1, "Café"),
+            Err(StoreSceneError::InvalidName(TelnetError::Protocol(
+                RolandError::InvalidValue
+            )))
+        ));
+        assert!(client
+            .store_scene_named(1, "Café")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid scene name: "));
+        assert!(mock.received().is_empty());
+
+        // Naming fails after the store went through
+        mock.set_address_error(scene::name(0), RolandError::Invalid);
+        let err = client.store_scene_named(1, "Segment 1").unwrap_err();
+        assert!(matches!(err, StoreSceneError::Name(_)));
+        assert_eq!(
+            err.to_string(),
+            "Naming the scene failed: Device rejected DTH:071000,53,65,67,6D,... (16 bytes): \
+             Invalid command due to other settings"
+        );
+        assert_eq!(
+            mock.received()[0],
+            Command::write_parameter(scene::STORE, 0)
+        );
+    }
+}
@@ -0,0 +1,633 @@
+//! Actions run at a wall-clock time or after a delay
+//!
+//! A [`Scheduler`], got with [`SharedClient::scheduler`], holds entries
+//! that the worker of the shared client runs when they are due, e.g.
+//! "recall scene 3 at 19:30:00". Entries fire in the order of their
+//! times, ties in the order they were scheduled. What happened to them is
+//! reported by [`Scheduler::drain_events`].
+//!
+//! # Missed entries
+//!
+//! An entry more than [`Scheduler::grace`] past its time when the worker
+//! gets to it, e.g. because the process was down, is reported as
+//! [`ScheduleEvent::Missed`] instead of being run late, unless
+//! [`Scheduler::set_run_late`] says otherwise.
+//!
+//! # Persistence
+//!
+//! With [`Scheduler::persist_to`], pending entries are saved as JSON after
+//! every change, with times in milliseconds since the Unix epoch, and
+//! loaded again on the next start:
+//!
+//! ```text
+//! {"entries":[{"id":1,"at_ms":1760470200000,"action":"recall_scene","scene":3}]}
+//! ```
+//!
+//! [`ScheduledAction::Callback`] entries can't be saved and are left out
+//! of the file.
+
+use crate::backup::{parse_json, write_json_string, DumpFormatError, JsonValue};
+use crate::shared::SharedClient;
+use crate::trigger::send;
+use crate::video::{VideoInput, VideoSwitcher};
+use crate::{TelnetClient, TelnetError};
+use roland_core::Command;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Default for [`Scheduler::grace`]
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(1);
+
+/// Closure run by [`ScheduledAction::Callback`]
+pub type ScheduledCallback = Box<dyn FnOnce(&mut TelnetClient) -> Result<(), TelnetError> + Send>;
+
+/// When an entry is due
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum When {
+    /// At a wall-clock time
+    At(SystemTime),
+    /// After a delay from the time it is scheduled
+    After(Duration),
+}
+
+impl From<SystemTime> for When {
+    fn from(time: SystemTime) -> Self {
+        When::At(time)
+    }
+}
+
+impl From<Duration> for When {
+    fn from(delay: Duration) -> Self {
+        When::After(delay)
+    }
+}
+
+/// What an entry does when it is due
+pub enum ScheduledAction {
+    /// Send commands in order, stopping at the first that fails
+    Commands(Vec<Command>),
+    /// Recall a scene memory, see [`TelnetClient::recall_scene`]
+    RecallScene(u8),
+    /// Select the program input, see [`VideoSwitcher::select_program`]
+    SelectProgram(VideoInput),
+    /// Switch the preset to program immediately
+    Cut,
+    /// Switch the preset to program with the transition effect
+    AutoTake,
+    /// Call a closure with the client
+    ///
+    /// The closure runs on the worker: use the client it is given, not a
+    /// `SharedClient` handle, which would wait for the worker forever.
+    Callback(ScheduledCallback),
+}
+
+impl fmt::Debug for ScheduledAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduledAction::Commands(commands) => {
+                f.debug_tuple("Commands").field(commands).finish()
+            }
+            ScheduledAction::RecallScene(n) => f.debug_tuple("RecallScene").field(n).finish(),
+            ScheduledAction::SelectProgram(input) => {
+                f.debug_tuple("SelectProgram").field(input).finish()
+            }
+            ScheduledAction::Cut => f.write_str("Cut"),
+            ScheduledAction::AutoTake => f.write_str("AutoTake"),
+            ScheduledAction::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+impl ScheduledAction {
+    fn run(self, client: &mut TelnetClient) -> Result<(), TelnetError> {
+        match self {
+            ScheduledAction::Commands(commands) => commands
+                .iter()
+                .try_for_each(|command| send(client, command)),
+            ScheduledAction::RecallScene(n) => client.recall_scene(n),
+            ScheduledAction::SelectProgram(input) => {
+                VideoSwitcher::new(client).select_program(input)
+            }
+            ScheduledAction::Cut => VideoSwitcher::new(client).cut(),
+            ScheduledAction::AutoTake => VideoSwitcher::new(client).auto_take(),
+            ScheduledAction::Callback(callback) => callback(client),
+        }
+    }
+
+    /// Write the JSON fields of the action, `None` for a callback
+    fn to_json(&self) -> Option<String> {
+        let json = match self {
+            ScheduledAction::Commands(commands) => {
+                let mut json = String::from("\"action\":\"commands\",\"commands\":[");
+                for (i, command) in commands.iter().enumerate() {
+                    if i > 0 {
+                        json.push(',');
+                    }
+                    write_json_string(&mut json, &command.encode());
+                }
+                json.push(']');
+                json
+            }
+            ScheduledAction::RecallScene(n) => {
+                format!("\"action\":\"recall_scene\",\"scene\":{}", n)
+            }
+            ScheduledAction::SelectProgram(input) => {
+                format!("\"action\":\"select_program\",\"input\":{}", input.index())
+            }
+            ScheduledAction::Cut => String::from("\"action\":\"cut\""),
+            ScheduledAction::AutoTake => String::from("\"action\":\"auto_take\""),
+            ScheduledAction::Callback(_) => return None,
+        };
+        Some(json)
+    }
+
+    fn from_json(entry: &JsonValue) -> Result<Self, DumpFormatError> {
+        let byte = |name| match entry.field(name) {
+            Some(JsonValue::Number(n)) if *n <= 255 => Ok(*n as u8),
+            _ => Err(DumpFormatError::MissingField(name)),
+        };
+        match entry.field("action") {
+            Some(JsonValue::String(action)) => match action.as_str() {
+                "commands" => {
+                    let Some(JsonValue::Array(commands)) = entry.field("commands") else {
+                        return Err(DumpFormatError::MissingField("commands"));
+                    };
+                    commands
+                        .iter()
+                        .map(|command| match command {
+                            JsonValue::String(command) => Command::parse(command)
+                                .map_err(|_| DumpFormatError::InvalidCommand(command.clone())),
+                            _ => Err(DumpFormatError::MissingField("commands")),
+                        })
+                        .collect::<Result<_, _>>()
+                        .map(ScheduledAction::Commands)
+                }
+                "recall_scene" => byte("scene").map(ScheduledAction::RecallScene),
+                "select_program" => VideoInput::from_index(byte("input")?)
+                    .map(ScheduledAction::SelectProgram)
+                    .ok_or(DumpFormatError::MissingField("input")),
+                "cut" => Ok(ScheduledAction::Cut),
+                "auto_take" => Ok(ScheduledAction::AutoTake),
+                _ => Err(DumpFormatError::MissingField("action")),
+            },
+            _ => Err(DumpFormatError::MissingField("action")),
+        }
+    }
+}
+
+/// Identifier of a scheduled entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntryId(pub u64);
+
+/// What happened to an entry
+#[derive(Debug)]
+pub enum ScheduleEvent {
+    /// The entry ran, with the result of its action
+    Fired {
+        /// Entry that ran
+        id: EntryId,
+        /// Result of the action
+        result: Result<(), TelnetError>,
+    },
+    /// The entry was past its time by more than the grace and didn't run
+    Missed {
+        /// Entry that was missed
+        id: EntryId,
+        /// When it was due
+        at: SystemTime,
+        /// Action that didn't run
+        action: ScheduledAction,
+    },
+    /// Saving the pending entries failed
+    SaveFailed(io::Error),
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: EntryId,
+    at: SystemTime,
+    action: ScheduledAction,
+}
+
+/// Entries of a scheduler, shared with the worker
+#[derive(Debug)]
+pub(crate) struct Schedule {
+    /// Pending entries, sorted by time then id
+    entries: Vec<Entry>,
+    next_id: u64,
+    grace: Duration,
+    run_late: bool,
+    path: Option<PathBuf>,
+    events: Vec<ScheduleEvent>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            next_id: 1,
+            grace: DEFAULT_GRACE,
+            run_late: false,
+            path: None,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl Schedule {
+    fn insert(&mut self, entry: Entry) {
+        let pos = self
+            .entries
+            .partition_point(|e| (e.at, e.id) <= (entry.at, entry.id));
+        self.entries.insert(pos, entry);
+    }
+
+    fn to_json(&self) -> String {
+        let mut json = String::from("{\"entries\":[");
+        let mut first = true;
+        for entry in &self.entries {
+            let Some(action) = entry.action.to_json() else {
+                continue;
+            };
+            if !first {
+                json.push(',');
+            }
+            first = false;
+            json.push_str(&format!(
+                "{{\"id\":{},\"at_ms\":{},{}}}",
+                entry.id.0,
+                unix_ms(entry.at),
+                action
+            ));
+        }
+        json.push_str("]}");
+        json
+    }
+
+    /// Save the pending entries, if persistence is on
+    fn save(&mut self) {
+        if let Some(path) = &self.path {
+            if let Err(e) = std::fs::write(path, self.to_json()) {
+                self.events.push(ScheduleEvent::SaveFailed(e));
+            }
+        }
+    }
+
+    /// Take the entries due at `now`, reporting the missed ones
+    fn take_due(&mut self, now: SystemTime) -> Vec<Entry> {
+        let due = self.entries.partition_point(|entry| entry.at <= now);
+        if due == 0 {
+            return Vec::new();
+        }
+        let mut run = Vec::new();
+        for entry in self.entries.drain(..due) {
+            let late = now.duration_since(entry.at).unwrap_or(Duration::ZERO);
+            if late > self.grace && !self.run_late {
+                self.events.push(ScheduleEvent::Missed {
+                    id: entry.id,
+                    at: entry.at,
+                    action: entry.action,
+                });
+            } else {
+                run.push(entry);
+            }
+        }
+        self.save();
+        run
+    }
+}
+
+/// Run the entries that are due, called by the worker
+///
+/// The lock isn't held while actions run, so callbacks can use the
+/// [`Scheduler`].
+pub(crate) fn run_due(schedule: &Mutex<Schedule>, client: &mut TelnetClient) {
+    let due = schedule.lock().unwrap().take_due(SystemTime::now());
+    for entry in due {
+        let result = entry.action.run(client);
+        schedule.lock().unwrap().events.push(ScheduleEvent::Fired {
+            id: entry.id,
+            result,
+        });
+    }
+}
+
+/// Handle to the entries run by a shared client's worker
+///
+/// Handles are cheap to clone and all refer to the same entries; see the
+/// [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    schedule: Arc<Mutex<Schedule>>,
+}
+
+impl SharedClient {
+    /// Get the scheduler whose entries this client's worker runs
+    pub fn scheduler(&self) -> Scheduler {
+        Scheduler {
+            schedule: self.schedule(),
+        }
+    }
+}
+
+impl Scheduler {
+    /// Schedule an action
+    ///
+    /// # Arguments
+    /// * `when` - A [`SystemTime`] or a [`Duration`] from now
+    /// * `action` - What to do then
+    ///
+    /// # Returns
+    /// * `EntryId` - Identifier for [`Scheduler::cancel`] and the events
+    pub fn schedule(&self, when: impl Into<When>, action: ScheduledAction) -> EntryId {
+        let at = match when.into() {
+            When::At(time) => time,
+            When::After(delay) => SystemTime::now() + delay,
+        };
+        let mut schedule = self.schedule.lock().unwrap();
+        let id = EntryId(schedule.next_id);
+        schedule.next_id += 1;
+        schedule.insert(Entry { id, at, action });
+        schedule.save();
+        id
+    }
+
+    /// Cancel a pending entry
+    ///
+    /// # Returns
+    /// * `Option<ScheduledAction>` - Action of the entry, or `None` if it
+    ///   already ran, was missed or never existed
+    pub fn cancel(&self, id: EntryId) -> Option<ScheduledAction> {
+        let mut schedule = self.schedule.lock().unwrap();
+        let pos = schedule.entries.iter().position(|entry| entry.id == id)?;
+        let entry = schedule.entries.remove(pos);
+        schedule.save();
+        Some(entry.action)
+    }
+
+    /// Get the pending entries with their times, in firing order
+    pub fn pending(&self) -> Vec<(EntryId, SystemTime)> {
+        let schedule = self.schedule.lock().unwrap();
+        schedule
+            .entries
+            .iter()
+            .map(|entry| (entry.id, entry.at))
+            .collect()
+    }
+
+    /// Take what happened to entries since the last call, oldest first
+    pub fn drain_events(&self) -> Vec<ScheduleEvent> {
+        std::mem::take(&mut self.schedule.lock().unwrap().events)
+    }
+
+    /// Set how late an entry may run before it counts as missed
+    pub fn set_grace(&self, grace: Duration) {
+        self.schedule.lock().unwrap().grace = grace;
+    }
+
+    /// Get how late an entry may run before it counts as missed
+    pub fn grace(&self) -> Duration {
+        self.schedule.lock().unwrap().grace
+    }
+
+    /// Run entries that are past their grace late instead of reporting
+    /// them as missed
+    pub fn set_run_late(&self, run_late: bool) {
+        self.schedule.lock().unwrap().run_late = run_late;
+    }
+
+    /// Save the pending entries to a JSON file from now on
+    ///
+    /// Entries saved in the file before, e.g. by the previous run of the
+    /// program, are loaded first, keeping their ids unless an entry
+    /// scheduled here already uses one. Entries whose time has passed are
+    /// then reported as missed when the worker gets to them, see the
+    /// [module documentation](self).
+    ///
+    /// # Returns
+    /// * `io::Result<usize>` - Number of entries loaded; a missing file
+    ///   loads none, a malformed one gives an `InvalidData` error wrapping
+    ///   the [`DumpFormatError`]
+    pub fn persist_to(&self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let path = path.as_ref();
+        let loaded = match std::fs::read_to_string(path) {
+            Ok(json) => load(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let count = loaded.len();
+        let mut schedule = self.schedule.lock().unwrap();
+        for mut entry in loaded {
+            if schedule.entries.iter().any(|e| e.id == entry.id) {
+                entry.id = EntryId(schedule.next_id);
+            }
+            schedule.next_id = schedule.next_id.max(entry.id.0 + 1);
+            schedule.insert(entry);
+        }
+        schedule.path = Some(path.to_path_buf());
+        schedule.save();
+        Ok(count)
+    }
+}
+
+/// Decode entries saved by [`Schedule::save`]
+fn load(json: &str) -> Result<Vec<Entry>, DumpFormatError> {
+    let value = parse_json(json)?;
+    let Some(JsonValue::Array(entries)) = value.field("entries") else {
+        return Err(DumpFormatError::MissingField("entries"));
+    };
+    entries
+        .iter()
+        .map(|entry| {
+            let id = match entry.field("id") {
+                Some(JsonValue::Number(id)) => EntryId(*id),
+                _ => return Err(DumpFormatError::MissingField("id")),
+            };
+            let at = match entry.field("at_ms") {
+                Some(JsonValue::Number(ms)) => UNIX_EPOCH + Duration::from_millis(*ms),
+                _ => return Err(DumpFormatError::MissingField("at_ms")),
+            };
+            let action = ScheduledAction::from_json(entry)?;
+            Ok(Entry { id, at, action })
+        })
+        .collect()
+}
+
+fn unix_ms(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDevice;
+    use roland_core::params::{audio, scene, video};
+    use std::thread;
+    use std::time::Instant;
+
+    fn connect(addr: std::net::SocketAddr) -> SharedClient {
+        SharedClient::new(crate::mock::connect(addr))
+    }
+
+    fn writes(commands: &[Command]) -> Vec<(roland_core::Address, u8)> {
+        commands
+            .iter()
+            .filter_map(|command| match command {
+                Command::WriteParameter { address, value } => Some((*address, *value)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Wait until the scheduler has no pending entries
+    fn wait_idle(scheduler: &Scheduler) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !scheduler.pending().is_empty() {
+            assert!(Instant::now() < deadline, "entries still pending");
+            thread::sleep(Duration::from_millis(10));
+        }
+        // The last entry may still be running
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_firing_order() {
+        let (addr, mock) = MockDevice::spawn();
+        let client = connect(addr);
+        let scheduler = client.scheduler();
+
+        let third = scheduler.schedule(
+            Duration::from_millis(150),
+            ScheduledAction::SelectProgram(VideoInput::Hdmi3),
+        );
+        let first = scheduler.schedule(
+            Duration::from_millis(50),
+            ScheduledAction::Commands(vec![Command::write_parameter(audio::CH5_MUTE, 1)]),
+        );
+        let second = scheduler.schedule(
+            SystemTime::now() + Duration::from_millis(100),
+            ScheduledAction::RecallScene(3),
+        );
+        assert_eq!(
+            scheduler
+                .pending()
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>(),
+            vec![first, second, third]
+        );
+        wait_idle(&scheduler);
+
+        assert_eq!(
+            writes(&mock.received()),
+            vec![
+                (audio::CH5_MUTE, 1),
+                (scene::RECALL, 2),
+                (video::PGM_SELECT, 2)
+            ]
+        );
+        let fired: Vec<EntryId> = scheduler
+            .drain_events()
+            .into_iter()
+            .map(|event| match event {
+                ScheduleEvent::Fired { id, result } => {
+                    result.unwrap();
+                    id
+                }
+                event => panic!("unexpected {:?}", event),
+            })
+            .collect();
+        assert_eq!(fired, vec![first, second, third]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (addr, mock) = MockDevice::spawn();
+        let client = connect(addr);
+        let scheduler = client.scheduler();
+
+        let cancelled = scheduler.schedule(Duration::from_millis(80), ScheduledAction::Cut);
+        let kept = scheduler.schedule(Duration::from_millis(100), ScheduledAction::AutoTake);
+        assert!(matches!(
+            scheduler.cancel(cancelled),
+            Some(ScheduledAction::Cut)
+        ));
+        assert!(scheduler.cancel(cancelled).is_none());
+        wait_idle(&scheduler);
+
+        assert_eq!(writes(&mock.received()), vec![(video::AUTO_TAKE, 1)]);
+        let events = scheduler.drain_events();
+        assert!(matches!(
+            events.as_slice(),
+            [ScheduleEvent::Fired { id, result: Ok(()) }] if *id == kept
+        ));
+        assert!(scheduler.cancel(kept).is_none());
+    }
+
+    #[test]
+    fn test_persistence_and_missed() {
+        let path =
+            std::env::temp_dir().join(format!("roland-schedule-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (addr, mock) = MockDevice::spawn();
+
+        // A first run saves its entries and goes down before they are due
+        {
+            let client = connect(addr);
+            let scheduler = client.scheduler();
+            assert_eq!(scheduler.persist_to(&path).unwrap(), 0);
+            scheduler.schedule(Duration::from_millis(50), ScheduledAction::RecallScene(5));
+            scheduler.schedule(
+                Duration::from_secs(60),
+                ScheduledAction::Commands(vec![Command::write_parameter(audio::CH5_MUTE, 0)]),
+            );
+            scheduler.schedule(
+                Duration::from_millis(50),
+                ScheduledAction::Callback(Box::new(|_| Ok(()))),
+            );
+            let json = std::fs::read_to_string(&path).unwrap();
+            assert!(json.contains("\"action\":\"recall_scene\",\"scene\":5"));
+            assert!(json.contains("\"DTH:"));
+            assert!(!json.contains("Callback"));
+            scheduler.cancel(EntryId(3));
+            // Stop the worker before the first entry is due
+        }
+        thread::sleep(Duration::from_millis(100));
+
+        let client = connect(addr);
+        let scheduler = client.scheduler();
+        assert_eq!(scheduler.grace(), DEFAULT_GRACE);
+        scheduler.set_grace(Duration::from_millis(20));
+        assert_eq!(scheduler.persist_to(&path).unwrap(), 2);
+        let later = scheduler.schedule(Duration::from_secs(60), ScheduledAction::Cut);
+        assert_eq!(later, EntryId(3));
+        thread::sleep(Duration::from_millis(100));
+
+        let events = scheduler.drain_events();
+        assert!(matches!(
+            events.as_slice(),
+            [ScheduleEvent::Missed {
+                id: EntryId(1),
+                action: ScheduledAction::RecallScene(5),
+                ..
+            }]
+        ));
+        assert!(writes(&mock.received()).is_empty());
+        assert_eq!(
+            scheduler
+                .pending()
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>(),
+            vec![EntryId(2), EntryId(3)]
+        );
+        let saved = load(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,434 @@
+//! Connection shared between threads
+//!
+//! [`SharedClient`] moves a [`TelnetClient`] onto a worker thread. Handles
+//! are cheap to clone and send every call to the worker, which runs them
+//! one at a time, so command/response pairs of different threads never
+//! interleave on the wire. While idle, the worker keeps draining the
+//! connection so unsolicited changes are queued as events, runs fader
+//! glides (see [`crate::glide`]) and scheduled entries (see
+//! [`crate::scheduler`]), and notices when the connection is lost.
+
+use crate::event::{EventQueue, EventStream};
+use crate::scheduler::{self, Schedule};
+use crate::trigger::Triggers;
+use crate::{DeviceEvent, TelnetClient, TelnetError};
+use roland_core::{Address, Command, Response};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often an idle worker reads what the device sent
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+type Job = Box<dyn FnOnce(&mut TelnetClient) + Send>;
+
+/// Cloneable handle to a connection owned by a worker thread
+///
+/// Dropping the last handle stops the worker and closes the connection.
+///
+/// # Example
+/// ```ignore
+/// use roland_rs::shared::SharedClient;
+///
+/// let client = SharedClient::connect("192.168.1.100", 23)?;
+/// let tally = client.clone();
+/// std::thread::spawn(move || tally.read_parameter("000000", 1));
+/// client.write_parameter("123456", 0x01)?;
+/// ```
+#[derive(Clone)]
+pub struct SharedClient {
+    worker: Arc<Worker>,
+}
+
+struct Worker {
+    jobs: Option<Sender<Job>>,
+    thread: Option<JoinHandle<()>>,
+    /// Queues of the open [`EventStream`]s
+    streams: Arc<Mutex<Vec<Arc<EventQueue>>>>,
+    /// Set once [`DeviceEvent::ConnectionLost`] was queued
+    lost: Arc<AtomicBool>,
+    triggers: Arc<Mutex<Option<Triggers>>>,
+    /// Entries of [`SharedClient::scheduler`]
+    schedule: Arc<Mutex<Schedule>>,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // Closing the channel stops the worker
+        self.jobs.take();
+        if let Some(thread) = self.thread.take() {
+            // A job may hold the last handle; the worker can't join itself
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl SharedClient {
+    /// Connect to a device and share the connection
+    ///
+    /// # Arguments
+    /// * `host` - IP address or hostname of the VR-6HD device
+    /// * `port` - Telnet port (default: 23)
+    ///
+    /// # Returns
+    /// * `Result<Self, TelnetError>` - Handle or error
+    pub fn connect(host: &str, port: u16) -> Result<Self, TelnetError> {
+        Ok(Self::new(TelnetClient::connect(host, port)?))
+    }
+
+    /// Share an existing connection
+    ///
+    /// # Arguments
+    /// * `client` - Connection to move onto the worker thread
+    pub fn new(mut client: TelnetClient) -> Self {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        let streams: Arc<Mutex<Vec<Arc<EventQueue>>>> = Arc::default();
+        let lost = Arc::new(AtomicBool::new(false));
+        let triggers: Arc<Mutex<Option<Triggers>>> = Arc::default();
+        let schedule: Arc<Mutex<Schedule>> = Arc::default();
+        let thread = {
+            let streams = streams.clone();
+            let lost = lost.clone();
+            let triggers = triggers.clone();
+            let schedule = schedule.clone();
+            thread::spawn(move || {
+                loop {
+                    match rx.recv_timeout(POLL_INTERVAL) {
+                        Ok(job) => job(&mut client),
+                        // Other errors surface on the next command
+                        Err(RecvTimeoutError::Timeout) => {
+                            if let Err(TelnetError::ConnectionClosed | TelnetError::Io(_)) =
+                                client.read_available()
+                            {
+                                if !lost.swap(true, Ordering::SeqCst) {
+                                    client.events.push_back(DeviceEvent::ConnectionLost);
+                                }
+                            }
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                    // Errors surface on the next command, like above
+                    let _ = client.flush_due_writes();
+                    let _ = client.run_glides();
+                    scheduler::run_due(&schedule, &mut client);
+                    match triggers.lock().unwrap().as_mut() {
+                        Some(triggers) => {
+                            triggers.run(&mut client);
+                        }
+                        None => client.changes.disable(),
+                    }
+                    feed(&streams, &mut client);
+                }
+                for queue in streams.lock().unwrap().drain(..) {
+                    queue.close();
+                }
+            })
+        };
+
+        Self {
+            worker: Arc::new(Worker {
+                jobs: Some(jobs),
+                thread: Some(thread),
+                streams,
+                lost,
+                triggers,
+                schedule,
+            }),
+        }
+    }
+
+    /// Open a stream of the events the worker receives
+    ///
+    /// While any stream is open, the worker hands every event to all
+    /// streams as soon as it is queued, and [`SharedClient::poll_event`]
+    /// and [`SharedClient::events`] get none. A stream holds at most
+    /// `capacity` events and drops the oldest when full; see
+    /// [`crate::event`].
+    pub fn event_stream(&self, capacity: usize) -> EventStream {
+        let (stream, queue) = EventStream::new(capacity);
+        self.worker.streams.lock().unwrap().push(queue);
+        stream
+    }
+
+    /// Have the worker run triggers, replacing any set before
+    ///
+    /// The worker runs them after every call and every read of the
+    /// connection, see [`Triggers::run`]. Actions run on the worker, so a
+    /// slow action delays the calls of every handle.
+    pub fn set_triggers(&self, triggers: Triggers) {
+        *self.worker.triggers.lock().unwrap() = Some(triggers);
+    }
+
+    /// Stop running triggers and get them back, e.g. for their errors
+    pub fn take_triggers(&self) -> Option<Triggers> {
+        self.worker.triggers.lock().unwrap().take()
+    }
+
+    pub(crate) fn schedule(&self) -> Arc<Mutex<Schedule>> {
+        self.worker.schedule.clone()
+    }
+
+    /// Replace the connection, e.g. after [`DeviceEvent::ConnectionLost`]
+    ///
+    /// `client` is used as it is, so set it up like the old one first.
+    /// Events the old connection queued are kept, followed by
+    /// [`DeviceEvent::Reconnected`].
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `ConnectionClosed` if the
+    ///   worker is gone
+    pub fn reconnect(&self, client: TelnetClient) -> Result<(), TelnetError> {
+        let lost = self.worker.lost.clone();
+        self.with(move |current| {
+            let mut old = std::mem::replace(current, client);
+            current.events = std::mem::take(&mut old.events);
+            current.events.push_back(DeviceEvent::Reconnected);
+            lost.store(false, Ordering::SeqCst);
+        })
+    }
+
+    /// Run a closure on the worker with exclusive access to the client
+    ///
+    /// Use this for anything without a method on the handle, or to run
+    /// several commands without other threads' commands in between.
+    ///
+    /// # Returns
+    /// * `Result<R, TelnetError>` - Result of the closure, or
+    ///   `ConnectionClosed` if the worker is gone
+    pub fn with<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut TelnetClient) -> R + Send + 'static,
+    ) -> Result<R, TelnetError> {
+        let (tx, rx) = mpsc::channel();
+        let job: Job = Box::new(move |client| {
+            let _ = tx.send(f(client));
+        });
+        self.worker
+            .jobs
+            .as_ref()
+            .ok_or(TelnetError::ConnectionClosed)?
+            .send(job)
+            .map_err(|_| TelnetError::ConnectionClosed)?;
+        rx.recv().map_err(|_| TelnetError::ConnectionClosed)
+    }
+
+    /// Send a command and wait for response
+    ///
+    /// See [`TelnetClient::send_command`].
+    pub fn send_command(&self, command: &Command) -> Result<Response, TelnetError> {
+        let command = command.clone();
+        self.with(move |client| client.send_command(&command))?
+    }
+
+    /// Write parameter value
+    ///
+    /// See [`TelnetClient::write_parameter`].
+    pub fn write_parameter(&self, address: &str, value: u8) -> Result<(), TelnetError> {
+        let address = address.to_string();
+        self.with(move |client| client.write_parameter(&address, value))?
+    }
+
+    /// Write parameter value to a parsed address
+    ///
+    /// See [`TelnetClient::write_parameter_addr`].
+    pub fn write_parameter_addr(&self, address: Address, value: u8) -> Result<(), TelnetError> {
+        self.with(move |client| client.write_parameter_addr(address, value))?
+    }
+
+    /// Read parameter value
+    ///
+    /// See [`TelnetClient::read_parameter`].
+    pub fn read_parameter(&self, address: &str, size: u32) -> Result<u8, TelnetError> {
+        let address = address.to_string();
+        self.with(move |client| client.read_parameter(&address, size))?
+    }
+
+    /// Read parameter value from a parsed address
+    ///
+    /// See [`TelnetClient::read_parameter_addr`].
+    pub fn read_parameter_addr(&self, address: Address, size: u32) -> Result<u8, TelnetError> {
+        self.with(move |client| client.read_parameter_addr(address, size))?
+    }
+
+    /// Get version information
+    ///
+    /// See [`TelnetClient::get_version`].
+    pub fn get_version(&self) -> Result<(String, String), TelnetError> {
+        self.with(|client| client.get_version())?
+    }
+
+    /// Get the next unsolicited event without blocking
+    ///
+    /// See [`TelnetClient::poll_event`].
+    pub fn poll_event(&self) -> Result<Option<DeviceEvent>, TelnetError> {
+        self.with(|client| client.poll_event())?
+    }
+
+    /// Take the events queued so far
+    ///
+    /// Each event is handed to exactly one handle.
+    pub fn events(&self) -> Result<Vec<DeviceEvent>, TelnetError> {
+        self.with(|client| client.events().collect())
+    }
+}
+
+/// Hand the queued events to the open streams, if there are any
+fn feed(streams: &Mutex<Vec<Arc<EventQueue>>>, client: &mut TelnetClient) {
+    let mut streams = streams.lock().unwrap();
+    // Streams that were dropped
+    streams.retain(|queue| Arc::strong_count(queue) > 1);
+    if streams.is_empty() {
+        return;
+    }
+    for event in client.events.drain(..) {
+        for queue in streams.iter() {
+            queue.push(event.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDevice;
+    use crate::tally::TallyState;
+    use crate::transport::TransportStatus;
+    use roland_core::params::{tally, transport};
+    use std::time::Instant;
+
+    fn connect(addr: std::net::SocketAddr) -> SharedClient {
+        SharedClient::new(crate::mock::connect(addr))
+    }
+
+    #[test]
+    fn test_interleaved_threads() {
+        let (addr, mock) = MockDevice::spawn();
+        let client = connect(addr);
+
+        let threads: Vec<_> = [0x10u8, 0x20]
+            .into_iter()
+            .map(|high| {
+                let client = client.clone();
+                thread::spawn(move || {
+                    let address = Address::new(high, 0x00, 0x00);
+                    for value in 0..100 {
+                        client.write_parameter_addr(address, value).unwrap();
+                        // Reads answer this thread's address, never the other's
+                        assert_eq!(client.read_parameter_addr(address, 1).unwrap(), value);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let writes = mock
+            .received()
+            .into_iter()
+            .filter(|command| matches!(command, Command::WriteParameter { .. }))
+            .count();
+        assert_eq!(writes, 200);
+        assert_eq!(mock.parameter(Address::new(0x10, 0x00, 0x00)), Some(99));
+        assert_eq!(mock.parameter(Address::new(0x20, 0x00, 0x00)), Some(99));
+    }
+
+    #[test]
+    fn test_idle_worker_queues_events() {
+        let (addr, mock) = MockDevice::spawn();
+        let client = connect(addr);
+        client.get_version().unwrap();
+
+        let fader = Address::new(0x05, 0x00, 0x00);
+        mock.send_unsolicited(fader, 0x40);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut events = Vec::new();
+        while events.is_empty() && Instant::now() < deadline {
+            events = client.events().unwrap();
+            thread::sleep(POLL_INTERVAL);
+        }
+        assert_eq!(
+            events,
+            vec![DeviceEvent::ParameterChanged {
+                address: fader,
+                value: 0x40,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let (addr, mock) = MockDevice::spawn();
+        let client = connect(addr);
+        client.get_version().unwrap();
+        let stream = client.event_stream(16);
+        let next = || stream.recv_timeout(Duration::from_secs(2)).unwrap();
+
+        let fader = Address::new(0x05, 0x00, 0x00);
+        mock.send_unsolicited(fader, 0x40);
+        assert_eq!(
+            next(),
+            DeviceEvent::ParameterChanged {
+                address: fader,
+                value: 0x40,
+            }
+        );
+
+        mock.send_unsolicited(tally::PROGRAM, 0x01);
+        assert!(matches!(next(), DeviceEvent::ParameterChanged { .. }));
+        assert_eq!(
+            next(),
+            DeviceEvent::TallyChanged(TallyState::from_bits(0x01, 0x00))
+        );
+
+        mock.send_unsolicited(transport::STATUS, 0x06);
+        assert!(matches!(next(), DeviceEvent::ParameterChanged { .. }));
+        assert_eq!(
+            next(),
+            DeviceEvent::TransportChanged(TransportStatus {
+                streaming: false,
+                recording: true,
+                sd_card_present: true,
+                elapsed: None,
+            })
+        );
+        // Handed to the stream, not to the handle
+        assert!(client.events().unwrap().is_empty());
+
+        // The device goes away
+        drop(mock);
+        assert_eq!(next(), DeviceEvent::ConnectionLost);
+        assert_eq!(stream.recv_timeout(Duration::from_millis(100)), None);
+
+        let (addr, mock) = MockDevice::spawn();
+        client.reconnect(crate::mock::connect(addr)).unwrap();
+        assert_eq!(next(), DeviceEvent::Reconnected);
+        client.get_version().unwrap();
+        mock.send_unsolicited(fader, 0x41);
+        assert!(matches!(
+            next(),
+            DeviceEvent::ParameterChanged { value: 0x41, .. }
+        ));
+
+        // Dropping the last handle ends the stream
+        drop(client);
+        assert_eq!(stream.recv(), None);
+    }
+
+    #[test]
+    fn test_drop_last_handle_stops_worker() {
+        let (addr, _mock) = MockDevice::spawn();
+        let client = connect(addr);
+        let other = client.clone();
+        drop(client);
+        assert!(other.get_version().is_ok());
+
+        let start = Instant::now();
+        drop(other);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,228 @@
+//! Split-screen composition of two inputs
+//!
+//! The device shows two inputs side by side, divided at a position that
+//! can be moved. It refuses a split with the same input on both sides,
+//! so [`TelnetClient::set_split_sources`] and
+//! [`TelnetClient::set_split_config`] check that first and fail with
+//! `Invalid` without sending anything.
+
+use crate::units::Percent;
+use crate::video::VideoInput;
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::split;
+use roland_core::{Address, RolandError};
+
+/// How the two inputs fill their side of the screen
+///
+/// Values this crate doesn't know decode to [`SplitMode::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SplitMode {
+    /// No split, normal output
+    Off,
+    /// Both inputs cropped to their side
+    Split,
+    /// Both inputs scaled to fit their side
+    Squeeze,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl SplitMode {
+    /// All documented modes, including [`SplitMode::Off`]
+    pub const ALL: [SplitMode; 3] = [SplitMode::Off, SplitMode::Split, SplitMode::Squeeze];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => SplitMode::Off,
+            1 => SplitMode::Split,
+            2 => SplitMode::Squeeze,
+            value => SplitMode::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            SplitMode::Off => 0,
+            SplitMode::Split => 1,
+            SplitMode::Squeeze => 2,
+            SplitMode::Other(value) => value,
+        }
+    }
+}
+
+/// Complete split configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitConfig {
+    /// Split mode
+    pub mode: SplitMode,
+    /// Input on the left
+    pub left: VideoInput,
+    /// Input on the right
+    pub right: VideoInput,
+    /// Divider position from the left edge
+    pub position: Percent,
+}
+
+impl TelnetClient {
+    /// Turn the split on or off, or change how the inputs are fitted
+    pub fn set_split_mode(&mut self, mode: SplitMode) -> Result<(), TelnetError> {
+        self.write_parameter_addr(split::MODE, mode.value())
+    }
+
+    /// Choose the inputs on both sides
+    ///
+    /// Both are written in a single command.
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `Invalid` if `left` and
+    ///   `right` are the same input (nothing is written then)
+    pub fn set_split_sources(
+        &mut self,
+        left: VideoInput,
+        right: VideoInput,
+    ) -> Result<(), TelnetError> {
+        check_sources(left, right)?;
+        self.write_parameter_block(split::LEFT_SOURCE, &[left.index(), right.index()])
+    }
+
+    /// Move the divider, 50% is the center of the screen
+    pub fn set_split_position(&mut self, position: Percent) -> Result<(), TelnetError> {
+        self.write_parameter_addr(split::POSITION, position.value())
+    }
+
+    /// Set the whole split configuration in a single command
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `Invalid` if both sides
+    ///   show the same input (nothing is written then)
+    pub fn set_split_config(&mut self, config: &SplitConfig) -> Result<(), TelnetError> {
+        check_sources(config.left, config.right)?;
+        self.write_parameter_block(
+            split::MODE,
+            &[
+                config.mode.value(),
+                config.left.index(),
+                config.right.index(),
+                config.position.value(),
+            ],
+        )
+    }
+
+    /// Read the split configuration
+    ///
+    /// # Returns
+    /// * `Result<SplitConfig, TelnetError>` - Configuration, or
+    ///   `InvalidValue` if a source or the position is out of range
+    pub fn split_config(&mut self) -> Result<SplitConfig, TelnetError> {
+        let mode = SplitMode::from_value(self.read_parameter_addr(split::MODE, 1)?);
+        let left = self.read_split_source(split::LEFT_SOURCE)?;
+        let right = self.read_split_source(split::RIGHT_SOURCE)?;
+        let position = self.read_parameter_addr(split::POSITION, 1)?;
+        let position =
+            Percent::new(position).map_err(|_| TelnetError::Protocol(RolandError::InvalidValue))?;
+        Ok(SplitConfig {
+            mode,
+            left,
+            right,
+            position,
+        })
+    }
+
+    fn read_split_source(&mut self, address: Address) -> Result<VideoInput, TelnetError> {
+        let value = self.read_parameter_addr(address, 1)?;
+        VideoInput::from_index(value).ok_or(TelnetError::Protocol(RolandError::InvalidValue))
+    }
+}
+
+/// Check that both sides show different inputs
+fn check_sources(left: VideoInput, right: VideoInput) -> Result<(), TelnetError> {
+    if left == right {
+        return Err(TelnetError::Protocol(RolandError::Invalid));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::Command;
+
+    #[test]
+    fn test_split_mode_values() {
+        assert_eq!(SplitMode::ALL.map(SplitMode::value), [0, 1, 2]);
+        for value in 0..=255 {
+            assert_eq!(SplitMode::from_value(value).value(), value);
+        }
+        assert_eq!(SplitMode::from_value(3), SplitMode::Other(3));
+    }
+
+    #[test]
+    fn test_split_round_trip() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        client.set_split_mode(SplitMode::Squeeze).unwrap();
+        client
+            .set_split_sources(VideoInput::Hdmi2, VideoInput::Still1)
+            .unwrap();
+        client
+            .set_split_position(Percent::new(30).unwrap())
+            .unwrap();
+        let encoded: Vec<String> = mock.received().iter().map(Command::encode).collect();
+        assert_eq!(
+            encoded,
+            vec!["DTH:020100,02;", "DTH:020101,01,04;", "DTH:020103,1E;"]
+        );
+        let config = client.split_config().unwrap();
+        assert_eq!(
+            config,
+            SplitConfig {
+                mode: SplitMode::Squeeze,
+                left: VideoInput::Hdmi2,
+                right: VideoInput::Still1,
+                position: Percent::new(30).unwrap(),
+            }
+        );
+
+        let config = SplitConfig {
+            mode: SplitMode::Split,
+            left: VideoInput::Hdmi4,
+            right: VideoInput::Hdmi1,
+            position: Percent::new(50).unwrap(),
+        };
+        client.set_split_config(&config).unwrap();
+        assert_eq!(client.split_config().unwrap(), config);
+    }
+
+    #[test]
+    fn test_same_source_rejected() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        assert!(matches!(
+            client.set_split_sources(VideoInput::Hdmi3, VideoInput::Hdmi3),
+            Err(TelnetError::Protocol(RolandError::Invalid))
+        ));
+        let config = SplitConfig {
+            mode: SplitMode::Split,
+            left: VideoInput::Hdmi1,
+            right: VideoInput::Hdmi1,
+            position: Percent::new(50).unwrap(),
+        };
+        assert!(matches!(
+            client.set_split_config(&config),
+            Err(TelnetError::Protocol(RolandError::Invalid))
+        ));
+        assert!(mock.received().is_empty());
+
+        mock.set_parameter(split::POSITION, 101);
+        assert!(matches!(
+            client.split_config(),
+            Err(TelnetError::Protocol(RolandError::InvalidValue))
+        ));
+    }
+}
@@ -0,0 +1,194 @@
+//! Device state snapshots and diffs
+//!
+//! A [`DeviceState`] holds parameter values by address. Diffing the
+//! current state against a target gives the writes that turn one into the
+//! other, so presets can be recalled without rewriting every parameter.
+
+use crate::backup::AddressRange;
+use crate::{TelnetClient, TelnetError};
+use roland_core::{Address, Command, RolandError};
+use std::collections::BTreeMap;
+
+/// Parameter values by address
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceState {
+    values: BTreeMap<Address, u8>,
+}
+
+impl DeviceState {
+    /// Create an empty state
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the value of a parameter
+    pub fn insert(&mut self, address: Address, value: u8) {
+        self.values.insert(address, value);
+    }
+
+    /// Set the values of consecutive parameters starting at `address`
+    ///
+    /// Values that would go past `FFFFFF` are ignored.
+    pub fn insert_block(&mut self, address: Address, data: &[u8]) {
+        let addresses = std::iter::successors(Some(address), |a| a.successor());
+        for (address, &value) in addresses.zip(data) {
+            self.insert(address, value);
+        }
+    }
+
+    /// Get the value of a parameter
+    pub fn get(&self, address: Address) -> Option<u8> {
+        self.values.get(&address).copied()
+    }
+
+    /// Number of parameters
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Check if the state holds no parameters
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Iterate over the parameters in address order
+    pub fn iter(&self) -> impl Iterator<Item = (Address, u8)> + '_ {
+        self.values
+            .iter()
+            .map(|(&address, &value)| (address, value))
+    }
+
+    /// Get the writes that turn this state into `target`
+    ///
+    /// Parameters of `target` that are missing here or have another value
+    /// are written; parameters missing from `target` are left alone.
+    /// Changes at consecutive addresses are coalesced into one
+    /// multi-byte write.
+    ///
+    /// # Returns
+    /// * `Vec<(Address, Vec<u8>)>` - Start address and values of each write,
+    ///   in address order
+    pub fn diff(&self, target: &DeviceState) -> Vec<(Address, Vec<u8>)> {
+        let mut writes: Vec<(Address, Vec<u8>)> = Vec::new();
+        let mut next = None;
+        for (address, value) in target.iter() {
+            if self.get(address) == Some(value) {
+                continue;
+            }
+            match writes.last_mut() {
+                Some((_, data)) if next == Some(address) => data.push(value),
+                _ => writes.push((address, vec![value])),
+            }
+            next = address.successor();
+        }
+        writes
+    }
+}
+
+impl FromIterator<(Address, u8)> for DeviceState {
+    fn from_iter<I: IntoIterator<Item = (Address, u8)>>(iter: I) -> Self {
+        Self {
+            values: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl TelnetClient {
+    /// Capture the current values of every address in `ranges`
+    ///
+    /// Each address is read with its own RQH.
+    ///
+    /// # Returns
+    /// * `Result<DeviceState, TelnetError>` - State, or the first error
+    pub fn snapshot(&mut self, ranges: &[AddressRange]) -> Result<DeviceState, TelnetError> {
+        let mut state = DeviceState::new();
+        for address in ranges.iter().flat_map(AddressRange::iter) {
+            state.insert(address, self.read_parameter_addr(address, 1)?);
+        }
+        Ok(state)
+    }
+
+    /// Apply writes from [`DeviceState::diff`]
+    ///
+    /// The writes are pipelined like [`TelnetClient::write_parameters`];
+    /// a device error for one write doesn't abort the others.
+    ///
+    /// # Returns
+    /// * `Result<Vec<Result<(), RolandError>>, TelnetError>` - Per-write
+    ///   results, or an error if the connection failed (`InvalidValue` if
+    ///   a write has no values)
+    pub fn apply_diff(
+        &mut self,
+        diff: &[(Address, Vec<u8>)],
+    ) -> Result<Vec<Result<(), RolandError>>, TelnetError> {
+        if diff.iter().any(|(_, data)| data.is_empty()) {
+            return Err(TelnetError::Protocol(RolandError::InvalidValue));
+        }
+        let commands: Vec<Command> = diff
+            .iter()
+            .map(|(address, data)| match data[..] {
+                [value] => Command::write_parameter(*address, value),
+                _ => Command::WriteBlock {
+                    address: *address,
+                    data: data.clone(),
+                },
+            })
+            .collect();
+        self.write_pipelined(&commands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+
+    #[test]
+    fn test_diff() {
+        let mut current = DeviceState::new();
+        current.insert_block(Address::new(0x05, 0x00, 0xFE), &[1, 2, 3, 4, 5]);
+        assert_eq!(current.len(), 5);
+        assert!(current.diff(&current.clone()).is_empty());
+
+        // Three changed bytes across a byte boundary, plus a lone one
+        let mut target = current.clone();
+        target.insert_block(Address::new(0x05, 0x00, 0xFF), &[0x20, 0x30, 0x40]);
+        target.insert(Address::new(0x06, 0x00, 0x00), 0x01);
+        assert_eq!(
+            current.diff(&target),
+            vec![
+                (Address::new(0x05, 0x00, 0xFF), vec![0x20, 0x30, 0x40]),
+                (Address::new(0x06, 0x00, 0x00), vec![0x01]),
+            ]
+        );
+
+        // Parameters only known on one side
+        assert!(current.diff(&DeviceState::new()).is_empty());
+        assert_eq!(DeviceState::new().diff(&current).len(), 1);
+    }
+
+    #[test]
+    fn test_snapshot_and_apply() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(Address::new(0x05, 0x00, 0x01), 0x10);
+        let mut client = connect(addr);
+
+        let range = AddressRange::with_len(Address::new(0x05, 0x00, 0x00), 4);
+        let current = client.snapshot(&[range]).unwrap();
+        assert_eq!(current.get(Address::new(0x05, 0x00, 0x01)), Some(0x10));
+
+        let mut target = current.clone();
+        target.insert_block(Address::new(0x05, 0x00, 0x01), &[0x11, 0x12, 0x13]);
+        mock.clear_received();
+        let diff = current.diff(&target);
+        assert_eq!(client.apply_diff(&diff).unwrap(), vec![Ok(())]);
+        assert_eq!(
+            mock.received(),
+            vec![Command::WriteBlock {
+                address: Address::new(0x05, 0x00, 0x01),
+                data: vec![0x11, 0x12, 0x13],
+            }]
+        );
+        assert_eq!(client.snapshot(&[range]).unwrap(), target);
+    }
+}
@@ -0,0 +1,233 @@
+//! Input signal and output format status
+
+use crate::video::VideoInput;
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::system;
+use roland_core::RolandError;
+
+/// Status bit: signal present
+const SIGNAL_BIT: u8 = 0x01;
+/// Status bit: HDCP-protected signal
+const HDCP_BIT: u8 = 0x02;
+
+/// Video format
+///
+/// Codes that this crate doesn't know yet, e.g. from newer firmware,
+/// decode to [`VideoFormat::Unknown`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VideoFormat {
+    /// 720/59.94p
+    Hd720p5994,
+    /// 720/50p
+    Hd720p50,
+    /// 1080/59.94i
+    Hd1080i5994,
+    /// 1080/50i
+    Hd1080i50,
+    /// 1080/59.94p
+    Hd1080p5994,
+    /// 1080/50p
+    Hd1080p50,
+    /// 1080/29.97p
+    Hd1080p2997,
+    /// 1080/25p
+    Hd1080p25,
+    /// 1080/23.98p
+    Hd1080p2398,
+    /// Format code not known to this crate
+    Unknown(u8),
+}
+
+/// Output video format, see [`VideoFormat`]
+pub type OutputFormat = VideoFormat;
+
+impl VideoFormat {
+    /// Decode a format code
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0 => VideoFormat::Hd720p5994,
+            1 => VideoFormat::Hd720p50,
+            2 => VideoFormat::Hd1080i5994,
+            3 => VideoFormat::Hd1080i50,
+            4 => VideoFormat::Hd1080p5994,
+            5 => VideoFormat::Hd1080p50,
+            6 => VideoFormat::Hd1080p2997,
+            7 => VideoFormat::Hd1080p25,
+            8 => VideoFormat::Hd1080p2398,
+            code => VideoFormat::Unknown(code),
+        }
+    }
+
+    /// Get the format code
+    pub fn code(self) -> u8 {
+        match self {
+            VideoFormat::Hd720p5994 => 0,
+            VideoFormat::Hd720p50 => 1,
+            VideoFormat::Hd1080i5994 => 2,
+            VideoFormat::Hd1080i50 => 3,
+            VideoFormat::Hd1080p5994 => 4,
+            VideoFormat::Hd1080p50 => 5,
+            VideoFormat::Hd1080p2997 => 6,
+            VideoFormat::Hd1080p25 => 7,
+            VideoFormat::Hd1080p2398 => 8,
+            VideoFormat::Unknown(code) => code,
+        }
+    }
+}
+
+/// Signal status of an HDMI input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputStatus {
+    /// A signal is present
+    pub signal: bool,
+    /// The signal is HDCP-protected
+    pub hdcp: bool,
+    /// Detected format, if a signal is present
+    pub format: Option<VideoFormat>,
+}
+
+impl InputStatus {
+    /// Decode the status byte and the format code
+    ///
+    /// Reserved status bits are ignored.
+    pub fn decode(status: u8, format: u8) -> Self {
+        let signal = status & SIGNAL_BIT != 0;
+        Self {
+            signal,
+            hdcp: status & HDCP_BIT != 0,
+            format: signal.then(|| VideoFormat::from_code(format)),
+        }
+    }
+}
+
+/// Summary of [`TelnetClient::health_check`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    /// Status of HDMI 1-4
+    pub inputs: Vec<(VideoInput, InputStatus)>,
+    /// Output format
+    pub output: OutputFormat,
+}
+
+impl HealthReport {
+    /// Get the HDMI inputs without a signal
+    pub fn missing_inputs(&self) -> impl Iterator<Item = VideoInput> + '_ {
+        self.inputs
+            .iter()
+            .filter(|(_, status)| !status.signal)
+            .map(|&(input, _)| input)
+    }
+
+    /// Check if every HDMI input has a signal
+    pub fn all_inputs_present(&self) -> bool {
+        self.missing_inputs().next().is_none()
+    }
+}
+
+impl TelnetClient {
+    /// Get the signal status of an HDMI input
+    ///
+    /// # Returns
+    /// * `Result<InputStatus, TelnetError>` - Status, or `Invalid` for the
+    ///   STILL channels, which have no physical input (nothing is sent then)
+    pub fn input_status(&mut self, input: VideoInput) -> Result<InputStatus, TelnetError> {
+        let index = input.index();
+        if index >= system::HDMI_INPUT_COUNT {
+            return Err(TelnetError::Protocol(RolandError::Invalid));
+        }
+        let status = self.read_parameter_addr(system::input_status(index), 1)?;
+        let format = if status & SIGNAL_BIT != 0 {
+            self.read_parameter_addr(system::input_format(index), 1)?
+        } else {
+            0
+        };
+        Ok(InputStatus::decode(status, format))
+    }
+
+    /// Get the output video format
+    pub fn output_format(&mut self) -> Result<OutputFormat, TelnetError> {
+        let code = self.read_parameter_addr(system::OUTPUT_FORMAT, 1)?;
+        Ok(VideoFormat::from_code(code))
+    }
+
+    /// Set the output video format
+    pub fn set_output_format(&mut self, format: OutputFormat) -> Result<(), TelnetError> {
+        self.write_parameter_addr(system::OUTPUT_FORMAT, format.code())
+    }
+
+    /// Check the status of all HDMI inputs and the output format
+    pub fn health_check(&mut self) -> Result<HealthReport, TelnetError> {
+        let mut inputs = Vec::with_capacity(system::HDMI_INPUT_COUNT as usize);
+        for input in &VideoInput::ALL[..system::HDMI_INPUT_COUNT as usize] {
+            inputs.push((*input, self.input_status(*input)?));
+        }
+        Ok(HealthReport {
+            inputs,
+            output: self.output_format()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+
+    #[test]
+    fn test_format_codes() {
+        for code in 0..=255 {
+            assert_eq!(VideoFormat::from_code(code).code(), code);
+        }
+        assert_eq!(VideoFormat::from_code(4), VideoFormat::Hd1080p5994);
+        assert_eq!(VideoFormat::from_code(42), OutputFormat::Unknown(42));
+    }
+
+    #[test]
+    fn test_decode_input_status() {
+        assert_eq!(
+            InputStatus::decode(0x03, 5),
+            InputStatus {
+                signal: true,
+                hdcp: true,
+                format: Some(VideoFormat::Hd1080p50),
+            }
+        );
+        assert_eq!(
+            InputStatus::decode(0xFC, 5),
+            InputStatus {
+                signal: false,
+                hdcp: false,
+                format: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_health_check() {
+        let (addr, mock) = MockDevice::spawn();
+        for input in 0..3 {
+            mock.set_parameter(system::input_status(input), 0x01);
+            mock.set_parameter(system::input_format(input), 4);
+        }
+        mock.set_parameter(system::OUTPUT_FORMAT, 99);
+        let mut client = connect(addr);
+
+        let report = client.health_check().unwrap();
+        assert_eq!(report.inputs.len(), 4);
+        assert_eq!(report.inputs[0].1.format, Some(VideoFormat::Hd1080p5994));
+        assert!(!report.all_inputs_present());
+        assert_eq!(
+            report.missing_inputs().collect::<Vec<_>>(),
+            vec![VideoInput::Hdmi4]
+        );
+        assert_eq!(report.output, OutputFormat::Unknown(99));
+
+        assert!(matches!(
+            client.input_status(VideoInput::Still1),
+            Err(TelnetError::Protocol(RolandError::Invalid))
+        ));
+
+        client.set_output_format(VideoFormat::Hd720p50).unwrap();
+        assert_eq!(client.output_format().unwrap(), VideoFormat::Hd720p50);
+    }
+}
@@ -0,0 +1,140 @@
+//! Still image selection and capture
+
+use crate::video::{VideoInput, VideoSwitcher};
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::still;
+use roland_core::RolandError;
+use std::time::Duration;
+
+impl TelnetClient {
+    /// Load a still image memory onto the STILL 1 video channel
+    ///
+    /// # Arguments
+    /// * `slot` - Still image memory number (1-16)
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `OutOfRange` for an invalid
+    ///   slot (nothing is sent then)
+    pub fn select_still(&mut self, slot: u8) -> Result<(), TelnetError> {
+        let value = still_value(slot)?;
+        self.write_parameter_addr(still::STILL1_IMAGE, value)
+    }
+
+    /// Load a still image memory and cut it to program
+    ///
+    /// The image is loaded onto STILL 1 before STILL 1 is switched to
+    /// program, so the previous image never shows.
+    ///
+    /// # Arguments
+    /// * `slot` - Still image memory number (1-16)
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `OutOfRange` for an invalid
+    ///   slot (nothing is sent then)
+    pub fn show_still_on_program(&mut self, slot: u8) -> Result<(), TelnetError> {
+        self.select_still(slot)?;
+        VideoSwitcher::new(self).select_program(VideoInput::Still1)
+    }
+
+    /// Capture the program output to a still image memory
+    ///
+    /// A capture takes a couple of seconds. After the ACK, the capture
+    /// status is polled until the device reports idle.
+    ///
+    /// # Arguments
+    /// * `slot` - Still image memory number (1-16)
+    /// * `timeout` - How long to wait for the capture to complete
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, `OutOfRange` for an invalid
+    ///   slot, or `Timeout` if the device is still busy
+    pub fn capture_program_to_still(
+        &mut self,
+        slot: u8,
+        timeout: Duration,
+    ) -> Result<(), TelnetError> {
+        let value = still_value(slot)?;
+        self.write_parameter_addr(still::CAPTURE, value)?;
+        self.wait_while_busy(still::CAPTURE_BUSY, timeout)
+    }
+}
+
+/// Convert a still image memory number to its parameter value
+fn still_value(slot: u8) -> Result<u8, TelnetError> {
+    if (1..=still::COUNT).contains(&slot) {
+        Ok(slot - 1)
+    } else {
+        Err(TelnetError::Protocol(RolandError::OutOfRange))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::params::video;
+    use roland_core::Command;
+    use std::thread;
+    use std::time::Instant;
+
+    #[test]
+    fn test_show_still_on_program() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        for slot in [0, 17] {
+            assert!(matches!(
+                client.show_still_on_program(slot),
+                Err(TelnetError::Protocol(RolandError::OutOfRange))
+            ));
+        }
+        assert!(mock.received().is_empty());
+
+        client.show_still_on_program(16).unwrap();
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::WriteParameter {
+                    address: still::STILL1_IMAGE,
+                    value: 15,
+                },
+                Command::WriteParameter {
+                    address: video::PGM_SELECT,
+                    value: VideoInput::Still1.index(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_capture() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(still::CAPTURE_BUSY, 1);
+        let mut client = connect(addr);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(150));
+                mock.set_parameter(still::CAPTURE_BUSY, 0);
+            });
+            client
+                .capture_program_to_still(3, Duration::from_secs(2))
+                .unwrap();
+        });
+        assert_eq!(mock.parameter(still::CAPTURE), Some(2));
+    }
+
+    #[test]
+    fn test_capture_timeout() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(still::CAPTURE_BUSY, 1);
+        let mut client = connect(addr);
+
+        let start = Instant::now();
+        assert!(matches!(
+            client.capture_program_to_still(1, Duration::from_millis(200)),
+            Err(TelnetError::Timeout)
+        ));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,302 @@
+//! Background subscriptions to unsolicited parameter changes
+//!
+//! [`TelnetClient::subscribe`] moves reading onto a background thread that
+//! keeps the socket drained. DTH frames for watched addresses are handed
+//! to a callback as soon as they arrive; every other frame is forwarded to
+//! the client over a channel, so commands keep working while subscribed.
+
+use crate::telnet::Iac;
+use crate::wire::{Direction, WireLog};
+use crate::{TelnetClient, TelnetError, TIMEOUT};
+use roland_core::{Address, Decoder, Response};
+use std::collections::HashSet;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often the reader thread checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Running background reader
+pub(crate) struct Subscription {
+    /// Frames that weren't handed to the callback
+    frames: Receiver<Result<String, TelnetError>>,
+    /// Address of the outstanding read, whose DTH must reach the client
+    awaiting: Arc<Mutex<Option<Address>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<(Decoder, Iac)>>,
+}
+
+impl Subscription {
+    /// Mark the address of an outstanding read, or clear it with `None`
+    pub(crate) fn set_awaiting(&self, address: Option<Address>) {
+        *self.awaiting.lock().unwrap() = address;
+    }
+
+    /// Wait for the next forwarded frame
+    pub(crate) fn recv_frame(&self, timeout: Duration) -> Result<String, TelnetError> {
+        match self.frames.recv_timeout(timeout) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => Err(TelnetError::Io(ErrorKind::TimedOut.into())),
+            Err(RecvTimeoutError::Disconnected) => Err(TelnetError::ConnectionClosed),
+        }
+    }
+
+    /// Get the next forwarded frame without blocking
+    pub(crate) fn try_recv_frame(&self) -> Option<Result<String, TelnetError>> {
+        self.frames.try_recv().ok()
+    }
+
+    /// Stop the reader thread and get back its decoder and IAC state
+    fn stop(mut self) -> (Decoder, Iac) {
+        self.shutdown().unwrap_or_default()
+    }
+
+    fn shutdown(&mut self) -> Option<(Decoder, Iac)> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.thread.take().and_then(|thread| thread.join().ok())
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl TelnetClient {
+    /// Watch addresses for unsolicited changes
+    ///
+    /// Spawns a reader thread that keeps the connection drained and calls
+    /// `callback` whenever the device pushes a DTH for one of `addresses`.
+    /// Commands can still be sent while subscribed; unsolicited frames for
+    /// other addresses are queued as events as usual. Subscribing again
+    /// replaces the previous subscription.
+    ///
+    /// The callback runs on the reader thread, so it must not block for
+    /// long. If a watched address is read with [`TelnetClient::read_parameter`],
+    /// the device's answer goes to the read rather than the callback.
+    ///
+    /// # Arguments
+    /// * `addresses` - Addresses to watch
+    /// * `callback` - Called with the address and new value of each change
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success or error
+    pub fn subscribe(
+        &mut self,
+        addresses: &[Address],
+        callback: impl FnMut(Address, u8) + Send + 'static,
+    ) -> Result<(), TelnetError> {
+        self.unsubscribe()?;
+
+        let stream = self.stream.try_clone()?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+
+        let (tx, frames) = mpsc::channel();
+        let awaiting = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader = Reader {
+            stream,
+            decoder: std::mem::take(&mut self.decoder),
+            iac: std::mem::take(&mut self.iac),
+            wire: self.wire.clone(),
+            watched: addresses.iter().copied().collect(),
+            awaiting: Arc::clone(&awaiting),
+            stop: Arc::clone(&stop),
+            frames: tx,
+        };
+        let thread = thread::spawn(move || reader.run(callback));
+
+        self.subscription = Some(Subscription {
+            frames,
+            awaiting,
+            stop,
+            thread: Some(thread),
+        });
+        Ok(())
+    }
+
+    /// Stop watching addresses
+    ///
+    /// Joins the reader thread and returns to reading on the calling
+    /// thread. Does nothing if there is no subscription.
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success or error
+    pub fn unsubscribe(&mut self) -> Result<(), TelnetError> {
+        let Some(subscription) = self.subscription.take() else {
+            return Ok(());
+        };
+
+        // Frames forwarded but not consumed yet can only be events
+        while let Some(Ok(frame)) = subscription.try_recv_frame() {
+            self.queue_event(&frame);
+        }
+        (self.decoder, self.iac) = subscription.stop();
+        self.stream.set_read_timeout(Some(TIMEOUT))?;
+        Ok(())
+    }
+
+    /// Check if a subscription is active
+    pub fn is_subscribed(&self) -> bool {
+        self.subscription.is_some()
+    }
+}
+
+/// State owned by the reader thread
+struct Reader {
+    stream: TcpStream,
+    decoder: Decoder,
+    iac: Iac,
+    wire: WireLog,
+    watched: HashSet<Address>,
+    awaiting: Arc<Mutex<Option<Address>>>,
+    stop: Arc<AtomicBool>,
+    frames: Sender<Result<String, TelnetError>>,
+}
+
+impl Reader {
+    fn run(mut self, mut callback: impl FnMut(Address, u8)) -> (Decoder, Iac) {
+        let mut buf = [0u8; 1024];
+        let mut data = Vec::new();
+
+        while !self.stop.load(Ordering::SeqCst) {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    let _ = self.frames.send(Err(TelnetError::ConnectionClosed));
+                    break;
+                }
+                Ok(n) => {
+                    self.wire.log(Direction::Received, &buf[..n]);
+                    data.clear();
+                    let negotiations = self.iac.filter(&buf[..n], &mut data);
+                    let refused = negotiations
+                        .into_iter()
+                        .filter_map(|n| n.refusal())
+                        .try_for_each(|r| {
+                            self.wire.log(Direction::Sent, &r.to_bytes());
+                            self.stream.write_all(&r.to_bytes())
+                        });
+                    if let Err(e) = refused {
+                        let _ = self.frames.send(Err(e.into()));
+                        break;
+                    }
+                    self.decoder.push(&data);
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue
+                }
+                Err(e) => {
+                    let _ = self.frames.send(Err(e.into()));
+                    break;
+                }
+            }
+
+            while let Some(frame) = self.decoder.next_frame() {
+                let parsed = Response::parse_with(&frame, self.decoder.options());
+                if let Ok(Response::Data { address, value }) = parsed {
+                    let awaited = *self.awaiting.lock().unwrap() == Some(address);
+                    if !awaited && self.watched.contains(&address) {
+                        callback(address, value);
+                        continue;
+                    }
+                }
+                // The client may be gone already; the frame is dropped then
+                let _ = self.frames.send(Ok(frame));
+            }
+        }
+        (self.decoder, self.iac)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::DeviceEvent;
+    use std::time::Instant;
+
+    #[test]
+    fn test_callback_for_watched_address() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        client.get_version().unwrap();
+
+        let fader = Address::new(0x05, 0x00, 0x00);
+        let (tx, rx) = mpsc::channel();
+        client
+            .subscribe(&[fader], move |address, value| {
+                tx.send((address, value)).unwrap();
+            })
+            .unwrap();
+        assert!(client.is_subscribed());
+
+        mock.send_unsolicited(fader, 0x40);
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(2)).unwrap(),
+            (fader, 0x40)
+        );
+        assert_eq!(client.poll_event().unwrap(), None);
+    }
+
+    #[test]
+    fn test_commands_while_subscribed() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let fader = Address::new(0x05, 0x00, 0x00);
+        let other = Address::new(0x05, 0x01, 0x00);
+        let (tx, rx) = mpsc::channel();
+        client
+            .subscribe(&[fader], move |address, value| {
+                tx.send((address, value)).unwrap();
+            })
+            .unwrap();
+
+        // Watched change interleaved with a write goes to the callback,
+        // an unwatched one is queued as an event
+        mock.inject_unsolicited(fader, 0x10);
+        mock.inject_unsolicited(other, 0x20);
+        client.write_parameter("123456", 0x01).unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(2)).unwrap(),
+            (fader, 0x10)
+        );
+        assert_eq!(
+            client.events().collect::<Vec<_>>(),
+            vec![DeviceEvent::ParameterChanged {
+                address: other,
+                value: 0x20,
+            }]
+        );
+
+        // Reading a watched address returns the value to the caller
+        assert_eq!(client.read_parameter("050000", 1).unwrap(), 0x10);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_unsubscribe_and_drop() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        let fader = Address::new(0x05, 0x00, 0x00);
+        client.subscribe(&[fader], |_, _| {}).unwrap();
+        client.unsubscribe().unwrap();
+        assert!(!client.is_subscribed());
+
+        // Reading on the calling thread works again
+        client.write_parameter("123456", 0x01).unwrap();
+        assert_eq!(mock.parameter(Address::new(0x12, 0x34, 0x56)), Some(0x01));
+
+        client.subscribe(&[fader], |_, _| {}).unwrap();
+        let start = Instant::now();
+        drop(client);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
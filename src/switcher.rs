@@ -0,0 +1,228 @@
+//! Vendor-neutral switcher control
+//!
+//! [`SwitcherControl`] covers the operations a show-control app needs
+//! from any video switcher, in terms of this crate's types rather than
+//! device bytes, so that the VR-6HD and other switchers can be driven
+//! through the same code. Errors are boxed, which keeps the trait object
+//! safe: a `Box<dyn SwitcherControl>` can hold any backend.
+//!
+//! Switchers differ in what they offer. [`SwitcherControl::capabilities`]
+//! reports the sizes that matter, and backends return [`Unsupported`] for
+//! operations they can't do at all.
+
+use crate::audio::{AudioChannel, AudioMixer, Db};
+use crate::video::{VideoInput, VideoSwitcher};
+use crate::TelnetClient;
+use roland_core::params::scene;
+use std::error::Error;
+use std::fmt;
+
+/// Error of a [`SwitcherControl`] operation
+///
+/// For the VR-6HD, this is a [`crate::TelnetError`]; use
+/// `downcast_ref` to get at it.
+pub type SwitcherError = Box<dyn Error + Send + Sync>;
+
+/// What a switcher supports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Number of selectable video inputs
+    ///
+    /// Inputs are used in [`VideoInput::ALL`] order, so a switcher with
+    /// four inputs accepts `Hdmi1` to `Hdmi4`.
+    pub inputs: usize,
+    /// Number of scene memories, numbered from 1
+    pub scenes: u8,
+}
+
+impl Capabilities {
+    /// Create capabilities for a backend
+    pub fn new(inputs: usize, scenes: u8) -> Self {
+        Self { inputs, scenes }
+    }
+
+    /// Check if an input is within the switcher's inputs
+    pub fn has_input(&self, input: VideoInput) -> bool {
+        (input.index() as usize) < self.inputs
+    }
+}
+
+/// An operation the switcher doesn't offer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unsupported {
+    /// Name of the operation, e.g. `"preview_select"`
+    pub operation: &'static str,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not supported by this switcher", self.operation)
+    }
+}
+
+impl Error for Unsupported {}
+
+/// High-level switcher operations
+///
+/// # Example
+/// ```no_run
+/// use roland_rs::audio::{AudioChannel, Db};
+/// use roland_rs::switcher::SwitcherControl;
+/// use roland_rs::video::VideoInput;
+/// use roland_rs::TelnetClient;
+///
+/// let mut switchers: Vec<Box<dyn SwitcherControl>> =
+///     vec![Box::new(TelnetClient::connect("192.168.1.100", 23)?)];
+/// for switcher in &mut switchers {
+///     switcher.preview_select(VideoInput::Hdmi2)?;
+///     switcher.take()?;
+///     switcher.set_fader(AudioChannel::Main, Db::ZERO)?;
+/// }
+/// # Ok::<(), roland_rs::switcher::SwitcherError>(())
+/// ```
+pub trait SwitcherControl {
+    /// Get what the switcher supports
+    fn capabilities(&self) -> Capabilities;
+
+    /// Put an input on program, switching immediately
+    fn program_select(&mut self, input: VideoInput) -> Result<(), SwitcherError>;
+
+    /// Put an input on preview (preset)
+    fn preview_select(&mut self, input: VideoInput) -> Result<(), SwitcherError>;
+
+    /// Transition preview to program using the selected transition
+    fn take(&mut self) -> Result<(), SwitcherError>;
+
+    /// Set the fader level of an audio channel
+    fn set_fader(&mut self, channel: AudioChannel, level: Db) -> Result<(), SwitcherError>;
+
+    /// Recall a scene memory (1 to [`Capabilities::scenes`])
+    fn recall_scene(&mut self, n: u8) -> Result<(), SwitcherError>;
+}
+
+impl SwitcherControl for TelnetClient {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new(VideoInput::ALL.len(), scene::COUNT)
+    }
+
+    fn program_select(&mut self, input: VideoInput) -> Result<(), SwitcherError> {
+        Ok(VideoSwitcher::new(self).select_program(input)?)
+    }
+
+    fn preview_select(&mut self, input: VideoInput) -> Result<(), SwitcherError> {
+        Ok(VideoSwitcher::new(self).select_preset(input)?)
+    }
+
+    fn take(&mut self) -> Result<(), SwitcherError> {
+        Ok(VideoSwitcher::new(self).auto_take()?)
+    }
+
+    fn set_fader(&mut self, channel: AudioChannel, level: Db) -> Result<(), SwitcherError> {
+        Ok(AudioMixer::new(self).set_fader(channel, level)?)
+    }
+
+    fn recall_scene(&mut self, n: u8) -> Result<(), SwitcherError> {
+        Ok(TelnetClient::recall_scene(self, n)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::TelnetError;
+    use roland_core::params::{audio, video};
+    use roland_core::{Command, RolandError};
+
+    /// Backend for a smaller switcher without preview
+    struct FourInputs {
+        program: Option<VideoInput>,
+    }
+
+    impl SwitcherControl for FourInputs {
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::new(4, 0)
+        }
+
+        fn program_select(&mut self, input: VideoInput) -> Result<(), SwitcherError> {
+            if !self.capabilities().has_input(input) {
+                return Err(format!("no input {:?}", input).into());
+            }
+            self.program = Some(input);
+            Ok(())
+        }
+
+        fn preview_select(&mut self, _: VideoInput) -> Result<(), SwitcherError> {
+            Err(Unsupported {
+                operation: "preview_select",
+            }
+            .into())
+        }
+
+        fn take(&mut self) -> Result<(), SwitcherError> {
+            Err(Unsupported { operation: "take" }.into())
+        }
+
+        fn set_fader(&mut self, _: AudioChannel, _: Db) -> Result<(), SwitcherError> {
+            Ok(())
+        }
+
+        fn recall_scene(&mut self, _: u8) -> Result<(), SwitcherError> {
+            Err(Unsupported {
+                operation: "recall_scene",
+            }
+            .into())
+        }
+    }
+
+    #[test]
+    fn test_vr6hd_backend() {
+        let (addr, mock) = MockDevice::spawn();
+        let client = connect(addr);
+        let mut switcher: Box<dyn SwitcherControl> = Box::new(client);
+        assert_eq!(switcher.capabilities(), Capabilities::new(6, 30));
+
+        switcher.program_select(VideoInput::Hdmi1).unwrap();
+        switcher.preview_select(VideoInput::Still1).unwrap();
+        switcher.take().unwrap();
+        switcher.set_fader(AudioChannel::Ch1, Db::ZERO).unwrap();
+        switcher.recall_scene(2).unwrap();
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::write_parameter(video::PGM_SELECT, 0),
+                Command::write_parameter(video::PST_SELECT, 4),
+                Command::write_parameter(video::AUTO_TAKE, 1),
+                Command::write_parameter(AudioChannel::Ch1.address(audio::LEVEL), 107),
+                Command::write_parameter(scene::RECALL, 1),
+            ]
+        );
+
+        // The client's error is still there behind the box
+        let error = switcher.recall_scene(31).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TelnetError>(),
+            Some(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+    }
+
+    #[test]
+    fn test_capability_differences() {
+        let mut switchers: Vec<Box<dyn SwitcherControl>> =
+            vec![Box::new(FourInputs { program: None })];
+        let switcher = &mut switchers[0];
+
+        assert!(switcher.capabilities().has_input(VideoInput::Hdmi4));
+        assert!(!switcher.capabilities().has_input(VideoInput::Still1));
+        switcher.program_select(VideoInput::Hdmi4).unwrap();
+        assert!(switcher.program_select(VideoInput::Still1).is_err());
+
+        let error = switcher.take().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<Unsupported>(),
+            Some(&Unsupported { operation: "take" })
+        );
+        assert_eq!(error.to_string(), "take is not supported by this switcher");
+    }
+}
@@ -0,0 +1,146 @@
+//! Program and preview tally
+
+use crate::video::VideoInput;
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::tally;
+use roland_core::Address;
+
+/// Mask of the bits that correspond to video inputs
+const INPUT_MASK: u8 = 0x3F;
+
+/// Which inputs are on program and preview
+///
+/// During a transition, the outgoing and incoming inputs are both on
+/// program, and an input can be on program and preview at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TallyState {
+    program: u8,
+    preview: u8,
+}
+
+impl TallyState {
+    /// Decode the program and preview tally bytes
+    ///
+    /// Bit n stands for [`VideoInput::from_index`]`(n)`; other bits are
+    /// ignored.
+    pub fn from_bits(program: u8, preview: u8) -> Self {
+        Self {
+            program: program & INPUT_MASK,
+            preview: preview & INPUT_MASK,
+        }
+    }
+
+    /// Check if an input is on program
+    pub fn is_program(&self, input: VideoInput) -> bool {
+        self.program & bit(input) != 0
+    }
+
+    /// Check if an input is on preview
+    pub fn is_preview(&self, input: VideoInput) -> bool {
+        self.preview & bit(input) != 0
+    }
+
+    /// Get the inputs on program
+    pub fn program(&self) -> impl Iterator<Item = VideoInput> + '_ {
+        VideoInput::ALL
+            .into_iter()
+            .filter(|&input| self.is_program(input))
+    }
+
+    /// Get the inputs on preview
+    pub fn preview(&self) -> impl Iterator<Item = VideoInput> + '_ {
+        VideoInput::ALL
+            .into_iter()
+            .filter(|&input| self.is_preview(input))
+    }
+}
+
+fn bit(input: VideoInput) -> u8 {
+    1 << input.index()
+}
+
+impl TelnetClient {
+    /// Get which inputs are on program and preview
+    pub fn tally(&mut self) -> Result<TallyState, TelnetError> {
+        let program = self.read_parameter_addr(tally::PROGRAM, 1)?;
+        let preview = self.read_parameter_addr(tally::PREVIEW, 1)?;
+        self.tally = TallyState::from_bits(program, preview);
+        Ok(self.tally)
+    }
+
+    /// Apply an unsolicited parameter change to the known tally
+    ///
+    /// Returns the new tally if `address` is a tally parameter.
+    pub(crate) fn update_tally(&mut self, address: Address, value: u8) -> Option<TallyState> {
+        let TallyState { program, preview } = self.tally;
+        self.tally = match address {
+            tally::PROGRAM => TallyState::from_bits(value, preview),
+            tally::PREVIEW => TallyState::from_bits(program, value),
+            _ => return None,
+        };
+        Some(self.tally)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::DeviceEvent;
+
+    #[test]
+    fn test_decode_each_input() {
+        for input in VideoInput::ALL {
+            let state = TallyState::from_bits(1 << input.index(), 0);
+            for other in VideoInput::ALL {
+                assert_eq!(state.is_program(other), other == input);
+                assert!(!state.is_preview(other));
+            }
+
+            let state = TallyState::from_bits(0, 1 << input.index());
+            assert_eq!(state.preview().collect::<Vec<_>>(), vec![input]);
+            assert_eq!(state.program().count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_decode_transition() {
+        // HDMI 1 -> HDMI 2 in progress, HDMI 2 still on preview,
+        // reserved bits set
+        let state = TallyState::from_bits(0xC3, 0x02);
+        assert_eq!(
+            state.program().collect::<Vec<_>>(),
+            vec![VideoInput::Hdmi1, VideoInput::Hdmi2]
+        );
+        assert!(state.is_program(VideoInput::Hdmi2));
+        assert!(state.is_preview(VideoInput::Hdmi2));
+        assert!(!state.is_preview(VideoInput::Hdmi1));
+        assert_eq!(state, TallyState::from_bits(0x03, 0x02));
+    }
+
+    #[test]
+    fn test_tally_query_and_event() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(tally::PROGRAM, 0x01);
+        mock.set_parameter(tally::PREVIEW, 0x10);
+        let mut client = connect(addr);
+
+        let state = client.tally().unwrap();
+        assert!(state.is_program(VideoInput::Hdmi1));
+        assert!(state.is_preview(VideoInput::Still1));
+
+        // Taking STILL 1 updates program; preview is remembered
+        mock.inject_unsolicited(tally::PROGRAM, 0x10);
+        client.get_version().unwrap();
+        assert_eq!(
+            client.events().collect::<Vec<_>>(),
+            vec![
+                DeviceEvent::ParameterChanged {
+                    address: tally::PROGRAM,
+                    value: 0x10,
+                },
+                DeviceEvent::TallyChanged(TallyState::from_bits(0x10, 0x10)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Telnet option negotiation
+//!
+//! Telnet-aware gateways may open a session with IAC negotiation such as
+//! `IAC DO ECHO`. [`Iac`] strips those sequences from the received bytes
+//! before they reach the frame decoder. The client refuses every option
+//! (`WONT` for `DO`, `DONT` for `WILL`), so the session stays a plain byte
+//! stream.
+
+/// Interpret As Command
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+/// Subnegotiation begin
+const SB: u8 = 250;
+/// Subnegotiation end
+const SE: u8 = 240;
+
+/// Option negotiation request received from the peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Negotiation {
+    Do(u8),
+    Dont(u8),
+    Will(u8),
+    Wont(u8),
+}
+
+impl Negotiation {
+    /// Get the reply refusing the request, if one is needed
+    ///
+    /// `DONT` and `WONT` need no reply since no option is ever enabled.
+    pub(crate) fn refusal(self) -> Option<Negotiation> {
+        match self {
+            Negotiation::Do(option) => Some(Negotiation::Wont(option)),
+            Negotiation::Will(option) => Some(Negotiation::Dont(option)),
+            Negotiation::Dont(_) | Negotiation::Wont(_) => None,
+        }
+    }
+
+    /// Encode as an IAC sequence
+    pub(crate) fn to_bytes(self) -> [u8; 3] {
+        match self {
+            Negotiation::Do(option) => [IAC, DO, option],
+            Negotiation::Dont(option) => [IAC, DONT, option],
+            Negotiation::Will(option) => [IAC, WILL, option],
+            Negotiation::Wont(option) => [IAC, WONT, option],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum State {
+    #[default]
+    Data,
+    /// After IAC
+    Command,
+    /// After IAC DO/DONT/WILL/WONT
+    Option(u8),
+    /// Inside IAC SB ... IAC SE
+    Subnegotiation,
+    /// After IAC inside a subnegotiation
+    SubnegotiationIac,
+}
+
+/// Filter removing IAC sequences from a byte stream
+///
+/// Sequences split across reads are handled, so the filter must see every
+/// received byte in order.
+#[derive(Debug, Default)]
+pub(crate) struct Iac {
+    state: State,
+}
+
+impl Iac {
+    /// Strip IAC sequences from `input`
+    ///
+    /// Data bytes are appended to `data`; the negotiation requests found
+    /// are returned. `IAC IAC` stands for a literal 0xFF data byte.
+    pub(crate) fn filter(&mut self, input: &[u8], data: &mut Vec<u8>) -> Vec<Negotiation> {
+        let mut negotiations = Vec::new();
+        for &byte in input {
+            self.state = match (self.state, byte) {
+                (State::Data, IAC) => State::Command,
+                (State::Data, byte) => {
+                    data.push(byte);
+                    State::Data
+                }
+                (State::Command, IAC) => {
+                    data.push(IAC);
+                    State::Data
+                }
+                (State::Command, DO | DONT | WILL | WONT) => State::Option(byte),
+                (State::Command, SB) => State::Subnegotiation,
+                // NOP, GA and the like carry no option
+                (State::Command, _) => State::Data,
+                (State::Option(command), option) => {
+                    negotiations.push(match command {
+                        DO => Negotiation::Do(option),
+                        DONT => Negotiation::Dont(option),
+                        WILL => Negotiation::Will(option),
+                        _ => Negotiation::Wont(option),
+                    });
+                    State::Data
+                }
+                (State::Subnegotiation, IAC) => State::SubnegotiationIac,
+                (State::Subnegotiation, _) => State::Subnegotiation,
+                (State::SubnegotiationIac, SE) => State::Data,
+                (State::SubnegotiationIac, _) => State::Subnegotiation,
+            };
+        }
+        negotiations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+
+    #[test]
+    fn test_filter() {
+        let mut iac = Iac::default();
+        let mut data = Vec::new();
+        // DO ECHO, WILL SUPPRESS-GO-AHEAD, SB TERMINAL-TYPE SEND SE, NOP
+        let input = b"\xFF\xFD\x01\xFF\xFB\x03\xFF\xFA\x18\x01\xFF\xF0\xFF\xF1VER;";
+        let negotiations = iac.filter(input, &mut data);
+        assert_eq!(data, b"VER;");
+        assert_eq!(
+            negotiations,
+            vec![Negotiation::Do(0x01), Negotiation::Will(0x03)]
+        );
+        assert_eq!(
+            negotiations
+                .iter()
+                .filter_map(|n| n.refusal())
+                .map(Negotiation::to_bytes)
+                .collect::<Vec<_>>(),
+            vec![[IAC, WONT, 0x01], [IAC, DONT, 0x03]]
+        );
+        assert_eq!(Negotiation::Wont(0x01).refusal(), None);
+
+        // Split across reads, and an escaped 0xFF
+        let mut data = Vec::new();
+        assert!(iac.filter(b"ack\xFF", &mut data).is_empty());
+        assert_eq!(iac.filter(b"\xFD", &mut data), vec![]);
+        assert_eq!(
+            iac.filter(b"\x1F\xFF\xFFx", &mut data),
+            vec![Negotiation::Do(0x1F)]
+        );
+        assert_eq!(data, b"ack\xFFx");
+    }
+
+    #[test]
+    fn test_negotiation_preamble() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_preamble(b"\xFF\xFD\x01\xFF\xFB\x01\xFF\xFD\x1F");
+        let mut client = connect(addr);
+
+        let (product, _) = client.get_version().unwrap();
+        assert_eq!(product, "VR-6HD");
+        // The refusals go out with the next command
+        assert_eq!(client.read_parameter("010000", 1).unwrap(), 0);
+        assert_eq!(mock.negotiation(), b"\xFF\xFC\x01\xFF\xFE\x01\xFF\xFC\x1F");
+    }
+}
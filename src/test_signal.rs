@@ -0,0 +1,239 @@
+//! Test tone and test pattern for line checks
+//!
+//! A test signal on a live stream or recording goes out to the audience,
+//! so turning one on first checks [`TelnetClient::transport_status`] and
+//! fails with [`TelnetError::SafetyInterlock`] while either is active.
+//! Pass `force = true` to skip the check. Turning a test signal off, and
+//! changing the tone's level or type, is always allowed.
+
+use crate::audio::Db;
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::system;
+
+/// Signal of the test tone
+///
+/// Values this crate doesn't know decode to [`ToneType::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ToneType {
+    /// 1 kHz sine
+    Sine1k,
+    /// 440 Hz sine
+    Sine440,
+    /// Pink noise
+    PinkNoise,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl ToneType {
+    /// All documented tone types
+    pub const ALL: [ToneType; 3] = [ToneType::Sine1k, ToneType::Sine440, ToneType::PinkNoise];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => ToneType::Sine1k,
+            1 => ToneType::Sine440,
+            2 => ToneType::PinkNoise,
+            value => ToneType::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            ToneType::Sine1k => 0,
+            ToneType::Sine440 => 1,
+            ToneType::PinkNoise => 2,
+            ToneType::Other(value) => value,
+        }
+    }
+}
+
+/// Test pattern on the video outputs
+///
+/// Values this crate doesn't know decode to [`TestPattern::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TestPattern {
+    /// No test pattern, normal output
+    Off,
+    /// Color bars
+    ColorBars,
+    /// Grayscale ramp
+    Ramp,
+    /// Crosshatch
+    Crosshatch,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl TestPattern {
+    /// All documented patterns, including [`TestPattern::Off`]
+    pub const ALL: [TestPattern; 4] = [
+        TestPattern::Off,
+        TestPattern::ColorBars,
+        TestPattern::Ramp,
+        TestPattern::Crosshatch,
+    ];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => TestPattern::Off,
+            1 => TestPattern::ColorBars,
+            2 => TestPattern::Ramp,
+            3 => TestPattern::Crosshatch,
+            value => TestPattern::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            TestPattern::Off => 0,
+            TestPattern::ColorBars => 1,
+            TestPattern::Ramp => 2,
+            TestPattern::Crosshatch => 3,
+            TestPattern::Other(value) => value,
+        }
+    }
+}
+
+impl TelnetClient {
+    /// Turn the test tone on or off
+    ///
+    /// # Arguments
+    /// * `enabled` - Whether the tone is on
+    /// * `force` - Turn it on even while streaming or recording
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `SafetyInterlock` if the
+    ///   tone would go out live (nothing is written then)
+    pub fn enable_test_tone(&mut self, enabled: bool, force: bool) -> Result<(), TelnetError> {
+        if enabled && !force {
+            self.check_not_live("enable the test tone")?;
+        }
+        self.write_parameter_addr(system::TEST_TONE, enabled as u8)
+    }
+
+    /// Set the test tone level, on the same scale as the faders
+    pub fn set_test_tone_level(&mut self, level: Db) -> Result<(), TelnetError> {
+        self.write_parameter_addr(system::TEST_TONE_LEVEL, level.to_byte())
+    }
+
+    /// Set the test tone signal
+    pub fn set_test_tone_type(&mut self, kind: ToneType) -> Result<(), TelnetError> {
+        self.write_parameter_addr(system::TEST_TONE_TYPE, kind.value())
+    }
+
+    /// Show a test pattern on the video outputs, or [`TestPattern::Off`]
+    ///
+    /// # Arguments
+    /// * `pattern` - Pattern to show
+    /// * `force` - Show it even while streaming or recording
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `SafetyInterlock` if the
+    ///   pattern would go out live (nothing is written then)
+    pub fn enable_test_pattern(
+        &mut self,
+        pattern: TestPattern,
+        force: bool,
+    ) -> Result<(), TelnetError> {
+        if pattern != TestPattern::Off && !force {
+            self.check_not_live("show a test pattern")?;
+        }
+        self.write_parameter_addr(system::TEST_PATTERN, pattern.value())
+    }
+
+    /// Fail with `SafetyInterlock` while streaming or recording
+    fn check_not_live(&mut self, operation: &'static str) -> Result<(), TelnetError> {
+        let status = self.transport_status()?;
+        if status.streaming || status.recording {
+            return Err(TelnetError::SafetyInterlock { operation });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::params::transport;
+
+    #[test]
+    fn test_line_check_when_idle() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(transport::STATUS, 0x04);
+        let mut client = connect(addr);
+
+        client.set_test_tone_type(ToneType::PinkNoise).unwrap();
+        client.set_test_tone_level(Db(-20.0)).unwrap();
+        client.enable_test_tone(true, false).unwrap();
+        client
+            .enable_test_pattern(TestPattern::ColorBars, false)
+            .unwrap();
+        assert_eq!(mock.parameter(system::TEST_TONE_TYPE), Some(2));
+        assert_eq!(mock.parameter(system::TEST_TONE_LEVEL), Some(67));
+        assert_eq!(mock.parameter(system::TEST_TONE), Some(1));
+        assert_eq!(mock.parameter(system::TEST_PATTERN), Some(1));
+
+        for value in 0..=255 {
+            assert_eq!(ToneType::from_value(value).value(), value);
+            assert_eq!(TestPattern::from_value(value).value(), value);
+        }
+    }
+
+    #[test]
+    fn test_interlock_while_live() {
+        let (addr, mock) = MockDevice::spawn();
+        // Streaming
+        mock.set_parameter(transport::STATUS, 0x01);
+        let mut client = connect(addr);
+
+        let err = client.enable_test_tone(true, false).unwrap_err();
+        assert!(matches!(
+            err,
+            TelnetError::SafetyInterlock {
+                operation: "enable the test tone"
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Refusing to enable the test tone while streaming or recording"
+        );
+        // Recording
+        mock.set_parameter(transport::STATUS, 0x02);
+        assert!(matches!(
+            client.enable_test_pattern(TestPattern::Crosshatch, false),
+            Err(TelnetError::SafetyInterlock { .. })
+        ));
+        assert_eq!(mock.parameter(system::TEST_TONE), None);
+        assert_eq!(mock.parameter(system::TEST_PATTERN), None);
+
+        // Turning off is always allowed
+        client.enable_test_tone(false, false).unwrap();
+        client.enable_test_pattern(TestPattern::Off, false).unwrap();
+        assert_eq!(mock.parameter(system::TEST_TONE), Some(0));
+    }
+
+    #[test]
+    fn test_force_override() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(transport::STATUS, 0x03);
+        let mut client = connect(addr);
+
+        mock.clear_received();
+        client.enable_test_tone(true, true).unwrap();
+        client
+            .enable_test_pattern(TestPattern::ColorBars, true)
+            .unwrap();
+        // No status check, just the writes
+        assert_eq!(mock.received().len(), 2);
+        assert_eq!(mock.parameter(system::TEST_TONE), Some(1));
+        assert_eq!(mock.parameter(system::TEST_PATTERN), Some(1));
+    }
+}
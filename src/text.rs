@@ -0,0 +1,193 @@
+//! ASCII text parameters
+//!
+//! Scene names, still names and the device name are stored in
+//! fixed-length blocks of ASCII characters, one per address, padded at
+//! the end. [`TelnetClient::read_string_parameter`] and
+//! [`TelnetClient::write_string_parameter`] convert between such blocks
+//! and strings.
+
+use crate::{offset, TelnetClient, TelnetError};
+use roland_core::{Address, RolandError};
+
+/// Byte text blocks are padded with when written
+pub const PADDING: u8 = b' ';
+
+/// How text blocks are sent to the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StringWriteMode {
+    /// The whole block in one multi-byte DTH, so the device never shows a
+    /// half-written name
+    #[default]
+    Block,
+    /// One DTH per character, for devices that reject multi-byte writes
+    /// to text parameters
+    PerByte,
+}
+
+impl TelnetClient {
+    /// Set how [`TelnetClient::write_string_parameter`] sends text
+    pub fn set_string_write_mode(&mut self, mode: StringWriteMode) {
+        self.string_write_mode = mode;
+    }
+
+    /// Get how [`TelnetClient::write_string_parameter`] sends text
+    pub fn string_write_mode(&self) -> StringWriteMode {
+        self.string_write_mode
+    }
+
+    /// Read a fixed-length ASCII text parameter
+    ///
+    /// Trailing NUL and space padding is removed.
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address of the first character
+    /// * `len` - Length of the block
+    ///
+    /// # Returns
+    /// * `Result<String, TelnetError>` - Text, or `InvalidValue` if the
+    ///   device sent a byte that isn't ASCII
+    pub fn read_string_parameter(
+        &mut self,
+        address: Address,
+        len: u32,
+    ) -> Result<String, TelnetError> {
+        let data = self.read_parameter_bytes(address, len)?;
+        if !data.is_ascii() {
+            return Err(TelnetError::Protocol(RolandError::InvalidValue));
+        }
+        let end = data
+            .iter()
+            .rposition(|&b| b != 0 && b != PADDING)
+            .map_or(0, |i| i + 1);
+        Ok(data[..end].iter().map(|&b| b as char).collect())
+    }
+
+    /// Write a fixed-length ASCII text parameter
+    ///
+    /// `text` is padded with spaces to `len` characters and sent as set
+    /// with [`TelnetClient::set_string_write_mode`].
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address of the first character
+    /// * `text` - Text to write
+    /// * `len` - Length of the block
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success or error; text that isn't
+    ///   printable ASCII fails with `InvalidValue`, and text longer than
+    ///   `len` with `OutOfRange`, without sending anything
+    pub fn write_string_parameter(
+        &mut self,
+        address: Address,
+        text: &str,
+        len: u32,
+    ) -> Result<(), TelnetError> {
+        check_text(text, len)?;
+        let mut data = text.as_bytes().to_vec();
+        data.resize(len as usize, PADDING);
+        match self.string_write_mode {
+            StringWriteMode::Block => self.write_parameter_block(address, &data),
+            StringWriteMode::PerByte => data
+                .iter()
+                .enumerate()
+                .try_for_each(|(i, &b)| self.write_parameter_now(offset(address, i), b)),
+        }
+    }
+}
+
+/// Check that text can be written to a block of `len` characters
+pub(crate) fn check_text(text: &str, len: u32) -> Result<(), TelnetError> {
+    if !text.bytes().all(|b| b.is_ascii_graphic() || b == b' ') {
+        return Err(TelnetError::Protocol(RolandError::InvalidValue));
+    }
+    if text.len() > len as usize {
+        return Err(TelnetError::Protocol(RolandError::OutOfRange));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::Command;
+
+    const NAME: Address = Address::new(0x07, 0x10, 0x00);
+
+    #[test]
+    fn test_round_trip() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        // Short
+        client.write_string_parameter(NAME, "Intro", 8).unwrap();
+        assert_eq!(
+            mock.received(),
+            vec![Command::WriteBlock {
+                address: NAME,
+                data: b"Intro   ".to_vec(),
+            }]
+        );
+        assert_eq!(client.read_string_parameter(NAME, 8).unwrap(), "Intro");
+
+        // Exact length
+        client.write_string_parameter(NAME, "Main Cam", 8).unwrap();
+        assert_eq!(client.read_string_parameter(NAME, 8).unwrap(), "Main Cam");
+
+        // Empty
+        client.write_string_parameter(NAME, "", 8).unwrap();
+        assert_eq!(client.read_string_parameter(NAME, 8).unwrap(), "");
+    }
+
+    #[test]
+    fn test_invalid_text_not_sent() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        for text in ["Café", "Tab\there"] {
+            assert!(matches!(
+                client.write_string_parameter(NAME, text, 8),
+                Err(TelnetError::Protocol(RolandError::InvalidValue))
+            ));
+        }
+        assert!(matches!(
+            client.write_string_parameter(NAME, "Too long!", 8),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+        assert!(mock.received().is_empty());
+    }
+
+    #[test]
+    fn test_read_padding_and_non_ascii() {
+        let (addr, mock) = MockDevice::spawn();
+        for (i, &b) in b"Cam 1\0\0 ".iter().enumerate() {
+            mock.set_parameter(offset(NAME, i), b);
+        }
+        let mut client = connect(addr);
+        assert_eq!(client.read_string_parameter(NAME, 8).unwrap(), "Cam 1");
+
+        mock.set_parameter(offset(NAME, 2), 0xE9);
+        assert!(matches!(
+            client.read_string_parameter(NAME, 8),
+            Err(TelnetError::Protocol(RolandError::InvalidValue))
+        ));
+    }
+
+    #[test]
+    fn test_per_byte_writes() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        client.set_string_write_mode(StringWriteMode::PerByte);
+
+        client.write_string_parameter(NAME, "AB", 3).unwrap();
+        assert_eq!(
+            mock.received(),
+            vec![
+                Command::write_parameter(NAME, b'A'),
+                Command::write_parameter(offset(NAME, 1), b'B'),
+                Command::write_parameter(offset(NAME, 2), b' '),
+            ]
+        );
+        assert_eq!(client.read_string_parameter(NAME, 3).unwrap(), "AB");
+    }
+}
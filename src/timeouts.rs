@@ -0,0 +1,195 @@
+//! Response timeouts per kind of command
+//!
+//! The device answers most commands in well under 50 ms, but takes
+//! seconds to acknowledge a scene recall or a still capture. A single
+//! timeout would either give up on those or take long to notice a lost
+//! fader write, so [`TelnetClient::send_command`] picks one by command.
+
+use crate::profile::Profile;
+use crate::TelnetClient;
+use roland_core::{Address, Command};
+use std::time::Duration;
+
+/// How long to wait for the response to a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Writes, except those below
+    pub write: Duration,
+    /// Recalling or storing a scene memory
+    pub scene: Duration,
+    /// Capturing a still image
+    pub capture: Duration,
+    /// Reads, `VER` and anything else
+    pub other: Duration,
+}
+
+/// 1 s for writes, 10 s for scenes and captures, 5 s otherwise
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            write: Duration::from_secs(1),
+            scene: Duration::from_secs(10),
+            capture: Duration::from_secs(10),
+            other: crate::TIMEOUT,
+        }
+    }
+}
+
+impl Timeouts {
+    /// Get the timeout for a command
+    ///
+    /// Writes are told apart by their address in `profile`, so a raw
+    /// write to `scene.recall` waits as long as
+    /// [`TelnetClient::recall_scene`].
+    pub fn for_command(&self, command: &Command, profile: &Profile) -> Duration {
+        match command {
+            Command::WriteParameter { address, .. } | Command::WriteBlock { address, .. } => {
+                self.for_write(*address, profile)
+            }
+            _ => self.other,
+        }
+    }
+
+    fn for_write(&self, address: Address, profile: &Profile) -> Duration {
+        if profile.is("scene.recall", address) || profile.is("scene.store", address) {
+            self.scene
+        } else if profile.is("still.capture", address) {
+            self.capture
+        } else {
+            self.write
+        }
+    }
+}
+
+impl TelnetClient {
+    /// Set the response timeouts
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Get the response timeouts
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use crate::param_map::ParameterMap;
+    use crate::TelnetError;
+    use roland_core::params::{scene, still};
+    use std::thread;
+    use std::time::Instant;
+
+    fn short() -> Timeouts {
+        Timeouts {
+            write: Duration::from_millis(100),
+            scene: Duration::from_secs(2),
+            capture: Duration::from_secs(2),
+            other: Duration::from_millis(100),
+        }
+    }
+
+    #[test]
+    fn test_for_command() {
+        let timeouts = Timeouts::default();
+        let profile = Profile::default();
+        let fader = Address::new(0x05, 0x00, 0x00);
+        assert_eq!(
+            timeouts.for_command(&Command::write_parameter(fader, 1), &profile),
+            timeouts.write
+        );
+        assert_eq!(
+            timeouts.for_command(&Command::write_parameter(scene::RECALL, 1), &profile),
+            timeouts.scene
+        );
+        assert_eq!(
+            timeouts.for_command(&Command::write_parameter(still::CAPTURE, 1), &profile),
+            timeouts.capture
+        );
+        assert_eq!(
+            timeouts.for_command(&Command::GetVersion, &profile),
+            timeouts.other
+        );
+    }
+
+    #[test]
+    fn test_for_command_of_profile() {
+        let timeouts = Timeouts::default();
+        let map = ParameterMap::from_toml("[scene.recall]\naddress = \"0B0000\"\n").unwrap();
+        let profile = Profile::Custom(map);
+        assert_eq!(
+            timeouts.for_command(
+                &Command::write_parameter(Address::new(0x0B, 0x00, 0x00), 1),
+                &profile
+            ),
+            timeouts.scene
+        );
+        assert_eq!(
+            timeouts.for_command(&Command::write_parameter(scene::RECALL, 1), &profile),
+            timeouts.write
+        );
+    }
+
+    #[test]
+    fn test_slow_ack_within_scene_timeout() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        client.set_timeouts(short());
+        mock.set_delay(Duration::from_millis(300));
+
+        // Scene recall waits for the late ACK
+        client.recall_scene(1).unwrap();
+
+        // A fader write gives up
+        let start = Instant::now();
+        assert!(matches!(
+            client.write_parameter_addr(Address::new(0x05, 0x00, 0x00), 1),
+            Err(TelnetError::Timeout)
+        ));
+        assert!(start.elapsed() < Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_early_ack() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        mock.set_delay(Duration::from_millis(20));
+
+        let start = Instant::now();
+        let response = client
+            .send_command_with_timeout(
+                &Command::write_parameter(scene::RECALL, 0),
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        assert!(response.is_ack());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_timeout_bounds_whole_wait() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        client.get_version().unwrap();
+        mock.set_delay(Duration::from_millis(800));
+
+        // Unrelated frames keep arriving while the answer is late
+        let feeder = thread::spawn(move || {
+            for value in 0..20 {
+                mock.send_unsolicited(Address::new(0x05, 0x00, 0x01), value);
+                thread::sleep(Duration::from_millis(25));
+            }
+            mock
+        });
+
+        let start = Instant::now();
+        let result =
+            client.send_command_with_timeout(&Command::GetVersion, Duration::from_millis(150));
+        assert!(matches!(result, Err(TelnetError::Timeout)));
+        assert!(start.elapsed() < Duration::from_millis(400));
+        drop(feeder.join().unwrap());
+    }
+}
@@ -0,0 +1,438 @@
+//! Streaming and recording control
+
+use crate::{TelnetClient, TelnetError};
+use roland_core::params::transport;
+use roland_core::{Address, RolandError};
+use std::time::Duration;
+
+/// Status bit: streaming
+const STREAMING_BIT: u8 = 0x01;
+/// Status bit: recording
+const RECORDING_BIT: u8 = 0x02;
+/// Status bit: SD card present
+const SD_CARD_BIT: u8 = 0x04;
+/// Storage status bit: card present
+const STORAGE_PRESENT_BIT: u8 = 0x01;
+/// Storage status bit: write protected
+const STORAGE_PROTECTED_BIT: u8 = 0x02;
+
+/// Streaming and recording status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TransportStatus {
+    /// Streaming is active
+    pub streaming: bool,
+    /// Recording is active
+    pub recording: bool,
+    /// An SD card is inserted
+    pub sd_card_present: bool,
+    /// Time since streaming or recording started, if either is active
+    pub elapsed: Option<Duration>,
+}
+
+impl TransportStatus {
+    /// Decode the status byte and the elapsed time bytes
+    ///
+    /// `elapsed` holds hours, minutes and seconds, and is ignored unless
+    /// streaming or recording is active. Reserved status bits are ignored.
+    pub fn decode(status: u8, elapsed: Option<[u8; 3]>) -> Self {
+        let streaming = status & STREAMING_BIT != 0;
+        let recording = status & RECORDING_BIT != 0;
+        let elapsed = elapsed
+            .filter(|_| streaming || recording)
+            .map(|[hours, minutes, seconds]| {
+                Duration::from_secs(hours as u64 * 3600 + minutes as u64 * 60 + seconds as u64)
+            });
+        Self {
+            streaming,
+            recording,
+            sd_card_present: status & SD_CARD_BIT != 0,
+            elapsed,
+        }
+    }
+}
+
+/// Decode the elapsed time block at [`transport::ELAPSED`]
+///
+/// # Returns
+/// * `Result<Option<Duration>, RolandError>` - Elapsed time, `None` for
+///   [`transport::ELAPSED_IDLE`], or `InvalidValue` if the minutes or
+///   seconds are 60 or more
+pub fn decode_elapsed(bytes: [u8; 3]) -> Result<Option<Duration>, RolandError> {
+    if bytes == transport::ELAPSED_IDLE {
+        return Ok(None);
+    }
+    let [hours, minutes, seconds] = bytes;
+    if minutes >= 60 || seconds >= 60 {
+        return Err(RolandError::InvalidValue);
+    }
+    Ok(Some(Duration::from_secs(
+        hours as u64 * 3600 + minutes as u64 * 60 + seconds as u64,
+    )))
+}
+
+/// SD card status, as read by [`TelnetClient::storage_status`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StorageStatus {
+    /// An SD card is inserted
+    pub present: bool,
+    /// Remaining recording time in minutes, `None` without a card
+    pub remaining_minutes: Option<u32>,
+    /// The card is write protected
+    pub write_protected: bool,
+}
+
+impl StorageStatus {
+    /// Decode the storage block, starting at [`transport::STORAGE_STATUS`]
+    ///
+    /// Reserved status bits are ignored.
+    pub fn decode(bytes: [u8; 3]) -> Self {
+        let [status, high, low] = bytes;
+        let present = status & STORAGE_PRESENT_BIT != 0;
+        Self {
+            present,
+            remaining_minutes: present.then(|| u32::from(u16::from_be_bytes([high, low]))),
+            write_protected: present && status & STORAGE_PROTECTED_BIT != 0,
+        }
+    }
+
+    /// Check if a recording of at least `minutes` fits on the card
+    pub fn can_record(&self, minutes: u32) -> bool {
+        self.present
+            && !self.write_protected
+            && self
+                .remaining_minutes
+                .is_some_and(|m| m > 0 && m >= minutes)
+    }
+}
+
+/// Outcome of a stop request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopOutcome {
+    /// The device stopped
+    Stopped,
+    /// Nothing was running
+    AlreadyStopped,
+}
+
+impl TelnetClient {
+    /// Start streaming
+    pub fn start_streaming(&mut self) -> Result<(), TelnetError> {
+        self.write_parameter_addr(transport::STREAMING, 1)
+    }
+
+    /// Stop streaming
+    ///
+    /// The device rejects stopping a stream that isn't running with
+    /// `ERR:4`; that is reported as [`StopOutcome::AlreadyStopped`].
+    pub fn stop_streaming(&mut self) -> Result<StopOutcome, TelnetError> {
+        self.stop(transport::STREAMING, |status| status.streaming)
+    }
+
+    /// Start recording to the SD card
+    ///
+    /// With a minimum set by [`TelnetClient::set_recording_precheck`], the
+    /// card is checked first.
+    ///
+    /// # Returns
+    /// * `Result<(), TelnetError>` - Success, or `StorageUnavailable` if the
+    ///   card is missing, write protected or has less than the minimum
+    ///   time left (nothing is written then)
+    pub fn start_recording(&mut self) -> Result<(), TelnetError> {
+        if let Some(minutes) = self.recording_precheck {
+            let status = self.storage_status()?;
+            if !status.can_record(minutes) {
+                return Err(TelnetError::StorageUnavailable { status });
+            }
+        }
+        self.write_parameter_addr(transport::RECORDING, 1)
+    }
+
+    /// Make [`TelnetClient::start_recording`] check the SD card first
+    ///
+    /// # Arguments
+    /// * `min_minutes` - Recording time the card must have left, or `None`
+    ///   to start without a check (the default). A card that is full is
+    ///   refused even with `Some(0)`.
+    pub fn set_recording_precheck(&mut self, min_minutes: Option<u32>) {
+        self.recording_precheck = min_minutes;
+    }
+
+    /// Get the SD card status with one read
+    pub fn storage_status(&mut self) -> Result<StorageStatus, TelnetError> {
+        let data = self.read_parameter_bytes(transport::STORAGE_STATUS, transport::STORAGE_SIZE)?;
+        Ok(StorageStatus::decode([data[0], data[1], data[2]]))
+    }
+
+    /// Stop recording
+    ///
+    /// The device rejects stopping a recording that isn't running with
+    /// `ERR:4`; that is reported as [`StopOutcome::AlreadyStopped`].
+    pub fn stop_recording(&mut self) -> Result<StopOutcome, TelnetError> {
+        self.stop(transport::RECORDING, |status| status.recording)
+    }
+
+    /// Get the streaming and recording status
+    ///
+    /// The elapsed time is only read while streaming or recording.
+    pub fn transport_status(&mut self) -> Result<TransportStatus, TelnetError> {
+        let status = self.read_parameter_addr(transport::STATUS, 1)?;
+        let active = status & (STREAMING_BIT | RECORDING_BIT) != 0;
+        let elapsed = if active {
+            Some(self.read_elapsed_block()?)
+        } else {
+            None
+        };
+        Ok(TransportStatus::decode(status, elapsed))
+    }
+
+    /// Get the time since recording started
+    ///
+    /// Reads the elapsed time block with one command. The device keeps a
+    /// single timer for streaming and recording, so while only streaming
+    /// this is the streaming time.
+    ///
+    /// # Returns
+    /// * `Result<Option<Duration>, TelnetError>` - Elapsed time, or `None`
+    ///   if nothing is running
+    pub fn recording_elapsed(&mut self) -> Result<Option<Duration>, TelnetError> {
+        let bytes = self.read_elapsed_block()?;
+        Ok(decode_elapsed(bytes)?)
+    }
+
+    fn read_elapsed_block(&mut self) -> Result<[u8; 3], TelnetError> {
+        let data = self.read_parameter_bytes(transport::ELAPSED, 3)?;
+        Ok([data[0], data[1], data[2]])
+    }
+
+    fn stop(
+        &mut self,
+        address: Address,
+        running: fn(&TransportStatus) -> bool,
+    ) -> Result<StopOutcome, TelnetError> {
+        match self.write_parameter_addr(address, 0) {
+            Ok(()) => Ok(StopOutcome::Stopped),
+            Err(
+                e @ TelnetError::Device {
+                    error: RolandError::Invalid,
+                    ..
+                },
+            ) => {
+                let status = self.transport_status()?;
+                if running(&status) {
+                    Err(e)
+                } else {
+                    Ok(StopOutcome::AlreadyStopped)
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+
+    #[test]
+    fn test_decode_status() {
+        // Idle, no card
+        assert_eq!(
+            TransportStatus::decode(0x00, None),
+            TransportStatus::default()
+        );
+
+        // Recording with a card, 1:02:03 in
+        assert_eq!(
+            TransportStatus::decode(0x06, Some([0x01, 0x02, 0x03])),
+            TransportStatus {
+                streaming: false,
+                recording: true,
+                sd_card_present: true,
+                elapsed: Some(Duration::from_secs(3723)),
+            }
+        );
+
+        // Streaming only; reserved bits are ignored
+        let status = TransportStatus::decode(0xF1, Some([0x00, 0x00, 0x2A]));
+        assert!(status.streaming);
+        assert!(!status.recording);
+        assert!(!status.sd_card_present);
+        assert_eq!(status.elapsed, Some(Duration::from_secs(42)));
+
+        // Elapsed time is meaningless when idle
+        assert_eq!(
+            TransportStatus::decode(0x04, Some([0x01, 0x00, 0x00])).elapsed,
+            None
+        );
+    }
+
+    #[test]
+    fn test_decode_elapsed() {
+        let cases = [
+            ([0x00, 0x00, 0x00], Some(0)),
+            ([0x00, 0x00, 0x3B], Some(59)),
+            ([0x00, 0x3B, 0x3B], Some(3599)),
+            // Past one hour
+            ([0x01, 0x00, 0x00], Some(3600)),
+            ([0x02, 0x1E, 0x2D], Some(9045)),
+            ([0x7F, 0x3B, 0x3B], Some(127 * 3600 + 3599)),
+            ([0x7F, 0x7F, 0x7F], None),
+        ];
+        for (bytes, secs) in cases {
+            assert_eq!(
+                decode_elapsed(bytes),
+                Ok(secs.map(Duration::from_secs)),
+                "{:02X?}",
+                bytes
+            );
+        }
+        for bytes in [[0x00, 0x3C, 0x00], [0x00, 0x00, 0x3C], [0x00, 0x7F, 0x7F]] {
+            assert_eq!(decode_elapsed(bytes), Err(RolandError::InvalidValue));
+        }
+    }
+
+    #[test]
+    fn test_recording_elapsed() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let set = |bytes: [u8; 3]| {
+            for (i, &b) in bytes.iter().enumerate() {
+                mock.set_parameter(crate::offset(transport::ELAPSED, i), b);
+            }
+        };
+
+        set(transport::ELAPSED_IDLE);
+        assert_eq!(client.recording_elapsed().unwrap(), None);
+        set([0x01, 0x00, 0x05]);
+        assert_eq!(
+            client.recording_elapsed().unwrap(),
+            Some(Duration::from_secs(3605))
+        );
+        assert_eq!(
+            mock.received(),
+            vec![roland_core::Command::read(transport::ELAPSED, 3).unwrap(); 2]
+        );
+    }
+
+    #[test]
+    fn test_start_and_status() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        client.start_streaming().unwrap();
+        client.start_recording().unwrap();
+        assert_eq!(mock.parameter(transport::STREAMING), Some(1));
+        assert_eq!(mock.parameter(transport::RECORDING), Some(1));
+
+        mock.set_parameter(transport::STATUS, 0x07);
+        mock.set_parameter(transport::ELAPSED, 0x00);
+        mock.set_parameter(Address::new(0x08, 0x00, 0x12), 0x01);
+        mock.set_parameter(Address::new(0x08, 0x00, 0x13), 0x05);
+        let status = client.transport_status().unwrap();
+        assert!(status.streaming && status.recording && status.sd_card_present);
+        assert_eq!(status.elapsed, Some(Duration::from_secs(65)));
+    }
+
+    #[test]
+    fn test_decode_storage() {
+        // No card; the remaining bytes are meaningless
+        let absent = StorageStatus::decode([0x00, 0x01, 0x00]);
+        assert_eq!(absent, StorageStatus::default());
+        assert!(!absent.can_record(0));
+
+        // Full card
+        let full = StorageStatus::decode([0x01, 0x00, 0x00]);
+        assert_eq!(full.remaining_minutes, Some(0));
+        assert!(!full.write_protected);
+        assert!(!full.can_record(0));
+
+        // Write protected, with reserved bits set
+        let protected = StorageStatus::decode([0xF3, 0x01, 0x2C]);
+        assert!(protected.present && protected.write_protected);
+        assert_eq!(protected.remaining_minutes, Some(300));
+        assert!(!protected.can_record(10));
+
+        // Ready; reserved bits are ignored
+        let ready = StorageStatus::decode([0x7D, 0x01, 0x2C]);
+        assert_eq!(
+            ready,
+            StorageStatus {
+                present: true,
+                remaining_minutes: Some(300),
+                write_protected: false,
+            }
+        );
+        assert!(ready.can_record(300));
+        assert!(!ready.can_record(301));
+    }
+
+    #[test]
+    fn test_recording_precheck() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let set = |bytes: [u8; 3]| {
+            for (i, &b) in bytes.iter().enumerate() {
+                mock.set_parameter(crate::offset(transport::STORAGE_STATUS, i), b);
+            }
+        };
+        client.set_recording_precheck(Some(30));
+
+        set([0x00, 0x00, 0x00]);
+        let err = client.start_recording().unwrap_err();
+        assert!(matches!(
+            err,
+            TelnetError::StorageUnavailable {
+                status: StorageStatus { present: false, .. }
+            }
+        ));
+        assert_eq!(err.to_string(), "SD card not ready for recording: no card");
+        set([0x03, 0x00, 0x3C]);
+        assert_eq!(
+            client.start_recording().unwrap_err().to_string(),
+            "SD card not ready for recording: write protected"
+        );
+        set([0x01, 0x00, 0x0A]);
+        assert_eq!(
+            client.start_recording().unwrap_err().to_string(),
+            "SD card not ready for recording: 10 minutes left"
+        );
+        assert_eq!(mock.parameter(transport::RECORDING), None);
+
+        set([0x01, 0x00, 0x3C]);
+        client.start_recording().unwrap();
+        assert_eq!(mock.parameter(transport::RECORDING), Some(1));
+
+        // Without a precheck nothing is read
+        client.set_recording_precheck(None);
+        mock.clear_received();
+        client.start_recording().unwrap();
+        assert_eq!(mock.received().len(), 1);
+    }
+
+    #[test]
+    fn test_stop() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+
+        assert_eq!(client.stop_recording().unwrap(), StopOutcome::Stopped);
+
+        // A stop the device rejects because nothing runs is still fine
+        mock.set_address_error(transport::STREAMING, RolandError::Invalid);
+        mock.set_parameter(transport::STATUS, 0x04);
+        assert_eq!(
+            client.stop_streaming().unwrap(),
+            StopOutcome::AlreadyStopped
+        );
+
+        // Rejected while still streaming is a real error
+        mock.set_parameter(transport::STATUS, 0x05);
+        assert!(matches!(
+            client.stop_streaming(),
+            Err(TelnetError::Device {
+                error: RolandError::Invalid,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,346 @@
+//! Automation driven by parameter changes
+//!
+//! [`Triggers`] watch the changes a client receives and run an action
+//! when a parameter takes a matching value, e.g. unmute CH5 whenever the
+//! operator puts HDMI 4 on program. Call [`Triggers::run`] after the
+//! client has read from the connection, or hand the registry to a
+//! [`SharedClient`](crate::shared::SharedClient) with
+//! [`SharedClient::set_triggers`](crate::shared::SharedClient::set_triggers)
+//! to have its worker run them.
+//!
+//! # Chained triggers
+//!
+//! Writes sent by [`TriggerAction::Commands`], and changes the device
+//! reports while an action runs, count as changes caused by the action
+//! and are checked against the triggers as well. Each change caused by
+//! an action is one level deeper than the change that fired it; changes
+//! deeper than [`Triggers::max_depth`] are dropped, so two triggers that
+//! fire each other can't loop forever. [`Triggers::suppressed`] counts
+//! the dropped changes.
+
+use crate::{offset, TelnetClient, TelnetError};
+use roland_core::{Address, Command, Response};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Closure run by [`TriggerAction::Callback`]
+pub type TriggerCallback = Box<dyn FnMut(&mut TelnetClient, Address, u8) + Send>;
+
+/// What a trigger does when it fires
+pub enum TriggerAction {
+    /// Call a closure with the client, the address and the new value
+    ///
+    /// The closure runs on the thread calling [`Triggers::run`], e.g. the
+    /// worker of a `SharedClient`: use the client it is given, not a
+    /// `SharedClient` handle, which would wait for the worker forever.
+    Callback(TriggerCallback),
+    /// Send commands in order
+    Commands(Vec<Command>),
+}
+
+impl fmt::Debug for TriggerAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerAction::Callback(_) => f.write_str("Callback"),
+            TriggerAction::Commands(commands) => f.debug_tuple("Commands").field(commands).finish(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Trigger {
+    address: Address,
+    predicate: fn(u8) -> bool,
+    action: TriggerAction,
+}
+
+/// Registry of triggers, see the [module documentation](self)
+#[derive(Debug)]
+pub struct Triggers {
+    triggers: Vec<Trigger>,
+    max_depth: usize,
+    suppressed: u64,
+    errors: Vec<TelnetError>,
+}
+
+impl Default for Triggers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Triggers {
+    /// Default for [`Triggers::max_depth`]
+    pub const DEFAULT_MAX_DEPTH: usize = 4;
+
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self {
+            triggers: Vec::new(),
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            suppressed: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Run `action` whenever `address` changes to a value `predicate` accepts
+    ///
+    /// Triggers fire in the order they were added.
+    pub fn add(&mut self, address: Address, predicate: fn(u8) -> bool, action: TriggerAction) {
+        self.triggers.push(Trigger {
+            address,
+            predicate,
+            action,
+        });
+    }
+
+    /// Get the number of triggers
+    pub fn len(&self) -> usize {
+        self.triggers.len()
+    }
+
+    /// Check if there are no triggers
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    /// Set how many levels deep changes caused by actions are followed
+    ///
+    /// 0 only runs triggers for changes reported by the device.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Get how many levels deep changes caused by actions are followed
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Get the number of changes dropped by the depth limit
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+
+    /// Take the errors of commands sent by actions
+    pub fn drain_errors(&mut self) -> impl Iterator<Item = TelnetError> + '_ {
+        self.errors.drain(..)
+    }
+
+    /// Run the triggers for the changes the client received
+    ///
+    /// Only changes received since the previous call are checked; the
+    /// first call just starts recording them. The events stay queued on
+    /// the client. Errors of commands sent by actions don't stop the
+    /// other actions; they are kept for [`Triggers::drain_errors`].
+    ///
+    /// # Returns
+    /// * `usize` - Number of times a trigger fired
+    pub fn run(&mut self, client: &mut TelnetClient) -> usize {
+        if !client.changes.enabled {
+            client.changes.enabled = true;
+            return 0;
+        }
+        let mut pending: VecDeque<(Address, u8, usize)> = client
+            .changes
+            .take()
+            .map(|(address, value)| (address, value, 0))
+            .collect();
+        let mut fired = 0;
+        while let Some((address, value, depth)) = pending.pop_front() {
+            if depth > self.max_depth {
+                self.suppressed += 1;
+                continue;
+            }
+            for trigger in &mut self.triggers {
+                if trigger.address != address || !(trigger.predicate)(value) {
+                    continue;
+                }
+                fired += 1;
+                let mut caused = Vec::new();
+                match &mut trigger.action {
+                    TriggerAction::Callback(callback) => callback(client, address, value),
+                    TriggerAction::Commands(commands) => {
+                        for command in commands.iter() {
+                            match send(client, command) {
+                                Ok(()) => caused.extend(written(command)),
+                                Err(e) => self.errors.push(e),
+                            }
+                        }
+                    }
+                }
+                caused.extend(client.changes.take());
+                pending.extend(
+                    caused
+                        .into_iter()
+                        .map(|(address, value)| (address, value, depth + 1)),
+                );
+            }
+        }
+        fired
+    }
+}
+
+/// Send a command, turning a device error into a `Device` error
+pub(crate) fn send(client: &mut TelnetClient, command: &Command) -> Result<(), TelnetError> {
+    match client.send_command(command)? {
+        Response::Error(e) => Err(TelnetError::device(command, e)),
+        _ => Ok(()),
+    }
+}
+
+/// Get the parameters a write command changed
+fn written(command: &Command) -> Vec<(Address, u8)> {
+    match command {
+        Command::WriteParameter { address, value } => vec![(*address, *value)],
+        Command::WriteBlock { address, data } => data
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| (offset(*address, i), value))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Changes a client received since [`Triggers::run`] last took them
+#[derive(Debug, Default)]
+pub(crate) struct ChangeLog {
+    /// Off until triggers run, so clients without any don't keep changes
+    enabled: bool,
+    changes: VecDeque<(Address, u8)>,
+}
+
+impl ChangeLog {
+    /// Record a change, if enabled
+    pub(crate) fn record(&mut self, address: Address, value: u8) {
+        if self.enabled {
+            self.changes.push_back((address, value));
+        }
+    }
+
+    /// Stop recording and forget the recorded changes
+    pub(crate) fn disable(&mut self) {
+        self.enabled = false;
+        self.changes.clear();
+    }
+
+    fn take(&mut self) -> impl Iterator<Item = (Address, u8)> + '_ {
+        self.changes.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDevice;
+    use crate::shared::SharedClient;
+    use roland_core::params::{audio, video};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn connect(addr: std::net::SocketAddr) -> TelnetClient {
+        let mut client = crate::mock::connect(addr);
+        // Unsolicited frames only go to sessions the mock is serving
+        client.get_version().unwrap();
+        client
+    }
+
+    /// Wait until the client has queued an event
+    fn wait_for_event(client: &mut TelnetClient) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while client.poll_event().unwrap().is_none() {
+            assert!(Instant::now() < deadline, "no event");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn unmute_ch5() -> TriggerAction {
+        TriggerAction::Commands(vec![Command::write_parameter(audio::CH5_MUTE, 0)])
+    }
+
+    #[test]
+    fn test_fires_once_per_matching_change() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(audio::CH5_MUTE, 1);
+        let mut client = connect(addr);
+        let mut triggers = Triggers::new();
+        triggers.add(video::PGM_SELECT, |value| value == 3, unmute_ch5());
+        assert_eq!(triggers.run(&mut client), 0);
+
+        for (pgm, fires) in [(3, 1), (2, 0), (3, 1)] {
+            mock.send_unsolicited(video::PGM_SELECT, pgm);
+            wait_for_event(&mut client);
+            assert_eq!(triggers.run(&mut client), fires);
+            assert_eq!(triggers.run(&mut client), 0);
+        }
+        let unmutes = mock
+            .received()
+            .iter()
+            .filter_map(|command| command.address())
+            .filter(|&address| address == audio::CH5_MUTE)
+            .count();
+        assert_eq!(unmutes, 2);
+        assert_eq!(mock.parameter(audio::CH5_MUTE), Some(0));
+        assert_eq!(triggers.drain_errors().count(), 0);
+    }
+
+    #[test]
+    fn test_depth_limit_stops_loop() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let (a, b) = (Address::new(0x10, 0, 0), Address::new(0x10, 0, 1));
+        let mut triggers = Triggers::new();
+        triggers.set_max_depth(3);
+        // Each trigger sets the other's address
+        triggers.add(
+            a,
+            |value| value == 1,
+            TriggerAction::Commands(vec![Command::write_parameter(b, 1)]),
+        );
+        triggers.add(
+            b,
+            |value| value == 1,
+            TriggerAction::Commands(vec![Command::write_parameter(a, 1)]),
+        );
+        triggers.run(&mut client);
+
+        mock.clear_received();
+        mock.send_unsolicited(a, 1);
+        wait_for_event(&mut client);
+        // The device's change, then three levels of changes by actions
+        assert_eq!(triggers.run(&mut client), 4);
+        assert_eq!(triggers.suppressed(), 1);
+        assert_eq!(mock.received().len(), 4);
+    }
+
+    #[test]
+    fn test_shared_worker_runs_triggers() {
+        let (addr, mock) = MockDevice::spawn();
+        let shared = SharedClient::new(connect(addr));
+        let mut triggers = Triggers::new();
+        triggers.add(
+            video::PGM_SELECT,
+            |value| value == 3,
+            TriggerAction::Callback(Box::new(|client, _, _| {
+                client.write_parameter_addr(audio::CH5_MUTE, 0).unwrap();
+            })),
+        );
+        shared.set_triggers(triggers);
+        shared.get_version().unwrap();
+
+        mock.send_unsolicited(video::PGM_SELECT, 3);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while mock.parameter(audio::CH5_MUTE).is_none() {
+            assert!(Instant::now() < deadline, "trigger didn't fire");
+            thread::sleep(Duration::from_millis(5));
+        }
+        // Other handles still get the event
+        assert!(shared
+            .events()
+            .unwrap()
+            .contains(&crate::DeviceEvent::ParameterChanged {
+                address: video::PGM_SELECT,
+                value: 3,
+            }));
+        assert!(shared.take_triggers().is_some());
+    }
+}
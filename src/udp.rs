@@ -0,0 +1,429 @@
+//! Remote control over UDP
+//!
+//! The device also takes the remote control protocol in UDP datagrams.
+//! There are no sessions, so any number of controllers can send, which
+//! suits tally lights and fader surfaces that only send writes. The cost
+//! is that a command or its response can be lost or arrive late:
+//!
+//! * [`UdpClient::send_command`] sends a command again if no response
+//!   arrives in time, up to [`UdpClient::set_retries`] times. A write that
+//!   was carried out but whose ACK was lost is carried out again, which
+//!   is harmless for setting a value but not for e.g. a cut.
+//! * [`UdpClient::send_unreliable`] sends without waiting, for writes
+//!   where a lost one is soon replaced by the next.
+//!
+//! A read is only answered by data for the address read, so a late
+//! answer to an earlier read can't be taken for it; such data is queued
+//! as an event like the data the device sends unsolicited. ACKs carry no
+//! address, and a late one can be taken as the answer to a later write,
+//! though not to a read. ACKs left over from
+//! [`UdpClient::send_unreliable`] are discarded before each command.
+//!
+//! Responses are matched to commands as by the Telnet client, including
+//! the DTH that 2.x firmware answers writes with, see
+//! [`UdpClient::set_parse_options`].
+
+use crate::{data_for, parse_address, write_answered, DeviceEvent, TelnetError};
+use roland_core::engine::answers_with;
+use roland_core::{Address, Command, Decoder, ParseOptions, Response, RolandError};
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Default time to wait for a response before sending again
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(200);
+/// Default number of times a command is sent again
+const DEFAULT_RETRIES: u32 = 2;
+
+/// VR-6HD client sending commands in UDP datagrams
+///
+/// # Example
+/// ```ignore
+/// use roland_rs::udp::UdpClient;
+///
+/// let mut client = UdpClient::connect("192.168.1.100", port)?;
+/// client.write_parameter("010000", 0x01)?;
+/// ```
+pub struct UdpClient {
+    socket: UdpSocket,
+    decoder: Decoder,
+    parse_options: ParseOptions,
+    timeout: Duration,
+    retries: u32,
+    events: VecDeque<DeviceEvent>,
+}
+
+impl UdpClient {
+    /// Bind a local port and send to a device
+    ///
+    /// Nothing is sent yet, so this succeeds even if no device listens;
+    /// commands then time out.
+    ///
+    /// # Arguments
+    /// * `host` - IP address or hostname of the VR-6HD device
+    /// * `port` - UDP port of the remote control protocol on the device
+    ///
+    /// # Returns
+    /// * `Result<Self, TelnetError>` - Client or error
+    pub fn connect(host: &str, port: u16) -> Result<Self, TelnetError> {
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        let addr = (host, port).to_socket_addrs()?.next().ok_or_else(|| {
+            TelnetError::Io(std::io::Error::new(
+                ErrorKind::NotFound,
+                "no address resolved",
+            ))
+        })?;
+        let local = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        Ok(Self {
+            socket,
+            decoder: Decoder::with_options(ParseOptions::LENIENT),
+            parse_options: ParseOptions::LENIENT,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Set how long to wait for a response before sending again
+    /// (default 200 ms)
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Set how many times a command is sent again without a response
+    /// (default 2)
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    /// Get how many times a command is sent again without a response
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Get the frame variants accepted from the device
+    pub fn parse_options(&self) -> ParseOptions {
+        self.parse_options
+    }
+
+    /// Change the frame variants accepted from the device
+    ///
+    /// Like [`TelnetClient::set_parse_options`](crate::TelnetClient::set_parse_options),
+    /// clients accept [`ParseOptions::LENIENT`] by default. Set
+    /// [`ParseOptions::write_answer`] for firmware that answers writes
+    /// with a DTH; otherwise such writes time out and are sent again.
+    pub fn set_parse_options(&mut self, options: ParseOptions) {
+        self.parse_options = options;
+        self.decoder.set_options(options);
+    }
+
+    /// Send a command and wait for its response
+    ///
+    /// # Arguments
+    /// * `command` - Command to send
+    ///
+    /// # Returns
+    /// * `Result<Response, TelnetError>` - Response from device, or
+    ///   `Timeout` if none arrived after the last retry
+    pub fn send_command(&mut self, command: &Command) -> Result<Response, TelnetError> {
+        self.discard_pending()?;
+        for _ in 0..=self.retries {
+            self.socket.send(command.encode().as_bytes())?;
+            if let Some(response) = self.read_response(command)? {
+                return Ok(response);
+            }
+        }
+        Err(TelnetError::Timeout)
+    }
+
+    /// Send a command without waiting for a response
+    ///
+    /// The command may be lost without notice.
+    pub fn send_unreliable(&mut self, command: &Command) -> Result<(), TelnetError> {
+        self.socket.send(command.encode().as_bytes())?;
+        Ok(())
+    }
+
+    /// Write a parameter value
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address (hex string, e.g., "123456")
+    /// * `value` - Value to write (0-255)
+    pub fn write_parameter(&mut self, address: &str, value: u8) -> Result<(), TelnetError> {
+        self.write_parameter_addr(parse_address(address)?, value)
+    }
+
+    /// Write a parameter value
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address
+    /// * `value` - Value to write (0-255)
+    pub fn write_parameter_addr(&mut self, address: Address, value: u8) -> Result<(), TelnetError> {
+        let cmd = Command::WriteParameter { address, value };
+        let response = self.send_command(&cmd)?;
+        write_answered(&cmd, response, self.parse_options)
+    }
+
+    /// Read a parameter value
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address (hex string, e.g., "123456")
+    /// * `size` - Size to read (typically 1 for single byte)
+    pub fn read_parameter(&mut self, address: &str, size: u32) -> Result<u8, TelnetError> {
+        self.read_parameter_addr(parse_address(address)?, size)
+    }
+
+    /// Read a parameter value
+    ///
+    /// # Arguments
+    /// * `address` - SysEx address
+    /// * `size` - Size to read (typically 1 for single byte)
+    pub fn read_parameter_addr(&mut self, address: Address, size: u32) -> Result<u8, TelnetError> {
+        let cmd = Command::read(address, size)?;
+        let response = self.send_command(&cmd)?;
+        data_for(&cmd, address, response).map(|(_, value)| value)
+    }
+
+    /// Get the product model and firmware version
+    ///
+    /// # Returns
+    /// * `Result<(String, String), TelnetError>` - Product and version
+    pub fn get_version(&mut self) -> Result<(String, String), TelnetError> {
+        let cmd = Command::GetVersion;
+        match self.send_command(&cmd)? {
+            Response::Version { product, version } => Ok((product, version)),
+            Response::Error(e) => Err(TelnetError::device(&cmd, e)),
+            _ => Err(TelnetError::Protocol(RolandError::InvalidResponse)),
+        }
+    }
+
+    /// Drain the events queued so far
+    pub fn events(&mut self) -> impl Iterator<Item = DeviceEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Wait for the response to `command` until the timeout
+    ///
+    /// Returns `None` if none arrived.
+    fn read_response(&mut self, command: &Command) -> Result<Option<Response>, TelnetError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            while let Some(response) = self.decoder.decode() {
+                if let Some(response) = self.take_response(command, response?) {
+                    return Ok(Some(response));
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(deadline - now))?;
+            if !self.receive()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Return `response` if it answers `command`, queueing data for other
+    /// addresses as events and dropping other frames
+    fn take_response(&mut self, command: &Command, response: Response) -> Option<Response> {
+        // A late ACK of an earlier write can't be the answer to a read
+        let stray_ack = response == Response::Acknowledge
+            && !matches!(
+                command,
+                Command::WriteParameter { .. } | Command::WriteBlock { .. }
+            );
+        if !stray_ack && answers_with(command, &response, self.parse_options) {
+            return Some(response);
+        }
+        self.queue_event(response);
+        None
+    }
+
+    fn queue_event(&mut self, response: Response) {
+        match response {
+            Response::Data { address, value } => self
+                .events
+                .push_back(DeviceEvent::ParameterChanged { address, value }),
+            Response::DataBlock { address, data } => {
+                for (i, value) in data.into_iter().enumerate() {
+                    let address = crate::offset(address, i);
+                    self.events
+                        .push_back(DeviceEvent::ParameterChanged { address, value });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Receive one datagram, returning false on timeout
+    fn receive(&mut self) -> Result<bool, TelnetError> {
+        let mut buf = [0u8; 1500];
+        match self.socket.recv(&mut buf) {
+            Ok(n) => {
+                self.decoder.push(&buf[..n]);
+                Ok(true)
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(false),
+            // The device's host reported the port closed
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                Err(TelnetError::ConnectionRefused(e))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Queue what arrived since the last command, dropping stale answers
+    fn discard_pending(&mut self) -> Result<(), TelnetError> {
+        self.socket.set_nonblocking(true)?;
+        let result = loop {
+            match self.receive() {
+                Ok(true) => {}
+                Ok(false) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.socket.set_nonblocking(false)?;
+        result?;
+        while let Some(response) = self.decoder.decode() {
+            // Frames that don't parse are as stale as the rest
+            if let Ok(response) = response {
+                self.queue_event(response);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Fault, FaultPlan, MockDevice};
+    use roland_core::WriteAnswer;
+    use std::net::SocketAddr;
+
+    const ADDRESS: Address = Address::new(0x12, 0x34, 0x56);
+
+    fn connect(addr: SocketAddr) -> UdpClient {
+        let mut client = UdpClient::connect(&addr.ip().to_string(), addr.port()).unwrap();
+        client.set_timeout(Duration::from_millis(100));
+        client
+    }
+
+    #[test]
+    fn test_commands() {
+        let (addr, mock) = MockDevice::spawn_udp();
+        let mut client = connect(addr);
+        assert_eq!(
+            client.get_version().unwrap(),
+            ("VR-6HD".to_string(), "1.00".to_string())
+        );
+        client.write_parameter("123456", 0x42).unwrap();
+        assert_eq!(client.read_parameter("123456", 1).unwrap(), 0x42);
+
+        mock.inject_error(RolandError::OutOfRange);
+        assert!(matches!(
+            client.write_parameter_addr(ADDRESS, 0x01),
+            Err(TelnetError::Device {
+                error: RolandError::OutOfRange,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_lost_responses() {
+        let (addr, mock) = MockDevice::spawn_udp();
+        let mut plan = FaultPlan::new();
+        for n in [1, 2, 4, 5, 6] {
+            plan.add(n, Fault::Drop);
+        }
+        mock.set_fault_plan(plan);
+        let mut client = connect(addr);
+
+        // Sent three times, the third ACK arrives
+        client.write_parameter_addr(ADDRESS, 0x10).unwrap();
+        assert_eq!(mock.received().len(), 3);
+
+        // Every answer lost
+        assert!(matches!(
+            client.write_parameter_addr(ADDRESS, 0x11),
+            Err(TelnetError::Timeout)
+        ));
+        assert_eq!(mock.received().len(), 6);
+        assert_eq!(client.retries(), 2);
+
+        client.set_retries(0);
+        client
+            .send_unreliable(&Command::write_parameter(ADDRESS, 0x12))
+            .unwrap();
+        assert_eq!(client.read_parameter_addr(ADDRESS, 1).unwrap(), 0x12);
+    }
+
+    #[test]
+    fn test_late_answers_matched_by_address() {
+        let (addr, mock) = MockDevice::spawn_udp();
+        let other = Address::new(0x01, 0x00, 0x00);
+        mock.set_parameter(ADDRESS, 0x7F);
+        mock.set_parameter(other, 0x03);
+        let mut plan = FaultPlan::new();
+        plan.add(1, Fault::Delay(Duration::from_millis(150)));
+        mock.set_fault_plan(plan);
+        let mut client = connect(addr);
+
+        // The first answer is late, so the read is sent again and its
+        // answer comes in around the read of `other`
+        assert_eq!(client.read_parameter_addr(ADDRESS, 1).unwrap(), 0x7F);
+        assert_eq!(client.read_parameter_addr(other, 1).unwrap(), 0x03);
+        assert!(client.events().all(|event| event
+            == DeviceEvent::ParameterChanged {
+                address: ADDRESS,
+                value: 0x7F
+            }));
+    }
+
+    #[test]
+    fn test_dth_write_answer() {
+        let (addr, mock) = MockDevice::spawn_udp();
+        mock.set_dth_write_answer(true);
+        mock.set_write_filter(ADDRESS, |value| value.min(0x7F));
+        let mut client = connect(addr);
+        client.set_parse_options(ParseOptions {
+            write_answer: WriteAnswer::Dth,
+            ..ParseOptions::LENIENT
+        });
+
+        // Answered by the DTH, not sent again
+        client.write_parameter_addr(ADDRESS, 0x40).unwrap();
+        assert_eq!(mock.received().len(), 1);
+        assert_eq!(client.events().count(), 0);
+
+        client.set_parse_options(ParseOptions {
+            write_answer: WriteAnswer::DthSameValue,
+            ..ParseOptions::LENIENT
+        });
+        assert!(matches!(
+            client.write_parameter_addr(ADDRESS, 0xFF),
+            Err(TelnetError::WriteMismatch { .. })
+        ));
+
+        // By default the ACK never comes
+        client.set_parse_options(ParseOptions::LENIENT);
+        mock.clear_received();
+        assert!(matches!(
+            client.write_parameter_addr(ADDRESS, 0x01),
+            Err(TelnetError::Timeout)
+        ));
+        assert_eq!(mock.received().len(), 3);
+    }
+}
@@ -0,0 +1,458 @@
+//! Units of parameter values and their byte scales
+//!
+//! Many parameters hold a physical value, a level in dB or a time in ms,
+//! as a byte. A [`ParamScale`] describes how: which value the lowest and
+//! highest byte stand for, and whether the bytes between are spaced
+//! evenly ([`Curve::Linear`]) or follow a fader taper ([`Curve::Log`]).
+//! The unit types [`Percent`], [`Db`] and [`Millis`] convert through a
+//! scale with `to_device_byte` and `from_device_byte`.
+//!
+//! [`Quantity`] is a value with its unit, parsed from text like `75%`,
+//! `-10.5dB` or `250ms`. Parameters in a [`crate::param_map::ParameterMap`]
+//! with a scale take such values by name.
+
+pub use crate::audio::Db;
+use roland_core::RolandError;
+use std::fmt;
+
+/// How the bytes of a scale are spaced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Curve {
+    /// Every byte step is the same step in value
+    Linear,
+    /// The byte is proportional to the gain `10^(dB / 20)`, the taper of
+    /// an audio fader: fine steps near the top, coarse ones near the
+    /// bottom. Values are in dB, and `min` can be -INF.
+    Log,
+}
+
+/// Mapping between the values of a parameter and its bytes
+///
+/// Values are rounded to the nearest byte. Values outside `min..=max` are
+/// rejected with `OutOfRange`, or moved to the nearest end of the scale
+/// if it [clamps](ParamScale::clamped). A scale can have an
+/// [off byte](ParamScale::with_off) below its range, e.g. a closed fader;
+/// values more than half a step below `min` encode to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamScale {
+    /// Value of the `first` byte
+    pub min: f32,
+    /// Value of the `last` byte
+    pub max: f32,
+    /// Byte of `min`
+    pub first: u8,
+    /// Byte of `max`
+    pub last: u8,
+    /// Spacing of the bytes between
+    pub curve: Curve,
+    /// Byte for values below the scale, decoding to -INF
+    pub off: Option<u8>,
+    /// Clamp values outside `min..=max` instead of rejecting them
+    pub clamp: bool,
+}
+
+impl ParamScale {
+    /// Audio fader: -53.0 dB to +10.0 dB in 0.5 dB steps at bytes 1-127,
+    /// with 0 closing the fader
+    pub const FADER: ParamScale = ParamScale::linear(-53.0, 10.0, 1, 127)
+        .with_off(0)
+        .clamped();
+
+    /// Create a scale with evenly spaced bytes
+    pub const fn linear(min: f32, max: f32, first: u8, last: u8) -> Self {
+        Self {
+            min,
+            max,
+            first,
+            last,
+            curve: Curve::Linear,
+            off: None,
+            clamp: false,
+        }
+    }
+
+    /// Create a scale with a fader taper, see [`Curve::Log`]
+    pub const fn log(min: f32, max: f32, first: u8, last: u8) -> Self {
+        Self {
+            curve: Curve::Log,
+            ..Self::linear(min, max, first, last)
+        }
+    }
+
+    /// Clamp values outside the scale instead of rejecting them
+    pub const fn clamped(self) -> Self {
+        Self {
+            clamp: true,
+            ..self
+        }
+    }
+
+    /// Encode values below the scale as `byte`
+    pub const fn with_off(self, byte: u8) -> Self {
+        Self {
+            off: Some(byte),
+            ..self
+        }
+    }
+
+    /// Get the value of one byte step, for linear scales
+    pub fn step(&self) -> f32 {
+        (self.max - self.min) / self.steps()
+    }
+
+    /// Check that the ends of the scale are usable
+    pub fn is_valid(&self) -> bool {
+        let ends_ok = match self.curve {
+            Curve::Linear => self.min.is_finite() && self.max.is_finite(),
+            Curve::Log => !self.min.is_nan() && self.max.is_finite(),
+        };
+        ends_ok && self.min < self.max && self.first < self.last
+    }
+
+    /// Encode a value
+    ///
+    /// # Returns
+    /// * `Result<u8, RolandError>` - The byte, or `OutOfRange` for a value
+    ///   outside a scale that doesn't clamp, or for NaN
+    pub fn encode(&self, value: f32) -> Result<u8, RolandError> {
+        if value.is_nan() {
+            return self.off.ok_or(RolandError::OutOfRange);
+        }
+        let steps = self.steps_to(value);
+        if steps < -0.5 {
+            if let Some(off) = self.off {
+                return Ok(off);
+            }
+        }
+        let steps = if (0.0..=self.steps()).contains(&steps) {
+            steps.round()
+        } else if self.clamp {
+            steps.clamp(0.0, self.steps()).round()
+        } else {
+            return Err(RolandError::OutOfRange);
+        };
+        Ok(self.first + steps as u8)
+    }
+
+    /// Decode a byte
+    ///
+    /// # Returns
+    /// * `Result<f32, RolandError>` - The value, -INF for the off byte, or
+    ///   `InvalidValue` for a byte outside the scale
+    pub fn decode(&self, byte: u8) -> Result<f32, RolandError> {
+        if Some(byte) == self.off {
+            return Ok(f32::NEG_INFINITY);
+        }
+        if !(self.first..=self.last).contains(&byte) {
+            return Err(RolandError::InvalidValue);
+        }
+        let steps = (byte - self.first) as f32;
+        Ok(match self.curve {
+            Curve::Linear => self.min + steps * self.step(),
+            Curve::Log => {
+                let (low, high) = (gain(self.min), gain(self.max));
+                20.0 * (low + steps / self.steps() * (high - low)).log10()
+            }
+        })
+    }
+
+    /// Number of byte steps from `min` to `max`
+    fn steps(&self) -> f32 {
+        (self.last - self.first) as f32
+    }
+
+    /// Number of byte steps from `min` to a value, unrounded
+    fn steps_to(&self, value: f32) -> f32 {
+        match self.curve {
+            Curve::Linear => (value - self.min) / self.step(),
+            Curve::Log => {
+                let (low, high) = (gain(self.min), gain(self.max));
+                (gain(value) - low) / (high - low) * self.steps()
+            }
+        }
+    }
+}
+
+// A scale with a NaN end isn't valid, so equality is total on the scales
+// in use
+impl Eq for ParamScale {}
+
+/// Convert dB to a gain factor
+fn gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Percentage from 0 to 100
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Percent(u8);
+
+impl Percent {
+    /// Full scale
+    pub const MAX: Percent = Percent(100);
+
+    /// Create a percentage
+    ///
+    /// # Returns
+    /// * `Result<Percent, RolandError>` - The percentage, or `OutOfRange`
+    ///   above 100
+    pub fn new(value: u8) -> Result<Self, RolandError> {
+        if value > 100 {
+            return Err(RolandError::OutOfRange);
+        }
+        Ok(Self(value))
+    }
+
+    /// Get the percentage
+    pub fn value(self) -> u8 {
+        self.0
+    }
+
+    /// Encode the percentage on a scale
+    pub fn to_device_byte(self, scale: &ParamScale) -> Result<u8, RolandError> {
+        scale.encode(self.0 as f32)
+    }
+
+    /// Decode a byte on a scale, rounding to whole percent
+    ///
+    /// The off byte decodes to 0%.
+    pub fn from_device_byte(byte: u8, scale: &ParamScale) -> Result<Self, RolandError> {
+        let value = scale.decode(byte)?;
+        Ok(Self(value.round().clamp(0.0, 100.0) as u8))
+    }
+}
+
+impl Db {
+    /// Encode the level on a scale
+    pub fn to_device_byte(self, scale: &ParamScale) -> Result<u8, RolandError> {
+        scale.encode(self.0)
+    }
+
+    /// Decode a byte on a scale
+    pub fn from_device_byte(byte: u8, scale: &ParamScale) -> Result<Self, RolandError> {
+        scale.decode(byte).map(Db)
+    }
+}
+
+/// Time in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Millis(pub u32);
+
+impl Millis {
+    /// Encode the time on a scale
+    pub fn to_device_byte(self, scale: &ParamScale) -> Result<u8, RolandError> {
+        scale.encode(self.0 as f32)
+    }
+
+    /// Decode a byte on a scale, rounding to whole milliseconds
+    ///
+    /// The off byte decodes to 0 ms.
+    pub fn from_device_byte(byte: u8, scale: &ParamScale) -> Result<Self, RolandError> {
+        let value = scale.decode(byte)?;
+        Ok(Self(value.round().max(0.0) as u32))
+    }
+}
+
+/// Unit of a [`Quantity`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    /// Percent, `%`
+    Percent,
+    /// Decibels, `dB`
+    Db,
+    /// Milliseconds, `ms`
+    Millis,
+}
+
+impl Unit {
+    /// All units
+    pub const ALL: [Unit; 3] = [Unit::Percent, Unit::Db, Unit::Millis];
+
+    /// Get the unit symbol
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Percent => "%",
+            Unit::Db => "dB",
+            Unit::Millis => "ms",
+        }
+    }
+
+    /// Look up a unit by symbol, case-insensitively
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|unit| unit.symbol().eq_ignore_ascii_case(symbol))
+    }
+
+    /// Decode a byte on a scale as a value of this unit
+    pub fn decode(self, byte: u8, scale: &ParamScale) -> Result<Quantity, RolandError> {
+        Ok(match self {
+            Unit::Percent => Quantity::Percent(Percent::from_device_byte(byte, scale)?),
+            Unit::Db => Quantity::Db(Db::from_device_byte(byte, scale)?),
+            Unit::Millis => Quantity::Millis(Millis::from_device_byte(byte, scale)?),
+        })
+    }
+}
+
+/// Value with a unit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quantity {
+    /// Percentage
+    Percent(Percent),
+    /// Level
+    Db(Db),
+    /// Time
+    Millis(Millis),
+}
+
+impl Quantity {
+    /// Parse a number followed by a unit symbol, e.g. `75%`, `-10.5dB`,
+    /// `-inf dB` or `250ms`
+    ///
+    /// Percentages and times must be whole numbers.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let split = text
+            .rfind(|c: char| c.is_ascii_digit() || c == '.' || c == 'f' || c == 'F')
+            .map_or(0, |i| i + 1);
+        let (number, symbol) = text.split_at(split);
+        let number = number.trim();
+        Some(match Unit::from_symbol(symbol.trim())? {
+            Unit::Percent => Quantity::Percent(Percent::new(number.parse().ok()?).ok()?),
+            Unit::Db => Quantity::Db(Db(number.parse().ok().filter(|v: &f32| !v.is_nan())?)),
+            Unit::Millis => Quantity::Millis(Millis(number.parse().ok()?)),
+        })
+    }
+
+    /// Get the unit
+    pub fn unit(self) -> Unit {
+        match self {
+            Quantity::Percent(_) => Unit::Percent,
+            Quantity::Db(_) => Unit::Db,
+            Quantity::Millis(_) => Unit::Millis,
+        }
+    }
+
+    /// Encode the value on a scale
+    pub fn to_device_byte(self, scale: &ParamScale) -> Result<u8, RolandError> {
+        match self {
+            Quantity::Percent(value) => value.to_device_byte(scale),
+            Quantity::Db(value) => value.to_device_byte(scale),
+            Quantity::Millis(value) => value.to_device_byte(scale),
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantity::Percent(value) => write!(f, "{}%", value.0),
+            Quantity::Db(value) => write!(f, "{}dB", value.0),
+            Quantity::Millis(value) => write!(f, "{}ms", value.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear_rounding() {
+        let scale = ParamScale::linear(10.0, 1000.0, 1, 100);
+        assert_eq!(scale.step(), 10.0);
+        assert_eq!(Millis(10).to_device_byte(&scale).unwrap(), 1);
+        assert_eq!(Millis(24).to_device_byte(&scale).unwrap(), 2);
+        assert_eq!(Millis(25).to_device_byte(&scale).unwrap(), 3);
+        assert_eq!(Millis(1000).to_device_byte(&scale).unwrap(), 100);
+        assert_eq!(Millis::from_device_byte(3, &scale).unwrap(), Millis(30));
+        assert_eq!(
+            Millis::from_device_byte(0, &scale),
+            Err(RolandError::InvalidValue)
+        );
+
+        let percent = ParamScale::linear(0.0, 100.0, 0, 255);
+        for value in 0..=100 {
+            let byte = Percent(value).to_device_byte(&percent).unwrap();
+            assert_eq!(Percent::from_device_byte(byte, &percent).unwrap().0, value);
+        }
+        assert_eq!(Percent::MAX.to_device_byte(&percent).unwrap(), 255);
+        assert_eq!(Percent::new(101), Err(RolandError::OutOfRange));
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let strict = ParamScale::linear(-15.0, 15.0, 0, 60);
+        assert_eq!(
+            Db(-15.1).to_device_byte(&strict),
+            Err(RolandError::OutOfRange)
+        );
+        assert_eq!(
+            Db(15.1).to_device_byte(&strict),
+            Err(RolandError::OutOfRange)
+        );
+        assert_eq!(
+            Db(f32::NAN).to_device_byte(&strict),
+            Err(RolandError::OutOfRange)
+        );
+
+        let clamped = strict.clamped();
+        assert_eq!(Db(-20.0).to_device_byte(&clamped).unwrap(), 0);
+        assert_eq!(Db(20.0).to_device_byte(&clamped).unwrap(), 60);
+
+        // Within half a step below `min` clamps, further down is off
+        let fader = ParamScale::FADER;
+        assert_eq!(Db(-53.2).to_device_byte(&fader).unwrap(), 1);
+        assert_eq!(Db(-53.3).to_device_byte(&fader).unwrap(), 0);
+        assert_eq!(Db::NEG_INFINITY.to_device_byte(&fader).unwrap(), 0);
+        assert_eq!(Db::from_device_byte(0, &fader).unwrap(), Db::NEG_INFINITY);
+        assert_eq!(
+            ParamScale::linear(-53.0, 10.0, 1, 127)
+                .with_off(0)
+                .encode(20.0),
+            Err(RolandError::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn test_log_taper() {
+        let scale = ParamScale::log(f32::NEG_INFINITY, 10.0, 0, 255);
+        assert!(scale.is_valid());
+        assert_eq!(Db::NEG_INFINITY.to_device_byte(&scale).unwrap(), 0);
+        assert_eq!(Db(10.0).to_device_byte(&scale).unwrap(), 255);
+        // Unity gain sits at 1/sqrt(10) of the gain at +10 dB
+        assert_eq!(Db::ZERO.to_device_byte(&scale).unwrap(), 81);
+        assert!((Db::from_device_byte(81, &scale).unwrap().0 - 0.0).abs() < 0.1);
+
+        // Steps get coarser towards the bottom
+        let db = |byte| Db::from_device_byte(byte, &scale).unwrap().0;
+        assert!(db(255) - db(254) < 0.05);
+        assert!(db(2) - db(1) > 5.0);
+        for byte in 1..=255 {
+            assert!(db(byte) > db(byte - 1));
+            assert_eq!(Db(db(byte)).to_device_byte(&scale).unwrap(), byte);
+        }
+
+        assert!(!ParamScale::linear(f32::NEG_INFINITY, 10.0, 0, 255).is_valid());
+        assert!(!ParamScale::log(0.0, 10.0, 5, 5).is_valid());
+    }
+
+    #[test]
+    fn test_quantity_parse() {
+        assert_eq!(Quantity::parse("-10.5dB"), Some(Quantity::Db(Db(-10.5))));
+        assert_eq!(
+            Quantity::parse("-inf dB"),
+            Some(Quantity::Db(Db::NEG_INFINITY))
+        );
+        assert_eq!(Quantity::parse("75%"), Some(Quantity::Percent(Percent(75))));
+        assert_eq!(
+            Quantity::parse(" 250 MS "),
+            Some(Quantity::Millis(Millis(250)))
+        );
+        for text in ["75", "101%", "7.5%", "dB", "NaN dB", "-5ms", "3 Hz"] {
+            assert_eq!(Quantity::parse(text), None, "{}", text);
+        }
+        for text in ["-10.5dB", "-infdB", "75%", "250ms"] {
+            assert_eq!(Quantity::parse(text).unwrap().to_string(), text);
+        }
+    }
+}
@@ -0,0 +1,739 @@
+//! High-level video switcher control
+
+use crate::units::Percent;
+use crate::{TelnetClient, TelnetError};
+use roland_core::{Address, RolandError};
+
+/// Video input channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VideoInput {
+    /// HDMI 1
+    Hdmi1,
+    /// HDMI 2
+    Hdmi2,
+    /// HDMI 3
+    Hdmi3,
+    /// HDMI 4
+    Hdmi4,
+    /// STILL 1
+    Still1,
+    /// STILL 2
+    Still2,
+}
+
+impl VideoInput {
+    /// All video inputs, in channel order
+    pub const ALL: [VideoInput; 6] = [
+        VideoInput::Hdmi1,
+        VideoInput::Hdmi2,
+        VideoInput::Hdmi3,
+        VideoInput::Hdmi4,
+        VideoInput::Still1,
+        VideoInput::Still2,
+    ];
+
+    /// Get the parameter value selecting this input (0-5)
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// Get the input selected by a parameter value
+    ///
+    /// Returns `None` for values outside 0-5.
+    pub fn from_index(index: u8) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+}
+
+/// Transition effect used by AUTO TAKE
+///
+/// Values this crate doesn't know, e.g. from newer firmware, decode to
+/// [`TransitionType::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TransitionType {
+    /// Dissolve, see [`MixEffect`]
+    Mix,
+    /// Wipe, see [`WipePattern`]
+    Wipe,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl TransitionType {
+    /// All documented transition types
+    pub const ALL: [TransitionType; 2] = [TransitionType::Mix, TransitionType::Wipe];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => TransitionType::Mix,
+            1 => TransitionType::Wipe,
+            value => TransitionType::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            TransitionType::Mix => 0,
+            TransitionType::Wipe => 1,
+            TransitionType::Other(value) => value,
+        }
+    }
+}
+
+/// Wipe pattern
+///
+/// Values this crate doesn't know decode to [`WipePattern::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum WipePattern {
+    /// Left to right
+    Horizontal,
+    /// Top to bottom
+    Vertical,
+    /// From the upper left corner
+    UpperLeft,
+    /// From the upper right corner
+    UpperRight,
+    /// From the lower left corner
+    LowerLeft,
+    /// From the lower right corner
+    LowerRight,
+    /// From the horizontal center outwards
+    HorizontalCenter,
+    /// From the vertical center outwards
+    VerticalCenter,
+    /// Growing box
+    Box,
+    /// Growing circle
+    Circle,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl WipePattern {
+    /// All documented wipe patterns, in parameter value order
+    pub const ALL: [WipePattern; 10] = [
+        WipePattern::Horizontal,
+        WipePattern::Vertical,
+        WipePattern::UpperLeft,
+        WipePattern::UpperRight,
+        WipePattern::LowerLeft,
+        WipePattern::LowerRight,
+        WipePattern::HorizontalCenter,
+        WipePattern::VerticalCenter,
+        WipePattern::Box,
+        WipePattern::Circle,
+    ];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => WipePattern::Horizontal,
+            1 => WipePattern::Vertical,
+            2 => WipePattern::UpperLeft,
+            3 => WipePattern::UpperRight,
+            4 => WipePattern::LowerLeft,
+            5 => WipePattern::LowerRight,
+            6 => WipePattern::HorizontalCenter,
+            7 => WipePattern::VerticalCenter,
+            8 => WipePattern::Box,
+            9 => WipePattern::Circle,
+            value => WipePattern::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            WipePattern::Horizontal => 0,
+            WipePattern::Vertical => 1,
+            WipePattern::UpperLeft => 2,
+            WipePattern::UpperRight => 3,
+            WipePattern::LowerLeft => 4,
+            WipePattern::LowerRight => 5,
+            WipePattern::HorizontalCenter => 6,
+            WipePattern::VerticalCenter => 7,
+            WipePattern::Box => 8,
+            WipePattern::Circle => 9,
+            WipePattern::Other(value) => value,
+        }
+    }
+}
+
+/// Dissolve curve of the MIX transition
+///
+/// Values this crate doesn't know decode to [`MixEffect::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum MixEffect {
+    /// Linear crossfade
+    Mix,
+    /// Full additive mix: both images at full level mid-transition
+    Fam,
+    /// Non-additive mix: the brighter pixel of the two images wins
+    Nam,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl MixEffect {
+    /// All documented mix effects
+    pub const ALL: [MixEffect; 3] = [MixEffect::Mix, MixEffect::Fam, MixEffect::Nam];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => MixEffect::Mix,
+            1 => MixEffect::Fam,
+            2 => MixEffect::Nam,
+            value => MixEffect::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            MixEffect::Mix => 0,
+            MixEffect::Fam => 1,
+            MixEffect::Nam => 2,
+            MixEffect::Other(value) => value,
+        }
+    }
+}
+
+/// Video sent to the USB output
+///
+/// Values this crate doesn't know decode to [`UsbVideoSource::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum UsbVideoSource {
+    /// Program output
+    Program,
+    /// AUX bus
+    Aux,
+    /// Multi-view of all inputs
+    MultiView,
+    /// Value not known to this crate
+    Other(u8),
+}
+
+impl UsbVideoSource {
+    /// All documented sources
+    pub const ALL: [UsbVideoSource; 3] = [
+        UsbVideoSource::Program,
+        UsbVideoSource::Aux,
+        UsbVideoSource::MultiView,
+    ];
+
+    /// Decode a parameter value
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0 => UsbVideoSource::Program,
+            1 => UsbVideoSource::Aux,
+            2 => UsbVideoSource::MultiView,
+            value => UsbVideoSource::Other(value),
+        }
+    }
+
+    /// Get the parameter value
+    pub fn value(self) -> u8 {
+        match self {
+            UsbVideoSource::Program => 0,
+            UsbVideoSource::Aux => 1,
+            UsbVideoSource::MultiView => 2,
+            UsbVideoSource::Other(value) => value,
+        }
+    }
+}
+
+/// Video switcher facade
+///
+/// Wraps a client to switch video without dealing with addresses.
+/// Addresses are resolved through the client's
+/// [`crate::profile::Profile`].
+///
+/// # Example
+/// ```no_run
+/// use roland_rs::video::{VideoInput, VideoSwitcher};
+/// use roland_rs::TelnetClient;
+///
+/// let mut client = TelnetClient::connect("192.168.1.100", 23)?;
+/// let mut switcher = VideoSwitcher::new(&mut client);
+/// switcher.select_preset(VideoInput::Hdmi2)?;
+/// switcher.set_transition_time_ms(1500)?;
+/// switcher.auto_take()?;
+/// # Ok::<(), roland_rs::TelnetError>(())
+/// ```
+pub struct VideoSwitcher<'a> {
+    client: &'a mut TelnetClient,
+}
+
+impl<'a> VideoSwitcher<'a> {
+    /// Maximum transition time in milliseconds
+    pub const MAX_TRANSITION_TIME_MS: u16 = 4000;
+
+    /// Create a facade for a connected client
+    pub fn new(client: &'a mut TelnetClient) -> Self {
+        Self { client }
+    }
+
+    /// Select the program (PGM) input, switching immediately
+    pub fn select_program(&mut self, input: VideoInput) -> Result<(), TelnetError> {
+        let address = self.address("video.pgm_select")?;
+        self.client.write_parameter_addr(address, input.index())
+    }
+
+    /// Select the preset (PST) input
+    pub fn select_preset(&mut self, input: VideoInput) -> Result<(), TelnetError> {
+        let address = self.address("video.pst_select")?;
+        self.client.write_parameter_addr(address, input.index())
+    }
+
+    /// Get the program (PGM) input
+    pub fn program(&mut self) -> Result<VideoInput, TelnetError> {
+        let address = self.address("video.pgm_select")?;
+        self.read_input(address)
+    }
+
+    /// Get the preset (PST) input
+    pub fn preset(&mut self) -> Result<VideoInput, TelnetError> {
+        let address = self.address("video.pst_select")?;
+        self.read_input(address)
+    }
+
+    /// Switch the preset to program immediately
+    pub fn cut(&mut self) -> Result<(), TelnetError> {
+        let address = self.address("video.cut")?;
+        self.client.write_parameter_addr(address, 1)
+    }
+
+    /// Switch the preset to program using the transition effect
+    pub fn auto_take(&mut self) -> Result<(), TelnetError> {
+        let address = self.address("video.auto_take")?;
+        self.client.write_parameter_addr(address, 1)
+    }
+
+    /// Set the transition time
+    ///
+    /// The device uses 100 ms steps, so `ms` is rounded to the nearest
+    /// step. Times above [`VideoSwitcher::MAX_TRANSITION_TIME_MS`] fail
+    /// with `OutOfRange` without sending anything.
+    pub fn set_transition_time_ms(&mut self, ms: u16) -> Result<(), TelnetError> {
+        if ms > Self::MAX_TRANSITION_TIME_MS {
+            return Err(TelnetError::Protocol(RolandError::OutOfRange));
+        }
+        let steps = ((ms + 50) / 100) as u8;
+        let address = self.address("video.transition_time")?;
+        self.client.write_parameter_addr(address, steps)
+    }
+
+    /// Get the transition time in milliseconds
+    pub fn transition_time_ms(&mut self) -> Result<u16, TelnetError> {
+        let address = self.address("video.transition_time")?;
+        let steps = self.client.read_parameter_addr(address, 1)?;
+        Ok(steps as u16 * 100)
+    }
+
+    /// Set the transition effect used by AUTO TAKE
+    pub fn set_transition_type(&mut self, kind: TransitionType) -> Result<(), TelnetError> {
+        let address = self.address("video.transition_type")?;
+        self.client.write_parameter_addr(address, kind.value())
+    }
+
+    /// Get the transition effect used by AUTO TAKE
+    pub fn transition_type(&mut self) -> Result<TransitionType, TelnetError> {
+        let address = self.address("video.transition_type")?;
+        let value = self.client.read_parameter_addr(address, 1)?;
+        Ok(TransitionType::from_value(value))
+    }
+
+    /// Set the pattern of the WIPE transition
+    pub fn set_wipe_pattern(&mut self, pattern: WipePattern) -> Result<(), TelnetError> {
+        let address = self.address("video.wipe_pattern")?;
+        self.client.write_parameter_addr(address, pattern.value())
+    }
+
+    /// Get the pattern of the WIPE transition
+    pub fn wipe_pattern(&mut self) -> Result<WipePattern, TelnetError> {
+        let address = self.address("video.wipe_pattern")?;
+        let value = self.client.read_parameter_addr(address, 1)?;
+        Ok(WipePattern::from_value(value))
+    }
+
+    /// Set the dissolve curve of the MIX transition
+    pub fn set_mix_effect(&mut self, effect: MixEffect) -> Result<(), TelnetError> {
+        let address = self.address("video.mix_effect")?;
+        self.client.write_parameter_addr(address, effect.value())
+    }
+
+    /// Get the dissolve curve of the MIX transition
+    pub fn mix_effect(&mut self) -> Result<MixEffect, TelnetError> {
+        let address = self.address("video.mix_effect")?;
+        let value = self.client.read_parameter_addr(address, 1)?;
+        Ok(MixEffect::from_value(value))
+    }
+
+    /// Set the video sent to the USB output
+    pub fn set_usb_video_source(&mut self, source: UsbVideoSource) -> Result<(), TelnetError> {
+        let address = self.address("video.usb_output_source")?;
+        self.client.write_parameter_addr(address, source.value())
+    }
+
+    /// Get the video sent to the USB output
+    pub fn usb_video_source(&mut self) -> Result<UsbVideoSource, TelnetError> {
+        let address = self.address("video.usb_output_source")?;
+        let value = self.client.read_parameter_addr(address, 1)?;
+        Ok(UsbVideoSource::from_value(value))
+    }
+
+    /// Check if a transition is in progress
+    pub fn transition_in_progress(&mut self) -> Result<bool, TelnetError> {
+        let address = self.address("video.transition_status")?;
+        Ok(self.client.read_parameter_addr(address, 1)? != 0)
+    }
+
+    /// Let [`VideoSwitcher::set_transition_position`] drive the transition
+    pub fn begin_manual_transition(&mut self) -> Result<(), TelnetError> {
+        let address = self.address("video.manual_transition")?;
+        self.client.write_parameter_addr(address, 1)
+    }
+
+    /// Move the transition, like a T-bar
+    ///
+    /// Meant to be called for every move of an external fader: with a
+    /// rate limit and coalescing set on the client, positions the rate
+    /// limit would delay are held back and only the latest is sent.
+    /// Takes effect between [`VideoSwitcher::begin_manual_transition`] and
+    /// [`VideoSwitcher::end_manual_transition`].
+    pub fn set_transition_position(&mut self, position: Percent) -> Result<(), TelnetError> {
+        let address = self.address("video.transition_position")?;
+        self.client.write_parameter_addr(address, position.value())
+    }
+
+    /// Move the transition without waiting for the ACK
+    ///
+    /// See [`TelnetClient::write_parameter_nowait`]; errors are reported
+    /// by [`TelnetClient::drain_write_errors`]. Positions are coalesced
+    /// like those of [`VideoSwitcher::set_transition_position`].
+    pub fn set_transition_position_nowait(&mut self, position: Percent) -> Result<(), TelnetError> {
+        let address = self.address("video.transition_position")?;
+        self.client
+            .write_parameter_nowait(address, position.value())
+    }
+
+    /// Hand the transition back to AUTO TAKE
+    ///
+    /// Held-back and unacknowledged positions are sent and acknowledged
+    /// first, so the last position has taken effect before manual mode
+    /// is turned off. This waits for the rate limit rather than holding
+    /// the write back.
+    pub fn end_manual_transition(&mut self) -> Result<(), TelnetError> {
+        self.client.flush_writes()?;
+        self.client.wait_write_acks()?;
+        let address = self.address("video.manual_transition")?;
+        self.client.write_parameter_addr(address, 0)?;
+        self.client.flush_writes()
+    }
+
+    /// Freeze or unfreeze the program output
+    pub fn freeze(&mut self, frozen: bool) -> Result<(), TelnetError> {
+        let address = self.address("video.freeze")?;
+        self.client.write_parameter_addr(address, frozen as u8)
+    }
+
+    /// Freeze the program output unless a transition is in progress
+    ///
+    /// Freezing mid-transition holds a mix of both inputs. This reads the
+    /// transition status first and fails with `TransitionInProgress`
+    /// without freezing if one is running. Unfreezing is never refused.
+    pub fn freeze_when_idle(&mut self, frozen: bool) -> Result<(), TelnetError> {
+        if frozen && self.transition_in_progress()? {
+            return Err(TelnetError::TransitionInProgress);
+        }
+        self.freeze(frozen)
+    }
+
+    /// Check if the program output is frozen
+    pub fn freeze_status(&mut self) -> Result<bool, TelnetError> {
+        let address = self.address("video.freeze")?;
+        Ok(self.client.read_parameter_addr(address, 1)? != 0)
+    }
+
+    /// Freeze or unfreeze an HDMI input wherever it is shown
+    ///
+    /// Stills fail with `Invalid` without sending anything.
+    pub fn freeze_input(&mut self, input: VideoInput, frozen: bool) -> Result<(), TelnetError> {
+        let address = self.input_freeze_address(input)?;
+        self.client.write_parameter_addr(address, frozen as u8)
+    }
+
+    /// Check if an HDMI input is frozen
+    pub fn input_freeze_status(&mut self, input: VideoInput) -> Result<bool, TelnetError> {
+        let address = self.input_freeze_address(input)?;
+        Ok(self.client.read_parameter_addr(address, 1)? != 0)
+    }
+
+    fn input_freeze_address(&self, input: VideoInput) -> Result<Address, TelnetError> {
+        let name = match input {
+            VideoInput::Hdmi1 => "video.hdmi1.freeze",
+            VideoInput::Hdmi2 => "video.hdmi2.freeze",
+            VideoInput::Hdmi3 => "video.hdmi3.freeze",
+            VideoInput::Hdmi4 => "video.hdmi4.freeze",
+            VideoInput::Still1 | VideoInput::Still2 => {
+                return Err(TelnetError::Protocol(RolandError::Invalid))
+            }
+        };
+        self.address(name)
+    }
+
+    /// Resolve a parameter through the client's profile
+    fn address(&self, name: &str) -> Result<Address, TelnetError> {
+        self.client.profile().address(name)
+    }
+
+    fn read_input(&mut self, address: Address) -> Result<VideoInput, TelnetError> {
+        let value = self.client.read_parameter_addr(address, 1)?;
+        VideoInput::from_index(value).ok_or(TelnetError::Protocol(RolandError::InvalidValue))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::params::video;
+    use roland_core::Command;
+
+    #[test]
+    fn test_video_input_index() {
+        for (i, input) in VideoInput::ALL.iter().enumerate() {
+            assert_eq!(input.index(), i as u8);
+            assert_eq!(VideoInput::from_index(i as u8), Some(*input));
+        }
+        assert_eq!(VideoInput::from_index(6), None);
+    }
+
+    #[test]
+    fn test_preset_then_take() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut switcher = VideoSwitcher::new(&mut client);
+
+        switcher.select_preset(VideoInput::Hdmi2).unwrap();
+        switcher.auto_take().unwrap();
+        switcher.select_program(VideoInput::Still1).unwrap();
+        switcher.cut().unwrap();
+
+        let encoded: Vec<String> = mock.received().iter().map(Command::encode).collect();
+        assert_eq!(
+            encoded,
+            vec![
+                "DTH:010001,01;",
+                "DTH:010011,01;",
+                "DTH:010000,04;",
+                "DTH:010010,01;",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_inputs() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(video::PGM_SELECT, 3);
+        mock.set_parameter(video::PST_SELECT, 9);
+        let mut client = connect(addr);
+        let mut switcher = VideoSwitcher::new(&mut client);
+
+        assert_eq!(switcher.program().unwrap(), VideoInput::Hdmi4);
+        assert!(matches!(
+            switcher.preset(),
+            Err(TelnetError::Protocol(RolandError::InvalidValue))
+        ));
+    }
+
+    #[test]
+    fn test_transition_time() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut switcher = VideoSwitcher::new(&mut client);
+
+        switcher.set_transition_time_ms(1450).unwrap();
+        assert_eq!(mock.parameter(video::TRANSITION_TIME), Some(15));
+        assert_eq!(switcher.transition_time_ms().unwrap(), 1500);
+
+        assert!(matches!(
+            switcher.set_transition_time_ms(4001),
+            Err(TelnetError::Protocol(RolandError::OutOfRange))
+        ));
+        assert_eq!(mock.received().len(), 2);
+    }
+
+    #[test]
+    fn test_effect_values() {
+        assert_eq!(TransitionType::ALL.map(TransitionType::value), [0, 1]);
+        assert_eq!(
+            WipePattern::ALL.map(WipePattern::value),
+            [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]
+        );
+        assert_eq!(MixEffect::ALL.map(MixEffect::value), [0, 1, 2]);
+        assert_eq!(UsbVideoSource::ALL.map(UsbVideoSource::value), [0, 1, 2]);
+        for value in 0..=255 {
+            assert_eq!(TransitionType::from_value(value).value(), value);
+            assert_eq!(WipePattern::from_value(value).value(), value);
+            assert_eq!(MixEffect::from_value(value).value(), value);
+            assert_eq!(UsbVideoSource::from_value(value).value(), value);
+        }
+        assert_eq!(TransitionType::from_value(2), TransitionType::Other(2));
+        assert_eq!(WipePattern::from_value(10), WipePattern::Other(10));
+        assert_eq!(MixEffect::from_value(3), MixEffect::Other(3));
+    }
+
+    #[test]
+    fn test_transition_effects() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut switcher = VideoSwitcher::new(&mut client);
+
+        switcher.set_transition_type(TransitionType::Wipe).unwrap();
+        switcher.set_wipe_pattern(WipePattern::Circle).unwrap();
+        switcher.set_mix_effect(MixEffect::Nam).unwrap();
+        assert_eq!(switcher.transition_type().unwrap(), TransitionType::Wipe);
+        assert_eq!(switcher.wipe_pattern().unwrap(), WipePattern::Circle);
+        assert_eq!(switcher.mix_effect().unwrap(), MixEffect::Nam);
+
+        // Unknown values from the device don't fail
+        mock.set_parameter(video::TRANSITION_TYPE, 7);
+        assert_eq!(
+            switcher.transition_type().unwrap(),
+            TransitionType::Other(7)
+        );
+    }
+
+    #[test]
+    fn test_usb_video_source() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut switcher = VideoSwitcher::new(&mut client);
+
+        for source in UsbVideoSource::ALL
+            .into_iter()
+            .chain([UsbVideoSource::Other(5)])
+        {
+            switcher.set_usb_video_source(source).unwrap();
+            assert_eq!(
+                mock.parameter(video::USB_OUTPUT_SOURCE),
+                Some(source.value())
+            );
+            assert_eq!(switcher.usb_video_source().unwrap(), source);
+        }
+    }
+
+    #[test]
+    fn test_freeze() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut switcher = VideoSwitcher::new(&mut client);
+
+        switcher.freeze(true).unwrap();
+        assert!(switcher.freeze_status().unwrap());
+        switcher.freeze(false).unwrap();
+        assert!(!switcher.freeze_status().unwrap());
+
+        switcher.freeze_input(VideoInput::Hdmi3, true).unwrap();
+        assert_eq!(mock.parameter(video::input_freeze(2)), Some(1));
+        assert!(switcher.input_freeze_status(VideoInput::Hdmi3).unwrap());
+        assert!(!switcher.input_freeze_status(VideoInput::Hdmi1).unwrap());
+
+        mock.clear_received();
+        assert!(matches!(
+            switcher.freeze_input(VideoInput::Still1, true),
+            Err(TelnetError::Protocol(RolandError::Invalid))
+        ));
+        assert!(mock.received().is_empty());
+    }
+
+    #[test]
+    fn test_freeze_when_idle() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_parameter(video::TRANSITION_STATUS, 1);
+        let mut client = connect(addr);
+        let mut switcher = VideoSwitcher::new(&mut client);
+
+        let err = switcher.freeze_when_idle(true).unwrap_err();
+        assert!(matches!(err, TelnetError::TransitionInProgress));
+        assert_eq!(err.to_string(), "A transition is in progress");
+        assert_eq!(mock.parameter(video::FREEZE), None);
+        // Unfreezing doesn't wait for the transition
+        switcher.freeze_when_idle(false).unwrap();
+        assert_eq!(mock.parameter(video::FREEZE), Some(0));
+
+        mock.set_parameter(video::TRANSITION_STATUS, 0);
+        switcher.freeze_when_idle(true).unwrap();
+        assert_eq!(mock.parameter(video::FREEZE), Some(1));
+    }
+
+    #[test]
+    fn test_manual_transition() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let mut switcher = VideoSwitcher::new(&mut client);
+
+        switcher.begin_manual_transition().unwrap();
+        switcher
+            .set_transition_position(Percent::new(40).unwrap())
+            .unwrap();
+        switcher
+            .set_transition_position_nowait(Percent::MAX)
+            .unwrap();
+        switcher.end_manual_transition().unwrap();
+
+        let encoded: Vec<String> = mock.received().iter().map(Command::encode).collect();
+        assert_eq!(
+            encoded,
+            vec![
+                "DTH:010013,01;",
+                "DTH:010014,28;",
+                "DTH:010014,64;",
+                "DTH:010013,00;",
+            ]
+        );
+        assert_eq!(client.pending_write_acks(), 0);
+    }
+
+    #[test]
+    fn test_transition_sweep_coalesced() {
+        let (addr, mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        client.set_rate_limit(20);
+        client.set_coalescing(true);
+        let mut switcher = VideoSwitcher::new(&mut client);
+
+        switcher.begin_manual_transition().unwrap();
+        for position in 0..=100 {
+            switcher
+                .set_transition_position(Percent::new(position).unwrap())
+                .unwrap();
+        }
+        switcher.end_manual_transition().unwrap();
+
+        // Positions written faster than the limit collapse to the last one
+        let positions: Vec<u8> = mock
+            .received()
+            .iter()
+            .filter_map(|command| match command {
+                Command::WriteParameter { address, value }
+                    if *address == video::TRANSITION_POSITION =>
+                {
+                    Some(*value)
+                }
+                _ => None,
+            })
+            .collect();
+        assert!(positions.len() < 5, "{:?}", positions);
+        assert_eq!(positions.last(), Some(&100));
+        assert_eq!(mock.parameter(video::MANUAL_TRANSITION), Some(0));
+    }
+}
@@ -0,0 +1,121 @@
+//! Raw wire logging for debugging
+//!
+//! [`TelnetClient::set_wire_logger`] hands every chunk of bytes written to
+//! or read from the connection to a callback, exactly as it went over the
+//! wire: control characters, Telnet negotiation and partial frames
+//! included.
+
+use crate::TelnetClient;
+use std::sync::{Arc, Mutex};
+
+/// Direction of bytes on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Written by the client
+    Sent,
+    /// Read from the device
+    Received,
+}
+
+type Logger = Box<dyn FnMut(Direction, &[u8]) + Send>;
+
+/// Logger slot shared with the subscription reader thread
+#[derive(Clone, Default)]
+pub(crate) struct WireLog(Arc<Mutex<Option<Logger>>>);
+
+impl WireLog {
+    /// Hand bytes to the logger, if one is set
+    pub(crate) fn log(&self, direction: Direction, bytes: &[u8]) {
+        if let Some(logger) = self.0.lock().unwrap().as_mut() {
+            logger(direction, bytes);
+        }
+    }
+
+    fn set(&self, logger: Option<Logger>) {
+        *self.0.lock().unwrap() = logger;
+    }
+}
+
+impl TelnetClient {
+    /// Log the raw bytes of the connection
+    ///
+    /// `logger` is called with every chunk written or read, before any
+    /// decoding. Received chunks are split wherever the socket reads
+    /// happened to split them, not at frame boundaries. Replaces any
+    /// previous logger.
+    ///
+    /// While subscribed, the logger is also called from the reader thread.
+    ///
+    /// # Arguments
+    /// * `logger` - Called with the direction and bytes of each chunk
+    pub fn set_wire_logger(&mut self, logger: impl FnMut(Direction, &[u8]) + Send + 'static) {
+        self.wire.set(Some(Box::new(logger)));
+    }
+
+    /// Stop logging the raw bytes of the connection
+    pub fn clear_wire_logger(&mut self) {
+        self.wire.set(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use roland_core::Address;
+
+    type Log = Arc<Mutex<Vec<(Direction, Vec<u8>)>>>;
+
+    fn collect(client: &mut TelnetClient) -> Log {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let collector = Arc::clone(&log);
+        client.set_wire_logger(move |direction, bytes| {
+            collector.lock().unwrap().push((direction, bytes.to_vec()));
+        });
+        log
+    }
+
+    #[test]
+    fn test_write_and_ack() {
+        let (addr, _mock) = MockDevice::spawn();
+        let mut client = connect(addr);
+        let log = collect(&mut client);
+
+        client.write_parameter("123456", 0x01).unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                (Direction::Sent, b"DTH:123456,01;".to_vec()),
+                (Direction::Received, b"\x06".to_vec()),
+            ]
+        );
+
+        client.clear_wire_logger();
+        client.write_parameter("123456", 0x02).unwrap();
+        assert_eq!(log.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_negotiation_and_subscription() {
+        let (addr, mock) = MockDevice::spawn();
+        mock.set_preamble(b"\xFF\xFD\x01");
+        let mut client = connect(addr);
+        let log = collect(&mut client);
+
+        client.subscribe(&[], |_, _| {}).unwrap();
+        mock.inject_unsolicited(Address::new(0x05, 0x00, 0x00), 0x40);
+        client.write_parameter("123456", 0x01).unwrap();
+        client.unsubscribe().unwrap();
+
+        let log = log.lock().unwrap();
+        let received: Vec<u8> = log
+            .iter()
+            .filter(|(direction, _)| *direction == Direction::Received)
+            .flat_map(|(_, bytes)| bytes.clone())
+            .collect();
+        // Raw bytes, before the IAC sequence is stripped
+        assert_eq!(&received[..3], b"\xFF\xFD\x01");
+        assert!(log.contains(&(Direction::Sent, b"\xFF\xFC\x01".to_vec())));
+        assert!(log.contains(&(Direction::Sent, b"DTH:123456,01;".to_vec())));
+    }
+}
@@ -0,0 +1,611 @@
+//! WebSocket bridge (requires the `ws` feature)
+//!
+//! [`WsBridge`] pushes parameter changes to browsers, e.g. for a tally or
+//! mixer dashboard. It subscribes to a set of watched addresses and
+//! broadcasts every change the device reports as a JSON text message:
+//!
+//! ```text
+//! {"address":"123456","value":5}
+//! ```
+//!
+//! Messages of the same shape from a browser are written to the device; a
+//! write that fails is answered with `{"error":"<description>"}`. A newly
+//! connected browser gets the current value of every watched address, and
+//! every change from the moment it connected, so none falls in between.
+//!
+//! Each browser has a bounded send queue. A browser that doesn't keep up
+//! is disconnected rather than holding up the reader thread, which must
+//! never block.
+
+use crate::backup::{parse_json, write_json_string, JsonValue};
+use crate::shared::SharedClient;
+use crate::TelnetError;
+use roland_core::{Address, RolandError};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Messages queued per browser before it counts as too slow
+const SEND_QUEUE: usize = 64;
+/// Largest accepted message from a browser
+const MAX_MESSAGE: usize = 64 * 1024;
+/// How long a write to a browser may take
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Value the handshake key is hashed with (RFC 6455)
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Source of peer ids
+static NEXT_PEER: AtomicU64 = AtomicU64::new(0);
+
+/// Connected browser
+struct Peer {
+    id: u64,
+    queue: SyncSender<Vec<u8>>,
+    /// Shut down to disconnect the browser
+    stream: TcpStream,
+}
+
+/// Connected browsers, shared with the reader thread
+type Peers = Arc<Mutex<Vec<Peer>>>;
+
+/// WebSocket server bridging browsers and a device
+pub struct WsBridge {
+    listener: TcpListener,
+    client: SharedClient,
+    watched: Vec<Address>,
+    peers: Peers,
+}
+
+impl WsBridge {
+    /// Listen for browsers on `addr` and watch `watched` on the device
+    ///
+    /// # Arguments
+    /// * `addr` - Address to listen on
+    /// * `client` - Connection to the device; its subscription is replaced
+    /// * `watched` - Addresses whose changes are broadcast
+    pub fn bind(
+        addr: impl ToSocketAddrs,
+        client: SharedClient,
+        watched: Vec<Address>,
+    ) -> Result<Self, TelnetError> {
+        let listener = TcpListener::bind(addr)?;
+        let peers = Peers::default();
+
+        let broadcast_to = Arc::clone(&peers);
+        let addresses = watched.clone();
+        client.with(move |client| {
+            client.subscribe(&addresses, move |address, value| {
+                broadcast(&broadcast_to, &value_message(address, value));
+            })
+        })??;
+
+        Ok(Self {
+            listener,
+            client,
+            watched,
+            peers,
+        })
+    }
+
+    /// Get the address the bridge listens on
+    pub fn local_addr(&self) -> Result<SocketAddr, TelnetError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Number of connected browsers
+    pub fn peers(&self) -> usize {
+        self.peers.lock().unwrap().len()
+    }
+
+    /// Serve browsers, each on its own thread
+    ///
+    /// Only fails if accepting connections fails.
+    pub fn run(&self) -> Result<(), TelnetError> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let client = self.client.clone();
+            let watched = self.watched.clone();
+            let peers = Arc::clone(&self.peers);
+            thread::spawn(move || {
+                // The browser may have gone away; nothing to report to
+                let _ = serve(stream, client, &watched, peers);
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Queue a message for every browser, disconnecting those that are behind
+fn broadcast(peers: &Peers, message: &str) {
+    let frame = encode_frame(OPCODE_TEXT, message.as_bytes());
+    peers
+        .lock()
+        .unwrap()
+        .retain(|peer| match peer.queue.try_send(frame.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {
+                let _ = peer.stream.shutdown(Shutdown::Both);
+                false
+            }
+        });
+}
+
+/// Handle one browser connection until it closes
+fn serve(
+    stream: TcpStream,
+    client: SharedClient,
+    watched: &[Address],
+    peers: Peers,
+) -> io::Result<()> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream.try_clone()?;
+    handshake(&mut reader, &mut writer)?;
+
+    // Registered before the snapshot is read, so a change made meanwhile
+    // is queued too rather than lost; it can only arrive ahead of a
+    // snapshot value that already includes it
+    let (queue, frames) = mpsc::sync_channel::<Vec<u8>>(SEND_QUEUE);
+    let id = NEXT_PEER.fetch_add(1, Ordering::Relaxed);
+    peers.lock().unwrap().push(Peer {
+        id,
+        queue: queue.clone(),
+        stream: stream.try_clone()?,
+    });
+    let sender = thread::spawn(move || {
+        for frame in frames {
+            if writer.write_all(&frame).is_err() {
+                break;
+            }
+        }
+        let _ = writer.shutdown(Shutdown::Both);
+    });
+
+    // Initial snapshot, through the queue to keep it in order with changes
+    for &address in watched {
+        let message = match client.read_parameter_addr(address, 1) {
+            Ok(value) => value_message(address, value),
+            Err(e) => error_message(Some(address), &e),
+        };
+        if queue
+            .send(encode_frame(OPCODE_TEXT, message.as_bytes()))
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    let result = receive(&mut reader, &client, &queue);
+
+    // Closing the queue lets the sender flush a close frame and shut down
+    peers.lock().unwrap().retain(|peer| peer.id != id);
+    drop(queue);
+    let _ = sender.join();
+    result
+}
+
+/// Read messages from a browser until it closes the connection
+fn receive(
+    reader: &mut impl Read,
+    client: &SharedClient,
+    queue: &SyncSender<Vec<u8>>,
+) -> io::Result<()> {
+    let mut message = Vec::new();
+    loop {
+        let frame = read_frame(reader)?;
+        match frame.opcode {
+            OPCODE_TEXT | OPCODE_CONTINUATION => {
+                if message.len() + frame.payload.len() > MAX_MESSAGE {
+                    let _ = queue.try_send(encode_frame(OPCODE_CLOSE, &1009u16.to_be_bytes()));
+                    return Ok(());
+                }
+                message.extend_from_slice(&frame.payload);
+                if frame.fin {
+                    if let Some(reply) = handle_message(client, &message) {
+                        let _ = queue.try_send(encode_frame(OPCODE_TEXT, reply.as_bytes()));
+                    }
+                    message.clear();
+                }
+            }
+            OPCODE_PING => {
+                let _ = queue.try_send(encode_frame(OPCODE_PONG, &frame.payload));
+            }
+            OPCODE_CLOSE => {
+                let _ = queue.try_send(encode_frame(OPCODE_CLOSE, &frame.payload));
+                return Ok(());
+            }
+            // Pongs and binary messages are ignored
+            _ => {}
+        }
+    }
+}
+
+/// Perform the write a browser asked for, returning the reply if any
+fn handle_message(client: &SharedClient, message: &[u8]) -> Option<String> {
+    let invalid = || error_message(None, &TelnetError::Protocol(RolandError::InvalidValue));
+    let Some(json) = std::str::from_utf8(message)
+        .ok()
+        .and_then(|text| parse_json(text).ok())
+    else {
+        return Some(invalid());
+    };
+    let address = match json.field("address") {
+        Some(JsonValue::String(address)) => match Address::from_hex(address) {
+            Ok(address) => address,
+            Err(_) => {
+                return Some(error_message(
+                    None,
+                    &TelnetError::InvalidAddress(address.clone()),
+                ))
+            }
+        },
+        _ => return Some(invalid()),
+    };
+    let value = match json.field("value") {
+        Some(JsonValue::Number(value)) => match u8::try_from(*value) {
+            Ok(value) => value,
+            Err(_) => return Some(invalid()),
+        },
+        _ => return Some(invalid()),
+    };
+    client
+        .write_parameter_addr(address, value)
+        .err()
+        .map(|e| error_message(Some(address), &e))
+}
+
+fn value_message(address: Address, value: u8) -> String {
+    format!(
+        "{{\"address\":\"{}\",\"value\":{}}}",
+        address.to_hex(),
+        value
+    )
+}
+
+fn error_message(address: Option<Address>, error: &TelnetError) -> String {
+    let mut json = String::from("{");
+    if let Some(address) = address {
+        json.push_str(&format!("\"address\":\"{}\",", address.to_hex()));
+    }
+    json.push_str("\"error\":");
+    write_json_string(&mut json, &error.to_string());
+    json.push('}');
+    json
+}
+
+/// Answer the HTTP upgrade request of a browser
+fn handshake(reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<()> {
+    let mut key = None;
+    let mut head_len = 0;
+    loop {
+        let mut line = String::new();
+        head_len += reader.read_line(&mut line)?;
+        if head_len > MAX_MESSAGE || !line.ends_with('\n') {
+            return Err(ErrorKind::InvalidData.into());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let Some(key) = key else {
+        writer.write_all(
+            b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        )?;
+        return Err(ErrorKind::InvalidData.into());
+    };
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    )
+}
+
+/// Compute the `Sec-WebSocket-Accept` value for a handshake key
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()))
+}
+
+/// WebSocket frame
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Read a frame, unmasking it if it is masked
+fn read_frame(reader: &mut impl Read) -> io::Result<Frame> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head)?;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > MAX_MESSAGE as u64 {
+        return Err(ErrorKind::InvalidData.into());
+    }
+
+    let mut mask = [0; 4];
+    if head[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Frame {
+        fin: head[0] & 0x80 != 0,
+        opcode: head[0] & 0x0F,
+        payload,
+    })
+}
+
+/// SHA-1 digest, needed only for the handshake
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (chunk, word) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Standard base64 with padding
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connect, MockDevice};
+    use std::time::Instant;
+
+    /// Minimal browser: handshake, then masked text frames
+    struct Browser {
+        reader: BufReader<TcpStream>,
+        stream: TcpStream,
+    }
+
+    impl Browser {
+        fn connect(addr: SocketAddr) -> Self {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            stream
+                .write_all(
+                    b"GET / HTTP/1.1\r\nHost: test\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                      Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+                )
+                .unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = String::new();
+            while !head.ends_with("\r\n\r\n") {
+                reader.read_line(&mut head).unwrap();
+            }
+            assert!(head.starts_with("HTTP/1.1 101"));
+            assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+            Self { reader, stream }
+        }
+
+        fn send(&mut self, text: &str) {
+            let mask = [0x12, 0x34, 0x56, 0x78];
+            let mut frame = vec![0x80 | OPCODE_TEXT, 0x80 | text.len() as u8];
+            frame.extend_from_slice(&mask);
+            frame.extend(text.bytes().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+            self.stream.write_all(&frame).unwrap();
+        }
+
+        fn recv(&mut self) -> String {
+            let frame = read_frame(&mut self.reader).unwrap();
+            assert_eq!(frame.opcode, OPCODE_TEXT);
+            String::from_utf8(frame.payload).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_handshake_helpers() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"abcd"), "YWJjZA==");
+
+        let frame = encode_frame(OPCODE_TEXT, &[0; 300]);
+        assert_eq!(&frame[..4], &[0x81, 126, 0x01, 0x2C]);
+        assert_eq!(read_frame(&mut &frame[..]).unwrap().payload.len(), 300);
+    }
+
+    #[test]
+    fn test_snapshot_broadcast_and_write() {
+        let (addr, mock) = MockDevice::spawn();
+        let tally = Address::new(0x0A, 0x00, 0x00);
+        mock.set_parameter(tally, 0x02);
+        let client = connect(addr);
+        let bridge = WsBridge::bind("127.0.0.1:0", SharedClient::new(client), vec![tally]).unwrap();
+        let addr = bridge.local_addr().unwrap();
+        let bridge = Arc::new(bridge);
+        let server = Arc::clone(&bridge);
+        thread::spawn(move || server.run());
+
+        let mut first = Browser::connect(addr);
+        assert_eq!(first.recv(), "{\"address\":\"0A0000\",\"value\":2}");
+        let mut second = Browser::connect(addr);
+        assert_eq!(second.recv(), "{\"address\":\"0A0000\",\"value\":2}");
+        while bridge.peers() < 2 {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        mock.send_unsolicited(tally, 0x05);
+        assert_eq!(first.recv(), "{\"address\":\"0A0000\",\"value\":5}");
+        assert_eq!(second.recv(), "{\"address\":\"0A0000\",\"value\":5}");
+
+        first.send("{\"address\":\"050000\",\"value\":7}");
+        first.send("{\"address\":\"05\",\"value\":7}");
+        assert!(first.recv().contains("\"error\""));
+        assert_eq!(mock.parameter(Address::new(0x05, 0x00, 0x00)), Some(7));
+    }
+
+    #[test]
+    fn test_change_during_snapshot_delivered() {
+        let (addr, mock) = MockDevice::spawn();
+        let tally = Address::new(0x0A, 0x00, 0x00);
+        mock.set_parameter(tally, 0x02);
+        let client = connect(addr);
+        let shared = SharedClient::new(client);
+        let bridge = WsBridge::bind("127.0.0.1:0", shared.clone(), vec![tally]).unwrap();
+        let addr = bridge.local_addr().unwrap();
+        let bridge = Arc::new(bridge);
+        let server = Arc::clone(&bridge);
+        thread::spawn(move || server.run());
+
+        // Hold the connection so the snapshot read waits, and change the
+        // value once the browser is registered
+        let (started, running) = mpsc::channel();
+        let registered = Arc::clone(&bridge);
+        let blocker = thread::spawn(move || {
+            shared.with(move |_| {
+                started.send(()).unwrap();
+                let start = Instant::now();
+                while registered.peers() == 0 && start.elapsed() < Duration::from_secs(2) {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                mock.send_unsolicited(tally, 0x05);
+                thread::sleep(Duration::from_millis(50));
+                mock
+            })
+        });
+        running.recv().unwrap();
+
+        let mut browser = Browser::connect(addr);
+        assert_eq!(browser.recv(), "{\"address\":\"0A0000\",\"value\":5}");
+        assert_eq!(browser.recv(), "{\"address\":\"0A0000\",\"value\":5}");
+        drop(blocker.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_slow_consumer_dropped() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let browser = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        // Nobody drains this queue
+        let (queue, _frames) = mpsc::sync_channel(1);
+        let peers = Peers::default();
+        peers.lock().unwrap().push(Peer {
+            id: 0,
+            queue,
+            stream,
+        });
+
+        broadcast(&peers, "{}");
+        assert_eq!(peers.lock().unwrap().len(), 1);
+        broadcast(&peers, "{}");
+        assert!(peers.lock().unwrap().is_empty());
+        assert_eq!((&browser).read(&mut [0; 1]).unwrap(), 0);
+    }
+}